@@ -1 +1,4 @@
 pub mod params;
+mod response_code;
+
+pub use response_code::ResponseCode;
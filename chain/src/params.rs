@@ -1,4 +1,9 @@
-use penumbra_crypto::asset;
+use penumbra_crypto::{
+    asset,
+    proofs::ProofVersion,
+    rdsa::{SpendAuth, VerificationKeyBytes},
+    Amount,
+};
 use penumbra_proto::{chain as pb, crypto as pbc, Protobuf};
 use serde::{Deserialize, Serialize};
 
@@ -7,7 +12,7 @@ pub struct AssetInfo {
     pub asset_id: asset::Id,
     pub denom: asset::Denom,
     pub as_of_block_height: u64,
-    pub total_supply: u64,
+    pub total_supply: Amount,
 }
 
 impl Protobuf<pb::AssetInfo> for AssetInfo {}
@@ -20,7 +25,10 @@ impl TryFrom<pb::AssetInfo> for AssetInfo {
             asset_id: asset::Id::try_from(msg.asset_id.unwrap())?,
             denom: asset::Denom::try_from(msg.denom.unwrap())?,
             as_of_block_height: msg.as_of_block_height,
-            total_supply: msg.total_supply,
+            total_supply: msg
+                .total_supply
+                .ok_or_else(|| anyhow::anyhow!("missing total supply"))?
+                .into(),
         })
     }
 }
@@ -31,27 +39,193 @@ impl From<AssetInfo> for pb::AssetInfo {
             asset_id: Some(pbc::AssetId::from(ai.asset_id)),
             denom: Some(pbc::Denom::from(ai.denom)),
             as_of_block_height: ai.as_of_block_height,
-            total_supply: ai.total_supply,
+            total_supply: Some(ai.total_supply.into()),
         }
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(try_from = "pb::ChainParams", into = "pb::ChainParams")]
 pub struct ChainParams {
     pub chain_id: String,
     pub epoch_duration: u64,
     pub unbonding_epochs: u64,
+    /// The maximum size of a block, in bytes.
+    pub max_block_bytes: u64,
+    /// The maximum amount of gas in a block, or -1 for no limit.
+    pub max_block_gas: i64,
+    /// The maximum total size of the evidence in a block, in bytes.
+    pub max_evidence_bytes: u64,
+    /// The proof version that proofs must use from `proof_version_height` on.
+    ///
+    /// Before that height, proofs may also use any earlier version.
+    pub proof_version: u32,
+    /// The height from which `proof_version` is required.
+    pub proof_version_height: u64,
+    /// The maximum number of outputs in a transaction.
+    ///
+    /// Memo ciphertexts have a fixed size, so this also bounds the memo data a transaction adds
+    /// to the compact blocks every client syncs.
+    pub max_outputs_per_transaction: u64,
+    /// The assets other than the staking token that fees may be paid in.
+    pub fee_rates: Vec<FeeRate>,
+    /// The reward for each transaction included in a block, paid in the staking token to the
+    /// funding streams of the validator that proposed the block.
+    pub proposer_reward_per_transaction: u64,
+    /// If nonempty, the only denominations that may be received over IBC.
+    ///
+    /// Entries match either a full denomination trace, like `transfer/channel-0/uatom`, or its
+    /// base denomination, like `uatom`.
+    pub inbound_denom_allowlist: Vec<String>,
+    /// Denominations that may not be received over IBC, matched like the allowlist.
+    ///
+    /// This takes precedence over the allowlist.
+    pub inbound_denom_denylist: Vec<String>,
+    /// The key allowed to register and update denom metadata, if any.
+    pub denom_metadata_authority: Option<VerificationKeyBytes<SpendAuth>>,
+    /// The height from which transactions that move value are rejected, or 0 for never.
+    ///
+    /// Blocks are still produced meanwhile, so validators can keep managing their keys.  Nothing
+    /// updates chain parameters on a running chain yet, so this can only be set at genesis.
+    pub emergency_halt_height: u64,
 }
 
+/// The scale of [`FeeRate::rate`]: a rate of `FEE_RATE_SCALE` means one unit of the asset is
+/// worth one unit of the staking token.
+pub const FEE_RATE_SCALE: u64 = 1_0000_0000;
+
+/// The rate at which fees may be paid in an asset other than the staking token.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "pb::FeeRate", into = "pb::FeeRate")]
+pub struct FeeRate {
+    /// The denomination of the asset.
+    pub denom: String,
+    /// The amount of the staking token one unit of the asset is worth, scaled by
+    /// [`FEE_RATE_SCALE`].
+    pub rate: u64,
+}
+
+impl FeeRate {
+    /// The amount of the staking token that `amount` of the asset is worth, rounded down.
+    pub fn staking_token_amount(&self, amount: u64) -> u64 {
+        // Saturate rather than overflow, since no fee can be worth more than the supply anyway.
+        ((amount as u128 * self.rate as u128) / FEE_RATE_SCALE as u128)
+            .try_into()
+            .unwrap_or(u64::MAX)
+    }
+}
+
+impl Protobuf<pb::FeeRate> for FeeRate {}
+
+impl From<pb::FeeRate> for FeeRate {
+    fn from(msg: pb::FeeRate) -> Self {
+        FeeRate {
+            denom: msg.denom,
+            rate: msg.rate,
+        }
+    }
+}
+
+impl From<FeeRate> for pb::FeeRate {
+    fn from(rate: FeeRate) -> Self {
+        pb::FeeRate {
+            denom: rate.denom,
+            rate: rate.rate,
+        }
+    }
+}
+
+impl ChainParams {
+    /// The rate at which fees may be paid in the given asset, if it is one of the assets other than
+    /// the staking token that fees may be paid in.
+    pub fn fee_rate(&self, asset_id: asset::Id) -> Option<&FeeRate> {
+        self.fee_rates.iter().find(|rate| {
+            asset::REGISTRY
+                .parse_denom(&rate.denom)
+                .map(|denom| denom.id() == asset_id)
+                .unwrap_or(false)
+        })
+    }
+
+    /// Whether the given denomination may be received over IBC.
+    ///
+    /// This should be checked when processing an inbound ICS-20 transfer packet, before minting
+    /// the voucher for it; a rejected packet is acknowledged with an error, so the counterparty
+    /// chain refunds the sender.
+    pub fn accepts_inbound_denom(&self, denom: &str) -> bool {
+        let base_denom = denom.rsplit('/').next().unwrap_or(denom);
+        let matches = |entry: &String| entry == denom || entry == base_denom;
+
+        if self.inbound_denom_denylist.iter().any(matches) {
+            return false;
+        }
+        self.inbound_denom_allowlist.is_empty() || self.inbound_denom_allowlist.iter().any(matches)
+    }
+
+    /// The maximum age, in blocks, of evidence of validator misbehavior that can still be acted on.
+    ///
+    /// This is the length of the unbonding period: after it, stake undelegated from a validator at
+    /// the time of its misbehavior has already been released from quarantine, so it could no longer
+    /// be slashed.
+    pub fn max_evidence_age_blocks(&self) -> u64 {
+        self.epoch_duration * self.unbonding_epochs
+    }
+
+    /// Whether transactions that move value are rejected in a block at the given height.
+    pub fn halts_value_transfers(&self, height: u64) -> bool {
+        self.emergency_halt_height != 0 && height >= self.emergency_halt_height
+    }
+
+    /// Whether proofs of the given version are accepted in a block at the given height.
+    pub fn accepts_proof_version(&self, version: ProofVersion, height: u64) -> bool {
+        let version = u32::from(version);
+        if height >= self.proof_version_height {
+            version == self.proof_version
+        } else {
+            version <= self.proof_version
+        }
+    }
+}
+
+/// Tendermint's default maximum block size.
+const DEFAULT_MAX_BLOCK_BYTES: u64 = 22020096;
+/// Tendermint's default maximum evidence size.
+const DEFAULT_MAX_EVIDENCE_BYTES: u64 = 1048576;
+/// The default maximum number of outputs in a transaction.
+const DEFAULT_MAX_OUTPUTS_PER_TRANSACTION: u64 = 32;
+
 impl Protobuf<pb::ChainParams> for ChainParams {}
 
 impl From<pb::ChainParams> for ChainParams {
     fn from(msg: pb::ChainParams) -> Self {
+        // Zero values come from genesis files predating these parameters.
+        let or_default = |value, default| if value == 0 { default } else { value };
         ChainParams {
             chain_id: msg.chain_id,
             epoch_duration: msg.epoch_duration,
             unbonding_epochs: msg.unbonding_epochs,
+            max_block_bytes: or_default(msg.max_block_bytes, DEFAULT_MAX_BLOCK_BYTES),
+            max_block_gas: if msg.max_block_gas == 0 {
+                -1
+            } else {
+                msg.max_block_gas
+            },
+            max_evidence_bytes: or_default(msg.max_evidence_bytes, DEFAULT_MAX_EVIDENCE_BYTES),
+            proof_version: msg.proof_version,
+            proof_version_height: msg.proof_version_height,
+            max_outputs_per_transaction: or_default(
+                msg.max_outputs_per_transaction,
+                DEFAULT_MAX_OUTPUTS_PER_TRANSACTION,
+            ),
+            fee_rates: msg.fee_rates.into_iter().map(Into::into).collect(),
+            proposer_reward_per_transaction: msg.proposer_reward_per_transaction,
+            inbound_denom_allowlist: msg.inbound_denom_allowlist,
+            inbound_denom_denylist: msg.inbound_denom_denylist,
+            // Anything but a 32-byte key, including nothing at all, means there is no authority.
+            denom_metadata_authority: <[u8; 32]>::try_from(msg.denom_metadata_authority.as_slice())
+                .ok()
+                .map(Into::into),
+            emergency_halt_height: msg.emergency_halt_height,
         }
     }
 }
@@ -62,6 +236,21 @@ impl From<ChainParams> for pb::ChainParams {
             chain_id: params.chain_id,
             epoch_duration: params.epoch_duration,
             unbonding_epochs: params.unbonding_epochs,
+            max_block_bytes: params.max_block_bytes,
+            max_block_gas: params.max_block_gas,
+            max_evidence_bytes: params.max_evidence_bytes,
+            proof_version: params.proof_version,
+            proof_version_height: params.proof_version_height,
+            max_outputs_per_transaction: params.max_outputs_per_transaction,
+            fee_rates: params.fee_rates.into_iter().map(Into::into).collect(),
+            proposer_reward_per_transaction: params.proposer_reward_per_transaction,
+            inbound_denom_allowlist: params.inbound_denom_allowlist,
+            inbound_denom_denylist: params.inbound_denom_denylist,
+            denom_metadata_authority: params
+                .denom_metadata_authority
+                .map(|key| <[u8; 32]>::from(key).to_vec())
+                .unwrap_or_default(),
+            emergency_halt_height: params.emergency_halt_height,
         }
     }
 }
@@ -72,6 +261,18 @@ impl Default for ChainParams {
             chain_id: String::new(),
             epoch_duration: 8640,
             unbonding_epochs: 30,
+            max_block_bytes: DEFAULT_MAX_BLOCK_BYTES,
+            max_block_gas: -1,
+            max_evidence_bytes: DEFAULT_MAX_EVIDENCE_BYTES,
+            proof_version: ProofVersion::LATEST.into(),
+            proof_version_height: 0,
+            max_outputs_per_transaction: DEFAULT_MAX_OUTPUTS_PER_TRANSACTION,
+            fee_rates: Vec::new(),
+            proposer_reward_per_transaction: 0,
+            inbound_denom_allowlist: Vec::new(),
+            inbound_denom_denylist: Vec::new(),
+            denom_metadata_authority: None,
+            emergency_halt_height: 0,
         }
     }
 }
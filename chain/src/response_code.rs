@@ -0,0 +1,46 @@
+use std::fmt;
+
+/// The code in a `CheckTx` or `DeliverTx` response, which tells clients why a transaction was
+/// rejected, so that they can react to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ResponseCode {
+    /// The transaction was accepted.
+    Ok = 0,
+    /// The transaction is invalid, for any reason without a more specific code.
+    Rejected = 1,
+    /// The node is in emergency mode, and is not accepting transactions that move value.
+    CircuitBreakerTripped = 2,
+    /// The transaction's anchor isn't one of the recent note commitment tree roots, so its proofs
+    /// must be rebuilt against a newer one.
+    StaleAnchor = 3,
+}
+
+impl ResponseCode {
+    /// The response code with the given value, treating unknown nonzero codes as
+    /// [`ResponseCode::Rejected`].
+    pub fn from_code(code: u32) -> Self {
+        match code {
+            0 => ResponseCode::Ok,
+            2 => ResponseCode::CircuitBreakerTripped,
+            3 => ResponseCode::StaleAnchor,
+            _ => ResponseCode::Rejected,
+        }
+    }
+
+    /// The value of the response code.
+    pub fn code(&self) -> u32 {
+        *self as u32
+    }
+}
+
+impl fmt::Display for ResponseCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ResponseCode::Ok => "ok",
+            ResponseCode::Rejected => "rejected",
+            ResponseCode::CircuitBreakerTripped => "circuit breaker tripped",
+            ResponseCode::StaleAnchor => "stale anchor",
+        })
+    }
+}
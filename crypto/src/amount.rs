@@ -0,0 +1,130 @@
+//! Amounts of an asset too large for a single note, like the total supply of an asset.
+
+use std::{fmt, str::FromStr};
+
+use penumbra_proto::{crypto as pb, Protobuf};
+use serde::{Deserialize, Serialize};
+
+/// An amount of some asset, in its base denomination.
+///
+/// A note's value is at most `u64::MAX`, but the total supply of an asset is the sum of every
+/// note of it, so it is tracked as a `u128`.  Arithmetic is checked, so that an overflow is an
+/// error rather than a panic or a silently wrapped supply.
+///
+/// Amounts are serialized as decimal strings, since JSON consumers often can't represent
+/// integers above 2^53 exactly.
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Amount(u128);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+
+    pub fn value(&self) -> u128 {
+        self.0
+    }
+
+    pub fn checked_add(self, rhs: impl Into<Amount>) -> Option<Amount> {
+        self.0.checked_add(rhs.into().0).map(Amount)
+    }
+
+    pub fn checked_sub(self, rhs: impl Into<Amount>) -> Option<Amount> {
+        self.0.checked_sub(rhs.into().0).map(Amount)
+    }
+}
+
+impl From<u64> for Amount {
+    fn from(amount: u64) -> Self {
+        Amount(amount.into())
+    }
+}
+
+impl From<u128> for Amount {
+    fn from(amount: u128) -> Self {
+        Amount(amount)
+    }
+}
+
+impl TryFrom<Amount> for u64 {
+    type Error = anyhow::Error;
+
+    fn try_from(amount: Amount) -> Result<Self, Self::Error> {
+        amount
+            .0
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("amount {} does not fit in 64 bits", amount))
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Debug for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for Amount {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Amount(
+            s.parse()
+                .map_err(|_| anyhow::anyhow!("invalid amount {:?}", s))?,
+        ))
+    }
+}
+
+impl From<Amount> for String {
+    fn from(amount: Amount) -> Self {
+        amount.to_string()
+    }
+}
+
+impl TryFrom<String> for Amount {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Protobuf<pb::Amount> for Amount {}
+
+impl From<Amount> for pb::Amount {
+    fn from(amount: Amount) -> Self {
+        pb::Amount {
+            lo: amount.0 as u64,
+            hi: (amount.0 >> 64) as u64,
+        }
+    }
+}
+
+impl From<pb::Amount> for Amount {
+    fn from(msg: pb::Amount) -> Self {
+        Amount(((msg.hi as u128) << 64) | msg.lo as u128)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proto_round_trip() {
+        for amount in [0, 1, u64::MAX as u128, u64::MAX as u128 + 1, u128::MAX] {
+            let amount = Amount::from(amount);
+            assert_eq!(Amount::from(pb::Amount::from(amount)), amount);
+        }
+    }
+
+    #[test]
+    fn conversion_to_u64_is_checked() {
+        assert_eq!(u64::try_from(Amount::from(u64::MAX)).unwrap(), u64::MAX);
+        assert!(u64::try_from(Amount::from(u64::MAX).checked_add(1u64).unwrap()).is_err());
+    }
+}
@@ -3,11 +3,13 @@
 mod cache;
 mod denom;
 mod id;
+mod metadata;
 mod registry;
 
 pub use cache::Cache;
 pub use denom::{Denom, Unit};
 pub use id::Id;
+pub use metadata::Metadata;
 pub use registry::{Registry, REGISTRY};
 
 #[cfg(test)]
@@ -112,6 +114,31 @@ mod tests {
         assert_eq!(format!("{}", base_denom), "cube".to_string());
     }
 
+    #[test]
+    fn test_metadata_checks() {
+        let metadata = Metadata {
+            denom: REGISTRY.parse_denom("cube").unwrap(),
+            symbol: "CUBE".to_string(),
+            decimals: 6,
+            description: "A cube.".to_string(),
+        };
+        assert!(metadata.check().is_ok());
+
+        for symbol in ["", "CUBE CUBE", "CUBECUBECUBECUBECUBE"] {
+            let bad_symbol = Metadata {
+                symbol: symbol.to_string(),
+                ..metadata.clone()
+            };
+            assert!(bad_symbol.check().is_err());
+        }
+
+        let bad_decimals = Metadata {
+            decimals: Metadata::MAX_DECIMALS + 1,
+            ..metadata
+        };
+        assert!(bad_decimals.check().is_err());
+    }
+
     proptest! {
         #[test]
         fn displaydenom_parsing_formatting_roundtrip(
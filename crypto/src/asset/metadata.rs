@@ -0,0 +1,85 @@
+use penumbra_proto::{crypto as pb, Protobuf};
+use serde::{Deserialize, Serialize};
+
+use crate::asset::Denom;
+
+/// Display metadata for a denomination, registered on-chain.
+///
+/// This lets wallets render assets that aren't in the built-in [`REGISTRY`](super::REGISTRY),
+/// such as IBC vouchers, without hardcoding how to display them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "pb::DenomMetadata", into = "pb::DenomMetadata")]
+pub struct Metadata {
+    /// The denomination the metadata describes.
+    pub denom: Denom,
+    /// A short ticker symbol to display amounts with.
+    pub symbol: String,
+    /// The number of decimal places between the base unit and the display unit.
+    pub decimals: u8,
+    /// A human-readable description of the asset.
+    pub description: String,
+}
+
+impl Metadata {
+    /// The maximum length of a symbol, in bytes.
+    pub const MAX_SYMBOL_LEN: usize = 16;
+    /// The maximum length of a description, in bytes.
+    pub const MAX_DESCRIPTION_LEN: usize = 280;
+    /// The maximum number of decimal places, beyond which amounts can't be displayed exactly.
+    pub const MAX_DECIMALS: u8 = 18;
+
+    /// Checks that the metadata is well-formed.
+    pub fn check(&self) -> anyhow::Result<()> {
+        if self.symbol.is_empty() || self.symbol.len() > Self::MAX_SYMBOL_LEN {
+            return Err(anyhow::anyhow!(
+                "symbol must be between 1 and {} bytes long",
+                Self::MAX_SYMBOL_LEN
+            ));
+        }
+        if !self.symbol.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(anyhow::anyhow!("symbol must be ASCII alphanumeric"));
+        }
+        if self.decimals > Self::MAX_DECIMALS {
+            return Err(anyhow::anyhow!(
+                "decimals must be at most {}",
+                Self::MAX_DECIMALS
+            ));
+        }
+        if self.description.len() > Self::MAX_DESCRIPTION_LEN {
+            return Err(anyhow::anyhow!(
+                "description must be at most {} bytes long",
+                Self::MAX_DESCRIPTION_LEN
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Protobuf<pb::DenomMetadata> for Metadata {}
+
+impl From<Metadata> for pb::DenomMetadata {
+    fn from(metadata: Metadata) -> Self {
+        pb::DenomMetadata {
+            denom: Some(metadata.denom.into()),
+            symbol: metadata.symbol,
+            decimals: metadata.decimals.into(),
+            description: metadata.description,
+        }
+    }
+}
+
+impl TryFrom<pb::DenomMetadata> for Metadata {
+    type Error = anyhow::Error;
+
+    fn try_from(msg: pb::DenomMetadata) -> Result<Self, Self::Error> {
+        Ok(Metadata {
+            denom: msg
+                .denom
+                .ok_or_else(|| anyhow::anyhow!("missing denom field in proto"))?
+                .try_into()?,
+            symbol: msg.symbol,
+            decimals: msg.decimals.try_into()?,
+            description: msg.description,
+        })
+    }
+}
@@ -11,6 +11,6 @@ mod fvk;
 mod ivk;
 mod ovk;
 
-pub use fvk::FullViewingKey;
+pub use fvk::{FullViewingKey, FVK_LEN_BYTES};
 pub use ivk::{IncomingViewingKey, IVK_LEN_BYTES};
 pub use ovk::{OutgoingViewingKey, OVK_LEN_BYTES};
@@ -1,8 +1,9 @@
 use ark_ff::PrimeField;
 use decaf377::FieldExt;
 use once_cell::sync::Lazy;
+use penumbra_proto::serializers::bech32str;
 
-use super::{DiversifierKey, IncomingViewingKey, NullifierKey, OutgoingViewingKey};
+use super::{DiversifierKey, IncomingViewingKey, NullifierKey, OutgoingViewingKey, NK_LEN_BYTES};
 use crate::{
     ka, merkle, note, prf,
     rdsa::{SpendAuth, VerificationKey},
@@ -11,6 +12,10 @@ use crate::{
 
 static IVK_DOMAIN_SEP: Lazy<Fq> = Lazy::new(|| Fq::from_le_bytes_mod_order(b"penumbra.derive.ivk"));
 
+/// The length of the byte encoding of a [`FullViewingKey`]: the spend
+/// verification key, followed by the nullifier key.
+pub const FVK_LEN_BYTES: usize = 64;
+
 /// The `FullViewingKey` allows one to identify incoming and outgoing notes only.
 #[derive(Clone, Debug)]
 pub struct FullViewingKey {
@@ -77,3 +82,62 @@ impl FullViewingKey {
         &self.ak
     }
 }
+
+impl std::fmt::Display for FullViewingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut bytes = Vec::with_capacity(FVK_LEN_BYTES);
+        bytes.extend_from_slice(&self.ak.to_bytes());
+        bytes.extend_from_slice(&self.nk.0.to_bytes());
+        f.write_str(&bech32str::encode(
+            &bytes,
+            bech32str::full_viewing_key::BECH32_PREFIX,
+            bech32str::Bech32m,
+        ))
+    }
+}
+
+impl std::str::FromStr for FullViewingKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = bech32str::decode(
+            s,
+            bech32str::full_viewing_key::BECH32_PREFIX,
+            bech32str::Bech32m,
+        )?;
+        if bytes.len() != FVK_LEN_BYTES {
+            return Err(anyhow::anyhow!("invalid full viewing key length"));
+        }
+
+        let ak = VerificationKey::try_from(&bytes[..32])?;
+        let nk_bytes: [u8; NK_LEN_BYTES] = bytes[32..].try_into()?;
+        let nk = NullifierKey(
+            Fq::from_bytes(nk_bytes).map_err(|_| anyhow::anyhow!("invalid nullifier key"))?,
+        );
+
+        Ok(Self::from_components(ak, nk))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_core::OsRng;
+
+    use super::*;
+    use crate::keys::SpendKey;
+
+    #[test]
+    fn viewing_keys_roundtrip_through_bech32() {
+        let sk = SpendKey::generate(OsRng);
+        let fvk = sk.full_viewing_key();
+
+        let fvk2: FullViewingKey = fvk.to_string().parse().unwrap();
+        assert_eq!(fvk.to_string(), fvk2.to_string());
+
+        let ivk2: IncomingViewingKey = fvk.incoming().to_string().parse().unwrap();
+        assert_eq!(
+            fvk.incoming().payment_address(0u64.into()).0,
+            ivk2.payment_address(0u64.into()).0
+        );
+    }
+}
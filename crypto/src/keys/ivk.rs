@@ -1,8 +1,12 @@
 use ark_ff::PrimeField;
+use decaf377::FieldExt;
+use penumbra_proto::serializers::bech32str;
 
 use super::{Diversifier, DiversifierIndex, DiversifierKey};
 use crate::{fmd, ka, prf, Address, Fr};
 
+/// The length of the byte encoding of an [`IncomingViewingKey`]: the
+/// key-agreement secret, followed by the diversifier key.
 pub const IVK_LEN_BYTES: usize = 64;
 
 /// Allows viewing incoming notes, i.e., notes sent to the spending key this
@@ -47,3 +51,40 @@ impl IncomingViewingKey {
         self.dk.index_for_diversifier(diversifier)
     }
 }
+
+impl std::fmt::Display for IncomingViewingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut bytes = Vec::with_capacity(IVK_LEN_BYTES);
+        bytes.extend_from_slice(&self.ivk.to_bytes());
+        bytes.extend_from_slice(&self.dk.0);
+        f.write_str(&bech32str::encode(
+            &bytes,
+            bech32str::incoming_viewing_key::BECH32_PREFIX,
+            bech32str::Bech32m,
+        ))
+    }
+}
+
+impl std::str::FromStr for IncomingViewingKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = bech32str::decode(
+            s,
+            bech32str::incoming_viewing_key::BECH32_PREFIX,
+            bech32str::Bech32m,
+        )?;
+        if bytes.len() != IVK_LEN_BYTES {
+            return Err(anyhow::anyhow!("invalid incoming viewing key length"));
+        }
+
+        let ivk_bytes: [u8; 32] = bytes[..32].try_into()?;
+        let ivk = ka::Secret::new_from_field(
+            Fr::from_bytes(ivk_bytes)
+                .map_err(|_| anyhow::anyhow!("invalid incoming viewing key"))?,
+        );
+        let dk = DiversifierKey(bytes[32..].try_into()?);
+
+        Ok(Self { ivk, dk })
+    }
+}
@@ -6,6 +6,7 @@ pub use decaf377_ka as ka;
 pub use decaf377_rdsa as rdsa;
 
 mod address;
+mod amount;
 pub mod asset;
 pub mod keys;
 pub mod memo;
@@ -17,6 +18,7 @@ pub mod proofs;
 pub mod value;
 
 pub use address::Address;
+pub use amount::Amount;
 pub use note::Note;
 pub use nullifier::Nullifier;
 pub use value::Value;
@@ -76,6 +76,27 @@ impl Root {
     }
 }
 
+/// Computes the root of the tree in which `path` authenticates `commitment`.
+///
+/// A path from an untrusted source authenticates the commitment against some anchor exactly when
+/// this is equal to the anchor.
+pub fn root_from_path(commitment: &note::Commitment, (position, auth_path): &Path) -> Root {
+    // This logic is from `incrementalmerkletree`'s `compute_root_from_auth_path` function, which
+    // is `pub(crate)`.
+    let position = u64::from(*position);
+    let mut cur = *commitment;
+    let mut lvl = Altitude::zero();
+    for (i, sibling) in auth_path.iter().enumerate() {
+        if (position >> i) & 1 == 1 {
+            cur = note::Commitment::combine(lvl, sibling, &cur);
+        } else {
+            cur = note::Commitment::combine(lvl, &cur, sibling);
+        }
+        lvl = lvl + 1;
+    }
+    Root(cur.0)
+}
+
 pub trait TreeExt {
     fn root2(&self) -> Root;
 }
@@ -89,6 +110,45 @@ where
     }
 }
 
+/// Methods for forgetting the parts of a [`NoteCommitmentTree`] that are no longer needed.
+///
+/// A tree only needs its frontier to be extended and to compute its root; everything else it holds
+/// is there to produce authentication paths for witnessed commitments, or to rewind to a
+/// checkpoint.
+pub trait PruneExt {
+    /// Forgets the witnesses of the given commitments, returning how many were witnessed.
+    fn forget<'a>(&mut self, commitments: impl IntoIterator<Item = &'a note::Commitment>) -> usize;
+
+    /// Reduces the tree to its frontier, dropping every witness and checkpoint, and keeping at
+    /// most `max_checkpoints` checkpoints from now on.  Returns whether there was anything to drop.
+    fn prune(&mut self, max_checkpoints: usize) -> bool;
+}
+
+impl PruneExt for NoteCommitmentTree {
+    fn forget<'a>(&mut self, commitments: impl IntoIterator<Item = &'a note::Commitment>) -> usize {
+        commitments
+            .into_iter()
+            .filter(|commitment| self.remove_witness(commitment))
+            .count()
+    }
+
+    fn prune(&mut self, max_checkpoints: usize) -> bool {
+        // Witnesses and checkpoints each split the tree into another bridge, so a tree that's
+        // nothing but its frontier has at most one.
+        if self.bridges().len() <= 1 {
+            return false;
+        }
+        let frontier = self
+            .bridges()
+            .last()
+            .expect("tree has bridges")
+            .frontier()
+            .clone();
+        *self = BridgeTree::from_frontier(max_checkpoints, frontier);
+        true
+    }
+}
+
 impl Hashable for note::Commitment {
     fn empty_leaf() -> Self {
         note::Commitment(Fq::zero())
@@ -133,3 +193,54 @@ mod sqlx_impls {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn root_from_path_matches_tree_root() {
+        let commitments = (1u64..=5)
+            .map(|i| note::Commitment(Fq::from(i)))
+            .collect::<Vec<_>>();
+
+        let mut tree = NoteCommitmentTree::new(0);
+        for (i, commitment) in commitments.iter().enumerate() {
+            tree.append(commitment);
+            if i == 2 {
+                tree.witness();
+            }
+        }
+
+        let path = tree.authentication_path(&commitments[2]).unwrap();
+        assert_eq!(root_from_path(&commitments[2], &path), tree.root2());
+        assert_ne!(root_from_path(&commitments[3], &path), tree.root2());
+    }
+
+    #[test]
+    fn pruning_keeps_root_and_position() {
+        let mut tree = NoteCommitmentTree::new(0);
+        for i in 1u64..=5 {
+            tree.append(&note::Commitment(Fq::from(i)));
+            tree.witness();
+        }
+        let root = tree.root2();
+        let size = bincode::serialize(&tree).unwrap().len();
+
+        assert!(tree.prune(0));
+        assert!(!tree.prune(0));
+        assert_eq!(tree.root2(), root);
+        assert!(bincode::serialize(&tree).unwrap().len() < size);
+        assert!(tree
+            .authentication_path(&note::Commitment(Fq::from(3u64)))
+            .is_none());
+
+        // The pruned tree carries on from where it left off.
+        let mut unpruned = NoteCommitmentTree::new(0);
+        for i in 1u64..=6 {
+            unpruned.append(&note::Commitment(Fq::from(i)));
+        }
+        tree.append(&note::Commitment(Fq::from(6u64)));
+        assert_eq!(tree.root2(), unpruned.root2());
+    }
+}
@@ -1 +1,42 @@
 pub mod transparent;
+
+/// The version of the proof system a spend or output proof was created for.
+///
+/// Each version corresponds to a circuit, and to the verification key that checks proofs for it.
+/// A circuit upgrade registers a new version here, alongside its verifier, and the chain moves
+/// onto it at a coordinated height set in its chain parameters, rather than with a restart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProofVersion {
+    /// The [`transparent`] proofs, which stand in for zk-SNARKs.
+    Transparent,
+    /// The [`transparent`] proofs, except that spends of zero-value notes don't need to show that
+    /// the note is in the note commitment tree, so that transactions can be padded with dummy
+    /// spends.
+    TransparentDummySpends,
+}
+
+impl ProofVersion {
+    /// The latest registered proof version, used to create new proofs.
+    pub const LATEST: ProofVersion = ProofVersion::TransparentDummySpends;
+}
+
+impl From<ProofVersion> for u32 {
+    fn from(version: ProofVersion) -> u32 {
+        match version {
+            ProofVersion::Transparent => 0,
+            ProofVersion::TransparentDummySpends => 1,
+        }
+    }
+}
+
+impl TryFrom<u32> for ProofVersion {
+    type Error = anyhow::Error;
+
+    fn try_from(version: u32) -> Result<Self, Self::Error> {
+        match version {
+            0 => Ok(ProofVersion::Transparent),
+            1 => Ok(ProofVersion::TransparentDummySpends),
+            _ => Err(anyhow::anyhow!("unknown proof version {}", version)),
+        }
+    }
+}
@@ -7,7 +7,7 @@ use decaf377_rdsa::{SpendAuth, VerificationKey};
 use penumbra_proto::{transparent_proofs, Message, Protobuf};
 use thiserror;
 
-use crate::{asset, ka, keys, merkle, merkle::Hashable, note, value, Fq, Fr, Nullifier, Value};
+use crate::{asset, ka, keys, merkle, note, value, Fq, Fr, Nullifier, Value};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -78,6 +78,33 @@ impl SpendProof {
         value_commitment: value::Commitment,
         nullifier: Nullifier,
         rk: VerificationKey<SpendAuth>,
+    ) -> anyhow::Result<(), Error> {
+        self.verify_inner(anchor, value_commitment, nullifier, rk, false)
+    }
+
+    /// Called to verify the proof like [`SpendProof::verify`], for
+    /// [`ProofVersion::TransparentDummySpends`](super::ProofVersion::TransparentDummySpends).
+    ///
+    /// Zero-value notes are exempt from the Merkle path check, so that dummy spends don't need a
+    /// note in the tree: spending one can't create value, since the value commitment is still
+    /// checked.
+    pub fn verify_allowing_dummy_spends(
+        &self,
+        anchor: merkle::Root,
+        value_commitment: value::Commitment,
+        nullifier: Nullifier,
+        rk: VerificationKey<SpendAuth>,
+    ) -> anyhow::Result<(), Error> {
+        self.verify_inner(anchor, value_commitment, nullifier, rk, true)
+    }
+
+    fn verify_inner(
+        &self,
+        anchor: merkle::Root,
+        value_commitment: value::Commitment,
+        nullifier: Nullifier,
+        rk: VerificationKey<SpendAuth>,
+        allow_dummy_spends: bool,
     ) -> anyhow::Result<(), Error> {
         // Note commitment integrity.
         let s_component_transmission_key = Fq::from_bytes(self.pk_d.0);
@@ -98,28 +125,16 @@ impl SpendProof {
             return Err(Error::MerklePathMismatch);
         }
 
-        // 2. Check the Merkle path leads to the expected anchor (`merkle::Root`).
-        let mut cur = self.note_commitment;
-
-        // This logic is from `incrementalmerkletree`'s `compute_root_from_auth_path` function which is
-        // `pub(crate)` so is included below.
-        let mut lvl = merkle::Altitude::zero();
-        for (i, v) in self.merkle_path.1.iter().enumerate().map(|(i, v)| {
-            (
-                ((<usize>::try_from(self.position).unwrap() >> i) & 1) == 1,
-                v,
-            )
-        }) {
-            if i {
-                cur = note::Commitment::combine(lvl, v, &cur);
-            } else {
-                cur = note::Commitment::combine(lvl, &cur, v);
+        // 2. Check the Merkle path leads to the expected anchor (`merkle::Root`), unless this is a
+        // dummy spend.
+        if !(allow_dummy_spends && self.value.amount == 0) {
+            let expected_root = merkle::root_from_path(
+                &self.note_commitment,
+                &(self.position, self.merkle_path.1.clone()),
+            );
+            if expected_root != anchor {
+                return Err(Error::MerkleRootMismatch);
             }
-            lvl = lvl + 1;
-        }
-        let expected_root = merkle::Root(cur.0);
-        if expected_root != anchor {
-            return Err(Error::MerkleRootMismatch);
         }
 
         // Value commitment integrity.
@@ -431,7 +446,7 @@ impl TryFrom<&[u8]> for OutputProof {
 
 #[cfg(test)]
 mod tests {
-    use ark_ff::UniformRand;
+    use ark_ff::{UniformRand, Zero};
     use rand_core::OsRng;
 
     use super::*;
@@ -666,6 +681,56 @@ mod tests {
             .is_ok());
     }
 
+    #[test]
+    fn test_spend_proof_verification_zero_value_note_outside_tree() {
+        let mut rng = OsRng;
+        let sk_sender = SpendKey::generate(&mut rng);
+        let fvk_sender = sk_sender.full_viewing_key();
+        let ivk_sender = fvk_sender.incoming();
+        let (sender, _dtk_d) = ivk_sender.payment_address(0u64.into());
+
+        let value_to_send = Value {
+            amount: 0,
+            asset_id: asset::REGISTRY.parse_denom("upenumbra").unwrap().id(),
+        };
+        let v_blinding = Fr::rand(&mut rng);
+
+        let note = Note::generate(&mut rng, &sender, value_to_send);
+        let note_commitment = note.commit();
+        let spend_auth_randomizer = Fr::rand(&mut rng);
+        let rsk = sk_sender.spend_auth_key().randomize(&spend_auth_randomizer);
+        let nk = *sk_sender.nullifier_key();
+        let ak = sk_sender.spend_auth_key().into();
+        // The note isn't in the tree, and the path is all zeroes.
+        let anchor = merkle::BridgeTree::<note::Commitment, 32>::new(5).root2();
+        let merkle_path = (0.into(), vec![note::Commitment(Fq::zero()); merkle::DEPTH]);
+
+        let proof = SpendProof {
+            merkle_path,
+            position: 0.into(),
+            g_d: *sender.diversified_generator(),
+            pk_d: *sender.transmission_key(),
+            value: value_to_send,
+            v_blinding,
+            note_commitment,
+            note_blinding: note.note_blinding(),
+            spend_auth_randomizer,
+            ak,
+            nk,
+        };
+
+        let rk: VerificationKey<SpendAuth> = rsk.into();
+        let nf = nk.derive_nullifier(0.into(), &note_commitment);
+        assert!(proof
+            .verify_allowing_dummy_spends(anchor, value_to_send.commit(v_blinding), nf.clone(), rk)
+            .is_ok());
+        // The original transparent proofs still require the note to be in the tree.
+        assert!(matches!(
+            proof.verify(anchor, value_to_send.commit(v_blinding), nf, rk),
+            Err(Error::MerkleRootMismatch)
+        ));
+    }
+
     #[test]
     fn test_spend_proof_verification_merkle_path_integrity_failure() {
         let mut rng = OsRng;
@@ -0,0 +1,252 @@
+//! An encrypted log of every transaction signed with the wallet's spend key.
+//!
+//! Organizations sharing a key need to be able to reconcile what it was used
+//! to sign.  Each record captures the spend authorization randomizer of every
+//! spend in the transaction, which is enough to check later that the
+//! randomized verification key in the transaction was derived from our key.
+//!
+//! The log is stored next to the wallet file, one record per line.  Each line
+//! is encrypted separately, so that records can be appended without rewriting
+//! the rest of the log.
+use std::{
+    io::Write,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context, Result};
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use penumbra_crypto::{keys::FullViewingKey, merkle, note, Address, FieldExt, Fr};
+use penumbra_stake::STAKING_TOKEN_ASSET_ID;
+use penumbra_transaction::{Action, Transaction};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use serde_with::{hex::Hex, serde_as};
+
+use crate::ClientStateFile;
+
+const NONCE_LEN_BYTES: usize = 12;
+
+/// The audit log belonging to a wallet.
+pub struct AuditLog {
+    path: PathBuf,
+    key: Key,
+}
+
+impl AuditLog {
+    /// Opens the audit log for the given wallet.
+    ///
+    /// The log's encryption key is derived from the wallet's spend key, so
+    /// only holders of the spend key can read or append to it.
+    pub fn open(state: &ClientStateFile) -> Self {
+        let key = blake2b_simd::Params::new()
+            .personal(b"Penumbra_AuditLg")
+            .hash_length(32)
+            .hash(&state.wallet().spend_key().seed().0);
+
+        Self {
+            path: state.audit_log_path(),
+            key: Key::clone_from_slice(key.as_bytes()),
+        }
+    }
+
+    /// Appends a record to the log.
+    pub fn append(&self, record: &Record) -> Result<()> {
+        let mut nonce = [0u8; NONCE_LEN_BYTES];
+        OsRng.fill_bytes(&mut nonce);
+
+        let plaintext = serde_json::to_vec(record)?;
+        let ciphertext = ChaCha20Poly1305::new(&self.key)
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+            .map_err(|_| anyhow!("could not encrypt audit record"))?;
+
+        let mut line = nonce.to_vec();
+        line.extend_from_slice(&ciphertext);
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("could not open audit log {}", self.path.display()))?;
+        writeln!(file, "{}", hex::encode(line))?;
+        file.sync_data()?;
+
+        tracing::debug!(path = ?self.path, "appended audit record");
+        Ok(())
+    }
+
+    /// Reads every record in the log, in the order they were appended.
+    ///
+    /// Returns an error if any record can't be decrypted, which means it was
+    /// corrupted, tampered with, or written under a different key.
+    pub fn records(&self) -> Result<Vec<Record>> {
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let cipher = ChaCha20Poly1305::new(&self.key);
+        contents
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                let bytes = hex::decode(line)
+                    .with_context(|| format!("audit record {} is not valid hex", i + 1))?;
+                if bytes.len() < NONCE_LEN_BYTES {
+                    return Err(anyhow!("audit record {} is truncated", i + 1));
+                }
+                let (nonce, ciphertext) = bytes.split_at(NONCE_LEN_BYTES);
+                let plaintext = cipher
+                    .decrypt(Nonce::from_slice(nonce), ciphertext)
+                    .map_err(|_| anyhow!("audit record {} could not be decrypted", i + 1))?;
+                serde_json::from_slice(&plaintext)
+                    .with_context(|| format!("audit record {} is malformed", i + 1))
+            })
+            .collect()
+    }
+}
+
+/// Records a signed transaction in the wallet's audit log.
+pub fn record(
+    state: &ClientStateFile,
+    transaction: &Transaction,
+    destinations: &[Address],
+) -> Result<()> {
+    AuditLog::open(state).append(&Record::new(transaction, destinations))
+}
+
+/// A record of a signed transaction.
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Record {
+    /// When the transaction was signed, in seconds since the UNIX epoch.
+    pub signed_at: u64,
+    #[serde_as(as = "Hex")]
+    pub transaction_id: [u8; 32],
+    /// The hash of the transaction body that was signed.
+    #[serde_as(as = "Hex")]
+    pub sighash: Vec<u8>,
+    pub chain_id: String,
+    pub fee: u64,
+    /// The asset the fee was paid in, if not the staking token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_asset_id: Option<String>,
+    pub spends: Vec<SpendRecord>,
+    pub outputs: Vec<OutputRecord>,
+    /// The addresses the transaction pays to, not counting change.
+    pub destinations: Vec<String>,
+}
+
+/// A record of one of the spends in a signed transaction.
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpendRecord {
+    #[serde_as(as = "Hex")]
+    pub note_commitment: [u8; 32],
+    pub position: u64,
+    pub amount: u64,
+    pub asset_id: String,
+    #[serde_as(as = "Hex")]
+    pub nullifier: [u8; 32],
+    /// The spend authorization randomizer.
+    #[serde_as(as = "Hex")]
+    pub randomizer: [u8; 32],
+    /// The randomized verification key the spend was signed under.
+    #[serde_as(as = "Hex")]
+    pub rk: [u8; 32],
+}
+
+/// A record of one of the outputs in a signed transaction.
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OutputRecord {
+    #[serde_as(as = "Hex")]
+    pub note_commitment: [u8; 32],
+    pub amount: u64,
+    pub asset_id: String,
+}
+
+impl Record {
+    /// Records a signed transaction paying to the given destinations.
+    pub fn new(transaction: &Transaction, destinations: &[Address]) -> Self {
+        let body = transaction.transaction_body();
+
+        let mut spends = Vec::new();
+        let mut outputs = Vec::new();
+        for action in &body.actions {
+            match action {
+                Action::Spend(spend) => {
+                    let proof = &spend.body.proof;
+                    spends.push(SpendRecord {
+                        note_commitment: proof.note_commitment.into(),
+                        position: proof.position.into(),
+                        amount: proof.value.amount,
+                        asset_id: proof.value.asset_id.to_string(),
+                        nullifier: spend.body.nullifier.to_bytes(),
+                        randomizer: proof.spend_auth_randomizer.to_bytes(),
+                        rk: spend.body.rk.to_bytes(),
+                    });
+                }
+                Action::Output(output) => outputs.push(OutputRecord {
+                    note_commitment: output.body.note_commitment.into(),
+                    amount: output.body.proof.value.amount,
+                    asset_id: output.body.proof.value.asset_id.to_string(),
+                }),
+                _ => {}
+            }
+        }
+
+        Self {
+            signed_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("time travels linearly in a forward direction")
+                .as_secs(),
+            transaction_id: transaction.id(),
+            sighash: body.sighash().to_vec(),
+            chain_id: body.chain_id.clone(),
+            fee: body.fee.amount,
+            fee_asset_id: (body.fee.asset_id != *STAKING_TOKEN_ASSET_ID)
+                .then(|| body.fee.asset_id.to_string()),
+            spends,
+            outputs,
+            destinations: destinations.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    /// Checks that every spend in the record was authorized by the given key.
+    ///
+    /// For each spend, the randomized verification key must be our spend
+    /// verification key randomized by the recorded randomizer, and the
+    /// nullifier must be the one our key derives for the recorded note.
+    pub fn verify(&self, fvk: &FullViewingKey) -> Result<()> {
+        for spend in &self.spends {
+            let randomizer =
+                Fr::from_bytes(spend.randomizer).map_err(|_| anyhow!("invalid randomizer"))?;
+            let rk = fvk.spend_verification_key().randomize(&randomizer);
+            if rk.to_bytes() != spend.rk {
+                return Err(anyhow!(
+                    "spend of note {} was not authorized by this key",
+                    hex::encode(spend.note_commitment)
+                ));
+            }
+
+            let note_commitment = note::Commitment::try_from(spend.note_commitment)
+                .map_err(|_| anyhow!("invalid note commitment"))?;
+            let nullifier = fvk.derive_nullifier(
+                merkle::Position::from(spend.position as usize),
+                &note_commitment,
+            );
+            if nullifier.to_bytes() != spend.nullifier {
+                return Err(anyhow!(
+                    "nullifier for note {} was not derived by this key",
+                    hex::encode(spend.note_commitment)
+                ));
+            }
+        }
+        Ok(())
+    }
+}
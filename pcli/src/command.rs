@@ -1,17 +1,30 @@
-use structopt::StructOpt;
+use structopt::{clap::Shell, StructOpt};
 
 mod addr;
+mod audit;
 mod balance;
+mod chain;
+mod daemon;
+mod debug;
+mod faucet;
+pub mod meta;
 mod stake;
 mod tx;
 mod validator;
+mod view;
 mod wallet;
 
 pub use addr::AddrCmd;
+pub use audit::AuditCmd;
 pub use balance::BalanceCmd;
+pub use chain::ChainCmd;
+pub use daemon::DaemonCmd;
+pub use debug::DebugCmd;
+pub use faucet::FaucetCmd;
 pub use stake::StakeCmd;
 pub use tx::TxCmd;
 pub use validator::ValidatorCmd;
+pub use view::ViewCmd;
 pub use wallet::WalletCmd;
 
 #[derive(Debug, StructOpt)]
@@ -26,13 +39,44 @@ pub enum Command {
     ///
     /// `pcli` syncs automatically prior to any action requiring chain state,
     /// but this command can be used to "pre-sync" before interactive use.
-    Sync,
+    Sync {
+        /// Report what the sync did and where its time went, e.g. to tell whether a slow sync
+        /// is limited by the network or by scanning.
+        #[structopt(long)]
+        stats: bool,
+    },
     /// Displays the current wallet balance.
     Balance(BalanceCmd),
     /// Manages a validator.
     Validator(ValidatorCmd),
     /// Manages delegations and undelegations.
     Stake(StakeCmd),
+    /// Inspects the log of transactions signed by this wallet.
+    Audit(AuditCmd),
+    /// Displays the chain parameters and validator set.
+    Chain(ChainCmd),
+    /// Runs in the background, making recurring payments on a schedule and finishing
+    /// redelegations once their stake has unbonded.
+    Daemon(DaemonCmd),
+    /// Serves an HTTP endpoint that dispenses small amounts of funds from this wallet.
+    Faucet(FaucetCmd),
+    /// Serves a gRPC view service answering queries about this wallet's balances and notes.
+    View(ViewCmd),
+    /// Diagnoses problems with the connection to the node.
+    Debug(DebugCmd),
+    /// Writes a shell completion script to stdout, e.g. `pcli completions bash >
+    /// /etc/bash_completion.d/pcli`.
+    Completions {
+        /// The shell to generate completions for.
+        #[structopt(possible_values = &Shell::variants(), case_insensitive = true)]
+        shell: Shell,
+    },
+    /// Describes pcli's commands and their options.
+    Commands {
+        /// Describe every command, argument and option as JSON.
+        #[structopt(long)]
+        json: bool,
+    },
 }
 
 impl Command {
@@ -42,10 +86,18 @@ impl Command {
             Command::Tx(cmd) => cmd.needs_sync(),
             Command::Wallet(cmd) => cmd.needs_sync(),
             Command::Addr(cmd) => cmd.needs_sync(),
-            Command::Sync => true,
+            Command::Sync { .. } => true,
             Command::Balance(cmd) => cmd.needs_sync(),
             Command::Validator(cmd) => cmd.needs_sync(),
             Command::Stake(cmd) => cmd.needs_sync(),
+            Command::Audit(cmd) => cmd.needs_sync(),
+            Command::Chain(cmd) => cmd.needs_sync(),
+            Command::Daemon(cmd) => cmd.needs_sync(),
+            Command::Faucet(cmd) => cmd.needs_sync(),
+            Command::View(cmd) => cmd.needs_sync(),
+            Command::Debug(cmd) => cmd.needs_sync(),
+            Command::Completions { .. } => false,
+            Command::Commands { .. } => false,
         }
     }
 }
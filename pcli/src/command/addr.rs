@@ -20,6 +20,7 @@ pub enum AddrCmd {
     /// Create a new address.
     New {
         /// A freeform label for the address, stored only locally.
+        #[structopt(short, long)]
         label: String,
     },
 }
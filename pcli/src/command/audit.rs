@@ -0,0 +1,85 @@
+use anyhow::{anyhow, Result};
+use comfy_table::{presets, Table};
+use penumbra_crypto::{asset, Value};
+use structopt::StructOpt;
+
+use crate::{
+    audit::{AuditLog, Record},
+    ClientStateFile,
+};
+
+#[derive(Debug, StructOpt)]
+pub enum AuditCmd {
+    /// Lists every transaction signed by this wallet.
+    List {
+        /// If set, prints each record as JSON, including its randomizers.
+        #[structopt(long)]
+        json: bool,
+    },
+    /// Checks that every record in the audit log is intact and that each
+    /// recorded spend was authorized by this wallet's spend key.
+    Verify,
+}
+
+impl AuditCmd {
+    /// Determine if this command requires a network sync before it executes.
+    pub fn needs_sync(&self) -> bool {
+        false
+    }
+
+    pub fn exec(&self, state: &ClientStateFile) -> Result<()> {
+        let records = AuditLog::open(state).records()?;
+
+        match self {
+            AuditCmd::List { json } => {
+                if *json {
+                    for record in &records {
+                        println!("{}", serde_json::to_string(record)?);
+                    }
+                    return Ok(());
+                }
+
+                let mut table = Table::new();
+                table.load_preset(presets::NOTHING);
+                table.set_header(vec!["Signed At", "Transaction", "Spent", "Destinations"]);
+                for record in &records {
+                    let spent = record
+                        .spends
+                        .iter()
+                        .map(|spend| {
+                            format_value(spend.amount, &spend.asset_id, state.asset_cache())
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    table.add_row(vec![
+                        record.signed_at.to_string(),
+                        hex::encode(record.transaction_id),
+                        spent,
+                        record.destinations.join(", "),
+                    ]);
+                }
+                println!("{}", table);
+            }
+            AuditCmd::Verify => {
+                let fvk = state.wallet().full_viewing_key();
+                for record in &records {
+                    record.verify(fvk).map_err(|e| {
+                        anyhow!("transaction {}: {}", hex::encode(record.transaction_id), e)
+                    })?;
+                }
+                println!("verified {} audit records", records.len());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Formats an amount of the given asset, using its denomination if it's known.
+fn format_value(amount: u64, asset_id: &str, cache: &asset::Cache) -> String {
+    asset_id
+        .parse::<asset::Id>()
+        .ok()
+        .and_then(|asset_id| Value { amount, asset_id }.try_format(cache))
+        .unwrap_or_else(|| format!("{} of {}", amount, asset_id))
+}
@@ -99,7 +99,11 @@ impl BalanceCmd {
 
         if self.by_address {
             for (address_id, by_denom) in state.unspent_notes_by_address_and_denom().into_iter() {
-                let (mut label, _) = state.wallet().address_by_index(address_id as usize)?;
+                // Notes can arrive at addresses that were derived without a local label
+                let mut label = match state.wallet().label_for_index(address_id as usize) {
+                    Some(label) => format!("{}: {}", address_id, label),
+                    None => format!("{}: (unlabeled)", address_id),
+                };
                 for (denom, notes) in by_denom.into_iter() {
                     let notes_groups = if self.by_note {
                         notes.into_iter().map(|n| vec![n]).collect()
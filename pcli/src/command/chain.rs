@@ -0,0 +1,181 @@
+use anyhow::{anyhow, Result};
+use comfy_table::{presets, Table};
+use futures::stream::TryStreamExt;
+use penumbra_chain::params::ChainParams;
+use penumbra_proto::light_wallet::ValidatorInfoRequest;
+use penumbra_stake::{ValidatorInfo, ValidatorState, SLASHING_PENALTY_BPS};
+use structopt::StructOpt;
+
+use crate::{fetch, ClientStateFile, Opt};
+
+#[derive(Debug, StructOpt)]
+pub enum ChainCmd {
+    /// Display the chain parameters and the current epoch.
+    Params,
+    /// Display the chain's validator set, ordered by voting power.
+    Validators {
+        /// Whether to show validators that are not currently part of the consensus set.
+        #[structopt(short = "i", long)]
+        show_inactive: bool,
+    },
+}
+
+impl ChainCmd {
+    pub fn needs_sync(&self) -> bool {
+        false
+    }
+
+    pub async fn exec(&self, opt: &Opt, state: &ClientStateFile) -> Result<()> {
+        match self {
+            ChainCmd::Params => {
+                let info = fetch::chain_info(opt, state).await?;
+                let params: ChainParams = info
+                    .chain_params
+                    .ok_or_else(|| anyhow!("chain info is missing the chain parameters"))?
+                    .into();
+
+                let mut table = Table::new();
+                table.load_preset(presets::NOTHING);
+                table.set_header(vec!["Parameter", "Value"]);
+                table.add_row(vec!["Chain ID".to_string(), params.chain_id.clone()]);
+                table.add_row(vec!["Height".to_string(), info.height.to_string()]);
+                table.add_row(vec![
+                    "Epoch".to_string(),
+                    format!(
+                        "{} ({} blocks until next epoch)",
+                        info.epoch_index, info.blocks_until_next_epoch
+                    ),
+                ]);
+                table.add_row(vec![
+                    "Epoch Duration".to_string(),
+                    format!("{} blocks", params.epoch_duration),
+                ]);
+                table.add_row(vec![
+                    "Unbonding Period".to_string(),
+                    format!(
+                        "{} epochs ({} blocks)",
+                        params.unbonding_epochs,
+                        params.epoch_duration * params.unbonding_epochs
+                    ),
+                ]);
+                table.add_row(vec![
+                    "Slashing Penalty".to_string(),
+                    format!("{}bps", SLASHING_PENALTY_BPS),
+                ]);
+                table.add_row(vec![
+                    "Max Block Size".to_string(),
+                    format!("{} bytes", params.max_block_bytes),
+                ]);
+                table.add_row(vec![
+                    "Max Evidence Size".to_string(),
+                    format!("{} bytes", params.max_evidence_bytes),
+                ]);
+                table.add_row(vec![
+                    "Max Outputs Per Transaction".to_string(),
+                    params.max_outputs_per_transaction.to_string(),
+                ]);
+                table.add_row(vec![
+                    "Proposer Reward Per Transaction".to_string(),
+                    format!("{}upenumbra", params.proposer_reward_per_transaction),
+                ]);
+                if !params.inbound_denom_allowlist.is_empty() {
+                    table.add_row(vec![
+                        "Inbound IBC Denom Allowlist".to_string(),
+                        params.inbound_denom_allowlist.join(", "),
+                    ]);
+                }
+                if !params.inbound_denom_denylist.is_empty() {
+                    table.add_row(vec![
+                        "Inbound IBC Denom Denylist".to_string(),
+                        params.inbound_denom_denylist.join(", "),
+                    ]);
+                }
+                if params.emergency_halt_height != 0 {
+                    table.add_row(vec![
+                        "Emergency Halt Height".to_string(),
+                        params.emergency_halt_height.to_string(),
+                    ]);
+                }
+                if let Some(authority) = params.denom_metadata_authority {
+                    table.add_row(vec![
+                        "Denom Metadata Authority".to_string(),
+                        hex::encode(<[u8; 32]>::from(authority)),
+                    ]);
+                }
+                table.add_row(vec![
+                    "Proof Version".to_string(),
+                    format!(
+                        "{} (required from height {})",
+                        params.proof_version, params.proof_version_height
+                    ),
+                ]);
+                // Older nodes don't report what they were built from.
+                if let Some(build_info) = info.build_info {
+                    table.add_row(vec![
+                        "Node Version".to_string(),
+                        format!(
+                            "{} ({}, {})",
+                            build_info.version, build_info.git_commit, build_info.profile
+                        ),
+                    ]);
+                }
+
+                println!("{}", table);
+            }
+            ChainCmd::Validators { show_inactive } => {
+                let mut client = opt.light_wallet_client().await?;
+
+                let mut validators = client
+                    .validator_info(ValidatorInfoRequest {
+                        show_inactive: *show_inactive,
+                        chain_id: state.chain_id().unwrap_or_default(),
+                    })
+                    .await?
+                    .into_inner()
+                    .try_collect::<Vec<_>>()
+                    .await?
+                    .into_iter()
+                    .map(TryInto::try_into)
+                    .collect::<Result<Vec<ValidatorInfo>, _>>()?;
+
+                // Sort by voting power (descending), breaking ties by identity key so the order
+                // is stable across invocations.
+                validators.sort_by(|a, b| {
+                    b.status
+                        .voting_power
+                        .cmp(&a.status.voting_power)
+                        .then_with(|| {
+                            a.validator
+                                .identity_key
+                                .to_string()
+                                .cmp(&b.validator.identity_key.to_string())
+                        })
+                });
+
+                let mut table = Table::new();
+                table.load_preset(presets::NOTHING);
+                table.set_header(vec!["Voting Power", "State", "Name", "Identity Key"]);
+
+                for v in validators {
+                    let state = match v.status.state {
+                        ValidatorState::Unbonding { unbonding_epoch } => {
+                            format!("UNBONDING (until epoch {})", unbonding_epoch)
+                        }
+                        other => other.name().to_str().to_string(),
+                    };
+
+                    table.add_row(vec![
+                        v.status.voting_power.to_string(),
+                        state,
+                        v.validator.name,
+                        v.validator.identity_key.to_string(),
+                    ]);
+                }
+
+                println!("{}", table);
+            }
+        }
+
+        Ok(())
+    }
+}
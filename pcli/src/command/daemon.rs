@@ -0,0 +1,292 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use penumbra_crypto::{Address, Value};
+use penumbra_stake::STAKING_TOKEN_ASSET_ID;
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+use structopt::StructOpt;
+
+use crate::{
+    audit, fetch,
+    redelegation::{Finished, Redelegations},
+    sync, ClientStateFile, Opt,
+};
+
+#[derive(Debug, StructOpt)]
+pub struct DaemonCmd {
+    /// The schedule of recurring payments to make, as a JSON file.
+    #[structopt(long, parse(from_os_str))]
+    pub schedule: PathBuf,
+    /// How often to sync and check for payments that are due, in seconds.
+    #[structopt(long, default_value = "10")]
+    pub poll_secs: u64,
+    /// The log of payment attempts, which also records when each payment was last made [default:
+    /// the schedule file, with the extension `log`].
+    #[structopt(long, parse(from_os_str))]
+    pub payment_log: Option<PathBuf>,
+    /// A shell command to run whenever a payment fails, with the name of the payment and the error
+    /// in the `PCLI_PAYMENT` and `PCLI_ERROR` environment variables.
+    #[structopt(long)]
+    pub alert_command: Option<String>,
+}
+
+/// A schedule of recurring payments.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    pub payments: Vec<Payment>,
+}
+
+/// A payment made every `interval_secs`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Payment {
+    /// A name for the payment, unique within the schedule.
+    pub name: String,
+    /// The address to pay.
+    pub to: String,
+    /// The amounts to send, written as typed values 1.87penumbra, 12cubes, etc.
+    pub values: Vec<String>,
+    pub interval_secs: u64,
+    /// The transaction fee, in upenumbra [default: the network profile's fee, or 0].
+    #[serde(default)]
+    pub fee: Option<u64>,
+    #[serde(default)]
+    pub memo: Option<String>,
+}
+
+/// A line of the payment log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PaymentLogEntry {
+    /// When the payment was attempted, in seconds since the UNIX epoch.
+    time: u64,
+    payment: String,
+    /// The ID of the transaction, if the payment was made.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    transaction_id: Option<String>,
+    /// Why the payment failed, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl DaemonCmd {
+    pub fn needs_sync(&self) -> bool {
+        true
+    }
+
+    pub async fn exec(&self, opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
+        let file = std::fs::File::open(&self.schedule)
+            .with_context(|| format!("could not open schedule {}", self.schedule.display()))?;
+        let schedule: Schedule = serde_json::from_reader(file)
+            .with_context(|| format!("could not parse schedule {}", self.schedule.display()))?;
+        // Check the whole schedule up front, rather than when each payment first comes due.
+        let mut payments = BTreeMap::new();
+        for payment in schedule.payments {
+            payment
+                .parse()
+                .with_context(|| format!("invalid payment {:?}", payment.name))?;
+            if payment.interval_secs == 0 {
+                return Err(anyhow::anyhow!(
+                    "payment {:?} has an interval of zero",
+                    payment.name
+                ));
+            }
+            if payments.contains_key(&payment.name) {
+                return Err(anyhow::anyhow!("duplicate payment {:?}", payment.name));
+            }
+            payments.insert(payment.name.clone(), payment);
+        }
+
+        let log_path = self
+            .payment_log
+            .clone()
+            .unwrap_or_else(|| self.schedule.with_extension("log"));
+        let mut last_paid = read_last_paid(&log_path)?;
+        tracing::info!(
+            payments = payments.len(),
+            log = %log_path.display(),
+            "starting payment scheduler"
+        );
+
+        let mut interval = tokio::time::interval(Duration::from_secs(self.poll_secs));
+        loop {
+            interval.tick().await;
+
+            // Keep running through network failures, which are usually transient.
+            if let Err(e) = sync(opt, state).await {
+                tracing::warn!(error = %format!("{:#}", e), "could not sync");
+                continue;
+            }
+            if let Err(e) = fetch::assets(opt, state).await {
+                tracing::warn!(error = %format!("{:#}", e), "could not fetch assets");
+            }
+            state.commit()?;
+
+            // Finish any redelegations whose stake has unbonded since the last sync.
+            let mut redelegations = Redelegations::open(state)?;
+            for (redelegation, result) in redelegations.finish(opt, state).await? {
+                match result {
+                    Ok(Finished::Delegated(transaction_id)) => tracing::info!(
+                        from = %redelegation.from,
+                        to = %redelegation.to,
+                        %transaction_id,
+                        "finished redelegation"
+                    ),
+                    Ok(Finished::Abandoned) => tracing::warn!(
+                        from = %redelegation.from,
+                        to = %redelegation.to,
+                        "abandoned redelegation, since the unbonded stake is no longer available"
+                    ),
+                    Err(e) => tracing::error!(
+                        from = %redelegation.from,
+                        to = %redelegation.to,
+                        error = %format!("{:#}", e),
+                        "could not finish redelegation"
+                    ),
+                }
+            }
+
+            let now = now();
+            for payment in payments.values() {
+                let due = last_paid
+                    .get(&payment.name)
+                    .map(|last| now >= last + payment.interval_secs)
+                    .unwrap_or(true);
+                if !due {
+                    continue;
+                }
+
+                let entry = match pay(opt, state, payment).await {
+                    Ok(transaction_id) => {
+                        tracing::info!(payment = %payment.name, %transaction_id, "made payment");
+                        last_paid.insert(payment.name.clone(), now);
+                        PaymentLogEntry {
+                            time: now,
+                            payment: payment.name.clone(),
+                            transaction_id: Some(transaction_id),
+                            error: None,
+                        }
+                    }
+                    Err(e) => {
+                        let error = format!("{:#}", e);
+                        tracing::error!(payment = %payment.name, %error, "payment failed");
+                        self.alert(&payment.name, &error).await;
+                        PaymentLogEntry {
+                            time: now,
+                            payment: payment.name.clone(),
+                            transaction_id: None,
+                            error: Some(error),
+                        }
+                    }
+                };
+                append_log(&log_path, &entry)?;
+            }
+        }
+    }
+
+    /// Runs the alert command, if there is one, for a failed payment.
+    async fn alert(&self, payment: &str, error: &str) {
+        let command = match &self.alert_command {
+            Some(command) => command,
+            None => return,
+        };
+        let status = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("PCLI_PAYMENT", payment)
+            .env("PCLI_ERROR", error)
+            .status()
+            .await;
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => tracing::warn!(%status, "alert command failed"),
+            Err(e) => tracing::warn!(error = %e, "could not run alert command"),
+        }
+    }
+}
+
+impl Payment {
+    fn parse(&self) -> Result<(Address, Vec<Value>)> {
+        let to = self
+            .to
+            .parse()
+            .map_err(|_| anyhow::anyhow!("address is invalid"))?;
+        let values = self
+            .values
+            .iter()
+            .map(|v| v.parse())
+            .collect::<Result<Vec<Value>, _>>()?;
+        Ok((to, values))
+    }
+}
+
+/// Makes a payment, returning the ID of its transaction.
+async fn pay(opt: &Opt, state: &mut ClientStateFile, payment: &Payment) -> Result<String> {
+    let (to, values) = payment.parse()?;
+    let fee = Value {
+        amount: payment.fee.unwrap_or_else(|| opt.default_fee()),
+        asset_id: *STAKING_TOKEN_ASSET_ID,
+    };
+    let result = opt
+        .build_and_submit_transaction(state, |state| {
+            let transaction =
+                state.build_send(&mut OsRng, &values, fee, to, None, payment.memo.clone())?;
+            audit::record(state, &transaction, &[to])?;
+            Ok(transaction)
+        })
+        .await;
+    match result {
+        Ok(transaction) => {
+            state.commit()?;
+            Ok(hex::encode(transaction.id()))
+        }
+        Err(e) => {
+            // Forget the spends of the failed transaction, so its notes can be spent again.
+            state.reload()?;
+            Err(e)
+        }
+    }
+}
+
+/// Reads when each payment was last made from the payment log.
+fn read_last_paid(path: &Path) -> Result<BTreeMap<String, u64>> {
+    let mut last_paid = BTreeMap::new();
+    if !path.exists() {
+        return Ok(last_paid);
+    }
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("could not open payment log {}", path.display()))?;
+    for line in BufReader::new(file).lines() {
+        let entry: PaymentLogEntry = serde_json::from_str(&line?)
+            .with_context(|| format!("could not parse payment log {}", path.display()))?;
+        if entry.transaction_id.is_some() {
+            last_paid.insert(entry.payment, entry.time);
+        }
+    }
+    Ok(last_paid)
+}
+
+fn append_log(path: &Path, entry: &PaymentLogEntry) -> Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("could not open payment log {}", path.display()))?;
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    file.write_all(&line)?;
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time travels linearly in a forward direction")
+        .as_secs()
+}
@@ -0,0 +1,112 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use comfy_table::{presets, Table};
+use penumbra_proto::{light_wallet::ChainInfoRequest, thin_wallet::NullifierStatusRequest};
+use rand_core::{OsRng, RngCore};
+use structopt::StructOpt;
+
+use crate::{ClientStateFile, Opt};
+
+#[derive(Debug, StructOpt)]
+pub enum DebugCmd {
+    /// Measure the latency of the node's RPC endpoints, to tell whether a slow sync or a slow
+    /// command is limited by the network.
+    Ping {
+        /// How many requests to send to each endpoint.
+        #[structopt(short, long, default_value = "5")]
+        count: u32,
+    },
+}
+
+impl DebugCmd {
+    pub fn needs_sync(&self) -> bool {
+        false
+    }
+
+    pub async fn exec(&self, opt: &Opt, state: &ClientStateFile) -> Result<()> {
+        match self {
+            DebugCmd::Ping { count } => {
+                let count = (*count).max(1);
+                let chain_id = state.chain_id().unwrap_or_default();
+
+                let mut table = Table::new();
+                table.load_preset(presets::NOTHING);
+                table.set_header(vec!["Endpoint", "Connect", "Min", "Avg", "Max"]);
+
+                let connect = Instant::now();
+                let mut light_wallet = opt.light_wallet_client().await?;
+                let connect = connect.elapsed();
+                let mut latencies = Vec::new();
+                for _ in 0..count {
+                    let request = Instant::now();
+                    light_wallet
+                        .chain_info(ChainInfoRequest {
+                            chain_id: chain_id.clone(),
+                        })
+                        .await?;
+                    latencies.push(request.elapsed());
+                }
+                table.add_row(row(
+                    format!("light wallet ({}:{})", opt.node(), opt.light_wallet_port()),
+                    Some(connect),
+                    &latencies,
+                ));
+
+                let connect = Instant::now();
+                let mut thin_wallet = opt.thin_wallet_client().await?;
+                let connect = connect.elapsed();
+                let mut latencies = Vec::new();
+                for _ in 0..count {
+                    // Ask about a random nullifier, so that the request reveals nothing.
+                    let mut nullifier = vec![0; 32];
+                    OsRng.fill_bytes(&mut nullifier);
+                    let request = Instant::now();
+                    thin_wallet
+                        .nullifier_status(NullifierStatusRequest {
+                            chain_id: chain_id.clone(),
+                            nullifier,
+                        })
+                        .await?;
+                    latencies.push(request.elapsed());
+                }
+                table.add_row(row(
+                    format!("thin wallet ({}:{})", opt.node(), opt.thin_wallet_port()),
+                    Some(connect),
+                    &latencies,
+                ));
+
+                // Tendermint's RPC is plain HTTP, so there's no connection to time separately.
+                let client = reqwest::Client::new();
+                let url = format!("http://{}:{}/health", opt.node(), opt.rpc_port());
+                let mut latencies = Vec::new();
+                for _ in 0..count {
+                    let request = Instant::now();
+                    client.get(&url).send().await?.error_for_status()?;
+                    latencies.push(request.elapsed());
+                }
+                table.add_row(row(
+                    format!("tendermint ({}:{})", opt.node(), opt.rpc_port()),
+                    None,
+                    &latencies,
+                ));
+
+                println!("{}", table);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn row(endpoint: String, connect: Option<Duration>, latencies: &[Duration]) -> Vec<String> {
+    let ms = |d: Duration| format!("{:.1}ms", d.as_secs_f64() * 1000.0);
+    let total = latencies.iter().sum::<Duration>();
+    vec![
+        endpoint,
+        connect.map(ms).unwrap_or_else(|| "-".to_string()),
+        ms(latencies.iter().copied().min().unwrap_or_default()),
+        ms(total / latencies.len().max(1) as u32),
+        ms(latencies.iter().copied().max().unwrap_or_default()),
+    ]
+}
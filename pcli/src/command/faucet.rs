@@ -0,0 +1,312 @@
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use hyper::{
+    body::HttpBody,
+    server::conn::AddrStream,
+    service::{make_service_fn, service_fn},
+    Body, Method, Response, Server, StatusCode,
+};
+use penumbra_crypto::{Address, Value};
+use penumbra_stake::STAKING_TOKEN_ASSET_ID;
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+use structopt::StructOpt;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{audit, sync, ClientStateFile, Opt};
+
+/// The largest request body the faucet reads, which is plenty for a JSON object with an address.
+const MAX_BODY_BYTES: usize = 4096;
+
+#[derive(Debug, StructOpt)]
+pub struct FaucetCmd {
+    /// The address to serve the faucet's HTTP endpoint on.
+    #[structopt(long, default_value = "127.0.0.1:8080")]
+    pub bind: SocketAddr,
+    /// The amounts to dispense to each requester, written as typed values 1.87penumbra, 12cubes,
+    /// etc.
+    #[structopt(long, default_value = "1penumbra")]
+    pub values: Vec<String>,
+    /// How long each address and each IP address must wait between requests, in seconds.
+    ///
+    /// Limits are kept in memory, so they are reset when the faucet restarts.
+    #[structopt(long, default_value = "86400")]
+    pub rate_limit_secs: u64,
+    /// How often to sync the wallet, in seconds.
+    #[structopt(long, default_value = "10")]
+    pub sync_secs: u64,
+    /// The transaction fee (paid in upenumbra) [default: the network profile's fee, or 0].
+    #[structopt(long)]
+    pub fee: Option<u64>,
+}
+
+/// The body of a request to the faucet.
+#[derive(Debug, Deserialize)]
+struct DispenseRequest {
+    address: String,
+}
+
+/// The body of the faucet's response.
+#[derive(Debug, Serialize)]
+struct DispenseResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transaction_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// A request passed from the HTTP server to the task that owns the wallet.
+struct Dispense {
+    address: String,
+    ip: IpAddr,
+    reply: oneshot::Sender<Result<String, (StatusCode, String)>>,
+}
+
+/// When each address and IP address last received funds.
+#[derive(Default)]
+struct RateLimits {
+    /// Keyed by the canonical encoding of the address, so that differently written forms of the
+    /// same address share a limit.
+    addresses: BTreeMap<String, Instant>,
+    ips: BTreeMap<IpAddr, Instant>,
+}
+
+impl RateLimits {
+    /// Forgets the addresses and IP addresses whose limits have expired, so that the maps only
+    /// grow with the number of requesters within one `rate_limit`.
+    fn evict(&mut self, rate_limit: Duration) {
+        self.addresses.retain(|_, last| last.elapsed() < rate_limit);
+        self.ips.retain(|_, last| last.elapsed() < rate_limit);
+    }
+}
+
+impl FaucetCmd {
+    pub fn needs_sync(&self) -> bool {
+        true
+    }
+
+    pub async fn exec(&self, opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
+        let values = self
+            .values
+            .iter()
+            .map(|v| v.parse())
+            .collect::<Result<Vec<Value>, _>>()?;
+
+        // The HTTP server runs on its own, handing requests to the loop below, which is the only
+        // thing that touches the wallet.
+        let (requests_tx, mut requests_rx) = mpsc::channel::<Dispense>(64);
+        let make_service = make_service_fn(move |conn: &AddrStream| {
+            let requests_tx = requests_tx.clone();
+            let ip = conn.remote_addr().ip();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    handle(request, ip, requests_tx.clone())
+                }))
+            }
+        });
+        let server = Server::try_bind(&self.bind)?.serve(make_service);
+        tracing::info!(bind = %self.bind, "serving faucet");
+
+        let dispenser = async {
+            let mut limits = RateLimits::default();
+            let mut interval = tokio::time::interval(Duration::from_secs(self.sync_secs));
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        if let Err(e) = sync(opt, state).await {
+                            tracing::warn!(error = %format!("{:#}", e), "could not sync");
+                        }
+                    }
+                    Some(request) = requests_rx.recv() => {
+                        let result = self
+                            .dispense(
+                                opt,
+                                state,
+                                &mut limits,
+                                &values,
+                                &request.address,
+                                request.ip,
+                            )
+                            .await;
+                        let _ = request.reply.send(result);
+                    }
+                }
+            }
+        };
+
+        tokio::select! {
+            result = server => result?,
+            _ = dispenser => {}
+        }
+        Ok(())
+    }
+
+    /// Sends funds to `address`, if neither it nor `ip` has received any too recently, returning
+    /// the ID of the transaction.
+    async fn dispense(
+        &self,
+        opt: &Opt,
+        state: &mut ClientStateFile,
+        limits: &mut RateLimits,
+        values: &[Value],
+        address: &str,
+        ip: IpAddr,
+    ) -> Result<String, (StatusCode, String)> {
+        let to: Address = address
+            .parse()
+            .map_err(|_| (StatusCode::BAD_REQUEST, "address is invalid".to_string()))?;
+
+        let address = to.to_string();
+
+        let rate_limit = Duration::from_secs(self.rate_limit_secs);
+        limits.evict(rate_limit);
+        let wait = [limits.addresses.get(&address), limits.ips.get(&ip)]
+            .into_iter()
+            .flatten()
+            .map(|last| rate_limit.saturating_sub(last.elapsed()))
+            .max()
+            .unwrap_or_default();
+        if !wait.is_zero() {
+            return Err((
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("try again in {} seconds", wait.as_secs() + 1),
+            ));
+        }
+
+        let fee = Value {
+            amount: self.fee.unwrap_or_else(|| opt.default_fee()),
+            asset_id: *STAKING_TOKEN_ASSET_ID,
+        };
+        let result = opt
+            .build_and_submit_transaction(state, |state| {
+                let transaction = state.build_send(&mut OsRng, values, fee, to, None, None)?;
+                audit::record(state, &transaction, &[to])?;
+                Ok(transaction)
+            })
+            .await
+            .and_then(|transaction| {
+                state.commit()?;
+                Ok(transaction)
+            });
+        match result {
+            Ok(transaction) => {
+                let transaction_id = hex::encode(transaction.id());
+                tracing::info!(%address, %ip, %transaction_id, "dispensed funds");
+                let now = Instant::now();
+                limits.addresses.insert(address, now);
+                limits.ips.insert(ip, now);
+                Ok(transaction_id)
+            }
+            Err(e) => {
+                tracing::error!(
+                    %address,
+                    %ip,
+                    error = %format!("{:#}", e),
+                    "could not dispense funds"
+                );
+                // Forget the spends of the failed transaction, so its notes can be spent again.
+                if let Err(e) = state.reload() {
+                    tracing::error!(error = %format!("{:#}", e), "could not reload wallet");
+                }
+                Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "could not dispense funds".to_string(),
+                ))
+            }
+        }
+    }
+}
+
+/// Handles an HTTP request: `POST /` with a JSON body like `{"address": "penumbrav0t..."}`.
+async fn handle(
+    request: hyper::Request<Body>,
+    ip: IpAddr,
+    requests_tx: mpsc::Sender<Dispense>,
+) -> Result<Response<Body>, Infallible> {
+    if request.method() != Method::POST || request.uri().path() != "/" {
+        return Ok(respond(
+            StatusCode::NOT_FOUND,
+            Err("expected POST /".to_string()),
+        ));
+    }
+
+    let body = match read_body(request.into_body()).await {
+        Ok(body) => body,
+        Err(status) => {
+            return Ok(respond(
+                status,
+                Err(format!(
+                    "expected a body of at most {} bytes",
+                    MAX_BODY_BYTES
+                )),
+            ))
+        }
+    };
+    let request = match serde_json::from_slice::<DispenseRequest>(&body) {
+        Ok(request) => request,
+        Err(_) => {
+            return Ok(respond(
+                StatusCode::BAD_REQUEST,
+                Err("expected a JSON body with an address".to_string()),
+            ))
+        }
+    };
+
+    let (reply, response) = oneshot::channel();
+    let dispense = Dispense {
+        address: request.address,
+        ip,
+        reply,
+    };
+    let result = match requests_tx.send(dispense).await {
+        Ok(()) => response.await.ok(),
+        Err(_) => None,
+    };
+    Ok(match result {
+        Some(Ok(transaction_id)) => respond(StatusCode::OK, Ok(transaction_id)),
+        Some(Err((status, error))) => respond(status, Err(error)),
+        None => respond(
+            StatusCode::SERVICE_UNAVAILABLE,
+            Err("the faucet is shutting down".to_string()),
+        ),
+    })
+}
+
+/// Reads a request body of at most [`MAX_BODY_BYTES`], without buffering any more than that.
+async fn read_body(mut body: Body) -> Result<Vec<u8>, StatusCode> {
+    if body.size_hint().lower() > MAX_BODY_BYTES as u64 {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|_| StatusCode::BAD_REQUEST)?;
+        if bytes.len() + chunk.len() > MAX_BODY_BYTES {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+fn respond(status: StatusCode, result: Result<String, String>) -> Response<Body> {
+    let (transaction_id, error) = match result {
+        Ok(transaction_id) => (Some(transaction_id), None),
+        Err(error) => (None, Some(error)),
+    };
+    let body = serde_json::to_vec(&DispenseResponse {
+        transaction_id,
+        error,
+    })
+    .expect("response serializes");
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body))
+        .expect("response is valid")
+}
@@ -0,0 +1,115 @@
+use anyhow::Result;
+use serde::Serialize;
+use structopt::{
+    clap::{App, Shell},
+    StructOpt,
+};
+
+use crate::Opt;
+
+/// Writes a completion script for the given shell to stdout.
+pub fn completions(shell: Shell) {
+    Opt::clap().gen_completions_to("pcli", shell, &mut std::io::stdout());
+}
+
+/// Describes every command and option, either as JSON (for wrappers and GUIs) or as an indented
+/// list of commands.
+pub fn commands(json: bool) -> Result<()> {
+    let info = CommandInfo::new(&Opt::clap());
+    if json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+    } else {
+        info.print(0);
+    }
+    Ok(())
+}
+
+/// A machine-readable description of a command, generated from the same definitions as the
+/// command-line parser, so that it can't drift from what `pcli` actually accepts.
+#[derive(Debug, Serialize)]
+struct CommandInfo {
+    name: String,
+    about: Option<String>,
+    /// Positional arguments, in order.
+    arguments: Vec<ArgInfo>,
+    options: Vec<ArgInfo>,
+    subcommands: Vec<CommandInfo>,
+}
+
+#[derive(Debug, Serialize)]
+struct ArgInfo {
+    name: String,
+    short: Option<char>,
+    long: Option<String>,
+    help: Option<String>,
+    /// Whether the option takes a value, rather than being a flag.
+    takes_value: bool,
+    default_value: Option<String>,
+    possible_values: Option<Vec<String>>,
+}
+
+impl CommandInfo {
+    // clap 2 has no public API for inspecting an `App`, so this reads the fields its own
+    // completion generators use.
+    fn new(app: &App) -> Self {
+        let p = &app.p;
+
+        let flags = p.flags.iter().map(|flag| ArgInfo {
+            name: flag.b.name.to_string(),
+            short: flag.s.short,
+            long: flag.s.long.map(str::to_string),
+            help: flag.b.help.map(str::to_string),
+            takes_value: false,
+            default_value: None,
+            possible_values: None,
+        });
+        let opts = p.opts.iter().map(|opt| ArgInfo {
+            name: opt.b.name.to_string(),
+            short: opt.s.short,
+            long: opt.s.long.map(str::to_string),
+            help: opt.b.help.map(str::to_string),
+            takes_value: true,
+            default_value: opt.v.default_val.map(|v| v.to_string_lossy().into_owned()),
+            possible_values: opt
+                .v
+                .possible_vals
+                .as_ref()
+                .map(|vals| vals.iter().map(|v| v.to_string()).collect()),
+        });
+        let arguments = p
+            .positionals
+            .values()
+            .map(|pos| ArgInfo {
+                name: pos.b.name.to_string(),
+                short: None,
+                long: None,
+                help: pos.b.help.map(str::to_string),
+                takes_value: true,
+                default_value: pos.v.default_val.map(|v| v.to_string_lossy().into_owned()),
+                possible_values: pos
+                    .v
+                    .possible_vals
+                    .as_ref()
+                    .map(|vals| vals.iter().map(|v| v.to_string()).collect()),
+            })
+            .collect();
+
+        CommandInfo {
+            name: p.meta.name.clone(),
+            about: p.meta.about.map(str::to_string),
+            arguments,
+            options: flags.chain(opts).collect(),
+            subcommands: p.subcommands.iter().map(CommandInfo::new).collect(),
+        }
+    }
+
+    fn print(&self, depth: usize) {
+        match &self.about {
+            Some(about) => println!("{}{}: {}", "  ".repeat(depth), self.name, about),
+            None => println!("{}{}", "  ".repeat(depth), self.name),
+        }
+        for subcommand in &self.subcommands {
+            subcommand.print(depth + 1);
+        }
+    }
+}
@@ -1,18 +1,21 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, time::Duration};
 
 use anyhow::{anyhow, Context, Result};
 use comfy_table::{presets, Table};
 use futures::stream::TryStreamExt;
 use penumbra_crypto::Value;
-use penumbra_proto::{light_wallet::ValidatorInfoRequest, thin_wallet::ValidatorRateRequest};
+use penumbra_proto::{light_wallet::ValidatorInfoRequest, thin_wallet::RewardAccrualRequest};
 use penumbra_stake::{
-    DelegationToken, Epoch, IdentityKey, RateData, ValidatorInfo, STAKING_TOKEN_ASSET_ID,
-    STAKING_TOKEN_DENOM,
+    DelegationToken, Epoch, IdentityKey, ValidatorInfo, STAKING_TOKEN_ASSET_ID, STAKING_TOKEN_DENOM,
 };
 use rand_core::OsRng;
 use structopt::StructOpt;
 
-use crate::{ClientStateFile, Opt};
+use crate::{
+    audit, fetch,
+    redelegation::{self, Finished, Redelegation, Redelegations},
+    ClientStateFile, Opt,
+};
 
 #[derive(Debug, StructOpt)]
 pub enum StakeCmd {
@@ -23,9 +26,9 @@ pub enum StakeCmd {
         to: String,
         /// The amount of stake to delegate.
         amount: String,
-        /// The transaction fee (paid in upenumbra).
-        #[structopt(long, default_value = "0")]
-        fee: u64,
+        /// The transaction fee (paid in upenumbra) [default: the network profile's fee, or 0].
+        #[structopt(long)]
+        fee: Option<u64>,
         /// Optional. Only spend funds originally received by the given address index.
         #[structopt(long)]
         source: Option<u64>,
@@ -34,14 +37,31 @@ pub enum StakeCmd {
     Undelegate {
         /// The amount of delegation tokens to undelegate.
         amount: String,
-        /// The transaction fee (paid in upenumbra).
-        #[structopt(long, default_value = "0")]
-        fee: u64,
+        /// The transaction fee (paid in upenumbra) [default: the network profile's fee, or 0].
+        #[structopt(long)]
+        fee: Option<u64>,
         /// Optional. Only spend funds originally received by the given address index.
         #[structopt(long)]
         source: Option<u64>,
     },
+    /// Withdraw all of this wallet's stake from every validator's delegation pool.
+    UndelegateAll {
+        /// The transaction fee of each undelegation (paid in upenumbra) [default: the network
+        /// profile's fee, or 0].
+        #[structopt(long)]
+        fee: Option<u64>,
+        /// Optional. Only undelegate funds originally received by the given address index.
+        #[structopt(long)]
+        source: Option<u64>,
+    },
     /// Redelegate stake from one validator's delegation pool to another.
+    ///
+    /// The stake moves in a single transaction, without unbonding, but each wallet can only
+    /// redelegate once per unbonding period, and the new delegation tokens from a wallet's first
+    /// redelegation are quarantined like an undelegation.  With `--unbond`, the stake is instead
+    /// undelegated
+    /// now, and delegated to the new validator once it has unbonded, by
+    /// `pcli stake finish-redelegations` or a running `pcli daemon`.
     Redelegate {
         /// The identity key of the validator to withdraw delegation from.
         #[structopt(long)]
@@ -49,17 +69,32 @@ pub enum StakeCmd {
         /// The identity key of the validator to delegate to.
         #[structopt(long)]
         to: String,
-        /// The amount of stake to delegate.
+        /// The amount of delegation tokens to redelegate.
         amount: String,
-        /// The transaction fee (paid in upenumbra).
-        #[structopt(long, default_value = "0")]
-        fee: u64,
+        /// The transaction fee (paid in upenumbra) [default: the network profile's fee, or 0].
+        #[structopt(long)]
+        fee: Option<u64>,
         /// Optional. Only spend funds originally received by the given address index.
         #[structopt(long)]
         source: Option<u64>,
+        /// Undelegate and delegate again once the stake has unbonded, which isn't subject to the
+        /// redelegation cooldown.
+        #[structopt(long)]
+        unbond: bool,
     },
+    /// Delegate the stake of redelegations that has finished unbonding.
+    FinishRedelegations,
     /// Display this wallet's delegations and their value.
     Show,
+    /// Display the staking rewards accrued by this wallet's delegations.
+    ///
+    /// Delegations to validators without rates since the given epoch, such as ones that joined
+    /// later, are skipped with a warning.
+    Rewards {
+        /// The epoch from which to compute accrued rewards (defaults to genesis).
+        #[structopt(long, default_value = "0")]
+        since: u64,
+    },
     /// Display all of the validators participating in the chain.
     ListValidators {
         /// Whether to show validators that are not currently part of the consensus set.
@@ -94,30 +129,25 @@ impl StakeCmd {
 
                 let to = to.parse::<IdentityKey>()?;
 
-                let current_epoch = Epoch::from_height(
-                    state.last_block_height().unwrap() as u64,
-                    state.chain_params().unwrap().epoch_duration,
-                );
-                let next_epoch = current_epoch.next();
-
-                let mut client = opt.thin_wallet_client().await?;
-
-                let rate_data: RateData = client
-                    .validator_rate(tonic::Request::new(ValidatorRateRequest {
-                        identity_key: Some(to.into()),
-                        epoch_index: next_epoch.index,
-                        chain_id: state
-                            .chain_id()
-                            .ok_or_else(|| anyhow!("missing chain_id"))?,
-                    }))
-                    .await?
-                    .into_inner()
-                    .try_into()?;
-
-                let transaction =
-                    state.build_delegate(&mut OsRng, rate_data, unbonded_amount, *fee, *source)?;
-
-                opt.submit_transaction(&transaction).await?;
+                let rate_data = fetch::next_rate_data(opt, state, to).await?;
+
+                // The delegation tokens are sent back to the source address.
+                let (_label, self_address) = state
+                    .wallet()
+                    .address_by_index(source.unwrap_or(0) as usize)?;
+                let fee = fee.unwrap_or_else(|| opt.default_fee());
+                opt.build_and_submit_transaction(state, |state| {
+                    let transaction = state.build_delegate(
+                        &mut OsRng,
+                        rate_data.clone(),
+                        unbonded_amount,
+                        fee,
+                        *source,
+                    )?;
+                    audit::record(state, &transaction, &[self_address])?;
+                    Ok(transaction)
+                })
+                .await?;
                 // Only commit the state if the transaction was submitted successfully,
                 // so that we don't store pending notes that will never appear on-chain.
                 state.commit()?;
@@ -142,41 +172,214 @@ impl StakeCmd {
 
                 let from = delegation_token.validator();
 
-                let current_epoch = Epoch::from_height(
-                    state.last_block_height().unwrap() as u64,
-                    state.chain_params().unwrap().epoch_duration,
-                );
-                let next_epoch = current_epoch.next();
+                let rate_data = fetch::next_rate_data(opt, state, from).await?;
+
+                // The unbonded stake is sent back to the source address.
+                let (_label, self_address) = state
+                    .wallet()
+                    .address_by_index(source.unwrap_or(0) as usize)?;
+                let fee = fee.unwrap_or_else(|| opt.default_fee());
+                opt.build_and_submit_transaction(state, |state| {
+                    let transaction = state.build_undelegate(
+                        &mut OsRng,
+                        rate_data.clone(),
+                        delegation_amount,
+                        fee,
+                        *source,
+                    )?;
+                    audit::record(state, &transaction, &[self_address])?;
+                    Ok(transaction)
+                })
+                .await?;
+                // Only commit the state if the transaction was submitted successfully,
+                // so that we don't store pending notes that will never appear on-chain.
+                state.commit()?;
+            }
+            StakeCmd::UndelegateAll { fee, source } => {
+                let fee = fee.unwrap_or_else(|| opt.default_fee());
+
+                // Undelegate each validator's tokens in a transaction of its own, since an
+                // undelegation only unbonds one validator's delegation pool.
+                let mut delegations = BTreeMap::<IdentityKey, u64>::new();
+                for (address_index, denom, note) in state.unspent_notes() {
+                    let note = match note.as_ready() {
+                        Some(note) => note,
+                        None => continue,
+                    };
+                    if source.map_or(false, |source| source != address_index) {
+                        continue;
+                    }
+                    if let Ok(dt) = DelegationToken::try_from(denom) {
+                        *delegations.entry(dt.validator()).or_default() += note.amount();
+                    }
+                }
+                if delegations.is_empty() {
+                    println!("No delegations to undelegate.");
+                }
 
-                let mut client = opt.thin_wallet_client().await?;
+                let (_label, self_address) = state
+                    .wallet()
+                    .address_by_index(source.unwrap_or(0) as usize)?;
+                for (from, delegation_amount) in delegations {
+                    let rate_data = fetch::next_rate_data(opt, state, from.clone()).await?;
+                    let transaction = opt
+                        .build_and_submit_transaction(state, |state| {
+                            let transaction = state.build_undelegate(
+                                &mut OsRng,
+                                rate_data.clone(),
+                                delegation_amount,
+                                fee,
+                                *source,
+                            )?;
+                            audit::record(state, &transaction, &[self_address])?;
+                            Ok(transaction)
+                        })
+                        .await
+                        .with_context(|| format!("could not undelegate from {}", from))?;
+                    // Commit after each undelegation, so a later failure doesn't forget it.
+                    state.commit()?;
+                    println!(
+                        "Undelegated {} from {} in transaction {}",
+                        Value {
+                            amount: rate_data.unbonded_amount(delegation_amount),
+                            asset_id: *STAKING_TOKEN_ASSET_ID,
+                        }
+                        .try_format(state.asset_cache())
+                        .unwrap(),
+                        from,
+                        hex::encode(transaction.id())
+                    );
+                }
+            }
+            StakeCmd::Redelegate {
+                from,
+                to,
+                amount,
+                fee,
+                source,
+                unbond,
+            } => {
+                let from = from.parse::<IdentityKey>()?;
+                let to = to.parse::<IdentityKey>()?;
+                if from == to {
+                    return Err(anyhow!("can't redelegate to the same validator"));
+                }
 
-                let rate_data: RateData = client
-                    .validator_rate(tonic::Request::new(ValidatorRateRequest {
-                        identity_key: Some(from.into()),
-                        epoch_index: next_epoch.index,
-                        chain_id: state
-                            .chain_id()
-                            .ok_or_else(|| anyhow!("missing chain_id"))?,
-                    }))
-                    .await?
-                    .into_inner()
-                    .try_into()?;
+                let Value {
+                    amount: delegation_amount,
+                    asset_id,
+                } = amount.parse::<Value>()?;
+                if asset_id != from.delegation_token().id() {
+                    return Err(anyhow!(
+                        "the amount to redelegate must be in {}",
+                        from.delegation_token().denom()
+                    ));
+                }
 
-                let transaction = state.build_undelegate(
-                    &mut OsRng,
-                    rate_data,
-                    delegation_amount,
-                    *fee,
-                    *source,
-                )?;
+                let rate_data = fetch::next_rate_data(opt, state, from.clone()).await?;
+                // Check that the new validator exists before undelegating from the old one.
+                let to_rate_data = fetch::next_rate_data(opt, state, to.clone()).await?;
+
+                let (_label, self_address) = state
+                    .wallet()
+                    .address_by_index(source.unwrap_or(0) as usize)?;
+                let fee = fee.unwrap_or_else(|| opt.default_fee());
+
+                if !*unbond {
+                    let transaction = opt
+                        .build_and_submit_transaction(state, |state| {
+                            let transaction = state.build_redelegate(
+                                &mut OsRng,
+                                rate_data.clone(),
+                                to_rate_data.clone(),
+                                delegation_amount,
+                                fee,
+                                *source,
+                            )?;
+                            audit::record(state, &transaction, &[self_address])?;
+                            Ok(transaction)
+                        })
+                        .await?;
+                    state.commit()?;
+                    println!(
+                        "Redelegated from {} to {} in transaction {}",
+                        from,
+                        to,
+                        hex::encode(transaction.id())
+                    );
+                    return Ok(());
+                }
 
-                opt.submit_transaction(&transaction).await?;
-                // Only commit the state if the transaction was submitted successfully,
-                // so that we don't store pending notes that will never appear on-chain.
+                let mut unbonded = None;
+                opt.build_and_submit_transaction(state, |state| {
+                    let (transaction, note) = redelegation::build_undelegation(state, |state| {
+                        state.build_undelegate(
+                            &mut OsRng,
+                            rate_data.clone(),
+                            delegation_amount,
+                            fee,
+                            *source,
+                        )
+                    })?;
+                    audit::record(state, &transaction, &[self_address])?;
+                    unbonded = Some(note);
+                    Ok(transaction)
+                })
+                .await?;
                 state.commit()?;
+
+                let unbonded = unbonded.expect("the submitted transaction was built");
+                Redelegations::open(state)?.push(Redelegation {
+                    from,
+                    to: to.clone(),
+                    note_commitment: unbonded.commit().into(),
+                    amount: unbonded.amount(),
+                    fee,
+                    source: *source,
+                })?;
+                let release_height = state
+                    .unbonding_notes()
+                    .find(|(_, note)| note.commit() == unbonded.commit())
+                    .map(|(release_height, _)| release_height)
+                    .expect("the unbonded note was registered");
+                let eta = time_until(opt, state, release_height)
+                    .await
+                    .map(|eta| format!(" (in {})", eta))
+                    .unwrap_or_default();
+                println!(
+                    "Undelegated {}; it will be delegated to {} once it unbonds at height {}{}, by \
+                     `pcli stake finish-redelegations` or `pcli daemon`.",
+                    unbonded.value().try_format(state.asset_cache()).unwrap(),
+                    to,
+                    release_height,
+                    eta
+                );
             }
-            StakeCmd::Redelegate { .. } => {
-                todo!()
+            StakeCmd::FinishRedelegations => {
+                let mut redelegations = Redelegations::open(state)?;
+                for (redelegation, result) in redelegations.finish(opt, state).await? {
+                    match result {
+                        Ok(Finished::Delegated(transaction_id)) => println!(
+                            "Redelegated from {} to {} in transaction {}",
+                            redelegation.from, redelegation.to, transaction_id
+                        ),
+                        Ok(Finished::Abandoned) => println!(
+                            "Abandoned redelegation from {} to {}: the unbonded stake is no \
+                             longer available",
+                            redelegation.from, redelegation.to
+                        ),
+                        Err(e) => println!(
+                            "Could not redelegate from {} to {}: {:#}",
+                            redelegation.from, redelegation.to, e
+                        ),
+                    }
+                }
+                for redelegation in redelegations.pending() {
+                    println!(
+                        "Redelegation from {} to {} is still unbonding",
+                        redelegation.from, redelegation.to
+                    );
+                }
             }
             StakeCmd::Show => {
                 let mut client = opt.light_wallet_client().await?;
@@ -261,6 +464,39 @@ impl StakeCmd {
                     unbonded.try_format(state.asset_cache()).unwrap(),
                 ]);
 
+                // Undelegated stake can't be spent until it's released from quarantine.
+                let epoch_duration = state
+                    .chain_params()
+                    .expect("chain params were fetched")
+                    .epoch_duration;
+                let mut unbonding = BTreeMap::<u64, u64>::new();
+                for (release_height, note) in state.unbonding_notes() {
+                    *unbonding.entry(release_height).or_default() += note.amount();
+                }
+                for (release_height, amount) in unbonding {
+                    let unbonding = Value {
+                        amount,
+                        asset_id: *STAKING_TOKEN_ASSET_ID,
+                    };
+                    total += unbonding.amount;
+                    let eta = time_until(opt, state, release_height)
+                        .await
+                        .map(|eta| format!(", in {}", eta))
+                        .unwrap_or_default();
+
+                    table.add_row(vec![
+                        format!(
+                            "Unbonding (until height {}, end of epoch {}{})",
+                            release_height,
+                            Epoch::from_height(release_height, epoch_duration).index,
+                            eta
+                        ),
+                        unbonding.try_format(state.asset_cache()).unwrap(),
+                        format!("{:.4}", 1.0),
+                        unbonding.try_format(state.asset_cache()).unwrap(),
+                    ]);
+                }
+
                 let total = Value {
                     amount: total,
                     asset_id: *STAKING_TOKEN_ASSET_ID,
@@ -274,6 +510,82 @@ impl StakeCmd {
                 ]);
                 println!("{}", table);
             }
+            StakeCmd::Rewards { since } => {
+                let mut client = opt.thin_wallet_client().await?;
+                let chain_id = state.chain_id().unwrap_or_default();
+
+                let mut table = Table::new();
+                table.load_preset(presets::NOTHING);
+                table.set_header(vec!["Tokens", "Value Then", "Value Now", "Accrued"]);
+
+                let staking_value = |amount: u64| {
+                    Value {
+                        amount,
+                        asset_id: *STAKING_TOKEN_ASSET_ID,
+                    }
+                    .try_format(state.asset_cache())
+                    .unwrap()
+                };
+
+                let mut total = 0;
+                for (denom, notes_by_address) in state.unspent_notes_by_denom_and_address() {
+                    let dt = if let Ok(dt) = DelegationToken::try_from(denom.clone()) {
+                        dt
+                    } else {
+                        continue;
+                    };
+
+                    let delegation = Value {
+                        amount: notes_by_address
+                            .values()
+                            .flat_map(|notes| notes.iter().map(|n| n.as_ref().amount()))
+                            .sum::<u64>(),
+                        asset_id: dt.id(),
+                    };
+
+                    let accrual = match client
+                        .reward_accrual(RewardAccrualRequest {
+                            chain_id: chain_id.clone(),
+                            identity_key: Some(dt.validator().into()),
+                            delegation_amount: delegation.amount,
+                            start_epoch_index: *since,
+                            end_epoch_index: 0,
+                        })
+                        .await
+                    {
+                        Ok(accrual) => accrual.into_inner(),
+                        // The validator has no rates at one end of the range, e.g. because it
+                        // joined after `since`, or migrated to a new identity key since.
+                        Err(status) if status.code() == tonic::Code::NotFound => {
+                            eprintln!(
+                                "Warning: skipping the delegation to {}, which has no rates \
+                                 between epoch {} and now.",
+                                dt.validator(),
+                                since,
+                            );
+                            continue;
+                        }
+                        Err(status) => return Err(status.into()),
+                    };
+
+                    table.add_row(vec![
+                        delegation.try_format(state.asset_cache()).unwrap(),
+                        staking_value(accrual.start_unbonded_amount),
+                        staking_value(accrual.end_unbonded_amount),
+                        staking_value(accrual.accrued_amount),
+                    ]);
+
+                    total += accrual.accrued_amount;
+                }
+
+                table.add_row(vec![
+                    "Total".to_string(),
+                    String::new(),
+                    String::new(),
+                    staking_value(total),
+                ]);
+                println!("{}", table);
+            }
             StakeCmd::ListValidators {
                 show_inactive,
                 detailed,
@@ -346,3 +658,31 @@ impl StakeCmd {
         Ok(())
     }
 }
+
+/// Describes roughly how long until the chain reaches `height`, like "~3d 4h", if the node can
+/// estimate it.
+async fn time_until(opt: &Opt, state: &ClientStateFile, height: u64) -> Option<String> {
+    match fetch::time_until(opt, state, height).await {
+        Ok(duration) => Some(format_time_until(duration)),
+        Err(e) => {
+            // Older nodes can't estimate block times; the height alone will have to do.
+            tracing::debug!(?e, "could not estimate time until height {}", height);
+            None
+        }
+    }
+}
+
+/// Formats a duration to its two largest units, which is as precise as block time estimates get.
+fn format_time_until(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    let (days, hours, minutes) = (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+    if days > 0 {
+        format!("~{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("~{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("~{}m", minutes)
+    } else {
+        "<1m".to_string()
+    }
+}
@@ -1,10 +1,15 @@
+use std::collections::BTreeSet;
+
 use anyhow::{anyhow, Result};
-use penumbra_crypto::{memo, merkle::TreeExt, Value};
+use penumbra_crypto::{asset, memo, merkle::TreeExt, Value};
+use penumbra_proto::thin_wallet::NullifierStatusRequest;
+use penumbra_stake::STAKING_TOKEN_ASSET_ID;
 use penumbra_transaction::Transaction;
 use rand_core::OsRng;
+use sha2::{Digest, Sha256};
 use structopt::StructOpt;
 
-use crate::{ClientStateFile, Opt};
+use crate::{audit, fetch, ClientStateFile, Opt};
 
 #[derive(Debug, StructOpt)]
 pub enum TxCmd {
@@ -15,9 +20,14 @@ pub enum TxCmd {
         to: String,
         /// The amounts to send, written as typed values 1.87penumbra, 12cubes, etc.
         values: Vec<String>,
-        /// The transaction fee (paid in upenumbra).
-        #[structopt(long, default_value = "0")]
-        fee: u64,
+        /// The transaction fee (paid in upenumbra) [default: the network profile's fee, or 0].
+        #[structopt(long)]
+        fee: Option<u64>,
+        /// The denomination to pay the fee in, if not the staking token.
+        ///
+        /// Only denominations with a fee rate in the chain parameters are accepted.
+        #[structopt(long)]
+        fee_denom: Option<String>,
         /// Optional. Only spend funds originally received by the given address index.
         #[structopt(long)]
         source: Option<u64>,
@@ -34,6 +44,15 @@ pub enum TxCmd {
     ///
     /// Currently, only zero-fee sweep transactions are implemented.
     Sweep,
+    /// Reports whether a transaction is pending, was confirmed, or was rejected.
+    ///
+    /// The mempool is checked first, then the node's transaction index, and
+    /// finally, for transactions recorded in this wallet's audit log, whether
+    /// the notes they spent were spent on-chain.
+    Status {
+        /// The transaction ID, in hex.
+        id: String,
+    },
 }
 
 impl TxCmd {
@@ -42,6 +61,7 @@ impl TxCmd {
         match self {
             TxCmd::Send { .. } => true,
             TxCmd::Sweep { .. } => true,
+            TxCmd::Status { .. } => false,
         }
     }
 
@@ -51,6 +71,7 @@ impl TxCmd {
                 values,
                 to,
                 fee,
+                fee_denom,
                 source: from,
                 memo,
             } => {
@@ -63,10 +84,23 @@ impl TxCmd {
                     .parse()
                     .map_err(|_| anyhow::anyhow!("address is invalid"))?;
 
-                let transaction =
-                    state.build_send(&mut OsRng, &values, *fee, to, *from, memo.clone())?;
-
-                opt.submit_transaction(&transaction).await?;
+                let fee = Value {
+                    amount: fee.unwrap_or_else(|| opt.default_fee()),
+                    asset_id: match fee_denom {
+                        Some(denom) => asset::REGISTRY
+                            .parse_denom(denom)
+                            .ok_or_else(|| anyhow!("invalid fee denomination {}", denom))?
+                            .id(),
+                        None => *STAKING_TOKEN_ASSET_ID,
+                    },
+                };
+                opt.build_and_submit_transaction(state, |state| {
+                    let transaction =
+                        state.build_send(&mut OsRng, &values, fee, to, *from, memo.clone())?;
+                    audit::record(state, &transaction, &[to])?;
+                    Ok(transaction)
+                })
+                .await?;
                 // Only commit the state if the transaction was submitted
                 // successfully, so that we don't store pending notes that will
                 // never appear on-chain.
@@ -75,11 +109,146 @@ impl TxCmd {
             TxCmd::Sweep => {
                 sweep(opt, state).await?;
             }
+            TxCmd::Status { id } => {
+                let id: [u8; 32] = hex::decode(id)?
+                    .try_into()
+                    .map_err(|_| anyhow!("transaction ID must be 32 bytes"))?;
+                println!("{}", status(opt, state, id).await?);
+            }
         }
         Ok(())
     }
 }
 
+/// What became of a transaction.
+enum TxStatus {
+    /// The transaction is in the node's mempool.
+    Pending,
+    /// The transaction was included in a block, and executed.
+    Confirmed { height: u64 },
+    /// The transaction was included in a block, but failed to execute.
+    Rejected { height: u64, code: i64, log: String },
+    /// Some of the notes the transaction spends were spent by another transaction.
+    Conflicted,
+    /// The transaction is neither pending nor on-chain, so it was dropped.
+    Dropped,
+    /// The transaction isn't pending, and can't be found on-chain.
+    Unknown,
+}
+
+impl std::fmt::Display for TxStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TxStatus::Pending => write!(f, "pending in the mempool"),
+            TxStatus::Confirmed { height } => write!(f, "confirmed at height {}", height),
+            TxStatus::Rejected { height, code, log } => write!(
+                f,
+                "rejected at height {} with code {}: {}",
+                height, code, log
+            ),
+            TxStatus::Conflicted => write!(
+                f,
+                "rejected: some of its notes were spent by another transaction"
+            ),
+            TxStatus::Dropped => write!(
+                f,
+                "dropped: it is not pending, and none of its notes were spent, \
+                 so they can be spent again"
+            ),
+            TxStatus::Unknown => write!(
+                f,
+                "unknown: it is not pending, the node has no record of it, and \
+                 it is not in this wallet's audit log"
+            ),
+        }
+    }
+}
+
+/// Determines what became of the transaction with the given ID.
+async fn status(opt: &Opt, state: &ClientStateFile, id: [u8; 32]) -> Result<TxStatus> {
+    // Transaction IDs are the SHA-256 hash of the encoded transaction, which is also how
+    // Tendermint identifies them.
+    let rpc = format!("http://{}:{}", opt.node(), opt.rpc_port());
+    let client = reqwest::Client::new();
+
+    let mempool: serde_json::Value = client
+        .get(format!("{}/unconfirmed_txs", rpc))
+        .query(&[("limit", "100")])
+        .send()
+        .await?
+        .json()
+        .await?;
+    let pending = mempool["result"]["txs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|tx| base64::decode(tx.as_str()?).ok())
+        .any(|tx| Sha256::digest(&tx).as_slice() == id);
+    if pending {
+        return Ok(TxStatus::Pending);
+    }
+
+    // Tendermint's transaction index may be disabled, so a miss here isn't conclusive.
+    let indexed: serde_json::Value = client
+        .get(format!("{}/tx", rpc))
+        .query(&[("hash", format!("0x{}", hex::encode(id)))])
+        .send()
+        .await?
+        .json()
+        .await?;
+    if let Some(height) = indexed["result"]["height"]
+        .as_str()
+        .and_then(|height| height.parse().ok())
+    {
+        let result = &indexed["result"]["tx_result"];
+        let code = result["code"].as_i64().unwrap_or_default();
+        return Ok(if code == 0 {
+            TxStatus::Confirmed { height }
+        } else {
+            TxStatus::Rejected {
+                height,
+                code,
+                log: result["log"].as_str().unwrap_or_default().to_string(),
+            }
+        });
+    }
+
+    // Otherwise, a transaction we built was confirmed exactly when all of its nullifiers were
+    // revealed in the same block.
+    let record = match audit::AuditLog::open(state)
+        .records()?
+        .into_iter()
+        .find(|record| record.transaction_id == id)
+    {
+        Some(record) => record,
+        None => return Ok(TxStatus::Unknown),
+    };
+    let mut client = opt.thin_wallet_client().await?;
+    let mut heights = BTreeSet::new();
+    let mut unspent = 0;
+    for spend in &record.spends {
+        let status = client
+            .nullifier_status(NullifierStatusRequest {
+                chain_id: record.chain_id.clone(),
+                nullifier: spend.nullifier.to_vec(),
+            })
+            .await?
+            .into_inner();
+        if status.spent {
+            heights.insert(status.height);
+        } else {
+            unspent += 1;
+        }
+    }
+    Ok(match (heights.len(), unspent) {
+        (0, _) => TxStatus::Dropped,
+        (1, 0) => TxStatus::Confirmed {
+            height: *heights.iter().next().unwrap(),
+        },
+        _ => TxStatus::Conflicted,
+    })
+}
+
 // This code is done outside of the client state as a test case for whether it's
 // possible to use that interface to implement bespoke note handling.
 //
@@ -99,6 +268,7 @@ async fn sweep(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
     // changes to be applied later.
     let mut spent_notes = Vec::new();
     let mut change_notes = Vec::new();
+    fetch::note_witnesses(opt, state).await?;
     let unspent = state.unspent_notes_by_address_and_denom();
     for (id, label, addr) in state.wallet().addresses() {
         if unspent.get(&(id as u64)).is_none() {
@@ -119,6 +289,7 @@ async fn sweep(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
                 tracing::info!(?denom, "building sweep transaction");
                 let mut tx_builder =
                     Transaction::build_with_root(state.note_commitment_tree().root2());
+                tx_builder.set_padding(state.padding());
                 tx_builder.set_fee(0).set_chain_id(
                     state
                         .chain_id()
@@ -126,12 +297,12 @@ async fn sweep(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
                 );
 
                 for note in group {
-                    tx_builder.add_spend(
+                    tx_builder.add_spend_with_path(
                         &mut OsRng,
-                        state.note_commitment_tree(),
+                        state.note_witness(note)?,
                         state.wallet().spend_key(),
                         (*note).clone(),
-                    )?;
+                    );
                     spent_notes.push((*note).clone());
                 }
                 let change = tx_builder.add_output_producing_note(
@@ -146,9 +317,11 @@ async fn sweep(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
                 );
                 change_notes.push(change);
 
-                transactions.push(tx_builder.finalize(&mut OsRng).map_err(|err| {
+                let transaction = tx_builder.finalize(&mut OsRng).map_err(|err| {
                     anyhow::anyhow!("error during transaction finalization: {}", err)
-                })?);
+                })?;
+                audit::record(state, &transaction, &[addr])?;
+                transactions.push(transaction);
             }
         }
     }
@@ -0,0 +1,383 @@
+use std::{collections::BTreeMap, net::SocketAddr, pin::Pin, time::Duration};
+
+use anyhow::Result;
+use futures::StreamExt;
+use penumbra_crypto::{asset, Address, Note, Value};
+use penumbra_proto::view::{
+    intent,
+    view_server::{View, ViewServer},
+    AssetBalance, BalancesRequest, NoteRecord, NoteStatus, NotesRequest, PlanTransactionRequest,
+    QuarantinedNote, QuarantinedNotesRequest, TransactionPlan,
+};
+use penumbra_stake::{IdentityKey, STAKING_TOKEN_ASSET_ID};
+use penumbra_wallet::{ClientState, Intent, UnspentNote};
+use rand_core::OsRng;
+use structopt::StructOpt;
+use tokio::sync::{mpsc, oneshot};
+use tonic::Status;
+
+use crate::{fetch, sync, ClientStateFile, Opt};
+
+#[derive(Debug, StructOpt)]
+pub struct ViewCmd {
+    /// The address to serve the view service on.
+    ///
+    /// The service reveals everything about the wallet's holdings to anyone who can reach it, so
+    /// it should only be bound to a local address.
+    #[structopt(long, default_value = "127.0.0.1:8081")]
+    pub bind: SocketAddr,
+    /// How often to sync the wallet, in seconds.
+    #[structopt(long, default_value = "10")]
+    pub sync_secs: u64,
+}
+
+/// A request passed from the gRPC server to the task that owns the wallet.
+enum Request {
+    /// A query, which answers itself by sending the result back to the server.
+    Query(Box<dyn FnOnce(&ClientState) + Send>),
+    /// A transaction to plan, which may need the rates of validators to delegate to, and where
+    /// to send the plan.
+    Plan(Plan, oneshot::Sender<Result<TransactionPlan, Status>>),
+}
+
+/// A transaction to plan, with delegations only naming their validator, since rates are fetched
+/// by the task that owns the wallet.
+struct Plan {
+    sends: Vec<(Address, Vec<Value>, Option<String>)>,
+    delegations: Vec<(IdentityKey, u64)>,
+    fee: Value,
+    source_address: Option<u64>,
+}
+
+impl Plan {
+    /// Plans the transaction with the wallet's notes, fetching the next rates of each validator to
+    /// delegate to.
+    async fn plan(self, opt: &Opt, state: &mut ClientStateFile) -> Result<TransactionPlan, Status> {
+        let mut intents = self
+            .sends
+            .into_iter()
+            .map(|(address, values, memo)| Intent::Send {
+                address,
+                values,
+                memo,
+            })
+            .collect::<Vec<_>>();
+        for (identity_key, unbonded_amount) in self.delegations {
+            let rate_data = fetch::next_rate_data(opt, state, identity_key)
+                .await
+                .map_err(|e| Status::unavailable(format!("could not fetch rates: {:#}", e)))?;
+            intents.push(Intent::Delegate {
+                rate_data,
+                unbonded_amount,
+            });
+        }
+
+        // Plans give the positions of the notes they spend, which we only know for notes we aren't
+        // witnessing ourselves once we've fetched their paths.
+        fetch::note_witnesses(opt, state)
+            .await
+            .map_err(|e| Status::unavailable(format!("could not fetch note witnesses: {:#}", e)))?;
+
+        state
+            .plan_transaction(&mut OsRng, intents, self.fee, self.source_address)
+            .map(Into::into)
+            .map_err(|e| Status::failed_precondition(format!("{:#}", e)))
+    }
+}
+
+impl ViewCmd {
+    pub fn needs_sync(&self) -> bool {
+        true
+    }
+
+    pub async fn exec(&self, opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
+        // As in the faucet, the server runs on its own, handing queries to the loop below, which
+        // is the only thing that touches the wallet.
+        let (requests_tx, mut requests_rx) = mpsc::channel::<Request>(64);
+        let server = tonic::transport::Server::builder()
+            .add_service(ViewServer::new(ViewService { requests_tx }))
+            .serve(self.bind);
+        tracing::info!(bind = %self.bind, "serving view service");
+
+        let responder = async {
+            let mut interval = tokio::time::interval(Duration::from_secs(self.sync_secs));
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        if let Err(e) = sync(opt, state).await {
+                            tracing::warn!(error = %format!("{:#}", e), "could not sync");
+                            continue;
+                        }
+                        if let Err(e) = fetch::assets(opt, state).await {
+                            tracing::warn!(error = %format!("{:#}", e), "could not fetch assets");
+                        }
+                        if let Err(e) = state.commit() {
+                            tracing::error!(error = %format!("{:#}", e), "could not save wallet");
+                        }
+                    }
+                    Some(request) = requests_rx.recv() => match request {
+                        Request::Query(query) => query(state),
+                        Request::Plan(plan, reply) => {
+                            let _ = reply.send(plan.plan(opt, state).await);
+                        }
+                    },
+                }
+            }
+        };
+
+        tokio::select! {
+            result = server => result?,
+            _ = responder => {}
+        }
+        Ok(())
+    }
+}
+
+struct ViewService {
+    requests_tx: mpsc::Sender<Request>,
+}
+
+impl ViewService {
+    /// Answers a query against the wallet, once the task that owns it gets to it.
+    async fn query<T, F>(&self, query: F) -> Result<T, Status>
+    where
+        T: Send + 'static,
+        F: FnOnce(&ClientState) -> T + Send + 'static,
+    {
+        let (reply, response) = oneshot::channel();
+        self.requests_tx
+            .send(Request::Query(Box::new(move |state| {
+                let _ = reply.send(query(state));
+            })))
+            .await
+            .map_err(|_| Status::unavailable("the view service is shutting down"))?;
+        response
+            .await
+            .map_err(|_| Status::unavailable("the view service is shutting down"))
+    }
+}
+
+type ViewStream<T> = Pin<Box<dyn futures::Stream<Item = Result<T, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl View for ViewService {
+    type BalancesStream = ViewStream<AssetBalance>;
+    type NotesStream = ViewStream<NoteRecord>;
+    type QuarantinedNotesStream = ViewStream<QuarantinedNote>;
+
+    async fn balances(
+        &self,
+        request: tonic::Request<BalancesRequest>,
+    ) -> Result<tonic::Response<Self::BalancesStream>, Status> {
+        let request = request.into_inner();
+        let asset_id = parse_asset_id(request.asset_id)?;
+        let balances = self
+            .query(move |state| tally_balances(state, asset_id, request.by_address))
+            .await?;
+        Ok(tonic::Response::new(
+            futures::stream::iter(balances.into_iter().map(Ok)).boxed(),
+        ))
+    }
+
+    async fn notes(
+        &self,
+        request: tonic::Request<NotesRequest>,
+    ) -> Result<tonic::Response<Self::NotesStream>, Status> {
+        let request = request.into_inner();
+        let asset_id = parse_asset_id(request.asset_id)?;
+        let notes = self
+            .query(move |state| {
+                state
+                    .unspent_notes()
+                    .filter(|(_, _, note)| request.include_submitted || note.as_ready().is_some())
+                    .filter(|(_, denom, note)| {
+                        asset_id.map_or(true, |id| denom.id() == id)
+                            && note.as_ref().amount() >= request.min_amount
+                    })
+                    .map(|(address_index, denom, note)| {
+                        let status = match note {
+                            UnspentNote::Ready(_) => NoteStatus::Ready,
+                            UnspentNote::SubmittedSpend(_) => NoteStatus::SubmittedSpend,
+                            UnspentNote::SubmittedChange(_) => NoteStatus::SubmittedChange,
+                        };
+                        NoteRecord {
+                            note_commitment: Some(note.as_ref().commit().into()),
+                            value: Some(note.as_ref().value().into()),
+                            denom: Some(denom.into()),
+                            address_index,
+                            status: status as i32,
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .await?;
+        Ok(tonic::Response::new(
+            futures::stream::iter(notes.into_iter().map(Ok)).boxed(),
+        ))
+    }
+
+    async fn quarantined_notes(
+        &self,
+        request: tonic::Request<QuarantinedNotesRequest>,
+    ) -> Result<tonic::Response<Self::QuarantinedNotesStream>, Status> {
+        let asset_id = parse_asset_id(request.into_inner().asset_id)?;
+        let notes = self
+            .query(move |state| {
+                state
+                    .unbonding_notes()
+                    .filter(|(_, note)| asset_id.map_or(true, |id| note.asset_id() == id))
+                    .map(|(release_height, note)| QuarantinedNote {
+                        note_commitment: Some(note.commit().into()),
+                        value: Some(note.value().into()),
+                        denom: state
+                            .asset_cache()
+                            .get(&note.asset_id())
+                            .cloned()
+                            .map(Into::into),
+                        address_index: state.address_index(note),
+                        release_height,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .await?;
+        Ok(tonic::Response::new(
+            futures::stream::iter(notes.into_iter().map(Ok)).boxed(),
+        ))
+    }
+
+    async fn plan_transaction(
+        &self,
+        request: tonic::Request<PlanTransactionRequest>,
+    ) -> Result<tonic::Response<TransactionPlan>, Status> {
+        let request = request.into_inner();
+
+        let mut sends = Vec::new();
+        let mut delegations = Vec::new();
+        for intent in request.intents {
+            match intent.intent {
+                Some(intent::Intent::Send(send)) => {
+                    let address = send
+                        .address
+                        .ok_or_else(|| Status::invalid_argument("missing address"))?
+                        .try_into()
+                        .map_err(|_| Status::invalid_argument("invalid address"))?;
+                    let values = send
+                        .values
+                        .into_iter()
+                        .map(Value::try_from)
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| Status::invalid_argument("invalid value"))?;
+                    let memo = (!send.memo.is_empty()).then(|| send.memo);
+                    sends.push((address, values, memo));
+                }
+                Some(intent::Intent::Delegate(delegate)) => {
+                    let identity_key = delegate
+                        .validator_identity
+                        .ok_or_else(|| Status::invalid_argument("missing validator identity"))?
+                        .try_into()
+                        .map_err(|_| Status::invalid_argument("invalid validator identity"))?;
+                    delegations.push((identity_key, delegate.unbonded_amount));
+                }
+                None => return Err(Status::invalid_argument("missing intent")),
+            }
+        }
+        let fee = request
+            .fee
+            .map(Value::try_from)
+            .transpose()
+            .map_err(|_| Status::invalid_argument("invalid fee"))?
+            .unwrap_or(Value {
+                amount: 0,
+                asset_id: *STAKING_TOKEN_ASSET_ID,
+            });
+
+        let (reply, response) = oneshot::channel();
+        self.requests_tx
+            .send(Request::Plan(
+                Plan {
+                    sends,
+                    delegations,
+                    fee,
+                    source_address: request.source_address,
+                },
+                reply,
+            ))
+            .await
+            .map_err(|_| Status::unavailable("the view service is shutting down"))?;
+        response
+            .await
+            .map_err(|_| Status::unavailable("the view service is shutting down"))?
+            .map(tonic::Response::new)
+    }
+}
+
+fn parse_asset_id(
+    asset_id: Option<penumbra_proto::crypto::AssetId>,
+) -> Result<Option<asset::Id>, Status> {
+    asset_id
+        .map(asset::Id::try_from)
+        .transpose()
+        .map_err(|_| Status::invalid_argument("invalid asset id"))
+}
+
+/// Tallies the wallet's balance of each asset, or only of `asset_id` if it is given, optionally
+/// broken down by address.
+fn tally_balances(
+    state: &ClientState,
+    asset_id: Option<asset::Id>,
+    by_address: bool,
+) -> Vec<AssetBalance> {
+    let mut balances = BTreeMap::<(asset::Id, u64), AssetBalance>::new();
+    for (address_index, _, note) in state.unspent_notes() {
+        if asset_id.map_or(false, |id| note.as_ref().asset_id() != id) {
+            continue;
+        }
+        let balance = balance_entry(
+            &mut balances,
+            state,
+            note.as_ref(),
+            address_index,
+            by_address,
+        );
+        *match note {
+            UnspentNote::Ready(_) => &mut balance.available,
+            UnspentNote::SubmittedSpend(_) => &mut balance.submitted_spend,
+            UnspentNote::SubmittedChange(_) => &mut balance.submitted_change,
+        } += note.as_ref().amount();
+    }
+    for (_, note) in state.unbonding_notes() {
+        if asset_id.map_or(false, |id| note.asset_id() != id) {
+            continue;
+        }
+        let address_index = state.address_index(note);
+        balance_entry(&mut balances, state, note, address_index, by_address).quarantined +=
+            note.amount();
+    }
+
+    balances.into_values().collect()
+}
+
+/// Returns the balance that `note` counts towards.
+///
+/// Without a breakdown by address, every note counts towards the balance of the first address.
+fn balance_entry<'a>(
+    balances: &'a mut BTreeMap<(asset::Id, u64), AssetBalance>,
+    state: &ClientState,
+    note: &Note,
+    address_index: u64,
+    by_address: bool,
+) -> &'a mut AssetBalance {
+    let address_index = if by_address { address_index } else { 0 };
+    balances
+        .entry((note.asset_id(), address_index))
+        .or_insert_with(|| AssetBalance {
+            asset_id: Some(note.asset_id().into()),
+            denom: state
+                .asset_cache()
+                .get(&note.asset_id())
+                .cloned()
+                .map(Into::into),
+            address_index,
+            ..Default::default()
+        })
+}
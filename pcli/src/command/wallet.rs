@@ -9,7 +9,7 @@ use serde::Deserialize;
 use sha2::{Digest, Sha256};
 use structopt::StructOpt;
 
-use crate::ClientStateFile;
+use crate::{state, ClientStateFile};
 
 #[derive(Debug, StructOpt)]
 pub enum WalletCmd {
@@ -20,12 +20,29 @@ pub enum WalletCmd {
     },
     /// Export the spend seed for the wallet.
     Export,
+    /// Export the full viewing key for the wallet, which can view all incoming
+    /// and outgoing transactions, but cannot spend funds.
+    ExportFullViewingKey,
+    /// Export the incoming viewing key for the wallet, which can view incoming
+    /// transactions only.
+    ExportIncomingViewingKey,
+    /// Export the address with the given index, whether or not it has a local label.
+    ExportAddress {
+        /// The diversifier index of the address.
+        index: u64,
+    },
     /// Generate a new spend seed.
     Generate,
     /// Keep the spend seed, but reset all other client state.
     Reset,
     /// Delete the entire wallet permanently.
     Delete,
+    /// Copy a wallet file into a new wallet database, which is committed to incrementally rather
+    /// than rewritten in full.
+    Migrate {
+        /// The path of the new wallet database, which must have a `.sqlite` extension.
+        to: PathBuf,
+    },
 }
 
 impl WalletCmd {
@@ -34,13 +51,17 @@ impl WalletCmd {
         match self {
             WalletCmd::Import { .. } => false,
             WalletCmd::Export => false,
+            WalletCmd::ExportFullViewingKey => false,
+            WalletCmd::ExportIncomingViewingKey => false,
+            WalletCmd::ExportAddress { .. } => false,
             WalletCmd::Generate => false,
             WalletCmd::Reset => false,
             WalletCmd::Delete => false,
+            WalletCmd::Migrate { .. } => false,
         }
     }
 
-    pub fn exec(&self, wallet_path: PathBuf) -> Result<()> {
+    pub fn exec(&self, wallet_path: PathBuf, wait_for_lock: bool) -> Result<()> {
         // Dispatch on the wallet command and return a new state if the command required a
         // wallet state to be saved to disk
         let state = match self {
@@ -53,11 +74,29 @@ impl WalletCmd {
             }
             // The rest of these commands don't require a wallet state to be saved to disk:
             WalletCmd::Export => {
-                let state = ClientStateFile::load(wallet_path.clone())?;
-                let seed = state.wallet().spend_key().seed().clone();
+                let wallet = ClientStateFile::load_wallet(wallet_path.clone(), wait_for_lock)?;
+                let seed = wallet.spend_key().seed().clone();
                 println!("{}", hex::encode(&seed.0));
                 None
             }
+            WalletCmd::ExportFullViewingKey => {
+                let wallet = ClientStateFile::load_wallet(wallet_path.clone(), wait_for_lock)?;
+                println!("{}", wallet.full_viewing_key());
+                None
+            }
+            WalletCmd::ExportIncomingViewingKey => {
+                let wallet = ClientStateFile::load_wallet(wallet_path.clone(), wait_for_lock)?;
+                println!("{}", wallet.incoming_viewing_key());
+                None
+            }
+            WalletCmd::ExportAddress { index } => {
+                let wallet = ClientStateFile::load_wallet(wallet_path.clone(), wait_for_lock)?;
+                let (address, _dtk) = wallet
+                    .incoming_viewing_key()
+                    .payment_address((*index).into());
+                println!("{}", address);
+                None
+            }
             WalletCmd::Delete => {
                 if wallet_path.is_file() {
                     std::fs::remove_file(&wallet_path)?;
@@ -75,6 +114,16 @@ impl WalletCmd {
                 }
                 None
             }
+            WalletCmd::Reset if state::is_sqlite(&wallet_path) => {
+                tracing::info!("resetting client state");
+
+                // A wallet database is reset in a single transaction, so there's no need for a
+                // temporary copy.
+                let wallet = ClientStateFile::load_wallet(wallet_path.clone(), wait_for_lock)?;
+                ClientStateFile::save(ClientState::new(wallet), wallet_path, wait_for_lock)?;
+
+                None
+            }
             WalletCmd::Reset => {
                 tracing::info!("resetting client state");
 
@@ -106,13 +155,45 @@ impl WalletCmd {
                 tracing::debug!("checking that we can deserialize fresh client state");
 
                 // Check that we can successfully parse the result from disk
-                ClientStateFile::load(tmp_path.clone()).context("can't parse wallet after attempting to reset: refusing to overwrite existing wallet file")?;
+                ClientStateFile::load(tmp_path.clone(), wait_for_lock).context("can't parse wallet after attempting to reset: refusing to overwrite existing wallet file")?;
 
                 tracing::debug!("overwriting previous client state");
 
                 // Overwrite the existing wallet state file, *atomically*
                 std::fs::rename(&tmp_path, &wallet_path)?;
 
+                None
+            }
+            WalletCmd::Migrate { to } => {
+                if !state::is_sqlite(to) {
+                    return Err(anyhow!(
+                        "Wallet databases must have a .sqlite extension, but {} does not",
+                        to.display()
+                    ));
+                }
+                if to.exists() {
+                    return Err(anyhow!(
+                        "Wallet path {} already exists, refusing to overwrite it",
+                        to.display()
+                    ));
+                }
+
+                // The two wallets may share a lock file, so the old one is released before the
+                // new one is written.
+                let state: ClientState =
+                    ClientStateFile::load(wallet_path.clone(), wait_for_lock)?.clone();
+                println!(
+                    "Copying wallet from {} to {}",
+                    wallet_path.display(),
+                    to.display()
+                );
+                ClientStateFile::save(state, to.clone(), wait_for_lock)?;
+                println!(
+                    "Pass --wallet-location {} to use the new wallet; {} is left as it was",
+                    to.display(),
+                    wallet_path.display()
+                );
+
                 None
             }
         };
@@ -128,7 +209,7 @@ impl WalletCmd {
             }
 
             println!("Saving wallet to {}", wallet_path.display());
-            ClientStateFile::save(state.clone(), wallet_path)?;
+            ClientStateFile::save(state.clone(), wallet_path, wait_for_lock)?;
 
             // Archive the newly generated state
             let archive_dir = ProjectDirs::from("zone", "penumbra", "penumbra-testnet-archive")
@@ -152,7 +233,7 @@ impl WalletCmd {
             // Save the wallet file in the archive directory
             let archive_path = wallet_archive_dir.join("penumbra_wallet.json");
             println!("Saving backup wallet to {}", archive_path.display());
-            ClientStateFile::save(state, archive_path)?;
+            ClientStateFile::save(state, archive_path, wait_for_lock)?;
         }
 
         Ok(())
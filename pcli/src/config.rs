@@ -0,0 +1,100 @@
+use std::{collections::BTreeMap, fs::File, path::Path};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// The environment variable naming the network to use, if `--network` isn't given.
+pub const NETWORK_ENV_VAR: &str = "PCLI_NETWORK";
+
+/// The contents of `pcli`'s config file, which holds named network profiles, e.g.
+///
+/// ```json
+/// {
+///   "default_network": "testnet",
+///   "networks": {
+///     "testnet": { "node": "testnet.penumbra.zone", "chain_id": "penumbra-thelxinoe-0a1b2c3d" },
+///     "local": { "node": "127.0.0.1", "wallet_location": "/tmp/local_wallet.json" }
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The network to use if none is selected with `--network` or `PCLI_NETWORK`.
+    #[serde(default)]
+    pub default_network: Option<String>,
+    #[serde(default)]
+    pub networks: BTreeMap<String, NetworkConfig>,
+}
+
+/// Settings for a named network.  Each one is overridden by the corresponding command-line flag.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// The address of the pd+tendermint node.
+    pub node: Option<String>,
+    /// Other nodes to use, in order, if the first is unreachable or behind the chain.
+    pub fallback_nodes: Option<Vec<String>>,
+    /// How many blocks a node's wallet server may be behind the chain before falling back to
+    /// another node.
+    pub max_server_lag: Option<u64>,
+    /// How many of the node and its fallbacks must agree on the state the wallet synced to.
+    pub quorum: Option<usize>,
+    /// The port to use to speak to tendermint.
+    pub rpc_port: Option<u16>,
+    /// The port to use to speak to pd's light wallet server.
+    pub light_wallet_port: Option<u16>,
+    /// The port to use to speak to pd's thin wallet server.
+    pub thin_wallet_port: Option<u16>,
+    /// The chain the node and the wallet must be on; if set, `pcli` refuses to talk to a node on
+    /// any other chain.
+    pub chain_id: Option<String>,
+    /// The location of the wallet file to use on this network.
+    pub wallet_location: Option<String>,
+    /// The default transaction fee, in upenumbra.
+    pub fee: Option<u64>,
+    /// How far to pad transactions with dummy spends and outputs: `minimal` or a power of two.
+    pub padding: Option<String>,
+    /// How many times to rebuild and resubmit a transaction that was rejected because its anchor
+    /// was stale.
+    pub anchor_retries: Option<u32>,
+    /// How to choose which notes to spend: `random`, `privacy-max`, `fee-min` or `age-priority`.
+    pub strategy: Option<String>,
+    /// The size, in bytes, of the largest gRPC message to expect from the node.
+    pub grpc_max_message_size: Option<u32>,
+    /// How often to ping the node on open gRPC connections, in seconds, or zero not to.
+    pub grpc_keepalive_secs: Option<u64>,
+    /// How long to wait for a gRPC connection to the node, in seconds.
+    pub grpc_connect_timeout_secs: Option<u64>,
+}
+
+impl Config {
+    /// Loads the config file, or an empty config if there isn't one.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let file = File::open(path)
+            .with_context(|| format!("could not open config file {}", path.display()))?;
+        serde_json::from_reader(file)
+            .with_context(|| format!("could not parse config file {}", path.display()))
+    }
+
+    /// Returns the settings for the network with the given name, or for the default network if
+    /// `name` is `None`.
+    ///
+    /// If no network is selected at all, the settings are empty, so everything is taken from
+    /// the command line or the built-in defaults.
+    pub fn network(&self, name: Option<&str>) -> Result<NetworkConfig> {
+        match name.or(self.default_network.as_deref()) {
+            Some(name) => self.networks.get(name).cloned().ok_or_else(|| {
+                anyhow::anyhow!(
+                    "unknown network {:?}; the config file defines: {}",
+                    name,
+                    self.networks.keys().cloned().collect::<Vec<_>>().join(", ")
+                )
+            }),
+            None => Ok(NetworkConfig::default()),
+        }
+    }
+}
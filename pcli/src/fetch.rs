@@ -1,27 +1,63 @@
-use anyhow::Result;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use penumbra_chain::params::ChainParams;
 use penumbra_crypto::asset;
-use penumbra_proto::{light_wallet::ChainParamsRequest, thin_wallet::AssetListRequest};
+use penumbra_proto::{
+    light_wallet::{AssetListRequest, ChainInfo, ChainInfoRequest, ChainParamsRequest},
+    thin_wallet::{
+        time_estimate_request::Target, NoteWitnessesRequest, TimeEstimateRequest,
+        ValidatorRateRequest,
+    },
+};
+use penumbra_stake::{IdentityKey, RateData};
 use tracing::instrument;
 
-use crate::{ClientStateFile, Opt};
+use crate::{sync, ClientStateFile, Opt};
+
+/// The number of assets to request at a time when syncing the asset registry.
+const ASSET_PAGE_SIZE: u32 = 1000;
+
+/// The most note witnesses the node serves in one request.
+const NOTE_WITNESS_BATCH_SIZE: usize = 1024;
+
+/// How many times to sync and fetch note witnesses again if the chain advances while fetching them.
+const NOTE_WITNESS_RETRIES: u32 = 3;
 
+/// Fetches every denomination in the chain's asset registry, including the
+/// delegation tokens of validators added since the last sync, and stores them
+/// in the client's asset cache.
 #[instrument(skip(opt, state))]
 pub async fn assets(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
-    let mut client = opt.thin_wallet_client().await?;
+    let mut client = opt.light_wallet_client().await?;
 
-    // Update asset registry.
-    let request = tonic::Request::new(AssetListRequest {
-        chain_id: state.chain_id().unwrap_or_default(),
-    });
-    let mut stream = client.asset_list(request).await?.into_inner();
-    while let Some(asset) = stream.message().await? {
-        state.asset_cache_mut().extend(std::iter::once(
-            asset::REGISTRY
-                .parse_denom(&asset.asset_denom)
-                .ok_or_else(|| {
-                    anyhow::anyhow!("invalid asset denomination: {}", asset.asset_denom)
-                })?,
-        ));
+    // Page through the asset registry in order of asset ID.
+    let mut start_after = Vec::new();
+    loop {
+        let request = tonic::Request::new(AssetListRequest {
+            chain_id: state.chain_id().unwrap_or_default(),
+            start_after: start_after.clone(),
+            limit: ASSET_PAGE_SIZE,
+        });
+        let mut stream = client.asset_list(request).await?.into_inner();
+
+        let mut count = 0;
+        while let Some(asset) = stream.message().await? {
+            state.asset_cache_mut().extend(std::iter::once(
+                asset::REGISTRY
+                    .parse_denom(&asset.asset_denom)
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("invalid asset denomination: {}", asset.asset_denom)
+                    })?,
+            ));
+            start_after = asset.asset_id;
+            count += 1;
+        }
+        tracing::debug!(count, "fetched page of asset registry");
+
+        if count < ASSET_PAGE_SIZE {
+            break;
+        }
     }
 
     state.commit()?;
@@ -29,12 +65,81 @@ pub async fn assets(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
     Ok(())
 }
 
+/// Fetches authentication paths for the unspent notes the wallet isn't witnessing itself, e.g.
+/// because it was synced with `--fetch-witnesses`, so that they can be spent.
+///
+/// The node serves the paths at its latest height, so the wallet must be synced to the same
+/// height for them to be checked against its own note commitment tree root.  If the chain moves
+/// on while they're being fetched, this syncs and fetches them again.
+#[instrument(skip(opt, state))]
+pub async fn note_witnesses(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
+    if state.unwitnessed_notes().is_empty() {
+        return Ok(());
+    }
+
+    let mut client = opt.thin_wallet_client().await?;
+    let mut retries = 0;
+    loop {
+        let note_commitments = state.unwitnessed_notes();
+        if note_commitments.is_empty() {
+            return Ok(());
+        }
+
+        let mut added = 0;
+        let mut stale = None;
+        for batch in note_commitments.chunks(NOTE_WITNESS_BATCH_SIZE) {
+            let witnesses = client
+                .note_witnesses(tonic::Request::new(NoteWitnessesRequest {
+                    chain_id: state.chain_id().unwrap_or_default(),
+                    note_commitments: batch.iter().map(|&c| c.into()).collect(),
+                }))
+                .await?
+                .into_inner();
+            if Some(witnesses.height) != state.last_block_height() {
+                stale = Some(witnesses.height);
+                break;
+            }
+            added += state.add_note_witnesses(witnesses)?;
+        }
+
+        match stale {
+            None => {
+                tracing::info!(
+                    added,
+                    requested = note_commitments.len(),
+                    "fetched note witnesses"
+                );
+                return Ok(());
+            }
+            Some(height) if retries < NOTE_WITNESS_RETRIES => {
+                retries += 1;
+                tracing::debug!(
+                    height,
+                    ?retries,
+                    "node served note witnesses at another height, syncing"
+                );
+                sync(opt, state).await?;
+            }
+            Some(height) => {
+                return Err(anyhow!(
+                    "node served note witnesses at height {}, but the wallet is synced to height {:?}",
+                    height,
+                    state.last_block_height()
+                ));
+            }
+        }
+    }
+}
+
 /// Fetches the global chain parameters and stores them on `ClientState`.
+///
+/// The epoch duration and unbonding period determine when undelegated stake can be spent, so
+/// changes to them are reported: estimates made under the old parameters may be off.
 #[instrument(skip(opt, state))]
 pub async fn chain_params(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
     let mut client = opt.light_wallet_client().await?;
 
-    let params = client
+    let params: ChainParams = client
         .chain_params(tonic::Request::new(ChainParamsRequest {
             chain_id: state.chain_id().unwrap_or_default(),
         }))
@@ -42,9 +147,124 @@ pub async fn chain_params(opt: &Opt, state: &mut ClientStateFile) -> Result<()>
         .into_inner()
         .into();
 
+    if let Some(old) = state.chain_params() {
+        if old.chain_id != params.chain_id {
+            return Err(anyhow::anyhow!(
+                "node is on chain {}, but the wallet is for chain {}",
+                params.chain_id,
+                old.chain_id
+            ));
+        }
+        if old.epoch_duration != params.epoch_duration
+            || old.unbonding_epochs != params.unbonding_epochs
+        {
+            tracing::warn!(
+                old_epoch_duration = old.epoch_duration,
+                epoch_duration = params.epoch_duration,
+                old_unbonding_epochs = old.unbonding_epochs,
+                unbonding_epochs = params.unbonding_epochs,
+                "the chain's unbonding period changed, so stake already unbonding may be released \
+                 at a different height than shown"
+            );
+        }
+    }
+
     tracing::info!(?params, "saving chain params");
 
     *state.chain_params_mut() = Some(params);
     state.commit()?;
     Ok(())
 }
+
+/// Checks that the node is on the chain with the given chain id.
+#[instrument(skip(opt))]
+pub async fn verify_chain_id(opt: &Opt, chain_id: &str) -> Result<()> {
+    let mut client = opt.light_wallet_client().await?;
+
+    // The node rejects requests that expect a different chain id, but check the response too, in
+    // case it's an older node that doesn't.
+    let info = client
+        .chain_info(tonic::Request::new(ChainInfoRequest {
+            chain_id: chain_id.to_string(),
+        }))
+        .await
+        .map_err(|e| anyhow::anyhow!("node is not on chain {}: {}", chain_id, e.message()))?
+        .into_inner();
+    let node_chain_id = info
+        .chain_params
+        .map(|params| params.chain_id)
+        .unwrap_or_default();
+    if node_chain_id != chain_id {
+        return Err(anyhow::anyhow!(
+            "node is on chain {}, but the network profile expects chain {}",
+            node_chain_id,
+            chain_id
+        ));
+    }
+
+    Ok(())
+}
+
+/// Fetches a summary of the current state of the chain.
+#[instrument(skip(opt, state))]
+pub async fn chain_info(opt: &Opt, state: &ClientStateFile) -> Result<ChainInfo> {
+    let mut client = opt.light_wallet_client().await?;
+
+    let info = client
+        .chain_info(tonic::Request::new(ChainInfoRequest {
+            chain_id: state.chain_id().unwrap_or_default(),
+        }))
+        .await?
+        .into_inner();
+
+    tracing::debug!(height = info.height, epoch_index = info.epoch_index);
+    Ok(info)
+}
+
+/// Fetches the rates of the given validator for the next epoch, when delegations and
+/// undelegations submitted now take effect.
+#[instrument(skip(opt, state))]
+pub async fn next_rate_data(
+    opt: &Opt,
+    state: &ClientStateFile,
+    identity_key: IdentityKey,
+) -> Result<RateData> {
+    let next_epoch_index = chain_info(opt, state).await?.epoch_index + 1;
+
+    let mut client = opt.thin_wallet_client().await?;
+    client
+        .validator_rate(tonic::Request::new(ValidatorRateRequest {
+            identity_key: Some(identity_key.into()),
+            epoch_index: next_epoch_index,
+            chain_id: state
+                .chain_id()
+                .ok_or_else(|| anyhow!("missing chain_id"))?,
+        }))
+        .await?
+        .into_inner()
+        .try_into()
+}
+
+/// Estimates how long it will be until the chain reaches the given height, from the average time
+/// between recent blocks, or zero if it already has.
+#[instrument(skip(opt, state))]
+pub async fn time_until(opt: &Opt, state: &ClientStateFile, height: u64) -> Result<Duration> {
+    let mut client = opt.thin_wallet_client().await?;
+    let estimate = client
+        .time_estimate(tonic::Request::new(TimeEstimateRequest {
+            chain_id: state.chain_id().unwrap_or_default(),
+            target: Some(Target::Height(height)),
+        }))
+        .await?
+        .into_inner();
+
+    tracing::debug!(
+        current_height = estimate.current_height,
+        average_block_time_ms = estimate.average_block_time_ms
+    );
+    let remaining_ms = estimate
+        .estimated_time_ms
+        .saturating_sub(estimate.current_time_ms)
+        .max(0);
+    Ok(Duration::from_millis(remaining_ms as u64))
+}
@@ -1,13 +1,18 @@
 #![allow(clippy::clone_on_copy)]
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 use anyhow::Result;
 use directories::ProjectDirs;
+use penumbra_transaction::Padding;
+use penumbra_wallet::SelectionStrategy;
 use structopt::StructOpt;
 
+mod audit;
 mod command;
+mod config;
 mod fetch;
 mod network;
+mod redelegation;
 mod state;
 mod sync;
 mod warning;
@@ -23,56 +28,293 @@ use sync::sync;
     version = env!("VERGEN_GIT_SEMVER"),
 )]
 pub struct Opt {
-    /// The address of the pd+tendermint node.
-    #[structopt(short, long, default_value = "testnet.penumbra.zone")]
-    pub node: String,
-    /// The port to use to speak to tendermint.
-    #[structopt(short, long, default_value = "26657")]
-    pub rpc_port: u16,
-    /// The port to use to speak to pd's light wallet server.
-    #[structopt(short, long, default_value = "26666")]
-    pub light_wallet_port: u16,
-    /// The port to use to speak to pd's thin wallet server.
-    #[structopt(short, long, default_value = "26667")]
-    pub thin_wallet_port: u16,
+    /// The network profile to use from the config file [default: $PCLI_NETWORK, or the config
+    /// file's default network].
+    #[structopt(long)]
+    pub network: Option<String>,
+    /// The address of the pd+tendermint node [default: testnet.penumbra.zone].
+    #[structopt(short, long)]
+    pub node: Option<String>,
+    /// Another node to use if the first is unreachable or behind the chain, which may be given
+    /// more than once; they are tried in order [default: the network profile's fallback nodes].
+    #[structopt(long = "fallback-node")]
+    pub fallback_nodes: Vec<String>,
+    /// How many blocks a node's wallet server may be behind the chain before falling back to
+    /// another node, or warning if there are none [default: the network profile's setting, or
+    /// 10].
+    #[structopt(long)]
+    pub max_server_lag: Option<u64>,
+    /// After syncing, require this many of the node and its fallbacks to agree on the synced
+    /// block's note commitment tree root and app hash, counting the node synced from [default:
+    /// the network profile's quorum, or 1, which skips the check].
+    #[structopt(long)]
+    pub quorum: Option<usize>,
+    /// The port to use to speak to tendermint [default: 26657].
+    #[structopt(short, long)]
+    pub rpc_port: Option<u16>,
+    /// The port to use to speak to pd's light wallet server [default: 26666].
+    #[structopt(short, long)]
+    pub light_wallet_port: Option<u16>,
+    /// The port to use to speak to pd's thin wallet server [default: 26667].
+    #[structopt(short, long)]
+    pub thin_wallet_port: Option<u16>,
     #[structopt(subcommand)]
     pub cmd: Command,
     /// The location of the wallet file [default: platform appdata directory]
     #[structopt(short, long)]
     pub wallet_location: Option<String>,
+    /// If another pcli process is using the wallet, wait for it to finish, rather than failing.
+    #[structopt(long)]
+    pub wait_for_lock: bool,
+    /// If set, report notes received, spent, restored and quarantined while
+    /// syncing to this UNIX socket, as newline-delimited JSON.
+    #[structopt(long, parse(from_os_str))]
+    pub events_socket: Option<PathBuf>,
+    /// While syncing, save progress to the wallet file every this many blocks.
+    #[structopt(long, default_value = "1000")]
+    pub sync_checkpoint_blocks: u64,
+    /// While syncing, also save progress whenever this many seconds have passed
+    /// since it was last saved.
+    #[structopt(long)]
+    pub sync_checkpoint_secs: Option<u64>,
+    /// Don't display a progress bar while syncing.
+    #[structopt(long)]
+    pub no_progress: bool,
+    /// How far to pad transactions with dummy spends and outputs: `minimal` for the smallest
+    /// standard shape, or a power of two to make every transaction have at least that many spends
+    /// and outputs [default: the network profile's padding, or minimal].
+    #[structopt(long)]
+    pub padding: Option<Padding>,
+    /// How many times to rebuild and resubmit a transaction that was rejected because its anchor
+    /// was stale [default: the network profile's anchor retries, or 3].
+    #[structopt(long)]
+    pub anchor_retries: Option<u32>,
+    /// How to choose which notes to spend: `random`, `privacy-max` to avoid spending notes sent to
+    /// different addresses together, `fee-min` to spend as few notes as possible, or
+    /// `age-priority` to spend the oldest notes first [default: the network profile's strategy,
+    /// or random].
+    #[structopt(long)]
+    pub strategy: Option<SelectionStrategy>,
+    /// While syncing, don't keep authentication paths for the notes the wallet receives, and fetch
+    /// them from the node when spending the notes instead.  This keeps the wallet small when
+    /// restoring it from its seed on a chain with a long history.
+    #[structopt(long)]
+    pub fetch_witnesses: bool,
+    /// The size, in bytes, of the largest gRPC message to expect from the node, e.g. a large
+    /// compact block [default: the network profile's setting, or 16 MiB].
+    #[structopt(long)]
+    pub grpc_max_message_size: Option<u32>,
+    /// Ping the node every this many seconds while a gRPC connection is open, so that long syncs
+    /// and idle streams aren't silently dropped; zero disables pings [default: the network
+    /// profile's setting, or 30].
+    #[structopt(long)]
+    pub grpc_keepalive_secs: Option<u64>,
+    /// Give up connecting to the node after this many seconds [default: the network profile's
+    /// setting, or 10].
+    #[structopt(long)]
+    pub grpc_connect_timeout_secs: Option<u64>,
+    /// The selected network's settings from the config file, used for anything not given on the
+    /// command line.
+    #[structopt(skip)]
+    pub profile: config::NetworkConfig,
+    /// The node chosen by [`Opt::select_node`], if it has been run.
+    #[structopt(skip)]
+    pub selected_node: Option<String>,
+}
+
+impl Opt {
+    pub fn node(&self) -> &str {
+        self.selected_node
+            .as_deref()
+            .or(self.node.as_deref())
+            .or(self.profile.node.as_deref())
+            .unwrap_or("testnet.penumbra.zone")
+    }
+
+    /// The nodes to try, in order of preference: the configured node, then its fallbacks.
+    pub fn candidate_nodes(&self) -> Vec<String> {
+        let fallbacks = if self.fallback_nodes.is_empty() {
+            self.profile.fallback_nodes.clone().unwrap_or_default()
+        } else {
+            self.fallback_nodes.clone()
+        };
+        let primary = self
+            .node
+            .clone()
+            .or_else(|| self.profile.node.clone())
+            .unwrap_or_else(|| "testnet.penumbra.zone".to_string());
+        let mut nodes = vec![primary];
+        for node in fallbacks {
+            if !nodes.contains(&node) {
+                nodes.push(node);
+            }
+        }
+        nodes
+    }
+
+    /// How many wallet servers must agree on the state the wallet synced to.
+    pub fn quorum(&self) -> usize {
+        self.quorum.or(self.profile.quorum).unwrap_or(1)
+    }
+
+    /// How many blocks a wallet server may lag behind the chain.
+    pub fn max_server_lag(&self) -> u64 {
+        self.max_server_lag
+            .or(self.profile.max_server_lag)
+            .unwrap_or(10)
+    }
+
+    pub fn rpc_port(&self) -> u16 {
+        self.rpc_port.or(self.profile.rpc_port).unwrap_or(26657)
+    }
+
+    pub fn light_wallet_port(&self) -> u16 {
+        self.light_wallet_port
+            .or(self.profile.light_wallet_port)
+            .unwrap_or(26666)
+    }
+
+    pub fn thin_wallet_port(&self) -> u16 {
+        self.thin_wallet_port
+            .or(self.profile.thin_wallet_port)
+            .unwrap_or(26667)
+    }
+
+    /// The fee to use for a transaction, if none was given for it on the command line.
+    pub fn default_fee(&self) -> u64 {
+        self.profile.fee.unwrap_or(0)
+    }
+
+    /// How many times to rebuild and resubmit a transaction with a stale anchor.
+    pub fn anchor_retries(&self) -> u32 {
+        self.anchor_retries
+            .or(self.profile.anchor_retries)
+            .unwrap_or(3)
+    }
+
+    /// How far to pad transactions with dummy spends and outputs.
+    pub fn padding(&self) -> Result<Padding> {
+        match (self.padding, &self.profile.padding) {
+            (Some(padding), _) => Ok(padding),
+            (None, Some(padding)) => padding.parse(),
+            (None, None) => Ok(Padding::default()),
+        }
+    }
+
+    /// The size of the largest gRPC message to expect from the node.
+    pub fn grpc_max_message_size(&self) -> u32 {
+        self.grpc_max_message_size
+            .or(self.profile.grpc_max_message_size)
+            .unwrap_or(16 << 20)
+    }
+
+    /// How often to ping the node on open gRPC connections, if at all.
+    pub fn grpc_keepalive(&self) -> Option<Duration> {
+        let secs = self
+            .grpc_keepalive_secs
+            .or(self.profile.grpc_keepalive_secs)
+            .unwrap_or(30);
+        (secs > 0).then(|| Duration::from_secs(secs))
+    }
+
+    /// How long to wait for a gRPC connection to the node.
+    pub fn grpc_connect_timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.grpc_connect_timeout_secs
+                .or(self.profile.grpc_connect_timeout_secs)
+                .unwrap_or(10),
+        )
+    }
+
+    /// How to choose which notes to spend.
+    pub fn strategy(&self) -> Result<SelectionStrategy> {
+        match (self.strategy, &self.profile.strategy) {
+            (Some(strategy), _) => Ok(strategy),
+            (None, Some(strategy)) => strategy.parse(),
+            (None, None) => Ok(SelectionStrategy::default()),
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let mut opt = Opt::from_args();
+
+    // Completions and command descriptions are consumed by other programs, so write nothing else.
+    match &opt.cmd {
+        Command::Completions { shell } => {
+            command::meta::completions(*shell);
+            return Ok(());
+        }
+        Command::Commands { json } => return command::meta::commands(*json),
+        _ => {}
+    }
+
     // Display a warning message to the user so they don't get upset when all their tokens are lost.
     if std::env::var("PCLI_UNLEASH_DANGER").is_err() {
         warning::display();
     }
 
     tracing_subscriber::fmt::init();
-    let opt = Opt::from_args();
 
     let project_dir =
         ProjectDirs::from("zone", "penumbra", "pcli").expect("can access penumbra project dir");
-    // Currently we use just the data directory. Create it if it is missing.
+    // Create the data directory if it is missing.
     std::fs::create_dir_all(project_dir.data_dir()).expect("can create penumbra data directory");
 
+    // Network profiles are read from `config.json` in the config directory.
+    let config = config::Config::load(&project_dir.config_dir().join("config.json"))?;
+    let network = opt
+        .network
+        .clone()
+        .or_else(|| std::env::var(config::NETWORK_ENV_VAR).ok());
+    opt.profile = config.network(network.as_deref())?;
+
     // We store wallet data in `penumbra_wallet.dat` in the state directory, unless
-    // the user provides another location.
-    let wallet_path = opt.wallet_location.as_ref().map_or_else(
-        || project_dir.data_dir().join("penumbra_wallet.json"),
-        PathBuf::from,
-    );
+    // the user or the network profile provides another location.
+    let wallet_path = opt
+        .wallet_location
+        .as_ref()
+        .or(opt.profile.wallet_location.as_ref())
+        .map_or_else(
+            || project_dir.data_dir().join("penumbra_wallet.json"),
+            PathBuf::from,
+        );
 
     // The wallet command takes the wallet_path directly, since it may need to create the client state,
     // so handle it specially here so that we can have common code for the other subcommands.
     if let Command::Wallet(wallet_cmd) = &opt.cmd {
-        wallet_cmd.exec(wallet_path)?;
+        wallet_cmd.exec(wallet_path, opt.wait_for_lock)?;
         return Ok(());
     }
 
     // Synchronize the wallet if the command requires it to be synchronized before it is run.
-    let mut state = ClientStateFile::load(wallet_path.clone())?;
+    let mut state = ClientStateFile::load(wallet_path.clone(), opt.wait_for_lock)?;
+    state.set_padding(opt.padding()?);
+    state.set_note_selection(opt.strategy()?);
+    state.set_fetch_witnesses(opt.fetch_witnesses);
+
+    // Commands that sync need a wallet server that's keeping up with the chain, so pick one before
+    // talking to the node at all.
+    if opt.cmd.needs_sync() {
+        opt.select_node(state.chain_id()).await?;
+    }
+
+    // If the network profile pins a chain, make sure both the node and the wallet are on it before
+    // doing anything else, so we never e.g. broadcast a transaction to the wrong chain.
+    if let Some(chain_id) = opt.profile.chain_id.clone() {
+        fetch::verify_chain_id(&opt, &chain_id).await?;
+        if let Some(wallet_chain_id) = state.chain_id() {
+            if wallet_chain_id != chain_id {
+                return Err(anyhow::anyhow!(
+                    "the wallet at {} is for chain {}, but the network profile expects chain {}",
+                    wallet_path.display(),
+                    wallet_chain_id,
+                    chain_id
+                ));
+            }
+        }
+    }
 
     // Chain params may not have been fetched yet, do so if necessary.
     if state.chain_params().is_none() {
@@ -81,13 +323,16 @@ async fn main() -> Result<()> {
     // From now on, we can .expect() on the chain params.
 
     if opt.cmd.needs_sync() {
-        sync(&opt, &mut state).await?;
+        let stats = sync(&opt, &mut state).await?;
         fetch::assets(&opt, &mut state).await?;
+        if let Command::Sync { stats: true } = opt.cmd {
+            println!("{}", stats);
+        }
     };
 
     match &opt.cmd {
         Command::Wallet(_) => unreachable!("wallet command already executed"),
-        Command::Sync => {
+        Command::Sync { .. } => {
             // We have already synchronized the wallet above, so we can just return.
         }
         Command::Tx(tx_cmd) => tx_cmd.exec(&opt, &mut state).await?,
@@ -95,6 +340,15 @@ async fn main() -> Result<()> {
         Command::Balance(balance_cmd) => balance_cmd.exec(&state)?,
         Command::Validator(cmd) => cmd.exec(&opt, &state).await?,
         Command::Stake(cmd) => cmd.exec(&opt, &mut state).await?,
+        Command::Audit(cmd) => cmd.exec(&state)?,
+        Command::Chain(cmd) => cmd.exec(&opt, &state).await?,
+        Command::Daemon(cmd) => cmd.exec(&opt, &mut state).await?,
+        Command::Faucet(cmd) => cmd.exec(&opt, &mut state).await?,
+        Command::View(cmd) => cmd.exec(&opt, &mut state).await?,
+        Command::Debug(cmd) => cmd.exec(&opt, &state).await?,
+        Command::Completions { .. } | Command::Commands { .. } => {
+            unreachable!("meta commands already executed")
+        }
     }
 
     Ok(())
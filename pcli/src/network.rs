@@ -1,28 +1,233 @@
+use std::fmt;
+
+use anyhow::Context;
+use penumbra_chain::ResponseCode;
+use penumbra_crypto::merkle::TreeExt;
 use penumbra_proto::{
-    light_wallet::light_wallet_client::LightWalletClient,
-    thin_wallet::thin_wallet_client::ThinWalletClient, Protobuf,
+    broadcast::{AnchorWindowRequest, BroadcastTransactionRequest},
+    client::v1alpha1::{
+        broadcast_client::BroadcastClient, light_wallet_client::LightWalletClient,
+        thin_wallet_client::ThinWalletClient,
+    },
+    light_wallet::ChainInfoRequest,
+    Protobuf,
 };
 use penumbra_transaction::Transaction;
 use rand::Rng;
-use tonic::transport::Channel;
-use tracing::instrument;
+use rand_core::{OsRng, RngCore};
+use tonic::transport::{Channel, Endpoint};
+use tracing::{instrument, Instrument};
+
+use crate::{fetch, sync, ClientStateFile, Opt};
+
+/// The number of blocks within which a transaction is expected to be included, which its anchor
+/// must remain valid for.  Proving and broadcasting rarely take more than a few blocks.
+const ANCHOR_MARGIN_BLOCKS: u64 = 10;
+
+/// The error returned when the node rejects a transaction.
+#[derive(Debug)]
+pub struct TransactionRejected {
+    pub code: ResponseCode,
+    pub log: String,
+}
+
+impl fmt::Display for TransactionRejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Error submitting transaction: code {} ({}), log: {}",
+            self.code.code(),
+            self.code,
+            self.log
+        )
+    }
+}
 
-use crate::Opt;
+impl std::error::Error for TransactionRejected {}
 
 impl Opt {
     /// Submits a transaction to the network, returning `Ok` only when the remote
     /// node has accepted the transaction, and erroring otherwise.
     #[instrument(skip(self, transaction))]
     pub async fn submit_transaction(&self, transaction: &Transaction) -> Result<(), anyhow::Error> {
-        tracing::info!("broadcasting transaction...");
+        self.broadcast(transaction, &new_trace_id(), true).await
+    }
+
+    /// Builds a transaction with `build` and submits it, returning `Ok` only when the remote node
+    /// has accepted the transaction.
+    ///
+    /// The wallet is synced first if the node says its anchor would expire before the transaction
+    /// is likely to be included.  If the node still rejects the transaction because its anchor is
+    /// stale, e.g. because it took too long to build, this discards the uncommitted changes to the
+    /// client state, syncs, and builds the transaction again against a fresh anchor, up to
+    /// [`Opt::anchor_retries`] times.
+    ///
+    /// Every attempt belongs to the same trace, which the node records on the spans for the
+    /// transaction's `CheckTx` and `DeliverTx`.
+    pub async fn build_and_submit_transaction<F>(
+        &self,
+        state: &mut ClientStateFile,
+        mut build: F,
+    ) -> Result<Transaction, anyhow::Error>
+    where
+        F: FnMut(&mut ClientStateFile) -> Result<Transaction, anyhow::Error>,
+    {
+        let trace_id = new_trace_id();
+        let span = tracing::info_span!("transaction", trace_id = %hex::encode(trace_id));
+        let mut retries = 0;
+        loop {
+            // Don't spend time proving against an anchor that's about to expire.
+            if !self
+                .anchor_valid_within(state, ANCHOR_MARGIN_BLOCKS)
+                .instrument(span.clone())
+                .await?
+            {
+                tracing::info!("wallet anchor is about to expire, syncing before building");
+                sync(self, state).await?;
+            }
+
+            // Notes we aren't witnessing ourselves can only be spent with paths fetched from the
+            // node against our current anchor.
+            fetch::note_witnesses(self, state)
+                .instrument(span.clone())
+                .await?;
 
+            let transaction = span.in_scope(|| build(state))?;
+            match self
+                .broadcast(&transaction, &trace_id, true)
+                .instrument(span.clone())
+                .await
+            {
+                Ok(()) => return Ok(transaction),
+                Err(e) => {
+                    let stale_anchor = matches!(
+                        e.downcast_ref::<TransactionRejected>(),
+                        Some(TransactionRejected {
+                            code: ResponseCode::StaleAnchor,
+                            ..
+                        })
+                    );
+                    if !stale_anchor || retries >= self.anchor_retries() {
+                        return Err(e);
+                    }
+                    retries += 1;
+                    tracing::warn!(
+                        ?retries,
+                        "transaction anchor was stale, rebuilding against a fresh anchor"
+                    );
+                    state.reload()?;
+                    sync(self, state).await?;
+                }
+            }
+        }
+    }
+
+    /// Checks with the node whether a transaction anchored at the wallet's current note commitment
+    /// tree root will still be valid if it's included within `within_blocks` blocks.
+    ///
+    /// Nodes that don't serve the broadcast service can't tell, so this assumes it will.
+    async fn anchor_valid_within(
+        &self,
+        state: &ClientStateFile,
+        within_blocks: u64,
+    ) -> Result<bool, anyhow::Error> {
+        let request = AnchorWindowRequest {
+            chain_id: state.chain_id().unwrap_or_default(),
+            anchor: Some(state.note_commitment_tree().root2().into()),
+            within_blocks,
+        };
+        match self.broadcast_client().await?.anchor_window(request).await {
+            Ok(rsp) => {
+                let rsp = rsp.into_inner();
+                tracing::debug!(
+                    height = rsp.height,
+                    blocks_remaining = rsp.blocks_remaining,
+                    "checked anchor window"
+                );
+                Ok(rsp.valid_within)
+            }
+            Err(status) if status.code() == tonic::Code::Unimplemented => Ok(true),
+            Err(status) => Err(status.into()),
+        }
+    }
+
+    /// Submits a transaction to the network, returning `Ok` as soon as the
+    /// transaction has been submitted, rather than waiting to learn whether the
+    /// node accepted it.
+    #[instrument(skip(self, transaction))]
+    pub async fn submit_transaction_unconfirmed(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<(), anyhow::Error> {
+        self.broadcast(transaction, &new_trace_id(), false).await
+    }
+
+    /// Submits a transaction through the node's broadcast service, as part of the trace
+    /// `trace_id`, waiting for it to be accepted if `await_check` is set.
+    async fn broadcast(
+        &self,
+        transaction: &Transaction,
+        trace_id: &[u8; 16],
+        await_check: bool,
+    ) -> Result<(), anyhow::Error> {
+        let traceparent = traceparent(trace_id);
+        tracing::info!(
+            id = %hex::encode(transaction.id()),
+            %traceparent,
+            "broadcasting transaction..."
+        );
+
+        let mut request = tonic::Request::new(BroadcastTransactionRequest {
+            transaction: transaction.encode_to_vec(),
+            chain_id: String::new(),
+            await_check,
+        });
+        request.metadata_mut().insert(
+            "traceparent",
+            traceparent
+                .parse()
+                .expect("traceparent is a valid header value"),
+        );
+        let rsp = match self
+            .broadcast_client()
+            .await?
+            .broadcast_transaction(request)
+            .await
+        {
+            Ok(rsp) => rsp.into_inner(),
+            // Nodes that don't run Tendermint themselves don't serve the broadcast service.
+            Err(status) if status.code() == tonic::Code::Unimplemented => {
+                tracing::debug!("node has no broadcast service, broadcasting through tendermint");
+                return self.broadcast_to_tendermint(transaction, await_check).await;
+            }
+            Err(status) => return Err(status.into()),
+        };
+
+        if rsp.code == 0 {
+            Ok(())
+        } else {
+            Err(TransactionRejected {
+                code: ResponseCode::from_code(rsp.code),
+                log: rsp.log,
+            }
+            .into())
+        }
+    }
+
+    /// Submits a transaction directly to Tendermint's RPC endpoint, waiting for it to be accepted
+    /// if `await_check` is set.
+    async fn broadcast_to_tendermint(
+        &self,
+        transaction: &Transaction,
+        await_check: bool,
+    ) -> Result<(), anyhow::Error> {
         let client = reqwest::Client::new();
         let req_id: u8 = rand::thread_rng().gen();
         let rsp: serde_json::Value = client
-            .post(format!(r#"http://{}:{}"#, self.node, self.rpc_port))
+            .post(format!(r#"http://{}:{}"#, self.node(), self.rpc_port()))
             .json(&serde_json::json!(
                 {
-                    "method": "broadcast_tx_sync",
+                    "method": if await_check { "broadcast_tx_sync" } else { "broadcast_tx_async" },
                     "params": [&transaction.encode_to_vec()],
                     "id": req_id,
                 }
@@ -33,6 +238,9 @@ impl Opt {
             .await?;
 
         tracing::info!("{}", rsp);
+        if !await_check {
+            return Ok(());
+        }
 
         // Sometimes the result is in a result key, and sometimes it's bare? (??)
         let result = rsp.get("result").unwrap_or(&rsp);
@@ -50,54 +258,176 @@ impl Opt {
                 .and_then(|l| l.as_str())
                 .ok_or_else(|| anyhow::anyhow!("could not parse JSON response"))?;
 
-            Err(anyhow::anyhow!(
-                "Error submitting transaction: code {}, log: {}",
-                code,
-                log
-            ))
+            Err(TransactionRejected {
+                code: ResponseCode::from_code(code as u32),
+                log: log.to_string(),
+            }
+            .into())
         }
     }
 
-    /// Submits a transaction to the network, returning `Ok` as soon as the
-    /// transaction has been submitted, rather than waiting to learn whether the
-    /// node accepted it.
-    #[instrument(skip(self, transaction))]
-    pub async fn submit_transaction_unconfirmed(
+    async fn broadcast_client(&self) -> Result<BroadcastClient<Channel>, anyhow::Error> {
+        Ok(BroadcastClient::new(
+            self.channel(self.light_wallet_port()).await?,
+        ))
+    }
+
+    pub async fn thin_wallet_client(&self) -> Result<ThinWalletClient<Channel>, anyhow::Error> {
+        Ok(ThinWalletClient::new(
+            self.channel(self.thin_wallet_port()).await?,
+        ))
+    }
+
+    pub async fn light_wallet_client(&self) -> Result<LightWalletClient<Channel>, anyhow::Error> {
+        self.light_wallet_client_for(self.node()).await
+    }
+
+    /// Connects to the light wallet server of the given node, rather than the selected one.
+    pub async fn light_wallet_client_for(
         &self,
-        transaction: &Transaction,
-    ) -> Result<(), anyhow::Error> {
-        tracing::info!("broadcasting transaction...");
+        node: &str,
+    ) -> Result<LightWalletClient<Channel>, anyhow::Error> {
+        // Ask the server to gzip the compact block stream.
+        Ok(
+            LightWalletClient::new(self.channel_to(node, self.light_wallet_port()).await?)
+                .accept_gzip(),
+        )
+    }
 
-        let client = reqwest::Client::new();
-        let req_id: u8 = rand::thread_rng().gen();
-        let rsp: serde_json::Value = client
-            .post(format!(r#"http://{}:{}"#, self.node, self.rpc_port))
-            .json(&serde_json::json!(
-                {
-                    "method": "broadcast_tx_async",
-                    "params": [&transaction.encode_to_vec()],
-                    "id": req_id,
-                }
-            ))
-            .send()
-            .await?
-            .json()
-            .await?;
+    /// Chooses which of the [candidate nodes](Opt::candidate_nodes) to use from now on.
+    ///
+    /// Nodes are tried in order, and the first whose wallet server is within
+    /// [`Opt::max_server_lag`] blocks of the chain tip is chosen.  The tip is the highest height
+    /// reported by any node tried so far, by its wallet server or its Tendermint RPC.  If no node
+    /// is close enough, the least stale one is used, with a warning, since a stale wallet is still
+    /// better than none.
+    pub async fn select_node(&mut self, chain_id: Option<String>) -> Result<(), anyhow::Error> {
+        let max_lag = self.max_server_lag();
+        let mut tip = 0;
+        let mut least_stale: Option<(String, u64)> = None;
+        let mut last_error = None;
+        for node in self.candidate_nodes() {
+            let (server_height, tendermint_height) =
+                match self.node_heights(&node, chain_id.clone()).await {
+                    Ok(heights) => heights,
+                    Err(e) => {
+                        tracing::warn!(%node, ?e, "could not reach node");
+                        last_error = Some(e);
+                        continue;
+                    }
+                };
+            tip = tip.max(server_height).max(tendermint_height.unwrap_or(0));
+            let lag = tip - server_height;
+            if lag <= max_lag {
+                tracing::debug!(%node, server_height, tip, "selected node");
+                self.selected_node = Some(node);
+                return Ok(());
+            }
+            tracing::warn!(%node, server_height, tip, "node's wallet server is behind the chain");
+            if least_stale
+                .as_ref()
+                .map_or(true, |(_, height)| server_height > *height)
+            {
+                least_stale = Some((node, server_height));
+            }
+        }
 
-        tracing::info!("{}", rsp);
+        match least_stale {
+            Some((node, server_height)) => {
+                eprintln!(
+                    "Warning: the wallet server at {} is {} blocks behind the chain (at height {} \
+                     of {}), so balances and notes may be out of date.",
+                    node,
+                    tip - server_height,
+                    server_height,
+                    tip
+                );
+                self.selected_node = Some(node);
+                Ok(())
+            }
+            None => Err(last_error
+                .unwrap_or_else(|| anyhow::anyhow!("no nodes configured"))
+                .context("could not reach any node")),
+        }
+    }
+
+    /// Returns the height of the given node's wallet server, along with the height of its
+    /// Tendermint node, if its RPC is reachable.
+    async fn node_heights(
+        &self,
+        node: &str,
+        chain_id: Option<String>,
+    ) -> Result<(u64, Option<u64>), anyhow::Error> {
+        let server_height =
+            LightWalletClient::new(self.channel_to(node, self.light_wallet_port()).await?)
+                .chain_info(ChainInfoRequest {
+                    chain_id: chain_id.unwrap_or_default(),
+                })
+                .await?
+                .into_inner()
+                .height;
 
-        Ok(())
+        // Not every node exposes its Tendermint RPC, so only use it if it answers.
+        let tendermint_height = async {
+            let status: serde_json::Value = reqwest::Client::new()
+                .get(format!("http://{}:{}/status", node, self.rpc_port()))
+                .timeout(self.grpc_connect_timeout())
+                .send()
+                .await?
+                .json()
+                .await?;
+            // Sometimes the result is in a result key, and sometimes it's bare.
+            let status = status.get("result").unwrap_or(&status);
+            status["sync_info"]["latest_block_height"]
+                .as_str()
+                .and_then(|height| height.parse().ok())
+                .ok_or_else(|| anyhow::anyhow!("could not parse tendermint status"))
+        }
+        .await
+        .map_err(|e: anyhow::Error| tracing::debug!(%node, ?e, "no tendermint height"))
+        .ok();
+
+        Ok((server_height, tendermint_height))
     }
 
-    pub async fn thin_wallet_client(&self) -> Result<ThinWalletClient<Channel>, anyhow::Error> {
-        ThinWalletClient::connect(format!("http://{}:{}", self.node, self.thin_wallet_port))
-            .await
-            .map_err(Into::into)
+    /// Connects to one of the node's gRPC services, with the configured transport settings.
+    async fn channel(&self, port: u16) -> Result<Channel, anyhow::Error> {
+        self.channel_to(self.node(), port).await
     }
 
-    pub async fn light_wallet_client(&self) -> Result<LightWalletClient<Channel>, anyhow::Error> {
-        LightWalletClient::connect(format!("http://{}:{}", self.node, self.light_wallet_port))
+    /// Connects to a gRPC service on the given node, with the configured transport settings.
+    async fn channel_to(&self, node: &str, port: u16) -> Result<Channel, anyhow::Error> {
+        let uri = format!("http://{}:{}", node, port);
+        // Size the HTTP/2 flow control windows so that the largest messages aren't throttled.
+        let window = self.grpc_max_message_size().max(1 << 16);
+        let mut endpoint = Endpoint::from_shared(uri.clone())?
+            .tcp_keepalive(self.grpc_keepalive())
+            .initial_stream_window_size(window)
+            .initial_connection_window_size(window.saturating_mul(4));
+        if let Some(keepalive) = self.grpc_keepalive() {
+            endpoint = endpoint
+                .http2_keep_alive_interval(keepalive)
+                .keep_alive_timeout(keepalive)
+                .keep_alive_while_idle(true);
+        }
+        tokio::time::timeout(self.grpc_connect_timeout(), endpoint.connect())
             .await
+            .map_err(|_| anyhow::anyhow!("timed out connecting to {}", uri))?
             .map_err(Into::into)
     }
 }
+
+/// Returns a new W3C trace context trace ID, for following a transaction through the node's traces.
+fn new_trace_id() -> [u8; 16] {
+    let mut trace_id = [0; 16];
+    OsRng.fill_bytes(&mut trace_id);
+    trace_id
+}
+
+/// Returns a W3C trace context `traceparent` header for a new span in the trace `trace_id`.
+fn traceparent(trace_id: &[u8; 16]) -> String {
+    let mut parent_id = [0; 8];
+    OsRng.fill_bytes(&mut parent_id);
+    // Version 00, with the sampled flag set.
+    format!("00-{}-{}-01", hex::encode(trace_id), hex::encode(parent_id))
+}
@@ -0,0 +1,202 @@
+//! Redelegations waiting for their undelegated stake to unbond.
+//!
+//! A wallet can only move a delegation from one validator to another directly once per unbonding
+//! period, so `pcli stake redelegate --unbond` instead undelegates from the first validator, and
+//! delegates the unbonded stake to the second once it is released from quarantine.  The second
+//! half of each redelegation is kept in a file next to the wallet until then, and finished by
+//! `pcli stake finish-redelegations` or by `pcli daemon`, which checks for released stake every
+//! time it syncs.
+use std::{collections::BTreeSet, path::PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use penumbra_crypto::{note, Note};
+use penumbra_stake::IdentityKey;
+use penumbra_transaction::Transaction;
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+use serde_with::{hex::Hex, serde_as};
+
+use crate::{audit, fetch, ClientStateFile, Opt};
+
+/// A redelegation whose stake has been undelegated, but not yet delegated again.
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Redelegation {
+    pub from: IdentityKey,
+    pub to: IdentityKey,
+    /// The commitment to the note holding the unbonded stake.
+    #[serde_as(as = "Hex")]
+    pub note_commitment: [u8; 32],
+    /// The amount of unbonded stake, from which the fee of the delegation is paid.
+    pub amount: u64,
+    pub fee: u64,
+    /// Only spend funds originally received by the given address index.
+    pub source: Option<u64>,
+}
+
+/// What became of a redelegation that is no longer pending.
+#[derive(Clone, Debug)]
+pub enum Finished {
+    /// The unbonded stake was delegated by the transaction with this ID.
+    Delegated(String),
+    /// The unbonded stake never arrived, because the undelegation was never included or the
+    /// validator was slashed, or was spent by some other transaction.
+    Abandoned,
+}
+
+/// The redelegations waiting for a wallet's stake to unbond.
+pub struct Redelegations {
+    path: PathBuf,
+    pending: Vec<Redelegation>,
+}
+
+impl Redelegations {
+    /// Reads the pending redelegations of the given wallet.
+    pub fn open(state: &ClientStateFile) -> Result<Self> {
+        let path = state.redelegations_path();
+        let pending = if path.exists() {
+            serde_json::from_slice(
+                &std::fs::read(&path)
+                    .with_context(|| format!("could not read {}", path.display()))?,
+            )
+            .with_context(|| format!("could not parse {}", path.display()))?
+        } else {
+            Vec::new()
+        };
+        Ok(Self { path, pending })
+    }
+
+    pub fn pending(&self) -> &[Redelegation] {
+        &self.pending
+    }
+
+    /// Adds a redelegation whose undelegation was just submitted.
+    pub fn push(&mut self, redelegation: Redelegation) -> Result<()> {
+        self.pending.push(redelegation);
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        if self.pending.is_empty() {
+            if self.path.exists() {
+                std::fs::remove_file(&self.path)?;
+            }
+            return Ok(());
+        }
+
+        // Write the whole file at once, like the wallet, so a crash can't lose a redelegation.
+        let tmp_path = self.path.with_extension("redelegations.tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec_pretty(&self.pending)?)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+
+    /// Delegates the stake of every pending redelegation that has been released from quarantine.
+    ///
+    /// The wallet should be synced first.  Redelegations that fail are kept, to be tried again
+    /// later, and returned with their error.
+    pub async fn finish(
+        &mut self,
+        opt: &Opt,
+        state: &mut ClientStateFile,
+    ) -> Result<Vec<(Redelegation, Result<Finished>)>> {
+        let mut results = Vec::new();
+        let mut still_pending = Vec::new();
+        for redelegation in std::mem::take(&mut self.pending) {
+            let commitment = note::Commitment::try_from(redelegation.note_commitment)
+                .map_err(|_| anyhow!("invalid note commitment"))?;
+
+            if state
+                .unbonding_notes()
+                .any(|(_, note)| note.commit() == commitment)
+            {
+                still_pending.push(redelegation);
+                continue;
+            }
+
+            let released = state
+                .unspent_notes()
+                .any(|(_, _, note)| note.as_ready().map(|note| note.commit()) == Some(commitment));
+            let result = if released {
+                delegate(opt, state, &redelegation).await
+            } else {
+                Ok(Finished::Abandoned)
+            };
+            if result.is_err() {
+                still_pending.push(redelegation.clone());
+            }
+            results.push((redelegation, result));
+        }
+
+        self.pending = still_pending;
+        self.save()?;
+        Ok(results)
+    }
+}
+
+/// Delegates the unbonded stake of a redelegation to its new validator.
+async fn delegate(
+    opt: &Opt,
+    state: &mut ClientStateFile,
+    redelegation: &Redelegation,
+) -> Result<Finished> {
+    let amount = redelegation
+        .amount
+        .checked_sub(redelegation.fee)
+        .ok_or_else(|| {
+            anyhow!(
+                "unbonded amount {} is insufficient to pay fees {}",
+                redelegation.amount,
+                redelegation.fee
+            )
+        })?;
+    let rate_data = fetch::next_rate_data(opt, state, redelegation.to.clone()).await?;
+
+    let (_label, self_address) = state
+        .wallet()
+        .address_by_index(redelegation.source.unwrap_or(0) as usize)?;
+    let result = opt
+        .build_and_submit_transaction(state, |state| {
+            let transaction = state.build_delegate(
+                &mut OsRng,
+                rate_data.clone(),
+                amount,
+                redelegation.fee,
+                redelegation.source,
+            )?;
+            audit::record(state, &transaction, &[self_address])?;
+            Ok(transaction)
+        })
+        .await;
+    match result {
+        Ok(transaction) => {
+            state.commit()?;
+            Ok(Finished::Delegated(hex::encode(transaction.id())))
+        }
+        Err(e) => {
+            // Forget the spends of the failed transaction, so its notes can be spent again.
+            state.reload()?;
+            Err(e)
+        }
+    }
+}
+
+/// Builds an undelegation with `build`, returning the transaction along with the note holding the
+/// unbonded stake.
+pub fn build_undelegation<F>(state: &mut ClientStateFile, build: F) -> Result<(Transaction, Note)>
+where
+    F: FnOnce(&mut ClientStateFile) -> Result<Transaction>,
+{
+    let unbonding = state
+        .unbonding_notes()
+        .map(|(_, note)| note.commit())
+        .collect::<BTreeSet<_>>();
+    let transaction = build(state)?;
+    let note = state
+        .unbonding_notes()
+        .map(|(_, note)| note)
+        .find(|note| !unbonding.contains(&note.commit()))
+        .cloned()
+        .ok_or_else(|| anyhow!("undelegation has no unbonded output"))?;
+    Ok((transaction, note))
+}
@@ -4,12 +4,18 @@ use std::{
 };
 
 use anyhow::{Context, Result};
-use penumbra_wallet::ClientState;
+use penumbra_wallet::{ClientState, Wallet};
+
+mod sqlite;
+
+use sqlite::SqliteStorage;
 
 pub struct ClientStateFile {
     path: PathBuf,
     state: ClientState,
-    lock: fslock::LockFile,
+    storage: Storage,
+    /// Held until the wrapper is dropped.
+    _lock: WalletLock,
 }
 
 impl Deref for ClientStateFile {
@@ -25,49 +31,130 @@ impl DerefMut for ClientStateFile {
     }
 }
 
-impl Drop for ClientStateFile {
-    fn drop(&mut self) {
-        self.lock.unlock().unwrap();
+/// Where the client state is persisted.
+enum Storage {
+    /// The whole state as a single JSON file, rewritten on every commit.
+    Json,
+    /// An SQLite database, updated incrementally on every commit.
+    Sqlite(SqliteStorage),
+}
+
+impl Storage {
+    /// Wallets whose path has a `.sqlite` extension are stored in an SQLite database, and all
+    /// others in a JSON file.
+    fn open(path: &Path) -> Result<Self> {
+        if is_sqlite(path) {
+            Ok(Storage::Sqlite(SqliteStorage::open(path)?))
+        } else {
+            Ok(Storage::Json)
+        }
     }
+
+    fn read_state(&self, path: &Path) -> Result<ClientState> {
+        let mut state = match self {
+            Storage::Json => read_json(path)?,
+            Storage::Sqlite(db) => db.load()?,
+        };
+
+        // Pruning timeouts on load means every freshly loaded wallet will be up to date on
+        // timeouts as of when it is taken off disk
+        state.prune_timeouts();
+
+        Ok(state)
+    }
+}
+
+/// Whether the wallet at `path` is stored in an SQLite database.
+pub fn is_sqlite(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| ext == "sqlite")
 }
 
 impl ClientStateFile {
     /// Create a new wrapper by saving to the provided `path`.
     ///
-    /// If you already have a wrapper, use [`Self::commit`].
-    pub fn save(state: ClientState, path: PathBuf) -> Result<Self> {
-        let lock = lock_wallet(&path)?;
-
-        let wrapper = Self { state, path, lock };
+    /// If you already have a wrapper, use [`Self::commit`].  If another process is using the
+    /// wallet, this waits for it to finish if `wait_for_lock` is set, and fails otherwise.
+    pub fn save(state: ClientState, path: PathBuf, wait_for_lock: bool) -> Result<Self> {
+        let lock = WalletLock::acquire(&path, wait_for_lock)?;
+        let storage = Storage::open(&path)?;
+
+        let wrapper = Self {
+            state,
+            path,
+            storage,
+            _lock: lock,
+        };
         wrapper.commit()?;
         Ok(wrapper)
     }
 
-    /// Create a new wrapper by loading from the provided `path`.
-    pub fn load(path: PathBuf) -> Result<Self> {
-        let lock = lock_wallet(&path)?;
+    /// Create a new wrapper by loading from the provided `path`, locking it as in [`Self::save`].
+    pub fn load(path: PathBuf, wait_for_lock: bool) -> Result<Self> {
+        let lock = WalletLock::acquire(&path, wait_for_lock)?;
+        if !path.exists() {
+            return Err(anyhow::anyhow!(
+                "Wallet data not found, run `pcli wallet generate` to generate Penumbra keys"
+            ));
+        }
+        let storage = Storage::open(&path)?;
+        let state = storage.read_state(&path)?;
+        Ok(Self {
+            state,
+            path,
+            storage,
+            _lock: lock,
+        })
+    }
 
-        let mut state: ClientState = match std::fs::read(&path) {
-            Ok(data) => serde_json::from_slice(&data).context("Could not parse wallet data")?,
-            Err(err) => match err.kind() {
-                std::io::ErrorKind::NotFound => return Err(err).context(
-                    "Wallet data not found, run `pcli wallet generate` to generate Penumbra keys",
-                ),
-                _ => return Err(err.into()),
-            },
-        };
+    /// Load only the wallet's keys from the provided `path`, locking it as in [`Self::save`].
+    ///
+    /// For a wallet database, this avoids reading the rest of the client state.
+    pub fn load_wallet(path: PathBuf, wait_for_lock: bool) -> Result<Wallet> {
+        let _lock = WalletLock::acquire(&path, wait_for_lock)?;
+        if is_sqlite(&path) {
+            if !path.exists() {
+                return Err(anyhow::anyhow!(
+                    "Wallet data not found, run `pcli wallet generate` to generate Penumbra keys"
+                ));
+            }
+            sqlite::load_wallet(&path)
+        } else {
+            Ok(read_json(&path)?.wallet().clone())
+        }
+    }
 
-        // Pruning timeouts on load means every freshly loaded wallet will be up to date on timeouts
-        // as of when it is taken off disk
-        state.prune_timeouts();
+    /// Discard any uncommitted changes to the client state, reloading it from disk.
+    ///
+    /// Settings that aren't persisted, like the padding policy, are kept.
+    pub fn reload(&mut self) -> Result<()> {
+        let padding = self.state.padding();
+        let note_selection = self.state.note_selection();
+        let fetch_witnesses = self.state.fetch_witnesses();
+        self.state = self.storage.read_state(&self.path)?;
+        self.state.set_padding(padding);
+        self.state.set_note_selection(note_selection);
+        self.state.set_fetch_witnesses(fetch_witnesses);
+        Ok(())
+    }
+
+    /// The location of the audit log of transactions signed by this wallet.
+    pub fn audit_log_path(&self) -> PathBuf {
+        self.path.with_extension("audit")
+    }
 
-        Ok(Self { state, path, lock })
+    /// The location of the redelegations waiting for this wallet's stake to unbond.
+    pub fn redelegations_path(&self) -> PathBuf {
+        self.path.with_extension("redelegations")
     }
 
     /// Commit the client state to disk.
     pub fn commit(&self) -> Result<()> {
         tracing::debug!("committing state");
 
+        if let Storage::Sqlite(db) = &self.storage {
+            return db.commit(&self.state);
+        }
+
         let tmp_path = self.path.with_extension("tmp");
 
         // Write the state to the temp file
@@ -85,15 +172,79 @@ impl ClientStateFile {
     }
 }
 
-fn lock_wallet(path: &Path) -> Result<fslock::LockFile> {
-    let mut lock = fslock::LockFile::open(&path.with_extension("lock"))?;
+fn read_json(path: &Path) -> Result<ClientState> {
+    let state: ClientState =
+        match std::fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data).context("Could not parse wallet data")?,
+            Err(err) => match err.kind() {
+                std::io::ErrorKind::NotFound => return Err(err).context(
+                    "Wallet data not found, run `pcli wallet generate` to generate Penumbra keys",
+                ),
+                _ => return Err(err.into()),
+            },
+        };
+
+    Ok(state)
+}
+
+/// An advisory lock on a wallet file, so that only one `pcli` at a time uses it.
+///
+/// The lock itself is an OS file lock on a `.lock` file next to the wallet, which the OS releases
+/// when its holder exits, however it exits.  While the lock is held, the lock file records the
+/// holder's process ID, for error messages, and it is emptied when the lock is released; a lock
+/// file that isn't empty when the lock is acquired was left by a process that was killed.
+struct WalletLock(fslock::LockFile);
+
+impl WalletLock {
+    fn acquire(wallet_path: &Path, wait: bool) -> Result<Self> {
+        let path = wallet_path.with_extension("lock");
+        let mut lock = fslock::LockFile::open(&path)
+            .with_context(|| format!("could not open lock file {}", path.display()))?;
+
+        tracing::debug!(?path, "Locking wallet file");
+        let previous_holder = read_holder(&path);
+        if lock.try_lock_with_pid()? {
+            if let Some(pid) = previous_holder {
+                tracing::warn!(
+                    pid,
+                    "recovering wallet lock from a pcli process that exited without releasing it"
+                );
+                // The wallet itself is only ever replaced atomically, but the killed process may
+                // have left a partially written copy behind.
+                let tmp_path = wallet_path.with_extension("tmp");
+                if tmp_path != wallet_path && tmp_path.exists() {
+                    std::fs::remove_file(&tmp_path)?;
+                }
+            }
+            return Ok(Self(lock));
+        }
+
+        let holder = match read_holder(&path) {
+            Some(pid) => format!("pcli process {}", pid),
+            None => "another pcli process".to_string(),
+        };
+        if !wait {
+            return Err(anyhow::anyhow!(
+                "the wallet at {} is in use by {}; wait for it to finish, or pass \
+                 --wait-for-lock to wait automatically",
+                wallet_path.display(),
+                holder
+            ));
+        }
+        tracing::info!(?path, "Waiting for {} to release the wallet", holder);
+        lock.lock_with_pid()?;
+        Ok(Self(lock))
+    }
+}
 
-    // Try to lock the file and note in the log if we are waiting for another process to finish
-    tracing::debug!(?path, "Locking wallet file");
-    if !lock.try_lock()? {
-        tracing::info!(?path, "Waiting to acquire lock for wallet");
-        lock.lock()?;
+impl Drop for WalletLock {
+    fn drop(&mut self) {
+        // Unlocking also empties the lock file.
+        self.0.unlock().unwrap();
     }
+}
 
-    Ok(lock)
+/// Reads the process ID recorded in a lock file, if any.
+fn read_holder(path: &Path) -> Option<u32> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
 }
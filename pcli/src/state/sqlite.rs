@@ -0,0 +1,481 @@
+//! Client state stored in an SQLite database, rather than in a single JSON file.
+//!
+//! Each part of the client state has its own table, so that committing only writes the rows that
+//! changed since the last commit, rather than rewriting the whole wallet every few blocks of a
+//! sync, and commands that only need part of the state, like the wallet's keys, only read that.
+
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use penumbra_wallet::{ClientState, ClientStateHelper, Wallet};
+use rusqlite::{params, Connection, OptionalExtension};
+
+/// The version of the schema below, recorded as the database's `user_version`.
+const SCHEMA_VERSION: i64 = 2;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS notes (
+    note_commitment TEXT NOT NULL,
+    -- unspent, submitted_spend, submitted_change, spent or unbonding
+    status TEXT NOT NULL,
+    note TEXT NOT NULL,
+    -- when a submitted note times out, in milliseconds since the UNIX epoch
+    timeout_ms INTEGER,
+    -- the height of the block expected to release an unbonding note, or the quarantine of a spent
+    -- note's spend, while the spend can still be reverted
+    release_height INTEGER,
+    -- the index of the address the note was sent to, if we found it while scanning
+    address_index INTEGER,
+    PRIMARY KEY (note_commitment, status)
+);
+CREATE TABLE IF NOT EXISTS nullifiers (
+    nullifier TEXT PRIMARY KEY,
+    note_commitment TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS transactions (
+    note_commitment TEXT PRIMARY KEY,
+    transaction_data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS assets (
+    asset_id TEXT PRIMARY KEY,
+    denom TEXT NOT NULL
+);
+";
+
+/// A note's row in the `notes` table, other than its key.
+#[derive(Clone, PartialEq)]
+struct NoteRow {
+    note: String,
+    timeout_ms: Option<i64>,
+    release_height: Option<i64>,
+    address_index: Option<i64>,
+}
+
+/// The rows of every table, as last read or written.
+#[derive(Default)]
+struct Rows {
+    /// The wallet, chain parameters, sync height and unconfirmed unbondings, as JSON, and the
+    /// serialized note commitment tree, including the witnesses of our notes.
+    meta: BTreeMap<String, Vec<u8>>,
+    /// By note commitment and status.
+    notes: BTreeMap<(String, String), NoteRow>,
+    nullifiers: BTreeMap<String, String>,
+    transactions: BTreeMap<String, String>,
+    assets: BTreeMap<String, String>,
+}
+
+/// An open wallet database.
+pub struct SqliteStorage {
+    /// The connection, and the rows it last committed, to compute the next commit's changes from.
+    inner: Mutex<(Connection, Rows)>,
+}
+
+impl SqliteStorage {
+    /// Opens the database at `path`, creating it if it doesn't exist, without reading the client
+    /// state in it.
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = connect(path)?;
+        let rows = Rows::read(&conn)?;
+        Ok(Self {
+            inner: Mutex::new((conn, rows)),
+        })
+    }
+
+    /// Reads the client state from the database.
+    pub fn load(&self) -> Result<ClientState> {
+        let inner = self.inner.lock().unwrap();
+        if !inner.1.meta.contains_key("wallet") {
+            return Err(anyhow::anyhow!("wallet database is empty"));
+        }
+        inner.1.to_helper()?.try_into()
+    }
+
+    /// Writes the changes to the client state since the last commit, in one transaction.
+    pub fn commit(&self, state: &ClientState) -> Result<()> {
+        let rows = Rows::from_helper(state.clone().into())?;
+        let mut inner = self.inner.lock().unwrap();
+        let (conn, committed) = &mut *inner;
+
+        let dbtx = conn.transaction()?;
+        sync_table(
+            &committed.meta,
+            &rows.meta,
+            |key, value| {
+                dbtx.prepare_cached("INSERT OR REPLACE INTO meta (key, value) VALUES (?, ?)")?
+                    .execute(params![key, value])
+            },
+            |key| {
+                dbtx.prepare_cached("DELETE FROM meta WHERE key = ?")?
+                    .execute(params![key])
+            },
+        )?;
+        sync_table(
+            &committed.notes,
+            &rows.notes,
+            |(note_commitment, status), row| {
+                dbtx.prepare_cached(
+                    "INSERT OR REPLACE INTO notes
+                    (note_commitment, status, note, timeout_ms, release_height, address_index)
+                    VALUES (?, ?, ?, ?, ?, ?)",
+                )?
+                .execute(params![
+                    note_commitment,
+                    status,
+                    row.note,
+                    row.timeout_ms,
+                    row.release_height,
+                    row.address_index
+                ])
+            },
+            |(note_commitment, status)| {
+                dbtx.prepare_cached("DELETE FROM notes WHERE note_commitment = ? AND status = ?")?
+                    .execute(params![note_commitment, status])
+            },
+        )?;
+        sync_table(
+            &committed.nullifiers,
+            &rows.nullifiers,
+            |nullifier, note_commitment| {
+                dbtx.prepare_cached(
+                    "INSERT OR REPLACE INTO nullifiers (nullifier, note_commitment) VALUES (?, ?)",
+                )?
+                .execute(params![nullifier, note_commitment])
+            },
+            |nullifier| {
+                dbtx.prepare_cached("DELETE FROM nullifiers WHERE nullifier = ?")?
+                    .execute(params![nullifier])
+            },
+        )?;
+        sync_table(
+            &committed.transactions,
+            &rows.transactions,
+            |note_commitment, transaction| {
+                dbtx.prepare_cached(
+                    "INSERT OR REPLACE INTO transactions (note_commitment, transaction_data)
+                    VALUES (?, ?)",
+                )?
+                .execute(params![note_commitment, transaction])
+            },
+            |note_commitment| {
+                dbtx.prepare_cached("DELETE FROM transactions WHERE note_commitment = ?")?
+                    .execute(params![note_commitment])
+            },
+        )?;
+        sync_table(
+            &committed.assets,
+            &rows.assets,
+            |asset_id, denom| {
+                dbtx.prepare_cached(
+                    "INSERT OR REPLACE INTO assets (asset_id, denom) VALUES (?, ?)",
+                )?
+                .execute(params![asset_id, denom])
+            },
+            |asset_id| {
+                dbtx.prepare_cached("DELETE FROM assets WHERE asset_id = ?")?
+                    .execute(params![asset_id])
+            },
+        )?;
+        dbtx.commit()?;
+
+        *committed = rows;
+        Ok(())
+    }
+}
+
+/// Reads only the wallet's keys from the database at `path`.
+pub fn load_wallet(path: &Path) -> Result<Wallet> {
+    let conn = connect(path)?;
+    let wallet = conn
+        .query_row("SELECT value FROM meta WHERE key = 'wallet'", [], |row| {
+            row.get::<_, Vec<u8>>(0)
+        })
+        .optional()?
+        .ok_or_else(|| anyhow::anyhow!("wallet database is empty"))?;
+    serde_json::from_slice(&wallet).context("could not parse wallet")
+}
+
+fn connect(path: &Path) -> Result<Connection> {
+    let conn = Connection::open(path)
+        .with_context(|| format!("could not open wallet database {}", path.display()))?;
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > SCHEMA_VERSION {
+        return Err(anyhow::anyhow!(
+            "wallet database {} was written by a newer pcli (schema version {}, expected {})",
+            path.display(),
+            version,
+            SCHEMA_VERSION
+        ));
+    }
+    // Version 1 databases predate the address index of each note.
+    if version == 1 {
+        conn.execute_batch("ALTER TABLE notes ADD COLUMN address_index INTEGER")?;
+    }
+    conn.execute_batch(SCHEMA)?;
+    conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    Ok(conn)
+}
+
+/// Writes the rows of a table that changed from `old` to `new`.
+fn sync_table<K: Ord, V: PartialEq>(
+    old: &BTreeMap<K, V>,
+    new: &BTreeMap<K, V>,
+    mut upsert: impl FnMut(&K, &V) -> rusqlite::Result<usize>,
+    mut delete: impl FnMut(&K) -> rusqlite::Result<usize>,
+) -> Result<()> {
+    for key in old.keys() {
+        if !new.contains_key(key) {
+            delete(key)?;
+        }
+    }
+    for (key, value) in new {
+        if old.get(key) != Some(value) {
+            upsert(key, value)?;
+        }
+    }
+    Ok(())
+}
+
+fn to_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+fn from_millis(millis: Option<i64>) -> Result<SystemTime> {
+    let millis = millis.ok_or_else(|| anyhow::anyhow!("submitted note has no timeout"))?;
+    Ok(UNIX_EPOCH + Duration::from_millis(millis as u64))
+}
+
+impl Rows {
+    fn read(conn: &Connection) -> Result<Self> {
+        let mut rows = Rows::default();
+
+        let mut stmt = conn.prepare("SELECT key, value FROM meta")?;
+        for row in stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))? {
+            let (key, value) = row?;
+            rows.meta.insert(key, value);
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT note_commitment, status, note, timeout_ms, release_height, address_index
+            FROM notes",
+        )?;
+        for row in stmt.query_map([], |row| {
+            Ok((
+                (row.get(0)?, row.get(1)?),
+                NoteRow {
+                    note: row.get(2)?,
+                    timeout_ms: row.get(3)?,
+                    release_height: row.get(4)?,
+                    address_index: row.get(5)?,
+                },
+            ))
+        })? {
+            let (key, note) = row?;
+            rows.notes.insert(key, note);
+        }
+
+        for (table, columns, map) in [
+            (
+                "nullifiers",
+                "nullifier, note_commitment",
+                &mut rows.nullifiers,
+            ),
+            (
+                "transactions",
+                "note_commitment, transaction_data",
+                &mut rows.transactions,
+            ),
+            ("assets", "asset_id, denom", &mut rows.assets),
+        ] {
+            let mut stmt = conn.prepare(&format!("SELECT {} FROM {}", columns, table))?;
+            for row in stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))? {
+                let (key, value) = row?;
+                map.insert(key, value);
+            }
+        }
+
+        Ok(rows)
+    }
+
+    fn from_helper(state: ClientStateHelper) -> Result<Self> {
+        let mut rows = Rows::default();
+
+        rows.meta
+            .insert("wallet".to_string(), serde_json::to_vec(&state.wallet)?);
+        rows.meta.insert(
+            "last_block_height".to_string(),
+            serde_json::to_vec(&state.last_block_height)?,
+        );
+        rows.meta.insert(
+            "note_commitment_tree".to_string(),
+            state.note_commitment_tree,
+        );
+        rows.meta.insert(
+            "chain_params".to_string(),
+            serde_json::to_vec(&state.chain_params)?,
+        );
+        rows.meta.insert(
+            "unconfirmed_unbondings".to_string(),
+            serde_json::to_vec(&state.unconfirmed_unbondings)?,
+        );
+
+        let address_indices = state
+            .address_indices
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+        let mut insert_note = |note_commitment: String,
+                               status: &str,
+                               note: String,
+                               timeout: Option<SystemTime>,
+                               release_height: Option<i64>| {
+            let address_index = address_indices
+                .get(&note_commitment)
+                .map(|index| *index as i64);
+            rows.notes.insert(
+                (note_commitment, status.to_string()),
+                NoteRow {
+                    note,
+                    timeout_ms: timeout.map(to_millis),
+                    release_height,
+                    address_index,
+                },
+            );
+        };
+        for (note_commitment, note) in state.unspent_set {
+            insert_note(note_commitment, "unspent", note, None, None);
+        }
+        for (note_commitment, timeout, note) in state.submitted_spend_set {
+            insert_note(
+                note_commitment,
+                "submitted_spend",
+                note,
+                Some(timeout),
+                None,
+            );
+        }
+        for (note_commitment, timeout, note) in state.submitted_change_set {
+            insert_note(
+                note_commitment,
+                "submitted_change",
+                note,
+                Some(timeout),
+                None,
+            );
+        }
+        let revertible_spends = state
+            .revertible_spends
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+        for (note_commitment, note) in state.spent_set {
+            let release_height = revertible_spends
+                .get(&note_commitment)
+                .map(|height| *height as i64);
+            insert_note(note_commitment, "spent", note, None, release_height);
+        }
+        for (note_commitment, release_height, note) in state.unbonding_set {
+            insert_note(
+                note_commitment,
+                "unbonding",
+                note,
+                None,
+                Some(release_height as i64),
+            );
+        }
+
+        rows.nullifiers = state.nullifier_map.into_iter().collect();
+        rows.transactions = state.transactions.into_iter().collect();
+        rows.assets = state
+            .asset_registry
+            .into_iter()
+            .map(|(id, denom)| (id.to_string(), denom))
+            .collect();
+
+        Ok(rows)
+    }
+
+    fn to_helper(&self) -> Result<ClientStateHelper> {
+        let meta = |key: &str| {
+            self.meta
+                .get(key)
+                .ok_or_else(|| anyhow::anyhow!("wallet database is missing {}", key))
+        };
+
+        let mut state = ClientStateHelper {
+            wallet: serde_json::from_slice(meta("wallet")?)?,
+            last_block_height: serde_json::from_slice(meta("last_block_height")?)?,
+            note_commitment_tree: meta("note_commitment_tree")?.clone(),
+            nullifier_map: self.nullifiers.clone().into_iter().collect(),
+            unspent_set: Vec::new(),
+            address_indices: Vec::new(),
+            submitted_spend_set: Vec::new(),
+            submitted_change_set: Vec::new(),
+            spent_set: Vec::new(),
+            revertible_spends: Vec::new(),
+            unbonding_set: Vec::new(),
+            // Databases written before unbondings were confirmed don't have this.
+            unconfirmed_unbondings: self
+                .meta
+                .get("unconfirmed_unbondings")
+                .map(|value| serde_json::from_slice(value))
+                .transpose()?
+                .unwrap_or_default(),
+            transactions: self.transactions.clone().into_iter().collect(),
+            asset_registry: self
+                .assets
+                .iter()
+                .map(|(id, denom)| Ok((id.parse()?, denom.clone())))
+                .collect::<Result<_>>()?,
+            chain_params: serde_json::from_slice(meta("chain_params")?)?,
+        };
+
+        for ((note_commitment, status), row) in &self.notes {
+            let (note_commitment, note) = (note_commitment.clone(), row.note.clone());
+            if let Some(address_index) = row.address_index {
+                state
+                    .address_indices
+                    .push((note_commitment.clone(), address_index as u64));
+            }
+            match status.as_str() {
+                "unspent" => state.unspent_set.push((note_commitment, note)),
+                "submitted_spend" => state.submitted_spend_set.push((
+                    note_commitment,
+                    from_millis(row.timeout_ms)?,
+                    note,
+                )),
+                "submitted_change" => state.submitted_change_set.push((
+                    note_commitment,
+                    from_millis(row.timeout_ms)?,
+                    note,
+                )),
+                "spent" => {
+                    if let Some(release_height) = row.release_height {
+                        state
+                            .revertible_spends
+                            .push((note_commitment.clone(), release_height as u64));
+                    }
+                    state.spent_set.push((note_commitment, note));
+                }
+                "unbonding" => state.unbonding_set.push((
+                    note_commitment,
+                    row.release_height
+                        .ok_or_else(|| anyhow::anyhow!("unbonding note has no release height"))?
+                        as u64,
+                    note,
+                )),
+                other => return Err(anyhow::anyhow!("unknown note status {:?}", other)),
+            }
+        }
+
+        Ok(state)
+    }
+}
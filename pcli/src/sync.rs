@@ -1,12 +1,114 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
 use anyhow::Result;
-use penumbra_proto::light_wallet::CompactBlockRangeRequest;
+use comfy_table::{presets, Table};
+use indicatif::{ProgressBar, ProgressStyle};
+use penumbra_crypto::merkle::TreeExt;
+use penumbra_proto::{light_wallet::CompactBlockRangeRequest, Message};
+use penumbra_wallet::ScanEvent;
+use tokio::{io::AsyncWriteExt, net::UnixStream};
 use tracing::instrument;
 
-use crate::{ClientStateFile, Opt};
+use crate::{fetch, ClientStateFile, Opt};
+
+/// What a sync did and where its time went, to tell whether a slow sync is limited by the network
+/// or by scanning.
+#[derive(Debug, Default)]
+pub struct SyncStats {
+    pub blocks: u64,
+    /// The encoded size of the compact blocks received, before any compression on the wire.
+    pub bytes: u64,
+    /// The number of notes trial-decrypted, which is every note in every block.
+    pub trial_decryptions: u64,
+    pub notes_received: u64,
+    pub notes_spent: u64,
+    /// The number of our notes whose spends were reverted by slashing.
+    pub notes_restored: u64,
+    /// The number of notes our undelegations and redelegations left in quarantine.
+    pub notes_quarantined: u64,
+    /// The time spent waiting for the node to send blocks.
+    pub fetching: Duration,
+    /// The time spent scanning blocks.
+    pub scanning: Duration,
+    /// The time spent saving the wallet.
+    pub saving: Duration,
+    /// The time spent writing to the events socket.
+    pub reporting: Duration,
+    pub total: Duration,
+}
+
+impl fmt::Display for SyncStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.total.as_secs_f64();
+        let per_sec = |n: u64| if secs > 0.0 { n as f64 / secs } else { 0.0 };
+        let share = |phase: Duration| {
+            if secs > 0.0 {
+                100.0 * phase.as_secs_f64() / secs
+            } else {
+                0.0
+            }
+        };
+
+        let mut table = Table::new();
+        table.load_preset(presets::NOTHING);
+        table.add_row(vec![
+            "Blocks".to_string(),
+            format!("{} ({:.1}/s)", self.blocks, per_sec(self.blocks)),
+        ]);
+        table.add_row(vec![
+            "Bytes".to_string(),
+            format!("{} ({:.0}/s)", self.bytes, per_sec(self.bytes)),
+        ]);
+        table.add_row(vec![
+            "Trial Decryptions".to_string(),
+            format!(
+                "{} ({:.1}/s)",
+                self.trial_decryptions,
+                per_sec(self.trial_decryptions)
+            ),
+        ]);
+        table.add_row(vec![
+            "Notes Received".to_string(),
+            self.notes_received.to_string(),
+        ]);
+        table.add_row(vec![
+            "Notes Spent".to_string(),
+            self.notes_spent.to_string(),
+        ]);
+        table.add_row(vec![
+            "Notes Restored".to_string(),
+            self.notes_restored.to_string(),
+        ]);
+        table.add_row(vec![
+            "Notes Quarantined".to_string(),
+            self.notes_quarantined.to_string(),
+        ]);
+        for (phase, time) in [
+            ("Fetching", self.fetching),
+            ("Scanning", self.scanning),
+            ("Saving", self.saving),
+            ("Reporting Events", self.reporting),
+        ] {
+            table.add_row(vec![
+                phase.to_string(),
+                format!("{:.3}s ({:.0}%)", time.as_secs_f64(), share(time)),
+            ]);
+        }
+        table.add_row(vec!["Total".to_string(), format!("{:.3}s", secs)]);
+        write!(f, "{}", table)
+    }
+}
 
 #[instrument(skip(opt, state), fields(start_height = state.last_block_height()))]
-pub async fn sync(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
+pub async fn sync(opt: &Opt, state: &mut ClientStateFile) -> Result<SyncStats> {
     tracing::info!("starting client sync");
+    let started = Instant::now();
+    let mut stats = SyncStats::default();
+    // Keep the chain params current, since they determine when unbonding stake is released.
+    fetch::chain_params(opt, state).await?;
     let mut client = opt.light_wallet_client().await?;
 
     let start_height = state.last_block_height().map(|h| h + 1).unwrap_or(0);
@@ -17,23 +119,269 @@ pub async fn sync(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
             chain_id: state
                 .chain_id()
                 .ok_or_else(|| anyhow::anyhow!("missing chain_id"))?,
+            release_validators: Vec::new(),
+            max_version: penumbra_wallet::COMPACT_BLOCK_VERSION,
         }))
         .await?
         .into_inner();
 
-    let mut count = 0;
-    while let Some(block) = stream.message().await? {
-        state.scan_block(block)?;
-        // very basic form of intermediate checkpointing
-        count += 1;
-        if count % 1000 == 1 {
+    let mut events_socket = match &opt.events_socket {
+        Some(path) => Some(UnixStream::connect(path).await?),
+        None => None,
+    };
+
+    // Used only to display progress, so don't fail the sync if we can't get it.
+    let progress = match fetch::chain_info(opt, state).await {
+        Ok(info) if !opt.no_progress && info.height >= start_height => {
+            let progress = ProgressBar::new(info.height - start_height + 1);
+            progress.set_style(
+                ProgressStyle::default_bar()
+                    .template("[{elapsed}] {bar:50} {pos}/{len} blocks ({eta} remaining)"),
+            );
+            progress
+        }
+        _ => ProgressBar::hidden(),
+    };
+
+    let checkpoint_interval = opt.sync_checkpoint_secs.map(Duration::from_secs);
+    let mut last_checkpoint = Instant::now();
+
+    loop {
+        let phase = Instant::now();
+        let block = match stream.message().await? {
+            Some(block) => block,
+            None => break,
+        };
+        stats.fetching += phase.elapsed();
+        stats.blocks += 1;
+        stats.bytes += block.encoded_len() as u64;
+        stats.trial_decryptions += block.fragments.len() as u64;
+
+        let phase = Instant::now();
+        let events = state.scan_block(block)?;
+        stats.scanning += phase.elapsed();
+        for event in &events {
+            match event {
+                ScanEvent::NoteReceived { .. } => stats.notes_received += 1,
+                ScanEvent::NoteSpent { .. } => stats.notes_spent += 1,
+                ScanEvent::NoteRestored { .. } => stats.notes_restored += 1,
+                ScanEvent::NoteQuarantined { .. } => stats.notes_quarantined += 1,
+            }
+        }
+
+        if let Some(socket) = &mut events_socket {
+            let phase = Instant::now();
+            for event in events {
+                let mut line = serde_json::to_vec(&event_json(state, &event))?;
+                line.push(b'\n');
+                socket.write_all(&line).await?;
+            }
+            stats.reporting += phase.elapsed();
+        }
+        progress.inc(1);
+
+        // Periodically save our progress, so an interrupted sync doesn't start over
+        let checkpoint_due = stats.blocks % opt.sync_checkpoint_blocks.max(1) == 0
+            || checkpoint_interval.map_or(false, |interval| last_checkpoint.elapsed() >= interval);
+        if checkpoint_due {
+            let phase = Instant::now();
             state.commit()?;
-            tracing::info!(height = ?state.last_block_height().unwrap(), "syncing...");
+            stats.saving += phase.elapsed();
+            last_checkpoint = Instant::now();
+            tracing::debug!(height = ?state.last_block_height().unwrap(), "saved sync checkpoint");
         }
     }
+    progress.finish_and_clear();
 
+    let phase = Instant::now();
     state.prune_timeouts();
     state.commit()?;
-    tracing::info!(end_height = ?state.last_block_height().unwrap(), "finished sync");
+    stats.saving += phase.elapsed();
+    stats.total = started.elapsed();
+    tracing::info!(end_height = ?state.last_block_height().unwrap(), ?stats, "finished sync");
+
+    if opt.quorum() > 1 {
+        cross_check(opt, state).await?;
+    }
+    Ok(stats)
+}
+
+/// Checks that at least [`Opt::quorum`] of the candidate nodes agree with the node synced from on
+/// the note commitment tree root and app hash of the block the wallet synced to.
+///
+/// A single malicious wallet server can hide notes or spends from the wallet, but it can't make
+/// honest servers report the same roots, so every disagreeing server is flagged.  Servers that
+/// haven't reached the block yet or can't be reached neither agree nor disagree.
+#[instrument(skip(opt, state))]
+async fn cross_check(opt: &Opt, state: &ClientStateFile) -> Result<()> {
+    let height = match state.last_block_height() {
+        Some(height) => height,
+        None => return Ok(()),
+    };
+    let chain_id = state.chain_id().unwrap_or_default();
+    let computed_root = state.note_commitment_tree().root2().to_bytes().to_vec();
+
+    let mut reported = Vec::new();
+    for node in opt.candidate_nodes() {
+        match block_roots(opt, &node, &chain_id, height).await {
+            Ok(Some(roots)) => reported.push((node, roots)),
+            Ok(None) => tracing::debug!(%node, height, "node hasn't reached the synced height"),
+            Err(e) => tracing::warn!(%node, ?e, "could not cross-check with node"),
+        }
+    }
+
+    // The node synced from already had its note commitment tree root checked while scanning, but
+    // its app hash is only as good as the other nodes' agreement with it.
+    let expected = reported
+        .iter()
+        .find(|(node, _)| node == opt.node())
+        .map(|(_, roots)| roots.clone())
+        .ok_or_else(|| anyhow::anyhow!("could not cross-check with the node synced from"))?;
+    if expected.0 != computed_root {
+        return Err(anyhow::anyhow!(
+            "node {} reports note commitment tree root {} at height {}, but the wallet computed {}",
+            opt.node(),
+            hex::encode(&expected.0),
+            height,
+            hex::encode(&computed_root)
+        ));
+    }
+
+    let mut agreeing = 0;
+    for (node, roots) in &reported {
+        if *roots == expected {
+            agreeing += 1;
+        } else {
+            eprintln!(
+                "Warning: node {} disagrees with {} at height {}: it reports note commitment \
+                 tree root {} and app hash {}, rather than {} and {}.",
+                node,
+                opt.node(),
+                height,
+                hex::encode(&roots.0),
+                hex::encode(&roots.1),
+                hex::encode(&expected.0),
+                hex::encode(&expected.1),
+            );
+        }
+    }
+
+    if agreeing < opt.quorum() {
+        return Err(anyhow::anyhow!(
+            "only {} of the {} nodes required agree on the state at height {}; the wallet may \
+             have been given false data, and may need to be reset with `pcli wallet reset`",
+            agreeing,
+            opt.quorum(),
+            height
+        ));
+    }
+    tracing::debug!(height, agreeing, "cross-checked synced state");
     Ok(())
 }
+
+/// Fetches the note commitment tree root and app hash of the block at `height` from the given
+/// node, or `None` if it doesn't have that block yet.
+async fn block_roots(
+    opt: &Opt,
+    node: &str,
+    chain_id: &str,
+    height: u64,
+) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+    let mut stream = opt
+        .light_wallet_client_for(node)
+        .await?
+        .compact_block_range(tonic::Request::new(CompactBlockRangeRequest {
+            start_height: height,
+            end_height: height,
+            chain_id: chain_id.to_string(),
+            release_validators: Vec::new(),
+            max_version: penumbra_wallet::COMPACT_BLOCK_VERSION,
+        }))
+        .await?
+        .into_inner();
+    Ok(stream
+        .message()
+        .await?
+        .filter(|block| block.height == height)
+        .map(|block| (block.nct_root.to_vec(), block.app_hash.to_vec())))
+}
+
+/// Formats a scan event as a JSON object for the events socket.
+fn event_json(state: &ClientStateFile, event: &ScanEvent) -> serde_json::Value {
+    let (kind, height, note_commitment, note, address_index, reward_source, release_height) =
+        match event {
+            ScanEvent::NoteReceived {
+                height,
+                note_commitment,
+                note,
+                address_index,
+                reward_source,
+            } => (
+                "note_received",
+                height,
+                note_commitment,
+                note,
+                Some(address_index),
+                reward_source.as_ref(),
+                None,
+            ),
+            ScanEvent::NoteSpent {
+                height,
+                note_commitment,
+                note,
+            } => (
+                "note_spent",
+                height,
+                note_commitment,
+                note,
+                None,
+                None,
+                None,
+            ),
+            ScanEvent::NoteRestored {
+                height,
+                note_commitment,
+                note,
+            } => (
+                "note_restored",
+                height,
+                note_commitment,
+                note,
+                None,
+                None,
+                None,
+            ),
+            ScanEvent::NoteQuarantined {
+                height,
+                note_commitment,
+                note,
+                release_height,
+            } => (
+                "note_quarantined",
+                height,
+                note_commitment,
+                note,
+                None,
+                None,
+                Some(release_height),
+            ),
+        };
+
+    serde_json::json!({
+        "event": kind,
+        "height": height,
+        "note_commitment": hex::encode(<[u8; 32]>::from(*note_commitment)),
+        "amount": note.amount(),
+        "asset_id": note.asset_id().to_string(),
+        // Denominations for new assets are fetched after the sync completes.
+        "denom": state.asset_cache().get(&note.asset_id()).map(ToString::to_string),
+        "address_index": address_index,
+        // Set for notes paying a validator's funding stream, e.g. commission income.
+        "reward_source": reward_source.map(|source| serde_json::json!({
+            "validator": source.validator_identity.to_string(),
+            "epoch_index": source.epoch_index,
+        })),
+        // Set for quarantined notes: the height at which they're expected to be received.
+        "release_height": release_height,
+    })
+}
@@ -0,0 +1,6 @@
+-- Transactions accepted by CheckTx but not yet included in a block, kept so
+-- that they can be revalidated and rebroadcast after a restart
+CREATE TABLE IF NOT EXISTS mempool_transactions (
+    id bytea PRIMARY KEY NOT NULL,
+    transaction bytea NOT NULL
+);
@@ -0,0 +1,2 @@
+-- Look up the rate history of a single validator without scanning every epoch
+CREATE INDEX IF NOT EXISTS validator_rates_identity_key_epoch ON validator_rates (identity_key, epoch);
@@ -0,0 +1,6 @@
+-- Validator identity key rotations, each taking effect at the end of `epoch`
+CREATE TABLE IF NOT EXISTS validator_migrations (
+    old_identity_key bytea PRIMARY KEY REFERENCES validators (identity_key),
+    new_identity_key bytea NOT NULL UNIQUE,
+    epoch bigint NOT NULL
+);
@@ -0,0 +1,12 @@
+-- The exchange rate adjustment applied to each slashed validator's delegation tokens
+CREATE TABLE IF NOT EXISTS validator_slashings (
+    identity_key bytea NOT NULL REFERENCES validators (identity_key),
+    height bigint NOT NULL,
+    epoch bigint NOT NULL,
+    penalty_bps bigint NOT NULL,
+    pre_slash_exchange_rate bigint NOT NULL,
+    post_slash_exchange_rate bigint NOT NULL,
+    PRIMARY KEY(identity_key, height),
+    -- slashing can only reduce the exchange rate
+    CONSTRAINT penalty_reduces_rate CHECK (post_slash_exchange_rate <= pre_slash_exchange_rate)
+);
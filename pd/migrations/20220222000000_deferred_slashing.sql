@@ -0,0 +1,4 @@
+-- Evidence may be for an infraction in an earlier epoch, whose exchange rate the penalty is
+-- computed against
+ALTER TABLE validator_slashings ADD COLUMN IF NOT EXISTS infraction_epoch bigint NOT NULL DEFAULT 0;
+ALTER TABLE validator_slashings ADD COLUMN IF NOT EXISTS infraction_exchange_rate bigint NOT NULL DEFAULT 0;
@@ -0,0 +1,9 @@
+-- Display metadata registered on-chain for known denominations
+CREATE TABLE IF NOT EXISTS denom_metadata (
+    asset_id bytea PRIMARY KEY REFERENCES assets (asset_id),
+    symbol varchar NOT NULL,
+    decimals integer NOT NULL,
+    description varchar NOT NULL,
+    -- the height at which the metadata was registered
+    height bigint NOT NULL
+);
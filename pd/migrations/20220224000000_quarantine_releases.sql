@@ -0,0 +1,8 @@
+-- The blocks in which each validator's quarantined notes and nullifiers were released
+CREATE TABLE IF NOT EXISTS quarantine_releases (
+    validator_identity_key bytea NOT NULL,
+    height bigint NOT NULL,
+    PRIMARY KEY (validator_identity_key, height)
+);
+
+CREATE INDEX ON quarantine_releases (height);
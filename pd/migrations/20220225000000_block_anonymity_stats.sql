@@ -0,0 +1,21 @@
+-- Anonymity-relevant statistics for each block
+CREATE TABLE IF NOT EXISTS block_anonymity_stats (
+    height bigint PRIMARY KEY REFERENCES blocks (height),
+    -- notes added to the note commitment tree in this block
+    notes_created bigint NOT NULL,
+    -- nullifiers revealed by spends in this block
+    nullifiers_revealed bigint NOT NULL,
+    -- the number of notes in the note commitment tree after this block
+    note_set_size bigint NOT NULL,
+    -- the number of nullifiers in the nullifier set after this block
+    nullifier_set_size bigint NOT NULL
+);
+
+-- The number of transactions of each shape in each block
+CREATE TABLE IF NOT EXISTS transaction_shapes (
+    height bigint NOT NULL REFERENCES blocks (height),
+    spends bigint NOT NULL,
+    outputs bigint NOT NULL,
+    count bigint NOT NULL,
+    PRIMARY KEY (height, spends, outputs)
+);
@@ -0,0 +1,7 @@
+-- The total fees paid by the transactions in each block, per asset
+CREATE TABLE IF NOT EXISTS block_fees (
+    height bigint NOT NULL REFERENCES blocks (height),
+    asset_id bytea NOT NULL,
+    amount bigint NOT NULL,
+    PRIMARY KEY (height, asset_id)
+);
@@ -0,0 +1,73 @@
+-- The rows written at each height, in the order they were written, so that followers and indexers
+-- can replay them rather than re-executing blocks.  A changed row has both an old and a new value,
+-- an inserted row only a new one, and a deleted row only an old one.
+CREATE TABLE IF NOT EXISTS changefeed (
+    seq bigserial PRIMARY KEY,
+    height bigint NOT NULL,
+    table_name text NOT NULL,
+    old_row jsonb,
+    new_row jsonb
+);
+
+CREATE INDEX IF NOT EXISTS changefeed_height_idx ON changefeed (height);
+
+-- Records a row change in the changefeed, if the writing transaction has set
+-- `penumbra.changefeed_height`, and otherwise does nothing.
+CREATE OR REPLACE FUNCTION record_change() RETURNS trigger AS $$
+DECLARE
+    change_height text := current_setting('penumbra.changefeed_height', true);
+BEGIN
+    IF change_height IS NULL OR change_height = '' THEN
+        RETURN NULL;
+    END IF;
+    INSERT INTO changefeed (height, table_name, old_row, new_row) VALUES (
+        change_height::bigint,
+        TG_TABLE_NAME,
+        CASE WHEN TG_OP = 'INSERT' THEN NULL ELSE to_jsonb(OLD) END,
+        CASE WHEN TG_OP = 'DELETE' THEN NULL ELSE to_jsonb(NEW) END
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Every table of chain state, but not the node's own mempool.
+CREATE TRIGGER blobs_changefeed AFTER INSERT OR UPDATE OR DELETE ON blobs
+    FOR EACH ROW EXECUTE FUNCTION record_change();
+CREATE TRIGGER jmt_changefeed AFTER INSERT OR UPDATE OR DELETE ON jmt
+    FOR EACH ROW EXECUTE FUNCTION record_change();
+CREATE TRIGGER assets_changefeed AFTER INSERT OR UPDATE OR DELETE ON assets
+    FOR EACH ROW EXECUTE FUNCTION record_change();
+CREATE TRIGGER blocks_changefeed AFTER INSERT OR UPDATE OR DELETE ON blocks
+    FOR EACH ROW EXECUTE FUNCTION record_change();
+CREATE TRIGGER nullifiers_changefeed AFTER INSERT OR UPDATE OR DELETE ON nullifiers
+    FOR EACH ROW EXECUTE FUNCTION record_change();
+CREATE TRIGGER notes_changefeed AFTER INSERT OR UPDATE OR DELETE ON notes
+    FOR EACH ROW EXECUTE FUNCTION record_change();
+CREATE TRIGGER validators_changefeed AFTER INSERT OR UPDATE OR DELETE ON validators
+    FOR EACH ROW EXECUTE FUNCTION record_change();
+CREATE TRIGGER validator_fundingstreams_changefeed AFTER INSERT OR UPDATE OR DELETE ON validator_fundingstreams
+    FOR EACH ROW EXECUTE FUNCTION record_change();
+CREATE TRIGGER base_rates_changefeed AFTER INSERT OR UPDATE OR DELETE ON base_rates
+    FOR EACH ROW EXECUTE FUNCTION record_change();
+CREATE TRIGGER validator_rates_changefeed AFTER INSERT OR UPDATE OR DELETE ON validator_rates
+    FOR EACH ROW EXECUTE FUNCTION record_change();
+CREATE TRIGGER delegation_changes_changefeed AFTER INSERT OR UPDATE OR DELETE ON delegation_changes
+    FOR EACH ROW EXECUTE FUNCTION record_change();
+CREATE TRIGGER quarantined_notes_changefeed AFTER INSERT OR UPDATE OR DELETE ON quarantined_notes
+    FOR EACH ROW EXECUTE FUNCTION record_change();
+CREATE TRIGGER quarantined_nullifiers_changefeed AFTER INSERT OR UPDATE OR DELETE ON quarantined_nullifiers
+    FOR EACH ROW EXECUTE FUNCTION record_change();
+CREATE TRIGGER validator_migrations_changefeed AFTER INSERT OR UPDATE OR DELETE ON validator_migrations
+    FOR EACH ROW EXECUTE FUNCTION record_change();
+CREATE TRIGGER validator_slashings_changefeed AFTER INSERT OR UPDATE OR DELETE ON validator_slashings
+    FOR EACH ROW EXECUTE FUNCTION record_change();
+CREATE TRIGGER denom_metadata_changefeed AFTER INSERT OR UPDATE OR DELETE ON denom_metadata
+    FOR EACH ROW EXECUTE FUNCTION record_change();
+CREATE TRIGGER quarantine_releases_changefeed AFTER INSERT OR UPDATE OR DELETE ON quarantine_releases
+    FOR EACH ROW EXECUTE FUNCTION record_change();
+CREATE TRIGGER block_anonymity_stats_changefeed AFTER INSERT OR UPDATE OR DELETE ON block_anonymity_stats
+    FOR EACH ROW EXECUTE FUNCTION record_change();
+CREATE TRIGGER transaction_shapes_changefeed AFTER INSERT OR UPDATE OR DELETE ON transaction_shapes
+    FOR EACH ROW EXECUTE FUNCTION record_change();
+CREATE TRIGGER block_fees_changefeed AFTER INSERT OR UPDATE OR DELETE ON block_fees
+    FOR EACH ROW EXECUTE FUNCTION record_change();
@@ -0,0 +1,4 @@
+-- The validator whose funding stream a reward note pays, and the epoch it was paid in, for notes
+-- minted by the chain rather than by a transaction
+ALTER TABLE notes ADD COLUMN IF NOT EXISTS reward_validator_identity_key bytea;
+ALTER TABLE notes ADD COLUMN IF NOT EXISTS reward_epoch bigint;
@@ -0,0 +1,24 @@
+-- The stake unbonded by each quarantined undelegation (NULL if unknown), for totalling the stake
+-- scheduled to unbond.  Rows are deleted when the undelegation's notes are released, or reverted
+-- because the validator was slashed.
+CREATE TABLE IF NOT EXISTS quarantined_unbondings (
+    transaction_id bytea PRIMARY KEY,
+    validator_identity_key bytea NOT NULL REFERENCES validators (identity_key),
+    unbonding_height bigint NOT NULL,
+    amount bigint,
+    CONSTRAINT positive_unbonding_height CHECK (unbonding_height >= 0)
+);
+CREATE INDEX ON quarantined_unbondings (unbonding_height);
+
+-- Undelegations quarantined before this table existed didn't record their amount, so they are
+-- backfilled with an unknown amount, and the totals they're part of are partial until they are
+-- released, at most one unbonding period from now.  They still need rows, so that the staking
+-- component knows to release them.
+INSERT INTO quarantined_unbondings (transaction_id, validator_identity_key, unbonding_height, amount)
+    SELECT DISTINCT ON (transaction_id) transaction_id, validator_identity_key, unbonding_height, NULL
+    FROM quarantined_notes
+    ON CONFLICT DO NOTHING;
+
+CREATE TRIGGER quarantined_unbondings_changefeed
+    AFTER INSERT OR UPDATE OR DELETE ON quarantined_unbondings
+    FOR EACH ROW EXECUTE FUNCTION record_change();
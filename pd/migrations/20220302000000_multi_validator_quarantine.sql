@@ -0,0 +1,10 @@
+-- A transaction may undelegate from several validators, so its notes and nullifiers are
+-- quarantined once per validator, and reverted if any of them is slashed.
+ALTER TABLE quarantined_notes DROP CONSTRAINT quarantined_notes_pkey;
+ALTER TABLE quarantined_notes ADD PRIMARY KEY (note_commitment, validator_identity_key);
+
+ALTER TABLE quarantined_nullifiers DROP CONSTRAINT quarantined_nullifiers_pkey;
+ALTER TABLE quarantined_nullifiers ADD PRIMARY KEY (nullifier, validator_identity_key);
+
+ALTER TABLE quarantined_unbondings DROP CONSTRAINT quarantined_unbondings_pkey;
+ALTER TABLE quarantined_unbondings ADD PRIMARY KEY (transaction_id, validator_identity_key);
@@ -0,0 +1,3 @@
+-- The total supply of an asset is the sum of every note of it, so it may exceed a bigint even
+-- though each note's amount fits in one.  A numeric(39) holds any 128-bit unsigned integer.
+ALTER TABLE assets ALTER COLUMN total_supply TYPE numeric(39, 0);
@@ -0,0 +1,15 @@
+-- Funding streams to the community pool have no address.
+ALTER TABLE validator_fundingstreams ALTER COLUMN address DROP NOT NULL;
+
+-- The commission each validator's funding streams deposited into the community pool in each block.
+-- The community pool's balance is the sum of its deposits.
+CREATE TABLE IF NOT EXISTS community_pool_deposits (
+    height bigint NOT NULL REFERENCES blocks (height),
+    validator_identity_key bytea NOT NULL,
+    amount bigint NOT NULL,
+    PRIMARY KEY (height, validator_identity_key)
+);
+
+CREATE TRIGGER community_pool_deposits_changefeed
+    AFTER INSERT OR UPDATE OR DELETE ON community_pool_deposits
+    FOR EACH ROW EXECUTE FUNCTION record_change();
@@ -0,0 +1,9 @@
+-- The epoch in which each delegator key last redelegated, which starts its redelegation cooldown.
+CREATE TABLE IF NOT EXISTS redelegations (
+    delegator_key bytea PRIMARY KEY,
+    epoch_index bigint NOT NULL
+);
+
+CREATE TRIGGER redelegations_changefeed
+    AFTER INSERT OR UPDATE OR DELETE ON redelegations
+    FOR EACH ROW EXECUTE FUNCTION record_change();
@@ -0,0 +1,15 @@
+-- The total supply of each asset as of the last block of each epoch in which it changed, for
+-- charting supplies over time.  History starts when this table is created; supplies before then
+-- are only known as of the latest block, in `assets`.
+CREATE TABLE IF NOT EXISTS supply_history (
+    asset_id bytea NOT NULL REFERENCES assets (asset_id),
+    epoch_index bigint NOT NULL,
+    -- The height of the last block in the epoch that changed the supply.
+    height bigint NOT NULL,
+    total_supply numeric(39, 0) NOT NULL,
+    PRIMARY KEY (asset_id, epoch_index)
+);
+
+CREATE TRIGGER supply_history_changefeed
+    AFTER INSERT OR UPDATE OR DELETE ON supply_history
+    FOR EACH ROW EXECUTE FUNCTION record_change();
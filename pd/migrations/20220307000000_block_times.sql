@@ -0,0 +1,3 @@
+-- The time in each block's header, in milliseconds since the UNIX epoch, for estimating when
+-- future heights will be reached.  Blocks committed before this column existed have no time.
+ALTER TABLE blocks ADD COLUMN time_ms bigint;
@@ -0,0 +1,13 @@
+-- The IDs of transactions included while their anchor is still valid, so that an identical
+-- transaction can't be included again.  Nullifiers already prevent replaying transactions that
+-- spend notes, but not ones that don't, like validator definitions.  Once a transaction's anchor
+-- falls out of the window of recent anchors it can no longer be included, so its row is removed.
+CREATE TABLE IF NOT EXISTS recent_transactions (
+    id bytea PRIMARY KEY,
+    height bigint NOT NULL REFERENCES blocks (height),
+    anchor bytea NOT NULL
+);
+
+CREATE TRIGGER recent_transactions_changefeed
+    AFTER INSERT OR UPDATE OR DELETE ON recent_transactions
+    FOR EACH ROW EXECUTE FUNCTION record_change();
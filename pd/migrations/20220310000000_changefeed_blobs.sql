@@ -0,0 +1,36 @@
+-- The note commitment tree is rewritten in full at every height, so copying it into the changefeed
+-- would grow the changefeed by the size of the whole tree per block.  Only its key is recorded, and
+-- followers rebuild the tree from the notes they apply.
+CREATE OR REPLACE FUNCTION record_blob_change() RETURNS trigger AS $$
+DECLARE
+    change_height text := current_setting('penumbra.changefeed_height', true);
+BEGIN
+    IF change_height IS NULL OR change_height = '' THEN
+        RETURN NULL;
+    END IF;
+    INSERT INTO changefeed (height, table_name, old_row, new_row) VALUES (
+        change_height::bigint,
+        TG_TABLE_NAME,
+        CASE
+            WHEN TG_OP = 'INSERT' THEN NULL
+            WHEN OLD.id = 'nct' THEN to_jsonb(OLD) - 'data'
+            ELSE to_jsonb(OLD)
+        END,
+        CASE
+            WHEN TG_OP = 'DELETE' THEN NULL
+            WHEN NEW.id = 'nct' THEN to_jsonb(NEW) - 'data'
+            ELSE to_jsonb(NEW)
+        END
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS blobs_changefeed ON blobs;
+CREATE TRIGGER blobs_changefeed AFTER INSERT OR UPDATE OR DELETE ON blobs
+    FOR EACH ROW EXECUTE FUNCTION record_blob_change();
+
+-- Drop the copies of the tree already recorded.
+UPDATE changefeed SET old_row = old_row - 'data', new_row = new_row - 'data'
+    WHERE table_name = 'blobs'
+    AND (old_row ->> 'id' = 'nct' OR new_row ->> 'id' = 'nct');
@@ -0,0 +1,15 @@
+-- The nullifiers whose spends were reverted in each block, because they were quarantined for a
+-- validator that was slashed, so that clients that already saw the spend can make the note
+-- spendable again.  A reverted note can be spent again later, so a nullifier can appear more than
+-- once.
+CREATE TABLE IF NOT EXISTS reverted_nullifiers (
+    nullifier bytea NOT NULL,
+    height bigint NOT NULL REFERENCES blocks (height),
+    PRIMARY KEY (nullifier, height)
+);
+
+CREATE INDEX ON reverted_nullifiers (height);
+
+CREATE TRIGGER reverted_nullifiers_changefeed
+    AFTER INSERT OR UPDATE OR DELETE ON reverted_nullifiers
+    FOR EACH ROW EXECUTE FUNCTION record_change();
@@ -0,0 +1,250 @@
+//! An administrative service for node operators, served on a Unix domain socket.
+//!
+//! The socket is created readable and writable only by the user running `pd`, so connecting to
+//! it is the authentication: anyone who can, could also have edited `pd`'s config or database.
+
+use std::{
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use penumbra_proto::admin::{
+    admin_client::AdminClient,
+    admin_server::{self, AdminServer},
+    BlockWrites, BlockWritesRequest, BlockWritesResponse, CircuitBreakerStatus, DelegationChange,
+    PendingBlockInfo, PendingBlockRequest, QueueDepths, QueueDepthsRequest, ReloadConfigRequest,
+    ReloadConfigResponse, RotateLogsRequest, RotateLogsResponse, RowCount,
+    SetCircuitBreakerRequest, SnapshotRequest, SnapshotResponse,
+};
+use tokio::net::{UnixListener, UnixStream};
+use tokio_stream::wrappers::UnixListenerStream;
+use tonic::{
+    transport::{Channel, Endpoint, Server},
+    Status,
+};
+use tracing::instrument;
+
+use crate::{log_file::LogFile, state, CircuitBreaker, Consensus, LiveConfig, StatelessCache};
+
+/// The handles the admin service needs into the rest of the node.
+#[derive(Clone)]
+pub struct Admin {
+    pub state: state::Reader,
+    pub consensus: Consensus,
+    pub stateless_cache: StatelessCache,
+    pub circuit_breaker: CircuitBreaker,
+    /// The log file, if `pd` is logging to one rather than to stdout.
+    pub log_file: Option<LogFile>,
+    /// The directory state snapshots are written into.
+    pub snapshot_dir: PathBuf,
+    pub config: LiveConfig,
+}
+
+impl Admin {
+    /// Serves the admin service on a Unix domain socket at `socket`, replacing any stale socket
+    /// left there by a previous run.
+    pub async fn serve(self, socket: &Path) -> Result<()> {
+        if socket.exists() {
+            std::fs::remove_file(socket)
+                .with_context(|| format!("could not remove stale socket {}", socket.display()))?;
+        }
+        let listener = UnixListener::bind(socket)
+            .with_context(|| format!("could not bind admin socket {}", socket.display()))?;
+        std::fs::set_permissions(socket, std::fs::Permissions::from_mode(0o600))?;
+        tracing::info!(?socket, "serving admin service");
+
+        Server::builder()
+            .trace_fn(|_| tracing::error_span!("admin"))
+            .add_service(AdminServer::new(self))
+            .serve_with_incoming(UnixListenerStream::new(listener))
+            .await?;
+        Ok(())
+    }
+
+    /// Writes a state snapshot each time the committed height passes a multiple of the configured
+    /// `snapshot_interval`.
+    ///
+    /// Snapshots are taken at the latest committed height, which may be a little past the
+    /// multiple if blocks are committed faster than snapshots are taken.
+    pub async fn write_periodic_snapshots(self) -> Result<()> {
+        let mut height_rx = self.state.height_rx().clone();
+        let mut last_height = height_rx.borrow_and_update().value();
+        loop {
+            height_rx.changed().await?;
+            let height = height_rx.borrow_and_update().value();
+            let due = match self.config.current().snapshot_interval {
+                Some(interval) => height / interval > last_height / interval,
+                None => false,
+            };
+            last_height = height;
+            if !due {
+                continue;
+            }
+
+            if let Err(e) = self.write_periodic_snapshot().await {
+                tracing::error!(?e, height, "could not write periodic state snapshot");
+            }
+        }
+    }
+
+    async fn write_periodic_snapshot(&self) -> Result<()> {
+        let snapshot = self.state.state_snapshot().await?;
+        let path = snapshot.write_to_dir(&self.snapshot_dir)?;
+        tracing::info!(
+            height = snapshot.height,
+            ?path,
+            "wrote periodic state snapshot"
+        );
+        Ok(())
+    }
+}
+
+/// Connects to the admin service on the Unix domain socket at `socket`.
+pub async fn connect(socket: PathBuf) -> Result<AdminClient<Channel>> {
+    // The URI is required, but ignored in favor of the connector.
+    let channel = Endpoint::from_static("http://[::]:0")
+        .connect_with_connector(tower::service_fn(move |_| {
+            UnixStream::connect(socket.clone())
+        }))
+        .await
+        .context("could not connect to the admin socket")?;
+    Ok(AdminClient::new(channel))
+}
+
+#[tonic::async_trait]
+impl admin_server::Admin for Admin {
+    #[instrument(skip(self, _request))]
+    async fn snapshot(
+        &self,
+        _request: tonic::Request<SnapshotRequest>,
+    ) -> Result<tonic::Response<SnapshotResponse>, Status> {
+        let snapshot = self
+            .state
+            .state_snapshot()
+            .await
+            .map_err(|e| Status::unavailable(format!("could not take snapshot: {:#}", e)))?;
+        let path = snapshot
+            .write_to_dir(&self.snapshot_dir)
+            .map_err(|e| Status::internal(format!("could not write snapshot: {:#}", e)))?;
+        tracing::info!(height = snapshot.height, ?path, "wrote state snapshot");
+
+        Ok(tonic::Response::new(SnapshotResponse {
+            height: snapshot.height,
+            path: path.display().to_string(),
+        }))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn rotate_logs(
+        &self,
+        _request: tonic::Request<RotateLogsRequest>,
+    ) -> Result<tonic::Response<RotateLogsResponse>, Status> {
+        let log_file = self
+            .log_file
+            .as_ref()
+            .ok_or_else(|| Status::failed_precondition("pd is not logging to a file"))?;
+        log_file
+            .reopen()
+            .map_err(|e| Status::internal(format!("{:#}", e)))?;
+        tracing::info!(path = ?log_file.path(), "reopened log file");
+
+        Ok(tonic::Response::new(RotateLogsResponse {
+            path: log_file.path().display().to_string(),
+        }))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn pending_block(
+        &self,
+        _request: tonic::Request<PendingBlockRequest>,
+    ) -> Result<tonic::Response<PendingBlockInfo>, Status> {
+        let info = match self.consensus.pending_block() {
+            Some(summary) => PendingBlockInfo {
+                in_progress: true,
+                height: summary.height.unwrap_or(0),
+                transaction_ids: summary
+                    .transaction_ids
+                    .iter()
+                    .map(|id| id.to_vec())
+                    .collect(),
+                notes: summary.notes as u64,
+                spent_nullifiers: summary.spent_nullifiers as u64,
+                quarantine_groups: summary.quarantine_groups as u64,
+                delegation_changes: summary
+                    .delegation_changes
+                    .into_iter()
+                    .map(|(identity_key, delegation_change)| DelegationChange {
+                        identity_key: Some(identity_key.into()),
+                        delegation_change,
+                    })
+                    .collect(),
+            },
+            None => PendingBlockInfo::default(),
+        };
+        Ok(tonic::Response::new(info))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn set_circuit_breaker(
+        &self,
+        request: tonic::Request<SetCircuitBreakerRequest>,
+    ) -> Result<tonic::Response<CircuitBreakerStatus>, Status> {
+        self.circuit_breaker.set(request.into_inner().tripped);
+        Ok(tonic::Response::new(CircuitBreakerStatus {
+            tripped: self.circuit_breaker.is_tripped(),
+        }))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn queue_depths(
+        &self,
+        _request: tonic::Request<QueueDepthsRequest>,
+    ) -> Result<tonic::Response<QueueDepths>, Status> {
+        Ok(tonic::Response::new(QueueDepths {
+            consensus_queue_depth: self.consensus.queue_depth() as u64,
+            consensus_queue_capacity: crate::consensus::QUEUE_CAPACITY as u64,
+            stateless_cache_entries: self.stateless_cache.len() as u64,
+            stateless_cache_capacity: self.stateless_cache.capacity() as u64,
+        }))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn block_writes(
+        &self,
+        _request: tonic::Request<BlockWritesRequest>,
+    ) -> Result<tonic::Response<BlockWritesResponse>, Status> {
+        let blocks = self
+            .consensus
+            .recent_writes()
+            .into_iter()
+            .map(|writes| BlockWrites {
+                height: writes.height,
+                rows: writes
+                    .by_kind()
+                    .iter()
+                    .map(|(kind, rows)| RowCount {
+                        kind: kind.to_string(),
+                        rows: *rows,
+                    })
+                    .collect(),
+            })
+            .collect();
+        Ok(tonic::Response::new(BlockWritesResponse { blocks }))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn reload_config(
+        &self,
+        _request: tonic::Request<ReloadConfigRequest>,
+    ) -> Result<tonic::Response<ReloadConfigResponse>, Status> {
+        let config = self
+            .config
+            .reload()
+            .map_err(|e| Status::failed_precondition(format!("{:#}", e)))?;
+        Ok(tonic::Response::new(ReloadConfigResponse {
+            config_json: serde_json::to_string_pretty(&config)
+                .map_err(|e| Status::internal(e.to_string()))?,
+        }))
+    }
+}
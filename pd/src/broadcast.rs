@@ -0,0 +1,177 @@
+//! A service for submitting transactions through `pd`, so that they can be followed from the
+//! client through `CheckTx` and `DeliverTx` in distributed traces.
+
+use std::collections::VecDeque;
+
+use penumbra_crypto::merkle;
+use penumbra_proto::{
+    broadcast::{
+        AnchorWindowRequest, AnchorWindowResponse, BroadcastTransactionRequest,
+        BroadcastTransactionResponse,
+    },
+    client::v1alpha1::broadcast_server,
+};
+use tonic::Status;
+use tracing::{field, instrument, Span};
+
+use crate::{state, StatelessCache, TraceContexts, NUM_RECENT_ANCHORS};
+
+/// The handles the broadcast service needs into the rest of the node.
+#[derive(Clone)]
+pub struct Broadcast {
+    pub state: state::Reader,
+    /// The URL of Tendermint's RPC endpoint, which transactions are forwarded to.
+    pub tendermint_rpc: String,
+    pub trace_contexts: TraceContexts,
+}
+
+#[tonic::async_trait]
+impl broadcast_server::Broadcast for Broadcast {
+    #[instrument(skip(self, request), fields(txid, traceparent))]
+    async fn broadcast_transaction(
+        &self,
+        request: tonic::Request<BroadcastTransactionRequest>,
+    ) -> Result<tonic::Response<BroadcastTransactionResponse>, Status> {
+        let traceparent = match request.metadata().get("traceparent") {
+            Some(value) => Some(
+                value
+                    .to_str()
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .ok_or_else(|| Status::invalid_argument("invalid traceparent"))?,
+            ),
+            None => None,
+        };
+        let request = request.into_inner();
+        self.state.check_chain_id(&request.chain_id)?;
+
+        // This is the key the mempool and consensus services look the trace context up by, and
+        // also the ID in their spans.
+        let key = StatelessCache::key(&request.transaction);
+        Span::current().record("txid", &field::debug(hex::encode(key)));
+        if let Some(traceparent) = traceparent {
+            Span::current().record("traceparent", &field::display(&traceparent));
+            // Record the context before Tendermint can pass the transaction to CheckTx.
+            self.trace_contexts.insert(key, traceparent);
+        }
+
+        let method = if request.await_check {
+            "broadcast_tx_sync"
+        } else {
+            "broadcast_tx_async"
+        };
+        let rsp: serde_json::Value = reqwest::Client::new()
+            .post(&self.tendermint_rpc)
+            .json(&serde_json::json!(
+                {
+                    "method": method,
+                    "params": [&request.transaction],
+                    "id": 0,
+                }
+            ))
+            .send()
+            .await
+            .map_err(|e| {
+                tracing::warn!(?e, "could not reach tendermint");
+                Status::unavailable("could not reach tendermint")
+            })?
+            .json()
+            .await
+            .map_err(|_| Status::unavailable("could not parse tendermint response"))?;
+
+        // Sometimes the result is in a result key, and sometimes it's bare.
+        let result = rsp.get("result").unwrap_or(&rsp);
+        let code = result.get("code").and_then(|c| c.as_u64()).unwrap_or(0);
+        let log = result
+            .get("log")
+            .and_then(|l| l.as_str())
+            .unwrap_or_default();
+        tracing::debug!(code, log, "broadcast transaction");
+
+        Ok(tonic::Response::new(BroadcastTransactionResponse {
+            code: code as u32,
+            log: log.to_string(),
+        }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn anchor_window(
+        &self,
+        request: tonic::Request<AnchorWindowRequest>,
+    ) -> Result<tonic::Response<AnchorWindowResponse>, Status> {
+        let request = request.into_inner();
+        self.state.check_chain_id(&request.chain_id)?;
+        let anchor = request
+            .anchor
+            .map(merkle::Root::try_from)
+            .transpose()
+            .map_err(|_| Status::invalid_argument("invalid anchor"))?;
+
+        let height = self.state.height_rx().borrow().value();
+        let valid_anchors = self.state.valid_anchors_rx().borrow().clone();
+        let latest_anchor = valid_anchors
+            .front()
+            .cloned()
+            .ok_or_else(|| Status::unavailable("no blocks have been committed yet"))?;
+        let blocks_remaining = anchor
+            .map(|anchor| blocks_remaining(&valid_anchors, &anchor))
+            .unwrap_or(0);
+
+        Ok(tonic::Response::new(AnchorWindowResponse {
+            height,
+            latest_anchor: Some(latest_anchor.into()),
+            window_size: NUM_RECENT_ANCHORS as u64,
+            blocks_remaining,
+            valid_within: blocks_remaining >= request.within_blocks,
+        }))
+    }
+}
+
+/// Returns how many of the upcoming blocks will still accept `anchor`, given the valid anchors,
+/// most recent first.
+///
+/// The next block is verified against the current window, and each block after it pushes the
+/// oldest root out, so an anchor at position `i` is accepted by the next `NUM_RECENT_ANCHORS - i`
+/// blocks.  Blocks that don't add any notes repeat the previous root, so the most recent position
+/// is the one that counts.
+fn blocks_remaining(valid_anchors: &VecDeque<merkle::Root>, anchor: &merkle::Root) -> u64 {
+    valid_anchors
+        .iter()
+        .position(|valid_anchor| valid_anchor == anchor)
+        .map(|position| NUM_RECENT_ANCHORS.saturating_sub(position) as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::{
+        merkle::{Frontier, NoteCommitmentTree, TreeExt},
+        note, Fq,
+    };
+
+    use super::*;
+
+    #[test]
+    fn anchors_expire_in_order() {
+        let mut tree = NoteCommitmentTree::new(0);
+        let old_anchor = tree.root2();
+        tree.append(&note::Commitment(Fq::from(1u64)));
+        let new_anchor = tree.root2();
+
+        // The old root was repeated by a block with no notes before the new one.
+        let valid_anchors = [new_anchor.clone(), old_anchor.clone(), old_anchor.clone()]
+            .into_iter()
+            .collect::<VecDeque<_>>();
+        assert_eq!(
+            blocks_remaining(&valid_anchors, &new_anchor),
+            NUM_RECENT_ANCHORS as u64
+        );
+        assert_eq!(
+            blocks_remaining(&valid_anchors, &old_anchor),
+            NUM_RECENT_ANCHORS as u64 - 1
+        );
+
+        tree.append(&note::Commitment(Fq::from(2u64)));
+        assert_eq!(blocks_remaining(&valid_anchors, &tree.root2()), 0);
+    }
+}
@@ -0,0 +1,53 @@
+//! What code a `pd` binary was built from, so that operators can confirm exactly what a node is
+//! running, e.g. when nodes disagree about the app hash.
+
+use penumbra_proto::light_wallet as pb;
+use serde::Serialize;
+
+use crate::component;
+
+/// The version, commit, profile and features a `pd` binary was built with.
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildInfo {
+    /// The semver version, from `git describe`.
+    pub version: &'static str,
+    /// The commit the binary was built from.
+    pub git_commit: &'static str,
+    /// The Cargo profile, e.g. `debug` or `release`.
+    pub profile: &'static str,
+    /// The Cargo features enabled in the build.
+    pub features: Vec<&'static str>,
+    /// The components whose state transitions the node runs, in order.
+    pub components: Vec<&'static str>,
+}
+
+impl BuildInfo {
+    /// Returns the build info of this binary.
+    pub fn current() -> Self {
+        Self {
+            version: env!("VERGEN_GIT_SEMVER"),
+            git_commit: env!("VERGEN_GIT_SHA"),
+            profile: env!("VERGEN_CARGO_PROFILE"),
+            features: env!("VERGEN_CARGO_FEATURES")
+                .split(',')
+                .filter(|feature| !feature.is_empty())
+                .collect(),
+            components: component::all()
+                .iter()
+                .map(|component| component.name())
+                .collect(),
+        }
+    }
+}
+
+impl From<BuildInfo> for pb::BuildInfo {
+    fn from(info: BuildInfo) -> Self {
+        pb::BuildInfo {
+            version: info.version.to_string(),
+            git_commit: info.git_commit.to_string(),
+            profile: info.profile.to_string(),
+            features: info.features.into_iter().map(Into::into).collect(),
+            components: info.components.into_iter().map(Into::into).collect(),
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use penumbra_proto::changefeed::{changefeed_server::Changefeed, WriteBatch, WriteBatchesRequest};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Status;
+use tracing::{instrument, Instrument, Span};
+
+use crate::state;
+
+#[tonic::async_trait]
+impl Changefeed for state::Reader {
+    type WriteBatchesStream = ReceiverStream<Result<WriteBatch, Status>>;
+
+    #[instrument(skip(self, request), fields(start_height = request.get_ref().start_height))]
+    async fn write_batches(
+        &self,
+        request: tonic::Request<WriteBatchesRequest>,
+    ) -> Result<tonic::Response<Self::WriteBatchesStream>, Status> {
+        self.check_chain_id(&request.get_ref().chain_id)?;
+        let mut next_height = request.into_inner().start_height;
+
+        // Batches from before the changefeed was enabled weren't recorded, and streaming them as
+        // empty batches would silently leave the follower behind.
+        let current_height = self.height_rx().borrow().value();
+        let first_height = self
+            .changefeed_start()
+            .await
+            .map_err(|_| tonic::Status::unavailable("database error"))?
+            .unwrap_or(current_height + 1);
+        if next_height < first_height {
+            return Err(tonic::Status::failed_precondition(format!(
+                "the changefeed starts at height {}",
+                first_height
+            )));
+        }
+
+        let state = self.clone();
+        let (tx, rx) = mpsc::channel(16);
+        tokio::spawn(
+            async move {
+                let mut height_rx = state.height_rx().clone();
+                loop {
+                    // The genesis batch is only complete once the first block is committed.
+                    let height = height_rx.borrow_and_update().value();
+                    while height > 0 && next_height <= height {
+                        let batch = match state.write_batch(next_height).await {
+                            Ok(batch) => batch,
+                            Err(e) => {
+                                tracing::error!(?e, "error reading write batch");
+                                let _ = tx
+                                    .send(Err(tonic::Status::unavailable("database error")))
+                                    .await;
+                                return;
+                            }
+                        };
+                        if tx.send(Ok(batch)).await.is_err() {
+                            // The client hung up.
+                            return;
+                        }
+                        next_height += 1;
+                    }
+                    if height_rx.changed().await.is_err() {
+                        return;
+                    }
+                }
+            }
+            .instrument(Span::current()),
+        );
+
+        Ok(tonic::Response::new(Self::WriteBatchesStream::new(rx)))
+    }
+}
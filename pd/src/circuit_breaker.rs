@@ -0,0 +1,142 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use penumbra_chain::ResponseCode;
+
+use crate::verify::PendingTransaction;
+
+/// The `CheckTx` and `DeliverTx` response code for transactions rejected because the circuit
+/// breaker is tripped or the chain is in an emergency halt, so that clients can tell them apart
+/// from invalid transactions.
+pub const CIRCUIT_BREAKER_CODE: u32 = ResponseCode::CircuitBreakerTripped as u32;
+
+/// An emergency switch that makes the node keep every transaction that moves value out of its
+/// mempool.
+///
+/// This is for incident response, e.g. when a vulnerability is discovered that would let
+/// transactions create value: a validator can stop proposing such transactions right away.
+/// Transactions that don't move value, like validator definitions and migrations, are still
+/// accepted, so validators can keep managing their keys.
+///
+/// The switch is local to the node, so it only applies to `CheckTx`: which transactions are valid
+/// in a block can't depend on it, or nodes that disagree would compute different app hashes.
+/// Rejecting value-moving transactions in blocks is up to the chain's
+/// [`emergency_halt_height`](penumbra_chain::params::ChainParams::emergency_halt_height), which
+/// every node agrees on, but which can only be set at genesis for now.
+///
+/// Clones of a `CircuitBreaker` share the same switch.
+#[derive(Clone, Debug, Default)]
+pub struct CircuitBreaker {
+    tripped: Arc<AtomicBool>,
+}
+
+impl CircuitBreaker {
+    /// Creates a circuit breaker, initially tripped or not.
+    pub fn new(tripped: bool) -> Self {
+        let breaker = Self::default();
+        breaker.set(tripped);
+        breaker
+    }
+
+    /// Trips or resets the circuit breaker.
+    pub fn set(&self, tripped: bool) {
+        if self.tripped.swap(tripped, Ordering::SeqCst) != tripped {
+            tracing::warn!(tripped, "circuit breaker changed");
+        }
+    }
+
+    /// Whether the circuit breaker is tripped.
+    pub fn is_tripped(&self) -> bool {
+        self.tripped.load(Ordering::SeqCst)
+    }
+
+    /// Checks whether a transaction may be accepted into the mempool, returning a
+    /// [`CircuitBreakerTripped`] error if the circuit breaker is tripped and the transaction moves
+    /// value.
+    pub fn check(&self, transaction: &PendingTransaction) -> anyhow::Result<()> {
+        if moves_value(transaction) && self.is_tripped() {
+            return Err(CircuitBreakerTripped.into());
+        }
+        Ok(())
+    }
+}
+
+/// Whether a transaction moves value, and so is rejected while the circuit breaker is tripped or
+/// the chain is in an emergency halt.
+pub(crate) fn moves_value(transaction: &PendingTransaction) -> bool {
+    !transaction.new_notes.is_empty()
+        || !transaction.spent_nullifiers.is_empty()
+        || !transaction.delegations.is_empty()
+        || !transaction.undelegations.is_empty()
+}
+
+/// The error returned for a transaction rejected by a tripped [`CircuitBreaker`], or during an
+/// emergency halt.
+#[derive(Debug)]
+pub struct CircuitBreakerTripped;
+
+impl fmt::Display for CircuitBreakerTripped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("transactions that move value are not being accepted during an emergency")
+    }
+}
+
+impl std::error::Error for CircuitBreakerTripped {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use penumbra_crypto::{merkle, Fq, Nullifier, Value, Zero};
+    use penumbra_stake::STAKING_TOKEN_ASSET_ID;
+    use penumbra_transaction::Shape;
+
+    use super::*;
+
+    fn pending(spent_nullifiers: BTreeSet<Nullifier>) -> PendingTransaction {
+        PendingTransaction {
+            id: [0; 32],
+            root: merkle::Root(Fq::zero()),
+            new_notes: BTreeMap::new(),
+            spent_nullifiers,
+            delegations: Vec::new(),
+            undelegations: Vec::new(),
+            redelegations: Vec::new(),
+            validators: Vec::new(),
+            validator_migrations: Vec::new(),
+            denom_metadata: Vec::new(),
+            proof_versions: BTreeSet::new(),
+            shape: Shape {
+                spends: 0,
+                outputs: 0,
+            },
+            fee: Value {
+                amount: 0,
+                asset_id: *STAKING_TOKEN_ASSET_ID,
+            },
+        }
+    }
+
+    #[test]
+    fn rejects_only_value_moving_transactions_when_tripped() {
+        let spend = pending([Nullifier(Fq::zero())].into_iter().collect());
+        let definition = pending(BTreeSet::new());
+
+        let breaker = CircuitBreaker::new(false);
+        assert!(breaker.check(&spend).is_ok());
+
+        // Clones share the switch.
+        breaker.clone().set(true);
+        let err = breaker.check(&spend).unwrap_err();
+        assert_eq!(crate::response_code::for_error(&err), CIRCUIT_BREAKER_CODE);
+        assert!(breaker.check(&definition).is_ok());
+
+        breaker.set(false);
+        assert!(breaker.check(&spend).is_ok());
+    }
+}
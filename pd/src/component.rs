@@ -0,0 +1,41 @@
+//! The parts of the application with state transitions of their own, which the consensus worker
+//! invokes at fixed points in each block.
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::{pending_block::Ended, state, PendingBlock};
+
+mod staking;
+
+pub use staking::{slashed_validators, Staking};
+
+/// Returns every component, in the order the consensus worker invokes them.
+pub fn all() -> Vec<Box<dyn Component>> {
+    vec![Box::new(Staking)]
+}
+
+/// What a component can read while processing the end of an epoch.
+pub struct EpochContext<'a> {
+    /// The committed state, as of the previous block.
+    pub state: &'a state::Reader,
+    /// The validator set as of the start of the block.
+    pub validators: &'a state::ValidatorInfoSnapshot,
+}
+
+/// A part of the application with state transitions of its own.
+///
+/// Components record their changes in the [`PendingBlock`] shared by every component, which
+/// already holds the changes made by the block's transactions and by earlier components.
+#[async_trait]
+pub trait Component: Send + Sync {
+    /// The name of the component, for reporting which components a node runs.
+    fn name(&self) -> &'static str;
+
+    /// Processes the end of an epoch, in the last block of the epoch.
+    async fn end_epoch(
+        &self,
+        ctx: &EpochContext<'_>,
+        pending_block: &mut PendingBlock<Ended>,
+    ) -> Result<()>;
+}
@@ -0,0 +1,229 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::{future, StreamExt};
+use penumbra_stake::{
+    FundingStreamRecipient, IdentityKey, ValidatorState, STAKING_TOKEN_ASSET_ID,
+    STAKING_TOKEN_DENOM,
+};
+
+use super::{Component, EpochContext};
+use crate::{
+    epoch::{cutoff, EpochInputs},
+    pending_block::Ended,
+    state, PendingBlock,
+};
+
+/// Validator rates, delegations, unbonding, and commission.
+pub struct Staking;
+
+#[async_trait]
+impl Component for Staking {
+    fn name(&self) -> &'static str {
+        "staking"
+    }
+
+    async fn end_epoch(
+        &self,
+        ctx: &EpochContext<'_>,
+        pending_block: &mut PendingBlock<Ended>,
+    ) -> Result<()> {
+        let reader = ctx.state;
+        let height = pending_block.phase.height;
+        let prev_epoch = pending_block.phase.epoch.clone();
+        let current_epoch = prev_epoch.next();
+
+        // Find all the validators which have *not* been slashed. Slashed validators may still have
+        // quarantined notes and nullifiers awaiting reversion, which must never be released.
+        let slashed_validators = slashed_validators(ctx.validators, pending_block);
+        let well_behaved_validators = ctx
+            .validators
+            .keys()
+            // THIS IS A LOAD-BEARING NEGATION: we want all validators which are *NOT* slashed
+            .filter(|identity_key| !slashed_validators.contains(identity_key))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        // Process unbonding notes and nullifiers for this epoch, if any undelegations are due:
+        // most epochs release nothing, so don't scan the quarantine for them.
+        let unbondings_due = reader.unbondings_due(height).await?;
+        tracing::debug!(?height, unbondings_due, "releasing unbonded stake");
+        if unbondings_due > 0 {
+            let (mut unbonding_notes, mut unbonding_nullifiers) = (
+                reader.quarantined_notes(Some(height), Some(well_behaved_validators.iter())),
+                reader.quarantined_nullifiers(Some(height), Some(well_behaved_validators.iter())),
+            );
+            while let Some(result) = unbonding_notes.next().await {
+                let (identity_key, commitment, data) = result?;
+                // A note is quarantined once for each validator its transaction undelegated from.
+                if !pending_block.notes.contains_key(&commitment) {
+                    pending_block.add_note(commitment, data);
+                }
+                pending_block.quarantine_releases.insert(identity_key);
+            }
+            while let Some(result) = unbonding_nullifiers.next().await {
+                let (identity_key, nullifier) = result?;
+                pending_block.unbonding_nullifiers.insert(nullifier);
+                pending_block.quarantine_releases.insert(identity_key);
+            }
+        }
+
+        // This all happens in the EndBlock critical path, so rather than awaiting each read from
+        // the committed state in turn, fetch everything up front, concurrently.
+        let (
+            current_base_rate,
+            current_rates,
+            staking_token_supply,
+            committed_delegation_changes,
+            mut migrations,
+        ) = tokio::try_join!(
+            reader.base_rate_data(current_epoch.index),
+            reader.rate_data(current_epoch.index),
+            reader.total_supply(*STAKING_TOKEN_ASSET_ID),
+            reader.delegation_changes(prev_epoch.index),
+            reader.validator_migrations(),
+        )?;
+
+        // Validators slashed in this block have their current rates reduced by the penalty, and
+        // the next epoch's rates accrue on top of the reduced rates.
+        let current_rates = current_rates
+            .into_iter()
+            .map(
+                |rate| match pending_block.slashings.get(&rate.identity_key) {
+                    Some(slashing) => slashing.post_slash.clone(),
+                    None => rate,
+                },
+            )
+            .collect::<Vec<_>>();
+
+        // The delegations in this block count toward the epoch it ends, but haven't been committed
+        // yet, so combine them with the ones already committed to the state.
+        let delegation_changes = cutoff::epoch_delegation_changes(
+            committed_delegation_changes,
+            &pending_block.delegation_changes,
+        );
+        // Likewise for the identity key migrations performed in this block.
+        for (old_identity_key, new_identity_key) in &pending_block.validator_migrations {
+            migrations.insert(
+                old_identity_key.clone(),
+                (new_identity_key.clone(), prev_epoch.index),
+            );
+        }
+
+        let mut inputs = EpochInputs {
+            prev_epoch_index: prev_epoch.index,
+            current_base_rate,
+            current_rates,
+            staking_token_supply: staking_token_supply
+                .ok_or_else(|| anyhow!("missing staking token supply"))?,
+            delegation_changes,
+            migrations,
+            funding_streams: BTreeMap::new(),
+            delegation_token_supplies: BTreeMap::new(),
+        };
+
+        // Prefetch the funding streams of every validator, and the supply of every delegation
+        // token, including those of validators' previous identity keys.
+        {
+            let previous_identity_keys = inputs.previous_identity_keys();
+            let token_identity_keys = inputs
+                .current_rates
+                .iter()
+                .flat_map(|rate| {
+                    std::iter::once(rate.identity_key.clone()).chain(
+                        previous_identity_keys
+                            .get(&rate.identity_key)
+                            .cloned()
+                            .unwrap_or_default(),
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            let funding_streams = future::try_join_all(
+                inputs
+                    .current_rates
+                    .iter()
+                    .map(|rate| reader.funding_streams(rate.identity_key.clone())),
+            );
+            let supplies = future::try_join_all(
+                token_identity_keys
+                    .iter()
+                    .map(|identity_key| reader.total_supply(identity_key.delegation_token().id())),
+            );
+            let (funding_streams, supplies) = tokio::try_join!(funding_streams, supplies)?;
+
+            inputs.funding_streams = inputs
+                .current_rates
+                .iter()
+                .map(|rate| rate.identity_key.clone())
+                .zip(funding_streams)
+                .map(|(identity_key, funding_streams)| {
+                    // Validator definitions updated in this block haven't been committed yet.
+                    match pending_block.validator_definitions.get(&identity_key) {
+                        Some(validator) => (identity_key, validator.funding_streams.clone()),
+                        None => (identity_key, funding_streams),
+                    }
+                })
+                .collect();
+            inputs.delegation_token_supplies = token_identity_keys
+                .into_iter()
+                .zip(supplies.into_iter().map(Option::unwrap_or_default))
+                .collect();
+        }
+
+        let transition = inputs.transition()?;
+
+        for (identity_key, delegation_token_supply) in transition.delegation_token_supplies {
+            pending_block.supply_updates.insert(
+                identity_key.delegation_token().id(),
+                (
+                    identity_key.delegation_token().denom(),
+                    delegation_token_supply,
+                ),
+            );
+        }
+        for (identity_key, amount, recipient) in transition.commission_rewards {
+            match recipient {
+                FundingStreamRecipient::Address(address) => {
+                    pending_block.add_validator_reward_note(amount, address, identity_key)
+                }
+                FundingStreamRecipient::CommunityPool => {
+                    pending_block.add_community_pool_deposit(amount, identity_key)
+                }
+            }
+        }
+        pending_block
+            .next_validator_migrations
+            .extend(transition.validator_migrations);
+        pending_block.next_rates = Some(transition.next_rates);
+        pending_block.next_base_rate = Some(transition.next_base_rate);
+        pending_block.next_validator_statuses = Some(transition.next_validator_statuses);
+        pending_block.supply_updates.insert(
+            *STAKING_TOKEN_ASSET_ID,
+            (STAKING_TOKEN_DENOM.clone(), transition.staking_token_supply),
+        );
+
+        Ok(())
+    }
+}
+
+/// Returns the validators slashed in this block or in some earlier block.
+pub fn slashed_validators(
+    validators: &state::ValidatorInfoSnapshot,
+    pending_block: &PendingBlock<Ended>,
+) -> BTreeSet<IdentityKey> {
+    validators
+        .values()
+        .filter(|info| matches!(info.status.state, ValidatorState::Slashed))
+        .map(|info| &info.validator.identity_key)
+        .chain(
+            pending_block
+                .validator_state_changes
+                .iter()
+                .filter(|(_, state)| matches!(state, ValidatorState::Slashed))
+                .map(|(identity_key, _)| identity_key),
+        )
+        .cloned()
+        .collect()
+}
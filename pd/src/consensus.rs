@@ -1,7 +1,9 @@
 mod message;
+mod params;
 mod service;
 mod worker;
 
 use message::Message;
-pub use service::Consensus;
+use service::RecentWrites;
+pub use service::{Consensus, QUEUE_CAPACITY, RECENT_WRITES_CAPACITY};
 use worker::Worker;
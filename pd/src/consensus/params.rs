@@ -0,0 +1,14 @@
+use penumbra_chain::params::ChainParams;
+use tendermint::consensus;
+
+/// Sets the consensus params that the application owns from the chain params, leaving the others
+/// (such as the accepted validator key types) as they are.
+pub fn apply_chain_params(consensus_params: &mut consensus::Params, chain_params: &ChainParams) {
+    consensus_params.block.max_bytes = chain_params.max_block_bytes;
+    consensus_params.block.max_gas = chain_params.max_block_gas;
+    // Tendermint only discards evidence once it is older than both its maximum age in blocks and
+    // its maximum age duration, so we also check the age of evidence in BeginBlock, but telling
+    // Tendermint about the limit avoids gossiping evidence that would be ignored.
+    consensus_params.evidence.max_age_num_blocks = chain_params.max_evidence_age_blocks();
+    consensus_params.evidence.max_bytes = chain_params.max_evidence_bytes as i64;
+}
@@ -1,6 +1,8 @@
 use std::{
+    collections::VecDeque,
     future::Future,
     pin::Pin,
+    sync::{Arc, Mutex},
     task::{Context, Poll},
 };
 
@@ -8,13 +10,25 @@ use futures::{ready, FutureExt};
 use tendermint::abci::{ConsensusRequest, ConsensusResponse};
 use tokio::sync::{
     mpsc::{self, error::SendError, OwnedPermit},
-    oneshot,
+    oneshot, watch,
 };
 use tokio_util::sync::ReusableBoxFuture;
 use tower_abci::BoxError;
 
 use super::{Message, Worker};
-use crate::{state, RequestExt};
+use crate::{
+    epoch::report::EpochReports, pending_block::PendingBlockSummary, state, verify::StatelessCache,
+    CircuitBreaker, InvariantChecks, LiveConfig, RequestExt, TraceContexts,
+};
+
+/// The number of ABCI consensus requests that can wait for the worker.
+pub const QUEUE_CAPACITY: usize = 10;
+
+/// The number of recently committed blocks whose row counts are kept for operators.
+pub const RECENT_WRITES_CAPACITY: usize = 100;
+
+/// The row counts of the most recently committed blocks, oldest first.
+pub(super) type RecentWrites = Arc<Mutex<VecDeque<state::BlockWrites>>>;
 
 enum State {
     NoPermit,
@@ -24,28 +38,77 @@ enum State {
 
 pub struct Consensus {
     queue: mpsc::Sender<Message>,
+    pending_block_rx: watch::Receiver<Option<PendingBlockSummary>>,
+    recent_writes: RecentWrites,
     future: ReusableBoxFuture<Result<OwnedPermit<Message>, SendError<()>>>,
     state: State,
 }
 
 impl Consensus {
-    pub async fn new(state: state::Writer) -> anyhow::Result<Self> {
-        let (queue_tx, queue_rx) = mpsc::channel(10);
+    pub async fn new(
+        state: state::Writer,
+        stateless_cache: StatelessCache,
+        trace_contexts: TraceContexts,
+        invariant_checks: InvariantChecks,
+        circuit_breaker: CircuitBreaker,
+        epoch_duration_override: Option<u64>,
+        epoch_reports: Option<EpochReports>,
+        config: LiveConfig,
+    ) -> anyhow::Result<Self> {
+        let (queue_tx, queue_rx) = mpsc::channel(QUEUE_CAPACITY);
+        let (pending_block_tx, pending_block_rx) = watch::channel(None);
+        let recent_writes = RecentWrites::default();
 
-        tokio::spawn(Worker::new(state, queue_rx).await?.run());
+        tokio::spawn(
+            Worker::new(
+                state,
+                stateless_cache,
+                trace_contexts,
+                invariant_checks,
+                circuit_breaker,
+                epoch_duration_override,
+                queue_rx,
+                pending_block_tx,
+                recent_writes.clone(),
+                epoch_reports,
+                config,
+            )
+            .await?
+            .run(),
+        );
 
         Ok(Self {
             queue: queue_tx,
+            pending_block_rx,
+            recent_writes,
             state: State::NoPermit,
             future: ReusableBoxFuture::new(async { unreachable!() }),
         })
     }
+
+    /// A summary of the block the worker is processing, if any.
+    pub fn pending_block(&self) -> Option<PendingBlockSummary> {
+        self.pending_block_rx.borrow().clone()
+    }
+
+    /// The number of rows of each kind written by the most recently committed blocks, oldest
+    /// first.
+    pub fn recent_writes(&self) -> Vec<state::BlockWrites> {
+        self.recent_writes.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// The number of requests waiting for the worker.
+    pub fn queue_depth(&self) -> usize {
+        QUEUE_CAPACITY - self.queue.capacity()
+    }
 }
 
 impl Clone for Consensus {
     fn clone(&self) -> Self {
         Self {
             queue: self.queue.clone(),
+            pending_block_rx: self.pending_block_rx.clone(),
+            recent_writes: self.recent_writes.clone(),
             state: State::NoPermit,
             future: ReusableBoxFuture::new(async { unreachable!() }),
         }
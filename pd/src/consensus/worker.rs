@@ -1,36 +1,117 @@
-use anyhow::{anyhow, Result};
-use futures::StreamExt;
-use metrics::absolute_counter;
-use penumbra_crypto::{asset, merkle::NoteCommitmentTree};
+use std::{collections::BTreeMap, sync::Arc, time::Instant};
+
+use anyhow::{anyhow, Context, Result};
+use metrics::{absolute_counter, counter, gauge, histogram, increment_counter};
+use penumbra_crypto::{
+    asset,
+    merkle::{NoteCommitmentTree, PruneExt},
+    Amount,
+};
 use penumbra_proto::Protobuf;
 use penumbra_stake::{
-    ValidatorState, ValidatorStatus, STAKING_TOKEN_ASSET_ID, STAKING_TOKEN_DENOM,
+    Epoch, ValidatorInfo, ValidatorState, SLASHING_PENALTY_BPS, STAKING_TOKEN_ASSET_ID,
+    STAKING_TOKEN_DENOM,
 };
 use penumbra_transaction::Transaction;
-use tendermint::abci::{self, ConsensusRequest as Request, ConsensusResponse as Response};
-use tokio::sync::mpsc;
+use tendermint::{
+    abci::{self, ConsensusRequest as Request, ConsensusResponse as Response},
+    account,
+};
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
 use tracing::Instrument;
 
-use super::Message;
-use crate::{genesis, state, verify::StatelessTransactionExt, PendingBlock};
+use super::{params, Message, RecentWrites, RECENT_WRITES_CAPACITY};
+use crate::{
+    component::{self, Component, EpochContext},
+    crash_report,
+    epoch::{
+        cutoff,
+        report::{EpochReport, EpochReports},
+    },
+    genesis, pd_metrics,
+    pending_block::{Ended, PendingBlockSummary, Slashing},
+    response_code, state, testnet,
+    verify::{StatelessCache, StatelessTransactionExt},
+    CircuitBreaker, InvariantChecks, LiveConfig, PendingBlock, TraceContexts,
+};
+
+/// The maximum number of quarantined notes and nullifiers reverted in a single block.
+///
+/// Reverting the undelegations from a slashed validator can involve arbitrarily many quarantined
+/// notes and nullifiers, so they are reverted in batches of at most this size, one per block,
+/// until none remain.
+const MAX_QUARANTINE_REVERTS_PER_BLOCK: u64 = 10_000;
 
 pub struct Worker {
-    state: state::Writer,
+    state: Arc<state::Writer>,
+    stateless_cache: StatelessCache,
+    trace_contexts: TraceContexts,
+    invariant_checks: InvariantChecks,
+    circuit_breaker: CircuitBreaker,
+    /// Replaces the genesis epoch duration, for local devnets.
+    epoch_duration_override: Option<u64>,
     queue: mpsc::Receiver<Message>,
+    /// Publishes a summary of the block being processed after each request, for operators.
+    pending_block_tx: watch::Sender<Option<PendingBlockSummary>>,
+    /// Records the row counts of each committed block, for operators.
+    recent_writes: RecentWrites,
+    /// Where to write a report at the end of each epoch, if anywhere.
+    epoch_reports: Option<EpochReports>,
+    /// How often to update the storage metrics, among other things.
+    config: LiveConfig,
     // todo: split up and modularize
+    /// The block being built, between BeginBlock and EndBlock.
     pending_block: Option<PendingBlock>,
+    /// The block that has ended, between EndBlock and Commit.
+    ended_block: Option<PendingBlock<Ended>>,
+    /// The validator set as of the start of the current block, shared by all its transactions.
+    validators: state::ValidatorInfoSnapshot,
     note_commitment_tree: NoteCommitmentTree,
+    /// The components whose state transitions the worker invokes, in order.
+    components: Vec<Box<dyn Component>>,
+    /// The background task writing the last committed block to the database, if it may not have
+    /// finished yet.
+    writing_block: Option<JoinHandle<Result<()>>>,
 }
 
 impl Worker {
-    pub async fn new(state: state::Writer, queue: mpsc::Receiver<Message>) -> Result<Self> {
+    pub async fn new(
+        state: state::Writer,
+        stateless_cache: StatelessCache,
+        trace_contexts: TraceContexts,
+        invariant_checks: InvariantChecks,
+        circuit_breaker: CircuitBreaker,
+        epoch_duration_override: Option<u64>,
+        queue: mpsc::Receiver<Message>,
+        pending_block_tx: watch::Sender<Option<PendingBlockSummary>>,
+        recent_writes: RecentWrites,
+        epoch_reports: Option<EpochReports>,
+        config: LiveConfig,
+    ) -> Result<Self> {
         let note_commitment_tree = state.private_reader().note_commitment_tree().await?;
+        let validators = state.private_reader().validator_info_rx().borrow().clone();
 
         Ok(Self {
-            state,
+            state: Arc::new(state),
+            stateless_cache,
+            trace_contexts,
+            invariant_checks,
+            circuit_breaker,
+            epoch_duration_override,
             queue,
+            pending_block_tx,
+            recent_writes,
+            epoch_reports,
+            config,
             pending_block: None,
+            ended_block: None,
+            validators,
             note_commitment_tree,
+            components: component::all(),
+            writing_block: None,
         })
     }
 
@@ -41,6 +122,8 @@ impl Worker {
             span,
         }) = self.queue.recv().await
         {
+            crash_report::record_request(&req);
+
             // The send only fails if the receiver was dropped, which happens
             // if the caller didn't propagate the message back to tendermint
             // for some reason -- but that's not our problem.
@@ -61,7 +144,7 @@ impl Worker {
                     Response::DeliverTx(match self.deliver_tx(deliver_tx).instrument(span).await {
                         Ok(()) => abci::response::DeliverTx::default(),
                         Err(e) => abci::response::DeliverTx {
-                            code: 1,
+                            code: response_code::for_error(&e),
                             log: e.to_string(),
                             ..Default::default()
                         },
@@ -80,18 +163,65 @@ impl Worker {
                         .expect("commit must succeed"),
                 ),
             });
+            self.publish_pending_block();
+        }
+        Ok(())
+    }
+
+    /// Waits for the last committed block to be written to the database, which the state is read
+    /// from directly or through its caches.
+    async fn finish_writing_block(&mut self) -> Result<()> {
+        if let Some(writing_block) = self.writing_block.take() {
+            let wait_start = Instant::now();
+            match writing_block.await {
+                Ok(result) => result?,
+                // Propagate panics, such as from failed invariant checks, to halt the node.
+                Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+                Err(e) => return Err(e.into()),
+            }
+            histogram!(
+                pd_metrics::DB_COMMIT_WAIT_DURATION_SECONDS,
+                wait_start.elapsed()
+            );
         }
         Ok(())
     }
 
+    /// Publishes a summary of the block being processed, if any.
+    fn publish_pending_block(&self) {
+        let summary = match (&self.pending_block, &self.ended_block) {
+            (Some(block), _) => Some(block.summary()),
+            (None, Some(block)) => Some(PendingBlockSummary {
+                height: Some(block.phase.height),
+                ..block.summary()
+            }),
+            (None, None) => None,
+        };
+        // This only fails if the `Consensus` service holding the receiver was dropped.
+        let _ = self.pending_block_tx.send(summary);
+    }
+
     async fn init_chain(
         &mut self,
         init_chain: abci::request::InitChain,
     ) -> Result<abci::response::InitChain> {
         tracing::info!(?init_chain);
         // Note that errors cannot be handled in InitChain, the application must crash.
-        let app_state: genesis::AppState = serde_json::from_slice(&init_chain.app_state_bytes)
+        let mut app_state: genesis::AppState = serde_json::from_slice(&init_chain.app_state_bytes)
             .expect("can parse app_state in genesis file");
+        if let Some(epoch_duration) = self.epoch_duration_override {
+            testnet::ensure_devnet_chain_id(&init_chain.chain_id, "--epoch-duration-override")?;
+            tracing::warn!(
+                genesis_epoch_duration = app_state.chain_params.epoch_duration,
+                ?epoch_duration,
+                "overriding epoch duration"
+            );
+            app_state.chain_params.epoch_duration = epoch_duration;
+        }
+        app_state
+            .check_validators_unique()
+            .and_then(|()| app_state.check_validator_powers())
+            .context("inconsistent genesis validator set")?;
 
         // Initialize the database with the app state.
         self.state.commit_genesis(&app_state).await?;
@@ -99,7 +229,13 @@ impl Worker {
         // Now start building the genesis block:
         self.note_commitment_tree = NoteCommitmentTree::new(0);
         let mut genesis_block = PendingBlock::new(self.note_commitment_tree.clone());
-        genesis_block.set_height(0, app_state.chain_params.epoch_duration);
+        genesis_block.time = Some(init_chain.time);
+
+        // The application owns the consensus params derived from the chain params, and records
+        // them so that it can update them if the chain params change.
+        let mut consensus_params = init_chain.consensus_params;
+        params::apply_chain_params(&mut consensus_params, &app_state.chain_params);
+        genesis_block.next_consensus_params = Some(consensus_params.clone());
 
         // Create a genesis transaction to record genesis notes.
         // TODO: eliminate this (#374)
@@ -117,11 +253,14 @@ impl Worker {
                 .expect("genesis allocations must have valid denominations");
 
             // Accumulate the allocation amount into the supply updates for this denom.
-            genesis_block
+            let supply = &mut genesis_block
                 .supply_updates
                 .entry(denom.id())
-                .or_insert((denom, 0))
-                .1 += allocation.amount;
+                .or_insert((denom, Amount::ZERO))
+                .1;
+            *supply = supply
+                .checked_add(allocation.amount)
+                .context("genesis allocations overflow the total supply")?;
         }
 
         // We might not have any allocations of delegation tokens, but we should record the denoms.
@@ -130,7 +269,7 @@ impl Worker {
             genesis_block
                 .supply_updates
                 .entry(denom.id())
-                .or_insert((denom, 0));
+                .or_insert((denom, Amount::ZERO));
         }
 
         let genesis_tx = tx_builder
@@ -143,8 +282,12 @@ impl Worker {
         genesis_block.add_transaction(verified_transaction);
 
         // Commit the genesis block to the state
-        self.pending_block = Some(genesis_block);
+        self.ended_block = Some(genesis_block.end(0, app_state.chain_params.epoch_duration));
         let app_hash = self.commit().await?.data;
+        // Unlike later blocks, wait for the genesis block to be written before responding: until
+        // it is, there are no blocks at all, so there's no committed height for readers to wait
+        // on, and clients syncing in the meantime would miss the genesis allocations.
+        self.finish_writing_block().await?;
 
         // Extract the Tendermint validators from the genesis app state
         //
@@ -164,7 +307,7 @@ impl Worker {
             .collect();
 
         Ok(abci::response::InitChain {
-            consensus_params: Some(init_chain.consensus_params),
+            consensus_params: Some(consensus_params),
             validators,
             app_hash,
         })
@@ -176,12 +319,160 @@ impl Worker {
     ) -> Result<abci::response::BeginBlock> {
         tracing::debug!(?begin_block);
 
+        self.finish_writing_block().await?;
+
         let block_metrics = self.state.private_reader().metrics().await?;
-        absolute_counter!("node_spent_nullifiers_total", block_metrics.nullifier_count);
-        absolute_counter!("node_notes_total", block_metrics.note_count);
+        absolute_counter!(
+            pd_metrics::SPENT_NULLIFIERS_TOTAL,
+            block_metrics.nullifier_count
+        );
+        absolute_counter!(pd_metrics::NOTES_TOTAL, block_metrics.note_count);
+
+        let storage_metrics_interval = self.config.current().storage_metrics_interval;
+        if storage_metrics_interval != 0
+            && begin_block.header.height.value() % storage_metrics_interval == 0
+        {
+            // These queries are only for monitoring, so run them in the background rather than
+            // delaying the block, and don't let a failure halt consensus.
+            let reader = self.state.private_reader().clone();
+            tokio::spawn(
+                async move {
+                    let storage_metrics = match reader.storage_metrics().await {
+                        Ok(storage_metrics) => storage_metrics,
+                        Err(e) => {
+                            tracing::warn!(?e, "could not collect storage metrics");
+                            return;
+                        }
+                    };
+                    gauge!(
+                        pd_metrics::DB_SIZE_BYTES,
+                        storage_metrics.db_size_bytes as f64
+                    );
+                    for (table, rows) in storage_metrics.table_rows {
+                        gauge!(pd_metrics::DB_TABLE_ROWS, rows as f64, "table" => table);
+                    }
+                    absolute_counter!(pd_metrics::DB_CACHE_HITS_TOTAL, storage_metrics.cache_hits);
+                    absolute_counter!(
+                        pd_metrics::DB_CACHE_MISSES_TOTAL,
+                        storage_metrics.cache_misses
+                    );
+                }
+                .instrument(tracing::Span::current()),
+            );
+        }
+        gauge!(
+            pd_metrics::CIRCUIT_BREAKER_TRIPPED,
+            self.circuit_breaker.is_tripped() as u8 as f64
+        );
 
-        assert!(self.pending_block.is_none());
-        self.pending_block = Some(PendingBlock::new(self.note_commitment_tree.clone()));
+        assert!(self.pending_block.is_none() && self.ended_block.is_none());
+        let mut pending_block = PendingBlock::new(self.note_commitment_tree.clone());
+
+        // Take a snapshot of the validator set for the whole block, rather than loading it
+        // whenever it's needed.
+        self.validators = self
+            .state
+            .private_reader()
+            .validator_info_rx()
+            .borrow()
+            .clone();
+
+        pending_block.time = Some(begin_block.header.time);
+        pending_block.proposer = self
+            .validator_by_address(begin_block.header.proposer_address.as_bytes())
+            .map(|info| info.validator.identity_key.clone());
+        if pending_block.proposer.is_none() {
+            tracing::warn!(proposer_address = ?begin_block.header.proposer_address, "block proposed by unknown validator");
+        }
+
+        // Slash any validators Tendermint reports evidence of misbehavior for.
+        // This reverts their quarantined undelegations in EndBlock.
+        if !begin_block.byzantine_validators.is_empty() {
+            let reader = self.state.private_reader();
+            let (epoch_duration, max_evidence_age) = {
+                let chain_params = reader.chain_params_rx().borrow();
+                (
+                    chain_params.epoch_duration,
+                    chain_params.max_evidence_age_blocks(),
+                )
+            };
+            let height = begin_block.header.height.value();
+            for evidence in &begin_block.byzantine_validators {
+                // Evidence older than the unbonding period is ignored, since the stake that was
+                // bonded at the time of the infraction may have been released already.
+                let evidence_age = height.saturating_sub(evidence.height.value());
+                if evidence_age > max_evidence_age {
+                    tracing::warn!(
+                        ?evidence,
+                        evidence_age,
+                        max_evidence_age,
+                        "ignoring expired evidence"
+                    );
+                    continue;
+                }
+
+                let info = match self.validator_by_address(&evidence.validator.address) {
+                    Some(info) => info,
+                    None => {
+                        tracing::warn!(?evidence, "evidence for unknown validator");
+                        continue;
+                    }
+                };
+                let identity_key = info.validator.identity_key.clone();
+                if pending_block.slashings.contains_key(&identity_key) {
+                    tracing::debug!(
+                        ?identity_key,
+                        ?evidence,
+                        "validator already slashed in this block"
+                    );
+                    continue;
+                }
+
+                // Evidence may be for an infraction several epochs ago, in which case the penalty
+                // is computed against the validator's rates as of the infraction, and applied to
+                // the rates that delegations and undelegations are currently priced at.
+                let pre_slash = reader
+                    .next_rate_data_rx()
+                    .borrow()
+                    .get(&identity_key)
+                    .cloned()
+                    .expect("misbehaving validator has rate data");
+                let infraction_epoch = Epoch::from_height(evidence.height.value(), epoch_duration);
+                let infraction_rate = reader
+                    .validator_rate_history(
+                        &identity_key,
+                        infraction_epoch.index,
+                        infraction_epoch.index,
+                    )
+                    .await?
+                    .pop()
+                    .unwrap_or_else(|| pre_slash.clone());
+                let post_slash = pre_slash.slash_as_of(&infraction_rate, SLASHING_PENALTY_BPS);
+
+                tracing::info!(
+                    ?identity_key,
+                    ?evidence,
+                    infraction_epoch = infraction_epoch.index,
+                    infraction_exchange_rate = infraction_rate.validator_exchange_rate,
+                    pre_slash_exchange_rate = pre_slash.validator_exchange_rate,
+                    post_slash_exchange_rate = post_slash.validator_exchange_rate,
+                    "slashing validator"
+                );
+                pending_block
+                    .validator_state_changes
+                    .insert(identity_key.clone(), ValidatorState::Slashed);
+                pending_block.slashings.insert(
+                    identity_key,
+                    Slashing {
+                        infraction_rate,
+                        pre_slash,
+                        post_slash,
+                    },
+                );
+            }
+        }
+
+        self.pending_block = Some(pending_block);
 
         Ok(Default::default())
     }
@@ -194,30 +485,33 @@ impl Worker {
     /// Byzantine node may propose a block containing double spends or other disallowed behavior,
     /// so it is not safe to assume all checks performed in `CheckTx` were done.
     async fn deliver_tx(&mut self, deliver_tx: abci::request::DeliverTx) -> Result<()> {
-        // Verify the transaction is well-formed...
-        let transaction = Transaction::decode(deliver_tx.tx)?
-            // ... and that it is internally consistent ...
-            .verify_stateless()?;
-        // ... and that it is consistent with the existing chain state.
+        let key = StatelessCache::key(&deliver_tx.tx);
+        if let Some(traceparent) = self.trace_contexts.remove(&key) {
+            tracing::Span::current().record("traceparent", &tracing::field::display(traceparent));
+        }
+        let transaction = match self.stateless_cache.take(&key) {
+            // We already performed stateless checks on these exact bytes in CheckTx.
+            Some(transaction) => {
+                increment_counter!(pd_metrics::STATELESS_CACHE_HITS_TOTAL);
+                transaction
+            }
+            // Verify the transaction is well-formed...
+            None => Transaction::decode(deliver_tx.tx)?
+                // ... and that it is internally consistent ...
+                .verify_stateless()?,
+        };
+        // ... and that it is consistent with the existing chain state.  The node's circuit
+        // breaker isn't checked here: it's local to the node, so it only filters the mempool.
         let transaction = self
             .state
             .private_reader()
-            .verify_stateful(transaction)
+            .verify_stateful(transaction, &self.validators)
             .await?;
 
-        let mut conflicts = self
-            .pending_block
+        self.pending_block
             .as_ref()
             .unwrap()
-            .spent_nullifiers
-            .intersection(&transaction.spent_nullifiers);
-
-        if let Some(conflict) = conflicts.next() {
-            return Err(anyhow!(
-                "nullifier {:?} is already spent in the pending block",
-                conflict
-            ));
-        }
+            .check_conflicts(&transaction.effects)?;
 
         self.pending_block
             .as_mut()
@@ -233,55 +527,76 @@ impl Worker {
     ) -> Result<abci::response::EndBlock> {
         tracing::debug!(?end_block);
 
-        let reader = self.state.private_reader();
-
-        let pending_block = self
-            .pending_block
-            .as_mut()
-            .expect("pending block must be Some in EndBlock");
-
         let height = end_block
             .height
             .try_into()
             .expect("height should be nonnegative");
-        let epoch = pending_block.set_height(
-            height,
-            self.state
-                .private_reader()
-                .chain_params_rx()
-                .borrow()
-                .epoch_duration,
-        );
+        let mut pending_block = self
+            .pending_block
+            .take()
+            .expect("pending block must be Some in EndBlock")
+            .end(
+                height,
+                self.state
+                    .private_reader()
+                    .chain_params_rx()
+                    .borrow()
+                    .epoch_duration,
+            );
+        let epoch = pending_block.phase.epoch.clone();
+
+        let reader = self.state.private_reader();
 
         tracing::debug!(?height, ?epoch, end_height = ?epoch.end_height());
 
-        // Find out which validators were slashed in this block
-        let slashed_validators = pending_block
-            .validator_state_changes
-            .iter()
-            .filter(|p| matches!(p.1, ValidatorState::Slashed))
-            .map(|p| p.0) // get the validator ID
-            .collect::<Vec<_>>();
-
-        // Immediately revert notes and nullifiers immediately from slashed validators in this block
-        let (mut slashed_notes, mut slashed_nullifiers) = (
-            reader.quarantined_notes(None, Some(slashed_validators.iter())),
-            reader.quarantined_nullifiers(None, Some(slashed_validators.iter())),
-        );
-        while let Some(result) = slashed_notes.next().await {
-            pending_block.reverting_notes.insert(result?.1); // insert the commitment
-        }
-        while let Some(result) = slashed_nullifiers.next().await {
-            pending_block.reverting_nullifiers.insert(result?.1); // insert the nullifier
+        // Find out which validators have been slashed, either in this block or previously
+        let slashed_validators = component::slashed_validators(&self.validators, &pending_block);
+
+        // Revert the notes and nullifiers quarantined for slashed validators, in bounded batches:
+        // whatever doesn't fit in this block's batch stays in quarantine, and is reverted in
+        // subsequent blocks.
+        if !slashed_validators.is_empty() {
+            let (notes, nullifiers, outstanding) = reader
+                .quarantine_revert_batch(&slashed_validators, MAX_QUARANTINE_REVERTS_PER_BLOCK)
+                .await?;
+            let backlog = outstanding.saturating_sub((notes.len() + nullifiers.len()) as u64);
+            if backlog > 0 {
+                tracing::info!(
+                    ?height,
+                    reverting = notes.len() + nullifiers.len(),
+                    ?backlog,
+                    "carrying over quarantine reverts to subsequent blocks"
+                );
+            }
+            gauge!(pd_metrics::QUARANTINE_REVERT_BACKLOG, backlog as f64);
+            pending_block.reverting_notes.extend(notes);
+            pending_block.reverting_nullifiers.extend(nullifiers);
         }
-        drop(slashed_notes);
-        drop(slashed_nullifiers);
+
+        // If the chain params changed in this block, update the consensus params derived from
+        // them, so that Tendermint applies the new limits from the next block on.
+        let consensus_param_updates = match pending_block.next_chain_params.as_ref() {
+            Some(chain_params) => {
+                let mut consensus_params = reader
+                    .consensus_params()
+                    .await?
+                    .ok_or_else(|| anyhow!("consensus params were not recorded at genesis"))?;
+                params::apply_chain_params(&mut consensus_params, chain_params);
+                tracing::info!(?consensus_params, "updating consensus params");
+                pending_block.next_consensus_params = Some(consensus_params.clone());
+                Some(consensus_params)
+            }
+            None => None,
+        };
 
         // If we are at the end of an epoch, process changes for it
-        if epoch.end_height().value() == height {
-            self.end_epoch().await?;
+        if cutoff::ends_epoch(height, epoch.duration) {
+            self.end_epoch(&mut pending_block).await?;
         }
 
+        self.pay_proposer_reward(&mut pending_block).await?;
+        self.ended_block = Some(pending_block);
+
         // TODO: later, set the EndBlock response to add validators
         // at the epoch boundary
 
@@ -289,28 +604,83 @@ impl Worker {
         // back to tendermint, so that we can see how the statuses are computed without risking
         // halting the testnet. in the future we want to add code here to send the next voting
         // powers back to tendermint.
-        Ok(Default::default())
+        Ok(abci::response::EndBlock {
+            consensus_param_updates,
+            ..Default::default()
+        })
     }
 
-    /// Process the state transitions for the end of an epoch.
-    async fn end_epoch(&mut self) -> Result<()> {
+    /// Returns the validator with the given consensus address.
+    ///
+    /// A validator that migrated its identity key keeps its consensus key, so only identity keys
+    /// that are still in use are considered.
+    fn validator_by_address(&self, address: &[u8]) -> Option<&ValidatorInfo> {
         let reader = self.state.private_reader();
+        let next_rate_data = reader.next_rate_data_rx().borrow();
+        self.validators.values().find(|info| {
+            account::Id::from(info.validator.consensus_key).as_bytes() == address
+                && next_rate_data.contains_key(&info.validator.identity_key)
+        })
+    }
 
-        let pending_block = self
-            .pending_block
-            .as_mut()
-            .expect("pending block must be Some in EndBlock");
+    /// Pays the block proposer's reward for the transactions included in the block.
+    async fn pay_proposer_reward(&self, pending_block: &mut PendingBlock<Ended>) -> Result<()> {
+        let reward_per_transaction = self
+            .state
+            .private_reader()
+            .chain_params_rx()
+            .borrow()
+            .proposer_reward_per_transaction;
+        let reward =
+            reward_per_transaction.saturating_mul(pending_block.transaction_ids.len() as u64);
+        let proposer = match pending_block.proposer.as_ref() {
+            Some(proposer) if reward > 0 => proposer.clone(),
+            _ => return Ok(()),
+        };
+        // Use the funding streams from any definition of the validator in this block, since
+        // they take effect at commit.
+        let funding_streams = match pending_block.validator_definitions.get(&proposer) {
+            Some(validator) => validator.funding_streams.clone(),
+            None => self.validators[&proposer].validator.funding_streams.clone(),
+        };
+
+        let paid = pending_block.add_proposer_reward(reward, &proposer, &funding_streams);
+        if paid == 0 {
+            return Ok(());
+        }
+        tracing::debug!(?proposer, reward, paid, "paid proposer reward");
+
+        // The reward is newly minted, so it adds to the staking token supply, on top of any
+        // update from the end of the epoch.
+        let supply = match pending_block.supply_updates.get(&*STAKING_TOKEN_ASSET_ID) {
+            Some((_, supply)) => *supply,
+            None => self
+                .state
+                .private_reader()
+                .total_supply(*STAKING_TOKEN_ASSET_ID)
+                .await?
+                .unwrap_or_default(),
+        };
+        pending_block.supply_updates.insert(
+            *STAKING_TOKEN_ASSET_ID,
+            (
+                STAKING_TOKEN_DENOM.clone(),
+                supply
+                    .checked_add(paid)
+                    .context("staking token supply overflow")?,
+            ),
+        );
 
-        let height = pending_block
-            .height
-            .expect("height must already have been set");
+        Ok(())
+    }
+
+    /// Process the state transitions for the end of an epoch.
+    async fn end_epoch(&self, pending_block: &mut PendingBlock<Ended>) -> Result<()> {
+        let height = pending_block.phase.height;
 
         // We've finished processing the last block of `epoch`, so we've
         // crossed the epoch boundary, and (prev | current | next) are:
-        let prev_epoch = pending_block
-            .epoch
-            .clone()
-            .expect("epoch must already have been set");
+        let prev_epoch = pending_block.phase.epoch.clone();
         let current_epoch = prev_epoch.next();
         let next_epoch = current_epoch.next();
 
@@ -321,170 +691,145 @@ impl Worker {
             ?next_epoch,
             "crossed epoch boundary, processing rate updates"
         );
-        metrics::increment_counter!("epoch");
-
-        // Find all the validators which were *not* slashed in this block
-        let well_behaved_validators = pending_block
-            .validator_state_changes
-            .iter()
-            // THIS IS A LOAD-BEARING NEGATION: we want all validators which are *NOT* slashed
-            .filter(|p| !matches!(p.1, ValidatorState::Slashed))
-            .map(|p| p.0.clone()) // get the validator ID
-            .collect::<Vec<_>>();
-
-        // Process unbonding notes and nullifiers for this epoch
-        let (mut unbonding_notes, mut unbonding_nullifiers) = (
-            reader.quarantined_notes(Some(height), Some(well_behaved_validators.iter())),
-            reader.quarantined_nullifiers(Some(height), Some(well_behaved_validators.iter())),
-        );
-        while let Some(result) = unbonding_notes.next().await {
-            let (_, commitment, data) = result?;
-            pending_block.add_note(commitment, data);
+        increment_counter!(pd_metrics::EPOCHS);
+
+        let ctx = EpochContext {
+            state: self.state.private_reader(),
+            validators: &self.validators,
+        };
+        for component in &self.components {
+            component.end_epoch(&ctx, pending_block).await?;
         }
-        while let Some(result) = unbonding_nullifiers.next().await {
-            pending_block.unbonding_nullifiers.insert(result?.1); // insert the nullifier
-        }
-        drop(unbonding_notes);
-        drop(unbonding_nullifiers);
-
-        // TODO (optimization): batch these queries
-        let current_base_rate = reader.base_rate_data(current_epoch.index).await?;
-        let current_rates = reader.rate_data(current_epoch.index).await?;
-
-        let mut staking_token_supply = reader
-            .asset_lookup(*STAKING_TOKEN_ASSET_ID)
-            .await?
-            .map(|info| info.total_supply)
-            .unwrap();
-
-        // steps (foreach validator):
-        // - get the total token supply for the validator's delegation tokens
-        // - process the updates to the token supply:
-        //   - collect all delegations occurring in previous epoch and apply them (adds to supply);
-        //   - collect all undelegations started in previous epoch and apply them (reduces supply);
-        // - feed the updated (current) token supply into current_rates.voting_power()
-        // - persist both the current voting power and the current supply
-        //
-
-        /// FIXME: set this less arbitrarily, and allow this to be set per-epoch
-        /// 3bps -> 11% return over 365 epochs, why not
-        const BASE_REWARD_RATE: u64 = 3_0000;
-
-        let next_base_rate = current_base_rate.next(BASE_REWARD_RATE);
-
-        // rename to curr_rate so it lines up with next_rate (same # chars)
-        tracing::debug!(curr_base_rate = ?current_base_rate);
-        tracing::debug!(?next_base_rate);
-
-        let mut next_rates = Vec::new();
-        let mut next_validator_statuses = Vec::new();
-
-        // this is a bit complicated: because we're in the EndBlock phase, and the
-        // delegations in this block have not yet been committed, we have to combine
-        // the delegations in pending_block with the ones already committed to the
-        // state. otherwise the delegations committed in the epoch threshold block
-        // would be lost.
-        let mut delegation_changes = reader.delegation_changes(prev_epoch.index).await?;
-        for (id_key, delta) in &pending_block.delegation_changes {
-            *delegation_changes.entry(id_key.clone()).or_insert(0) += delta;
-        }
-
-        for current_rate in &current_rates {
-            let identity_key = current_rate.identity_key.clone();
-
-            let funding_streams = reader.funding_streams(identity_key.clone()).await?;
-            let next_rate = current_rate.next(&next_base_rate, funding_streams.as_ref());
-
-            // TODO: if a validator isn't part of the consensus set, should we ignore them
-            // and not update their rates?
-            let delegation_delta = delegation_changes.get(&identity_key).unwrap_or(&0i64);
-
-            let delegation_amount = delegation_delta.abs() as u64;
-            let unbonded_amount = current_rate.unbonded_amount(delegation_amount);
 
-            let mut delegation_token_supply = reader
-                .asset_lookup(identity_key.delegation_token().id())
-                .await?
-                .map(|info| info.total_supply)
-                .unwrap_or(0);
-
-            if *delegation_delta > 0 {
-                // net delegation: subtract the unbonded amount from the staking token supply
-                staking_token_supply = staking_token_supply.checked_sub(unbonded_amount).unwrap();
-                delegation_token_supply = delegation_token_supply
-                    .checked_add(delegation_amount)
-                    .unwrap();
-            } else {
-                // net undelegation: add the unbonded amount to the staking token supply
-                staking_token_supply = staking_token_supply.checked_add(unbonded_amount).unwrap();
-                delegation_token_supply = delegation_token_supply
-                    .checked_sub(delegation_amount)
-                    .unwrap();
-            }
-
-            // update the delegation token supply
-            pending_block.supply_updates.insert(
-                identity_key.delegation_token().id(),
-                (
-                    identity_key.delegation_token().denom(),
-                    delegation_token_supply,
-                ),
-            );
-
-            let voting_power = next_rate.voting_power(delegation_token_supply, &next_base_rate);
-            let next_status = ValidatorStatus {
-                identity_key,
-                voting_power,
-                // TODO: this state needs to be set correctly based on current state and any changes
-                // within the current block. This will be fixed by #375.
-                state: ValidatorState::Active,
-            };
-
-            // distribute validator commission
-            for stream in funding_streams {
-                let commission_reward_amount = stream.reward_amount(
-                    delegation_token_supply,
-                    &next_base_rate,
-                    &current_base_rate,
-                );
+        Ok(())
+    }
 
-                pending_block.add_validator_reward_note(commission_reward_amount, stream.address);
+    /// Reports the end of the epoch ended by `pending_block`, which must not have been committed.
+    async fn epoch_report(&self, pending_block: &PendingBlock<Ended>) -> Result<EpochReport> {
+        let reader = self.state.private_reader();
+        let mut previous_supplies = BTreeMap::new();
+        for asset_id in pending_block.supply_updates.keys() {
+            if let Some(supply) = reader.total_supply(*asset_id).await? {
+                previous_supplies.insert(*asset_id, supply);
             }
-
-            // rename to curr_rate so it lines up with next_rate (same # chars)
-            tracing::debug!(curr_rate = ?current_rate);
-            tracing::debug!(?next_rate);
-            tracing::debug!(?delegation_delta);
-            tracing::debug!(?delegation_token_supply);
-            tracing::debug!(?next_status);
-
-            next_rates.push(next_rate);
-            next_validator_statuses.push(next_status);
         }
-
-        tracing::debug!(?staking_token_supply);
-
-        pending_block.next_rates = Some(next_rates);
-        pending_block.next_base_rate = Some(next_base_rate);
-        pending_block.next_validator_statuses = Some(next_validator_statuses);
-        pending_block.supply_updates.insert(
-            *STAKING_TOKEN_ASSET_ID,
-            (STAKING_TOKEN_DENOM.clone(), staking_token_supply),
-        );
-
-        Ok(())
+        let chain_id = reader.chain_params_rx().borrow().chain_id.clone();
+        Ok(EpochReport::new(
+            chain_id,
+            pending_block,
+            &previous_supplies,
+            &self.validators,
+        ))
     }
 
     async fn commit(&mut self) -> Result<abci::response::Commit> {
-        let pending_block = self
-            .pending_block
+        let mut pending_block = self
+            .ended_block
             .take()
-            .expect("pending_block must be Some in Commit");
+            .expect("ended_block must be Some in Commit");
 
+        // The node never needs more than the tree's frontier, so make sure nothing else is carried
+        // into the next block or stored.  This is deterministic, so every node stores the same tree.
+        if pending_block.note_commitment_tree.prune(0) {
+            tracing::debug!("pruned note commitment tree to its frontier");
+        }
         // Pull the updated note commitment tree, for use in the next block.
         self.note_commitment_tree = pending_block.note_commitment_tree.clone();
 
-        let app_hash = self.state.commit_block(pending_block).await?;
+        let height = pending_block.phase.height;
+        let end_of_epoch = pending_block.next_rates.is_some();
+
+        // Record how much the block grows the anonymity set, and what its transactions look like.
+        histogram!(
+            pd_metrics::BLOCK_NOTES_CREATED,
+            pending_block.notes.len() as f64
+        );
+        histogram!(
+            pd_metrics::BLOCK_NULLIFIERS_REVEALED,
+            pending_block.spent_nullifiers.len() as f64
+        );
+        for (shape, count) in &pending_block.transaction_shapes {
+            counter!(
+                pd_metrics::TRANSACTIONS_BY_SHAPE_TOTAL,
+                *count,
+                "shape" => shape.to_string()
+            );
+        }
+        // Fees paid in other assets are counted at their fee rate, so that the total is
+        // comparable across assets.
+        let fees = {
+            let reader = self.state.private_reader();
+            let chain_params = reader.chain_params_rx().borrow();
+            pending_block
+                .fee_totals
+                .iter()
+                .map(|(asset_id, amount)| {
+                    if *asset_id == *STAKING_TOKEN_ASSET_ID {
+                        *amount
+                    } else {
+                        chain_params
+                            .fee_rate(*asset_id)
+                            .map(|rate| rate.staking_token_amount(*amount))
+                            .unwrap_or(0)
+                    }
+                })
+                .fold(0u64, u64::saturating_add)
+        };
+        counter!(pd_metrics::FEES_TOTAL, fees);
+
+        // The app hash depends on the Jellyfish Merkle tree as of the previous block.
+        self.finish_writing_block().await?;
+        let epoch_report = match &self.epoch_reports {
+            Some(_) if end_of_epoch => Some(self.epoch_report(&pending_block).await?),
+            _ => None,
+        };
+        let prepared = self.state.prepare_block(pending_block).await?;
+        let app_hash = prepared.app_hash().to_vec();
+        crash_report::record_app_hash(&app_hash);
+
+        // Write the block to the database in the background, so that Tendermint can move on to
+        // the next block meanwhile, and wait for it before reading the state again.  If pd stops
+        // before the write finishes, the database is a block behind Tendermint, which replays the
+        // block from its own block store on restart.
+        let state = self.state.clone();
+        let recent_writes = self.recent_writes.clone();
+        let epoch_reports = self.epoch_reports.clone();
+        let check_invariants = end_of_epoch && self.invariant_checks == InvariantChecks::Epoch;
+        self.writing_block = Some(tokio::spawn(
+            async move {
+                let commit_start = Instant::now();
+                let writes = state.commit_block(prepared).await?;
+                histogram!(pd_metrics::DB_COMMIT_DURATION_SECONDS, commit_start.elapsed());
+                {
+                    let mut recent_writes = recent_writes.lock().unwrap();
+                    if recent_writes.len() >= RECENT_WRITES_CAPACITY {
+                        recent_writes.pop_front();
+                    }
+                    recent_writes.push_back(writes);
+                }
+                if let (Some(epoch_reports), Some(epoch_report)) = (epoch_reports, epoch_report) {
+                    // The report is for auditors, so failing to write it mustn't halt the node.
+                    if let Err(e) = epoch_reports.publish(epoch_report) {
+                        tracing::error!(error = %format!("{:#}", e), "could not write epoch report");
+                    }
+                }
+
+                if check_invariants {
+                    let violations = state.private_reader().check_invariants(height).await?;
+                    if !violations.is_empty() {
+                        // Halt rather than keep building on a corrupted state.
+                        panic!(
+                            "chain state invariants violated at height {}:\n{}",
+                            height,
+                            violations.join("\n")
+                        );
+                    }
+                    tracing::info!(?height, "checked chain state invariants");
+                }
+                Ok(())
+            }
+            .in_current_span(),
+        ));
 
         tracing::info!(app_hash = ?hex::encode(&app_hash), "finished block commit");
 
@@ -0,0 +1,163 @@
+//! Opt-in crash reports for diagnosing consensus failures.
+//!
+//! When enabled with [`install`], the consensus worker records a summary of
+//! each ABCI request it processes, and a panic hook writes the current request,
+//! the most recent requests, and the last committed app hash to a JSON file in
+//! the report directory.  Nothing is sent anywhere; operators can attach the
+//! report file to bug reports.
+
+use std::{
+    collections::VecDeque,
+    fs,
+    panic::PanicInfo,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tendermint::abci::{
+    request::{BeginBlock, DeliverTx, EndBlock},
+    ConsensusRequest,
+};
+
+/// How many of the most recent requests to include in a report.
+const RECENT_REQUESTS: usize = 32;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static CONTEXT: Lazy<Mutex<CrashContext>> = Lazy::new(Default::default);
+
+/// A summary of a consensus request, mirroring the fields of its tracing span.
+#[derive(Debug, Clone, Serialize)]
+struct RequestSummary {
+    request: &'static str,
+    height: Option<u64>,
+    txid: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct CrashContext {
+    /// The request being processed, if any.
+    current_request: Option<RequestSummary>,
+    /// The most recently processed requests, oldest first.
+    recent_requests: VecDeque<RequestSummary>,
+    /// The hex-encoded app hash of the last committed block.
+    last_app_hash: Option<String>,
+    /// The height of the block being processed, from the last `BeginBlock`.
+    #[serde(skip)]
+    height: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct CrashReport<'a> {
+    version: &'static str,
+    timestamp: u64,
+    message: String,
+    location: Option<String>,
+    #[serde(flatten)]
+    context: Option<&'a CrashContext>,
+}
+
+/// Install a panic hook that writes crash reports into `report_dir`, and start
+/// recording consensus requests.
+pub fn install(report_dir: PathBuf) {
+    ENABLED.store(true, Ordering::Relaxed);
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        match write_report(&report_dir, info) {
+            Ok(path) => eprintln!("wrote crash report to {}", path.display()),
+            Err(e) => eprintln!("could not write crash report: {}", e),
+        }
+        default_hook(info);
+    }));
+}
+
+/// Record that the consensus worker is starting to process `req`.
+pub fn record_request(req: &ConsensusRequest) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut context = CONTEXT.lock().unwrap_or_else(|e| e.into_inner());
+
+    let summary = match req {
+        ConsensusRequest::InitChain(_) => RequestSummary {
+            request: "InitChain",
+            height: None,
+            txid: None,
+        },
+        ConsensusRequest::BeginBlock(BeginBlock { header, .. }) => {
+            context.height = Some(header.height.value());
+            RequestSummary {
+                request: "BeginBlock",
+                height: context.height,
+                txid: None,
+            }
+        }
+        ConsensusRequest::DeliverTx(DeliverTx { tx }) => RequestSummary {
+            request: "DeliverTx",
+            height: context.height,
+            txid: Some(hex::encode(&Sha256::digest(tx.as_ref()))),
+        },
+        ConsensusRequest::EndBlock(EndBlock { height }) => RequestSummary {
+            request: "EndBlock",
+            height: Some(*height as u64),
+            txid: None,
+        },
+        ConsensusRequest::Commit => RequestSummary {
+            request: "Commit",
+            height: context.height,
+            txid: None,
+        },
+    };
+
+    if let Some(previous) = context.current_request.replace(summary) {
+        if context.recent_requests.len() == RECENT_REQUESTS {
+            context.recent_requests.pop_front();
+        }
+        context.recent_requests.push_back(previous);
+    }
+}
+
+/// Record the app hash of a newly committed block.
+pub fn record_app_hash(app_hash: &[u8]) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    CONTEXT
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .last_app_hash = Some(hex::encode(app_hash));
+}
+
+fn write_report(report_dir: &Path, info: &PanicInfo) -> anyhow::Result<PathBuf> {
+    let message = if let Some(s) = info.payload().downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = info.payload().downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic payload".to_string()
+    };
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    // Don't block if the panic happened while the context was locked.
+    let context = CONTEXT.try_lock().ok();
+    let report = CrashReport {
+        version: env!("VERGEN_GIT_SEMVER"),
+        timestamp,
+        message,
+        location: info.location().map(ToString::to_string),
+        context: context.as_deref(),
+    };
+
+    fs::create_dir_all(report_dir)?;
+    let path = report_dir.join(format!("pd-crash-{}.json", timestamp));
+    fs::write(&path, serde_json::to_vec_pretty(&report)?)?;
+
+    Ok(path)
+}
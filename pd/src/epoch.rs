@@ -0,0 +1,358 @@
+//! The state transition at the end of an epoch, separated from the state it's computed from, so
+//! that it can be run outside of consensus, e.g. by `pd debug simulate-epoch`.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Context, Result};
+use penumbra_crypto::Amount;
+use penumbra_stake::{
+    BaseRateData, FundingStreamRecipient, FundingStreams, IdentityKey, RateData, ValidatorState,
+    ValidatorStatus,
+};
+
+use crate::state::StateSnapshot;
+
+pub mod cutoff;
+pub mod report;
+
+/// FIXME: set this less arbitrarily, and allow this to be set per-epoch
+/// 3bps -> 11% return over 365 epochs, why not
+pub const BASE_REWARD_RATE: u64 = 3_0000;
+
+/// Everything the end of an epoch is computed from.
+///
+/// The epoch that is ending is the *previous* epoch, and the one that is starting is the
+/// *current* epoch, whose rates were already computed at the end of the previous one.
+#[derive(Debug, Clone)]
+pub struct EpochInputs {
+    /// The index of the epoch that is ending.
+    pub prev_epoch_index: u64,
+    /// The base rate for the current epoch.
+    pub current_base_rate: BaseRateData,
+    /// The rates for the current epoch, already reduced for any validators slashed in the
+    /// final block of the previous epoch.
+    pub current_rates: Vec<RateData>,
+    pub staking_token_supply: Amount,
+    /// The net delegations to each identity key over the previous epoch.
+    pub delegation_changes: BTreeMap<IdentityKey, i64>,
+    /// Every validator identity key migration, from the old identity key to the new identity key
+    /// and the epoch in which it was performed.
+    pub migrations: BTreeMap<IdentityKey, (IdentityKey, u64)>,
+    /// The funding streams of each validator in `current_rates`.
+    pub funding_streams: BTreeMap<IdentityKey, FundingStreams>,
+    /// The supply of the delegation token of each validator in `current_rates`, and of each of
+    /// their previous identity keys.
+    pub delegation_token_supplies: BTreeMap<IdentityKey, Amount>,
+}
+
+/// The results of the end of an epoch.
+#[derive(Debug, Clone)]
+pub struct EpochTransition {
+    pub next_base_rate: BaseRateData,
+    /// The rates for the next epoch, under each validator's next identity key.
+    pub next_rates: Vec<RateData>,
+    pub next_validator_statuses: Vec<ValidatorStatus>,
+    pub staking_token_supply: Amount,
+    /// The updated supply of each delegation token affected by the epoch's delegations.
+    pub delegation_token_supplies: BTreeMap<IdentityKey, Amount>,
+    /// The identity key migrations taking effect at the end of this epoch, from old to new.
+    pub validator_migrations: BTreeMap<IdentityKey, IdentityKey>,
+    /// The commission paid to each validator funding stream, by the validator's identity key, as
+    /// an amount of the staking token.
+    pub commission_rewards: Vec<(IdentityKey, u64, FundingStreamRecipient)>,
+}
+
+impl EpochInputs {
+    /// The inputs for the end of the epoch a snapshot was taken in, as if the rest of the epoch
+    /// had the given net delegations in addition to those recorded in the snapshot.
+    pub fn from_snapshot(
+        snapshot: &StateSnapshot,
+        delegation_changes: &BTreeMap<IdentityKey, i64>,
+    ) -> Self {
+        let mut all_delegation_changes = snapshot
+            .delegation_changes
+            .iter()
+            .cloned()
+            .collect::<BTreeMap<_, _>>();
+        for (identity_key, delta) in delegation_changes {
+            *all_delegation_changes
+                .entry(identity_key.clone())
+                .or_insert(0) += delta;
+        }
+
+        Self {
+            prev_epoch_index: snapshot.epoch_index,
+            current_base_rate: snapshot.base_rate.clone(),
+            current_rates: snapshot.rates.clone(),
+            staking_token_supply: snapshot.staking_token_supply,
+            delegation_changes: all_delegation_changes,
+            migrations: snapshot
+                .validator_migrations
+                .iter()
+                .map(|(old, new, epoch)| (old.clone(), (new.clone(), *epoch)))
+                .collect(),
+            funding_streams: snapshot
+                .validators
+                .iter()
+                .map(|info| {
+                    (
+                        info.validator.identity_key.clone(),
+                        info.validator.funding_streams.clone(),
+                    )
+                })
+                .collect(),
+            delegation_token_supplies: snapshot.delegation_token_supplies.iter().cloned().collect(),
+        }
+    }
+
+    /// Computes the end of the epoch.
+    pub fn transition(&self) -> Result<EpochTransition> {
+        let mut staking_token_supply = self.staking_token_supply;
+
+        // steps (foreach validator):
+        // - get the total token supply for the validator's delegation tokens
+        // - process the updates to the token supply:
+        //   - collect all delegations occurring in previous epoch and apply them (adds to supply);
+        //   - collect all undelegations started in previous epoch and apply them (reduces supply);
+        // - feed the updated (current) token supply into current_rates.voting_power()
+        // - persist both the current voting power and the current supply
+        //
+
+        let next_base_rate = self.current_base_rate.next(BASE_REWARD_RATE);
+
+        // rename to curr_rate so it lines up with next_rate (same # chars)
+        tracing::debug!(curr_base_rate = ?self.current_base_rate);
+        tracing::debug!(?next_base_rate);
+
+        let previous_identity_keys = self.previous_identity_keys();
+
+        let mut transition = EpochTransition {
+            next_base_rate: next_base_rate.clone(),
+            next_rates: Vec::new(),
+            next_validator_statuses: Vec::new(),
+            staking_token_supply: Amount::ZERO,
+            delegation_token_supplies: BTreeMap::new(),
+            validator_migrations: BTreeMap::new(),
+            commission_rewards: Vec::new(),
+        };
+
+        for current_rate in &self.current_rates {
+            let identity_key = current_rate.identity_key.clone();
+
+            let funding_streams = self
+                .funding_streams
+                .get(&identity_key)
+                .ok_or_else(|| anyhow!("missing funding streams for validator {}", identity_key))?;
+            let mut next_rate = current_rate.next(&next_base_rate, funding_streams.as_ref());
+
+            // If the validator is migrating, its next rate is recorded under its new identity key.
+            let next_identity_key = match self.migrations.get(&identity_key) {
+                Some((new_identity_key, epoch)) if *epoch == self.prev_epoch_index => {
+                    tracing::info!(
+                        old = %identity_key,
+                        new = %new_identity_key,
+                        "migrating validator identity key"
+                    );
+                    transition
+                        .validator_migrations
+                        .insert(identity_key.clone(), new_identity_key.clone());
+                    new_identity_key.clone()
+                }
+                _ => identity_key.clone(),
+            };
+            next_rate.identity_key = next_identity_key.clone();
+
+            // The total supply of the validator's delegation tokens, across all its identity keys.
+            let mut total_delegation_token_supply = Amount::ZERO;
+            let mut delegation_delta = 0i64;
+            let token_identity_keys = std::iter::once(identity_key.clone()).chain(
+                previous_identity_keys
+                    .get(&identity_key)
+                    .cloned()
+                    .unwrap_or_default(),
+            );
+            for token_identity_key in token_identity_keys {
+                // TODO: if a validator isn't part of the consensus set, should we ignore them
+                // and not update their rates?
+                let token_delegation_delta = *self
+                    .delegation_changes
+                    .get(&token_identity_key)
+                    .unwrap_or(&0i64);
+                delegation_delta += token_delegation_delta;
+
+                let delegation_amount = token_delegation_delta.abs() as u64;
+                let unbonded_amount = current_rate.unbonded_amount(delegation_amount);
+
+                let mut delegation_token_supply = *self
+                    .delegation_token_supplies
+                    .get(&token_identity_key)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "missing delegation token supply for identity key {}",
+                            token_identity_key
+                        )
+                    })?;
+
+                if token_delegation_delta > 0 {
+                    // net delegation: subtract the unbonded amount from the staking token supply
+                    staking_token_supply = staking_token_supply
+                        .checked_sub(unbonded_amount)
+                        .context("staking token supply underflow")?;
+                    delegation_token_supply = delegation_token_supply
+                        .checked_add(delegation_amount)
+                        .context("delegation token supply overflow")?;
+                } else {
+                    // net undelegation: add the unbonded amount to the staking token supply
+                    staking_token_supply = staking_token_supply
+                        .checked_add(unbonded_amount)
+                        .context("staking token supply overflow")?;
+                    delegation_token_supply = delegation_token_supply
+                        .checked_sub(delegation_amount)
+                        .context("delegation token supply underflow")?;
+                }
+
+                transition
+                    .delegation_token_supplies
+                    .insert(token_identity_key, delegation_token_supply);
+
+                total_delegation_token_supply = total_delegation_token_supply
+                    .checked_add(delegation_token_supply)
+                    .context("delegation token supply overflow")?;
+            }
+            let delegation_token_supply = total_delegation_token_supply;
+
+            let voting_power = next_rate.voting_power(delegation_token_supply, &next_base_rate)?;
+            let next_status = ValidatorStatus {
+                identity_key: next_identity_key,
+                voting_power,
+                // TODO: this state needs to be set correctly based on current state and any changes
+                // within the current block. This will be fixed by #375.
+                state: ValidatorState::Active,
+            };
+
+            // distribute validator commission
+            for stream in funding_streams.clone() {
+                let commission_reward_amount = stream.reward_amount(
+                    delegation_token_supply,
+                    &next_base_rate,
+                    &self.current_base_rate,
+                )?;
+
+                transition.commission_rewards.push((
+                    identity_key.clone(),
+                    commission_reward_amount,
+                    stream.recipient,
+                ));
+            }
+
+            // rename to curr_rate so it lines up with next_rate (same # chars)
+            tracing::debug!(curr_rate = ?current_rate);
+            tracing::debug!(?next_rate);
+            tracing::debug!(?delegation_delta);
+            tracing::debug!(?delegation_token_supply);
+            tracing::debug!(?next_status);
+
+            transition.next_rates.push(next_rate);
+            transition.next_validator_statuses.push(next_status);
+        }
+
+        tracing::debug!(?staking_token_supply);
+        transition.staking_token_supply = staking_token_supply;
+
+        Ok(transition)
+    }
+
+    /// The identity keys each validator was previously known by, by its current identity key.
+    ///
+    /// Validators that rotated their identity key in the previous epoch are known by their new
+    /// identity key from the next epoch on. Delegation tokens for a validator's old identity
+    /// keys remain valid at the same rate, so they still count towards its voting power.
+    pub fn previous_identity_keys(&self) -> BTreeMap<IdentityKey, Vec<IdentityKey>> {
+        let mut previous_identity_keys = BTreeMap::<IdentityKey, Vec<IdentityKey>>::new();
+        for (old_identity_key, (_, epoch)) in &self.migrations {
+            if *epoch == self.prev_epoch_index {
+                // This migration takes effect now, and its old key is still the current one.
+                continue;
+            }
+            let mut identity_key = old_identity_key;
+            while let Some((new_identity_key, epoch)) = self.migrations.get(identity_key) {
+                if *epoch == self.prev_epoch_index {
+                    break;
+                }
+                identity_key = new_identity_key;
+            }
+            previous_identity_keys
+                .entry(identity_key.clone())
+                .or_default()
+                .push(old_identity_key.clone());
+        }
+        previous_identity_keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::rdsa::{SigningKey, SpendAuth, VerificationKey};
+    use rand_core::OsRng;
+
+    use super::*;
+
+    fn identity_key() -> IdentityKey {
+        IdentityKey(VerificationKey::from(&SigningKey::<SpendAuth>::new(OsRng)))
+    }
+
+    fn rate(identity_key: &IdentityKey) -> RateData {
+        RateData {
+            identity_key: identity_key.clone(),
+            epoch_index: 1,
+            validator_reward_rate: 0,
+            validator_exchange_rate: 1_0000_0000,
+        }
+    }
+
+    #[test]
+    fn delegations_move_supply_and_follow_migrations() {
+        let (a, old_b, b) = (identity_key(), identity_key(), identity_key());
+
+        let inputs = EpochInputs {
+            prev_epoch_index: 0,
+            current_base_rate: BaseRateData {
+                epoch_index: 1,
+                base_reward_rate: 0,
+                base_exchange_rate: 1_0000_0000,
+            },
+            current_rates: vec![rate(&a), rate(&old_b)],
+            staking_token_supply: 1_000u64.into(),
+            delegation_changes: [(a.clone(), 100), (old_b.clone(), -50)]
+                .into_iter()
+                .collect(),
+            migrations: [(old_b.clone(), (b.clone(), 0))].into_iter().collect(),
+            funding_streams: [
+                (a.clone(), FundingStreams::new()),
+                (old_b.clone(), FundingStreams::new()),
+            ]
+            .into_iter()
+            .collect(),
+            delegation_token_supplies: [(a.clone(), 500u64.into()), (old_b.clone(), 500u64.into())]
+                .into_iter()
+                .collect(),
+        };
+
+        let transition = inputs.transition().unwrap();
+        // At an exchange rate of 1, delegations move supply one-for-one.
+        assert_eq!(transition.staking_token_supply, 950u64.into());
+        assert_eq!(transition.delegation_token_supplies[&a], 600u64.into());
+        assert_eq!(transition.delegation_token_supplies[&old_b], 450u64.into());
+        assert_eq!(transition.validator_migrations[&old_b], b);
+        assert_eq!(transition.next_rates[1].identity_key, b);
+        assert_eq!(
+            transition
+                .next_validator_statuses
+                .iter()
+                .map(|status| status.identity_key.clone())
+                .collect::<Vec<_>>(),
+            vec![a, b]
+        );
+        assert!(transition.commission_rewards.is_empty());
+    }
+}
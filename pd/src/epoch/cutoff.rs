@@ -0,0 +1,114 @@
+//! Which epoch the delegation changes in a block count toward.
+//!
+//! A block's (un)delegations are priced at the rates for the epoch after the one containing the
+//! block, and its net delegation changes are recorded under the epoch containing the block, to be
+//! applied to the token supplies at the end of that epoch. This includes the final block of the
+//! epoch, whose changes are applied at the end of the same block: they haven't been committed yet
+//! when the epoch ends, so they're merged in from the pending block.
+
+use std::collections::BTreeMap;
+
+use penumbra_stake::{Epoch, IdentityKey};
+
+/// Returns the index of the epoch whose end applies the delegation changes in the block at
+/// `height`.
+pub fn applying_epoch(height: u64, epoch_duration: u64) -> u64 {
+    Epoch::from_height(height, epoch_duration).index
+}
+
+/// Returns the index of the epoch whose rates the (un)delegations in the block at `height` must
+/// be priced at.
+pub fn pricing_epoch(height: u64, epoch_duration: u64) -> u64 {
+    applying_epoch(height, epoch_duration) + 1
+}
+
+/// Returns whether the block at `height` is the final block of its epoch, so that the epoch ends
+/// before the block's delegation changes are committed.
+pub fn ends_epoch(height: u64, epoch_duration: u64) -> bool {
+    Epoch::from_height(height, epoch_duration)
+        .end_height()
+        .value()
+        == height
+}
+
+/// Returns the net delegation changes over an epoch, from those committed in the epoch and those
+/// in its final block, which is still pending.
+pub fn epoch_delegation_changes(
+    mut committed: BTreeMap<IdentityKey, i64>,
+    final_block: &BTreeMap<IdentityKey, i64>,
+) -> BTreeMap<IdentityKey, i64> {
+    for (identity_key, delta) in final_block {
+        *committed.entry(identity_key.clone()).or_insert(0) += delta;
+    }
+    committed
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::rdsa::{SigningKey, SpendAuth, VerificationKey};
+    use rand_core::OsRng;
+
+    use super::*;
+
+    fn identity_key() -> IdentityKey {
+        IdentityKey(VerificationKey::from(&SigningKey::<SpendAuth>::new(OsRng)))
+    }
+
+    #[test]
+    fn boundary_blocks() {
+        for epoch_duration in [1, 2, 3, 10, 719] {
+            for epoch_index in 0..4 {
+                let epoch_end = Epoch {
+                    index: epoch_index,
+                    duration: epoch_duration,
+                }
+                .end_height()
+                .value();
+
+                // The block before the final block counts toward the same epoch, and is committed
+                // before the epoch ends. (With one-block epochs, it's the final block of the
+                // previous epoch.)
+                if epoch_end > 0 {
+                    let height = epoch_end - 1;
+                    let expected_epoch = if epoch_duration == 1 {
+                        epoch_index - 1
+                    } else {
+                        epoch_index
+                    };
+                    assert_eq!(applying_epoch(height, epoch_duration), expected_epoch);
+                    assert_eq!(pricing_epoch(height, epoch_duration), expected_epoch + 1);
+                    assert_eq!(ends_epoch(height, epoch_duration), epoch_duration == 1);
+                }
+
+                // The final block counts toward the epoch it ends.
+                assert_eq!(applying_epoch(epoch_end, epoch_duration), epoch_index);
+                assert_eq!(pricing_epoch(epoch_end, epoch_duration), epoch_index + 1);
+                assert!(ends_epoch(epoch_end, epoch_duration));
+
+                // The block after it counts toward the next epoch, and is priced at the rates for
+                // the one after that.
+                let height = epoch_end + 1;
+                assert_eq!(applying_epoch(height, epoch_duration), epoch_index + 1);
+                assert_eq!(pricing_epoch(height, epoch_duration), epoch_index + 2);
+                assert_eq!(ends_epoch(height, epoch_duration), epoch_duration == 1);
+            }
+        }
+    }
+
+    #[test]
+    fn final_block_changes_are_merged() {
+        let (a, b, c) = (identity_key(), identity_key(), identity_key());
+
+        let committed = [(a.clone(), 100), (b.clone(), -20)].into_iter().collect();
+        let final_block = [(b.clone(), 20), (c.clone(), 5)].into_iter().collect();
+        let changes = epoch_delegation_changes(committed, &final_block);
+
+        // A validator whose changes cancel out still had (un)delegations in the epoch.
+        assert_eq!(
+            changes,
+            [(a, 100), (b, 0), (c, 5)]
+                .into_iter()
+                .collect::<BTreeMap<_, _>>()
+        );
+    }
+}
@@ -0,0 +1,260 @@
+//! Reports of the outcome of each epoch boundary, written to disk and served to validators and
+//! explorers, so that they can audit the end of each epoch without re-executing it.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{Context, Result};
+use penumbra_crypto::{
+    asset,
+    rdsa::{SigningKey, SpendAuth, VerificationKey},
+    Amount,
+};
+use penumbra_proto::epoch_report::{
+    epoch_reports_server, LatestEpochReportRequest, SignedEpochReport,
+};
+use penumbra_stake::{BaseRateData, IdentityKey, RateData, ValidatorStatus};
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+use tonic::Status;
+
+use crate::{pending_block::Ended, state, PendingBlock};
+
+/// What happened at the end of an epoch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EpochReport {
+    pub chain_id: String,
+    /// The index of the epoch that ended.
+    pub epoch_index: u64,
+    /// The height of the epoch's last block.
+    pub height: u64,
+    /// The base rate for the next epoch.
+    pub next_base_rate: BaseRateData,
+    /// The rates for the next epoch.
+    pub next_rates: Vec<RateData>,
+    /// The voting powers and states of the validators for the next epoch.
+    pub next_validator_statuses: Vec<ValidatorStatus>,
+    /// The assets whose supply changed in the epoch's last block.
+    pub supply_changes: Vec<SupplyChange>,
+    /// The rewards paid to each validator's funding streams in the epoch's last block, including
+    /// the commission for the epoch.
+    pub rewards_paid: Vec<RewardPaid>,
+    /// The validators whose state changed at the end of the epoch.
+    pub transitions: Vec<StateTransition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupplyChange {
+    pub denom: String,
+    pub previous_supply: Amount,
+    pub next_supply: Amount,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RewardPaid {
+    pub identity_key: IdentityKey,
+    /// The total paid to the validator's funding streams, in the staking token.
+    pub amount: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateTransition {
+    pub identity_key: IdentityKey,
+    /// The validator's previous state, or `None` if it's a new validator.
+    pub from: Option<String>,
+    pub to: String,
+}
+
+impl EpochReport {
+    /// Reports the end of the epoch ended by `block`, given the supply of each asset it updates
+    /// as of the previous block, and the validators as of the start of the block.
+    pub fn new(
+        chain_id: String,
+        block: &PendingBlock<Ended>,
+        previous_supplies: &BTreeMap<asset::Id, Amount>,
+        validators: &state::ValidatorInfoSnapshot,
+    ) -> Self {
+        let next_validator_statuses = block.next_validator_statuses.clone().unwrap_or_default();
+        let transitions = next_validator_statuses
+            .iter()
+            .filter_map(|status| {
+                let from = validators
+                    .get(&status.identity_key)
+                    .map(|info| &info.status.state);
+                (from != Some(&status.state)).then(|| StateTransition {
+                    identity_key: status.identity_key.clone(),
+                    from: from.map(|state| state.name().to_str().to_string()),
+                    to: status.state.name().to_str().to_string(),
+                })
+            })
+            .collect();
+
+        Self {
+            chain_id,
+            epoch_index: block.phase.epoch.index,
+            height: block.phase.height,
+            next_base_rate: block
+                .next_base_rate
+                .clone()
+                .expect("an epoch's last block has a next base rate"),
+            next_rates: block.next_rates.clone().unwrap_or_default(),
+            next_validator_statuses,
+            supply_changes: block
+                .supply_updates
+                .iter()
+                .map(|(id, (denom, next_supply))| SupplyChange {
+                    denom: denom.to_string(),
+                    previous_supply: previous_supplies.get(id).copied().unwrap_or_default(),
+                    next_supply: *next_supply,
+                })
+                .collect(),
+            rewards_paid: block
+                .rewards_paid
+                .iter()
+                .map(|(identity_key, amount)| RewardPaid {
+                    identity_key: identity_key.clone(),
+                    amount: *amount,
+                })
+                .collect(),
+            transitions,
+        }
+    }
+}
+
+/// An epoch report as written to disk.
+#[derive(Serialize, Deserialize)]
+struct StoredReport {
+    report: EpochReport,
+    signer: Option<IdentityKey>,
+    /// The signature over the report's compact JSON serialization, in hex.
+    signature: Option<String>,
+}
+
+/// Writes an epoch report at the end of each epoch, and serves the latest one.
+#[derive(Clone)]
+pub struct EpochReports {
+    dir: PathBuf,
+    /// The validator identity key reports are signed with, if any.
+    signing_key: Option<SigningKey<SpendAuth>>,
+    /// The latest report, and the chain it's for.
+    latest: Arc<RwLock<Option<(String, SignedEpochReport)>>>,
+}
+
+impl EpochReports {
+    /// Writes reports into `dir`, creating it if need be, and serves the latest report already
+    /// there, if any, until the next epoch ends.
+    pub fn new(dir: PathBuf, signing_key: Option<SigningKey<SpendAuth>>) -> Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("could not create {}", dir.display()))?;
+        let reports = Self {
+            dir,
+            signing_key,
+            latest: Default::default(),
+        };
+        if let Some(path) = reports.latest_path()? {
+            let stored: StoredReport = serde_json::from_slice(&std::fs::read(&path)?)
+                .with_context(|| format!("invalid epoch report {}", path.display()))?;
+            *reports.latest.write().unwrap() = Some(stored.signed()?);
+        }
+        Ok(reports)
+    }
+
+    /// Signs a report, if there's a signing key, and writes it to disk.
+    pub fn publish(&self, report: EpochReport) -> Result<()> {
+        let (signer, signature) = match &self.signing_key {
+            Some(signing_key) => {
+                let signature = signing_key.sign(OsRng, &serde_json::to_vec(&report)?);
+                (
+                    Some(IdentityKey(VerificationKey::from(signing_key))),
+                    Some(hex::encode(<[u8; 64]>::from(signature))),
+                )
+            }
+            None => (None, None),
+        };
+        let stored = StoredReport {
+            report,
+            signer,
+            signature,
+        };
+
+        let path = self.path(stored.report.epoch_index);
+        std::fs::write(&path, serde_json::to_vec_pretty(&stored)?)
+            .with_context(|| format!("could not write {}", path.display()))?;
+        tracing::info!(?path, "wrote epoch report");
+        *self.latest.write().unwrap() = Some(stored.signed()?);
+        Ok(())
+    }
+
+    fn path(&self, epoch_index: u64) -> PathBuf {
+        self.dir.join(format!("epoch-{}.json", epoch_index))
+    }
+
+    /// Returns the path of the report for the latest epoch in the report directory, if any.
+    fn latest_path(&self) -> Result<Option<PathBuf>> {
+        let mut latest = None;
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if let Some(epoch_index) = epoch_index(&path) {
+                if latest
+                    .as_ref()
+                    .map_or(true, |(index, _)| epoch_index > *index)
+                {
+                    latest = Some((epoch_index, path));
+                }
+            }
+        }
+        Ok(latest.map(|(_, path)| path))
+    }
+}
+
+/// Parses the epoch index from the name of a report written by [`EpochReports::publish`].
+fn epoch_index(path: &Path) -> Option<u64> {
+    path.file_name()?
+        .to_str()?
+        .strip_prefix("epoch-")?
+        .strip_suffix(".json")?
+        .parse()
+        .ok()
+}
+
+impl StoredReport {
+    fn signed(&self) -> Result<(String, SignedEpochReport)> {
+        Ok((
+            self.report.chain_id.clone(),
+            SignedEpochReport {
+                report_json: serde_json::to_vec(&self.report)?,
+                signer: self.signer.clone().map(Into::into),
+                signature: self
+                    .signature
+                    .as_ref()
+                    .map(hex::decode)
+                    .transpose()?
+                    .unwrap_or_default(),
+            },
+        ))
+    }
+}
+
+#[tonic::async_trait]
+impl epoch_reports_server::EpochReports for EpochReports {
+    async fn latest_epoch_report(
+        &self,
+        request: tonic::Request<LatestEpochReportRequest>,
+    ) -> Result<tonic::Response<SignedEpochReport>, Status> {
+        let latest = self.latest.read().unwrap();
+        let (chain_id, report) = latest
+            .as_ref()
+            .ok_or_else(|| Status::not_found("no epoch has ended since reports were enabled"))?;
+        let expected = &request.get_ref().chain_id;
+        if !expected.is_empty() && expected != chain_id {
+            return Err(Status::failed_precondition(format!(
+                "provided chain_id {} does not match chain_id {}",
+                expected, chain_id
+            )));
+        }
+        Ok(tonic::Response::new(report.clone()))
+    }
+}
@@ -1,6 +1,9 @@
-use penumbra_chain::params::ChainParams;
+use std::collections::BTreeSet;
 
+use penumbra_chain::params::ChainParams;
+use penumbra_crypto::{asset, Amount};
 use penumbra_proto::{genesis as pb, Protobuf};
+use penumbra_stake::{BaseRateData, RateData};
 use serde::{Deserialize, Serialize};
 
 use super::{Allocation, ValidatorPower};
@@ -17,6 +20,98 @@ pub struct AppState {
     pub allocations: Vec<Allocation>,
 }
 
+/// The base and validator exchange rates at genesis, as recorded by
+/// [`Writer::commit_genesis`](crate::state::Writer::commit_genesis).
+const GENESIS_EXCHANGE_RATE: u64 = 1_0000_0000;
+
+impl AppState {
+    /// Checks that no two genesis validators share an identity key or a consensus key.
+    ///
+    /// Validators are stored by identity key, and Tendermint identifies them by consensus key, so
+    /// either kind of duplicate would leave the chain with a validator set it can't represent.
+    pub fn check_validators_unique(&self) -> anyhow::Result<()> {
+        let mut identity_keys = BTreeSet::new();
+        let mut consensus_keys = BTreeSet::new();
+        for ValidatorPower { validator, .. } in &self.validators {
+            if !identity_keys.insert(&validator.identity_key) {
+                return Err(anyhow::anyhow!(
+                    "genesis validator {} has duplicate identity key {}",
+                    validator.name,
+                    validator.identity_key
+                ));
+            }
+            if !consensus_keys.insert(validator.consensus_key.to_bytes()) {
+                return Err(anyhow::anyhow!(
+                    "genesis validator {} ({}) has the same consensus key as another genesis \
+                     validator",
+                    validator.name,
+                    validator.identity_key
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that each genesis validator's voting power is the voting power of its genesis
+    /// delegation pool, at the genesis exchange rate.
+    ///
+    /// Otherwise, the voting power the chain starts with would disagree with the one computed from
+    /// the delegation token supply at the first epoch boundary.
+    pub fn check_validator_powers(&self) -> anyhow::Result<()> {
+        let base_rate_data = BaseRateData {
+            epoch_index: 0,
+            base_reward_rate: 0,
+            base_exchange_rate: GENESIS_EXCHANGE_RATE,
+        };
+
+        for ValidatorPower { validator, power } in &self.validators {
+            let delegation_token = validator.identity_key.delegation_token();
+            let delegation_pool = self
+                .allocations
+                .iter()
+                .filter(|allocation| {
+                    asset::REGISTRY
+                        .parse_denom(&allocation.denom)
+                        .map(|denom| denom.id())
+                        == Some(delegation_token.id())
+                })
+                .try_fold(Amount::ZERO, |pool, allocation| {
+                    pool.checked_add(allocation.amount)
+                })
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "genesis delegation pool of validator {} overflows",
+                        validator.name
+                    )
+                })?;
+
+            let rate_data = RateData {
+                identity_key: validator.identity_key.clone(),
+                epoch_index: 0,
+                validator_reward_rate: 0,
+                validator_exchange_rate: GENESIS_EXCHANGE_RATE,
+            };
+            let expected_power = rate_data.voting_power(delegation_pool, &base_rate_data)?;
+
+            if power.value() != expected_power {
+                return Err(anyhow::anyhow!(
+                    "genesis validator {} ({}) has voting power {}, but its genesis delegation \
+                     pool of {}{} gives it voting power {}",
+                    validator.name,
+                    validator.identity_key,
+                    power.value(),
+                    delegation_pool,
+                    delegation_token,
+                    expected_power
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl From<AppState> for pb::GenesisAppState {
     fn from(a: AppState) -> Self {
         pb::GenesisAppState {
@@ -49,3 +144,61 @@ impl TryFrom<pb::GenesisAppState> for AppState {
 }
 
 impl Protobuf<pb::GenesisAppState> for AppState {}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::rdsa::{SigningKey, SpendAuth, VerificationKey};
+    use penumbra_stake::{FundingStreams, IdentityKey, Validator};
+    use rand_core::OsRng;
+
+    use super::*;
+
+    fn genesis_validator(name: &str) -> ValidatorPower {
+        ValidatorPower {
+            validator: Validator {
+                identity_key: IdentityKey(VerificationKey::from(&SigningKey::<SpendAuth>::new(
+                    OsRng,
+                ))),
+                consensus_key: tendermint::PrivateKey::Ed25519(ed25519_consensus::SigningKey::new(
+                    OsRng,
+                ))
+                .public_key(),
+                name: name.to_string(),
+                website: String::new(),
+                description: String::new(),
+                funding_streams: FundingStreams::new(),
+                sequence_number: 0,
+            },
+            power: 1u32.into(),
+        }
+    }
+
+    #[test]
+    fn rejects_duplicate_genesis_validators() {
+        let a = genesis_validator("a");
+        let b = genesis_validator("b");
+        let app_state = |validators| AppState {
+            validators,
+            ..Default::default()
+        };
+
+        assert!(app_state(vec![a.clone(), b.clone()])
+            .check_validators_unique()
+            .is_ok());
+        assert!(app_state(vec![a.clone(), a.clone()])
+            .check_validators_unique()
+            .is_err());
+
+        let mut same_identity = b.clone();
+        same_identity.validator.identity_key = a.validator.identity_key.clone();
+        assert!(app_state(vec![a.clone(), same_identity])
+            .check_validators_unique()
+            .is_err());
+
+        let mut same_consensus_key = b;
+        same_consensus_key.validator.consensus_key = a.validator.consensus_key.clone();
+        assert!(app_state(vec![a, same_consensus_key])
+            .check_validators_unique()
+            .is_err());
+    }
+}
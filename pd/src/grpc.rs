@@ -0,0 +1,88 @@
+//! Transport settings for the gRPC services `pd` serves, and for its connections to other `pd`s.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use structopt::StructOpt;
+use tonic::transport::{Channel, Endpoint, Server};
+
+/// The largest HTTP/2 frame size a peer may be asked to accept.
+const MAX_FRAME_SIZE: u32 = (1 << 24) - 1;
+
+/// Transport settings shared by every gRPC server and client in `pd`.
+///
+/// The defaults suit long-lived streams, like the compact block and changefeed streams, which can
+/// sit idle between blocks: without keepalives, a NAT or load balancer between the peers may drop
+/// the connection without either of them noticing.
+#[derive(Debug, Clone, StructOpt)]
+pub struct GrpcOptions {
+    /// The size, in bytes, of the largest gRPC message to expect, e.g. a batch of compact blocks
+    /// or a write batch.
+    ///
+    /// HTTP/2 frames and flow control windows are sized so that messages up to this size are
+    /// sent without stalling on the peer's window updates.
+    #[structopt(long, default_value = "16777216")]
+    pub grpc_max_message_size: u32,
+    /// Send an HTTP/2 ping on each connection every this many seconds, to keep idle streams
+    /// open and to notice dead peers.  Zero disables keepalives.
+    #[structopt(long, default_value = "30")]
+    pub grpc_keepalive_secs: u64,
+    /// Close a connection whose peer hasn't answered a keepalive ping within this many seconds.
+    #[structopt(long, default_value = "20")]
+    pub grpc_keepalive_timeout_secs: u64,
+    /// Give up connecting to another `pd` after this many seconds.
+    #[structopt(long, default_value = "10")]
+    pub grpc_connect_timeout_secs: u64,
+}
+
+impl GrpcOptions {
+    fn keepalive(&self) -> Option<Duration> {
+        (self.grpc_keepalive_secs > 0).then(|| Duration::from_secs(self.grpc_keepalive_secs))
+    }
+
+    fn keepalive_timeout(&self) -> Duration {
+        Duration::from_secs(self.grpc_keepalive_timeout_secs)
+    }
+
+    /// The flow control window for each stream, and, with room for a few streams at once, for
+    /// each connection.
+    fn windows(&self) -> (u32, u32) {
+        let stream = self.grpc_max_message_size.max(1 << 16);
+        (stream, stream.saturating_mul(4))
+    }
+
+    /// Returns a server builder with these settings.
+    pub fn server(&self) -> Server {
+        let (stream_window, connection_window) = self.windows();
+        Server::builder()
+            .tcp_keepalive(self.keepalive())
+            .http2_keepalive_interval(self.keepalive())
+            .http2_keepalive_timeout(Some(self.keepalive_timeout()))
+            .initial_stream_window_size(stream_window)
+            .initial_connection_window_size(connection_window)
+            .max_frame_size(self.grpc_max_message_size.clamp(1 << 14, MAX_FRAME_SIZE))
+    }
+
+    /// Connects to the gRPC server at `uri` with these settings.
+    pub async fn connect(&self, uri: &str) -> Result<Channel> {
+        let (stream_window, connection_window) = self.windows();
+        let mut endpoint = Endpoint::from_shared(uri.to_string())
+            .with_context(|| format!("invalid URI {:?}", uri))?
+            .tcp_keepalive(self.keepalive())
+            .initial_stream_window_size(stream_window)
+            .initial_connection_window_size(connection_window);
+        if let Some(keepalive) = self.keepalive() {
+            endpoint = endpoint
+                .http2_keep_alive_interval(keepalive)
+                .keep_alive_timeout(self.keepalive_timeout())
+                // Streams are idle between blocks, which is exactly when they need keeping alive.
+                .keep_alive_while_idle(true);
+        }
+
+        let timeout = Duration::from_secs(self.grpc_connect_timeout_secs);
+        tokio::time::timeout(timeout, endpoint.connect())
+            .await
+            .map_err(|_| anyhow::anyhow!("timed out connecting to {}", uri))?
+            .with_context(|| format!("could not connect to {}", uri))
+    }
+}
@@ -9,9 +9,7 @@ use tendermint::abci::{self, response::Echo, InfoRequest, InfoResponse};
 use tower_abci::BoxError;
 use tracing::Instrument;
 
-use crate::{db::schema, state, RequestExt};
-
-const ABCI_INFO_VERSION: &str = env!("VERGEN_GIT_SEMVER");
+use crate::{db::schema, state, BuildInfo, RequestExt};
 
 #[derive(Clone, Debug)]
 pub struct Info {
@@ -33,9 +31,12 @@ impl Info {
             None => (0u32.into(), vec![0; 32].into()),
         };
 
+        // The full build info goes in the free-form data, so that operators can see exactly what
+        // code the node is running from Tendermint's `/abci_info`.
+        let build_info = BuildInfo::current();
         Ok(abci::response::Info {
-            data: "penumbra".to_string(),
-            version: ABCI_INFO_VERSION.to_string(),
+            data: serde_json::to_string(&build_info)?,
+            version: build_info.version.to_string(),
             app_version: 1,
             last_block_height,
             last_block_app_hash,
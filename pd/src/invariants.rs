@@ -0,0 +1,156 @@
+//! Checks of chain state invariants that are too expensive to run on every block.
+
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use anyhow::{anyhow, Context, Result};
+use penumbra_crypto::Amount;
+use penumbra_stake::IdentityKey;
+
+use crate::state;
+
+/// When to check the chain state invariants.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InvariantChecks {
+    /// Never check the invariants.
+    Never,
+    /// Check the invariants after committing the last block of each epoch, halting if any of them
+    /// are violated.
+    Epoch,
+}
+
+impl Default for InvariantChecks {
+    fn default() -> Self {
+        InvariantChecks::Never
+    }
+}
+
+impl FromStr for InvariantChecks {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "never" => Ok(InvariantChecks::Never),
+            "epoch" => Ok(InvariantChecks::Epoch),
+            _ => Err(anyhow!("unknown invariant check mode {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for InvariantChecks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            InvariantChecks::Never => "never",
+            InvariantChecks::Epoch => "epoch",
+        })
+    }
+}
+
+impl state::Reader {
+    /// Checks the chain state invariants as of the latest committed block, at `height`, returning
+    /// a description of each violation.
+    ///
+    /// This should only be called at an epoch boundary, when validator statuses have just been
+    /// recomputed.
+    pub async fn check_invariants(&self, height: u64) -> Result<Vec<String>> {
+        let mut violations = Vec::new();
+
+        // Each validator's voting power must be computed from the total supply of its delegation
+        // tokens, including those for identity keys it has migrated away from.
+        let next_rate_data = self.next_rate_data().await?;
+        let mut token_identity_keys = next_rate_data
+            .keys()
+            .map(|identity_key| (identity_key.clone(), vec![identity_key.clone()]))
+            .collect::<BTreeMap<IdentityKey, Vec<IdentityKey>>>();
+        let migrations = self.validator_migrations().await?;
+        for old_identity_key in migrations.keys() {
+            let mut identity_key = old_identity_key;
+            while let Some((new_identity_key, _)) = migrations.get(identity_key) {
+                identity_key = new_identity_key;
+            }
+            match token_identity_keys.get_mut(identity_key) {
+                Some(keys) => keys.push(old_identity_key.clone()),
+                None => violations.push(format!(
+                    "identity key {} migrated to {}, which has no rate data",
+                    old_identity_key, identity_key
+                )),
+            }
+        }
+
+        let voting_powers = self
+            .validator_info(true)
+            .await?
+            .into_iter()
+            .map(|info| (info.validator.identity_key, info.status.voting_power))
+            .collect::<BTreeMap<_, _>>();
+        for (identity_key, rate_data) in &next_rate_data {
+            let base_rate_data = self.base_rate_data(rate_data.epoch_index).await?;
+            let mut delegation_token_supply = Amount::ZERO;
+            for token_identity_key in &token_identity_keys[identity_key] {
+                let supply = self
+                    .total_supply(token_identity_key.delegation_token().id())
+                    .await?
+                    .unwrap_or_default();
+                delegation_token_supply = delegation_token_supply
+                    .checked_add(supply)
+                    .context("delegation token supply overflow")?;
+            }
+            let expected = rate_data.voting_power(delegation_token_supply, &base_rate_data)?;
+            match voting_powers.get(identity_key) {
+                Some(&voting_power) if voting_power == expected => {}
+                Some(&voting_power) => violations.push(format!(
+                    "validator {} has voting power {}, but its delegation token supply of {} at \
+                     exchange rate {} gives {}",
+                    identity_key,
+                    voting_power,
+                    delegation_token_supply,
+                    rate_data.validator_exchange_rate,
+                    expected
+                )),
+                None => violations.push(format!(
+                    "validator {} has rate data but no status",
+                    identity_key
+                )),
+            }
+        }
+
+        // A quarantined note must not also have been inserted into the note commitment tree, and
+        // no note or nullifier can be quarantined for longer than the unbonding period.
+        let quarantined_notes_in_nct = self.quarantined_notes_in_nct().await?;
+        if quarantined_notes_in_nct > 0 {
+            violations.push(format!(
+                "{} quarantined notes are also in the note commitment tree",
+                quarantined_notes_in_nct
+            ));
+        }
+        let max_unbonding_height = {
+            let chain_params = self.chain_params_rx().borrow();
+            height + chain_params.epoch_duration * chain_params.unbonding_epochs
+        };
+        if let Some(unbonding_height) = self.max_quarantine_unbonding_height().await? {
+            if unbonding_height > max_unbonding_height {
+                violations.push(format!(
+                    "quarantine has unbonding height {}, after the unbonding period ends at {}",
+                    unbonding_height, max_unbonding_height
+                ));
+            }
+        }
+
+        // Every note in the notes table must be in the note commitment tree, and vice versa.
+        let tree_size = self
+            .note_commitment_tree()
+            .await?
+            .bridges()
+            .last()
+            .map(|b| u64::from(b.frontier().position()) + 1)
+            .unwrap_or(0);
+        let note_count = self.metrics().await?.note_count;
+        if tree_size != note_count {
+            violations.push(format!(
+                "note commitment tree has {} leaves, but there are {} notes",
+                tree_size, note_count
+            ));
+        }
+
+        Ok(violations)
+    }
+}
@@ -0,0 +1,180 @@
+//! Answers requests to the unversioned client services with their versioned replacements.
+
+use std::task::{Context, Poll};
+
+use http::{uri::PathAndQuery, Uri};
+use tower::{Layer, Service};
+
+/// The unversioned client services, by their gRPC names, and the versioned services that replaced
+/// them, which take the same requests and give the same responses.
+const LEGACY_SERVICES: &[(&str, &str)] = &[
+    (
+        "penumbra.light_wallet.LightWallet",
+        "penumbra.client.v1alpha1.LightWallet",
+    ),
+    (
+        "penumbra.thin_wallet.ThinWallet",
+        "penumbra.client.v1alpha1.ThinWallet",
+    ),
+    (
+        "penumbra.broadcast.Broadcast",
+        "penumbra.client.v1alpha1.Broadcast",
+    ),
+];
+
+/// Routes requests to an unversioned client service to the versioned service that replaced it, so
+/// that clients built before the services were versioned keep working.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LegacyServicesLayer;
+
+impl<S> Layer<S> for LegacyServicesLayer {
+    type Service = LegacyServices<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LegacyServices { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct LegacyServices<S> {
+    inner: S,
+}
+
+impl<S, B> Service<http::Request<B>> for LegacyServices<S>
+where
+    S: Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        if let Some(path) = versioned_path(req.uri().path()) {
+            tracing::trace!(from = %req.uri().path(), to = %path, "routing legacy request");
+            let mut parts = req.uri().clone().into_parts();
+            parts.path_and_query = Some(path);
+            *req.uri_mut() = Uri::from_parts(parts).expect("only the path was changed");
+        }
+        self.inner.call(req)
+    }
+}
+
+/// Returns the path of the versioned method that replaced the unversioned one at `path`, if it was
+/// replaced.
+fn versioned_path(path: &str) -> Option<PathAndQuery> {
+    let (service, method) = path.strip_prefix('/')?.split_once('/')?;
+    let (_, versioned) = LEGACY_SERVICES
+        .iter()
+        .find(|(legacy, _)| *legacy == service)?;
+    format!("/{}/{}", versioned, method).parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use penumbra_proto::{
+        broadcast::{
+            broadcast_client::BroadcastClient as LegacyBroadcastClient, AnchorWindowRequest,
+            AnchorWindowResponse, BroadcastTransactionRequest, BroadcastTransactionResponse,
+        },
+        client::v1alpha1::{
+            broadcast_client::BroadcastClient,
+            broadcast_server::{Broadcast, BroadcastServer},
+            light_wallet_server::LightWalletServer,
+            thin_wallet_server::ThinWalletServer,
+        },
+    };
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::{transport::NamedService, Status};
+
+    use super::*;
+    use crate::state;
+
+    #[test]
+    fn legacy_services_map_to_served_services() {
+        for served in [
+            <LightWalletServer<state::Reader> as NamedService>::NAME,
+            <ThinWalletServer<state::Reader> as NamedService>::NAME,
+            <BroadcastServer<crate::Broadcast> as NamedService>::NAME,
+        ] {
+            assert!(LEGACY_SERVICES
+                .iter()
+                .any(|(_, versioned)| *versioned == served));
+        }
+        assert_eq!(
+            versioned_path("/penumbra.thin_wallet.ThinWallet/NoteWitnesses")
+                .unwrap()
+                .as_str(),
+            "/penumbra.client.v1alpha1.ThinWallet/NoteWitnesses"
+        );
+        assert!(versioned_path("/penumbra.client.v1alpha1.ThinWallet/NoteWitnesses").is_none());
+        assert!(versioned_path("/penumbra.admin.Admin/Snapshot").is_none());
+    }
+
+    /// A broadcast service that only describes a fixed anchor window.
+    struct FixedAnchorWindow;
+
+    #[tonic::async_trait]
+    impl Broadcast for FixedAnchorWindow {
+        async fn broadcast_transaction(
+            &self,
+            _request: tonic::Request<BroadcastTransactionRequest>,
+        ) -> Result<tonic::Response<BroadcastTransactionResponse>, Status> {
+            Err(Status::unimplemented("not broadcasting"))
+        }
+
+        async fn anchor_window(
+            &self,
+            request: tonic::Request<AnchorWindowRequest>,
+        ) -> Result<tonic::Response<AnchorWindowResponse>, Status> {
+            Ok(tonic::Response::new(AnchorWindowResponse {
+                height: 7,
+                window_size: 256,
+                valid_within: request.into_inner().within_blocks <= 256,
+                ..Default::default()
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn legacy_clients_reach_versioned_services() {
+        let listener = tokio::net::TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .layer(LegacyServicesLayer)
+                .add_service(BroadcastServer::new(FixedAnchorWindow))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        let request = AnchorWindowRequest {
+            within_blocks: 10,
+            ..Default::default()
+        };
+        let legacy = LegacyBroadcastClient::connect(addr.clone())
+            .await
+            .unwrap()
+            .anchor_window(request.clone())
+            .await
+            .unwrap()
+            .into_inner();
+        let versioned = BroadcastClient::connect(addr)
+            .await
+            .unwrap()
+            .anchor_window(request)
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(legacy, versioned);
+        assert_eq!(legacy.height, 7);
+        assert!(legacy.valid_within);
+    }
+}
@@ -4,28 +4,63 @@
 #![recursion_limit = "512"]
 #![allow(clippy::clone_on_copy)]
 
+mod broadcast;
+mod build_info;
+mod changefeed;
+mod circuit_breaker;
+mod component;
 mod consensus;
 mod db;
 mod info;
+mod invariants;
+mod legacy_services;
+mod live_config;
 mod mempool;
 mod pd_metrics;
 mod pending_block;
+mod rate_limit;
 mod request_ext;
+mod response_code;
+mod scanning;
 mod snapshot;
+mod trace_context;
 mod verify;
 mod wallet;
 
+pub mod admin;
+pub mod crash_report;
+pub mod epoch;
 pub mod genesis;
+pub mod grpc;
+pub mod log_file;
 pub mod state;
+pub mod supervisor;
 pub mod testnet;
 
+pub use broadcast::Broadcast;
+pub use build_info::BuildInfo;
+pub use circuit_breaker::{CircuitBreaker, CIRCUIT_BREAKER_CODE};
 pub use consensus::Consensus;
 pub use info::Info;
+pub use invariants::InvariantChecks;
+pub use legacy_services::LegacyServicesLayer;
+pub use live_config::{LiveConfig, LogFilterHandle, NodeConfig};
 pub use mempool::Mempool;
 pub use pd_metrics::register_all_metrics;
 use pending_block::PendingBlock;
+pub use pending_block::PendingBlockSummary;
+pub use rate_limit::RateLimitLayer;
 use request_ext::RequestExt;
+pub use scanning::Scanning;
 pub use snapshot::Snapshot;
+pub use trace_context::{TraceContexts, TraceParent};
+pub use verify::StatelessCache;
 
 /// The age limit, in blocks, on anchors accepted in transaction verification.
 pub const NUM_RECENT_ANCHORS: usize = 256;
+
+/// The newest compact block format this node serves; older clients are served older formats.
+pub const COMPACT_BLOCK_VERSION: u32 = 1;
+
+/// The maximum number of stateless verification results cached between `CheckTx` and `DeliverTx`.
+pub const STATELESS_CACHE_SIZE: usize = 4096;
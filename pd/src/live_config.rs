@@ -0,0 +1,159 @@
+//! Configuration that doesn't affect consensus, and so can be changed while `pd` is running.
+//!
+//! The configuration is read from a JSON file given with `--config`, and read again when `pd`
+//! receives `SIGHUP` or a `ReloadConfig` request on the admin service.  A file that fails to parse
+//! or validate is rejected as a whole, and the previous configuration stays in effect.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+/// A handle for replacing the filter of the global tracing subscriber.
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
+
+/// The settings that can be changed without restarting `pd`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeConfig {
+    /// Which logs to record, in the syntax of `RUST_LOG`, or `None` to use `RUST_LOG`.
+    pub log_filter: Option<String>,
+    /// The most requests per second each of the wallet, changefeed and scanning services
+    /// accepts, or `None` for no limit.  Requests over the limit fail with `RESOURCE_EXHAUSTED`.
+    pub rpc_rate_limit: Option<u32>,
+    /// Update the database size and cache metrics every this many blocks, or never if zero.
+    /// Estimating table sizes is not free on a large database.
+    pub storage_metrics_interval: u64,
+    /// Write a state snapshot into the snapshot directory at every height divisible by this, as
+    /// `pd admin snapshot` does, or never if `None`.
+    pub snapshot_interval: Option<u64>,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self {
+            log_filter: None,
+            rpc_rate_limit: None,
+            storage_metrics_interval: 1,
+            snapshot_interval: None,
+        }
+    }
+}
+
+impl NodeConfig {
+    /// Reads and validates the configuration file at `path`.
+    pub fn read(path: &Path) -> Result<Self> {
+        let config: NodeConfig = serde_json::from_slice(
+            &std::fs::read(path).with_context(|| format!("could not read {}", path.display()))?,
+        )
+        .with_context(|| format!("invalid config file {}", path.display()))?;
+        config.log_filter()?;
+        if config.rpc_rate_limit == Some(0) {
+            return Err(anyhow::anyhow!(
+                "rpc_rate_limit must be positive; omit it to disable rate limiting"
+            ));
+        }
+        if config.snapshot_interval == Some(0) {
+            return Err(anyhow::anyhow!(
+                "snapshot_interval must be positive; omit it to disable periodic snapshots"
+            ));
+        }
+        Ok(config)
+    }
+
+    fn log_filter(&self) -> Result<EnvFilter> {
+        match &self.log_filter {
+            Some(directives) => EnvFilter::try_new(directives)
+                .with_context(|| format!("invalid log_filter {:?}", directives)),
+            None => Ok(EnvFilter::from_default_env()),
+        }
+    }
+}
+
+/// The current [`NodeConfig`], shared by every part of `pd` it configures.
+///
+/// Clones of a `LiveConfig` share the same configuration.  The default has no configuration
+/// file, so it never changes.
+#[derive(Clone, Default)]
+pub struct LiveConfig {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    path: Option<PathBuf>,
+    current: RwLock<NodeConfig>,
+    log_filter: Option<LogFilterHandle>,
+}
+
+impl LiveConfig {
+    /// Reads the configuration from `path`, if any, and applies its log filter with
+    /// `log_filter`.
+    pub fn load(path: Option<PathBuf>, log_filter: Option<LogFilterHandle>) -> Result<Self> {
+        let current = match &path {
+            Some(path) => NodeConfig::read(path)?,
+            None => NodeConfig::default(),
+        };
+        let config = Self {
+            inner: Arc::new(Inner {
+                path,
+                current: RwLock::new(NodeConfig::default()),
+                log_filter,
+            }),
+        };
+        config.apply(current)?;
+        Ok(config)
+    }
+
+    /// The configuration currently in effect.
+    pub fn current(&self) -> NodeConfig {
+        self.inner.current.read().unwrap().clone()
+    }
+
+    pub fn rpc_rate_limit(&self) -> Option<u32> {
+        self.inner.current.read().unwrap().rpc_rate_limit
+    }
+
+    /// Reads the configuration file again, and applies it if it's valid.
+    pub fn reload(&self) -> Result<NodeConfig> {
+        let path = self
+            .inner
+            .path
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("pd was started without --config"))?;
+        let config = NodeConfig::read(path)?;
+        self.apply(config.clone())?;
+        Ok(config)
+    }
+
+    fn apply(&self, config: NodeConfig) -> Result<()> {
+        let previous = self.current();
+        if let Some(handle) = &self.inner.log_filter {
+            if config.log_filter != previous.log_filter {
+                handle.reload(config.log_filter()?)?;
+            }
+        }
+        if config != previous {
+            tracing::info!(?previous, current = ?config, "applied config");
+        }
+        *self.inner.current.write().unwrap() = config;
+        Ok(())
+    }
+
+    /// Reloads the configuration whenever `pd` receives `SIGHUP`, logging any errors.
+    pub async fn reload_on_sighup(self) -> Result<()> {
+        let mut hangups = signal(SignalKind::hangup())?;
+        while hangups.recv().await.is_some() {
+            tracing::info!("received SIGHUP, reloading config");
+            if let Err(e) = self.reload() {
+                tracing::error!(?e, "could not reload config, keeping the previous one");
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+//! Logging to a file that can be reopened, for log rotation.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, Result};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// A log file, appended to by every clone.
+///
+/// Log rotation tools move the file aside and then ask the process to reopen it, so that it
+/// starts writing to a fresh file at the original path; [`LogFile::reopen`] does the latter.
+#[derive(Clone, Debug)]
+pub struct LogFile {
+    path: PathBuf,
+    // We never hold this lock across an await point, so a blocking mutex is fine.
+    file: Arc<Mutex<File>>,
+}
+
+impl LogFile {
+    /// Opens the log file at `path` for appending, creating it if it doesn't exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = Self::open_file(&path)?;
+        Ok(Self {
+            path,
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// The path of the log file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reopens the log file, so that subsequent logs go to whatever file is now at its path.
+    pub fn reopen(&self) -> Result<()> {
+        let file = Self::open_file(&self.path)?;
+        *self.file.lock().unwrap() = file;
+        Ok(())
+    }
+
+    fn open_file(path: &Path) -> Result<File> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("could not open log file {}", path.display()))
+    }
+}
+
+impl MakeWriter for LogFile {
+    type Writer = LogFileWriter;
+
+    fn make_writer(&self) -> Self::Writer {
+        LogFileWriter(self.file.clone())
+    }
+}
+
+/// A writer for a single log line, which holds the log file's lock only while writing.
+pub struct LogFileWriter(Arc<Mutex<File>>);
+
+impl Write for LogFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap().flush()
+    }
+}
@@ -2,19 +2,25 @@
 use std::{
     net::{Ipv4Addr, SocketAddr},
     path::PathBuf,
+    time::Duration,
 };
 
 use metrics_exporter_prometheus::PrometheusBuilder;
 use penumbra_chain::params::ChainParams;
 use penumbra_crypto::rdsa::{SigningKey, SpendAuth, VerificationKey};
 use penumbra_proto::{
-    light_wallet::light_wallet_server::LightWalletServer,
-    thin_wallet::thin_wallet_server::ThinWalletServer,
+    changefeed::changefeed_server::ChangefeedServer,
+    client::v1alpha1::{
+        broadcast_server::BroadcastServer, light_wallet_server::LightWalletServer,
+        thin_wallet_server::ThinWalletServer,
+    },
+    epoch_report::epoch_reports_server::EpochReportsServer,
+    scanning::scanning_server::ScanningServer,
 };
-use penumbra_stake::{FundingStream, FundingStreams, Validator};
+use penumbra_stake::{FundingStream, FundingStreamRecipient, FundingStreams, Validator};
 use rand_core::OsRng;
 use structopt::StructOpt;
-use tonic::transport::Server;
+use tracing_subscriber::prelude::*;
 
 #[derive(Debug, StructOpt)]
 #[structopt(
@@ -47,9 +53,191 @@ enum Command {
         /// Bind the thin wallet service to this port.
         #[structopt(short, long, default_value = "26667")]
         thin_wallet_port: u16,
+        /// Bind the thin wallet service to this host, rather than the one given by `--host`.
+        ///
+        /// The thin wallet service answers specific queries, which reveal what
+        /// a client is interested in, so operators may want to expose only the
+        /// light wallet service publicly and keep this one on a private interface.
+        #[structopt(long)]
+        thin_wallet_host: Option<String>,
+        /// Don't serve the thin wallet service at all.
+        #[structopt(long)]
+        disable_thin_wallet: bool,
+        /// Record the writes of each block in a changefeed, and serve it on this port, for
+        /// followers and indexers to stay in sync without re-executing blocks.
+        ///
+        /// Only blocks committed while this is set are recorded.
+        #[structopt(long)]
+        changefeed_port: Option<u16>,
+        /// How many blocks of writes to keep in the changefeed; followers that fall further
+        /// behind than this must start again from a copy of the database.
+        #[structopt(long, default_value = "100000")]
+        changefeed_retention: u64,
+        /// Serve the scanning service on this port, for the clients listed in
+        /// `--scanning-clients`.
+        ///
+        /// The scanning service trial-decrypts blocks with the clients' full viewing keys, so
+        /// it's only for clients who trust this node with everything about their activity.
+        #[structopt(long, requires = "scanning-clients")]
+        scanning_port: Option<u16>,
+        /// A JSON file listing the `token` and `full_viewing_key` of each client of the scanning
+        /// service.
+        #[structopt(long, parse(from_os_str), requires = "scanning-port")]
+        scanning_clients: Option<PathBuf>,
         /// Bind the metrics endpoint to this port.
         #[structopt(short, long, default_value = "9000")]
         metrics_port: u16,
+        /// Persist transactions accepted into the mempool, and rebroadcast them after a restart.
+        #[structopt(long)]
+        persist_mempool: bool,
+        /// The URL of Tendermint's RPC endpoint, used to broadcast transactions submitted to the
+        /// broadcast service and to rebroadcast persisted transactions.
+        ///
+        /// With `--with-tendermint`, the supervised Tendermint serves its RPC endpoint here.
+        #[structopt(long, default_value = "http://127.0.0.1:26657")]
+        tendermint_rpc: String,
+        /// Run Tendermint as a child process, restarting it if it exits, rather than expecting
+        /// it to be run separately.
+        #[structopt(long)]
+        with_tendermint: bool,
+        /// The Tendermint binary to run with `--with-tendermint`.
+        #[structopt(long, parse(from_os_str), default_value = "tendermint")]
+        tendermint_bin: PathBuf,
+        /// Tendermint's home directory with `--with-tendermint`, initialized if it doesn't exist.
+        #[structopt(long, default_value = "~/.penumbra/tendermint")]
+        tendermint_home: String,
+        /// The address Tendermint's P2P server listens on with `--with-tendermint`.
+        #[structopt(long, default_value = "0.0.0.0:26656")]
+        tendermint_p2p_addr: String,
+        /// The moniker written into a newly initialized Tendermint config with `--with-tendermint`.
+        #[structopt(long, default_value = "penumbra-node")]
+        moniker: String,
+        /// If set, write a crash report into this directory if `pd` panics.
+        #[structopt(long, parse(from_os_str))]
+        crash_report_dir: Option<PathBuf>,
+        /// When to check expensive invariants of the chain state: `never`, or `epoch` to check
+        /// them at each epoch boundary and halt if any are violated.
+        #[structopt(long, default_value = "never")]
+        invariant_checks: pd::InvariantChecks,
+        /// Start in emergency mode, keeping every transaction that moves value out of this
+        /// node's mempool (with response code 2).
+        ///
+        /// This doesn't change which transactions are valid in a block; that is up to the
+        /// chain's emergency halt height, which can only be set at genesis.
+        #[structopt(long)]
+        emergency_mode: bool,
+        /// Write logs to this file rather than to stdout.
+        ///
+        /// The file can be rotated by moving it aside and running `pd admin rotate-logs`.
+        #[structopt(long, parse(from_os_str))]
+        log_file: Option<PathBuf>,
+        /// Serve the admin service on a Unix domain socket at this path.
+        ///
+        /// The socket is only accessible to the user running `pd`.  The admin service is
+        /// disabled unless this is set.
+        #[structopt(long, parse(from_os_str))]
+        admin_socket: Option<PathBuf>,
+        /// The directory the admin service writes state snapshots into.
+        #[structopt(long, default_value = "~/.penumbra/snapshots")]
+        snapshot_dir: String,
+        /// For local devnets: replace the epoch duration in the genesis file with this number of
+        /// blocks, so that the staking lifecycle can be exercised in minutes.
+        ///
+        /// This only takes effect when the chain is initialized, and is refused on public chain
+        /// IDs.
+        #[structopt(long)]
+        epoch_duration_override: Option<u64>,
+        /// For local devnets: with `--with-tendermint`, initialize Tendermint's config to produce
+        /// blocks as quickly as possible.
+        ///
+        /// This is refused if the genesis file is for a public chain ID.
+        #[structopt(long)]
+        fast_blocks: bool,
+        /// Write a JSON report of the outcome of each epoch boundary into this directory, and
+        /// serve the latest one alongside the light wallet service.
+        #[structopt(long, parse(from_os_str))]
+        epoch_report_dir: Option<PathBuf>,
+        /// Sign epoch reports with the validator identity key in this `validator_signingkey.json`
+        /// file.
+        #[structopt(long, parse(from_os_str), requires = "epoch-report-dir")]
+        epoch_report_signing_key: Option<PathBuf>,
+        /// Read settings that can be changed without restarting, like the log filter and RPC
+        /// rate limit, from this JSON file, and read it again on `SIGHUP` or
+        /// `pd admin reload-config`.
+        #[structopt(long, parse(from_os_str))]
+        config: Option<PathBuf>,
+        #[structopt(flatten)]
+        grpc: pd::grpc::GrpcOptions,
+    },
+
+    /// Serve only the wallet services, from a read-only copy of another `pd`'s database.
+    ///
+    /// Replicas don't run consensus, so any number of them can serve wallet clients from Postgres
+    /// streaming replicas of a single node's database, or from their own databases kept in sync
+    /// with `--follow`.  Replicas of a read-only database poll it for new blocks, so they may lag
+    /// the node by up to `--poll-ms`.
+    Replica {
+        /// The URI used to connect to the Postgres database, which is only ever read, unless
+        /// `--follow` is set.
+        #[structopt(short, long)]
+        database_uri: String,
+        /// Keep the database in sync by following the changefeed served at this URL by another
+        /// `pd`, rather than relying on Postgres replication.
+        #[structopt(long)]
+        follow: Option<String>,
+        /// Bind the services to this host.
+        #[structopt(short, long, default_value = "127.0.0.1")]
+        host: String,
+        /// Bind the light wallet service to this port.
+        #[structopt(short, long, default_value = "26666")]
+        light_wallet_port: u16,
+        /// Bind the thin wallet service to this port.
+        #[structopt(short, long, default_value = "26667")]
+        thin_wallet_port: u16,
+        /// Bind the thin wallet service to this host, rather than the one given by `--host`.
+        #[structopt(long)]
+        thin_wallet_host: Option<String>,
+        /// Don't serve the thin wallet service at all.
+        #[structopt(long)]
+        disable_thin_wallet: bool,
+        /// Bind the metrics endpoint to this port.
+        #[structopt(short, long, default_value = "9000")]
+        metrics_port: u16,
+        /// Serve the database's changefeed on this port, so that this replica can be followed in
+        /// turn.
+        #[structopt(long)]
+        changefeed_port: Option<u16>,
+        /// How many blocks of writes to keep in this database's changefeed, when following
+        /// another `pd`'s.
+        #[structopt(long, default_value = "100000")]
+        changefeed_retention: u64,
+        /// Serve the scanning service on this port, for the clients listed in
+        /// `--scanning-clients`.
+        #[structopt(long, requires = "scanning-clients")]
+        scanning_port: Option<u16>,
+        /// A JSON file listing the `token` and `full_viewing_key` of each client of the scanning
+        /// service.
+        #[structopt(long, parse(from_os_str), requires = "scanning-port")]
+        scanning_clients: Option<PathBuf>,
+        /// How often to poll the database for new blocks, in milliseconds, unless following a
+        /// changefeed.
+        #[structopt(long, default_value = "500")]
+        poll_ms: u64,
+        /// Read the log filter and RPC rate limit from this JSON file, as with `pd start`, and
+        /// read it again on `SIGHUP`.
+        #[structopt(long, parse(from_os_str))]
+        config: Option<PathBuf>,
+        #[structopt(flatten)]
+        grpc: pd::grpc::GrpcOptions,
+    },
+
+    /// Operate a running `pd` through its admin service.
+    Admin {
+        /// The path of the admin socket, as given to `pd start --admin-socket`.
+        #[structopt(long, parse(from_os_str))]
+        socket: PathBuf,
+        #[structopt(subcommand)]
+        cmd: AdminCommand,
     },
 
     /// Generates a directory structure containing necessary files to run a
@@ -89,9 +277,101 @@ enum Command {
         /// IP Address to start `tendermint` nodes on. Increments by three to make room for `pd` and `postgres` per node.
         #[structopt(short, long, default_value = "192.167.10.2")]
         starting_ip: Ipv4Addr,
+        /// Configure each node's Tendermint to listen on this address (e.g.
+        /// `tcp://0.0.0.0:26659`) for a remote signer holding its consensus key.
+        ///
+        /// The consensus keys are then written to a `remote_signer` directory beside each node's
+        /// config, to be imported into the signer, rather than into the node's config.
+        #[structopt(long)]
+        priv_validator_laddr: Option<String>,
+        /// For local devnets: configure Tendermint to produce blocks as quickly as possible.
+        ///
+        /// This is refused on public chain IDs.
+        #[structopt(long)]
+        fast_blocks: bool,
+    },
+
+    /// Generate and inspect validator keys.
+    Keys(KeysCommand),
+
+    /// Debugging tools that run parts of the state machine outside of consensus.
+    Debug(DebugCommand),
+}
+
+#[derive(Debug, StructOpt)]
+enum DebugCommand {
+    /// Simulate the end of an epoch from a state snapshot, and print the resulting rates and
+    /// validator statuses.
+    ///
+    /// The simulation ends the epoch the snapshot was taken in, as if the rest of the epoch had
+    /// no further delegations other than those given with `--delegation-changes`.  Nothing is
+    /// written to any database, so this can be run repeatedly on the same snapshot.
+    SimulateEpoch {
+        /// A state snapshot, as written by `pd admin snapshot`.
+        #[structopt(long, parse(from_os_str))]
+        snapshot: PathBuf,
+        /// A JSON file with additional net delegations to include, as an object mapping
+        /// validator identity keys to signed amounts of delegation tokens, e.g.
+        /// `{ "penumbravalid1...": 1000, "penumbravalid1...": -500 }`.
+        #[structopt(long, parse(from_os_str))]
+        delegation_changes: Option<PathBuf>,
     },
 }
 
+/// A validator has two keys:
+///
+/// - its *identity key*, a `decaf377-rdsa` spend authorization key, which identifies the
+///   validator to delegators and signs its validator definitions;
+/// - its *consensus key*, an Ed25519 key used by Tendermint to sign blocks, which can be kept in
+///   a remote signer rather than on the node itself.
+#[derive(Debug, StructOpt)]
+enum KeysCommand {
+    /// Generate a new identity key and consensus key.
+    Generate {
+        /// Write `validator_signingkey.json` and `priv_validator_key.json` into this directory,
+        /// which must not already contain them.
+        #[structopt(short, long, parse(from_os_str))]
+        output_dir: PathBuf,
+    },
+    /// Show the public keys, as they appear in validator definitions, for the given key files.
+    Show {
+        /// A `validator_signingkey.json` file holding an identity signing key.
+        #[structopt(long, parse(from_os_str))]
+        identity_key_file: Option<PathBuf>,
+        /// A Tendermint `priv_validator_key.json` file holding a consensus key.
+        #[structopt(long, parse(from_os_str))]
+        consensus_key_file: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum AdminCommand {
+    /// Write a snapshot of the staking state at the latest committed height.
+    Snapshot,
+    /// Reopen the log file, after it has been moved aside.
+    RotateLogs,
+    /// Show the block currently being processed, if any.
+    PendingBlock,
+    /// Trip or reset the emergency mode circuit breaker, which keeps transactions that move
+    /// value out of this node's mempool.
+    CircuitBreaker {
+        /// Trip the circuit breaker, rejecting transactions that move value.
+        #[structopt(long, conflicts_with = "reset", required_unless = "reset")]
+        trip: bool,
+        /// Reset the circuit breaker, accepting transactions again.
+        #[structopt(long)]
+        reset: bool,
+    },
+    /// Show the depths of the node's internal queues.
+    QueueDepths,
+    /// Show how many rows of each kind the most recently committed blocks wrote, e.g. to find
+    /// what is growing the database.
+    BlockWrites,
+    /// Read the `--config` file again and apply it, as on `SIGHUP`, and show the config now in
+    /// effect.
+    ReloadConfig,
+}
+
 // Extracted from tonic's remote_addr implementation; we'd like to instrument
 // spans with the remote addr at the server level rather than at the individual
 // request level, but the hook available to do that gives us an http::Request
@@ -105,11 +385,140 @@ fn remote_addr(req: &http::Request<()>) -> Option<SocketAddr> {
         .and_then(|i| i.remote_addr())
 }
 
+/// Serves the light wallet service on `addr`, along with the broadcast service, if there is one.
+async fn serve_light_wallet(
+    state_reader: pd::state::Reader,
+    addr: String,
+    broadcast: Option<pd::Broadcast>,
+    epoch_reports: Option<pd::epoch::report::EpochReports>,
+    grpc: pd::grpc::GrpcOptions,
+    config: pd::LiveConfig,
+) -> Result<(), tonic::transport::Error> {
+    let router = grpc
+        .server()
+        .trace_fn(|req| match remote_addr(req) {
+            Some(remote_addr) => tracing::error_span!("light_wallet", ?remote_addr),
+            None => tracing::error_span!("light_wallet"),
+        })
+        .layer(pd::RateLimitLayer::new(config))
+        .layer(pd::LegacyServicesLayer)
+        // Compact blocks are streamed in bulk during sync, so
+        // compress them for clients that can accept it.
+        .add_service(
+            LightWalletServer::new(state_reader)
+                .send_gzip()
+                .accept_gzip(),
+        )
+        .add_optional_service(epoch_reports.map(EpochReportsServer::new));
+    let addr = addr.parse().expect("this is a valid address");
+    match broadcast {
+        Some(broadcast) => {
+            router
+                .add_service(BroadcastServer::new(broadcast))
+                .serve(addr)
+                .await
+        }
+        None => router.serve(addr).await,
+    }
+}
+
+/// Serves the thin wallet service on `addr`, or never finishes if there isn't one, so that pd
+/// keeps running the other services.
+async fn serve_thin_wallet(
+    state_reader: pd::state::Reader,
+    addr: Option<String>,
+    grpc: pd::grpc::GrpcOptions,
+    config: pd::LiveConfig,
+) -> Result<(), tonic::transport::Error> {
+    let addr = match addr {
+        Some(addr) => addr,
+        None => return std::future::pending().await,
+    };
+    grpc.server()
+        // Unlike the light wallet service, don't record the
+        // client's address, so that the logs don't link
+        // clients to the specific notes and assets they asked about.
+        .trace_fn(|_| tracing::error_span!("thin_wallet"))
+        .layer(pd::RateLimitLayer::new(config))
+        .layer(pd::LegacyServicesLayer)
+        .add_service(ThinWalletServer::new(state_reader))
+        .serve(addr.parse().expect("this is a valid address"))
+        .await
+}
+
+/// Serves the changefeed service on `addr`, or never finishes if there isn't one.
+async fn serve_changefeed(
+    state_reader: pd::state::Reader,
+    addr: Option<String>,
+    grpc: pd::grpc::GrpcOptions,
+    config: pd::LiveConfig,
+) -> Result<(), tonic::transport::Error> {
+    let addr = match addr {
+        Some(addr) => addr,
+        None => return std::future::pending().await,
+    };
+    grpc.server()
+        .trace_fn(|req| match remote_addr(req) {
+            Some(remote_addr) => tracing::error_span!("changefeed", ?remote_addr),
+            None => tracing::error_span!("changefeed"),
+        })
+        .layer(pd::RateLimitLayer::new(config))
+        // Write batches repeat the same column names and values, so they compress well.
+        .add_service(
+            ChangefeedServer::new(state_reader)
+                .send_gzip()
+                .accept_gzip(),
+        )
+        .serve(addr.parse().expect("this is a valid address"))
+        .await
+}
+
+/// Serves the scanning service on `addr`, or never finishes if there isn't one.
+async fn serve_scanning(
+    scanning: Option<pd::Scanning>,
+    addr: Option<String>,
+    grpc: pd::grpc::GrpcOptions,
+    config: pd::LiveConfig,
+) -> Result<(), tonic::transport::Error> {
+    let (scanning, addr) = match (scanning, addr) {
+        (Some(scanning), Some(addr)) => (scanning, addr),
+        _ => return std::future::pending().await,
+    };
+    grpc.server()
+        // Like the thin wallet service, don't record the client's address.
+        .trace_fn(|_| tracing::error_span!("scanning"))
+        .layer(pd::RateLimitLayer::new(config))
+        .add_service(ScanningServer::new(scanning).send_gzip().accept_gzip())
+        .serve(addr.parse().expect("this is a valid address"))
+        .await
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
     let opt = Opt::from_args();
 
+    let log_file = match &opt.cmd {
+        Command::Start {
+            log_file: Some(path),
+            ..
+        } => Some(pd::log_file::LogFile::open(path)?),
+        _ => None,
+    };
+    // The filter can be replaced by reloading the config, so it's a layer of its own.
+    let (log_filter, log_filter_handle) =
+        tracing_subscriber::reload::Layer::new(tracing_subscriber::EnvFilter::from_default_env());
+    let registry = tracing_subscriber::registry().with(log_filter);
+    match &log_file {
+        Some(log_file) => registry
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_writer(log_file.clone())
+                    .with_ansi(false),
+            )
+            .init(),
+        None => registry.with(tracing_subscriber::fmt::layer()).init(),
+    }
+
     match opt.cmd {
         Command::Start {
             host,
@@ -117,7 +526,32 @@ async fn main() -> anyhow::Result<()> {
             abci_port,
             light_wallet_port,
             thin_wallet_port,
+            thin_wallet_host,
+            disable_thin_wallet,
+            changefeed_port,
+            changefeed_retention,
+            scanning_port,
+            scanning_clients,
             metrics_port,
+            persist_mempool,
+            tendermint_rpc,
+            with_tendermint,
+            tendermint_bin,
+            tendermint_home,
+            tendermint_p2p_addr,
+            moniker,
+            crash_report_dir,
+            invariant_checks,
+            emergency_mode,
+            log_file: _,
+            admin_socket,
+            snapshot_dir,
+            epoch_duration_override,
+            fast_blocks,
+            epoch_report_dir,
+            epoch_report_signing_key,
+            config,
+            grpc,
         } => {
             tracing::info!(
                 ?host,
@@ -125,13 +559,106 @@ async fn main() -> anyhow::Result<()> {
                 ?abci_port,
                 ?light_wallet_port,
                 ?thin_wallet_port,
+                ?thin_wallet_host,
+                ?disable_thin_wallet,
+                ?changefeed_port,
+                ?changefeed_retention,
+                ?scanning_port,
+                ?scanning_clients,
+                ?persist_mempool,
+                ?with_tendermint,
+                ?crash_report_dir,
+                %invariant_checks,
+                ?emergency_mode,
+                ?admin_socket,
+                ?epoch_duration_override,
+                ?fast_blocks,
+                ?epoch_report_dir,
+                ?config,
+                ?grpc,
                 "starting pd"
             );
+            let config = pd::LiveConfig::load(config, Some(log_filter_handle))?;
+            if epoch_duration_override == Some(0) {
+                return Err(anyhow::anyhow!(
+                    "--epoch-duration-override must be positive"
+                ));
+            }
+            if epoch_duration_override.is_some() && !fast_blocks {
+                tracing::info!(
+                    "epochs pass faster with short block intervals, e.g. with --fast-blocks \
+                     or a short timeout-commit in Tendermint's config"
+                );
+            }
+            if let Some(crash_report_dir) = crash_report_dir {
+                pd::crash_report::install(crash_report_dir);
+            }
+
             // Initialize state
-            let (state_reader, state_writer) = pd::state::new(&database_uri).await?;
+            let (state_reader, mut state_writer) = pd::state::new(&database_uri).await?;
+            if changefeed_port.is_some() {
+                state_writer = state_writer.with_changefeed(changefeed_retention);
+            }
+
+            // Shared between the consensus and mempool services, so that DeliverTx can
+            // skip stateless checks for transactions we already checked in CheckTx.
+            let stateless_cache = pd::StatelessCache::new(pd::STATELESS_CACHE_SIZE);
+            // Likewise, so that both reject transactions while in emergency mode.
+            let circuit_breaker = pd::CircuitBreaker::new(emergency_mode);
+            // Shared with the broadcast service too, so that both can record the trace
+            // contexts of transactions submitted through it.
+            let trace_contexts = pd::TraceContexts::default();
+
+            let epoch_reports = match epoch_report_dir {
+                Some(dir) => {
+                    let signing_key = match epoch_report_signing_key {
+                        Some(path) => Some(serde_json::from_slice::<SigningKey<SpendAuth>>(
+                            &std::fs::read(&path)?,
+                        )?),
+                        None => None,
+                    };
+                    Some(pd::epoch::report::EpochReports::new(dir, signing_key)?)
+                }
+                None => None,
+            };
+
+            let consensus = pd::Consensus::new(
+                state_writer,
+                stateless_cache.clone(),
+                trace_contexts.clone(),
+                invariant_checks,
+                circuit_breaker.clone(),
+                epoch_duration_override,
+                epoch_reports.clone(),
+                config.clone(),
+            )
+            .await?;
+            let admin = pd::admin::Admin {
+                state: state_reader.clone(),
+                consensus: consensus.clone(),
+                stateless_cache: stateless_cache.clone(),
+                circuit_breaker: circuit_breaker.clone(),
+                log_file,
+                snapshot_dir: pd::testnet::canonicalize_path(&snapshot_dir),
+                config: config.clone(),
+            };
+            let mut mempool = pd::Mempool::new(
+                state_reader.clone(),
+                stateless_cache,
+                trace_contexts.clone(),
+                circuit_breaker,
+            );
+            if persist_mempool {
+                mempool = mempool.with_persistence();
 
-            let consensus = pd::Consensus::new(state_writer).await?;
-            let mempool = pd::Mempool::new(state_reader.clone());
+                let mempool = mempool.clone();
+                let tendermint_rpc = tendermint_rpc.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = mempool.rebroadcast_persisted(tendermint_rpc).await {
+                        tracing::error!(?e, "could not rebroadcast persisted transactions");
+                    }
+                });
+            }
             let info = pd::Info::new(state_reader.clone());
             let snapshot = pd::Snapshot {};
 
@@ -146,32 +673,57 @@ async fn main() -> anyhow::Result<()> {
                     .listen(format!("{}:{}", host, abci_port)),
             );
 
-            let light_wallet_server = tokio::spawn(
-                Server::builder()
-                    .trace_fn(|req| match remote_addr(req) {
-                        Some(remote_addr) => tracing::error_span!("light_wallet", ?remote_addr),
-                        None => tracing::error_span!("light_wallet"),
-                    })
-                    .add_service(LightWalletServer::new(state_reader.clone()))
-                    .serve(
-                        format!("{}:{}", host, light_wallet_port)
-                            .parse()
-                            .expect("this is a valid address"),
-                    ),
-            );
-            let thin_wallet_server = tokio::spawn(
-                Server::builder()
-                    .trace_fn(|req| match remote_addr(req) {
-                        Some(remote_addr) => tracing::error_span!("thin_wallet", ?remote_addr),
-                        None => tracing::error_span!("thin_wallet"),
-                    })
-                    .add_service(ThinWalletServer::new(state_reader.clone()))
-                    .serve(
-                        format!("{}:{}", host, thin_wallet_port)
-                            .parse()
-                            .expect("this is a valid address"),
-                    ),
-            );
+            let broadcast = pd::Broadcast {
+                state: state_reader.clone(),
+                tendermint_rpc: tendermint_rpc.clone(),
+                trace_contexts,
+            };
+            let light_wallet_server = tokio::spawn(serve_light_wallet(
+                state_reader.clone(),
+                format!("{}:{}", host, light_wallet_port),
+                Some(broadcast),
+                epoch_reports,
+                grpc.clone(),
+                config.clone(),
+            ));
+            let thin_wallet_server = tokio::spawn(serve_thin_wallet(
+                state_reader.clone(),
+                (!disable_thin_wallet).then(|| {
+                    format!(
+                        "{}:{}",
+                        thin_wallet_host.unwrap_or_else(|| host.clone()),
+                        thin_wallet_port
+                    )
+                }),
+                grpc.clone(),
+                config.clone(),
+            ));
+            let changefeed_server = tokio::spawn(serve_changefeed(
+                state_reader.clone(),
+                changefeed_port.map(|port| format!("{}:{}", host, port)),
+                grpc.clone(),
+                config.clone(),
+            ));
+            let scanning = scanning_clients
+                .map(|path| pd::Scanning::load(state_reader.clone(), &path))
+                .transpose()?;
+            let scanning_server = tokio::spawn(serve_scanning(
+                scanning,
+                scanning_port.map(|port| format!("{}:{}", host, port)),
+                grpc.clone(),
+                config.clone(),
+            ));
+
+            tokio::spawn(admin.clone().write_periodic_snapshots());
+            tokio::spawn(config.reload_on_sighup());
+
+            let admin_server = tokio::spawn(async move {
+                match admin_socket {
+                    Some(socket) => admin.serve(&socket).await,
+                    // Never finish, so that pd keeps running the other services.
+                    None => std::future::pending().await,
+                }
+            });
 
             // This service lets Prometheus pull metrics from `pd`
             PrometheusBuilder::new()
@@ -185,13 +737,251 @@ async fn main() -> anyhow::Result<()> {
 
             pd::register_all_metrics();
 
+            let tendermint = {
+                let rpc_url = reqwest::Url::parse(&tendermint_rpc)?;
+                let supervisor = pd::supervisor::TendermintSupervisor {
+                    binary: tendermint_bin,
+                    home: pd::testnet::canonicalize_path(&tendermint_home),
+                    moniker,
+                    abci_addr: format!("{}:{}", host, abci_port),
+                    rpc_addr: format!(
+                        "{}:{}",
+                        rpc_url.host_str().unwrap_or("127.0.0.1"),
+                        rpc_url.port_or_known_default().unwrap_or(26657)
+                    ),
+                    p2p_addr: tendermint_p2p_addr,
+                    fast_blocks,
+                };
+                tokio::spawn(async move {
+                    if !with_tendermint {
+                        // Never finish, so that pd keeps running the other services.
+                        return std::future::pending().await;
+                    }
+                    supervisor.run().await
+                })
+            };
+
             // TODO: better error reporting
             // We error out if either service errors, rather than keep running
             tokio::select! {
                 x = abci_server => x?.map_err(|e| anyhow::anyhow!(e))?,
                 x = light_wallet_server => x?.map_err(|e| anyhow::anyhow!(e))?,
                 x = thin_wallet_server => x?.map_err(|e| anyhow::anyhow!(e))?,
+                x = changefeed_server => x?.map_err(|e| anyhow::anyhow!(e))?,
+                x = scanning_server => x?.map_err(|e| anyhow::anyhow!(e))?,
+                x = tendermint => x??,
+                x = admin_server => x??,
+            };
+        }
+        Command::Replica {
+            database_uri,
+            follow,
+            host,
+            light_wallet_port,
+            thin_wallet_port,
+            thin_wallet_host,
+            disable_thin_wallet,
+            changefeed_port,
+            changefeed_retention,
+            scanning_port,
+            scanning_clients,
+            metrics_port,
+            poll_ms,
+            config,
+            grpc,
+        } => {
+            tracing::info!(
+                ?host,
+                ?database_uri,
+                ?follow,
+                ?light_wallet_port,
+                ?thin_wallet_port,
+                ?thin_wallet_host,
+                ?disable_thin_wallet,
+                ?changefeed_port,
+                ?changefeed_retention,
+                ?scanning_port,
+                ?scanning_clients,
+                ?poll_ms,
+                ?config,
+                ?grpc,
+                "starting pd replica"
+            );
+            let config = pd::LiveConfig::load(config, Some(log_filter_handle))?;
+            tokio::spawn(config.clone().reload_on_sighup());
+
+            let state_reader = match follow {
+                Some(changefeed) => {
+                    pd::state::follower(
+                        &database_uri,
+                        changefeed,
+                        changefeed_retention,
+                        grpc.clone(),
+                    )
+                    .await?
+                }
+                None => pd::state::replica(&database_uri, Duration::from_millis(poll_ms)).await?,
+            };
+
+            // Without Tendermint, there's nowhere to broadcast transactions to.
+            let light_wallet_server = tokio::spawn(serve_light_wallet(
+                state_reader.clone(),
+                format!("{}:{}", host, light_wallet_port),
+                None,
+                None,
+                grpc.clone(),
+                config.clone(),
+            ));
+            let thin_wallet_server = tokio::spawn(serve_thin_wallet(
+                state_reader.clone(),
+                (!disable_thin_wallet).then(|| {
+                    format!(
+                        "{}:{}",
+                        thin_wallet_host.unwrap_or_else(|| host.clone()),
+                        thin_wallet_port
+                    )
+                }),
+                grpc.clone(),
+                config.clone(),
+            ));
+            let scanning = scanning_clients
+                .map(|path| pd::Scanning::load(state_reader.clone(), &path))
+                .transpose()?;
+            let scanning_server = tokio::spawn(serve_scanning(
+                scanning,
+                scanning_port.map(|port| format!("{}:{}", host, port)),
+                grpc.clone(),
+                config.clone(),
+            ));
+            let changefeed_server = tokio::spawn(serve_changefeed(
+                state_reader,
+                changefeed_port.map(|port| format!("{}:{}", host, port)),
+                grpc.clone(),
+                config,
+            ));
+
+            PrometheusBuilder::new()
+                .with_http_listener(
+                    format!("{}:{}", host, metrics_port)
+                        .parse::<SocketAddr>()
+                        .expect("this is a valid address"),
+                )
+                .install()
+                .expect("metrics service set up");
+
+            tokio::select! {
+                x = light_wallet_server => x?.map_err(|e| anyhow::anyhow!(e))?,
+                x = thin_wallet_server => x?.map_err(|e| anyhow::anyhow!(e))?,
+                x = changefeed_server => x?.map_err(|e| anyhow::anyhow!(e))?,
+                x = scanning_server => x?.map_err(|e| anyhow::anyhow!(e))?,
+            };
+        }
+        Command::Admin { socket, cmd } => {
+            use penumbra_proto::admin::{
+                BlockWritesRequest, PendingBlockRequest, QueueDepthsRequest, ReloadConfigRequest,
+                RotateLogsRequest, SetCircuitBreakerRequest, SnapshotRequest,
             };
+            use penumbra_stake::IdentityKey;
+
+            let mut client = pd::admin::connect(socket).await?;
+            match cmd {
+                AdminCommand::Snapshot => {
+                    let rsp = client.snapshot(SnapshotRequest {}).await?.into_inner();
+                    println!("Wrote snapshot at height {} to {}", rsp.height, rsp.path);
+                }
+                AdminCommand::RotateLogs => {
+                    let rsp = client.rotate_logs(RotateLogsRequest {}).await?.into_inner();
+                    println!("Reopened {}", rsp.path);
+                }
+                AdminCommand::PendingBlock => {
+                    let info = client
+                        .pending_block(PendingBlockRequest {})
+                        .await?
+                        .into_inner();
+                    if !info.in_progress {
+                        println!("No block in progress");
+                    } else {
+                        if info.height == 0 {
+                            println!("Block in progress, not yet ended");
+                        } else {
+                            println!("Block {} in progress, ended", info.height);
+                        }
+                        println!("Transactions: {}", info.transaction_ids.len());
+                        for id in &info.transaction_ids {
+                            println!("  {}", hex::encode(id));
+                        }
+                        println!("New notes: {}", info.notes);
+                        println!("Spent nullifiers: {}", info.spent_nullifiers);
+                        println!("Quarantine groups: {}", info.quarantine_groups);
+                        println!("Delegation changes: {}", info.delegation_changes.len());
+                        for change in info.delegation_changes {
+                            let identity_key = IdentityKey::try_from(
+                                change
+                                    .identity_key
+                                    .ok_or_else(|| anyhow::anyhow!("missing identity key"))?,
+                            )?;
+                            println!("  {}: {:+}", identity_key, change.delegation_change);
+                        }
+                    }
+                }
+                AdminCommand::CircuitBreaker { trip, reset: _ } => {
+                    let status = client
+                        .set_circuit_breaker(SetCircuitBreakerRequest { tripped: trip })
+                        .await?
+                        .into_inner();
+                    println!(
+                        "Circuit breaker is {}",
+                        if status.tripped { "tripped" } else { "reset" }
+                    );
+                }
+                AdminCommand::QueueDepths => {
+                    let depths = client
+                        .queue_depths(QueueDepthsRequest {})
+                        .await?
+                        .into_inner();
+                    println!(
+                        "Consensus queue: {}/{}",
+                        depths.consensus_queue_depth, depths.consensus_queue_capacity
+                    );
+                    println!(
+                        "Stateless verification cache: {}/{}",
+                        depths.stateless_cache_entries, depths.stateless_cache_capacity
+                    );
+                }
+                AdminCommand::ReloadConfig => {
+                    let rsp = client
+                        .reload_config(ReloadConfigRequest {})
+                        .await?
+                        .into_inner();
+                    println!("{}", rsp.config_json);
+                }
+                AdminCommand::BlockWrites => {
+                    let rsp = client
+                        .block_writes(BlockWritesRequest {})
+                        .await?
+                        .into_inner();
+                    if rsp.blocks.is_empty() {
+                        println!("No blocks committed since pd started");
+                    }
+                    for block in rsp.blocks {
+                        let rows = block
+                            .rows
+                            .iter()
+                            .filter(|count| count.rows > 0)
+                            .map(|count| format!("{} {}", count.rows, count.kind))
+                            .collect::<Vec<_>>();
+                        println!(
+                            "Block {}: {}",
+                            block.height,
+                            if rows.is_empty() {
+                                "nothing".to_string()
+                            } else {
+                                rows.join(", ")
+                            }
+                        );
+                    }
+                }
+            }
         }
         Command::GenerateTestnet {
             num_validator_nodes,
@@ -206,6 +996,8 @@ async fn main() -> anyhow::Result<()> {
             validators_input_file,
             output_dir,
             chain_id,
+            priv_validator_laddr,
+            fast_blocks,
         } => {
             use rand::Rng;
             use std::{
@@ -216,6 +1008,10 @@ async fn main() -> anyhow::Result<()> {
                 time::{Duration, SystemTime, UNIX_EPOCH},
             };
 
+            if fast_blocks {
+                pd::testnet::ensure_devnet_chain_id(&chain_id, "--fast-blocks")?;
+            }
+
             let randomizer = OsRng.gen::<u32>();
             let chain_id = format!("{}-{}", chain_id, hex::encode(&randomizer.to_le_bytes()));
 
@@ -295,14 +1091,7 @@ async fn main() -> anyhow::Result<()> {
             for (n, vk) in validator_keys.iter().enumerate() {
                 let node_name = format!("node{}", n);
 
-                let app_state = genesis::AppState {
-                    allocations: allocations.iter().map(|a| a.into()).collect(),
-                    chain_params: ChainParams {
-                        chain_id: chain_id.clone(),
-                        epoch_duration,
-                        unbonding_epochs,
-                    },
-                    validators: validators
+                let genesis_validators = validators
                         .iter()
                         .map(|v| {
                             Ok(ValidatorPower {
@@ -320,9 +1109,9 @@ async fn main() -> anyhow::Result<()> {
                                             .iter()
                                             .map(|fs| {
                                                 Ok(FundingStream {
-                                            address: Address::from_str(&fs.address).map_err(|_|
+                                            recipient: FundingStreamRecipient::Address(Address::from_str(&fs.address).map_err(|_|
                                                 anyhow::anyhow!("invalid funding stream address in validators.json"),
-                                            )?,
+                                            )?),
                                             rate_bps: fs.rate_bps,
                                         })
                                             })
@@ -336,8 +1125,51 @@ async fn main() -> anyhow::Result<()> {
                                 power: v.voting_power.into(),
                             })
                         })
-                        .collect::<Result<Vec<ValidatorPower>,anyhow::Error>>()?,
+                        .collect::<Result<Vec<ValidatorPower>,anyhow::Error>>()?;
+
+                // Each validator's declared voting power must be backed by an equal genesis
+                // delegation pool (the genesis exchange rate is 1), which we allocate to the
+                // validator's first funding stream paying to an address.
+                let mut genesis_allocations: Vec<genesis::Allocation> =
+                    allocations.iter().map(|a| a.into()).collect();
+                for ValidatorPower { validator, power } in &genesis_validators {
+                    let address = validator
+                        .funding_streams
+                        .as_ref()
+                        .iter()
+                        .find_map(|stream| match stream.recipient {
+                            FundingStreamRecipient::Address(address) => Some(address),
+                            FundingStreamRecipient::CommunityPool => None,
+                        })
+                        .ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "validator {} has no funding stream to hold its genesis delegation",
+                                validator.name
+                            )
+                        })?;
+                    genesis_allocations.push(genesis::Allocation {
+                        amount: power.value(),
+                        denom: validator
+                            .identity_key
+                            .delegation_token()
+                            .denom()
+                            .to_string(),
+                        address,
+                    });
+                }
+
+                let app_state = genesis::AppState {
+                    allocations: genesis_allocations,
+                    chain_params: ChainParams {
+                        chain_id: chain_id.clone(),
+                        epoch_duration,
+                        unbonding_epochs,
+                        ..Default::default()
+                    },
+                    validators: genesis_validators,
                 };
+                app_state.check_validators_unique()?;
+                app_state.check_validator_powers()?;
 
                 // Create the directory for this node
                 let mut node_dir = output_dir.clone();
@@ -408,7 +1240,8 @@ async fn main() -> anyhow::Result<()> {
                 // Note that this isn't a re-implementation of the `Config` type from
                 // Tendermint (https://github.com/tendermint/tendermint/blob/6291d22f46f4c4f9121375af700dbdafa51577e7/config/config.go#L92)
                 // so if they change their defaults or the available fields, that won't be reflected in our template.
-                let tm_config = generate_tm_config(&node_name);
+                let tm_config =
+                    generate_tm_config(&node_name, priv_validator_laddr.as_deref(), fast_blocks);
                 let mut config_file_path = node_config_dir.clone();
                 config_file_path.push("config.toml");
                 println!(
@@ -447,7 +1280,14 @@ async fn main() -> anyhow::Result<()> {
                     pub_key: vk.validator_cons_pk,
                     priv_key,
                 };
-                let mut priv_validator_key_file_path = node_config_dir.clone();
+                // With a remote signer, the node itself shouldn't hold the consensus key.
+                let mut priv_validator_key_file_path = if priv_validator_laddr.is_some() {
+                    let remote_signer_dir = node_dir.join("remote_signer");
+                    fs::create_dir_all(&remote_signer_dir)?;
+                    remote_signer_dir
+                } else {
+                    node_config_dir.clone()
+                };
                 priv_validator_key_file_path.push("priv_validator_key.json");
                 println!(
                     "Writing {} priv validator key file to: {}",
@@ -484,6 +1324,172 @@ async fn main() -> anyhow::Result<()> {
                 println!("-------------------------------------");
             }
         }
+        Command::Keys(KeysCommand::Generate { output_dir }) => {
+            use std::fs::{self, OpenOptions};
+            use std::io::Write;
+
+            use penumbra_stake::IdentityKey;
+            use tendermint::account::Id;
+            use tendermint_config::PrivValidatorKey;
+
+            let identity_sk = SigningKey::<SpendAuth>::new(OsRng);
+            let identity_key = IdentityKey(VerificationKey::from(&identity_sk));
+
+            let consensus_sk =
+                tendermint::PrivateKey::Ed25519(ed25519_consensus::SigningKey::new(OsRng));
+            let consensus_pk = consensus_sk.public_key();
+            let priv_validator_key = PrivValidatorKey {
+                address: Id::from(consensus_pk),
+                pub_key: consensus_pk,
+                priv_key: consensus_sk,
+            };
+
+            fs::create_dir_all(&output_dir)?;
+            for (name, contents) in [
+                (
+                    "validator_signingkey.json",
+                    serde_json::to_string_pretty(&identity_sk)?,
+                ),
+                (
+                    "priv_validator_key.json",
+                    serde_json::to_string_pretty(&priv_validator_key)?,
+                ),
+            ] {
+                let path = output_dir.join(name);
+                // Never overwrite existing keys.
+                OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&path)
+                    .map_err(|e| anyhow::anyhow!("could not create {}: {}", path.display(), e))?
+                    .write_all(contents.as_bytes())?;
+                println!("Wrote {}", path.display());
+            }
+
+            println!("Identity key: {}", identity_key);
+            println!("Consensus key: {}", serde_json::to_string(&consensus_pk)?);
+        }
+        Command::Debug(DebugCommand::SimulateEpoch {
+            snapshot,
+            delegation_changes,
+        }) => {
+            use std::{collections::BTreeMap, str::FromStr};
+
+            use penumbra_stake::IdentityKey;
+
+            let snapshot = pd::state::StateSnapshot::read(&snapshot)?;
+            let delegation_changes = match delegation_changes {
+                Some(path) => {
+                    let changes: BTreeMap<String, i64> =
+                        serde_json::from_slice(&std::fs::read(&path)?)?;
+                    changes
+                        .into_iter()
+                        .map(|(identity_key, delta)| {
+                            Ok((IdentityKey::from_str(&identity_key)?, delta))
+                        })
+                        .collect::<anyhow::Result<BTreeMap<_, _>>>()?
+                }
+                None => BTreeMap::new(),
+            };
+
+            let inputs = pd::epoch::EpochInputs::from_snapshot(&snapshot, &delegation_changes);
+            let transition = inputs.transition()?;
+
+            println!(
+                "Ending epoch {} from the snapshot at height {}",
+                inputs.prev_epoch_index, snapshot.height
+            );
+            println!(
+                "Base rate for epoch {}: reward rate {}, exchange rate {}",
+                transition.next_base_rate.epoch_index,
+                transition.next_base_rate.base_reward_rate,
+                transition.next_base_rate.base_exchange_rate
+            );
+            println!(
+                "Staking token supply: {} -> {}",
+                inputs.staking_token_supply, transition.staking_token_supply
+            );
+            for ((current_rate, next_rate), status) in inputs
+                .current_rates
+                .iter()
+                .zip(&transition.next_rates)
+                .zip(&transition.next_validator_statuses)
+            {
+                println!("Validator {}", current_rate.identity_key);
+                if next_rate.identity_key != current_rate.identity_key {
+                    println!("  migrating to {}", next_rate.identity_key);
+                }
+                println!(
+                    "  net delegations: {:+}",
+                    inputs
+                        .delegation_changes
+                        .get(&current_rate.identity_key)
+                        .unwrap_or(&0)
+                );
+                println!(
+                    "  reward rate: {} -> {}",
+                    current_rate.validator_reward_rate, next_rate.validator_reward_rate
+                );
+                println!(
+                    "  exchange rate: {} -> {}",
+                    current_rate.validator_exchange_rate, next_rate.validator_exchange_rate
+                );
+                println!("  voting power: {}", status.voting_power);
+                println!("  state: {:?}", status.state);
+            }
+            for (identity_key, supply) in &transition.delegation_token_supplies {
+                println!(
+                    "Delegation token supply for {}: {} -> {}",
+                    identity_key,
+                    inputs
+                        .delegation_token_supplies
+                        .get(identity_key)
+                        .copied()
+                        .unwrap_or_default(),
+                    supply
+                );
+            }
+            for (identity_key, amount, recipient) in &transition.commission_rewards {
+                match recipient {
+                    FundingStreamRecipient::Address(address) => println!(
+                        "Commission of {} from {} to {}",
+                        amount, identity_key, address
+                    ),
+                    FundingStreamRecipient::CommunityPool => println!(
+                        "Commission of {} from {} to the community pool",
+                        amount, identity_key
+                    ),
+                }
+            }
+        }
+        Command::Keys(KeysCommand::Show {
+            identity_key_file,
+            consensus_key_file,
+        }) => {
+            use penumbra_stake::IdentityKey;
+            use tendermint_config::PrivValidatorKey;
+
+            if identity_key_file.is_none() && consensus_key_file.is_none() {
+                return Err(anyhow::anyhow!(
+                    "at least one of --identity-key-file and --consensus-key-file is required"
+                ));
+            }
+            if let Some(path) = identity_key_file {
+                let identity_sk: SigningKey<SpendAuth> =
+                    serde_json::from_slice(&std::fs::read(&path)?)?;
+                let identity_key = IdentityKey(VerificationKey::from(&identity_sk));
+                println!("Identity key: {}", identity_key);
+            }
+            if let Some(path) = consensus_key_file {
+                let priv_validator_key: PrivValidatorKey =
+                    serde_json::from_slice(&std::fs::read(&path)?)?;
+                println!(
+                    "Consensus key: {}",
+                    serde_json::to_string(&priv_validator_key.pub_key)?
+                );
+                println!("Consensus address: {}", priv_validator_key.address);
+            }
+        }
     }
 
     Ok(())
@@ -4,17 +4,18 @@ use std::{
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
+    time::Duration,
 };
 
-use anyhow::anyhow;
 use futures::FutureExt;
 use penumbra_crypto::Nullifier;
 use penumbra_proto::Protobuf;
 use penumbra_transaction::Transaction;
 use tendermint::{
     abci::{
-        request::CheckTx as CheckTxRequest, response::CheckTx as CheckTxResponse, MempoolRequest,
-        MempoolResponse,
+        request::{CheckTx as CheckTxRequest, CheckTxKind},
+        response::CheckTx as CheckTxResponse,
+        MempoolRequest, MempoolResponse,
     },
     block,
 };
@@ -22,28 +23,150 @@ use tokio::sync::{watch, Mutex as AsyncMutex};
 use tower_abci::BoxError;
 use tracing::Instrument;
 
-use crate::{state, verify::StatelessTransactionExt, RequestExt};
+use crate::{
+    circuit_breaker::CircuitBreakerTripped,
+    response_code, state,
+    verify::{StaleAnchor, StatelessCache, StatelessTransactionExt},
+    CircuitBreaker, RequestExt, TraceContexts,
+};
+
+/// How many times to try reaching Tendermint when rebroadcasting persisted transactions.
+const REBROADCAST_ATTEMPTS: usize = 60;
+
+/// The error for a transaction spending a nullifier already spent by another transaction in the
+/// mempool.
+#[derive(Debug)]
+struct MempoolConflict(Nullifier);
+
+impl std::fmt::Display for MempoolConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "nullifier {:?} already spent in mempool", self.0)
+    }
+}
+
+impl std::error::Error for MempoolConflict {}
 
 #[derive(Clone, Debug)]
 pub struct Mempool {
     nullifiers: Arc<AsyncMutex<BTreeSet<Nullifier>>>,
     state: state::Reader,
-    // We keep our own copy of the height watcher rather than borrowing from our
-    // state::Reader so we can mutate it while tracking height updates.
-    height_rx: watch::Receiver<block::Height>,
+    stateless_cache: StatelessCache,
+    trace_contexts: TraceContexts,
+    circuit_breaker: CircuitBreaker,
+    // If set, transactions accepted by CheckTx are persisted here, so that
+    // they can be rebroadcast after a restart.
+    store: Option<state::MempoolStore>,
+    // We keep our own copy of the committed height watcher rather than borrowing
+    // from our state::Reader so we can mutate it while tracking height updates.
+    committed_height_rx: watch::Receiver<block::Height>,
 }
 
 impl Mempool {
-    pub fn new(state: state::Reader) -> Self {
+    pub fn new(
+        state: state::Reader,
+        stateless_cache: StatelessCache,
+        trace_contexts: TraceContexts,
+        circuit_breaker: CircuitBreaker,
+    ) -> Self {
         let nullifiers = Arc::new(AsyncMutex::new(Default::default()));
-        let height_rx = state.height_rx().clone();
+        let committed_height_rx = state.committed_height_rx().clone();
         Self {
             nullifiers,
             state,
-            height_rx,
+            stateless_cache,
+            trace_contexts,
+            circuit_breaker,
+            store: None,
+            committed_height_rx,
         }
     }
 
+    /// Persist transactions accepted by `CheckTx` until they are included in a
+    /// block, so that they survive a restart of the node.
+    pub fn with_persistence(mut self) -> Self {
+        self.store = Some(self.state.mempool_store());
+        self
+    }
+
+    /// Revalidate the transactions persisted before a restart against the
+    /// committed chain state, and rebroadcast the ones that are still valid to
+    /// Tendermint's RPC endpoint at `tendermint_rpc`.
+    ///
+    /// Persisted transactions which are no longer valid (e.g., because they
+    /// were included in a block in the meantime) are dropped.  Rebroadcast
+    /// transactions go through `CheckTx` again as usual.
+    pub async fn rebroadcast_persisted(&self, tendermint_rpc: String) -> anyhow::Result<()> {
+        let store = match &self.store {
+            Some(store) => store,
+            None => return Ok(()),
+        };
+
+        // Check the persisted transactions statelessly one by one, and then against the chain
+        // state all at once, since they were all pending at the same height.
+        let mut pending = Vec::new();
+        let mut transactions = Vec::new();
+        let mut invalid = Vec::new();
+        for (id, tx_bytes) in store.transactions().await? {
+            match Transaction::decode(&tx_bytes[..]).and_then(|tx| tx.verify_stateless()) {
+                Ok(transaction) => {
+                    pending.push((id, tx_bytes));
+                    transactions.push(transaction);
+                }
+                Err(e) => invalid.push((id, e)),
+            }
+        }
+        let validators = self.state.validator_info_rx().borrow().clone();
+        let results = self
+            .state
+            .verify_stateful_batch(transactions, &validators)
+            .await?;
+
+        let mut valid = Vec::new();
+        for ((id, tx_bytes), result) in pending.into_iter().zip(results) {
+            match result {
+                Ok(_) => valid.push(tx_bytes),
+                Err(e) => invalid.push((id, e)),
+            }
+        }
+        for (id, e) in invalid {
+            tracing::info!(id = ?hex::encode(id), ?e, "dropping persisted transaction");
+            store.remove(id).await?;
+        }
+        tracing::info!(count = valid.len(), "rebroadcasting persisted transactions");
+
+        let client = reqwest::Client::new();
+        for tx_bytes in valid {
+            // Tendermint only starts serving RPC requests once it has connected
+            // to our ABCI server, so retry until it comes up.
+            let mut attempts = 0;
+            loop {
+                let rsp = client
+                    .post(&tendermint_rpc)
+                    .json(&serde_json::json!(
+                        {
+                            "method": "broadcast_tx_async",
+                            "params": [&tx_bytes],
+                            "id": 0,
+                        }
+                    ))
+                    .send()
+                    .await;
+
+                match rsp {
+                    Ok(_) => break,
+                    Err(e) if attempts < REBROADCAST_ATTEMPTS => {
+                        tracing::debug!(?e, "tendermint RPC unavailable, retrying");
+                        attempts += 1;
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Perform checks before adding a transaction into the mempool via `CheckTx`.
     ///
     /// In the transaction validation performed before adding a transaction into the
@@ -58,15 +181,35 @@ impl Mempool {
     /// code, and the transaction will not be added into the mempool.
     ///
     /// We do not queue up any state changes into `PendingBlock` until `DeliverTx` where these
-    /// checks are repeated.
+    /// checks are repeated.  The results of stateless checks are recorded in the
+    /// [`StatelessCache`], so `DeliverTx` only needs to repeat the stateful checks.
     async fn check_tx(&self, check_tx: CheckTxRequest) -> Result<(), anyhow::Error> {
-        // Verify the transaction is well-formed...
-        let transaction = Transaction::decode(check_tx.tx)?;
-        tracing::info!(?transaction, ?check_tx.kind);
-        // ... and that it is internally consistent ...
-        let transaction = transaction.verify_stateless()?;
-        // ... and that it is consistent with the existing chain state.
-        let transaction = self.state.verify_stateful(transaction).await?;
+        let key = StatelessCache::key(&check_tx.tx);
+        // The context stays recorded until DeliverTx, which removes it.
+        if let Some(traceparent) = self.trace_contexts.get(&key) {
+            tracing::Span::current().record("traceparent", &tracing::field::display(traceparent));
+        }
+        let tx_bytes = check_tx.tx.clone();
+        let transaction = match self.stateless_cache.get(&key) {
+            // We already checked this transaction, e.g., in a previous Recheck.
+            Some(transaction) => transaction,
+            None => {
+                // Verify the transaction is well-formed...
+                let transaction = Transaction::decode(check_tx.tx)?;
+                tracing::info!(?transaction, ?check_tx.kind);
+                // ... and that it is internally consistent ...
+                let transaction = transaction.verify_stateless()?;
+                self.stateless_cache.insert(key, transaction.clone());
+                transaction
+            }
+        };
+        // ... and that it can be accepted at all right now ...
+        self.circuit_breaker.check(&transaction)?;
+        // ... and that it is consistent with the existing chain state, once the latest block
+        // (whose nullifiers we no longer track below) has been written to it.
+        self.state.wait_for_committed_block().await?;
+        let validators = self.state.validator_info_rx().borrow().clone();
+        let transaction = self.state.verify_stateful(transaction, &validators).await?;
 
         // We've verified that the transaction is consistent with the existing
         // chain state, but we want to ensure that it doesn't conflict with any
@@ -84,15 +227,22 @@ impl Mempool {
         // so we need to hold the lock for the whole check.
         let mut nullifiers = self.nullifiers.lock().await;
 
-        for nf in &transaction.spent_nullifiers {
+        for nf in &transaction.effects.spent_nullifiers {
             if nullifiers.contains(nf) {
-                return Err(anyhow!("nullifier {:?} already spent in mempool", nf));
+                return Err(MempoolConflict(nf.clone()).into());
             }
         }
 
-        for nf in transaction.spent_nullifiers {
+        for nf in transaction.effects.spent_nullifiers {
             nullifiers.insert(nf);
         }
+        drop(nullifiers);
+
+        // The store is keyed by transaction ID, which is what the writer uses to clear it when
+        // the transaction is committed (unlike the cache key, which hashes the encoding).
+        if let Some(store) = &self.store {
+            store.insert(transaction.id, &tx_bytes).await?;
+        }
 
         Ok(())
     }
@@ -104,8 +254,8 @@ impl tower::Service<MempoolRequest> for Mempool {
     type Future = Pin<Box<dyn Future<Output = Result<MempoolResponse, BoxError>> + Send + 'static>>;
 
     fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        // Check whether a new block has arrived since our last CheckTx request.
-        if self.height_rx.has_changed()? {
+        // Check whether a new block has been committed since our last CheckTx request.
+        if self.committed_height_rx.has_changed()? {
             // Wipe our mempool nullifier set.  Notice that this leaves any
             // *clones* of the previous version of the mempool nullifier set
             // unchanged, so any in-flight CheckTx requests will continue to use
@@ -115,7 +265,7 @@ impl tower::Service<MempoolRequest> for Mempool {
             // it).
             self.nullifiers = Arc::new(AsyncMutex::new(Default::default()));
             // Finally, mark the new height as having been seen.
-            self.height_rx.borrow_and_update();
+            self.committed_height_rx.borrow_and_update();
         }
         Poll::Ready(Ok(()))
     }
@@ -126,16 +276,49 @@ impl tower::Service<MempoolRequest> for Mempool {
         let mempool = self.clone();
 
         async move {
+            let tx_bytes = check_tx.tx.clone();
+            let kind = check_tx.kind;
             match mempool.check_tx(check_tx).await {
                 Ok(()) => Ok(MempoolResponse::CheckTx(CheckTxResponse::default())),
-                Err(e) => Ok(MempoolResponse::CheckTx(CheckTxResponse {
-                    code: 1,
-                    log: e.to_string(),
-                    ..Default::default()
-                })),
+                Err(e) => {
+                    // If a persisted transaction fails a Recheck because it has become invalid,
+                    // Tendermint evicts it from the mempool, so we should forget it too.  A
+                    // failed New check doesn't say anything about the copy we persisted, which
+                    // may be a duplicate submission.  Transactions that don't even decode were
+                    // never persisted.
+                    if matches!(kind, CheckTxKind::Recheck) && is_invalid(&e) {
+                        if let (Some(store), Ok(transaction)) =
+                            (&mempool.store, Transaction::decode(tx_bytes))
+                        {
+                            if let Err(e) = store.remove(transaction.id()).await {
+                                tracing::warn!(
+                                    ?e,
+                                    "could not remove transaction from mempool store"
+                                );
+                            }
+                        }
+                    }
+                    Ok(MempoolResponse::CheckTx(CheckTxResponse {
+                        code: response_code::for_error(&e),
+                        log: e.to_string(),
+                        ..Default::default()
+                    }))
+                }
             }
         }
         .instrument(span)
         .boxed()
     }
 }
+
+/// Whether a transaction rejected with `error` is invalid in itself, rather than only for now.
+///
+/// A transaction that conflicts with another in the mempool, or is rejected by the circuit breaker,
+/// may still be valid once the other one is dropped or the breaker is reset, and whether an anchor
+/// is stale depends on the chain's recent roots rather than the transaction.  Persisted
+/// transactions that never become valid again are discarded when they're rebroadcast.
+fn is_invalid(error: &anyhow::Error) -> bool {
+    !(error.is::<MempoolConflict>()
+        || error.is::<CircuitBreakerTripped>()
+        || error.is::<StaleAnchor>())
+}
@@ -1,10 +1,177 @@
-use metrics::register_counter;
+//! The metrics exported by `pd`.
+//!
+//! Dashboards and alerts depend on these names, types and labels, so every metric is declared
+//! here and recorded through its constant, and renaming or relabeling one is a breaking change.
+//! New metrics should follow the naming conventions described in the README.
 
-/// Registers all metrics tracked by `pd`.
+use metrics::{
+    describe_counter, describe_gauge, describe_histogram, register_counter, register_gauge,
+    register_histogram, Unit,
+};
+
+/// Counter: the number of nullifiers revealed since genesis.
+pub const SPENT_NULLIFIERS_TOTAL: &str = "node_spent_nullifiers_total";
+/// Counter: the number of notes created since genesis.
+pub const NOTES_TOTAL: &str = "node_notes_total";
+/// Counter: the number of transactions included in blocks.
+pub const TRANSACTIONS_TOTAL: &str = "node_transactions_total";
+/// Counter: the number of transactions whose stateless verification was reused from `CheckTx`.
+pub const STATELESS_CACHE_HITS_TOTAL: &str = "node_stateless_cache_hits_total";
+/// Gauge: the size of the database on disk, in bytes.
+pub const DB_SIZE_BYTES: &str = "node_db_size_bytes";
+/// Gauge: the estimated number of live rows in a table.  Labels: `table`.
+pub const DB_TABLE_ROWS: &str = "node_db_table_rows";
+/// Histogram: the time taken to commit a block to the database, in seconds.
+pub const DB_COMMIT_DURATION_SECONDS: &str = "node_db_commit_duration_seconds";
+/// Histogram: the time consensus waited for the previous block's commit, in seconds.
+pub const DB_COMMIT_WAIT_DURATION_SECONDS: &str = "node_db_commit_wait_duration_seconds";
+/// Counter: the number of rows written to the database.  Labels: `kind`.
+pub const DB_ROWS_WRITTEN_TOTAL: &str = "node_db_rows_written_total";
+/// Counter: the number of block reads served from Postgres' buffer cache.
+pub const DB_CACHE_HITS_TOTAL: &str = "node_db_cache_hits_total";
+/// Counter: the number of block reads that missed Postgres' buffer cache.
+pub const DB_CACHE_MISSES_TOTAL: &str = "node_db_cache_misses_total";
+/// Gauge: the number of quarantined notes and nullifiers waiting to be reverted.
+pub const QUARANTINE_REVERT_BACKLOG: &str = "node_quarantine_revert_backlog";
+/// Histogram: the number of notes created by each block.
+pub const BLOCK_NOTES_CREATED: &str = "node_block_notes_created";
+/// Histogram: the number of nullifiers revealed by each block.
+pub const BLOCK_NULLIFIERS_REVEALED: &str = "node_block_nullifiers_revealed";
+/// Counter: the number of transactions included in blocks, by shape.  Labels: `shape`.
+pub const TRANSACTIONS_BY_SHAPE_TOTAL: &str = "node_transactions_by_shape_total";
+/// Counter: the fees paid by included transactions, in staking token units.
+pub const FEES_TOTAL: &str = "node_fees_total";
+/// Gauge: 1 if the circuit breaker has stopped accepting transactions, 0 otherwise.
+pub const CIRCUIT_BREAKER_TRIPPED: &str = "node_circuit_breaker_tripped";
+/// Gauge: the size of the serialized note commitment tree, in bytes.
+pub const NCT_SIZE_BYTES: &str = "node_nct_size_bytes";
+/// Gauge: the number of bridges kept in the note commitment tree.
+pub const NCT_BRIDGES: &str = "node_nct_bridges";
+/// Counter: the number of epoch boundaries crossed.
+///
+/// This predates the naming conventions, and keeps its name so existing dashboards still work.
+pub const EPOCHS: &str = "epoch";
+
+/// Registers and describes all metrics tracked by `pd`.
 pub fn register_all_metrics() {
-    register_counter!("node_spent_nullifiers_total");
-    register_counter!("node_notes_total");
-    register_counter!("node_transactions_total");
+    register_counter!(SPENT_NULLIFIERS_TOTAL);
+    describe_counter!(
+        SPENT_NULLIFIERS_TOTAL,
+        Unit::Count,
+        "The number of nullifiers revealed since genesis"
+    );
+    register_counter!(NOTES_TOTAL);
+    describe_counter!(
+        NOTES_TOTAL,
+        Unit::Count,
+        "The number of notes created since genesis"
+    );
+    register_counter!(TRANSACTIONS_TOTAL);
+    describe_counter!(
+        TRANSACTIONS_TOTAL,
+        Unit::Count,
+        "The number of transactions included in blocks"
+    );
+    register_counter!(STATELESS_CACHE_HITS_TOTAL);
+    describe_counter!(
+        STATELESS_CACHE_HITS_TOTAL,
+        Unit::Count,
+        "The number of transactions whose stateless verification was reused from CheckTx"
+    );
+    register_gauge!(DB_SIZE_BYTES);
+    describe_gauge!(
+        DB_SIZE_BYTES,
+        Unit::Bytes,
+        "The size of the database on disk"
+    );
+    register_gauge!(DB_TABLE_ROWS);
+    describe_gauge!(
+        DB_TABLE_ROWS,
+        Unit::Count,
+        "The estimated number of live rows in each table"
+    );
+    register_histogram!(DB_COMMIT_DURATION_SECONDS);
+    describe_histogram!(
+        DB_COMMIT_DURATION_SECONDS,
+        Unit::Seconds,
+        "The time taken to commit a block to the database"
+    );
+    register_histogram!(DB_COMMIT_WAIT_DURATION_SECONDS);
+    describe_histogram!(
+        DB_COMMIT_WAIT_DURATION_SECONDS,
+        Unit::Seconds,
+        "The time consensus waited for the previous block's commit"
+    );
+    register_counter!(DB_ROWS_WRITTEN_TOTAL);
+    describe_counter!(
+        DB_ROWS_WRITTEN_TOTAL,
+        Unit::Count,
+        "The number of rows written to the database, by kind"
+    );
+    register_counter!(DB_CACHE_HITS_TOTAL);
+    describe_counter!(
+        DB_CACHE_HITS_TOTAL,
+        Unit::Count,
+        "The number of block reads served from the buffer cache"
+    );
+    register_counter!(DB_CACHE_MISSES_TOTAL);
+    describe_counter!(
+        DB_CACHE_MISSES_TOTAL,
+        Unit::Count,
+        "The number of block reads that missed the buffer cache"
+    );
+    register_gauge!(QUARANTINE_REVERT_BACKLOG);
+    describe_gauge!(
+        QUARANTINE_REVERT_BACKLOG,
+        Unit::Count,
+        "The number of quarantined notes and nullifiers waiting to be reverted"
+    );
+    register_histogram!(BLOCK_NOTES_CREATED);
+    describe_histogram!(
+        BLOCK_NOTES_CREATED,
+        Unit::Count,
+        "The number of notes created by each block"
+    );
+    register_histogram!(BLOCK_NULLIFIERS_REVEALED);
+    describe_histogram!(
+        BLOCK_NULLIFIERS_REVEALED,
+        Unit::Count,
+        "The number of nullifiers revealed by each block"
+    );
+    register_counter!(TRANSACTIONS_BY_SHAPE_TOTAL);
+    describe_counter!(
+        TRANSACTIONS_BY_SHAPE_TOTAL,
+        Unit::Count,
+        "The number of transactions included in blocks, by shape"
+    );
+    register_counter!(FEES_TOTAL);
+    describe_counter!(
+        FEES_TOTAL,
+        "The fees paid by included transactions, in staking token units"
+    );
+    register_gauge!(CIRCUIT_BREAKER_TRIPPED);
+    describe_gauge!(
+        CIRCUIT_BREAKER_TRIPPED,
+        "Whether the circuit breaker has stopped accepting transactions"
+    );
+    register_gauge!(NCT_SIZE_BYTES);
+    describe_gauge!(
+        NCT_SIZE_BYTES,
+        Unit::Bytes,
+        "The size of the serialized note commitment tree"
+    );
+    register_gauge!(NCT_BRIDGES);
+    describe_gauge!(
+        NCT_BRIDGES,
+        Unit::Count,
+        "The number of bridges kept in the note commitment tree"
+    );
+    register_counter!(EPOCHS);
+    describe_counter!(
+        EPOCHS,
+        Unit::Count,
+        "The number of epoch boundaries crossed"
+    );
 }
 
 /// Represents a bundle of structured metrics data.
@@ -12,3 +179,15 @@ pub struct MetricsData {
     pub nullifier_count: u64,
     pub note_count: u64,
 }
+
+/// Statistics about the database backing the chain state.
+pub struct StorageMetricsData {
+    /// The size of the database on disk, in bytes.
+    pub db_size_bytes: u64,
+    /// The (estimated) number of live rows in each table.
+    pub table_rows: Vec<(String, u64)>,
+    /// The number of block reads served from Postgres' buffer cache.
+    pub cache_hits: u64,
+    /// The number of block reads that missed the buffer cache and went to disk.
+    pub cache_misses: u64,
+}
@@ -2,32 +2,46 @@ use std::collections::{BTreeMap, BTreeSet};
 
 use ark_ff::PrimeField;
 use decaf377::Fr;
+use penumbra_chain::params::ChainParams;
 use penumbra_crypto::{
     asset, ka,
-    merkle::{Frontier, NoteCommitmentTree},
-    note, Address, Fq, Note, Nullifier, One, Value,
+    merkle::{self, Frontier, NoteCommitmentTree},
+    note, Address, Amount, Fq, Note, Nullifier, One, Value,
 };
 use penumbra_stake::{
-    BaseRateData, Epoch, IdentityKey, RateData, ValidatorState, ValidatorStatus,
-    STAKING_TOKEN_ASSET_ID,
+    BaseRateData, Epoch, FundingStreamRecipient, FundingStreams, IdentityKey, RateData,
+    RewardSource, Validator, ValidatorState, ValidatorStatus, STAKING_TOKEN_ASSET_ID,
 };
+use penumbra_transaction::Shape;
+use tendermint::{consensus, Time};
 use tracing::instrument;
 
-use crate::verify::{NoteData, PositionedNoteData, VerifiedTransaction};
+use crate::verify::{NoteData, PositionedNoteData, StateEffects, VerifiedTransaction};
 
 /// Stores pending state changes from transactions.
+///
+/// A block starts out [`Building`], while its transactions are delivered, and
+/// becomes [`Ended`] once EndBlock tells us its height; only an ended block
+/// can be committed.
+///
+/// Everything here is written to the chain state in iteration order, so it
+/// must be kept in ordered collections (`BTreeMap`, `BTreeSet`, or a `Vec` in
+/// delivery order), for every node to commit the same state.
 #[derive(Debug, Clone)]
-pub struct PendingBlock {
-    pub height: Option<u64>,
+pub struct PendingBlock<Phase = Building> {
     pub note_commitment_tree: NoteCommitmentTree,
+    /// IDs of the transactions included in this block.
+    pub transaction_ids: Vec<[u8; 32]>,
+    /// The number of transactions of each shape included in this block.
+    pub transaction_shapes: BTreeMap<Shape, u64>,
+    /// The total fees paid by the transactions in this block, by asset ID.
+    pub fee_totals: BTreeMap<asset::Id, u64>,
     /// Stores note commitments for convienience when updating the NCT.
     pub notes: BTreeMap<note::Commitment, PositionedNoteData>,
     /// Nullifiers that were spent in this block.
     pub spent_nullifiers: BTreeSet<Nullifier>,
     /// Records any updates to the token supply of some asset that happened in this block.
-    pub supply_updates: BTreeMap<asset::Id, (asset::Denom, u64)>,
-    /// Indicates the epoch the block belongs to.
-    pub epoch: Option<Epoch>,
+    pub supply_updates: BTreeMap<asset::Id, (asset::Denom, Amount)>,
     /// If this is the last block of an epoch, base rates for the next epoch go here.
     pub next_base_rate: Option<BaseRateData>,
     /// If this is the last block of an epoch, validator rates for the next epoch go here.
@@ -39,8 +53,18 @@ pub struct PendingBlock {
     /// The counter containing the number of rewards notes in the epoch. we need this to keep the
     /// blinding factor of the reward notes unique.
     reward_counter: u64,
+    /// The counter containing the number of proposer reward notes in the block, kept apart from
+    /// `reward_counter` so that paying a proposer doesn't change the epoch's reward notes.
+    proposer_reward_counter: u64,
+    /// The rewards paid to each validator's funding streams in this block.
+    pub rewards_paid: BTreeMap<IdentityKey, u64>,
+    /// The rewards paid into the community pool in this block, by the validator whose funding
+    /// stream paid them.
+    pub community_pool_deposits: BTreeMap<IdentityKey, u64>,
     /// Records pending state changes to validators.
     pub validator_state_changes: BTreeMap<IdentityKey, ValidatorState>,
+    /// Slashing penalties applied to validators' rates in this block.
+    pub slashings: BTreeMap<IdentityKey, Slashing>,
     /// Records all the quarantined inputs/outputs from this block.
     pub quarantine: Vec<QuarantineGroup>,
     /// Nullifiers to remove from the quarantined set when this block is committed, making their
@@ -50,75 +74,393 @@ pub struct PendingBlock {
     pub reverting_notes: BTreeSet<note::Commitment>,
     /// Nullifiers to remove from the nullifier set when this block is committed, reverting their spend.
     pub reverting_nullifiers: BTreeSet<Nullifier>,
+    /// Validators whose quarantined notes or nullifiers are released in this block.
+    pub quarantine_releases: BTreeSet<IdentityKey>,
+    /// Updated chain parameters, taking effect after this block.
+    ///
+    /// Nothing changes the chain parameters after genesis yet; this is where e.g. governance would.
+    pub next_chain_params: Option<ChainParams>,
+    /// Updated consensus parameters, derived from the chain parameters.
+    pub next_consensus_params: Option<consensus::Params>,
+    /// Validator identity key migrations performed in this block, from old to new key.
+    pub validator_migrations: BTreeMap<IdentityKey, IdentityKey>,
+    /// If this is the last block of an epoch, the validator identity key migrations taking effect
+    /// in the next epoch go here.
+    pub next_validator_migrations: BTreeMap<IdentityKey, IdentityKey>,
+    /// Updated validator definitions in this block, by identity key.
+    pub validator_definitions: BTreeMap<IdentityKey, Validator>,
+    /// Denom metadata registered in this block, by asset ID.
+    pub denom_metadata: BTreeMap<asset::Id, asset::Metadata>,
+    /// The epoch index of each redelegation in this block, by delegator key.
+    pub redelegations: BTreeMap<[u8; 32], u64>,
+    /// The anchor of each transaction in this block, by transaction ID, so the transactions can't
+    /// be included again while their anchors are valid.
+    pub recent_transactions: BTreeMap<[u8; 32], merkle::Root>,
+    /// The validator that proposed this block, if it is a known validator.
+    pub proposer: Option<IdentityKey>,
+    /// The time in the block's header, or the genesis time for the genesis block.
+    pub time: Option<Time>,
+    /// The phase-specific state of the block.
+    pub phase: Phase,
+}
+
+/// The phase of a [`PendingBlock`] whose transactions are being delivered.
+#[derive(Debug, Clone)]
+pub struct Building;
+
+/// The phase of a [`PendingBlock`] after EndBlock, once its height is known.
+#[derive(Debug, Clone)]
+pub struct Ended {
+    /// The height of the block.
+    pub height: u64,
+    /// The epoch the block belongs to.
+    pub epoch: Epoch,
 }
 
 /// A group of notes and nullifiers, all to be quarantined relative to a shared set of validators.
 #[derive(Debug, Clone)]
 pub struct QuarantineGroup {
-    /// If this validator is slashed while the notes and nullifiers in this quarantined group, then
-    /// all of the notes should be dropped and all the nullifiers removed from the NCT.
-    pub validator_identity_key: IdentityKey,
+    /// The transaction whose undelegations this group unbonds.
+    pub transaction_id: [u8; 32],
+    /// The amount of stake unbonded from each validator undelegated from, or zero for a validator
+    /// that was only redelegated away from.
+    ///
+    /// If any of these validators is slashed while the notes and nullifiers in this group are
+    /// quarantined, then all of the notes should be dropped and all the nullifiers removed from
+    /// the NCT.
+    pub unbondings: BTreeMap<IdentityKey, u64>,
     /// The set of notes in this group.
     pub notes: BTreeMap<note::Commitment, NoteData>,
     /// The set of nullifiers in this group.
     pub nullifiers: BTreeSet<Nullifier>,
 }
 
-impl PendingBlock {
+/// A summary of a [`PendingBlock`], for node operators to inspect.
+#[derive(Debug, Clone, Default)]
+pub struct PendingBlockSummary {
+    /// The height of the block, once it has ended.
+    pub height: Option<u64>,
+    pub transaction_ids: Vec<[u8; 32]>,
+    /// The number of notes added to the note commitment tree.
+    pub notes: usize,
+    pub spent_nullifiers: usize,
+    pub quarantine_groups: usize,
+    pub delegation_changes: BTreeMap<IdentityKey, i64>,
+}
+
+/// A slashing penalty applied to a validator's rates.
+#[derive(Debug, Clone)]
+pub struct Slashing {
+    /// The validator's rates in the epoch of the infraction, against which the penalty is computed.
+    pub infraction_rate: RateData,
+    /// The validator's current rates, before applying the penalty.
+    pub pre_slash: RateData,
+    /// The validator's current rates, after applying the penalty.
+    pub post_slash: RateData,
+}
+
+impl PendingBlock<Building> {
     pub fn new(note_commitment_tree: NoteCommitmentTree) -> Self {
         Self {
-            height: None,
             note_commitment_tree,
+            transaction_ids: Vec::new(),
+            transaction_shapes: BTreeMap::new(),
+            fee_totals: BTreeMap::new(),
             notes: BTreeMap::new(),
             spent_nullifiers: BTreeSet::new(),
             supply_updates: BTreeMap::new(),
-            epoch: None,
             next_base_rate: None,
             next_rates: None,
             next_validator_statuses: None,
             delegation_changes: BTreeMap::new(),
             reward_counter: 0,
+            proposer_reward_counter: 0,
+            rewards_paid: BTreeMap::new(),
+            community_pool_deposits: BTreeMap::new(),
             validator_state_changes: BTreeMap::new(),
+            slashings: BTreeMap::new(),
             quarantine: Vec::new(),
             reverting_notes: BTreeSet::new(),
             unbonding_nullifiers: BTreeSet::new(),
             reverting_nullifiers: BTreeSet::new(),
+            quarantine_releases: BTreeSet::new(),
+            next_chain_params: None,
+            next_consensus_params: None,
+            validator_migrations: BTreeMap::new(),
+            next_validator_migrations: BTreeMap::new(),
+            validator_definitions: BTreeMap::new(),
+            denom_metadata: BTreeMap::new(),
+            redelegations: BTreeMap::new(),
+            recent_transactions: BTreeMap::new(),
+            proposer: None,
+            time: None,
+            phase: Building,
         }
     }
 
-    /// We only get the height from ABCI in EndBlock, so this allows setting it in-place.
-    pub fn set_height(&mut self, height: u64, epoch_duration: u64) -> Epoch {
-        self.height = Some(height);
-        let epoch = Epoch::from_height(height, epoch_duration);
-        self.epoch = Some(epoch.clone());
-        epoch
+    /// Ends the block at the given height, after which no more transactions can be added.
+    ///
+    /// We only get the height from ABCI in EndBlock, so this is where the block learns it.
+    pub fn end(self, height: u64, epoch_duration: u64) -> PendingBlock<Ended> {
+        PendingBlock {
+            note_commitment_tree: self.note_commitment_tree,
+            transaction_ids: self.transaction_ids,
+            transaction_shapes: self.transaction_shapes,
+            fee_totals: self.fee_totals,
+            notes: self.notes,
+            spent_nullifiers: self.spent_nullifiers,
+            supply_updates: self.supply_updates,
+            next_base_rate: self.next_base_rate,
+            next_rates: self.next_rates,
+            next_validator_statuses: self.next_validator_statuses,
+            delegation_changes: self.delegation_changes,
+            reward_counter: self.reward_counter,
+            proposer_reward_counter: self.proposer_reward_counter,
+            rewards_paid: self.rewards_paid,
+            community_pool_deposits: self.community_pool_deposits,
+            validator_state_changes: self.validator_state_changes,
+            slashings: self.slashings,
+            quarantine: self.quarantine,
+            unbonding_nullifiers: self.unbonding_nullifiers,
+            reverting_notes: self.reverting_notes,
+            reverting_nullifiers: self.reverting_nullifiers,
+            quarantine_releases: self.quarantine_releases,
+            next_chain_params: self.next_chain_params,
+            next_consensus_params: self.next_consensus_params,
+            validator_migrations: self.validator_migrations,
+            next_validator_migrations: self.next_validator_migrations,
+            validator_definitions: self.validator_definitions,
+            denom_metadata: self.denom_metadata,
+            redelegations: self.redelegations,
+            recent_transactions: self.recent_transactions,
+            proposer: self.proposer,
+            time: self.time,
+            phase: Ended {
+                height,
+                epoch: Epoch::from_height(height, epoch_duration),
+            },
+        }
+    }
+
+    /// Checks that a verified transaction's effects don't conflict with the transactions already
+    /// in this block.
+    ///
+    /// Each transaction is verified against the committed state, so this catches conflicts
+    /// between transactions in the same block.
+    pub fn check_conflicts(&self, effects: &StateEffects) -> anyhow::Result<()> {
+        if let Some((id, _)) = &effects.recent_transaction {
+            if self.recent_transactions.contains_key(id) {
+                return Err(anyhow::anyhow!(
+                    "transaction {} is already included in the pending block",
+                    hex::encode(id)
+                ));
+            }
+        }
+
+        if let Some(conflict) = self
+            .spent_nullifiers
+            .intersection(&effects.spent_nullifiers)
+            .next()
+        {
+            return Err(anyhow::anyhow!(
+                "nullifier {:?} is already spent in the pending block",
+                conflict
+            ));
+        }
+
+        for (old_identity_key, new_identity_key) in &effects.validator_migrations {
+            if self.validator_migrations.contains_key(old_identity_key)
+                || self
+                    .validator_migrations
+                    .values()
+                    .any(|k| k == new_identity_key)
+            {
+                return Err(anyhow::anyhow!(
+                    "validator migration from {} to {} conflicts with the pending block",
+                    old_identity_key,
+                    new_identity_key
+                ));
+            }
+        }
+
+        // Only one definition per validator can be included in a block, since both would have
+        // been checked against the same committed sequence number.
+        if let Some(identity_key) = effects
+            .validator_definitions
+            .keys()
+            .find(|k| self.validator_definitions.contains_key(k))
+        {
+            return Err(anyhow::anyhow!(
+                "validator definition for {} conflicts with the pending block",
+                identity_key
+            ));
+        }
+
+        if let Some(metadata) = effects
+            .denom_metadata
+            .iter()
+            .find_map(|(id, metadata)| self.denom_metadata.contains_key(id).then(|| metadata))
+        {
+            return Err(anyhow::anyhow!(
+                "metadata for denom {} is already registered in the pending block",
+                metadata.denom
+            ));
+        }
+
+        // Each redelegation was checked against the committed cooldowns, so a delegator key can
+        // only redelegate once per block.
+        if let Some(delegator_key) = effects
+            .redelegations
+            .keys()
+            .find(|k| self.redelegations.contains_key(k))
+        {
+            return Err(anyhow::anyhow!(
+                "redelegation by delegator key {} conflicts with the pending block",
+                hex::encode(delegator_key)
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Adds the state changes from a verified transaction.
+    pub fn add_transaction(&mut self, transaction: VerifiedTransaction) {
+        self.transaction_ids.push(transaction.id);
+        *self
+            .transaction_shapes
+            .entry(transaction.shape)
+            .or_insert(0) += 1;
+        let effects = transaction.effects;
+
+        if !effects.undelegations.is_empty() || !effects.quarantined_redelegations.is_empty() {
+            // If a transaction contains undelegations, or a delegator key's first redelegation,
+            // we *do not insert any of its outputs* into the NCT; instead we store them
+            // separately, to be inserted into the NCT only after the unbonding period occurs.
+            // Redelegated stake stays bonded, so it doesn't count towards the stake unbonded from
+            // the validator it left.
+            let mut unbondings = effects.undelegations;
+            for identity_key in effects.quarantined_redelegations {
+                unbondings.entry(identity_key).or_insert(0);
+            }
+            self.quarantine.push(QuarantineGroup {
+                transaction_id: transaction.id,
+                unbondings,
+                notes: effects.new_notes.into_iter().collect(),
+                nullifiers: effects.spent_nullifiers.iter().cloned().collect(),
+            });
+        } else {
+            // Otherwise, we insert its outputs immediately into the NCT.
+            for (commitment, data) in effects.new_notes {
+                self.add_note(commitment, data);
+            }
+        }
+
+        // Unconditionally, insert all nullifiers spent in this transaction into the spent set to
+        // prevent double-spends, regardless of quarantine status.
+        for nullifier in effects.spent_nullifiers {
+            self.spent_nullifiers.insert(nullifier);
+        }
+
+        for (asset_id, amount) in effects.fees {
+            *self.fee_totals.entry(asset_id).or_insert(0) += amount;
+        }
+
+        // Tally the delegation changes in this transaction
+        for (identity_key, delegation_change) in effects.delegation_changes {
+            *self.delegation_changes.entry(identity_key).or_insert(0) += delegation_change;
+        }
+
+        self.validator_migrations
+            .extend(effects.validator_migrations);
+        self.validator_definitions
+            .extend(effects.validator_definitions);
+        self.denom_metadata.extend(effects.denom_metadata);
+        self.redelegations.extend(effects.redelegations);
+        self.recent_transactions.extend(effects.recent_transaction);
     }
+}
 
-    /// Adds a reward output for a validator's funding stream.
+impl PendingBlock<Ended> {
+    /// Adds a reward output for one of the funding streams of the validator with the given
+    /// identity key.
     #[instrument(skip(self, destination), fields(destination = %destination))]
-    pub fn add_validator_reward_note(&mut self, amount: u64, destination: Address) {
+    pub fn add_validator_reward_note(
+        &mut self,
+        amount: u64,
+        destination: Address,
+        validator_identity: IdentityKey,
+    ) {
         if amount == 0 {
             // Skip adding an empty note to the chain.
             return;
         }
 
-        let val = Value {
-            amount,
-            asset_id: *STAKING_TOKEN_ASSET_ID,
-        };
-
         let blinding_factor_input = blake2b_simd::Params::default()
             .personal(b"fundingstrm_note")
             .to_state()
-            .update(&self.epoch.as_ref().unwrap().index.to_le_bytes())
+            .update(&self.phase.epoch.index.to_le_bytes())
             .update(&self.reward_counter.to_le_bytes())
             .finalize();
+        self.reward_counter += 1;
+
+        self.add_reward_note(
+            amount,
+            destination,
+            validator_identity,
+            Fq::from_le_bytes_mod_order(blinding_factor_input.as_bytes()),
+        );
+    }
+
+    /// Adds a note paying part of a block proposer's reward to the given destination.
+    ///
+    /// Its blinding factor is derived in a domain of its own, from the height and a per-block
+    /// counter, so that epoch reward notes keep the commitments they had before proposers were
+    /// paid.
+    fn add_proposer_reward_note(
+        &mut self,
+        amount: u64,
+        destination: Address,
+        validator_identity: IdentityKey,
+    ) {
+        if amount == 0 {
+            return;
+        }
+
+        let blinding_factor_input = blake2b_simd::Params::default()
+            .personal(b"proposer_rwdnote")
+            .to_state()
+            .update(&self.phase.height.to_le_bytes())
+            .update(&self.proposer_reward_counter.to_le_bytes())
+            .finalize();
+        self.proposer_reward_counter += 1;
+
+        self.add_reward_note(
+            amount,
+            destination,
+            validator_identity,
+            Fq::from_le_bytes_mod_order(blinding_factor_input.as_bytes()),
+        );
+    }
+
+    /// Adds a reward note with the given blinding factor, and records it as paid by the
+    /// validator with the given identity key.
+    fn add_reward_note(
+        &mut self,
+        amount: u64,
+        destination: Address,
+        validator_identity: IdentityKey,
+        blinding_factor: Fq,
+    ) {
+        let val = Value {
+            amount,
+            asset_id: *STAKING_TOKEN_ASSET_ID,
+        };
 
         let note = Note::from_parts(
             *destination.diversifier(),
             *destination.transmission_key(),
             val,
-            Fq::from_le_bytes_mod_order(blinding_factor_input.as_bytes()),
+            blinding_factor,
         )
         .unwrap();
         let commitment = note.commit();
@@ -132,11 +474,79 @@ impl PendingBlock {
             ephemeral_key: esk.diversified_public(&note.diversified_generator()),
             encrypted_note,
             transaction_id: [0; 32],
+            reward_source: Some(RewardSource {
+                validator_identity: validator_identity.clone(),
+                epoch_index: self.phase.epoch.index,
+            }),
         };
 
         self.add_note(commitment, note_data);
 
-        self.reward_counter += 1;
+        *self.rewards_paid.entry(validator_identity).or_insert(0) += amount;
+    }
+
+    /// Adds a reward paid into the community pool by one of the funding streams of the validator
+    /// with the given identity key.
+    pub fn add_community_pool_deposit(&mut self, amount: u64, validator_identity: IdentityKey) {
+        if amount == 0 {
+            return;
+        }
+
+        *self
+            .community_pool_deposits
+            .entry(validator_identity.clone())
+            .or_insert(0) += amount;
+        *self.rewards_paid.entry(validator_identity).or_insert(0) += amount;
+    }
+
+    /// Pays a block proposer's reward to its funding streams, in proportion to their rates.
+    ///
+    /// Returns the amount actually paid, which may be less than `reward` due to rounding, or zero
+    /// if the validator has no funding streams.
+    pub fn add_proposer_reward(
+        &mut self,
+        reward: u64,
+        proposer: &IdentityKey,
+        funding_streams: &FundingStreams,
+    ) -> u64 {
+        let total_bps = funding_streams
+            .as_ref()
+            .iter()
+            .map(|stream| stream.rate_bps as u128)
+            .sum::<u128>();
+        if total_bps == 0 {
+            return 0;
+        }
+
+        let mut paid = 0;
+        for stream in funding_streams.as_ref() {
+            // This can't overflow, since the stream's share is at most the whole reward.
+            let amount = (reward as u128 * stream.rate_bps as u128 / total_bps) as u64;
+            match stream.recipient {
+                FundingStreamRecipient::Address(address) => {
+                    self.add_proposer_reward_note(amount, address, proposer.clone())
+                }
+                FundingStreamRecipient::CommunityPool => {
+                    self.add_community_pool_deposit(amount, proposer.clone())
+                }
+            }
+            paid += amount;
+        }
+        paid
+    }
+}
+
+impl<Phase> PendingBlock<Phase> {
+    /// Summarizes the block so far.
+    pub fn summary(&self) -> PendingBlockSummary {
+        PendingBlockSummary {
+            height: None,
+            transaction_ids: self.transaction_ids.clone(),
+            notes: self.notes.len(),
+            spent_nullifiers: self.spent_nullifiers.len(),
+            quarantine_groups: self.quarantine.len(),
+            delegation_changes: self.delegation_changes.clone(),
+        }
     }
 
     /// Adds a new note to this pending block.
@@ -154,35 +564,238 @@ impl PendingBlock {
         self.notes
             .insert(commitment, PositionedNoteData { position, data });
     }
+}
 
-    /// Adds the state changes from a verified transaction.
-    pub fn add_transaction(&mut self, transaction: VerifiedTransaction) {
-        if let Some(validator_identity_key) = transaction.undelegation_validator {
-            // If a transaction contains an undelegation, we *do not insert any of its outputs*
-            // into the NCT; instead we store them separately, to be inserted into the NCT only
-            // after the unbonding period occurs.
-            self.quarantine.push(QuarantineGroup {
-                validator_identity_key,
-                notes: transaction.new_notes.into_iter().collect(),
-                nullifiers: transaction.spent_nullifiers.iter().cloned().collect(),
-            });
-        } else {
-            // If a transaction does not contain any undelegations, we insert its outputs
-            // immediately into the NCT.
-            for (commitment, data) in transaction.new_notes {
-                self.add_note(commitment, data);
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::{
+        keys::SpendKey,
+        merkle::TreeExt,
+        rdsa::{SigningKey, SpendAuth, VerificationKey},
+        Zero,
+    };
+    use penumbra_stake::FundingStream;
+    use rand_core::OsRng;
 
-        // Unconditionally, insert all nullifiers spent in this transaction into the spent set to
-        // prevent double-spends, regardless of quarantine status.
-        for nullifier in transaction.spent_nullifiers {
-            self.spent_nullifiers.insert(nullifier);
-        }
+    use super::*;
 
-        // Tally the delegation changes in this transaction
-        for (identity_key, delegation_change) in transaction.delegation_changes {
-            *self.delegation_changes.entry(identity_key).or_insert(0) += delegation_change;
+    fn identity_key() -> IdentityKey {
+        IdentityKey(VerificationKey::from(&SigningKey::<SpendAuth>::new(OsRng)))
+    }
+
+    fn verified(id: u8, effects: StateEffects) -> VerifiedTransaction {
+        VerifiedTransaction {
+            id: [id; 32],
+            shape: Shape {
+                spends: 0,
+                outputs: 0,
+            },
+            effects,
         }
     }
+
+    #[test]
+    fn applies_effects() {
+        let mut block = PendingBlock::new(NoteCommitmentTree::new(0));
+        let validator = identity_key();
+        let nullifier = Nullifier(Fq::zero());
+
+        block.add_transaction(verified(
+            1,
+            StateEffects {
+                spent_nullifiers: [nullifier.clone()].into_iter().collect(),
+                delegation_changes: [(validator.clone(), 10)].into_iter().collect(),
+                fees: [(*STAKING_TOKEN_ASSET_ID, 5)].into_iter().collect(),
+                ..Default::default()
+            },
+        ));
+        block.add_transaction(verified(
+            2,
+            StateEffects {
+                delegation_changes: [(validator.clone(), -4)].into_iter().collect(),
+                undelegations: [(validator.clone(), 4)].into_iter().collect(),
+                fees: [(*STAKING_TOKEN_ASSET_ID, 3)].into_iter().collect(),
+                ..Default::default()
+            },
+        ));
+
+        assert_eq!(block.transaction_ids, vec![[1; 32], [2; 32]]);
+        assert_eq!(block.transaction_shapes.values().sum::<u64>(), 2);
+        assert!(block.spent_nullifiers.contains(&nullifier));
+        assert_eq!(block.delegation_changes[&validator], 6);
+        assert_eq!(block.fee_totals[&*STAKING_TOKEN_ASSET_ID], 8);
+        // Only the transaction with an undelegation is quarantined.
+        assert_eq!(block.quarantine.len(), 1);
+        assert_eq!(block.quarantine[0].unbondings[&validator], 4);
+    }
+
+    #[test]
+    fn accumulates_undelegations_across_validators() {
+        let mut block = PendingBlock::new(NoteCommitmentTree::new(0));
+        let (a, b, c) = (identity_key(), identity_key(), identity_key());
+
+        // One transaction undelegates from two validators and delegates to a third...
+        block.add_transaction(verified(
+            1,
+            StateEffects {
+                delegation_changes: [(a.clone(), -10), (b.clone(), -20), (c.clone(), 25)]
+                    .into_iter()
+                    .collect(),
+                undelegations: [(a.clone(), 10), (b.clone(), 20)].into_iter().collect(),
+                ..Default::default()
+            },
+        ));
+        // ...and another delegates to the first and undelegates from the third.
+        block.add_transaction(verified(
+            2,
+            StateEffects {
+                delegation_changes: [(a.clone(), 10), (c.clone(), -5)].into_iter().collect(),
+                undelegations: [(c.clone(), 5)].into_iter().collect(),
+                ..Default::default()
+            },
+        ));
+
+        // A net change of zero is still recorded, since the validator saw (un)delegations.
+        assert_eq!(block.delegation_changes[&a], 0);
+        assert_eq!(block.delegation_changes[&b], -20);
+        assert_eq!(block.delegation_changes[&c], 20);
+
+        // Each transaction is quarantined once, relative to all the validators it undelegated from.
+        assert_eq!(block.quarantine.len(), 2);
+        assert_eq!(
+            block.quarantine[0].unbondings,
+            [(a, 10), (b, 20)].into_iter().collect()
+        );
+        assert_eq!(
+            block.quarantine[1].unbondings,
+            [(c, 5)].into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn quarantines_first_redelegations_without_unbonding() {
+        let mut block = PendingBlock::new(NoteCommitmentTree::new(0));
+        let (a, b) = (identity_key(), identity_key());
+
+        // A delegator key's first redelegation is quarantined relative to the validator it left,
+        // but doesn't unbond any stake from it...
+        block.add_transaction(verified(
+            1,
+            StateEffects {
+                delegation_changes: [(a.clone(), -10), (b.clone(), 10)].into_iter().collect(),
+                redelegations: [([1; 32], 1)].into_iter().collect(),
+                quarantined_redelegations: [a.clone()].into_iter().collect(),
+                ..Default::default()
+            },
+        ));
+        // ...unless the same transaction also undelegates from it...
+        block.add_transaction(verified(
+            2,
+            StateEffects {
+                delegation_changes: [(a.clone(), -15), (b.clone(), 10)].into_iter().collect(),
+                undelegations: [(a.clone(), 5)].into_iter().collect(),
+                redelegations: [([2; 32], 1)].into_iter().collect(),
+                quarantined_redelegations: [a.clone()].into_iter().collect(),
+                ..Default::default()
+            },
+        ));
+        // ...while later redelegations aren't quarantined at all.
+        block.add_transaction(verified(
+            3,
+            StateEffects {
+                delegation_changes: [(a.clone(), -10), (b, 10)].into_iter().collect(),
+                redelegations: [([3; 32], 1)].into_iter().collect(),
+                ..Default::default()
+            },
+        ));
+
+        assert_eq!(block.quarantine.len(), 2);
+        assert_eq!(
+            block.quarantine[0].unbondings,
+            [(a.clone(), 0)].into_iter().collect()
+        );
+        assert_eq!(
+            block.quarantine[1].unbondings,
+            [(a, 5)].into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn detects_conflicts() {
+        let mut block = PendingBlock::new(NoteCommitmentTree::new(0));
+        let (old_identity_key, new_identity_key) = (identity_key(), identity_key());
+        let nullifier = Nullifier(Fq::zero());
+
+        let spend = StateEffects {
+            spent_nullifiers: [nullifier].into_iter().collect(),
+            ..Default::default()
+        };
+        let migration = StateEffects {
+            validator_migrations: [(old_identity_key, new_identity_key.clone())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        assert!(block.check_conflicts(&spend).is_ok());
+        block.add_transaction(verified(1, spend.clone()));
+        block.add_transaction(verified(2, migration));
+
+        // A double spend within the block is rejected...
+        assert!(block.check_conflicts(&spend).is_err());
+        // ... as is another migration to the same new identity key.
+        let other_migration = StateEffects {
+            validator_migrations: [(identity_key(), new_identity_key)].into_iter().collect(),
+            ..Default::default()
+        };
+        assert!(block.check_conflicts(&other_migration).is_err());
+
+        // A transaction without nullifiers can still only be included once.
+        let definition = StateEffects {
+            recent_transaction: Some(([3; 32], block.note_commitment_tree.root2())),
+            ..Default::default()
+        };
+        assert!(block.check_conflicts(&definition).is_ok());
+        block.add_transaction(verified(3, definition.clone()));
+        assert!(block.check_conflicts(&definition).is_err());
+    }
+
+    #[test]
+    fn epoch_reward_notes_are_independent_of_proposer_rewards() {
+        let validator = identity_key();
+        let (address, _dtk) = SpendKey::generate(OsRng)
+            .full_viewing_key()
+            .incoming()
+            .payment_address(0u64.into());
+        let funding_streams = FundingStreams::try_from(vec![FundingStream {
+            recipient: FundingStreamRecipient::Address(address),
+            rate_bps: 100,
+        }])
+        .unwrap();
+
+        // The epoch's rewards are paid the same at its last block whether or not that block's
+        // proposer was paid first...
+        let mut plain = PendingBlock::new(NoteCommitmentTree::new(0)).end(9, 10);
+        plain.add_validator_reward_note(1_000, address, validator.clone());
+        let mut proposed = PendingBlock::new(NoteCommitmentTree::new(0)).end(9, 10);
+        assert_eq!(
+            proposed.add_proposer_reward(500, &validator, &funding_streams),
+            500
+        );
+        proposed.add_validator_reward_note(1_000, address, validator.clone());
+
+        assert_eq!(plain.notes.len(), 1);
+        assert_eq!(proposed.notes.len(), 2);
+        assert!(plain
+            .notes
+            .keys()
+            .all(|commitment| proposed.notes.contains_key(commitment)));
+
+        // ...while the proposer's rewards in different blocks get distinct notes.
+        let mut other = PendingBlock::new(NoteCommitmentTree::new(0)).end(8, 10);
+        other.add_proposer_reward(500, &validator, &funding_streams);
+        assert!(other
+            .notes
+            .keys()
+            .all(|commitment| !proposed.notes.contains_key(commitment)));
+    }
 }
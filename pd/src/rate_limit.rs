@@ -0,0 +1,126 @@
+//! A limit on the rate of requests a gRPC service accepts, set by the live configuration.
+
+use std::{
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use futures::future::{self, Either, Ready};
+use tonic::{body::BoxBody, Status};
+use tower::{Layer, Service};
+
+use crate::LiveConfig;
+
+/// Limits the requests to the services it wraps to the configured `rpc_rate_limit` per second,
+/// answering the rest with `RESOURCE_EXHAUSTED`.
+///
+/// The limit is shared by every connection to the server the layer is added to, and a streaming
+/// request counts once, however long it streams for.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    config: LiveConfig,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl RateLimitLayer {
+    pub fn new(config: LiveConfig) -> Self {
+        Self {
+            config,
+            bucket: Arc::new(Mutex::new(Bucket {
+                // Clamped to the limit on the first request.
+                tokens: f64::INFINITY,
+                last_refill: Instant::now(),
+            })),
+        }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A token bucket holding up to a second's worth of requests.
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn try_take(&mut self, per_second: u32) -> bool {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last_refill).as_secs_f64() * per_second as f64;
+        self.tokens = (self.tokens + refill).min(per_second as f64);
+        self.last_refill = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimit<S> {
+    inner: S,
+    layer: RateLimitLayer,
+}
+
+impl<S, B> Service<http::Request<B>> for RateLimit<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<BoxBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Either<S::Future, Ready<Result<S::Response, S::Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        if let Some(limit) = self.layer.config.rpc_rate_limit() {
+            if !self.layer.bucket.lock().unwrap().try_take(limit) {
+                tracing::debug!(path = %req.uri().path(), "rate limited request");
+                return Either::Right(future::ready(Ok(Status::resource_exhausted(format!(
+                    "this node accepts at most {} requests per second",
+                    limit
+                ))
+                .to_http())));
+            }
+        }
+        Either::Left(self.inner.call(req))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn bucket_allows_a_burst_of_one_second_then_refills() {
+        let mut bucket = Bucket {
+            tokens: f64::INFINITY,
+            last_refill: Instant::now(),
+        };
+        for _ in 0..10 {
+            assert!(bucket.try_take(10));
+        }
+        assert!(!bucket.try_take(10));
+
+        bucket.last_refill -= Duration::from_millis(250);
+        assert!(bucket.try_take(10));
+        assert!(bucket.try_take(10));
+        assert!(!bucket.try_take(10));
+    }
+}
@@ -3,7 +3,7 @@ use tendermint::abci::{
     request::{BeginBlock, CheckTx, DeliverTx, EndBlock, InitChain, Query},
     ConsensusRequest, InfoRequest, MempoolRequest, Request, SnapshotRequest,
 };
-use tracing::error_span;
+use tracing::{error_span, field};
 
 pub trait RequestExt {
     /// Create a [`tracing::Span`] for this request, including the request name
@@ -20,7 +20,7 @@ impl RequestExt for ConsensusRequest {
                 error_span!(parent: &p, "BeginBlock", height = ?header.height, hash = ?hex::encode(hash.as_ref()))
             }
             ConsensusRequest::DeliverTx(DeliverTx { tx }) => {
-                error_span!(parent: &p, "DeliverTx", txid = ?hex::encode(&Sha256::digest(tx.as_ref())))
+                error_span!(parent: &p, "DeliverTx", txid = ?hex::encode(&Sha256::digest(tx.as_ref())), traceparent = field::Empty)
             }
             ConsensusRequest::EndBlock(EndBlock { height }) => {
                 error_span!(parent: &p, "EndBlock", ?height)
@@ -39,7 +39,7 @@ impl RequestExt for MempoolRequest {
         let p = error_span!("abci");
         match self {
             MempoolRequest::CheckTx(CheckTx { kind, tx }) => {
-                error_span!(parent: &p, "CheckTx", ?kind, txid = ?hex::encode(&Sha256::digest(tx.as_ref())))
+                error_span!(parent: &p, "CheckTx", ?kind, txid = ?hex::encode(&Sha256::digest(tx.as_ref())), traceparent = field::Empty)
             }
         }
     }
@@ -92,13 +92,13 @@ impl RequestExt for Request {
                 error_span!(parent: &p, "Query", ?path, ?height, prove)
             }
             Request::CheckTx(CheckTx { kind, tx }) => {
-                error_span!(parent: &p, "CheckTx", ?kind, txid = ?hex::encode(&Sha256::digest(tx.as_ref())))
+                error_span!(parent: &p, "CheckTx", ?kind, txid = ?hex::encode(&Sha256::digest(tx.as_ref())), traceparent = field::Empty)
             }
             Request::BeginBlock(BeginBlock { hash, header, .. }) => {
                 error_span!(parent: &p, "BeginBlock", height = ?header.height, hash = ?hex::encode(hash.as_ref()))
             }
             Request::DeliverTx(DeliverTx { tx }) => {
-                error_span!(parent: &p, "DeliverTx", txid = ?hex::encode(&Sha256::digest(tx.as_ref())))
+                error_span!(parent: &p, "DeliverTx", txid = ?hex::encode(&Sha256::digest(tx.as_ref())), traceparent = field::Empty)
             }
             Request::EndBlock(EndBlock { height }) => error_span!(parent: &p, "EndBlock", ?height),
             Request::Commit => error_span!(parent: &p, "Commit"),
@@ -0,0 +1,15 @@
+use penumbra_chain::ResponseCode;
+
+use crate::{circuit_breaker::CircuitBreakerTripped, verify::StaleAnchor};
+
+/// The `CheckTx` and `DeliverTx` response code for a transaction rejected with `error`.
+pub fn for_error(error: &anyhow::Error) -> u32 {
+    let code = if error.is::<CircuitBreakerTripped>() {
+        ResponseCode::CircuitBreakerTripped
+    } else if error.is::<StaleAnchor>() {
+        ResponseCode::StaleAnchor
+    } else {
+        ResponseCode::Rejected
+    };
+    code.code()
+}
@@ -0,0 +1,255 @@
+//! An opt-in scanning service, for clients willing to share a full viewing key with their own
+//! `pd`: the node trial-decrypts each block on the client's behalf, and streams only the client's
+//! notes and the nullifiers spending them.
+//!
+//! This is separate from the public wallet services, which never learn anything about a client's
+//! keys, and is only served to clients authenticated with a token the operator configured for
+//! their key.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use penumbra_crypto::{keys::FullViewingKey, merkle, note, Note, Nullifier};
+use penumbra_proto::{
+    light_wallet::{CompactBlock, StateFragment},
+    scanning::{scanning_server, ScanBlockRangeRequest, ScannedBlock, ScannedNote},
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::{mpsc, Mutex};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Status;
+use tracing::{instrument, Instrument, Span};
+
+use crate::state;
+
+/// A client configured by the operator, in the scanning clients file.
+#[derive(Deserialize)]
+struct ClientConfig {
+    /// The bearer token the client authenticates with.
+    token: String,
+    /// The client's full viewing key, in bech32.
+    full_viewing_key: String,
+}
+
+/// What the service has learned about a client's notes so far.
+struct ClientScan {
+    fvk: FullViewingKey,
+    /// The height of the next block to scan.
+    next_height: u64,
+    /// The nullifiers of every note found for the client.
+    ///
+    /// A note may be spent long after it was received, so every block up to `next_height` has been
+    /// scanned for the client's notes, whatever range the client asked for.
+    nullifiers: BTreeSet<Nullifier>,
+}
+
+/// The scanning service, for the clients configured by the operator.
+#[derive(Clone)]
+pub struct Scanning {
+    state: state::Reader,
+    /// The clients, by the SHA-256 hash of their token, so that looking them up doesn't depend on
+    /// how much of the token is right.
+    clients: Arc<BTreeMap<[u8; 32], Arc<Mutex<ClientScan>>>>,
+}
+
+impl Scanning {
+    /// Loads the clients to serve from a JSON file listing each client's `token` and
+    /// `full_viewing_key`.
+    pub fn load(state: state::Reader, clients_path: &Path) -> Result<Self> {
+        let configs: Vec<ClientConfig> = serde_json::from_slice(
+            &std::fs::read(clients_path)
+                .with_context(|| format!("could not read {}", clients_path.display()))?,
+        )
+        .context("invalid scanning clients file")?;
+
+        let mut clients = BTreeMap::new();
+        for config in configs {
+            if config.token.is_empty() {
+                return Err(anyhow::anyhow!("scanning client tokens must not be empty"));
+            }
+            let fvk = config
+                .full_viewing_key
+                .parse()
+                .context("invalid full viewing key")?;
+            let client = ClientScan {
+                fvk,
+                next_height: 0,
+                nullifiers: BTreeSet::new(),
+            };
+            if clients
+                .insert(token_hash(&config.token), Arc::new(Mutex::new(client)))
+                .is_some()
+            {
+                return Err(anyhow::anyhow!("scanning client tokens must be distinct"));
+            }
+        }
+        tracing::info!(clients = clients.len(), "loaded scanning clients");
+
+        Ok(Self {
+            state,
+            clients: Arc::new(clients),
+        })
+    }
+
+    /// Returns the scan state of the client authenticated by a request's bearer token.
+    fn authenticate<T>(
+        &self,
+        request: &tonic::Request<T>,
+    ) -> Result<Arc<Mutex<ClientScan>>, Status> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| Status::unauthenticated("missing bearer token"))?;
+        self.clients
+            .get(&token_hash(token))
+            .cloned()
+            .ok_or_else(|| Status::unauthenticated("unknown bearer token"))
+    }
+}
+
+fn token_hash(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+impl ClientScan {
+    /// Scans a compact block for the client's notes and spends, remembering the nullifiers of
+    /// any new notes.
+    fn scan(&mut self, block: CompactBlock) -> Result<ScannedBlock> {
+        let mut notes = Vec::new();
+        for (i, fragment) in block.fragments.into_iter().enumerate() {
+            let position = block.start_position + i as u64;
+            if let Some(note_commitment) = self.try_decrypt(&fragment)? {
+                let nullifier = self
+                    .fvk
+                    .derive_nullifier(merkle::Position::from(position as usize), &note_commitment);
+                self.nullifiers.insert(nullifier);
+                notes.push(ScannedNote {
+                    fragment: Some(fragment),
+                    position,
+                });
+            }
+        }
+
+        let mut spent_nullifiers = Vec::new();
+        for nullifier in block.nullifiers {
+            let parsed = Nullifier::try_from(&nullifier[..])?;
+            if self.nullifiers.contains(&parsed) {
+                spent_nullifiers.push(nullifier);
+            }
+        }
+
+        if block.height >= self.next_height {
+            self.next_height = block.height + 1;
+        }
+
+        Ok(ScannedBlock {
+            height: block.height,
+            notes,
+            spent_nullifiers,
+            nct_root: block.nct_root,
+        })
+    }
+
+    /// Returns the note commitment of a fragment, if the client's key decrypts it.
+    fn try_decrypt(&self, fragment: &StateFragment) -> Result<Option<note::Commitment>> {
+        let ephemeral_key = fragment.ephemeral_key[..]
+            .try_into()
+            .context("invalid ephemeral key")?;
+        match Note::decrypt(
+            &fragment.encrypted_note,
+            self.fvk.incoming(),
+            &ephemeral_key,
+        ) {
+            Ok(_) => Ok(Some(
+                fragment.note_commitment[..]
+                    .try_into()
+                    .context("invalid note commitment")?,
+            )),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl scanning_server::Scanning for Scanning {
+    type ScanBlockRangeStream = ReceiverStream<Result<ScannedBlock, Status>>;
+
+    #[instrument(
+        skip(self, request),
+        fields(
+            start_height = request.get_ref().start_height,
+            end_height = request.get_ref().end_height,
+        ),
+    )]
+    async fn scan_block_range(
+        &self,
+        request: tonic::Request<ScanBlockRangeRequest>,
+    ) -> Result<tonic::Response<Self::ScanBlockRangeStream>, Status> {
+        let client = self.authenticate(&request)?;
+        self.state.check_chain_id(&request.get_ref().chain_id)?;
+        let ScanBlockRangeRequest {
+            start_height,
+            end_height,
+            ..
+        } = request.into_inner();
+
+        let (tx, rx) = mpsc::channel(16);
+        let current_height = match self
+            .state
+            .latest_block_info()
+            .await
+            .map_err(|_| Status::unavailable("database error"))?
+        {
+            Some(latest) => latest.height as u64,
+            // Not even the genesis block has been committed yet.
+            None => return Ok(tonic::Response::new(ReceiverStream::new(rx))),
+        };
+        let end_height = if end_height == 0 {
+            current_height
+        } else {
+            std::cmp::min(end_height, current_height)
+        };
+
+        let state = self.state.clone();
+        tokio::spawn(
+            async move {
+                // Each client's requests are scanned one at a time, since they share what has been
+                // learned about the client's notes.
+                let mut client = client.lock().await;
+
+                // Notes received before the requested range may be spent within it, so first catch
+                // up on any blocks that haven't been scanned for the client yet.
+                let scan_start = std::cmp::min(client.next_height, start_height);
+                let mut blocks = state.compact_blocks(scan_start as i64, end_height as i64);
+                while let Some(block) = blocks.next().await {
+                    let scanned = match block.and_then(|block| client.scan(block)) {
+                        Ok(scanned) => scanned,
+                        Err(e) => {
+                            tracing::error!(?e, "error scanning block");
+                            let _ = tx.send(Err(Status::internal("error scanning block"))).await;
+                            return;
+                        }
+                    };
+                    if scanned.height < start_height {
+                        continue;
+                    }
+                    if tx.send(Ok(scanned)).await.is_err() {
+                        // The client hung up.
+                        return;
+                    }
+                }
+            }
+            .instrument(Span::current()),
+        );
+
+        Ok(tonic::Response::new(ReceiverStream::new(rx)))
+    }
+}
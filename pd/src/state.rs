@@ -3,12 +3,22 @@ use sqlx::postgres::PgPoolOptions;
 use tokio::sync::watch;
 use tracing::instrument;
 
+mod changefeed;
 mod jellyfish;
+mod mempool_store;
 mod reader;
+mod replica;
+mod snapshot;
+mod witness_tree;
 mod writer;
 
-pub use reader::Reader;
-pub use writer::Writer;
+pub mod state_key;
+
+pub use mempool_store::MempoolStore;
+pub use reader::{Reader, ValidatorInfoSnapshot};
+pub use replica::{follower, replica};
+pub use snapshot::StateSnapshot;
+pub use writer::{BlockWrites, PreparedBlock, Writer};
 
 #[instrument]
 pub async fn new(uri: &str) -> Result<(Reader, Writer)> {
@@ -43,7 +53,9 @@ pub async fn new(uri: &str) -> Result<(Reader, Writer)> {
     // call below.
     let (chain_params_tx, chain_params_rx) = watch::channel(Default::default());
     let (height_tx, height_rx) = watch::channel(Default::default());
+    let (committed_height_tx, committed_height_rx) = watch::channel(Default::default());
     let (next_rate_data_tx, next_rate_data_rx) = watch::channel(Default::default());
+    let (validator_info_tx, validator_info_rx) = watch::channel(Default::default());
     let (valid_anchors_tx, valid_anchors_rx) = watch::channel(Default::default());
 
     let reader = Reader {
@@ -51,8 +63,11 @@ pub async fn new(uri: &str) -> Result<(Reader, Writer)> {
         //tmp: reader_tmp,
         chain_params_rx,
         height_rx,
+        committed_height_rx,
         next_rate_data_rx,
+        validator_info_rx,
         valid_anchors_rx,
+        witness_tree: Default::default(),
     };
 
     // Create a private reader instance for the writer's use
@@ -66,8 +81,11 @@ pub async fn new(uri: &str) -> Result<(Reader, Writer)> {
         //tmp: writer_tmp,
         chain_params_tx,
         height_tx,
+        committed_height_tx,
         next_rate_data_tx,
+        validator_info_tx,
         valid_anchors_tx,
+        changefeed: None,
     };
 
     writer.init_caches().await?;
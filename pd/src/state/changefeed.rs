@@ -0,0 +1,304 @@
+use anyhow::{Context, Result};
+use penumbra_crypto::{
+    merkle::{NoteCommitmentTree, Tree},
+    note,
+};
+use penumbra_proto::changefeed::{RowChange, WriteBatch};
+use serde_json::{Map, Value};
+use sqlx::{query, Pool, Postgres, Transaction};
+
+use super::{state_key, Reader, Writer};
+
+/// The tables recorded in the changefeed, which are the only ones a write batch may change.
+const TABLES: &[&str] = &[
+    "blobs",
+    "jmt",
+    "assets",
+    "blocks",
+    "nullifiers",
+    "notes",
+    "validators",
+    "validator_fundingstreams",
+    "base_rates",
+    "validator_rates",
+    "delegation_changes",
+    "quarantined_notes",
+    "quarantined_nullifiers",
+    "validator_migrations",
+    "validator_slashings",
+    "denom_metadata",
+    "quarantine_releases",
+    "block_anonymity_stats",
+    "transaction_shapes",
+    "block_fees",
+    "quarantined_unbondings",
+    "community_pool_deposits",
+    "redelegations",
+    "supply_history",
+    "recent_transactions",
+    "reverted_nullifiers",
+];
+
+impl Reader {
+    /// Returns the lowest height recorded in the changefeed, if any.
+    pub async fn changefeed_start(&self) -> Result<Option<u64>> {
+        let mut conn = self.pool.acquire().await?;
+        let row = query!(r#"SELECT MIN(height) AS "height" FROM changefeed"#)
+            .fetch_one(&mut conn)
+            .await?;
+        Ok(row.height.map(|height| height as u64))
+    }
+
+    /// Returns the highest height recorded in the changefeed, if any.
+    pub async fn changefeed_end(&self) -> Result<Option<u64>> {
+        let mut conn = self.pool.acquire().await?;
+        let row = query!(r#"SELECT MAX(height) AS "height" FROM changefeed"#)
+            .fetch_one(&mut conn)
+            .await?;
+        Ok(row.height.map(|height| height as u64))
+    }
+
+    /// Returns the rows written at `height`, as recorded in the changefeed.
+    pub async fn write_batch(&self, height: u64) -> Result<WriteBatch> {
+        let mut conn = self.pool.acquire().await?;
+        let rows = query!(
+            r#"SELECT table_name, old_row::text AS "old_row", new_row::text AS "new_row"
+            FROM changefeed WHERE height = $1 ORDER BY seq"#,
+            height as i64
+        )
+        .fetch_all(&mut conn)
+        .await?;
+
+        Ok(WriteBatch {
+            height,
+            changes: rows
+                .into_iter()
+                .map(|row| RowChange {
+                    table: row.table_name,
+                    old_row: row.old_row.unwrap_or_default(),
+                    new_row: row.new_row.unwrap_or_default(),
+                })
+                .collect(),
+        })
+    }
+}
+
+impl Writer {
+    /// Records the rows written by each commit in the changefeed, for followers and indexers,
+    /// keeping the last `retention` blocks of changes.
+    pub fn with_changefeed(mut self, retention: u64) -> Self {
+        self.changefeed = Some(retention);
+        self
+    }
+}
+
+/// Records every row written in the rest of `dbtx` in the changefeed, at `height`.
+pub(super) async fn record_changes(
+    dbtx: &mut Transaction<'_, Postgres>,
+    height: u64,
+) -> Result<()> {
+    // The setting is local to the transaction, and read by the changefeed triggers.
+    query!(
+        "SELECT set_config('penumbra.changefeed_height', $1, true)",
+        height.to_string()
+    )
+    .fetch_one(&mut *dbtx)
+    .await?;
+    Ok(())
+}
+
+/// Deletes the changes recorded more than `retention` blocks before `height`.
+///
+/// Followers further behind than that can no longer catch up from the changefeed, and must start
+/// again from a copy of the database.
+pub(super) async fn prune(
+    dbtx: &mut Transaction<'_, Postgres>,
+    height: u64,
+    retention: u64,
+) -> Result<()> {
+    query!(
+        "DELETE FROM changefeed WHERE height < $1",
+        height.saturating_sub(retention) as i64
+    )
+    .execute(&mut *dbtx)
+    .await?;
+    Ok(())
+}
+
+/// Applies a write batch from another node's changefeed, and records it in this node's changefeed,
+/// keeping the last `retention` blocks of changes, so that it can be followed in turn.
+pub(super) async fn apply(pool: &Pool<Postgres>, batch: &WriteBatch, retention: u64) -> Result<()> {
+    let mut dbtx = pool.begin().await?;
+    record_changes(&mut dbtx, batch.height).await?;
+    let mut nct_changed = false;
+    for change in &batch.changes {
+        // Only the key of the note commitment tree is recorded, so it's rebuilt below instead.
+        if is_note_commitment_tree(change)? {
+            nct_changed = true;
+            continue;
+        }
+        apply_change(&mut dbtx, change).await.with_context(|| {
+            format!(
+                "could not apply change to {:?} at height {}",
+                change.table, batch.height
+            )
+        })?;
+    }
+    if nct_changed {
+        update_note_commitment_tree(&mut dbtx, batch.height)
+            .await
+            .with_context(|| {
+                format!(
+                    "could not update the note commitment tree at height {}",
+                    batch.height
+                )
+            })?;
+    }
+    prune(&mut dbtx, batch.height, retention).await?;
+    dbtx.commit().await?;
+    Ok(())
+}
+
+/// Whether `change` is to the note commitment tree's blob.
+fn is_note_commitment_tree(change: &RowChange) -> Result<bool> {
+    if change.table != "blobs" {
+        return Ok(false);
+    }
+    let row = match (parse_row(&change.old_row)?, parse_row(&change.new_row)?) {
+        (_, Some(row)) | (Some(row), None) => row,
+        (None, None) => return Ok(false),
+    };
+    Ok(row.get("id").and_then(Value::as_str) == Some(state_key::note_commitment_tree().as_str()))
+}
+
+/// Appends the notes added at `height` to the stored note commitment tree.
+async fn update_note_commitment_tree(
+    dbtx: &mut Transaction<'_, Postgres>,
+    height: u64,
+) -> Result<()> {
+    let key = state_key::note_commitment_tree();
+    let stored = query!("SELECT data FROM blobs WHERE id = $1", key.as_str())
+        .fetch_optional(&mut *dbtx)
+        .await?;
+    let mut tree: NoteCommitmentTree = match stored {
+        Some(row) => bincode::deserialize(&row.data)?,
+        None => NoteCommitmentTree::new(0),
+    };
+
+    let notes = query!(
+        "SELECT note_commitment FROM notes WHERE height = $1 ORDER BY position ASC",
+        height as i64
+    )
+    .fetch_all(&mut *dbtx)
+    .await?;
+    for row in notes {
+        tree.append(&note::Commitment::try_from(&*row.note_commitment)?);
+    }
+
+    query!(
+        "INSERT INTO blobs (id, data) VALUES ($1, $2) ON CONFLICT (id) DO UPDATE SET data = $2",
+        key.as_str(),
+        &bincode::serialize(&tree)?[..]
+    )
+    .execute(&mut *dbtx)
+    .await?;
+    Ok(())
+}
+
+async fn apply_change(dbtx: &mut Transaction<'_, Postgres>, change: &RowChange) -> Result<()> {
+    // Table and column names are interpolated into the queries below, so only accept known
+    // tables, and plain identifiers as columns.
+    let table = TABLES
+        .iter()
+        .find(|table| **table == change.table)
+        .ok_or_else(|| anyhow::anyhow!("unknown table"))?;
+
+    let changed = match (parse_row(&change.old_row)?, parse_row(&change.new_row)?) {
+        (None, Some(_)) => sqlx::query(&format!(
+            "INSERT INTO {0} SELECT * FROM jsonb_populate_record(NULL::{0}, $1::jsonb)",
+            table
+        ))
+        .bind(&change.new_row)
+        .execute(&mut *dbtx)
+        .await?
+        .rows_affected(),
+        (Some(old_row), None) => sqlx::query(&format!(
+            "DELETE FROM {} WHERE ctid = ({})",
+            table,
+            find_row(table, &old_row)?
+        ))
+        .bind(&change.old_row)
+        .execute(&mut *dbtx)
+        .await?
+        .rows_affected(),
+        (Some(old_row), Some(new_row)) => {
+            let columns = new_row
+                .keys()
+                .map(|column| identifier(column))
+                .collect::<Result<Vec<_>>>()?
+                .join(", ");
+            sqlx::query(&format!(
+                "UPDATE {0} SET ({1}) = (
+                    SELECT {1} FROM jsonb_populate_record(NULL::{0}, $2::jsonb)
+                ) WHERE ctid = ({2})",
+                table,
+                columns,
+                find_row(table, &old_row)?
+            ))
+            .bind(&change.old_row)
+            .bind(&change.new_row)
+            .execute(&mut *dbtx)
+            .await?
+            .rows_affected()
+        }
+        (None, None) => return Err(anyhow::anyhow!("change has neither an old nor a new row")),
+    };
+
+    if changed != 1 {
+        // The follower has diverged from the node it follows, so stop rather than compound it.
+        return Err(anyhow::anyhow!("changed {} rows, expected 1", changed));
+    }
+    Ok(())
+}
+
+/// Returns a query for the location of one row of `table` equal to the row bound to `$1`.
+fn find_row(table: &str, row: &Map<String, Value>) -> Result<String> {
+    // Compare column by column, rather than whole rows, so that indexes can be used.
+    let mut conditions = Vec::new();
+    for (column, value) in row {
+        let column = identifier(column)?;
+        conditions.push(if value.is_null() {
+            format!("old.{} IS NULL", column)
+        } else {
+            format!("old.{0} = r.{0}", column)
+        });
+    }
+    if conditions.is_empty() {
+        conditions.push("TRUE".to_string());
+    }
+    Ok(format!(
+        "SELECT old.ctid FROM {0} AS old, jsonb_populate_record(NULL::{0}, $1::jsonb) AS r
+        WHERE {1} LIMIT 1",
+        table,
+        conditions.join(" AND ")
+    ))
+}
+
+fn parse_row(row: &str) -> Result<Option<Map<String, Value>>> {
+    if row.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(row)?))
+}
+
+fn identifier(name: &str) -> Result<&str> {
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        Ok(name)
+    } else {
+        Err(anyhow::anyhow!("invalid column name {:?}", name))
+    }
+}
@@ -0,0 +1,59 @@
+use anyhow::Result;
+use sqlx::{query, Pool, Postgres};
+
+/// Persistent storage for transactions accepted into the mempool but not yet
+/// included in a block.
+///
+/// Tendermint's mempool only lives in memory, so without this, restarting the
+/// node drops any user transactions it had accepted.  Transactions are keyed by
+/// their transaction ID, and are removed by [`super::Writer::commit_block`] once
+/// they are included in a block.
+#[derive(Debug, Clone)]
+pub struct MempoolStore {
+    pub(super) pool: Pool<Postgres>,
+}
+
+impl MempoolStore {
+    /// Record a transaction that was accepted into the mempool.
+    pub async fn insert(&self, id: [u8; 32], transaction: &[u8]) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        query!(
+            "INSERT INTO mempool_transactions (id, transaction) VALUES ($1, $2)
+            ON CONFLICT (id) DO NOTHING",
+            &id[..],
+            transaction
+        )
+        .execute(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Forget about a transaction, e.g., because it is no longer valid.
+    pub async fn remove(&self, id: [u8; 32]) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        query!("DELETE FROM mempool_transactions WHERE id = $1", &id[..])
+            .execute(&mut conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Returns all persisted transactions, as `(id, encoded transaction)` pairs.
+    pub async fn transactions(&self) -> Result<Vec<([u8; 32], Vec<u8>)>> {
+        let mut conn = self.pool.acquire().await?;
+        let rows = query!("SELECT id, transaction FROM mempool_transactions")
+            .fetch_all(&mut conn)
+            .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let id: [u8; 32] = row
+                    .id
+                    .try_into()
+                    .map_err(|_| anyhow::anyhow!("invalid mempool transaction id"))?;
+                Ok((id, row.transaction))
+            })
+            .collect()
+    }
+}
@@ -3,6 +3,7 @@ use std::{
     collections::{BTreeMap, BTreeSet, VecDeque},
     pin::Pin,
     str::FromStr,
+    sync::Arc,
 };
 
 use anyhow::{Context, Result};
@@ -11,25 +12,40 @@ use futures::stream::{Stream, StreamExt, TryStreamExt};
 use penumbra_chain::params::ChainParams;
 use penumbra_crypto::{
     asset,
-    merkle::{self, NoteCommitmentTree},
-    note, Address, FieldExt, Fq, Nullifier,
+    merkle::{self, NoteCommitmentTree, PruneExt},
+    note, Address, Amount, FieldExt, Fq, Nullifier,
 };
 use penumbra_proto::{
     chain,
-    light_wallet::{CompactBlock, StateFragment},
-    thin_wallet::{Asset, TransactionDetail},
+    crypto::{Denom, DenomMetadata},
+    light_wallet::{Asset, CompactBlock, StateFragment},
+    thin_wallet::{
+        BlockAnonymityStats, EpochSupply, TransactionDetail, TransactionShapeCount,
+        ValidatorSlashing,
+    },
     Protobuf,
 };
 use penumbra_stake::{
-    BaseRateData, FundingStream, FundingStreams, IdentityKey, RateData, RateDataById, Validator,
-    ValidatorInfo, ValidatorState, ValidatorStateName, ValidatorStatus,
+    BaseRateData, FundingStream, FundingStreamRecipient, FundingStreams, IdentityKey, RateData,
+    RateDataById, RewardSource, Validator, ValidatorInfo, ValidatorState, ValidatorStateName,
+    ValidatorStatus,
 };
 use sqlx::{query, query_as, Pool, Postgres};
-use tendermint::block;
-use tokio::sync::watch;
+use tendermint::{block, consensus};
+use tokio::sync::{watch, Mutex};
 use tracing::instrument;
 
-use crate::{db::schema, genesis, pd_metrics::MetricsData, verify::NoteData};
+use super::{state_key, witness_tree::WitnessTree};
+use crate::{
+    db::schema,
+    genesis,
+    pd_metrics::{MetricsData, StorageMetricsData},
+    verify::NoteData,
+    COMPACT_BLOCK_VERSION,
+};
+
+/// A shared snapshot of the [`ValidatorInfo`] of every validator, by identity key.
+pub type ValidatorInfoSnapshot = Arc<BTreeMap<IdentityKey, ValidatorInfo>>;
 
 #[derive(Debug, Clone)]
 pub struct Reader {
@@ -37,8 +53,13 @@ pub struct Reader {
     //pub(super) tmp: evmap::ReadHandle<&'static str, String>,
     pub(super) chain_params_rx: watch::Receiver<ChainParams>,
     pub(super) height_rx: watch::Receiver<block::Height>,
+    pub(super) committed_height_rx: watch::Receiver<block::Height>,
     pub(super) next_rate_data_rx: watch::Receiver<RateDataById>,
+    pub(super) validator_info_rx: watch::Receiver<ValidatorInfoSnapshot>,
     pub(super) valid_anchors_rx: watch::Receiver<VecDeque<merkle::Root>>,
+    /// Every node of the note commitment tree, built on the first request for authentication
+    /// paths and extended by later ones.
+    pub(super) witness_tree: Arc<Mutex<WitnessTree>>,
 }
 
 impl Reader {
@@ -60,6 +81,23 @@ impl Reader {
         &self.height_rx
     }
 
+    /// Returns a borrowed [`watch::Receiver`] for the height of the latest block committed to
+    /// Tendermint, which is ahead of the [`height_rx`](Self::height_rx) while the block is still
+    /// being written to the database.
+    pub fn committed_height_rx(&self) -> &watch::Receiver<block::Height> {
+        &self.committed_height_rx
+    }
+
+    /// Waits until the latest block committed to Tendermint has been written to the database.
+    pub async fn wait_for_committed_block(&self) -> Result<()> {
+        let committed_height = *self.committed_height_rx.borrow();
+        let mut height_rx = self.height_rx.clone();
+        while *height_rx.borrow_and_update() < committed_height {
+            height_rx.changed().await?;
+        }
+        Ok(())
+    }
+
     /// Returns a borrowed [`watch::Receiver`] for the latest [`RateDataById`].
     ///
     /// This receiver can be used to access an in-memory copy of the latest data
@@ -69,6 +107,17 @@ impl Reader {
         &self.next_rate_data_rx
     }
 
+    /// Returns a borrowed [`watch::Receiver`] for the latest [`ValidatorInfoSnapshot`].
+    ///
+    /// This receiver can be used to access an in-memory copy of the latest data
+    /// without accessing the database, but note the warning on
+    /// [`watch::Receiver::borrow`] about potential deadlocks.  Cloning the
+    /// borrowed snapshot only clones the [`Arc`], so callers should do that
+    /// rather than hold the borrow.
+    pub fn validator_info_rx(&self) -> &watch::Receiver<ValidatorInfoSnapshot> {
+        &self.validator_info_rx
+    }
+
     /// Returns a borrowed [`watch::Receiver`] for the latest set of valid anchors.
     ///
     /// This receiver can be used to access an in-memory copy of the latest data
@@ -78,6 +127,13 @@ impl Reader {
         &self.valid_anchors_rx
     }
 
+    /// Returns a [`MempoolStore`](super::MempoolStore) sharing this reader's connection pool.
+    pub fn mempool_store(&self) -> super::MempoolStore {
+        super::MempoolStore {
+            pool: self.pool.clone(),
+        }
+    }
+
     /// Retrieve a nullifier if it exists.
     pub async fn nullifier(&self, nullifier: Nullifier) -> Result<Option<schema::NullifiersRow>> {
         let mut conn = self.pool.acquire().await?;
@@ -96,19 +152,18 @@ impl Reader {
     }
 
     /// Retrieve the current note commitment tree.
+    ///
+    /// The tree is reduced to its frontier, in case it was stored with witnesses it no longer needs.
     pub async fn note_commitment_tree(&self) -> Result<NoteCommitmentTree> {
-        let mut conn = self.pool.acquire().await?;
-        let note_commitment_tree = if let Some(schema::BlobsRow { data, .. }) = query_as!(
-            schema::BlobsRow,
-            "SELECT id, data FROM blobs WHERE id = 'nct';"
-        )
-        .fetch_optional(&mut conn)
-        .await?
-        {
-            bincode::deserialize(&data).context("Could not parse saved note commitment tree")?
-        } else {
-            NoteCommitmentTree::new(0)
-        };
+        let mut note_commitment_tree: NoteCommitmentTree =
+            if let Some(data) = self.blob(state_key::note_commitment_tree()).await? {
+                bincode::deserialize(&data).context("Could not parse saved note commitment tree")?
+            } else {
+                NoteCommitmentTree::new(0)
+            };
+        if note_commitment_tree.prune(0) {
+            tracing::info!("pruned stored note commitment tree to its frontier");
+        }
 
         Ok(note_commitment_tree)
     }
@@ -135,6 +190,66 @@ impl Reader {
         })
     }
 
+    /// Returns statistics about the database, for capacity planning.
+    ///
+    /// Row counts are Postgres' estimates, which are cheap to read, rather than exact counts.
+    pub async fn storage_metrics(&self) -> Result<StorageMetricsData> {
+        let mut conn = self.pool.acquire().await?;
+
+        let size = query!("SELECT pg_database_size(current_database()) AS size")
+            .fetch_one(&mut conn)
+            .await?;
+
+        let tables =
+            query!("SELECT relname::text AS name, n_live_tup AS rows FROM pg_stat_user_tables")
+                .fetch_all(&mut conn)
+                .await?;
+
+        let cache = query!(
+            "SELECT blks_hit, blks_read FROM pg_stat_database WHERE datname = current_database()"
+        )
+        .fetch_one(&mut conn)
+        .await?;
+
+        Ok(StorageMetricsData {
+            db_size_bytes: size.size.unwrap_or(0) as u64,
+            table_rows: tables
+                .into_iter()
+                .filter_map(|row| Some((row.name?, row.rows.unwrap_or(0) as u64)))
+                .collect(),
+            cache_hits: cache.blks_hit.unwrap_or(0) as u64,
+            cache_misses: cache.blks_read.unwrap_or(0) as u64,
+        })
+    }
+
+    /// Returns the number of quarantined notes that are also in the notes table.
+    pub async fn quarantined_notes_in_nct(&self) -> Result<u64> {
+        let mut conn = self.pool.acquire().await?;
+        let row = query!(
+            "SELECT COUNT(*) AS count
+            FROM quarantined_notes JOIN notes USING (note_commitment)"
+        )
+        .fetch_one(&mut conn)
+        .await?;
+
+        Ok(row.count.unwrap_or(0) as u64)
+    }
+
+    /// Returns the latest unbonding height of any quarantined note or nullifier.
+    pub async fn max_quarantine_unbonding_height(&self) -> Result<Option<u64>> {
+        let mut conn = self.pool.acquire().await?;
+        let row = query!(
+            "SELECT GREATEST(
+                (SELECT MAX(unbonding_height) FROM quarantined_notes),
+                (SELECT MAX(unbonding_height) FROM quarantined_nullifiers)
+            ) AS unbonding_height"
+        )
+        .fetch_one(&mut conn)
+        .await?;
+
+        Ok(row.unbonding_height.map(|height| height as u64))
+    }
+
     /// Returns the intersection of the provided nullifiers with the nullifiers
     /// in the database.
     pub async fn check_nullifiers(
@@ -167,16 +282,64 @@ impl Reader {
         Ok(existing)
     }
 
-    /// Retrieve the node genesis configuration.
-    pub async fn genesis_configuration(&self) -> Result<genesis::AppState> {
+    /// Returns which of the given transaction IDs belong to transactions included while their
+    /// anchor is still valid.
+    pub async fn recent_transactions(
+        &self,
+        ids: &BTreeSet<[u8; 32]>,
+    ) -> Result<BTreeSet<[u8; 32]>> {
         let mut conn = self.pool.acquire().await?;
-        let genesis_config = if let Some(schema::BlobsRow { data, .. }) = query_as!(
-            schema::BlobsRow,
-            "SELECT id, data FROM blobs WHERE id = 'gc';"
+
+        let ids = ids.iter().map(|id| id.to_vec()).collect::<Vec<_>>();
+        let recent = query!(
+            "SELECT id FROM recent_transactions WHERE id = ANY($1)",
+            &ids[..],
         )
-        .fetch_optional(&mut conn)
+        .fetch_all(&mut conn)
         .await?
-        {
+        .into_iter()
+        .map(|row| row.id.as_slice().try_into().expect("db data is valid"))
+        .collect();
+
+        Ok(recent)
+    }
+
+    /// Returns the epoch index in which each of the given delegator keys last redelegated, for
+    /// those that have redelegated.
+    pub async fn last_redelegations(
+        &self,
+        delegator_keys: &BTreeSet<[u8; 32]>,
+    ) -> Result<BTreeMap<[u8; 32], u64>> {
+        let mut conn = self.pool.acquire().await?;
+
+        let delegator_keys = delegator_keys
+            .iter()
+            .map(|key| key.to_vec())
+            .collect::<Vec<_>>();
+        let last_redelegations = query!(
+            "SELECT delegator_key, epoch_index FROM redelegations WHERE delegator_key = ANY($1)",
+            &delegator_keys[..],
+        )
+        .fetch_all(&mut conn)
+        .await?
+        .into_iter()
+        .map(|row| {
+            (
+                row.delegator_key
+                    .as_slice()
+                    .try_into()
+                    .expect("db data is valid"),
+                row.epoch_index as u64,
+            )
+        })
+        .collect();
+
+        Ok(last_redelegations)
+    }
+
+    /// Retrieve the node genesis configuration.
+    pub async fn genesis_configuration(&self) -> Result<genesis::AppState> {
+        let genesis_config = if let Some(data) = self.blob(state_key::genesis_config()).await? {
             serde_json::from_slice(&data).context("Could not parse saved genesis config")?
         } else {
             // This is only reached on the initial startup.
@@ -187,6 +350,55 @@ impl Reader {
         Ok(genesis_config)
     }
 
+    /// Retrieves the current chain parameters, which are the genesis chain parameters unless they
+    /// have since been updated.
+    pub async fn chain_params(&self) -> Result<ChainParams> {
+        match self.blob(state_key::chain_params()).await? {
+            Some(data) => {
+                serde_json::from_slice(&data).context("Could not parse saved chain params")
+            }
+            None => Ok(self.genesis_configuration().await?.chain_params),
+        }
+    }
+
+    /// Retrieves the consensus parameters last sent to Tendermint, if any.
+    pub async fn consensus_params(&self) -> Result<Option<consensus::Params>> {
+        self.blob(state_key::consensus_params())
+            .await?
+            .map(|data| {
+                serde_json::from_slice(&data).context("Could not parse saved consensus params")
+            })
+            .transpose()
+    }
+
+    /// Retrieves the blob stored under `key`, if any.
+    pub async fn blob(&self, key: state_key::Blob) -> Result<Option<Vec<u8>>> {
+        let mut conn = self.pool.acquire().await?;
+        let row = query_as!(
+            schema::BlobsRow,
+            "SELECT id, data FROM blobs WHERE id = $1",
+            key.as_str()
+        )
+        .fetch_optional(&mut conn)
+        .await?;
+
+        Ok(row.map(|row| row.data))
+    }
+
+    /// Retrieves every blob whose key starts with `prefix`, in order of their keys.
+    pub async fn blobs_with_prefix(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        let mut conn = self.pool.acquire().await?;
+        let rows = query_as!(
+            schema::BlobsRow,
+            "SELECT id, data FROM blobs WHERE starts_with(id, $1) ORDER BY id",
+            prefix
+        )
+        .fetch_all(&mut conn)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| (row.id, row.data)).collect())
+    }
+
     /// Retrieve the latest block info, if any.
     pub async fn latest_block_info(&self) -> Result<Option<schema::BlocksRow>> {
         let mut conn = self.pool.acquire().await?;
@@ -200,6 +412,24 @@ impl Reader {
         Ok(latest)
     }
 
+    /// Retrieves the heights and times, in milliseconds since the UNIX epoch, of up to `last` of
+    /// the latest blocks with a recorded time, newest first.
+    pub async fn recent_block_times(&self, last: u64) -> Result<Vec<(u64, i64)>> {
+        let mut conn = self.pool.acquire().await?;
+        let rows = query!(
+            r#"SELECT height, time_ms AS "time_ms!" FROM blocks
+            WHERE time_ms IS NOT NULL ORDER BY height DESC LIMIT $1"#,
+            last.min(i64::MAX as u64) as i64,
+        )
+        .fetch_all(&mut conn)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.height as u64, row.time_ms))
+            .collect())
+    }
+
     // retrieve the `last` latest node commitment tree anchors from the database
     pub async fn recent_anchors(&self, last: usize) -> Result<VecDeque<merkle::Root>> {
         let mut conn = self.pool.acquire().await?;
@@ -218,6 +448,120 @@ impl Reader {
         Ok(nct_vec)
     }
 
+    /// Computes authentication paths for the given notes in the note commitment tree as of the
+    /// latest block, returning that block's height and the tree's root along with the paths.
+    ///
+    /// Notes that aren't in the tree are skipped.  The paths are read off an in-memory copy of the
+    /// whole tree, which only has the notes added since the last request appended to it.
+    pub async fn note_authentication_paths(
+        &self,
+        commitments: &BTreeSet<note::Commitment>,
+    ) -> Result<(u64, merkle::Root, Vec<(note::Commitment, merkle::Path)>)> {
+        let height = u64::from(self.height().await?);
+
+        // Requests wait for each other here, so that the tree is only extended once per block.
+        let mut tree = self.witness_tree.lock().await;
+        if tree.height() < Some(height) {
+            let start = tree.height().map_or(-1, |tree_height| tree_height as i64);
+            let mut notes = query!(
+                "SELECT note_commitment, position FROM notes
+                WHERE height > $1 AND height <= $2
+                ORDER BY position ASC",
+                start,
+                height as i64,
+            )
+            .fetch(&self.pool);
+            while let Some(row) = notes.next().await {
+                let row = row?;
+                let expected = tree.len();
+                if row.position as u64 != expected {
+                    // Only happens if the notes table was rewritten behind our back, so start
+                    // over on the next request.
+                    *tree = WitnessTree::default();
+                    return Err(anyhow::anyhow!(
+                        "expected note at position {}, found one at {}",
+                        expected,
+                        row.position
+                    ));
+                }
+                tree.append(note::Commitment::try_from(&*row.note_commitment)?);
+            }
+            tree.set_height(height);
+        }
+        // Another request may have extended the tree past the height we read.
+        let height = tree.height().unwrap_or(height);
+
+        let paths = commitments
+            .iter()
+            .filter_map(|commitment| {
+                tree.authentication_path(commitment)
+                    .map(|path| (*commitment, path))
+            })
+            .collect();
+
+        Ok((height, tree.root(), paths))
+    }
+
+    /// Retrieves the anonymity statistics of the blocks in the given (inclusive) range of heights.
+    pub async fn anonymity_stats(
+        &self,
+        start_height: u64,
+        end_height: u64,
+    ) -> Result<Vec<BlockAnonymityStats>> {
+        let mut conn = self.pool.acquire().await?;
+
+        let rows = query!(
+            "SELECT height, notes_created, nullifiers_revealed, note_set_size, nullifier_set_size
+            FROM block_anonymity_stats
+            WHERE height >= $1 AND height <= $2
+            ORDER BY height ASC",
+            start_height as i64,
+            end_height as i64,
+        )
+        .fetch_all(&mut conn)
+        .await?;
+
+        let mut stats = rows
+            .into_iter()
+            .map(|row| {
+                (
+                    row.height,
+                    BlockAnonymityStats {
+                        height: row.height as u64,
+                        notes_created: row.notes_created as u64,
+                        nullifiers_revealed: row.nullifiers_revealed as u64,
+                        note_set_size: row.note_set_size as u64,
+                        nullifier_set_size: row.nullifier_set_size as u64,
+                        transaction_shapes: Vec::new(),
+                    },
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let shapes = query!(
+            "SELECT height, spends, outputs, count
+            FROM transaction_shapes
+            WHERE height >= $1 AND height <= $2
+            ORDER BY height ASC, spends ASC, outputs ASC",
+            start_height as i64,
+            end_height as i64,
+        )
+        .fetch_all(&mut conn)
+        .await?;
+
+        for row in shapes {
+            if let Some(block) = stats.get_mut(&row.height) {
+                block.transaction_shapes.push(TransactionShapeCount {
+                    spends: row.spends as u64,
+                    outputs: row.outputs as u64,
+                    count: row.count as u64,
+                });
+            }
+        }
+
+        Ok(stats.into_values().collect())
+    }
+
     /// Retrieve the latest block height.
     pub async fn height(&self) -> Result<block::Height> {
         Ok(self
@@ -289,6 +633,38 @@ impl Reader {
             .collect())
     }
 
+    /// Returns the rate data for a single validator in every epoch from
+    /// `start_epoch_index` to `end_epoch_index` inclusive, ordered by epoch.
+    pub async fn validator_rate_history(
+        &self,
+        identity_key: &IdentityKey,
+        start_epoch_index: u64,
+        end_epoch_index: u64,
+    ) -> Result<Vec<RateData>> {
+        let mut conn = self.pool.acquire().await?;
+        let rows = query!(
+            "SELECT epoch, validator_reward_rate, validator_exchange_rate
+            FROM validator_rates
+            WHERE identity_key = $1 AND epoch >= $2 AND epoch <= $3
+            ORDER BY epoch ASC",
+            identity_key.encode_to_vec(),
+            start_epoch_index as i64,
+            end_epoch_index.min(i64::MAX as u64) as i64,
+        )
+        .fetch_all(&mut conn)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| RateData {
+                identity_key: identity_key.clone(),
+                epoch_index: row.epoch as u64,
+                validator_exchange_rate: row.validator_exchange_rate as u64,
+                validator_reward_rate: row.validator_reward_rate as u64,
+            })
+            .collect())
+    }
+
     pub async fn next_rate_data(&self) -> Result<BTreeMap<IdentityKey, RateData>> {
         let mut conn = self.pool.acquire().await?;
         let rows = query!(
@@ -333,10 +709,14 @@ impl Reader {
 
         let mut streams = Vec::new();
         for row in rows.into_iter() {
-            let addr = row.address.parse::<Address>()?;
+            // Funding streams to the community pool have no address.
+            let recipient = match row.address {
+                Some(address) => FundingStreamRecipient::Address(address.parse::<Address>()?),
+                None => FundingStreamRecipient::CommunityPool,
+            };
 
             streams.push(FundingStream {
-                address: addr,
+                recipient,
                 rate_bps: row.rate_bps.try_into()?,
             })
         }
@@ -344,6 +724,37 @@ impl Reader {
         Ok(FundingStreams::try_from(streams)?)
     }
 
+    /// Fetches the current definition of the validator with the given identity key, if any.
+    pub async fn validator_definition(
+        &self,
+        identity_key: &IdentityKey,
+    ) -> Result<Option<Validator>> {
+        let mut conn = self.pool.acquire().await?;
+        let row = query!(
+            "SELECT consensus_key, sequence_number, name, website, description
+            FROM validators WHERE identity_key = $1",
+            identity_key.encode_to_vec(),
+        )
+        .fetch_optional(&mut conn)
+        .await?;
+
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        Ok(Some(Validator {
+            identity_key: identity_key.clone(),
+            consensus_key: tendermint::PublicKey::from_raw_ed25519(row.consensus_key.as_slice())
+                .ok_or_else(|| anyhow::anyhow!("invalid ed25519 consensus pubkey"))?,
+            name: row.name,
+            website: row.website,
+            description: row.description,
+            funding_streams: self.funding_streams(identity_key.clone()).await?,
+            sequence_number: row.sequence_number as u32,
+        }))
+    }
+
     /// Fetches the latest validator info.
     ///
     /// If `show_inactive` is set, includes validators with 0 voting power.
@@ -413,6 +824,18 @@ impl Reader {
             .collect()
     }
 
+    /// Fetches the latest validator info of every validator, including inactive ones, as a
+    /// shareable snapshot.
+    pub async fn validator_info_snapshot(&self) -> Result<ValidatorInfoSnapshot> {
+        Ok(Arc::new(
+            self.validator_info(true)
+                .await?
+                .into_iter()
+                .map(|info| (info.validator.identity_key.clone(), info))
+                .collect(),
+        ))
+    }
+
     /// Retrieve a stream of [`CompactBlock`]s for the given (inclusive) range.
     ///
     /// If the range corresponds to blocks that don't exist, the stream will be empty.
@@ -423,7 +846,29 @@ impl Reader {
         end_height: i64,
     ) -> impl Stream<Item = Result<CompactBlock>> + Send + Unpin {
         let pool = self.pool.clone();
+        let chain_id = self.chain_params_rx().borrow().chain_id.clone();
         Box::pin(try_stream! {
+            // The notes in the range are contiguous in the note commitment tree, so we only need to
+            // look up where the range starts, and can count from there.
+            let mut start_position = query!(
+                r#"SELECT COALESCE(MAX(position) + 1, 0) AS "size!" FROM notes WHERE height < $1"#,
+                start_height
+            )
+            .fetch_one(&pool)
+            .await?
+            .size as u64;
+
+            let mut blocks = query!(
+                "SELECT height, nct_anchor, app_hash
+                    FROM blocks
+                    WHERE height BETWEEN $1 AND $2
+                    ORDER BY height ASC",
+                start_height,
+                end_height
+            )
+            .fetch(&pool)
+            .peekable();
+
             let mut nullifiers = query!(
                 "SELECT height, nullifier
                     FROM nullifiers
@@ -435,8 +880,20 @@ impl Reader {
             .fetch(&pool)
             .peekable();
 
+            let mut reverted_nullifiers = query!(
+                "SELECT height, nullifier
+                    FROM reverted_nullifiers
+                    WHERE height BETWEEN $1 AND $2
+                    ORDER BY height ASC",
+                start_height,
+                end_height
+            )
+            .fetch(&pool)
+            .peekable();
+
             let mut fragments = query!(
-                "SELECT height, note_commitment, ephemeral_key, encrypted_note
+                "SELECT height, note_commitment, ephemeral_key, encrypted_note,
+                        reward_validator_identity_key, reward_epoch
                     FROM notes
                     WHERE height BETWEEN $1 AND $2
                     ORDER BY position ASC",
@@ -451,8 +908,28 @@ impl Reader {
                     height: height as u64,
                     fragments: vec![],
                     nullifiers: vec![],
+                    reverted_nullifiers: vec![],
+                    nct_root: Default::default(),
+                    app_hash: Default::default(),
+                    chain_id: chain_id.clone(),
+                    start_position,
+                    version: COMPACT_BLOCK_VERSION,
                 };
 
+                match Pin::new(&mut blocks).peek().await {
+                    // Skip the block data if the next row is for a different height
+                    Some(Ok(row)) if row.height != height => {}
+                    Some(_) => {
+                        let row = Pin::new(&mut blocks)
+                            .next()
+                            .await
+                            .expect("we already peeked, so there is a next row")?;
+                        compact_block.nct_root = row.nct_anchor.into();
+                        compact_block.app_hash = row.app_hash.into();
+                    }
+                    None => {}
+                }
+
                 while let Some(row) = Pin::new(&mut nullifiers).peek().await {
                     // Bail out of the loop if the next iteration would be a different height
                     if let Ok(row) = row {
@@ -468,6 +945,21 @@ impl Reader {
                     compact_block.nullifiers.push(row.nullifier.into());
                 }
 
+                while let Some(row) = Pin::new(&mut reverted_nullifiers).peek().await {
+                    // Bail out of the loop if the next iteration would be a different height
+                    if let Ok(row) = row {
+                        if row.height != height {
+                            break;
+                        }
+                    }
+
+                    let row = Pin::new(&mut reverted_nullifiers)
+                        .next()
+                        .await
+                        .expect("we already peeked, so there is a next row")?;
+                    compact_block.reverted_nullifiers.push(row.nullifier.into());
+                }
+
                 while let Some(row) = Pin::new(&mut fragments).peek().await {
                     // Bail out of the loop if the next iteration would be a different height
                     if let Ok(row) = row {
@@ -480,13 +972,29 @@ impl Reader {
                         .next()
                         .await
                         .expect("we already peeked, so there is a next row")?;
+                    let reward_source = match (
+                        row.reward_validator_identity_key,
+                        row.reward_epoch,
+                    ) {
+                        (Some(identity_key), Some(epoch_index)) => Some(
+                            RewardSource {
+                                validator_identity: IdentityKey::decode(&*identity_key)?,
+                                epoch_index: epoch_index as u64,
+                            }
+                            .into(),
+                        ),
+                        _ => None,
+                    };
                     compact_block.fragments.push(StateFragment {
                         note_commitment: row.note_commitment.into(),
                         ephemeral_key: row.ephemeral_key.into(),
                         encrypted_note: row.encrypted_note.into(),
+                        reward_source,
                     });
                 }
 
+                start_position += compact_block.fragments.len() as u64;
+
                 tracing::debug!(
                     ?height,
                     nullifiers_size = compact_block.nullifiers.len(),
@@ -499,15 +1007,46 @@ impl Reader {
         })
     }
 
+    /// Returns the heights, in the given (inclusive) range, of the blocks in which quarantined notes
+    /// or nullifiers associated with any of the given validators were released.
+    pub async fn quarantine_release_heights<'a>(
+        &self,
+        validators: impl IntoIterator<Item = &'a IdentityKey>,
+        start_height: u64,
+        end_height: u64,
+    ) -> Result<Vec<u64>> {
+        let validator_list = validators
+            .into_iter()
+            .map(|v| v.encode_to_vec())
+            .collect::<Vec<_>>();
+
+        Ok(query!(
+            "SELECT DISTINCT height
+            FROM quarantine_releases
+            WHERE validator_identity_key = ANY($1) AND height BETWEEN $2 AND $3
+            ORDER BY height ASC",
+            &validator_list,
+            start_height as i64,
+            end_height as i64,
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| row.height as u64)
+        .collect())
+    }
+
     /// Retrieve a stream of quarantined notes and their commitments, paired with the validator
     /// identity key with which they are associated.
     ///
     /// If `maximum_unbonding_height` is `Some`, only notes whose unbonding height is less than or
     /// equal to that height will be returned.
     ///
-    /// If `validators` is `Some`, only notes which were associated with an undelegation from some
-    /// validator in that set will be returned. (This is more efficient than filtering after
-    /// receiving he stream, because the database is performing the filtration.)
+    /// If `validators` is `Some`, only notes all of whose undelegations were from validators in
+    /// that set will be returned. (This is more efficient than filtering after receiving he stream,
+    /// because the database is performing the filtration.)
+    ///
+    /// A note from a transaction undelegating from several validators is returned once for each.
     pub fn quarantined_notes<'a>(
         &self,
         maximum_unbonding_height: Option<u64>,
@@ -526,10 +1065,15 @@ impl Reader {
 
         query!(
             "SELECT validator_identity_key, note_commitment, ephemeral_key, encrypted_note, transaction_id
-            FROM quarantined_notes
+            FROM quarantined_notes AS q
             WHERE
                 unbonding_height <= $1 AND
-                ($2 OR validator_identity_key = ANY($3))",
+                ($2 OR NOT EXISTS (
+                    SELECT 1 FROM quarantined_notes AS other
+                    WHERE
+                        other.note_commitment = q.note_commitment AND
+                        NOT (other.validator_identity_key = ANY($3))
+                ))",
             maximum_unbonding_height.unwrap_or(u64::MAX) as i64,
             all_validators,
             &validator_list,
@@ -546,6 +1090,7 @@ impl Reader {
                             ephemeral_key: row.ephemeral_key[..].try_into()?,
                             encrypted_note: row.encrypted_note[..].try_into()?,
                             transaction_id: row.transaction_id[..].try_into()?,
+                            reward_source: None,
                         },
                     ))
                 })
@@ -553,15 +1098,189 @@ impl Reader {
         })
     }
 
+    /// Returns the number of quarantined notes and nullifiers, respectively, keyed by the
+    /// validator they are associated with and their unbonding height.
+    ///
+    /// If `validator` is `Some`, only the quarantined notes and nullifiers associated with that
+    /// validator are counted.
+    pub async fn quarantine_schedule(
+        &self,
+        validator: Option<&IdentityKey>,
+    ) -> Result<BTreeMap<(IdentityKey, u64), (u64, u64)>> {
+        let mut conn = self.pool.acquire().await?;
+
+        let all_validators = validator.is_none();
+        let validator = validator.map(|v| v.encode_to_vec()).unwrap_or_default();
+
+        let note_rows = query!(
+            r#"SELECT validator_identity_key, unbonding_height, COUNT(*) AS "count!"
+            FROM quarantined_notes
+            WHERE ($1 OR validator_identity_key = $2)
+            GROUP BY validator_identity_key, unbonding_height"#,
+            all_validators,
+            &validator[..],
+        )
+        .fetch_all(&mut conn)
+        .await?;
+
+        let nullifier_rows = query!(
+            r#"SELECT validator_identity_key, unbonding_height, COUNT(*) AS "count!"
+            FROM quarantined_nullifiers
+            WHERE ($1 OR validator_identity_key = $2)
+            GROUP BY validator_identity_key, unbonding_height"#,
+            all_validators,
+            &validator[..],
+        )
+        .fetch_all(&mut conn)
+        .await?;
+
+        let mut schedule = BTreeMap::<_, (u64, u64)>::new();
+        for row in note_rows {
+            let identity_key = IdentityKey::decode(&*row.validator_identity_key)?;
+            schedule
+                .entry((identity_key, row.unbonding_height as u64))
+                .or_default()
+                .0 += row.count as u64;
+        }
+        for row in nullifier_rows {
+            let identity_key = IdentityKey::decode(&*row.validator_identity_key)?;
+            schedule
+                .entry((identity_key, row.unbonding_height as u64))
+                .or_default()
+                .1 += row.count as u64;
+        }
+
+        Ok(schedule)
+    }
+
+    /// Returns the number of quarantined undelegations, the total stake they unbond, and the number
+    /// of them whose amount is unknown, keyed by their unbonding height.
+    ///
+    /// Undelegations quarantined before `pd` recorded the amounts they unbond have an unknown
+    /// amount, which isn't included in the total.  Undelegations from a slashed validator are
+    /// counted until their notes have been reverted.  Quarantined redelegations don't unbond any
+    /// stake, so they aren't counted.
+    pub async fn unbonding_totals(&self) -> Result<BTreeMap<u64, (u64, u64, u64)>> {
+        let mut conn = self.pool.acquire().await?;
+
+        let rows = query!(
+            r#"SELECT
+                unbonding_height,
+                COUNT(*) FILTER (WHERE amount IS NULL OR amount > 0) AS "count!",
+                COALESCE(SUM(amount), 0)::bigint AS "amount!",
+                COUNT(*) FILTER (WHERE amount IS NULL) AS "unknown_amount_count!"
+            FROM quarantined_unbondings
+            GROUP BY unbonding_height"#
+        )
+        .fetch_all(&mut conn)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                (
+                    row.unbonding_height as u64,
+                    (
+                        row.count as u64,
+                        row.amount as u64,
+                        row.unknown_amount_count as u64,
+                    ),
+                )
+            })
+            .collect())
+    }
+
+    /// Returns the number of quarantined undelegations and redelegations whose unbonding height is
+    /// at most `height`.
+    pub async fn unbondings_due(&self, height: u64) -> Result<u64> {
+        let mut conn = self.pool.acquire().await?;
+
+        let row = query!(
+            r#"SELECT COUNT(*) AS "count!" FROM quarantined_unbondings WHERE unbonding_height <= $1"#,
+            height as i64
+        )
+        .fetch_one(&mut conn)
+        .await?;
+
+        Ok(row.count as u64)
+    }
+
+    /// Returns the next batch of at most `limit` quarantined notes and nullifiers to revert, from
+    /// among those associated with any of the given (slashed) validators, along with the total
+    /// number of quarantined notes and nullifiers associated with them, including the batch.
+    ///
+    /// Notes are batched before nullifiers, each in order of their bytes, so that every node
+    /// reverts the same batch.
+    pub async fn quarantine_revert_batch<'a>(
+        &self,
+        validators: impl IntoIterator<Item = &'a IdentityKey>,
+        limit: u64,
+    ) -> Result<(Vec<note::Commitment>, Vec<Nullifier>, u64)> {
+        let mut conn = self.pool.acquire().await?;
+
+        let validator_list = validators
+            .into_iter()
+            .map(|v| v.encode_to_vec())
+            .collect::<Vec<_>>();
+
+        let outstanding = query!(
+            r#"SELECT
+                (SELECT COUNT(DISTINCT note_commitment) FROM quarantined_notes
+                    WHERE validator_identity_key = ANY($1)) +
+                (SELECT COUNT(DISTINCT nullifier) FROM quarantined_nullifiers
+                    WHERE validator_identity_key = ANY($1))
+                AS "count!""#,
+            &validator_list,
+        )
+        .fetch_one(&mut conn)
+        .await?
+        .count as u64;
+
+        let notes = query!(
+            "SELECT DISTINCT note_commitment
+            FROM quarantined_notes
+            WHERE validator_identity_key = ANY($1)
+            ORDER BY note_commitment
+            LIMIT $2",
+            &validator_list,
+            limit as i64,
+        )
+        .fetch_all(&mut conn)
+        .await?
+        .into_iter()
+        .map(|row| note::Commitment::try_from(&*row.note_commitment))
+        .collect::<Result<Vec<_>, _>>()?;
+
+        let nullifiers = query!(
+            "SELECT DISTINCT nullifier
+            FROM quarantined_nullifiers
+            WHERE validator_identity_key = ANY($1)
+            ORDER BY nullifier
+            LIMIT $2",
+            &validator_list,
+            limit.saturating_sub(notes.len() as u64) as i64,
+        )
+        .fetch_all(&mut conn)
+        .await?
+        .into_iter()
+        .map(|row| Nullifier::try_from(&row.nullifier[..]))
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((notes, nullifiers, outstanding))
+    }
+
     /// Retrieve a stream of quarantined nullifiers, paired with the validator identity key with
     /// which they are associated.
     ///
     /// If `maximum_unbonding_height` is `Some`, only nullifiers whose unbonding height is less than or
     /// equal to that height will be returned.
     ///
-    /// If `validators` is `Some`, only nullifiers which were associated with an undelegation from some
-    /// validator in that set will be returned. (This is more efficient than filtering after
-    /// receiving he stream, because the database is performing the filtration.)
+    /// If `validators` is `Some`, only nullifiers all of whose undelegations were from validators in
+    /// that set will be returned. (This is more efficient than filtering after receiving he stream,
+    /// because the database is performing the filtration.)
+    ///
+    /// A nullifier from a transaction undelegating from several validators is returned once for
+    /// each.
     pub fn quarantined_nullifiers<'a>(
         &self,
         maximum_unbonding_height: Option<u64>,
@@ -579,10 +1298,15 @@ impl Reader {
 
         query!(
             "SELECT validator_identity_key, nullifier
-            FROM quarantined_nullifiers
+            FROM quarantined_nullifiers AS q
             WHERE
                 unbonding_height <= $1 AND
-                ($2 OR validator_identity_key = ANY($3))",
+                ($2 OR NOT EXISTS (
+                    SELECT 1 FROM quarantined_nullifiers AS other
+                    WHERE
+                        other.nullifier = q.nullifier AND
+                        NOT (other.validator_identity_key = ANY($3))
+                ))",
             maximum_unbonding_height.unwrap_or(u64::MAX) as i64,
             all_validators,
             &validator_list,
@@ -621,7 +1345,8 @@ impl Reader {
         let mut conn = self.pool.acquire().await?;
 
         let asset = query!(
-            "SELECT denom, asset_id, total_supply FROM assets WHERE asset_id = $1",
+            r#"SELECT denom, asset_id, total_supply::text AS "total_supply!"
+            FROM assets WHERE asset_id = $1"#,
             asset_id.to_bytes().to_vec(),
         )
         .fetch_optional(&mut conn)
@@ -630,37 +1355,231 @@ impl Reader {
         let height = self.height().await?;
 
         // TODO: should we be returning proto types from our state methods, or domain types?
-        Ok(asset.map(|asset| {
-            let inner = Fq::from_bytes(asset.asset_id.try_into().unwrap())
-                .expect("invalid asset id in database");
-
-            chain::AssetInfo {
-                denom: Some(
-                    asset::REGISTRY
-                        .parse_denom(asset.denom.as_str())
-                        .unwrap()
-                        .into(),
-                ),
-                asset_id: Some(asset::Id(inner).into()),
-                total_supply: asset.total_supply as u64, // postgres only has i64....
-                as_of_block_height: u64::from(height),
-            }
-        }))
+        asset
+            .map(|asset| -> Result<chain::AssetInfo> {
+                let inner = Fq::from_bytes(asset.asset_id.try_into().unwrap())
+                    .expect("invalid asset id in database");
+                let total_supply = asset.total_supply.parse::<Amount>()?;
+
+                Ok(chain::AssetInfo {
+                    denom: Some(
+                        asset::REGISTRY
+                            .parse_denom(asset.denom.as_str())
+                            .unwrap()
+                            .into(),
+                    ),
+                    asset_id: Some(asset::Id(inner).into()),
+                    total_supply: Some(total_supply.into()),
+                    as_of_block_height: u64::from(height),
+                })
+            })
+            .transpose()
     }
 
-    /// Retrieves the entire Asset Registry.
-    pub async fn asset_list(&self) -> Result<Vec<Asset>> {
+    /// Retrieves the total supply of an asset, or `None` if the asset has never been minted.
+    pub async fn total_supply(&self, asset_id: asset::Id) -> Result<Option<Amount>> {
         let mut conn = self.pool.acquire().await?;
 
-        Ok(query!("SELECT denom, asset_id FROM assets")
-            .fetch_all(&mut conn)
-            .await?
-            .into_iter()
-            .map(|row| Asset {
+        // Supplies are stored as `numeric`, since they may not fit in a `bigint`, and read as
+        // text to convert them without loss.
+        query!(
+            r#"SELECT total_supply::text AS "total_supply!" FROM assets WHERE asset_id = $1"#,
+            asset_id.to_bytes().to_vec(),
+        )
+        .fetch_optional(&mut conn)
+        .await?
+        .map(|row| row.total_supply.parse())
+        .transpose()
+    }
+
+    /// Retrieves the total supply of an asset at the end of each epoch in a range in which it
+    /// changed, oldest first.  The entry for the current epoch holds the supply so far.
+    pub async fn supply_history(
+        &self,
+        asset_id: asset::Id,
+        start_epoch_index: u64,
+        end_epoch_index: u64,
+    ) -> Result<Vec<EpochSupply>> {
+        let mut conn = self.pool.acquire().await?;
+
+        let rows = query!(
+            r#"SELECT epoch_index, height, total_supply::text AS "total_supply!"
+            FROM supply_history
+            WHERE asset_id = $1 AND epoch_index >= $2 AND epoch_index <= $3
+            ORDER BY epoch_index ASC"#,
+            asset_id.to_bytes().to_vec(),
+            start_epoch_index.min(i64::MAX as u64) as i64,
+            end_epoch_index.min(i64::MAX as u64) as i64,
+        )
+        .fetch_all(&mut conn)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(EpochSupply {
+                    epoch_index: row.epoch_index as u64,
+                    height: row.height as u64,
+                    total_supply: Some(row.total_supply.parse::<Amount>()?.into()),
+                })
+            })
+            .collect()
+    }
+
+    /// Retrieves the balance of the community pool, in the staking token.
+    pub async fn community_pool_balance(&self) -> Result<Amount> {
+        let mut conn = self.pool.acquire().await?;
+
+        query!(
+            r#"SELECT COALESCE(SUM(amount), 0)::text AS "balance!" FROM community_pool_deposits"#
+        )
+        .fetch_one(&mut conn)
+        .await?
+        .balance
+        .parse()
+    }
+
+    /// Retrieves the display metadata registered for an asset, if any.
+    pub async fn denom_metadata(&self, asset_id: asset::Id) -> Result<Option<asset::Metadata>> {
+        let mut conn = self.pool.acquire().await?;
+
+        query!(
+            "SELECT assets.denom, symbol, decimals, description
+            FROM denom_metadata JOIN assets USING (asset_id)
+            WHERE asset_id = $1",
+            &asset_id.to_bytes()[..],
+        )
+        .fetch_optional(&mut conn)
+        .await?
+        .map(|row| {
+            Ok(asset::Metadata {
+                denom: asset::REGISTRY
+                    .parse_denom(&row.denom)
+                    .ok_or_else(|| anyhow::anyhow!("invalid denom {} in database", row.denom))?,
+                symbol: row.symbol,
+                decimals: row.decimals.try_into()?,
+                description: row.description,
+            })
+        })
+        .transpose()
+    }
+
+    /// Retrieves a page of the Asset Registry, ordered by asset ID.
+    ///
+    /// Only assets whose IDs sort after `start_after` are returned, so passing
+    /// an empty slice starts from the beginning of the registry.  If `limit`
+    /// is `None`, the rest of the registry is returned.
+    pub async fn asset_list(&self, start_after: &[u8], limit: Option<u32>) -> Result<Vec<Asset>> {
+        let mut conn = self.pool.acquire().await?;
+
+        Ok(query!(
+            r#"SELECT
+                assets.denom,
+                assets.asset_id,
+                denom_metadata.symbol AS "symbol?",
+                denom_metadata.decimals AS "decimals?",
+                denom_metadata.description AS "description?"
+            FROM assets LEFT JOIN denom_metadata USING (asset_id)
+            WHERE assets.asset_id > $1
+            ORDER BY assets.asset_id ASC
+            LIMIT $2"#,
+            start_after,
+            limit.map(i64::from)
+        )
+        .fetch_all(&mut conn)
+        .await?
+        .into_iter()
+        .map(|row| {
+            // The metadata columns are all set if the asset has metadata, and all null otherwise.
+            let metadata = match (row.symbol, row.decimals, row.description) {
+                (Some(symbol), Some(decimals), Some(description)) => Some(DenomMetadata {
+                    denom: Some(Denom {
+                        denom: row.denom.clone(),
+                    }),
+                    symbol,
+                    decimals: decimals as u32,
+                    description,
+                }),
+                _ => None,
+            };
+            Asset {
                 asset_denom: row.denom,
                 asset_id: row.asset_id,
+                metadata,
+            }
+        })
+        .collect())
+    }
+
+    /// Retrieves the slashing penalties applied to validators' rates, ordered by height, optionally
+    /// restricted to a single validator.
+    pub async fn validator_slashings(
+        &self,
+        identity_key: Option<&IdentityKey>,
+    ) -> Result<Vec<ValidatorSlashing>> {
+        let mut conn = self.pool.acquire().await?;
+
+        let all_validators = identity_key.is_none();
+        let identity_key = identity_key.map(|v| v.encode_to_vec()).unwrap_or_default();
+
+        let rows = query!(
+            "SELECT
+                identity_key,
+                height,
+                epoch,
+                penalty_bps,
+                pre_slash_exchange_rate,
+                post_slash_exchange_rate,
+                infraction_epoch,
+                infraction_exchange_rate
+            FROM validator_slashings
+            WHERE ($1 OR identity_key = $2)
+            ORDER BY height ASC",
+            all_validators,
+            &identity_key[..],
+        )
+        .fetch_all(&mut conn)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(ValidatorSlashing {
+                    identity_key: Some(IdentityKey::decode(row.identity_key.as_slice())?.into()),
+                    height: row.height as u64,
+                    epoch_index: row.epoch as u64,
+                    penalty_bps: row.penalty_bps as u64,
+                    pre_slash_exchange_rate: row.pre_slash_exchange_rate as u64,
+                    post_slash_exchange_rate: row.post_slash_exchange_rate as u64,
+                    infraction_epoch_index: row.infraction_epoch as u64,
+                    infraction_exchange_rate: row.infraction_exchange_rate as u64,
+                })
             })
-            .collect())
+            .collect()
+    }
+
+    /// Retrieves every validator identity key migration, mapping each old identity key to the new
+    /// identity key and the epoch in which the migration was performed.
+    ///
+    /// A migration takes effect at the end of the epoch in which it was performed.
+    pub async fn validator_migrations(&self) -> Result<BTreeMap<IdentityKey, (IdentityKey, u64)>> {
+        let mut conn = self.pool.acquire().await?;
+
+        let rows =
+            query!("SELECT old_identity_key, new_identity_key, epoch FROM validator_migrations")
+                .fetch_all(&mut conn)
+                .await?;
+
+        let mut migrations = BTreeMap::new();
+        for row in rows {
+            migrations.insert(
+                IdentityKey::decode(row.old_identity_key.as_slice())?,
+                (
+                    IdentityKey::decode(row.new_identity_key.as_slice())?,
+                    row.epoch as u64,
+                ),
+            );
+        }
+
+        Ok(migrations)
     }
 
     /// Retrieve the delegation changes for the supplied epoch
@@ -0,0 +1,215 @@
+use std::{collections::VecDeque, time::Duration};
+
+use anyhow::Result;
+use penumbra_chain::params::ChainParams;
+use penumbra_crypto::merkle;
+use penumbra_proto::changefeed::{changefeed_client::ChangefeedClient, WriteBatchesRequest};
+use penumbra_stake::RateDataById;
+use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
+use tendermint::block;
+use tokio::sync::watch;
+use tracing::instrument;
+
+use super::{changefeed, Reader, ValidatorInfoSnapshot};
+use crate::{grpc::GrpcOptions, NUM_RECENT_ANCHORS};
+
+/// Opens a [`Reader`] on a read-only copy of another `pd`'s database, such as a Postgres streaming
+/// replica, for serving the wallet services without participating in consensus.
+///
+/// Nothing writes to the database through this `pd`, so nothing tells the reader when a block is
+/// committed; instead, the database is polled every `poll_interval`, and the reader's in-memory
+/// caches are refreshed whenever the height changes.
+#[instrument]
+pub async fn replica(uri: &str, poll_interval: Duration) -> Result<Reader> {
+    let pool = PgPoolOptions::new()
+        .max_connections(16)
+        .connect(uri)
+        .await?;
+
+    // We can't run migrations on a read-only copy, so check that the primary already has.
+    let migrator = sqlx::migrate!("./migrations");
+    let expected = migrator.iter().map(|m| m.version).max().unwrap_or(0);
+    let applied: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations")
+        .fetch_one(&pool)
+        .await?;
+    if applied.unwrap_or(0) < expected {
+        return Err(anyhow::anyhow!(
+            "the database has migrations up to {}, but this pd expects {}: \
+             upgrade the primary pd first",
+            applied.unwrap_or(0),
+            expected
+        ));
+    }
+
+    let (reader, follower) = Follower::new(pool);
+    follower.refresh().await?;
+    tokio::spawn(follower.run(poll_interval));
+
+    Ok(reader)
+}
+
+/// Opens a [`Reader`] on a database kept in sync with another `pd`'s by following its changefeed,
+/// for serving the wallet services without participating in consensus.
+///
+/// The database is only written by applying the other node's write batches, which are also
+/// recorded in this database's changefeed, keeping the last `retention` blocks of them, so
+/// followers can be followed in turn.
+#[instrument(skip(grpc))]
+pub async fn follower(
+    uri: &str,
+    changefeed: String,
+    retention: u64,
+    grpc: GrpcOptions,
+) -> Result<Reader> {
+    let pool = PgPoolOptions::new()
+        .max_connections(16)
+        .connect(uri)
+        .await?;
+    tracing::info!("running migrations");
+    sqlx::migrate!("./migrations").run(&pool).await?;
+
+    let (reader, follower) = Follower::new(pool);
+    // An empty database has no chain state to load until the genesis batch arrives.
+    if reader.changefeed_end().await?.is_some() {
+        follower.refresh().await?;
+    }
+    tokio::spawn(follower.follow(changefeed, retention, grpc));
+
+    Ok(reader)
+}
+
+/// Keeps a replica [`Reader`]'s in-memory caches up to date with the database.
+struct Follower {
+    reader: Reader,
+    chain_params_tx: watch::Sender<ChainParams>,
+    height_tx: watch::Sender<block::Height>,
+    committed_height_tx: watch::Sender<block::Height>,
+    next_rate_data_tx: watch::Sender<RateDataById>,
+    validator_info_tx: watch::Sender<ValidatorInfoSnapshot>,
+    valid_anchors_tx: watch::Sender<VecDeque<merkle::Root>>,
+}
+
+impl Follower {
+    fn new(pool: Pool<Postgres>) -> (Reader, Follower) {
+        let (chain_params_tx, chain_params_rx) = watch::channel(Default::default());
+        let (height_tx, height_rx) = watch::channel(Default::default());
+        let (committed_height_tx, committed_height_rx) = watch::channel(Default::default());
+        let (next_rate_data_tx, next_rate_data_rx) = watch::channel(Default::default());
+        let (validator_info_tx, validator_info_rx) = watch::channel(Default::default());
+        let (valid_anchors_tx, valid_anchors_rx) = watch::channel(Default::default());
+
+        let reader = Reader {
+            pool,
+            chain_params_rx,
+            height_rx,
+            committed_height_rx,
+            next_rate_data_rx,
+            validator_info_rx,
+            valid_anchors_rx,
+            witness_tree: Default::default(),
+        };
+        let follower = Follower {
+            reader: reader.clone(),
+            chain_params_tx,
+            height_tx,
+            committed_height_tx,
+            next_rate_data_tx,
+            validator_info_tx,
+            valid_anchors_tx,
+        };
+        (reader, follower)
+    }
+
+    async fn run(self, poll_interval: Duration) {
+        let mut interval = tokio::time::interval(poll_interval);
+        loop {
+            interval.tick().await;
+            let height = match self.reader.height().await {
+                Ok(height) => height,
+                Err(e) => {
+                    tracing::warn!(?e, "could not poll replica height");
+                    continue;
+                }
+            };
+            if height == *self.height_tx.borrow() {
+                continue;
+            }
+            if let Err(e) = self.refresh().await {
+                tracing::warn!(?e, "could not refresh replica caches");
+            }
+        }
+    }
+
+    /// Applies write batches from the changefeed at `changefeed`, reconnecting whenever the stream
+    /// fails.
+    async fn follow(self, changefeed: String, retention: u64, grpc: GrpcOptions) {
+        loop {
+            if let Err(e) = self.follow_stream(&changefeed, retention, &grpc).await {
+                tracing::warn!(error = %format!("{:#}", e), "changefeed stream failed");
+            }
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        }
+    }
+
+    async fn follow_stream(
+        &self,
+        changefeed: &str,
+        retention: u64,
+        grpc: &GrpcOptions,
+    ) -> Result<()> {
+        let mut next_height = self
+            .reader
+            .changefeed_end()
+            .await?
+            .map(|height| height + 1)
+            .unwrap_or(0);
+        // Before the genesis batch, we don't know the chain ID to expect.
+        let chain_id = self.reader.chain_params_rx().borrow().chain_id.clone();
+        tracing::info!(%changefeed, next_height, "following changefeed");
+
+        let mut client = ChangefeedClient::new(grpc.connect(changefeed).await?);
+        let mut batches = client
+            .write_batches(WriteBatchesRequest {
+                start_height: next_height,
+                chain_id,
+            })
+            .await?
+            .into_inner();
+        while let Some(batch) = batches.message().await? {
+            if batch.height != next_height {
+                return Err(anyhow::anyhow!(
+                    "expected a write batch for height {}, got {}",
+                    next_height,
+                    batch.height
+                ));
+            }
+            changefeed::apply(&self.reader.pool, &batch, retention).await?;
+            self.refresh().await?;
+            next_height += 1;
+        }
+        Ok(())
+    }
+
+    /// Reloads every cache from the database.
+    ///
+    /// The primary may commit a block while this runs, so the caches can briefly disagree with
+    /// each other; they are consistent again after the next refresh.
+    async fn refresh(&self) -> Result<()> {
+        let chain_params = self.reader.chain_params().await?;
+        let next_rate_data = self.reader.next_rate_data().await?;
+        let validator_info = self.reader.validator_info_snapshot().await?;
+        let valid_anchors = self.reader.recent_anchors(NUM_RECENT_ANCHORS).await?;
+        let height = self.reader.height().await?;
+
+        // The height goes last, since it's what clients wait on for new blocks.
+        let _ = self.chain_params_tx.send(chain_params);
+        let _ = self.next_rate_data_tx.send(next_rate_data);
+        let _ = self.validator_info_tx.send(validator_info);
+        let _ = self.valid_anchors_tx.send(valid_anchors);
+        let _ = self.committed_height_tx.send(height);
+        let _ = self.height_tx.send(height);
+        tracing::debug!(?height, "refreshed replica caches");
+
+        Ok(())
+    }
+}
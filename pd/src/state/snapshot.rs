@@ -0,0 +1,119 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use futures::future;
+use penumbra_chain::params::ChainParams;
+use penumbra_crypto::Amount;
+use penumbra_stake::{
+    BaseRateData, Epoch, IdentityKey, RateData, ValidatorInfo, STAKING_TOKEN_ASSET_ID,
+};
+use serde::{Deserialize, Serialize};
+
+use super::Reader;
+
+/// A snapshot of the staking state at a committed height: everything the end of its epoch
+/// computes the next rates and validator statuses from, other than the delegations still to come
+/// in the rest of the epoch.
+///
+/// Snapshots are written as JSON, so they can be inspected and edited by hand.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    /// The height of the block the snapshot was taken after.
+    pub height: u64,
+    /// The epoch the block belongs to.
+    pub epoch_index: u64,
+    pub chain_params: ChainParams,
+    /// Every validator, including inactive ones.
+    pub validators: Vec<ValidatorInfo>,
+    /// The base rate for the epoch after `epoch_index`, which was computed at the start of
+    /// `epoch_index` and is accrued on at its end.
+    pub base_rate: BaseRateData,
+    /// The validator rates for the epoch after `epoch_index`, likewise.
+    pub rates: Vec<RateData>,
+    pub staking_token_supply: Amount,
+    /// The supply of each validator's delegation token, by identity key, including the identity
+    /// keys validators have migrated away from.
+    pub delegation_token_supplies: Vec<(IdentityKey, Amount)>,
+    /// The net delegations to each validator so far in `epoch_index`.
+    pub delegation_changes: Vec<(IdentityKey, i64)>,
+    /// Every validator identity key migration, as the old identity key, the new identity key,
+    /// and the epoch in which it was performed.
+    pub validator_migrations: Vec<(IdentityKey, IdentityKey, u64)>,
+}
+
+impl StateSnapshot {
+    /// Writes the snapshot into `dir`, returning the path of the new file.
+    pub fn write_to_dir(&self, dir: &Path) -> Result<PathBuf> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("could not create {}", dir.display()))?;
+        let path = dir.join(format!("state-snapshot-{}.json", self.height));
+        let file = std::fs::File::create(&path)
+            .with_context(|| format!("could not create {}", path.display()))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(path)
+    }
+
+    /// Reads a snapshot written by [`StateSnapshot::write_to_dir`].
+    pub fn read(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("could not open {}", path.display()))?;
+        serde_json::from_reader(file)
+            .with_context(|| format!("could not parse state snapshot {}", path.display()))
+    }
+}
+
+impl Reader {
+    /// Takes a snapshot of the staking state at the latest committed height.
+    pub async fn state_snapshot(&self) -> Result<StateSnapshot> {
+        let (height, chain_params) = tokio::try_join!(self.height(), self.chain_params())?;
+        let height = u64::from(height);
+        let epoch = Epoch::from_height(height, chain_params.epoch_duration);
+
+        let (
+            validators,
+            base_rate,
+            rates,
+            staking_token_supply,
+            delegation_changes,
+            validator_migrations,
+        ) = tokio::try_join!(
+            self.validator_info(true),
+            self.base_rate_data(epoch.index + 1),
+            self.rate_data(epoch.index + 1),
+            self.total_supply(*STAKING_TOKEN_ASSET_ID),
+            self.delegation_changes(epoch.index),
+            self.validator_migrations(),
+        )?;
+
+        let token_identity_keys = validators
+            .iter()
+            .map(|info| info.validator.identity_key.clone())
+            .chain(validator_migrations.keys().cloned())
+            .collect::<Vec<_>>();
+        let supplies = future::try_join_all(
+            token_identity_keys
+                .iter()
+                .map(|identity_key| self.total_supply(identity_key.delegation_token().id())),
+        )
+        .await?;
+
+        Ok(StateSnapshot {
+            height,
+            epoch_index: epoch.index,
+            chain_params,
+            validators,
+            base_rate,
+            rates,
+            staking_token_supply: staking_token_supply.unwrap_or_default(),
+            delegation_token_supplies: token_identity_keys
+                .into_iter()
+                .zip(supplies.into_iter().map(Option::unwrap_or_default))
+                .collect(),
+            delegation_changes: delegation_changes.into_iter().collect(),
+            validator_migrations: validator_migrations
+                .into_iter()
+                .map(|(old, (new, epoch))| (old, new, epoch))
+                .collect(),
+        })
+    }
+}
@@ -0,0 +1,56 @@
+//! The keys of the state that isn't stored in a table of its own: the blobs in the `blobs` table,
+//! and the values committed to by the Jellyfish Merkle tree.
+//!
+//! Every such key is constructed here, so that the reader and writer can't disagree on them.
+
+use jmt::{
+    define_hasher,
+    hash::{CryptoHasher, DefaultHasher, HashValue},
+};
+use once_cell::sync::{Lazy, OnceCell};
+
+/// A key in the `blobs` table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Blob(&'static str);
+
+impl Blob {
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+/// The note commitment tree as of the latest block.
+pub fn note_commitment_tree() -> Blob {
+    Blob("nct")
+}
+
+/// The genesis configuration.
+pub fn genesis_config() -> Blob {
+    Blob("gc")
+}
+
+/// The chain parameters, once they have been changed from the genesis chain parameters.
+pub fn chain_params() -> Blob {
+    Blob("chain_params")
+}
+
+/// The consensus parameters last sent to Tendermint.
+pub fn consensus_params() -> Blob {
+    Blob("consensus_params")
+}
+
+/// The key of the note commitment tree anchor in the Jellyfish Merkle tree.
+pub fn note_commitment_anchor() -> HashValue {
+    let mut state = NoteCommitmentAnchorHasher::default();
+    state.update(b"");
+    state.finish()
+}
+
+define_hasher! {
+    (
+        NoteCommitmentAnchorHasher,
+        NOTE_COMMITMENT_ANCHOR_HASHER,
+        NOTE_COMMITMENT_ANCHOR_SEED,
+        b"nct"
+    )
+}
@@ -0,0 +1,158 @@
+use std::{collections::BTreeMap, fmt};
+
+use penumbra_crypto::{
+    merkle::{self, Altitude, Hashable},
+    note,
+};
+
+/// A copy of the note commitment tree that keeps every node, so that the authentication path of
+/// any note can be read off it, rather than only those of notes witnessed as they were appended.
+///
+/// It holds about twice as many nodes as there are notes, plus each note's position, and appending
+/// a note rehashes only the nodes above it.
+pub(super) struct WitnessTree {
+    /// The height of the block whose notes were last appended, if any.
+    height: Option<u64>,
+    /// The nodes at each altitude below the root, from the leaves up, leaving out those whose
+    /// subtrees are still empty.
+    levels: Vec<Vec<note::Commitment>>,
+    /// The position of each note in the tree.
+    positions: BTreeMap<note::Commitment, u64>,
+    /// The root of an empty subtree at each altitude below the root.
+    empty_roots: Vec<note::Commitment>,
+}
+
+// Spelled out so that logging a reader doesn't print the whole tree.
+impl fmt::Debug for WitnessTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WitnessTree")
+            .field("height", &self.height)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl Default for WitnessTree {
+    fn default() -> Self {
+        Self {
+            height: None,
+            levels: vec![Vec::new(); merkle::DEPTH],
+            positions: BTreeMap::new(),
+            empty_roots: (0..merkle::DEPTH)
+                .map(|level| note::Commitment::empty_root(altitude(level)))
+                .collect(),
+        }
+    }
+}
+
+impl WitnessTree {
+    /// The height of the block whose notes were last appended, if any.
+    pub fn height(&self) -> Option<u64> {
+        self.height
+    }
+
+    /// Records that every note up to the block at `height` has been appended.
+    pub fn set_height(&mut self, height: u64) {
+        self.height = Some(height);
+    }
+
+    /// The number of notes in the tree, which is also the position of the next one.
+    pub fn len(&self) -> u64 {
+        self.levels[0].len() as u64
+    }
+
+    /// Appends `commitment` to the tree.
+    pub fn append(&mut self, commitment: note::Commitment) {
+        let mut index = self.levels[0].len();
+        self.positions.insert(commitment, index as u64);
+        let mut node = commitment;
+        for level in 0..merkle::DEPTH {
+            if index < self.levels[level].len() {
+                self.levels[level][index] = node;
+            } else {
+                self.levels[level].push(node);
+            }
+            if level + 1 == merkle::DEPTH {
+                break;
+            }
+            let (left, right) = self.children(level, index / 2);
+            node = note::Commitment::combine(altitude(level), &left, &right);
+            index /= 2;
+        }
+    }
+
+    /// The root of the tree.
+    pub fn root(&self) -> merkle::Root {
+        let top = merkle::DEPTH - 1;
+        let (left, right) = self.children(top, 0);
+        merkle::Root(note::Commitment::combine(altitude(top), &left, &right).0)
+    }
+
+    /// The authentication path of `commitment`, if it's in the tree.
+    pub fn authentication_path(&self, commitment: &note::Commitment) -> Option<merkle::Path> {
+        let position = *self.positions.get(commitment)?;
+
+        let mut index = position as usize;
+        let path = (0..merkle::DEPTH)
+            .map(|level| {
+                let sibling = self.node(level, index ^ 1);
+                index /= 2;
+                sibling
+            })
+            .collect();
+        Some((merkle::Position::from(position as usize), path))
+    }
+
+    /// The two children of the node at `index` one level above `level`.
+    fn children(&self, level: usize, index: usize) -> (note::Commitment, note::Commitment) {
+        (self.node(level, 2 * index), self.node(level, 2 * index + 1))
+    }
+
+    fn node(&self, level: usize, index: usize) -> note::Commitment {
+        self.levels[level]
+            .get(index)
+            .copied()
+            .unwrap_or(self.empty_roots[level])
+    }
+}
+
+fn altitude(level: usize) -> Altitude {
+    Altitude::zero() + level as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::{
+        merkle::{NoteCommitmentTree, Tree, TreeExt},
+        Fq,
+    };
+
+    use super::*;
+
+    #[test]
+    fn paths_match_the_bridge_tree() {
+        let commitments = (1u64..=11)
+            .map(|i| note::Commitment(Fq::from(i)))
+            .collect::<Vec<_>>();
+
+        let mut tree = NoteCommitmentTree::new(0);
+        let mut witness_tree = WitnessTree::default();
+        assert_eq!(witness_tree.root(), tree.root2());
+        for commitment in &commitments {
+            tree.append(commitment);
+            tree.witness();
+            witness_tree.append(*commitment);
+            assert_eq!(witness_tree.root(), tree.root2());
+        }
+
+        for commitment in &commitments {
+            assert_eq!(
+                witness_tree.authentication_path(commitment),
+                tree.authentication_path(commitment)
+            );
+        }
+        assert!(witness_tree
+            .authentication_path(&note::Commitment(Fq::from(12u64)))
+            .is_none());
+    }
+}
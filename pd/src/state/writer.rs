@@ -1,17 +1,25 @@
 use std::collections::VecDeque;
 
 use anyhow::Result;
-use jmt::TreeWriterAsync;
+use jmt::{NodeBatch, TreeWriterAsync};
+use metrics::{counter, gauge};
 use penumbra_chain::params::ChainParams;
 use penumbra_crypto::merkle::{self, TreeExt};
 use penumbra_proto::Protobuf;
-use penumbra_stake::{FundingStream, RateDataById, ValidatorStateName};
-use sqlx::{query, Pool, Postgres};
+use penumbra_stake::{
+    FundingStream, FundingStreamRecipient, RateDataById, ValidatorStateName, SLASHING_PENALTY_BPS,
+};
+use sqlx::{query, Pool, Postgres, Transaction};
 use tendermint::block;
 use tokio::sync::watch;
 
-use super::jellyfish;
-use crate::{genesis, pending_block::QuarantineGroup, PendingBlock, NUM_RECENT_ANCHORS};
+use super::{changefeed, jellyfish, state_key};
+use crate::{
+    epoch::cutoff,
+    genesis, pd_metrics,
+    pending_block::{Ended, QuarantineGroup},
+    PendingBlock, NUM_RECENT_ANCHORS,
+};
 
 #[derive(Debug)]
 pub struct Writer {
@@ -24,21 +32,22 @@ pub struct Writer {
     // Push channels for chain state
     pub(super) chain_params_tx: watch::Sender<ChainParams>,
     pub(super) height_tx: watch::Sender<block::Height>,
+    pub(super) committed_height_tx: watch::Sender<block::Height>,
     pub(super) next_rate_data_tx: watch::Sender<RateDataById>,
+    pub(super) validator_info_tx: watch::Sender<super::ValidatorInfoSnapshot>,
     pub(super) valid_anchors_tx: watch::Sender<VecDeque<merkle::Root>>,
+    /// The number of blocks of writes to keep in the changefeed, if recording one.
+    pub(super) changefeed: Option<u64>,
 }
 
 impl Writer {
     /// Initializes in-memory caches / notification channels.
     /// Called by `state::new()` on init.
     pub(super) async fn init_caches(&self) -> Result<()> {
-        let chain_params = self
-            .private_reader
-            .genesis_configuration()
-            .await?
-            .chain_params;
+        let chain_params = self.private_reader.chain_params().await?;
         let height = self.private_reader.height().await?;
         let next_rate_data = self.private_reader.next_rate_data().await?;
+        let validator_info = self.private_reader.validator_info_snapshot().await?;
         let valid_anchors = self
             .private_reader
             .recent_anchors(NUM_RECENT_ANCHORS)
@@ -47,7 +56,9 @@ impl Writer {
         // Sends fail if every receiver has been dropped, which is not our problem.
         let _ = self.chain_params_tx.send(chain_params);
         let _ = self.height_tx.send(height);
+        let _ = self.committed_height_tx.send(height);
         let _ = self.next_rate_data_tx.send(next_rate_data);
+        let _ = self.validator_info_tx.send(validator_info);
         let _ = self.valid_anchors_tx.send(valid_anchors);
 
         Ok(())
@@ -63,15 +74,17 @@ impl Writer {
     /// Commits the genesis config to the database, prior to the first block commit.
     pub async fn commit_genesis(&self, genesis_config: &genesis::AppState) -> Result<()> {
         let mut dbtx = self.pool.begin().await?;
+        if self.changefeed.is_some() {
+            changefeed::record_changes(&mut dbtx, 0).await?;
+        }
 
         let genesis_bytes = serde_json::to_vec(&genesis_config)?;
 
         // ON CONFLICT is excluded here so that an error is raised
         // if genesis config is attempted to be set more than once
         query!(
-            r#"
-            INSERT INTO blobs (id, data) VALUES ('gc', $1)
-            "#,
+            "INSERT INTO blobs (id, data) VALUES ($1, $2)",
+            state_key::genesis_config().as_str(),
             &genesis_bytes[..]
         )
         .execute(&mut dbtx)
@@ -124,7 +137,11 @@ impl Writer {
             .execute(&mut dbtx)
             .await?;
 
-            for FundingStream { address, rate_bps } in validator.funding_streams.as_ref() {
+            for FundingStream {
+                recipient,
+                rate_bps,
+            } in validator.funding_streams.as_ref()
+            {
                 query!(
                     "INSERT INTO validator_fundingstreams (
                         identity_key,
@@ -132,7 +149,7 @@ impl Writer {
                         rate_bps
                     ) VALUES ($1, $2, $3)",
                     validator.identity_key.encode_to_vec(),
-                    address.to_string(),
+                    recipient_address(recipient),
                     *rate_bps as i32,
                 )
                 .execute(&mut dbtx)
@@ -179,6 +196,9 @@ impl Writer {
         // We wrote these, so push updates to subscribers.
         let _ = self.chain_params_tx.send(chain_params);
         let _ = self.next_rate_data_tx.send(next_rate_data);
+        let _ = self
+            .validator_info_tx
+            .send(self.private_reader.validator_info_snapshot().await?);
         // These haven't been set yet.
         // let _ = self.height_tx.send(height);
         // let _ = self.valid_anchors_tx.send(valid_anchors);
@@ -186,42 +206,21 @@ impl Writer {
         Ok(())
     }
 
-    /// Commits a block to the state, returning the new app hash.
-    pub async fn commit_block(&self, block: PendingBlock) -> Result<Vec<u8>> {
-        // TODO: batch these queries?
-        let mut dbtx = self.pool.begin().await?;
-
+    /// Computes the app hash a block commits to, without writing anything.
+    ///
+    /// This reads the Jellyfish Merkle tree as of the last committed block, so the previous block
+    /// must have been committed first.  The returned block is considered committed to Tendermint,
+    /// so readers can wait for it to be written with [`Reader::wait_for_committed_block`].
+    ///
+    /// [`Reader::wait_for_committed_block`]: super::Reader::wait_for_committed_block
+    pub async fn prepare_block(&self, block: PendingBlock<Ended>) -> Result<PreparedBlock> {
         let nct_anchor = block.note_commitment_tree.root2();
-        let nct_bytes = bincode::serialize(&block.note_commitment_tree)?;
-        query!(
-            r#"
-            INSERT INTO blobs (id, data) VALUES ('nct', $1)
-            ON CONFLICT (id) DO UPDATE SET data = $1
-            "#,
-            &nct_bytes[..]
-        )
-        .execute(&mut dbtx)
-        .await?;
-
-        let height = block.height.expect("height must be set");
-
-        // The Jellyfish Merkle tree batches writes to its backing store, so we
-        // first need to write the JMT kv pairs...
         let (jmt_root, tree_update_batch) = jmt::JellyfishMerkleTree::new(&self.private_reader)
             .put_value_set(
-                // TODO: create a JmtKey enum, where each variant has
-                // a different domain-separated hash
-                vec![(
-                    jellyfish::Key::NoteCommitmentAnchor.hash(),
-                    nct_anchor.clone(),
-                )],
-                height,
+                vec![(state_key::note_commitment_anchor(), nct_anchor.clone())],
+                block.phase.height,
             )
             .await?;
-        // ... and then write the resulting batch update to the backing store:
-        jellyfish::DbTx(&mut dbtx)
-            .write_node_batch(&tree_update_batch.node_batch)
-            .await?;
 
         // The app hash is the root of the Jellyfish Merkle Tree.  We save the
         // NCT anchor separately for convenience, but it's already included in
@@ -229,48 +228,189 @@ impl Writer {
         // TODO: no way to access the Diem HashValue as array, even though it's stored that way?
         let app_hash: [u8; 32] = jmt_root.to_vec().try_into().unwrap();
 
+        let _ = self
+            .committed_height_tx
+            .send(block.phase.height.try_into().unwrap());
+
+        Ok(PreparedBlock {
+            block,
+            nct_anchor,
+            node_batch: tree_update_batch.node_batch,
+            app_hash,
+        })
+    }
+
+    /// Commits a prepared block to the state, returning how many rows of each kind it wrote.
+    pub async fn commit_block(&self, prepared: PreparedBlock) -> Result<BlockWrites> {
+        let PreparedBlock {
+            block,
+            nct_anchor,
+            node_batch,
+            app_hash,
+        } = prepared;
+
+        // TODO: batch these queries?
+        let mut dbtx = self.pool.begin().await?;
+        if let Some(retention) = self.changefeed {
+            changefeed::record_changes(&mut dbtx, block.phase.height).await?;
+            changefeed::prune(&mut dbtx, block.phase.height, retention).await?;
+        }
+
+        // The validator info snapshot only needs refreshing if this block changes a validator's
+        // definition, identity key, rates or status.
+        let validators_changed = block.next_rates.is_some()
+            || !block.slashings.is_empty()
+            || !block.validator_definitions.is_empty()
+            || !block.validator_state_changes.is_empty();
+
+        let nct_bytes = bincode::serialize(&block.note_commitment_tree)?;
+        gauge!(pd_metrics::NCT_SIZE_BYTES, nct_bytes.len() as f64);
+        gauge!(
+            pd_metrics::NCT_BRIDGES,
+            block.note_commitment_tree.bridges().len() as f64
+        );
+        put_blob(&mut dbtx, state_key::note_commitment_tree(), &nct_bytes).await?;
+
+        let height = block.phase.height;
+        let mut writes = BlockWrites {
+            height,
+            ..Default::default()
+        };
+
+        // The Jellyfish Merkle tree batched its writes when the block was prepared.
+        jellyfish::DbTx(&mut dbtx)
+            .write_node_batch(&node_batch)
+            .await?;
+
         query!(
-            "INSERT INTO blocks (height, nct_anchor, app_hash) VALUES ($1, $2, $3)",
+            "INSERT INTO blocks (height, nct_anchor, app_hash, time_ms) VALUES ($1, $2, $3, $4)",
             height as i64,
             &nct_anchor.to_bytes()[..],
-            &app_hash[..]
+            &app_hash[..],
+            block
+                .time
+                .map(|time| (time.unix_timestamp_nanos() / 1_000_000) as i64)
         )
         .execute(&mut dbtx)
         .await?;
 
-        // Drop quarantined notes associated with a validator slashed in this block
-        for note_commitment in block.reverting_notes {
+        // Record the block's anonymity statistics, carrying the size of the nullifier set forward
+        // from the previous block.
+        let note_set_size = block
+            .note_commitment_tree
+            .bridges()
+            .last()
+            .map(|b| u64::from(b.frontier().position()) + 1)
+            .unwrap_or(0);
+        query!(
+            "INSERT INTO block_anonymity_stats (
+                height,
+                notes_created,
+                nullifiers_revealed,
+                note_set_size,
+                nullifier_set_size
+            )
+            SELECT $1::bigint, $2::bigint, $3::bigint, $4::bigint, COALESCE(
+                (SELECT nullifier_set_size FROM block_anonymity_stats WHERE height = $1 - 1),
+                0
+            ) + $3 - $5::bigint",
+            height as i64,
+            block.notes.len() as i64,
+            block.spent_nullifiers.len() as i64,
+            note_set_size as i64,
+            block.reverting_nullifiers.len() as i64,
+        )
+        .execute(&mut dbtx)
+        .await?;
+        for (shape, count) in &block.transaction_shapes {
+            query!(
+                "INSERT INTO transaction_shapes (height, spends, outputs, count) VALUES ($1, $2, $3, $4)",
+                height as i64,
+                shape.spends as i64,
+                shape.outputs as i64,
+                *count as i64,
+            )
+            .execute(&mut dbtx)
+            .await?;
+        }
+        for (asset_id, amount) in &block.fee_totals {
             query!(
+                "INSERT INTO block_fees (height, asset_id, amount) VALUES ($1, $2, $3)",
+                height as i64,
+                &asset_id.to_bytes()[..],
+                *amount as i64,
+            )
+            .execute(&mut dbtx)
+            .await?;
+        }
+        for (identity_key, amount) in &block.community_pool_deposits {
+            query!(
+                "INSERT INTO community_pool_deposits (height, validator_identity_key, amount)
+                VALUES ($1, $2, $3)",
+                height as i64,
+                identity_key.encode_to_vec(),
+                *amount as i64,
+            )
+            .execute(&mut dbtx)
+            .await?;
+        }
+
+        // Drop quarantined notes associated with a validator slashed in this block, along with the
+        // record of the stake their undelegation would have unbonded
+        for note_commitment in block.reverting_notes {
+            writes.unquarantined += query!(
+                "DELETE FROM quarantined_unbondings WHERE transaction_id IN (
+                    SELECT transaction_id FROM quarantined_notes WHERE note_commitment = $1
+                )",
+                &<[u8; 32]>::from(note_commitment)[..]
+            )
+            .execute(&mut dbtx)
+            .await?
+            .rows_affected();
+            writes.unquarantined += query!(
                 "DELETE FROM quarantined_notes WHERE note_commitment = $1",
                 &<[u8; 32]>::from(note_commitment)[..]
             )
             .execute(&mut dbtx)
-            .await?;
+            .await?
+            .rows_affected();
         }
 
         // Drop quarantined nullifiers from the main nullifier set if they were associated with a
         // validator slashed in this block (thus reverting their spend)
         for nullifier in block.reverting_nullifiers {
             // Forget about this nullifier, making the associated note spendable again
-            query!(
+            writes.nullifiers_reverted += query!(
                 "DELETE FROM nullifiers WHERE nullifier = $1",
                 &nullifier.to_bytes()[..]
             )
             .execute(&mut dbtx)
+            .await?
+            .rows_affected();
+
+            // Record the revert, so that clients that already saw the spend learn of it
+            query!(
+                "INSERT INTO reverted_nullifiers (nullifier, height) VALUES ($1, $2)",
+                &nullifier.to_bytes()[..],
+                height as i64
+            )
+            .execute(&mut dbtx)
             .await?;
 
             // We have reverted this nullifier, so we can remove it from quarantine
-            query!(
+            writes.unquarantined += query!(
                 "DELETE FROM quarantined_nullifiers WHERE nullifier = $1",
                 &nullifier.to_bytes()[..]
             )
             .execute(&mut dbtx)
-            .await?;
+            .await?
+            .rows_affected();
         }
 
         // Add newly created notes into the chain state.
         for (note_commitment, positioned_note) in block.notes.into_iter() {
-            query!(
+            let reward_source = &positioned_note.data.reward_source;
+            writes.notes += query!(
                 r#"
                 INSERT INTO notes (
                     note_commitment,
@@ -278,25 +418,35 @@ impl Writer {
                     encrypted_note,
                     transaction_id,
                     position,
-                    height
-                ) VALUES ($1, $2, $3, $4, $5, $6)"#,
+                    height,
+                    reward_validator_identity_key,
+                    reward_epoch
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#,
                 &<[u8; 32]>::from(note_commitment)[..],
                 &positioned_note.data.ephemeral_key.0[..],
                 &positioned_note.data.encrypted_note[..],
                 &positioned_note.data.transaction_id[..],
                 positioned_note.position as i64,
                 height as i64,
+                reward_source
+                    .as_ref()
+                    .map(|source| source.validator_identity.encode_to_vec()),
+                reward_source
+                    .as_ref()
+                    .map(|source| source.epoch_index as i64),
             )
             .execute(&mut dbtx)
-            .await?;
+            .await?
+            .rows_affected();
 
             // If the note was previously quarantined, drop it from quarantine
-            query!(
+            writes.unquarantined += query!(
                 "DELETE FROM quarantined_notes WHERE note_commitment = $1",
                 &<[u8; 32]>::from(note_commitment)[..]
             )
             .execute(&mut dbtx)
-            .await?;
+            .await?
+            .rows_affected();
         }
 
         // Calculate the height at which notes quarantined in this block should unbond. If the
@@ -307,72 +457,130 @@ impl Writer {
             .chain_params_rx()
             .borrow()
             .unbonding_epochs;
-        let epoch_duration = block.epoch.as_ref().unwrap().duration;
+        let epoch_duration = block.phase.epoch.duration;
         let unbonding_height = height + (epoch_duration * unbonding_epochs);
 
         // Add notes and nullifiers from transactions containing undelegations to a quarantine
-        // queue, to be extracted when their unbonding period expires.
+        // queue, to be extracted when their unbonding period expires. They are quarantined once
+        // for each validator undelegated from, so that they are reverted if any of them is slashed.
         for QuarantineGroup {
-            validator_identity_key,
+            transaction_id,
+            unbondings,
             notes,
             nullifiers,
         } in block.quarantine
         {
-            // Quarantine all notes associated with this quarantine group
-            for (&note_commitment, data) in notes.iter() {
-                // Hold the note data in quarantine
-                query!(
-                    r#"
-                    INSERT INTO quarantined_notes (
-                        note_commitment,
-                        ephemeral_key,
-                        encrypted_note,
+            for (validator_identity_key, unbonded_amount) in unbondings {
+                let validator_identity_key = &validator_identity_key.0.to_bytes()[..];
+
+                writes.quarantined += query!(
+                    "INSERT INTO quarantined_unbondings (
                         transaction_id,
+                        validator_identity_key,
                         unbonding_height,
-                        validator_identity_key
-                    ) VALUES ($1, $2, $3, $4, $5, $6)"#,
-                    &<[u8; 32]>::from(note_commitment)[..],
-                    &data.ephemeral_key.0[..],
-                    &data.encrypted_note[..],
-                    &data.transaction_id[..],
+                        amount
+                    ) VALUES ($1, $2, $3, $4)",
+                    &transaction_id[..],
+                    validator_identity_key,
                     unbonding_height as i64,
-                    &validator_identity_key.0.to_bytes()[..],
+                    unbonded_amount as i64,
                 )
                 .execute(&mut dbtx)
-                .await?;
+                .await?
+                .rows_affected();
+
+                // Quarantine all notes associated with this quarantine group
+                for (&note_commitment, data) in notes.iter() {
+                    // Hold the note data in quarantine
+                    writes.quarantined += query!(
+                        r#"
+                        INSERT INTO quarantined_notes (
+                            note_commitment,
+                            ephemeral_key,
+                            encrypted_note,
+                            transaction_id,
+                            unbonding_height,
+                            validator_identity_key
+                        ) VALUES ($1, $2, $3, $4, $5, $6)"#,
+                        &<[u8; 32]>::from(note_commitment)[..],
+                        &data.ephemeral_key.0[..],
+                        &data.encrypted_note[..],
+                        &data.transaction_id[..],
+                        unbonding_height as i64,
+                        validator_identity_key,
+                    )
+                    .execute(&mut dbtx)
+                    .await?
+                    .rows_affected();
+                }
+
+                // Quarantine all nullifiers associated with this quarantine group
+                for &nullifier in nullifiers.iter() {
+                    let nullifier_bytes = &<[u8; 32]>::from(nullifier)[..];
+
+                    // Keep track of the nullifier associated with the block height
+                    writes.quarantined += query!(
+                        r#"
+                        INSERT INTO quarantined_nullifiers (nullifier, unbonding_height, validator_identity_key)
+                        VALUES ($1, $2, $3)"#,
+                        nullifier_bytes,
+                        unbonding_height as i64,
+                        validator_identity_key,
+                    )
+                    .execute(&mut dbtx)
+                    .await?
+                    .rows_affected();
+                }
             }
+        }
 
-            // Quarantine all nullifiers associated with this quarantine group
-            for &nullifier in nullifiers.iter() {
-                let nullifier_bytes = &<[u8; 32]>::from(nullifier)[..];
-
-                // Keep track of the nullifier associated with the block height
-                query!(
-                    r#"
-                    INSERT INTO quarantined_nullifiers (nullifier, unbonding_height, validator_identity_key)
-                    VALUES ($1, $2, $3)"#,
-                    nullifier_bytes,
-                    unbonding_height as i64,
-                    &validator_identity_key.0.to_bytes()[..],
-                )
-                .execute(&mut dbtx)
-                .await?;
-            }
+        // Record which validators' quarantined notes and nullifiers were released in this block,
+        // so that clients can find the blocks they need to claim their unbonded notes.
+        for identity_key in block.quarantine_releases {
+            query!(
+                "INSERT INTO quarantine_releases (validator_identity_key, height) VALUES ($1, $2)",
+                identity_key.encode_to_vec(),
+                height as i64,
+            )
+            .execute(&mut dbtx)
+            .await?;
+            // Everything quarantined for the validator up to this height was released
+            writes.unquarantined += query!(
+                "DELETE FROM quarantined_unbondings
+                WHERE validator_identity_key = $1 AND unbonding_height <= $2",
+                &identity_key.0.to_bytes()[..],
+                height as i64,
+            )
+            .execute(&mut dbtx)
+            .await?
+            .rows_affected();
         }
 
         // Mark spent notes as spent.
         for nullifier in block.spent_nullifiers.into_iter() {
-            query!(
+            writes.nullifiers += query!(
                 "INSERT INTO nullifiers VALUES ($1, $2)",
                 &<[u8; 32]>::from(nullifier)[..],
                 height as i64,
             )
             .execute(&mut dbtx)
+            .await?
+            .rows_affected();
+        }
+
+        // Transactions included in this block no longer need to be kept around
+        // for rebroadcast after a restart.
+        for transaction_id in block.transaction_ids {
+            query!(
+                "DELETE FROM mempool_transactions WHERE id = $1",
+                &transaction_id[..]
+            )
+            .execute(&mut dbtx)
             .await?;
         }
 
-        // Track the net change in delegations in this block.
-        let epoch_index = block.epoch.unwrap().index;
+        // Track the net change in delegations in this block, under the epoch whose end applies it.
+        let epoch_index = cutoff::applying_epoch(height, block.phase.epoch.duration);
         for (identity_key, delegation_change) in block.delegation_changes {
             query!(
                 "INSERT INTO delegation_changes VALUES ($1, $2, $3)",
@@ -384,15 +592,217 @@ impl Writer {
             .await?;
         }
 
-        // Save any new assets found in the block to the asset registry.
-        for (id, asset) in block.supply_updates {
+        // Record validator identity key migrations performed in this block; they take effect at
+        // the end of the epoch.
+        for (old_identity_key, new_identity_key) in block.validator_migrations {
+            query!(
+                "INSERT INTO validator_migrations (old_identity_key, new_identity_key, epoch)
+                VALUES ($1, $2, $3)",
+                old_identity_key.encode_to_vec(),
+                new_identity_key.encode_to_vec(),
+                epoch_index as i64
+            )
+            .execute(&mut dbtx)
+            .await?;
+        }
+
+        // Apply updated validator definitions.  Their sequence numbers were checked during
+        // verification, and the stored sequence number prevents replaying older definitions.
+        for (identity_key, validator) in block.validator_definitions {
+            query!(
+                "UPDATE validators
+                SET sequence_number = $1, name = $2, website = $3, description = $4
+                WHERE identity_key = $5",
+                validator.sequence_number as i64,
+                validator.name,
+                validator.website,
+                validator.description,
+                identity_key.encode_to_vec(),
+            )
+            .execute(&mut dbtx)
+            .await?;
+
             query!(
+                "DELETE FROM validator_fundingstreams WHERE identity_key = $1",
+                identity_key.encode_to_vec(),
+            )
+            .execute(&mut dbtx)
+            .await?;
+
+            for FundingStream {
+                recipient,
+                rate_bps,
+            } in validator.funding_streams.as_ref()
+            {
+                query!(
+                    "INSERT INTO validator_fundingstreams (identity_key, address, rate_bps)
+                    VALUES ($1, $2, $3)",
+                    identity_key.encode_to_vec(),
+                    recipient_address(recipient),
+                    *rate_bps as i64,
+                )
+                .execute(&mut dbtx)
+                .await?;
+            }
+        }
+
+        // Record registered denom metadata, replacing any earlier registration: the denom metadata
+        // authority can update it.
+        for (asset_id, metadata) in block.denom_metadata {
+            query!(
+                "INSERT INTO denom_metadata (asset_id, symbol, decimals, description, height)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (asset_id) DO UPDATE SET
+                    symbol = excluded.symbol,
+                    decimals = excluded.decimals,
+                    description = excluded.description,
+                    height = excluded.height",
+                &asset_id.to_bytes()[..],
+                metadata.symbol,
+                metadata.decimals as i32,
+                metadata.description,
+                height as i64,
+            )
+            .execute(&mut dbtx)
+            .await?;
+        }
+
+        // Restart the redelegation cooldown of each delegator key that redelegated in this block.
+        for (delegator_key, epoch_index) in block.redelegations {
+            query!(
+                "INSERT INTO redelegations (delegator_key, epoch_index) VALUES ($1, $2)
+                ON CONFLICT (delegator_key) DO UPDATE SET epoch_index = excluded.epoch_index",
+                &delegator_key[..],
+                epoch_index as i64,
+            )
+            .execute(&mut dbtx)
+            .await?;
+        }
+
+        // Remember the transactions in this block until their anchors expire, so they can't be
+        // included again.
+        for (id, anchor) in block.recent_transactions {
+            query!(
+                "INSERT INTO recent_transactions (id, height, anchor) VALUES ($1, $2, $3)",
+                &id[..],
+                height as i64,
+                &anchor.to_bytes()[..],
+            )
+            .execute(&mut dbtx)
+            .await?;
+        }
+
+        // Validators migrating to a new identity key at this epoch boundary take on the new key,
+        // keeping their definition and funding streams, and their old key becomes inactive.  This
+        // must happen before the next rates are recorded under the new key.
+        for (old_identity_key, new_identity_key) in block.next_validator_migrations {
+            query!(
+                "INSERT INTO validators (
+                    identity_key,
+                    consensus_key,
+                    sequence_number,
+                    name,
+                    website,
+                    description,
+                    voting_power,
+                    validator_state,
+                    unbonding_epoch
+                )
+                SELECT $1, consensus_key, sequence_number, name, website, description,
+                    voting_power, validator_state, unbonding_epoch
+                FROM validators WHERE identity_key = $2",
+                new_identity_key.encode_to_vec(),
+                old_identity_key.encode_to_vec(),
+            )
+            .execute(&mut dbtx)
+            .await?;
+
+            query!(
+                "INSERT INTO validator_fundingstreams (identity_key, address, rate_bps)
+                SELECT $1, address, rate_bps
+                FROM validator_fundingstreams WHERE identity_key = $2",
+                new_identity_key.encode_to_vec(),
+                old_identity_key.encode_to_vec(),
+            )
+            .execute(&mut dbtx)
+            .await?;
+
+            writes.statuses += query!(
+                "UPDATE validators SET voting_power = 0, validator_state = $1, unbonding_epoch = NULL
+                WHERE identity_key = $2",
+                ValidatorStateName::Inactive.to_str().to_string(),
+                old_identity_key.encode_to_vec(),
+            )
+            .execute(&mut dbtx)
+            .await?
+            .rows_affected();
+        }
+
+        // Save any new assets found in the block to the asset registry.  Supplies are passed as
+        // text, since they may not fit in a `bigint`.
+        for (id, asset) in block.supply_updates {
+            writes.supplies += query!(
                 "INSERT INTO assets (asset_id, denom, total_supply)
-                VALUES ($1, $2, $3)
-                ON CONFLICT (asset_id) DO UPDATE SET denom=$2, total_supply=$3",
+                VALUES ($1, $2, $3::text::numeric)
+                ON CONFLICT (asset_id) DO UPDATE SET denom=$2, total_supply=$3::text::numeric",
                 &id.to_bytes()[..],
                 asset.0.to_string(),
-                asset.1 as i64
+                asset.1.to_string()
+            )
+            .execute(&mut dbtx)
+            .await?
+            .rows_affected();
+
+            // Keep each epoch's latest supply, so that once the epoch ends, its row holds the
+            // supply at the end of the epoch.
+            writes.supplies += query!(
+                "INSERT INTO supply_history (asset_id, epoch_index, height, total_supply)
+                VALUES ($1, $2, $3, $4::text::numeric)
+                ON CONFLICT (asset_id, epoch_index)
+                DO UPDATE SET height = excluded.height, total_supply = excluded.total_supply",
+                &id.to_bytes()[..],
+                block.phase.epoch.index as i64,
+                height as i64,
+                asset.1.to_string()
+            )
+            .execute(&mut dbtx)
+            .await?
+            .rows_affected();
+        }
+
+        // Apply slashing penalties to the rates of validators slashed in this block, and record
+        // the adjustment so that delegators can check it against the announced penalty.
+        for (identity_key, slashing) in block.slashings.iter() {
+            writes.rates += query!(
+                "UPDATE validator_rates SET validator_exchange_rate = $1
+                WHERE identity_key = $2 AND epoch = $3",
+                slashing.post_slash.validator_exchange_rate as i64,
+                identity_key.encode_to_vec(),
+                slashing.post_slash.epoch_index as i64,
+            )
+            .execute(&mut dbtx)
+            .await?
+            .rows_affected();
+
+            query!(
+                "INSERT INTO validator_slashings (
+                    identity_key,
+                    height,
+                    epoch,
+                    penalty_bps,
+                    pre_slash_exchange_rate,
+                    post_slash_exchange_rate,
+                    infraction_epoch,
+                    infraction_exchange_rate
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+                identity_key.encode_to_vec(),
+                height as i64,
+                slashing.post_slash.epoch_index as i64,
+                SLASHING_PENALTY_BPS as i64,
+                slashing.pre_slash.validator_exchange_rate as i64,
+                slashing.post_slash.validator_exchange_rate as i64,
+                slashing.infraction_rate.epoch_index as i64,
+                slashing.infraction_rate.validator_exchange_rate as i64,
             )
             .execute(&mut dbtx)
             .await?;
@@ -401,17 +811,18 @@ impl Writer {
         if let (Some(base_rate_data), Some(rate_data)) =
             (block.next_base_rate, block.next_rates.as_ref())
         {
-            query!(
+            writes.rates += query!(
                 "INSERT INTO base_rates VALUES ($1, $2, $3)",
                 base_rate_data.epoch_index as i64,
                 base_rate_data.base_reward_rate as i64,
                 base_rate_data.base_exchange_rate as i64,
             )
             .execute(&mut dbtx)
-            .await?;
+            .await?
+            .rows_affected();
 
             for rate in rate_data {
-                query!(
+                writes.rates += query!(
                     "INSERT INTO validator_rates VALUES ($1, $2, $3, $4)",
                     rate.identity_key.encode_to_vec(),
                     rate.epoch_index as i64,
@@ -419,36 +830,82 @@ impl Writer {
                     rate.validator_exchange_rate as i64,
                 )
                 .execute(&mut dbtx)
-                .await?;
+                .await?
+                .rows_affected();
             }
         }
 
         if let Some(validator_statuses) = block.next_validator_statuses {
             for status in validator_statuses {
-                query!(
+                writes.statuses += query!(
                     "UPDATE validators SET voting_power=$1 WHERE identity_key = $2",
                     status.voting_power as i64,
                     status.identity_key.encode_to_vec(),
                 )
                 .execute(&mut dbtx)
-                .await?;
+                .await?
+                .rows_affected();
             }
         }
 
+        if let Some(chain_params) = &block.next_chain_params {
+            put_blob(
+                &mut dbtx,
+                state_key::chain_params(),
+                &serde_json::to_vec(chain_params)?,
+            )
+            .await?;
+        }
+
+        if let Some(consensus_params) = &block.next_consensus_params {
+            put_blob(
+                &mut dbtx,
+                state_key::consensus_params(),
+                &serde_json::to_vec(consensus_params)?,
+            )
+            .await?;
+        }
+
         let mut valid_anchors = self.valid_anchors_tx.borrow().clone();
         if valid_anchors.len() >= NUM_RECENT_ANCHORS {
             valid_anchors.pop_back();
         }
         valid_anchors.push_front(nct_anchor);
-        let next_rate_data = block.next_rates.map(|next_rates| {
-            next_rates
-                .into_iter()
-                .map(|rd| (rd.identity_key.clone(), rd))
-                .collect::<RateDataById>()
-        });
+
+        // A transaction whose anchor is no longer valid can't be included again, so there's no
+        // need to remember it.
+        let valid_anchor_bytes = valid_anchors
+            .iter()
+            .map(|anchor| anchor.to_bytes().to_vec())
+            .collect::<Vec<_>>();
+        query!(
+            "DELETE FROM recent_transactions WHERE NOT (anchor = ANY($1))",
+            &valid_anchor_bytes[..],
+        )
+        .execute(&mut dbtx)
+        .await?;
+
+        let next_rate_data = match block.next_rates {
+            Some(next_rates) => Some(
+                next_rates
+                    .into_iter()
+                    .map(|rd| (rd.identity_key.clone(), rd))
+                    .collect::<RateDataById>(),
+            ),
+            // Slashing changes the rates for the next epoch in place.
+            None if !block.slashings.is_empty() => {
+                let mut next_rate_data = self.next_rate_data_tx.borrow().clone();
+                for (identity_key, slashing) in block.slashings {
+                    next_rate_data.insert(identity_key, slashing.post_slash);
+                }
+                Some(next_rate_data)
+            }
+            None => None,
+        };
 
         // Finally, commit the transaction and then update subscribers
         dbtx.commit().await?;
+        writes.record_metrics();
 
         // Errors in sends arise only if no one is listening -- not our problem.
         let _ = self.height_tx.send(height.try_into().unwrap());
@@ -456,8 +913,98 @@ impl Writer {
         if let Some(next_rate_data) = next_rate_data {
             let _ = self.next_rate_data_tx.send(next_rate_data);
         }
-        // chain_params_tx is a no-op, currently chain params don't change
+        if let Some(chain_params) = block.next_chain_params {
+            let _ = self.chain_params_tx.send(chain_params);
+        }
+        if validators_changed {
+            let _ = self
+                .validator_info_tx
+                .send(self.private_reader.validator_info_snapshot().await?);
+        }
+
+        Ok(writes)
+    }
+}
+
+/// The number of rows of each kind a block wrote, to spot unexpected storage growth, such as
+/// quarantined notes that are never released.
+#[derive(Clone, Debug, Default)]
+pub struct BlockWrites {
+    pub height: u64,
+    /// Notes added to the note set.
+    pub notes: u64,
+    /// Nullifiers added to the nullifier set.
+    pub nullifiers: u64,
+    /// Nullifiers removed from the nullifier set, reverting their spends.
+    pub nullifiers_reverted: u64,
+    /// Base and validator rates recorded or adjusted.
+    pub rates: u64,
+    /// Validator voting powers and states updated.
+    pub statuses: u64,
+    /// Asset supplies recorded or updated.
+    pub supplies: u64,
+    /// Notes, nullifiers and unbondings added to quarantine.
+    pub quarantined: u64,
+    /// Notes, nullifiers and unbondings removed from quarantine, whether released or reverted.
+    pub unquarantined: u64,
+}
+
+impl BlockWrites {
+    /// The row counts, by the `kind` label they're recorded under.
+    pub fn by_kind(&self) -> [(&'static str, u64); 8] {
+        [
+            ("notes", self.notes),
+            ("nullifiers", self.nullifiers),
+            ("nullifiers_reverted", self.nullifiers_reverted),
+            ("rates", self.rates),
+            ("statuses", self.statuses),
+            ("supplies", self.supplies),
+            ("quarantined", self.quarantined),
+            ("unquarantined", self.unquarantined),
+        ]
+    }
+
+    fn record_metrics(&self) {
+        for (kind, rows) in self.by_kind() {
+            counter!(pd_metrics::DB_ROWS_WRITTEN_TOTAL, rows, "kind" => kind);
+        }
+    }
+}
+
+/// A block whose app hash has been computed, ready to be written to the state.
+pub struct PreparedBlock {
+    block: PendingBlock<Ended>,
+    nct_anchor: merkle::Root,
+    node_batch: NodeBatch<merkle::Root>,
+    app_hash: [u8; 32],
+}
+
+impl PreparedBlock {
+    pub fn app_hash(&self) -> &[u8] {
+        &self.app_hash
+    }
+}
+
+/// Writes `data` to the blob stored under `key`, replacing any previous value.
+async fn put_blob(
+    dbtx: &mut Transaction<'_, Postgres>,
+    key: state_key::Blob,
+    data: &[u8],
+) -> Result<()> {
+    query!(
+        "INSERT INTO blobs (id, data) VALUES ($1, $2) ON CONFLICT (id) DO UPDATE SET data = $2",
+        key.as_str(),
+        data
+    )
+    .execute(&mut *dbtx)
+    .await?;
+    Ok(())
+}
 
-        Ok(app_hash.to_vec())
+/// The address a funding stream is recorded with, which is `NULL` for the community pool.
+fn recipient_address(recipient: &FundingStreamRecipient) -> Option<String> {
+    match recipient {
+        FundingStreamRecipient::Address(address) => Some(address.to_string()),
+        FundingStreamRecipient::CommunityPool => None,
     }
 }
@@ -0,0 +1,171 @@
+//! Running Tendermint as a child process of `pd`.
+//!
+//! With `pd start --with-tendermint`, `pd` initializes a Tendermint home
+//! directory if there isn't one yet, starts the `tendermint` binary pointed at
+//! `pd`'s ABCI server, forwards its output into `pd`'s logs, and restarts it if
+//! it exits.  This lets a single-node operator run one command instead of
+//! orchestrating two services; the child is killed when `pd` exits.
+
+use std::{
+    path::PathBuf,
+    process::Stdio,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::Command,
+};
+
+use crate::testnet::{ensure_devnet_chain_id, generate_tm_config};
+
+/// The delay before the first restart after Tendermint exits.
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(1);
+/// The longest delay between restarts when Tendermint keeps exiting.
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+/// If Tendermint ran for at least this long before exiting, the next restart isn't delayed by
+/// previous failures.
+const HEALTHY_RUN: Duration = Duration::from_secs(60);
+
+/// Configuration for a supervised Tendermint process.
+#[derive(Debug, Clone)]
+pub struct TendermintSupervisor {
+    /// The `tendermint` binary to run.
+    pub binary: PathBuf,
+    /// Tendermint's home directory, initialized on first start.
+    pub home: PathBuf,
+    /// The node's moniker, written into a newly initialized config.
+    pub moniker: String,
+    /// The address of `pd`'s ABCI server, e.g. `127.0.0.1:26658`.
+    pub abci_addr: String,
+    /// The address Tendermint's RPC server listens on, e.g. `127.0.0.1:26657`.
+    pub rpc_addr: String,
+    /// The address Tendermint's P2P server listens on, e.g. `0.0.0.0:26656`.
+    pub p2p_addr: String,
+    /// Whether a newly initialized config should produce blocks quickly, for local devnets.
+    pub fast_blocks: bool,
+}
+
+impl TendermintSupervisor {
+    /// Runs Tendermint, restarting it whenever it exits, until it can't be started at all.
+    pub async fn run(self) -> Result<()> {
+        self.init().await?;
+
+        let mut delay = MIN_RESTART_DELAY;
+        loop {
+            let started = Instant::now();
+            let status = self.run_once().await?;
+
+            if started.elapsed() >= HEALTHY_RUN {
+                delay = MIN_RESTART_DELAY;
+            }
+            tracing::warn!(%status, ?delay, "tendermint exited, restarting");
+            tokio::time::sleep(delay).await;
+            delay = std::cmp::min(delay * 2, MAX_RESTART_DELAY);
+        }
+    }
+
+    /// Initializes the home directory, if it hasn't been already.
+    ///
+    /// The config is written from the same template as `pd generate-testnet` uses, and
+    /// `tendermint init` fills in the keys and a single-validator genesis file, leaving the
+    /// existing config in place.
+    async fn init(&self) -> Result<()> {
+        let config_dir = self.home.join("config");
+        let config_file = config_dir.join("config.toml");
+        if config_file.exists() {
+            return Ok(());
+        }
+
+        tracing::info!(home = ?self.home, "initializing tendermint home directory");
+        tokio::fs::create_dir_all(&config_dir)
+            .await
+            .with_context(|| format!("could not create {}", config_dir.display()))?;
+        tokio::fs::write(
+            &config_file,
+            generate_tm_config(&self.moniker, None, self.fast_blocks),
+        )
+        .await
+        .with_context(|| format!("could not write {}", config_file.display()))?;
+
+        let status = Command::new(&self.binary)
+            .arg("init")
+            .arg("validator")
+            .arg("--home")
+            .arg(&self.home)
+            .status()
+            .await
+            .with_context(|| format!("could not run {}", self.binary.display()))?;
+        anyhow::ensure!(status.success(), "tendermint init failed: {}", status);
+
+        // `tendermint init` keeps a genesis file that was put in place beforehand, e.g. to join
+        // an existing network, so only now do we know which chain the fast blocks are for.
+        if self.fast_blocks {
+            if let Err(e) = self.ensure_devnet_genesis().await {
+                // Without the config, the home directory isn't considered initialized, so the
+                // next start writes it again.
+                tokio::fs::remove_file(&config_file)
+                    .await
+                    .with_context(|| format!("could not remove {}", config_file.display()))?;
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Refuses fast blocks if the genesis file is for a public network.
+    async fn ensure_devnet_genesis(&self) -> Result<()> {
+        let genesis_file = self.home.join("config").join("genesis.json");
+        let genesis: serde_json::Value = serde_json::from_slice(
+            &tokio::fs::read(&genesis_file)
+                .await
+                .with_context(|| format!("could not read {}", genesis_file.display()))?,
+        )
+        .with_context(|| format!("could not parse {}", genesis_file.display()))?;
+        let chain_id = genesis["chain_id"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("{} has no chain ID", genesis_file.display()))?;
+        ensure_devnet_chain_id(chain_id, "--fast-blocks")
+    }
+
+    /// Runs Tendermint until it exits, returning its exit status.
+    async fn run_once(&self) -> Result<std::process::ExitStatus> {
+        let mut child = Command::new(&self.binary)
+            .arg("start")
+            .arg("--home")
+            .arg(&self.home)
+            .arg("--proxy_app")
+            .arg(format!("tcp://{}", self.abci_addr))
+            .arg("--rpc.laddr")
+            .arg(format!("tcp://{}", self.rpc_addr))
+            .arg("--p2p.laddr")
+            .arg(format!("tcp://{}", self.p2p_addr))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("could not start {}", self.binary.display()))?;
+        tracing::info!(pid = ?child.id(), "started tendermint");
+
+        let stdout = child.stdout.take().map(|s| tokio::spawn(forward_logs(s)));
+        let stderr = child.stderr.take().map(|s| tokio::spawn(forward_logs(s)));
+
+        let status = child.wait().await?;
+        // Drain whatever Tendermint wrote before exiting, so the reason it exited is logged.
+        for task in stdout.into_iter().chain(stderr) {
+            let _ = task.await;
+        }
+
+        Ok(status)
+    }
+}
+
+/// Forwards each line of Tendermint's output into `pd`'s logs.
+async fn forward_logs(output: impl AsyncRead + Unpin) {
+    let mut lines = BufReader::new(output).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        tracing::info!(target: "tendermint", "{}", line);
+    }
+}
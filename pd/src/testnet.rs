@@ -67,14 +67,61 @@ where
 /// Hardcoded Tendermint config template. Should produce tendermint config similar to
 /// https://github.com/tendermint/tendermint/blob/6291d22f46f4c4f9121375af700dbdafa51577e7/cmd/tendermint/commands/init.go#L45
 /// There exists https://github.com/informalsystems/tendermint-rs/blob/a12118978f2ffea4042d6d38ebfb290d12611314/config/src/config.rs#L23 but
-/// this seemed more straightforward as only the moniker is changed right now.
-pub fn generate_tm_config(node_name: &str) -> String {
+/// this seemed more straightforward as only the moniker and remote signer address are changed right now.
+///
+/// If `priv_validator_laddr` is set, Tendermint listens there for a remote signer (e.g. `tmkms`)
+/// holding the consensus key, rather than reading the key from `priv_validator_key.json`.
+///
+/// With `fast_blocks`, Tendermint waits [`FAST_BLOCKS_TIMEOUT_COMMIT`] rather than
+/// [`DEFAULT_TIMEOUT_COMMIT`] after each block, for local devnets.
+pub fn generate_tm_config(
+    node_name: &str,
+    priv_validator_laddr: Option<&str>,
+    fast_blocks: bool,
+) -> String {
     format!(
         include_str!("../../testnets/tm_config_template.toml"),
-        node_name
+        node_name,
+        priv_validator_laddr.unwrap_or_default(),
+        if fast_blocks {
+            FAST_BLOCKS_TIMEOUT_COMMIT
+        } else {
+            DEFAULT_TIMEOUT_COMMIT
+        },
     )
 }
 
+/// How long Tendermint waits after committing a block before starting the next one.
+pub const DEFAULT_TIMEOUT_COMMIT: &str = "1s";
+
+/// The Tendermint `timeout-commit` for local devnets with fast blocks.
+pub const FAST_BLOCKS_TIMEOUT_COMMIT: &str = "100ms";
+
+/// The names of the public testnets, whose chain IDs are `penumbra-<name>-<suffix>`.
+const PUBLIC_TESTNET_NAMES: &str = include_str!("../../testnets/names.txt");
+
+/// Whether `chain_id` is that of a public Penumbra network, rather than a local devnet.
+pub fn is_public_chain_id(chain_id: &str) -> bool {
+    let chain_id = chain_id.to_lowercase();
+    chain_id == "penumbra"
+        || PUBLIC_TESTNET_NAMES
+            .lines()
+            .map(|name| format!("penumbra-{}", name.trim().to_lowercase()))
+            .any(|prefix| chain_id == prefix || chain_id.starts_with(&format!("{}-", prefix)))
+}
+
+/// Refuses to use the developer setting `flag` on a public network.
+pub fn ensure_devnet_chain_id(chain_id: &str, flag: &str) -> Result<()> {
+    anyhow::ensure!(
+        !is_public_chain_id(chain_id),
+        "{} is only for local devnets, but {} is a public chain ID; use a chain ID that isn't \
+         named after a public testnet",
+        flag,
+        chain_id
+    );
+    Ok(())
+}
+
 /// Represents initial allocations to the testnet.
 #[derive(Debug, Deserialize)]
 pub struct TestnetAllocation {
@@ -0,0 +1,146 @@
+// The entries are never iterated, only looked up, so their order can't affect the chain state.
+#![allow(clippy::disallowed_types)]
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+
+/// The number of transactions whose trace contexts are remembered until they are delivered.
+const CAPACITY: usize = 4096;
+
+/// A W3C trace context `traceparent` header, linking our spans to a client's trace.
+///
+/// See <https://www.w3.org/TR/trace-context/#traceparent-header>.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceParent(String);
+
+impl FromStr for TraceParent {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s.split('-').collect::<Vec<_>>();
+        let valid = match fields[..] {
+            [version, trace_id, parent_id, flags] => {
+                is_hex(version, 2)
+                    && version != "ff"
+                    && is_hex(trace_id, 32)
+                    && trace_id.bytes().any(|b| b != b'0')
+                    && is_hex(parent_id, 16)
+                    && parent_id.bytes().any(|b| b != b'0')
+                    && is_hex(flags, 2)
+            }
+            _ => false,
+        };
+        if valid {
+            Ok(TraceParent(s.to_string()))
+        } else {
+            Err(anyhow!("invalid traceparent {:?}", s))
+        }
+    }
+}
+
+impl fmt::Display for TraceParent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+fn is_hex(field: &str, len: usize) -> bool {
+    field.len() == len
+        && field
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// The trace contexts of transactions submitted through the broadcast service, keyed by the hash
+/// of the encoded transaction, so that their `CheckTx` and `DeliverTx` spans can be linked to the
+/// client's trace.
+///
+/// Tendermint doesn't pass anything but the transaction bytes along to the ABCI application, so
+/// the contexts are kept here in the meantime.  Transactions that are never delivered are
+/// eventually evicted, oldest first.
+///
+/// Clones of a `TraceContexts` share the same underlying storage.
+#[derive(Clone, Debug, Default)]
+pub struct TraceContexts {
+    // We never hold this lock across an await point, so a blocking mutex is fine.
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// A counter incremented on every insertion, used to order entries by age.
+    tick: u64,
+    entries: HashMap<[u8; 32], (u64, TraceParent)>,
+    /// The keys of the entries, ordered by when they were inserted.
+    age: BTreeMap<u64, [u8; 32]>,
+}
+
+impl TraceContexts {
+    /// Records the trace context of the transaction with the given key, evicting the oldest entry
+    /// if there are too many.
+    pub fn insert(&self, key: [u8; 32], traceparent: TraceParent) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+
+        if let Some((previous, _)) = inner.entries.insert(key, (tick, traceparent)) {
+            inner.age.remove(&previous);
+        }
+        inner.age.insert(tick, key);
+
+        while inner.entries.len() > CAPACITY {
+            let (&oldest, &evicted) = inner
+                .age
+                .iter()
+                .next()
+                .expect("age index has an entry for every trace context");
+            inner.age.remove(&oldest);
+            inner.entries.remove(&evicted);
+        }
+    }
+
+    /// Returns the trace context of the transaction with the given key, if any.
+    pub fn get(&self, key: &[u8; 32]) -> Option<TraceParent> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .entries
+            .get(key)
+            .map(|(_, traceparent)| traceparent.clone())
+    }
+
+    /// Removes and returns the trace context of the transaction with the given key, if any.
+    pub fn remove(&self, key: &[u8; 32]) -> Option<TraceParent> {
+        let mut inner = self.inner.lock().unwrap();
+        let (inserted, traceparent) = inner.entries.remove(key)?;
+        inner.age.remove(&inserted);
+        Some(traceparent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_traceparent() {
+        let valid = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        assert_eq!(valid.parse::<TraceParent>().unwrap().to_string(), valid);
+
+        for invalid in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        ] {
+            assert!(invalid.parse::<TraceParent>().is_err(), "{:?}", invalid);
+        }
+    }
+}
@@ -1,23 +1,50 @@
 use std::collections::{BTreeMap, BTreeSet};
 
-use penumbra_crypto::{ka, merkle, note, Nullifier};
-use penumbra_stake::{Delegate, IdentityKey, Undelegate, Validator};
+use penumbra_crypto::{asset, ka, merkle, note, proofs::ProofVersion, Nullifier, Value};
+use penumbra_stake::{
+    Delegate, IdentityKey, Redelegate, RewardSource, Undelegate, Validator, ValidatorMigration,
+};
+use penumbra_transaction::{action::RegisterDenomMetadata, Shape};
 
+mod cache;
 mod stateful;
 mod stateless;
 
 // TODO: eliminate (#374)
+pub use cache::StatelessCache;
 pub use stateful::mark_genesis_as_verified;
 pub use stateless::StatelessTransactionExt;
 
 #[cfg(test)]
 mod tests;
 
+/// The error for a transaction whose anchor isn't one of the recent note commitment tree roots,
+/// either because it's too old or because it was never a root at all.
+///
+/// Clients can rebuild the transaction's proofs against a newer anchor and try again.
+#[derive(Debug)]
+pub struct StaleAnchor;
+
+impl std::fmt::Display for StaleAnchor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid note commitment tree root: the anchor is not among the {} most recent",
+            crate::NUM_RECENT_ANCHORS
+        )
+    }
+}
+
+impl std::error::Error for StaleAnchor {}
+
 #[derive(Debug, Clone)]
 pub struct NoteData {
     pub ephemeral_key: ka::Public,
     pub encrypted_note: [u8; note::NOTE_CIPHERTEXT_BYTES],
     pub transaction_id: [u8; 32],
+    /// If the note was minted by the chain to pay a validator's funding stream, rather than
+    /// created by a transaction, which validator and epoch it was paid for.
+    pub reward_source: Option<RewardSource>,
 }
 
 #[derive(Debug, Clone)]
@@ -28,6 +55,7 @@ pub struct PositionedNoteData {
 
 /// `PendingTransaction` holds data after stateless checks have been applied.
 /// TODO this is a bad name
+#[derive(Debug, Clone)]
 pub struct PendingTransaction {
     /// Transaction ID.
     pub id: [u8; 32],
@@ -39,10 +67,32 @@ pub struct PendingTransaction {
     pub spent_nullifiers: BTreeSet<Nullifier>,
     /// Delegations performed in this transaction.
     pub delegations: Vec<Delegate>,
-    /// Undelegation, if any, performed in this transaction (there must be no more than one).
-    pub undelegation: Option<Undelegate>,
+    /// Undelegations performed in this transaction (there must be no more than one per validator).
+    pub undelegations: Vec<Undelegate>,
+    /// Redelegations performed in this transaction, whose delegator signatures have been checked
+    /// (there must be no more than one per delegator key).
+    pub redelegations: Vec<Redelegate>,
     /// Validators defined in the transaction.
     pub validators: Vec<Validator>,
+    /// Validator identity key migrations performed in this transaction.
+    pub validator_migrations: Vec<ValidatorMigration>,
+    /// Denom metadata registrations in this transaction, whose authority signatures have been
+    /// checked.
+    pub denom_metadata: Vec<RegisterDenomMetadata>,
+    /// The proof versions used by the spend and output proofs in this transaction.
+    ///
+    /// Whether they are accepted depends on the chain parameters and the height, so this is
+    /// checked during stateful verification.
+    pub proof_versions: BTreeSet<ProofVersion>,
+    /// The number of spends and outputs in this transaction.
+    ///
+    /// The limit on outputs is a chain parameter, so this is checked during stateful verification.
+    pub shape: Shape,
+    /// The fee paid by this transaction.
+    ///
+    /// Which assets fees can be paid in is a chain parameter, so this is checked during stateful
+    /// verification.
+    pub fee: Value,
 }
 
 /// `VerifiedTransaction` represents a transaction after all checks have passed.
@@ -50,6 +100,20 @@ pub struct PendingTransaction {
 pub struct VerifiedTransaction {
     /// Transaction ID.
     pub id: [u8; 32],
+    /// The number of spends and outputs in this transaction, for anonymity statistics.
+    pub shape: Shape,
+    /// The changes the transaction makes to the chain state.
+    pub effects: StateEffects,
+}
+
+/// The changes a transaction makes to the chain state.
+///
+/// These are computed once, during stateful verification, and applied as-is
+/// by [`PendingBlock::add_transaction`](crate::PendingBlock::add_transaction).
+/// The token supply changes resulting from (un)delegations are recorded as
+/// `delegation_changes`, and applied to the supply at the end of the epoch.
+#[derive(Debug, Clone, Default)]
+pub struct StateEffects {
     /// Note data to add from outputs in this transaction.
     pub new_notes: BTreeMap<note::Commitment, NoteData>,
     /// List of spent nullifiers from spends in this transaction.
@@ -60,6 +124,27 @@ pub struct VerifiedTransaction {
     /// indicates that a validator's net change in delegation in this transaction was zero *but it
     /// experienced some (un)delegations*.
     pub delegation_changes: BTreeMap<IdentityKey, i64>,
-    /// The validators from whom an undelegation was performed in this transaction.
-    pub undelegation_validator: Option<IdentityKey>,
+    /// The amount of stake unbonded by undelegations in this transaction, keyed by the current
+    /// identity key of the validator it was undelegated from.
+    pub undelegations: BTreeMap<IdentityKey, u64>,
+    /// The epoch index each delegator key in this transaction redelegated at, which starts its
+    /// redelegation cooldown.
+    pub redelegations: BTreeMap<[u8; 32], u64>,
+    /// The current identity keys of the validators redelegated away from by delegator keys
+    /// redelegating for the first time.
+    ///
+    /// The redelegated stake stays bonded, but the transaction is quarantined relative to these
+    /// validators as if it had undelegated from them.
+    pub quarantined_redelegations: BTreeSet<IdentityKey>,
+    /// Validator identity key migrations performed in this transaction, from old to new key.
+    pub validator_migrations: BTreeMap<IdentityKey, IdentityKey>,
+    /// Updated validator definitions in this transaction, by identity key.
+    pub validator_definitions: BTreeMap<IdentityKey, Validator>,
+    /// Denom metadata registered in this transaction, by asset ID.
+    pub denom_metadata: BTreeMap<asset::Id, asset::Metadata>,
+    /// The fees paid in this transaction, by asset ID.
+    pub fees: BTreeMap<asset::Id, u64>,
+    /// The transaction's ID and anchor, recorded so that the same transaction can't be included
+    /// again while its anchor is valid.  Unset for the genesis transaction, which has no anchor.
+    pub recent_transaction: Option<([u8; 32], merkle::Root)>,
 }
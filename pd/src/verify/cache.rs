@@ -0,0 +1,197 @@
+// The entries are never iterated, only looked up, so their order can't affect the chain state.
+#![allow(clippy::disallowed_types)]
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
+
+use sha2::{Digest, Sha256};
+
+use super::PendingTransaction;
+
+/// A bounded LRU cache of stateless verification results, keyed by the hash of
+/// the encoded transaction.
+///
+/// Stateless verification (checking signatures and proofs) is the expensive
+/// part of transaction validation, and its result depends only on the
+/// transaction bytes.  This cache is shared between the mempool and consensus
+/// services, so that a transaction this node already checked in `CheckTx` does
+/// not have its proofs verified a second time in `DeliverTx`.  Stateful checks
+/// are *not* cached, since the chain state may change in between.
+///
+/// Clones of a `StatelessCache` share the same underlying storage.
+#[derive(Clone, Debug)]
+pub struct StatelessCache {
+    // We never hold this lock across an await point, so a blocking mutex is fine.
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    capacity: usize,
+    /// A counter incremented on every access, used to order entries by recency.
+    tick: u64,
+    /// The cached entries, along with the tick at which they were last used.
+    entries: HashMap<[u8; 32], (u64, PendingTransaction)>,
+    /// The keys of the cached entries, ordered by the tick at which they were
+    /// last used, so the least recently used entry comes first.
+    recency: BTreeMap<u64, [u8; 32]>,
+}
+
+impl StatelessCache {
+    /// Create a new cache holding at most `capacity` verified transactions.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                capacity,
+                tick: 0,
+                entries: HashMap::new(),
+                recency: BTreeMap::new(),
+            })),
+        }
+    }
+
+    /// Computes the cache key for an encoded transaction.
+    ///
+    /// This is the same hash used to identify transactions in ABCI request spans.
+    pub fn key(tx_bytes: &[u8]) -> [u8; 32] {
+        let mut key = [0; 32];
+        key[..].copy_from_slice(Sha256::digest(tx_bytes).as_slice());
+        key
+    }
+
+    /// Look up the stateless verification result for a transaction, marking it as
+    /// recently used.
+    pub fn get(&self, key: &[u8; 32]) -> Option<PendingTransaction> {
+        let mut inner = self.inner.lock().unwrap();
+        let tick = inner.next_tick();
+
+        let (last_used, transaction) = inner.entries.get_mut(key)?;
+        let previous = std::mem::replace(last_used, tick);
+        let transaction = transaction.clone();
+
+        inner.recency.remove(&previous);
+        inner.recency.insert(tick, *key);
+
+        Some(transaction)
+    }
+
+    /// Remove and return the stateless verification result for a transaction.
+    pub fn take(&self, key: &[u8; 32]) -> Option<PendingTransaction> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let (last_used, transaction) = inner.entries.remove(key)?;
+        inner.recency.remove(&last_used);
+
+        Some(transaction)
+    }
+
+    /// Record the stateless verification result for a transaction, evicting the
+    /// least recently used entry if the cache is full.
+    pub fn insert(&self, key: [u8; 32], transaction: PendingTransaction) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.capacity == 0 {
+            return;
+        }
+        let tick = inner.next_tick();
+
+        if let Some((previous, _)) = inner.entries.insert(key, (tick, transaction)) {
+            inner.recency.remove(&previous);
+        }
+        inner.recency.insert(tick, key);
+
+        while inner.entries.len() > inner.capacity {
+            let (&oldest, &evicted) = inner
+                .recency
+                .iter()
+                .next()
+                .expect("recency index has an entry for every cached transaction");
+            inner.recency.remove(&oldest);
+            inner.entries.remove(&evicted);
+        }
+    }
+
+    /// Returns the number of cached transactions.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    /// Returns true if the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the maximum number of cached transactions.
+    pub fn capacity(&self) -> usize {
+        self.inner.lock().unwrap().capacity
+    }
+}
+
+impl Inner {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use penumbra_crypto::{merkle, Fq, Value, Zero};
+    use penumbra_stake::STAKING_TOKEN_ASSET_ID;
+    use penumbra_transaction::Shape;
+
+    use super::*;
+
+    fn pending(id: u8) -> PendingTransaction {
+        PendingTransaction {
+            id: [id; 32],
+            root: merkle::Root(Fq::zero()),
+            new_notes: BTreeMap::new(),
+            spent_nullifiers: BTreeSet::new(),
+            delegations: Vec::new(),
+            undelegations: Vec::new(),
+            redelegations: Vec::new(),
+            validators: Vec::new(),
+            validator_migrations: Vec::new(),
+            denom_metadata: Vec::new(),
+            proof_versions: BTreeSet::new(),
+            shape: Shape {
+                spends: 0,
+                outputs: 0,
+            },
+            fee: Value {
+                amount: 0,
+                asset_id: *STAKING_TOKEN_ASSET_ID,
+            },
+        }
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = StatelessCache::new(2);
+        cache.insert([1; 32], pending(1));
+        cache.insert([2; 32], pending(2));
+
+        // Touch the first entry, so that the second is the least recently used.
+        assert_eq!(cache.get(&[1; 32]).unwrap().id, [1; 32]);
+
+        cache.insert([3; 32], pending(3));
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&[2; 32]).is_none());
+        assert!(cache.get(&[1; 32]).is_some());
+        assert!(cache.get(&[3; 32]).is_some());
+    }
+
+    #[test]
+    fn take_removes_entry() {
+        let cache = StatelessCache::new(2);
+        cache.insert([1; 32], pending(1));
+
+        assert_eq!(cache.take(&[1; 32]).unwrap().id, [1; 32]);
+        assert!(cache.take(&[1; 32]).is_none());
+        assert!(cache.is_empty());
+    }
+}
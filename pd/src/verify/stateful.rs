@@ -1,23 +1,217 @@
 use std::collections::{BTreeMap, BTreeSet};
 
 use anyhow::Error;
-use penumbra_crypto::{note, Nullifier};
-use penumbra_transaction::{Action, Transaction};
+use penumbra_crypto::{asset, note, Nullifier};
+use penumbra_stake::{
+    IdentityKey, RateData, Validator, ValidatorInfo, ValidatorState, STAKING_TOKEN_ASSET_ID,
+};
+use penumbra_transaction::{Action, Shape, Transaction};
 
-use super::{NoteData, PendingTransaction, VerifiedTransaction};
-use crate::state;
+use super::{NoteData, PendingTransaction, StaleAnchor, StateEffects, VerifiedTransaction};
+use crate::{
+    circuit_breaker::{self, CircuitBreakerTripped},
+    state,
+};
+
+/// The chain state read from the database during stateful verification.
+///
+/// This is read once for a batch of transactions, so that each nullifier, asset and migration is
+/// only looked up once, however many of the transactions refer to it.
+#[derive(Debug, Default)]
+struct StatefulReads {
+    /// The nullifiers spent by the transactions that were already spent in the chain state.
+    spent_nullifiers: BTreeSet<Nullifier>,
+    /// The validator identity key migrations, if any of the transactions need them.
+    migrations: BTreeMap<IdentityKey, (IdentityKey, u64)>,
+    /// The assets the transactions register metadata for that the chain knows about.
+    known_assets: BTreeSet<asset::Id>,
+    /// The epoch index each of the transactions' delegator keys last redelegated at, if any did.
+    last_redelegations: BTreeMap<[u8; 32], u64>,
+    /// The transactions that were already included while their anchor is still valid.
+    recent_transactions: BTreeSet<[u8; 32]>,
+}
 
 impl state::Reader {
+    /// Checks the transaction against the chain state.
+    ///
+    /// The `validators` snapshot is shared by all the transactions verified against the same
+    /// state, so that they don't each need to load the validator set.
     pub async fn verify_stateful(
         &self,
         transaction: PendingTransaction,
+        validators: &BTreeMap<IdentityKey, ValidatorInfo>,
+    ) -> Result<VerifiedTransaction, Error> {
+        let reads = self
+            .stateful_reads(std::slice::from_ref(&transaction))
+            .await?;
+        self.verify_stateful_with(transaction, validators, &reads)
+    }
+
+    /// Checks each of the transactions against the chain state, reading the state they depend on
+    /// from the database in one pass, rather than once per transaction.
+    ///
+    /// Each transaction is checked independently, as if by [`Self::verify_stateful`]: conflicts
+    /// between the transactions, such as two spends of the same note, are not detected.  The
+    /// outer error is for failures to read the chain state, which make every result unknown.
+    pub async fn verify_stateful_batch(
+        &self,
+        transactions: Vec<PendingTransaction>,
+        validators: &BTreeMap<IdentityKey, ValidatorInfo>,
+    ) -> Result<Vec<Result<VerifiedTransaction, Error>>, Error> {
+        let reads = self.stateful_reads(&transactions).await?;
+        Ok(transactions
+            .into_iter()
+            .map(|transaction| self.verify_stateful_with(transaction, validators, &reads))
+            .collect())
+    }
+
+    /// Reads the chain state that stateful verification of the transactions depends on, beyond
+    /// what the reader caches in memory.
+    async fn stateful_reads(
+        &self,
+        transactions: &[PendingTransaction],
+    ) -> Result<StatefulReads, Error> {
+        let nullifiers = transactions
+            .iter()
+            .flat_map(|transaction| transaction.spent_nullifiers.iter().cloned())
+            .collect::<BTreeSet<_>>();
+        let spent_nullifiers = if nullifiers.is_empty() {
+            BTreeSet::new()
+        } else {
+            self.check_nullifiers(&nullifiers).await?
+        };
+
+        let migrations = if transactions.iter().any(|transaction| {
+            !transaction.undelegations.is_empty()
+                || !transaction.redelegations.is_empty()
+                || !transaction.validator_migrations.is_empty()
+                || !transaction.validators.is_empty()
+        }) {
+            self.validator_migrations().await?
+        } else {
+            BTreeMap::new()
+        };
+
+        let mut known_assets = BTreeSet::new();
+        let metadata_assets = transactions
+            .iter()
+            .flat_map(|transaction| transaction.denom_metadata.iter())
+            .map(|registration| registration.body.metadata.denom.id())
+            .collect::<BTreeSet<_>>();
+        for asset_id in metadata_assets {
+            if self.asset_lookup(asset_id).await?.is_some() {
+                known_assets.insert(asset_id);
+            }
+        }
+
+        let delegator_keys = transactions
+            .iter()
+            .flat_map(|transaction| transaction.redelegations.iter())
+            .map(|redelegation| redelegation.body.delegator_key.to_bytes())
+            .collect::<BTreeSet<_>>();
+        let last_redelegations = if delegator_keys.is_empty() {
+            BTreeMap::new()
+        } else {
+            self.last_redelegations(&delegator_keys).await?
+        };
+
+        let ids = transactions
+            .iter()
+            .map(|transaction| transaction.id)
+            .collect::<BTreeSet<_>>();
+        let recent_transactions = self.recent_transactions(&ids).await?;
+
+        Ok(StatefulReads {
+            spent_nullifiers,
+            migrations,
+            known_assets,
+            last_redelegations,
+            recent_transactions,
+        })
+    }
+
+    /// Checks the transaction against the chain state, given the state read for it by
+    /// [`Self::stateful_reads`].
+    fn verify_stateful_with(
+        &self,
+        transaction: PendingTransaction,
+        validators: &BTreeMap<IdentityKey, ValidatorInfo>,
+        reads: &StatefulReads,
     ) -> Result<VerifiedTransaction, Error> {
         let anchor_is_valid = self.valid_anchors_rx().borrow().contains(&transaction.root);
         if !anchor_is_valid {
-            return Err(anyhow::anyhow!("invalid note commitment tree root"));
+            return Err(StaleAnchor.into());
+        }
+
+        // Nullifiers stop a transaction that spends notes from being included twice, but not one
+        // that doesn't, so check that this one wasn't included while its anchor was valid.
+        if reads.recent_transactions.contains(&transaction.id) {
+            return Err(anyhow::anyhow!(
+                "Transaction {} was already included in a recent block",
+                hex::encode(transaction.id)
+            ));
+        }
+
+        // The transaction will be included in the block after the last committed one.
+        let height = self.height_rx().borrow().value() + 1;
+        for proof_version in &transaction.proof_versions {
+            let accepted = self
+                .chain_params_rx()
+                .borrow()
+                .accepts_proof_version(*proof_version, height);
+            if !accepted {
+                return Err(anyhow::anyhow!(
+                    "proof version {:?} is not accepted at height {}",
+                    proof_version,
+                    height
+                ));
+            }
         }
 
-        let existing_nullifiers = self.check_nullifiers(&transaction.spent_nullifiers).await?;
+        // Unlike the node's own circuit breaker, the emergency halt is part of the chain state, so
+        // every node agrees on which transactions it rejects.
+        if self
+            .chain_params_rx()
+            .borrow()
+            .halts_value_transfers(height)
+            && circuit_breaker::moves_value(&transaction)
+        {
+            return Err(CircuitBreakerTripped.into());
+        }
+
+        let max_outputs = self.chain_params_rx().borrow().max_outputs_per_transaction;
+        if transaction.shape.outputs as u64 > max_outputs {
+            return Err(anyhow::anyhow!(
+                "transaction has {} outputs, but at most {} are allowed",
+                transaction.shape.outputs,
+                max_outputs
+            ));
+        }
+
+        // Fees can be paid in the staking token, or in any asset the chain parameters give a
+        // fee rate for.
+        let fee_asset_id = transaction.fee.asset_id;
+        if fee_asset_id != *STAKING_TOKEN_ASSET_ID
+            && self
+                .chain_params_rx()
+                .borrow()
+                .fee_rate(fee_asset_id)
+                .is_none()
+        {
+            return Err(anyhow::anyhow!(
+                "fees cannot be paid in asset {}",
+                fee_asset_id
+            ));
+        }
+        let mut fees = BTreeMap::new();
+        if transaction.fee.amount > 0 {
+            fees.insert(fee_asset_id, transaction.fee.amount);
+        }
+
+        let existing_nullifiers = transaction
+            .spent_nullifiers
+            .intersection(&reads.spent_nullifiers)
+            .collect::<BTreeSet<_>>();
         if !existing_nullifiers.is_empty() {
             return Err(anyhow::anyhow!(
                 "nullifiers already spent in state: {:?}",
@@ -79,15 +273,15 @@ impl state::Reader {
                 ));
             }
         }
-        if let Some(ref u) = transaction.undelegation {
-            let rate_data = self
-                .next_rate_data_rx()
-                .borrow()
-                .get(&u.validator_identity)
-                .ok_or_else(|| {
-                    anyhow::anyhow!("Unknown validator identity {}", u.validator_identity)
-                })?
-                .clone();
+
+        // Validators whose identity key has been rotated are only known by their new identity key
+        // from the epoch after the migration, but their old delegation tokens remain valid.
+        let migrations = &reads.migrations;
+
+        let mut undelegations = BTreeMap::new();
+        for u in &transaction.undelegations {
+            let (current_identity, rate_data) =
+                self.current_rate_data(&u.validator_identity, migrations)?;
 
             // Check whether the epoch is correct first, to give a more helpful
             // error message if it's wrong.
@@ -124,6 +318,18 @@ impl state::Reader {
                 *delegation_changes
                     .entry(u.validator_identity.clone())
                     .or_insert(0) -= i64::try_from(u.delegation_amount).unwrap();
+                // The undelegated stake is quarantined under the validator's current identity key,
+                // so that it is reverted if the validator is slashed under its new key. Delegation
+                // tokens from before and after a migration are still one validator's stake.
+                if undelegations
+                    .insert(current_identity.clone(), u.unbonded_amount)
+                    .is_some()
+                {
+                    return Err(anyhow::anyhow!(
+                        "Multiple undelegations from validator {} in one transaction",
+                        current_identity
+                    ));
+                }
             } else {
                 return Err(anyhow::anyhow!(
                     "Given {} delegation tokens, expected {} unbonded stake but description produces {}",
@@ -134,14 +340,280 @@ impl state::Reader {
             }
         }
 
+        let mut redelegations = BTreeMap::new();
+        let mut quarantined_redelegations = BTreeSet::new();
+        for r in &transaction.redelegations {
+            let r = &r.body;
+            let (from_identity, from_rate_data) =
+                self.current_rate_data(&r.from_validator_identity, migrations)?;
+            let to_rate_data = self
+                .next_rate_data_rx()
+                .borrow()
+                .get(&r.to_validator_identity)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Unknown validator identity {}", r.to_validator_identity)
+                })?
+                .clone();
+            if from_identity == r.to_validator_identity {
+                return Err(anyhow::anyhow!(
+                    "Redelegation from {} to {} doesn't change validator",
+                    r.from_validator_identity,
+                    r.to_validator_identity
+                ));
+            }
+
+            // Both validators' rates are for the same epoch, so checking one is enough.
+            if r.epoch_index != from_rate_data.epoch_index {
+                return Err(anyhow::anyhow!(
+                    "Redelegation was prepared for next epoch {} but the next epoch is {}",
+                    r.epoch_index,
+                    from_rate_data.epoch_index
+                ));
+            }
+
+            // A redelegation is priced like an undelegation followed by a delegation: from the
+            // delegation tokens consumed, to the stake they're worth, to the delegation tokens
+            // that stake buys, rounding at each step.
+            let expected_unbonded_amount = from_rate_data.unbonded_amount(r.from_delegation_amount);
+            if expected_unbonded_amount != r.unbonded_amount {
+                return Err(anyhow::anyhow!(
+                    "Given {} delegation tokens, expected {} unbonded stake but redelegation produces {}",
+                    r.from_delegation_amount,
+                    expected_unbonded_amount,
+                    r.unbonded_amount,
+                ));
+            }
+            let expected_to_delegation_amount = to_rate_data.delegation_amount(r.unbonded_amount);
+            if expected_to_delegation_amount != r.to_delegation_amount {
+                return Err(anyhow::anyhow!(
+                    "Given {} unbonded stake, expected {} delegation tokens but redelegation produces {}",
+                    r.unbonded_amount,
+                    expected_to_delegation_amount,
+                    r.to_delegation_amount,
+                ));
+            }
+
+            // Redelegated stake skips the unbonding quarantine, so a delegator can only
+            // redelegate once per unbonding period, or it could keep moving its stake away from
+            // any validator about to be slashed.
+            let delegator_key = r.delegator_key.to_bytes();
+            let cooldown_epochs = self.chain_params_rx().borrow().unbonding_epochs;
+            match reads.last_redelegations.get(&delegator_key) {
+                Some(last_epoch_index) => {
+                    if r.epoch_index < last_epoch_index + cooldown_epochs {
+                        return Err(anyhow::anyhow!(
+                            "Delegator key {} last redelegated in epoch {}, and can't redelegate again until epoch {}",
+                            hex::encode(delegator_key),
+                            last_epoch_index,
+                            last_epoch_index + cooldown_epochs
+                        ));
+                    }
+                }
+                None => {
+                    // Delegator keys are free to make, so a key's first redelegation is
+                    // quarantined under the first validator like an undelegation.  Otherwise a
+                    // fresh key per redelegation would skip both the quarantine and the cooldown.
+                    quarantined_redelegations.insert(from_identity.clone());
+                }
+            }
+            redelegations.insert(delegator_key, r.epoch_index);
+
+            // Both validators' delegation token supplies change at the end of the epoch, but the
+            // stake stays bonded, so the staking token supply only changes by rounding.
+            *delegation_changes
+                .entry(r.from_validator_identity.clone())
+                .or_insert(0) -= i64::try_from(r.from_delegation_amount).unwrap();
+            *delegation_changes
+                .entry(r.to_validator_identity.clone())
+                .or_insert(0) += i64::try_from(r.to_delegation_amount).unwrap();
+        }
+
+        let mut validator_migrations = BTreeMap::new();
+        for m in &transaction.validator_migrations {
+            // Only a validator that is currently known by its identity key can migrate away from it.
+            if !self
+                .next_rate_data_rx()
+                .borrow()
+                .contains_key(&m.old_identity_key)
+            {
+                return Err(anyhow::anyhow!(
+                    "Unknown validator identity {}",
+                    m.old_identity_key
+                ));
+            }
+            if migrations.contains_key(&m.old_identity_key)
+                || validator_migrations.contains_key(&m.old_identity_key)
+            {
+                return Err(anyhow::anyhow!(
+                    "Validator {} is already migrating its identity key",
+                    m.old_identity_key
+                ));
+            }
+
+            // The new identity key must never have been used by any validator, or delegation
+            // tokens for it would already exist.
+            let new_key_in_use = self
+                .next_rate_data_rx()
+                .borrow()
+                .contains_key(&m.new_identity_key)
+                || migrations.contains_key(&m.new_identity_key)
+                || migrations
+                    .values()
+                    .any(|(new_identity_key, _)| new_identity_key == &m.new_identity_key)
+                || validator_migrations
+                    .values()
+                    .any(|k| k == &m.new_identity_key);
+            if new_key_in_use {
+                return Err(anyhow::anyhow!(
+                    "Identity key {} is already in use",
+                    m.new_identity_key
+                ));
+            }
+
+            validator_migrations.insert(m.old_identity_key.clone(), m.new_identity_key.clone());
+        }
+
+        let mut validator_definitions = BTreeMap::new();
+        for v in transaction.validators {
+            // TODO: support defining new validators; for now, definitions can only update the
+            // configuration of a validator that is already known to the chain.
+            let current = &validators
+                .get(&v.identity_key)
+                .ok_or_else(|| anyhow::anyhow!("Unknown validator identity {}", v.identity_key))?
+                .validator;
+
+            // Definitions are signed by the validator's identity key, but anyone could resubmit an
+            // old one, so the chain only accepts definitions with increasing sequence numbers.
+            if v.sequence_number <= current.sequence_number {
+                return Err(anyhow::anyhow!(
+                    "Validator definition for {} has sequence number {}, but the current sequence number is {}",
+                    v.identity_key,
+                    v.sequence_number,
+                    current.sequence_number
+                ));
+            }
+            // Tendermint identifies validators by consensus key, so no two may share one.
+            if let Some(other) = consensus_key_conflict(&v, validators, migrations) {
+                return Err(anyhow::anyhow!(
+                    "Validator definition for {} uses the consensus key of validator {}",
+                    v.identity_key,
+                    other.validator.identity_key
+                ));
+            }
+            if v.consensus_key != current.consensus_key {
+                return Err(anyhow::anyhow!(
+                    "Validator definition for {} changes the consensus key, which is not supported",
+                    v.identity_key
+                ));
+            }
+            if validator_definitions.contains_key(&v.identity_key) {
+                return Err(anyhow::anyhow!(
+                    "Multiple definitions for validator {} in one transaction",
+                    v.identity_key
+                ));
+            }
+
+            validator_definitions.insert(v.identity_key.clone(), v);
+        }
+
+        let mut denom_metadata = BTreeMap::new();
+        let authority = self.chain_params_rx().borrow().denom_metadata_authority;
+        for registration in transaction.denom_metadata {
+            // Only the chain's denom metadata authority can register or update metadata, so that
+            // nobody can claim a symbol for someone else's asset, such as a delegation token.
+            if authority != Some(registration.body.authority_key.into()) {
+                return Err(anyhow::anyhow!(
+                    "Denom metadata must be registered by the chain's denom metadata authority"
+                ));
+            }
+            let metadata = registration.body.metadata;
+            // Metadata can only be registered for assets the chain knows about.
+            let asset_id = metadata.denom.id();
+            if !reads.known_assets.contains(&asset_id) {
+                return Err(anyhow::anyhow!(
+                    "Cannot register metadata for unknown denom {}",
+                    metadata.denom
+                ));
+            }
+            if denom_metadata.contains_key(&asset_id) {
+                return Err(anyhow::anyhow!(
+                    "Multiple metadata registrations for denom {} in one transaction",
+                    metadata.denom
+                ));
+            }
+
+            denom_metadata.insert(asset_id, metadata);
+        }
+
         Ok(VerifiedTransaction {
             id: transaction.id,
-            new_notes: transaction.new_notes,
-            spent_nullifiers: transaction.spent_nullifiers,
-            delegation_changes,
-            undelegation_validator: transaction.undelegation.map(|u| u.validator_identity),
+            shape: transaction.shape,
+            effects: StateEffects {
+                new_notes: transaction.new_notes,
+                spent_nullifiers: transaction.spent_nullifiers,
+                delegation_changes,
+                undelegations,
+                redelegations,
+                quarantined_redelegations,
+                validator_migrations,
+                validator_definitions,
+                denom_metadata,
+                fees,
+                recent_transaction: Some((transaction.id, transaction.root)),
+            },
         })
     }
+
+    /// The next epoch's rates for the validator whose delegation tokens are named by
+    /// `identity_key`, along with the validator's current identity key.
+    ///
+    /// Validators whose identity key has been rotated are only known by their new identity key
+    /// from the epoch after the migration, but their old delegation tokens remain valid.
+    fn current_rate_data(
+        &self,
+        identity_key: &IdentityKey,
+        migrations: &BTreeMap<IdentityKey, (IdentityKey, u64)>,
+    ) -> Result<(IdentityKey, RateData), Error> {
+        let next_rate_data = self.next_rate_data_rx().borrow();
+        let mut current_identity = identity_key.clone();
+        loop {
+            if let Some(rate_data) = next_rate_data.get(&current_identity) {
+                return Ok((current_identity, rate_data.clone()));
+            }
+            current_identity = migrations
+                .get(&current_identity)
+                .map(|(new_identity_key, _)| new_identity_key.clone())
+                .ok_or_else(|| anyhow::anyhow!("Unknown validator identity {}", identity_key))?;
+        }
+    }
+}
+
+/// Finds another validator using the consensus key of `definition`, if there is one.
+///
+/// A validator that migrated its identity key keeps its consensus key, so the inactive entries
+/// for the identity keys it migrated away from aren't conflicts.
+fn consensus_key_conflict<'a>(
+    definition: &Validator,
+    validators: &'a BTreeMap<IdentityKey, ValidatorInfo>,
+    migrations: &BTreeMap<IdentityKey, (IdentityKey, u64)>,
+) -> Option<&'a ValidatorInfo> {
+    let migrated_to_definition = |identity_key: &IdentityKey| {
+        let mut identity_key = identity_key;
+        while let Some((new_identity_key, _)) = migrations.get(identity_key) {
+            if new_identity_key == &definition.identity_key {
+                return true;
+            }
+            identity_key = new_identity_key;
+        }
+        false
+    };
+
+    validators.values().find(|info| {
+        info.validator.identity_key != definition.identity_key
+            && info.validator.consensus_key == definition.consensus_key
+            && !(info.status.state == ValidatorState::Inactive
+                && migrated_to_definition(&info.validator.identity_key))
+    })
 }
 
 // TODO: replace this with just inserting genesis notes directly
@@ -158,6 +630,7 @@ pub fn mark_genesis_as_verified(transaction: Transaction) -> VerifiedTransaction
                         ephemeral_key: inner.body.ephemeral_key,
                         encrypted_note: inner.body.encrypted_note,
                         transaction_id: transaction.id(),
+                        reward_source: None,
                     },
                 );
             }
@@ -169,9 +642,82 @@ pub fn mark_genesis_as_verified(transaction: Transaction) -> VerifiedTransaction
 
     VerifiedTransaction {
         id: transaction.id(),
-        new_notes,
-        spent_nullifiers: BTreeSet::<Nullifier>::new(),
-        delegation_changes: BTreeMap::new(),
-        undelegation_validator: None,
+        shape: Shape::of(&transaction.transaction_body),
+        effects: StateEffects {
+            new_notes,
+            ..Default::default()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::rdsa::{SigningKey, SpendAuth, VerificationKey};
+    use penumbra_stake::{FundingStreams, ValidatorStatus};
+    use rand_core::OsRng;
+
+    use super::*;
+
+    fn validator_info(state: ValidatorState) -> ValidatorInfo {
+        let identity_key = IdentityKey(VerificationKey::from(&SigningKey::<SpendAuth>::new(OsRng)));
+        ValidatorInfo {
+            validator: Validator {
+                identity_key: identity_key.clone(),
+                consensus_key: tendermint::PrivateKey::Ed25519(ed25519_consensus::SigningKey::new(
+                    OsRng,
+                ))
+                .public_key(),
+                name: String::new(),
+                website: String::new(),
+                description: String::new(),
+                funding_streams: FundingStreams::new(),
+                sequence_number: 0,
+            },
+            status: ValidatorStatus {
+                identity_key: identity_key.clone(),
+                voting_power: 0,
+                state,
+            },
+            rate_data: RateData {
+                identity_key,
+                epoch_index: 0,
+                validator_reward_rate: 0,
+                validator_exchange_rate: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn consensus_keys_conflict_outside_a_migration_chain() {
+        let a = validator_info(ValidatorState::Active);
+        let mut old_a = validator_info(ValidatorState::Inactive);
+        old_a.validator.consensus_key = a.validator.consensus_key.clone();
+        let b = validator_info(ValidatorState::Active);
+        let validators = [&a, &old_a, &b]
+            .into_iter()
+            .map(|info| (info.validator.identity_key.clone(), info.clone()))
+            .collect::<BTreeMap<_, _>>();
+
+        // With no migrations, the old entry sharing the key is another validator.
+        let migrations = BTreeMap::new();
+        let conflict = consensus_key_conflict(&a.validator, &validators, &migrations);
+        assert_eq!(
+            conflict.map(|info| &info.validator.identity_key),
+            Some(&old_a.validator.identity_key)
+        );
+
+        // Once it's known to have migrated to `a`, it's `a`'s own entry.
+        let migrations = [(
+            old_a.validator.identity_key.clone(),
+            (a.validator.identity_key.clone(), 0),
+        )]
+        .into_iter()
+        .collect();
+        assert!(consensus_key_conflict(&a.validator, &validators, &migrations).is_none());
+
+        // But another validator still can't take that key.
+        let mut definition = b.validator.clone();
+        definition.consensus_key = a.validator.consensus_key.clone();
+        assert!(consensus_key_conflict(&definition, &validators, &migrations).is_some());
     }
 }
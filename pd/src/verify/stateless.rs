@@ -1,9 +1,9 @@
 use std::collections::{BTreeMap, BTreeSet};
 
 use anyhow::{Context, Error};
-use penumbra_crypto::{note, Nullifier};
-use penumbra_stake::{Delegate, Undelegate, Validator};
-use penumbra_transaction::{Action, Transaction};
+use penumbra_crypto::{note, proofs::ProofVersion, Nullifier};
+use penumbra_stake::{Delegate, Redelegate, Undelegate, Validator, ValidatorMigration};
+use penumbra_transaction::{action::RegisterDenomMetadata, Action, Shape, Transaction};
 
 use super::{NoteData, PendingTransaction};
 
@@ -22,6 +22,16 @@ impl StatelessTransactionExt for Transaction {
     fn verify_stateless(&self) -> Result<PendingTransaction, Error> {
         let id = self.id();
 
+        // 0. Check the transaction has a standard shape, so that it's indistinguishable from
+        // the other transactions in its shape class.
+        let shape = Shape::of(&self.transaction_body);
+        if !shape.is_standard() {
+            return Err(anyhow::anyhow!(
+                "transaction has non-standard shape {}",
+                shape
+            ));
+        }
+
         let sighash = self.transaction_body().sighash();
 
         // 1. Check binding signature.
@@ -35,32 +45,44 @@ impl StatelessTransactionExt for Transaction {
         let mut spent_nullifiers = BTreeSet::<Nullifier>::new();
         let mut new_notes = BTreeMap::<note::Commitment, NoteData>::new();
         let mut delegations = Vec::<Delegate>::new();
-        let mut undelegation = None::<Undelegate>;
-        let validators = Vec::<Validator>::new();
+        let mut undelegations = Vec::<Undelegate>::new();
+        let mut redelegations = Vec::<Redelegate>::new();
+        let mut validators = Vec::<Validator>::new();
+        let mut validator_migrations = Vec::<ValidatorMigration>::new();
+        let mut denom_metadata = Vec::<RegisterDenomMetadata>::new();
+        let mut proof_versions = BTreeSet::<ProofVersion>::new();
 
         for action in self.transaction_body().actions {
             match action {
                 Action::Output(output) => {
-                    if output
-                        .body
-                        .proof
-                        .verify(
-                            output.body.value_commitment,
-                            output.body.note_commitment,
-                            output.body.ephemeral_key,
-                        )
-                        .is_err()
-                    {
+                    // Check the proof with the verifier for the proof version it claims.
+                    let verified = match output.body.proof_version {
+                        ProofVersion::Transparent | ProofVersion::TransparentDummySpends => {
+                            output.body.proof.verify(
+                                output.body.value_commitment,
+                                output.body.note_commitment,
+                                output.body.ephemeral_key,
+                            )
+                        }
+                    };
+                    proof_versions.insert(output.body.proof_version);
+                    if verified.is_err() {
                         // TODO should the verification error be bubbled up here?
                         return Err(anyhow::anyhow!("An output proof did not verify"));
                     }
 
+                    // Each output is a new note, which must be distinct from the others.
+                    if new_notes.contains_key(&output.body.note_commitment) {
+                        return Err(anyhow::anyhow!("Duplicate output note commitment"));
+                    }
+
                     new_notes.insert(
                         output.body.note_commitment,
                         NoteData {
                             ephemeral_key: output.body.ephemeral_key,
                             encrypted_note: output.body.encrypted_note,
                             transaction_id: id,
+                            reward_source: None,
                         },
                     );
                 }
@@ -71,17 +93,24 @@ impl StatelessTransactionExt for Transaction {
                         .verify(&sighash, &spend.auth_sig)
                         .context("spend auth signature failed to verify")?;
 
-                    if spend
-                        .body
-                        .proof
-                        .verify(
+                    let verified = match spend.body.proof_version {
+                        ProofVersion::Transparent => spend.body.proof.verify(
                             self.transaction_body().merkle_root,
                             spend.body.value_commitment,
                             spend.body.nullifier.clone(),
                             spend.body.rk,
-                        )
-                        .is_err()
-                    {
+                        ),
+                        ProofVersion::TransparentDummySpends => {
+                            spend.body.proof.verify_allowing_dummy_spends(
+                                self.transaction_body().merkle_root,
+                                spend.body.value_commitment,
+                                spend.body.nullifier.clone(),
+                                spend.body.rk,
+                            )
+                        }
+                    };
+                    proof_versions.insert(spend.body.proof_version);
+                    if verified.is_err() {
                         // TODO should the verification error be bubbled up here?
                         return Err(anyhow::anyhow!("A spend proof did not verify"));
                     }
@@ -99,11 +128,52 @@ impl StatelessTransactionExt for Transaction {
                     delegations.push(delegate);
                 }
                 Action::Undelegate(undelegate) => {
-                    if undelegation.is_none() {
-                        undelegation = Some(undelegate);
-                    } else {
-                        return Err(anyhow::anyhow!("Multiple undelegations in one transaction"));
+                    // Each validator's delegation tokens must be undelegated together, so that the
+                    // unbonded stake is computed once, with one rounding.
+                    if undelegations
+                        .iter()
+                        .any(|u| u.validator_identity == undelegate.validator_identity)
+                    {
+                        return Err(anyhow::anyhow!(
+                            "Multiple undelegations from validator {} in one transaction",
+                            undelegate.validator_identity
+                        ));
+                    }
+                    undelegations.push(undelegate);
+                }
+                Action::Redelegate(redelegate) => {
+                    redelegate
+                        .verify(&sighash)
+                        .context("redelegation failed to verify")?;
+                    // Each redelegation starts its delegator's cooldown, so a delegator key can
+                    // only redelegate once per transaction.
+                    if redelegations
+                        .iter()
+                        .any(|r| r.body.delegator_key == redelegate.body.delegator_key)
+                    {
+                        return Err(anyhow::anyhow!(
+                            "Multiple redelegations by one delegator key in one transaction"
+                        ));
                     }
+                    redelegations.push(redelegate);
+                }
+                Action::ValidatorDefinition(definition) => {
+                    definition
+                        .verify()
+                        .context("validator definition failed to verify")?;
+                    validators.push(definition.validator);
+                }
+                Action::ValidatorMigration(migration) => {
+                    migration
+                        .verify()
+                        .context("validator migration failed to verify")?;
+                    validator_migrations.push(migration);
+                }
+                Action::RegisterDenomMetadata(registration) => {
+                    registration
+                        .verify(&sighash)
+                        .context("denom metadata registration failed to verify")?;
+                    denom_metadata.push(registration);
                 }
                 _ => {
                     return Err(anyhow::anyhow!("unsupported action"));
@@ -113,7 +183,7 @@ impl StatelessTransactionExt for Transaction {
 
         // We prohibit actions other than `Spend`, `Delegate`, `Output` and `Undelegate` in
         // transactions that contain `Undelegate`, to avoid having to quarantine them.
-        if undelegation.is_some() {
+        if !undelegations.is_empty() {
             use Action::*;
             for action in self.transaction_body().actions {
                 if !matches!(action, Undelegate(_) | Delegate(_) | Spend(_) | Output(_)) {
@@ -128,8 +198,14 @@ impl StatelessTransactionExt for Transaction {
             new_notes,
             spent_nullifiers,
             delegations,
-            undelegation,
+            undelegations,
+            redelegations,
             validators,
+            validator_migrations,
+            denom_metadata,
+            proof_versions,
+            shape,
+            fee: self.transaction_body().fee.value(),
         })
     }
 }
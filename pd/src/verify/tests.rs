@@ -6,7 +6,7 @@ use penumbra_crypto::{
     merkle::{Frontier, NoteCommitmentTree, Tree, TreeExt},
     Fq, Note, Value,
 };
-use penumbra_transaction::Transaction;
+use penumbra_transaction::{Action, Transaction};
 use rand_core::OsRng;
 
 use super::*;
@@ -66,3 +66,43 @@ fn test_transaction_succeeds_if_values_balance() {
         .verify_stateless()
         .expect("stateless verification should pass");
 }
+
+#[test]
+fn test_transaction_fails_with_non_standard_shape() {
+    let mut rng = OsRng;
+    let sk = SpendKey::generate(&mut rng);
+    let (dest, _) = sk
+        .full_viewing_key()
+        .incoming()
+        .payment_address(0u64.into());
+
+    let mut transaction = Transaction::build_with_root(NoteCommitmentTree::new(0).root2())
+        .set_fee(0)
+        .set_chain_id("penumbra".to_string())
+        .add_output(
+            &mut rng,
+            &dest,
+            Value {
+                amount: 0,
+                asset_id: asset::REGISTRY.parse_denom("upenumbra").unwrap().id(),
+            },
+            MemoPlaintext::default(),
+            sk.full_viewing_key().outgoing(),
+        )
+        .finalize(&mut rng)
+        .expect("transaction created ok");
+
+    // The builder pads to 2-in/2-out; dropping an output leaves a 2-in/1-out transaction.
+    let output = transaction
+        .transaction_body
+        .actions
+        .iter()
+        .position(|action| matches!(action, Action::Output(_)))
+        .unwrap();
+    transaction.transaction_body.actions.remove(output);
+
+    let err = transaction
+        .verify_stateless()
+        .expect_err("stateless verification should fail");
+    assert!(err.to_string().contains("non-standard shape 2-in/1-out"));
+}
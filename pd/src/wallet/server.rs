@@ -8,7 +8,7 @@ impl state::Reader {
     ///
     /// Passes through if the provided chain_id is empty or matches, and
     /// otherwise errors.
-    fn check_chain_id(&self, provided: &str) -> Result<(), tonic::Status> {
+    pub(crate) fn check_chain_id(&self, provided: &str) -> Result<(), tonic::Status> {
         if provided.is_empty() || self.chain_params_rx().borrow().chain_id == provided {
             Ok(())
         } else {
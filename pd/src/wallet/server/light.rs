@@ -3,17 +3,20 @@ use std::pin::Pin;
 use futures::stream::{StreamExt, TryStreamExt};
 use penumbra_proto::{
     chain::ChainParams,
+    client::v1alpha1::light_wallet_server::LightWallet,
     light_wallet::{
-        light_wallet_server::LightWallet, ChainParamsRequest, CompactBlock,
+        Asset, AssetListRequest, ChainInfo, ChainInfoRequest, ChainParamsRequest, CompactBlock,
         CompactBlockRangeRequest, ValidatorInfoRequest,
     },
     stake::ValidatorInfo,
 };
-
+use penumbra_stake::{Epoch, IdentityKey};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
 use tonic::Status;
-use tracing::instrument;
+use tracing::{instrument, Instrument, Span};
 
-use crate::state;
+use crate::{state, BuildInfo, COMPACT_BLOCK_VERSION};
 
 #[tonic::async_trait]
 impl LightWallet for state::Reader {
@@ -23,6 +26,8 @@ impl LightWallet for state::Reader {
     type ValidatorInfoStream =
         Pin<Box<dyn futures::Stream<Item = Result<ValidatorInfo, tonic::Status>> + Send>>;
 
+    type AssetListStream = ReceiverStream<Result<Asset, Status>>;
+
     #[instrument(skip(self, request), fields())]
     async fn chain_params(
         &self,
@@ -30,14 +35,43 @@ impl LightWallet for state::Reader {
     ) -> Result<tonic::Response<ChainParams>, Status> {
         self.check_chain_id(&request.get_ref().chain_id)?;
 
-        let genesis_configuration = self
-            .genesis_configuration()
+        let chain_params = self
+            .chain_params()
             .await
-            .map_err(|_| tonic::Status::unavailable("error retrieving genesis configuration"))?;
+            .map_err(|_| tonic::Status::unavailable("error retrieving chain params"))?;
 
-        Ok(tonic::Response::new(
-            genesis_configuration.chain_params.into(),
-        ))
+        Ok(tonic::Response::new(chain_params.into()))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn chain_info(
+        &self,
+        request: tonic::Request<ChainInfoRequest>,
+    ) -> Result<tonic::Response<ChainInfo>, Status> {
+        self.check_chain_id(&request.get_ref().chain_id)?;
+
+        let (height, app_hash) = match self
+            .latest_block_info()
+            .await
+            .map_err(|_| tonic::Status::unavailable("database error"))?
+        {
+            Some(block) => (block.height as u64, block.app_hash),
+            None => (0, vec![0; 32]),
+        };
+
+        let chain_params = self.chain_params_rx().borrow().clone();
+        let epoch = Epoch::from_height(height, chain_params.epoch_duration);
+        let next_epoch_start_height = epoch.next().start_height().value();
+
+        Ok(tonic::Response::new(ChainInfo {
+            height,
+            app_hash,
+            epoch_index: epoch.index,
+            next_epoch_start_height,
+            blocks_until_next_epoch: next_epoch_start_height - height,
+            chain_params: Some(chain_params.into()),
+            build_info: Some(BuildInfo::current().into()),
+        }))
     }
 
     #[instrument(skip(self, request), fields(show_inactive = request.get_ref().show_inactive))]
@@ -73,14 +107,30 @@ impl LightWallet for state::Reader {
         let CompactBlockRangeRequest {
             start_height,
             end_height,
+            release_validators,
+            max_version,
             ..
         } = request.into_inner();
 
-        let current_height = self
-            .height()
+        // Serve the newest format both sides understand.
+        let version = max_version.clamp(1, COMPACT_BLOCK_VERSION);
+
+        let release_validators = release_validators
+            .into_iter()
+            .map(IdentityKey::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| tonic::Status::invalid_argument("invalid identity key"))?;
+
+        let current_height = match self
+            .latest_block_info()
             .await
             .map_err(|_| tonic::Status::unavailable("database error"))?
-            .value();
+        {
+            Some(latest) => latest.height as u64,
+            // Not even the genesis block has been committed yet.  Streaming an empty block at
+            // height 0 would make clients skip the genesis allocations, so stream nothing.
+            None => return Ok(tonic::Response::new(futures::stream::empty().boxed())),
+        };
 
         // Treat end_height = 0 as end_height = current_height so that if the
         // end_height is unspecified in the proto, it will be treated as a
@@ -95,17 +145,90 @@ impl LightWallet for state::Reader {
         // but the start height is already recorded in the span.
         tracing::info!(
             end_height,
+            version,
             num_blocks = end_height.saturating_sub(start_height),
             "starting compact_block_range response"
         );
 
+        if !release_validators.is_empty() {
+            let heights = self
+                .quarantine_release_heights(&release_validators, start_height, end_height)
+                .await
+                .map_err(|_| tonic::Status::unavailable("database error"))?;
+            tracing::debug!(
+                num_blocks = heights.len(),
+                "filtered to quarantine releases"
+            );
+
+            let state = self.clone();
+            let stream = futures::stream::iter(heights)
+                .flat_map(move |height| state.compact_blocks(height as i64, height as i64))
+                .map_ok(move |block| downgrade(block, version))
+                .map_err(|e| tonic::Status::internal(e.to_string()));
+
+            return Ok(tonic::Response::new(stream.boxed()));
+        }
+
         let stream = self
             .compact_blocks(
                 start_height.try_into().unwrap(),
                 end_height.try_into().unwrap(),
             )
+            .map_ok(move |block| downgrade(block, version))
             .map_err(|e| tonic::Status::internal(e.to_string()));
 
         Ok(tonic::Response::new(stream.boxed()))
     }
+
+    #[instrument(skip(self, request))]
+    async fn asset_list(
+        &self,
+        request: tonic::Request<AssetListRequest>,
+    ) -> Result<tonic::Response<Self::AssetListStream>, Status> {
+        self.check_chain_id(&request.get_ref().chain_id)?;
+
+        let request = request.into_inner();
+        let limit = if request.limit == 0 {
+            None
+        } else {
+            Some(request.limit)
+        };
+        tracing::debug!(start_after = ?hex::encode(&request.start_after), ?limit);
+        let state = self.clone();
+
+        let (tx, rx) = mpsc::channel(100);
+        tokio::spawn(
+            async move {
+                let assets = match state.asset_list(&request.start_after, limit).await {
+                    Ok(assets) => assets,
+                    Err(e) => {
+                        tracing::error!(?e, "error listing assets");
+                        let _ = tx
+                            .send(Err(tonic::Status::unavailable("database error")))
+                            .await;
+                        return;
+                    }
+                };
+                for asset in assets {
+                    tracing::debug!(asset_id = ?hex::encode(&asset.asset_id), asset_denom = ?asset.asset_denom, "sending asset");
+                    if tx.send(Ok(asset)).await.is_err() {
+                        // The client hung up.
+                        return;
+                    }
+                }
+            }
+            .instrument(Span::current()),
+        );
+
+        Ok(tonic::Response::new(Self::AssetListStream::new(rx)))
+    }
+}
+
+/// Converts a compact block from the newest format to the given older `version`.
+///
+/// Version 1 is the only format so far.  When a field is added that older clients would
+/// misinterpret, the newer block is translated here, so those clients can keep syncing.
+fn downgrade(mut block: CompactBlock, version: u32) -> CompactBlock {
+    block.version = version;
+    block
 }
@@ -1,22 +1,60 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    pin::Pin,
+};
+
 use futures::stream::{StreamExt, TryStreamExt};
+use penumbra_crypto::{note, Nullifier};
 use penumbra_proto::{
     self as proto,
     chain::AssetInfo,
+    client::v1alpha1::{light_wallet_server::LightWallet, thin_wallet_server::ThinWallet},
+    light_wallet::{Asset, AssetListRequest},
     thin_wallet::{
-        thin_wallet_server::ThinWallet, Asset, AssetListRequest, AssetLookupRequest,
-        TransactionByNoteRequest, TransactionDetail, ValidatorRateRequest, ValidatorStatusRequest,
+        time_estimate_request::Target, AnonymityStatsRequest, AssetLookupRequest,
+        BlockAnonymityStats, EpochSupply, EpochUnbonding, NoteWitness, NoteWitnesses,
+        NoteWitnessesRequest, NullifierStatus, NullifierStatusRequest, QuarantineRelease,
+        QuarantineScheduleRequest, RewardAccrual, RewardAccrualRequest, SupplyHistoryRequest,
+        TimeEstimate, TimeEstimateRequest, TransactionByNoteRequest, TransactionDetail,
+        UnbondingTotalsRequest, ValidatorRateHistoryRequest, ValidatorRateRequest,
+        ValidatorSequenceNumber, ValidatorSequenceNumberRequest, ValidatorSlashing,
+        ValidatorSlashingsRequest, ValidatorStatusRequest,
     },
 };
-use penumbra_stake::IdentityKey;
-use tokio::sync::mpsc;
+use penumbra_stake::{Epoch, IdentityKey};
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::Status;
-use tracing::{instrument, Instrument, Span};
+use tracing::instrument;
 
 use crate::state;
 
+/// The maximum number of notes whose witnesses can be requested at once.
+const MAX_NOTE_WITNESSES: usize = 1024;
+/// The maximum number of blocks whose anonymity statistics can be requested at once.
+const MAX_ANONYMITY_STATS_BLOCKS: u64 = 10_000;
+/// The number of recent blocks whose times are averaged to estimate when future blocks will come.
+const BLOCK_TIME_WINDOW: u64 = 1_000;
+
 #[tonic::async_trait]
 impl ThinWallet for state::Reader {
+    type QuarantineScheduleStream =
+        Pin<Box<dyn futures::Stream<Item = Result<QuarantineRelease, tonic::Status>> + Send>>;
+
+    type ValidatorRateHistoryStream =
+        Pin<Box<dyn futures::Stream<Item = Result<proto::stake::RateData, tonic::Status>> + Send>>;
+
+    type ValidatorSlashingsStream =
+        Pin<Box<dyn futures::Stream<Item = Result<ValidatorSlashing, tonic::Status>> + Send>>;
+
+    type AnonymityStatsStream =
+        Pin<Box<dyn futures::Stream<Item = Result<BlockAnonymityStats, tonic::Status>> + Send>>;
+
+    type UnbondingTotalsStream =
+        Pin<Box<dyn futures::Stream<Item = Result<EpochUnbonding, tonic::Status>> + Send>>;
+
+    type SupplyHistoryStream =
+        Pin<Box<dyn futures::Stream<Item = Result<EpochSupply, tonic::Status>> + Send>>;
+
     type AssetListStream = ReceiverStream<Result<Asset, Status>>;
 
     #[instrument(skip(self, request))]
@@ -61,33 +99,12 @@ impl ThinWallet for state::Reader {
         Ok(tonic::Response::new(asset))
     }
 
-    #[instrument(skip(self, request))]
+    /// Deprecated: the asset list moved to the light wallet service, which this answers with.
     async fn asset_list(
         &self,
         request: tonic::Request<AssetListRequest>,
     ) -> Result<tonic::Response<Self::AssetListStream>, Status> {
-        self.check_chain_id(&request.get_ref().chain_id)?;
-
-        tracing::debug!("processing request");
-        let state = self.clone();
-
-        let (tx, rx) = mpsc::channel(100);
-        tokio::spawn(
-            async move {
-                let assets = state
-                    .asset_list()
-                    .await
-                    .map_err(|_| tonic::Status::unavailable("database error"))
-                    .unwrap();
-                for asset in &assets[..] {
-tracing::debug!(asset_id = ?hex::encode(&asset.asset_id), asset_denom = ?asset.asset_denom, "sending asset");
-                    tx.send(Ok(asset.clone())).await.unwrap();
-                }
-            }
-            .instrument(Span::current()),
-        );
-
-        Ok(tonic::Response::new(Self::AssetListStream::new(rx)))
+        LightWallet::asset_list(self, request).await
     }
 
     #[instrument(skip(self, request))]
@@ -127,4 +144,443 @@ tracing::debug!(asset_id = ?hex::encode(&asset.asset_id), asset_denom = ?asset.a
 
         Ok(tonic::Response::new(rate.into()))
     }
+
+    #[instrument(skip(self, request))]
+    async fn validator_rate_history(
+        &self,
+        request: tonic::Request<ValidatorRateHistoryRequest>,
+    ) -> Result<tonic::Response<Self::ValidatorRateHistoryStream>, Status> {
+        self.check_chain_id(&request.get_ref().chain_id)?;
+
+        let request = request.into_inner();
+        let identity_key = IdentityKey::try_from(
+            request
+                .identity_key
+                .ok_or_else(|| tonic::Status::invalid_argument("missing identity key"))?,
+        )
+        .map_err(|_| tonic::Status::invalid_argument("invalid identity key"))?;
+
+        // Treat end_epoch_index = 0 as a request for every epoch after the start.
+        let end_epoch_index = if request.end_epoch_index == 0 {
+            u64::MAX
+        } else {
+            request.end_epoch_index
+        };
+
+        let rates = self
+            .validator_rate_history(&identity_key, request.start_epoch_index, end_epoch_index)
+            .await
+            .map_err(|_| tonic::Status::unavailable("database error"))?;
+
+        Ok(tonic::Response::new(
+            futures::stream::iter(rates.into_iter().map(|rate| Ok(rate.into()))).boxed(),
+        ))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn reward_accrual(
+        &self,
+        request: tonic::Request<RewardAccrualRequest>,
+    ) -> Result<tonic::Response<RewardAccrual>, Status> {
+        self.check_chain_id(&request.get_ref().chain_id)?;
+
+        let request = request.into_inner();
+        let identity_key = IdentityKey::try_from(
+            request
+                .identity_key
+                .ok_or_else(|| tonic::Status::invalid_argument("missing identity key"))?,
+        )
+        .map_err(|_| tonic::Status::invalid_argument("invalid identity key"))?;
+
+        // Treat end_epoch_index = 0 as a request for rewards up to the current epoch.
+        let end_epoch_index = if request.end_epoch_index == 0 {
+            let epoch_duration = self.chain_params_rx().borrow().epoch_duration;
+            let height = self.height_rx().borrow().value();
+            Epoch::from_height(height, epoch_duration).index
+        } else {
+            request.end_epoch_index
+        };
+        if request.start_epoch_index > end_epoch_index {
+            return Err(tonic::Status::invalid_argument(
+                "start epoch is after end epoch",
+            ));
+        }
+
+        let mut rates = Vec::with_capacity(2);
+        for epoch_index in [request.start_epoch_index, end_epoch_index] {
+            let rate = self
+                .rate_data(epoch_index)
+                .await
+                .map_err(|_| tonic::Status::unavailable("database error"))?
+                .into_iter()
+                .find(|data| data.identity_key == identity_key)
+                .ok_or_else(|| tonic::Status::not_found("validator not found"))?;
+            rates.push(rate);
+        }
+        let (start_rate_data, end_rate_data) = (rates.remove(0), rates.remove(0));
+
+        let accrual = RewardAccrual {
+            start_unbonded_amount: start_rate_data.unbonded_amount(request.delegation_amount),
+            end_unbonded_amount: end_rate_data.unbonded_amount(request.delegation_amount),
+            accrued_amount: end_rate_data
+                .rewards_accrued_since(&start_rate_data, request.delegation_amount),
+            start_rate_data: Some(start_rate_data.into()),
+            end_rate_data: Some(end_rate_data.into()),
+        };
+
+        Ok(tonic::Response::new(accrual))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn quarantine_schedule(
+        &self,
+        request: tonic::Request<QuarantineScheduleRequest>,
+    ) -> Result<tonic::Response<Self::QuarantineScheduleStream>, Status> {
+        self.check_chain_id(&request.get_ref().chain_id)?;
+
+        let identity_key = request
+            .into_inner()
+            .identity_key
+            .map(IdentityKey::try_from)
+            .transpose()
+            .map_err(|_| tonic::Status::invalid_argument("invalid identity key"))?;
+
+        let schedule = self
+            .quarantine_schedule(identity_key.as_ref())
+            .await
+            .map_err(|_| tonic::Status::unavailable("database error"))?;
+
+        // Quarantined notes and nullifiers are released at the end of the epoch
+        // containing their unbonding height, so group them by that epoch.
+        let epoch_duration = self.chain_params_rx().borrow().epoch_duration;
+        let mut releases = BTreeMap::<(IdentityKey, u64), QuarantineRelease>::new();
+        for ((identity_key, unbonding_height), (note_count, nullifier_count)) in schedule {
+            let epoch = Epoch::from_height(unbonding_height, epoch_duration);
+            let release = releases
+                .entry((identity_key.clone(), epoch.index))
+                .or_insert_with(|| QuarantineRelease {
+                    identity_key: Some(identity_key.into()),
+                    epoch_index: epoch.index,
+                    release_height: epoch.end_height().value(),
+                    note_count: 0,
+                    nullifier_count: 0,
+                });
+            release.note_count += note_count;
+            release.nullifier_count += nullifier_count;
+        }
+
+        Ok(tonic::Response::new(
+            futures::stream::iter(releases.into_values().map(Ok)).boxed(),
+        ))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn validator_slashings(
+        &self,
+        request: tonic::Request<ValidatorSlashingsRequest>,
+    ) -> Result<tonic::Response<Self::ValidatorSlashingsStream>, Status> {
+        self.check_chain_id(&request.get_ref().chain_id)?;
+
+        let identity_key = request
+            .into_inner()
+            .identity_key
+            .map(IdentityKey::try_from)
+            .transpose()
+            .map_err(|_| tonic::Status::invalid_argument("invalid identity key"))?;
+
+        let slashings = self
+            .validator_slashings(identity_key.as_ref())
+            .await
+            .map_err(|_| tonic::Status::unavailable("database error"))?;
+
+        Ok(tonic::Response::new(
+            futures::stream::iter(slashings.into_iter().map(Ok)).boxed(),
+        ))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn validator_sequence_number(
+        &self,
+        request: tonic::Request<ValidatorSequenceNumberRequest>,
+    ) -> Result<tonic::Response<ValidatorSequenceNumber>, Status> {
+        self.check_chain_id(&request.get_ref().chain_id)?;
+
+        let identity_key = IdentityKey::try_from(
+            request
+                .into_inner()
+                .identity_key
+                .ok_or_else(|| tonic::Status::invalid_argument("missing identity key"))?,
+        )
+        .map_err(|_| tonic::Status::invalid_argument("invalid identity key"))?;
+
+        let validator = self
+            .validator_definition(&identity_key)
+            .await
+            .map_err(|_| tonic::Status::unavailable("database error"))?
+            .ok_or_else(|| tonic::Status::not_found("validator not found"))?;
+
+        Ok(tonic::Response::new(ValidatorSequenceNumber {
+            identity_key: Some(identity_key.into()),
+            current_sequence_number: validator.sequence_number,
+            next_sequence_number: validator.sequence_number.saturating_add(1),
+        }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn note_witnesses(
+        &self,
+        request: tonic::Request<NoteWitnessesRequest>,
+    ) -> Result<tonic::Response<NoteWitnesses>, Status> {
+        self.check_chain_id(&request.get_ref().chain_id)?;
+
+        let request = request.into_inner();
+        if request.note_commitments.len() > MAX_NOTE_WITNESSES {
+            return Err(tonic::Status::invalid_argument(format!(
+                "at most {} note witnesses can be requested at once",
+                MAX_NOTE_WITNESSES
+            )));
+        }
+
+        let commitments = request
+            .note_commitments
+            .into_iter()
+            .map(note::Commitment::try_from)
+            .collect::<Result<BTreeSet<_>, _>>()
+            .map_err(|_| tonic::Status::invalid_argument("invalid note commitment"))?;
+
+        let (height, anchor, paths) = self
+            .note_authentication_paths(&commitments)
+            .await
+            .map_err(|_| tonic::Status::unavailable("database error"))?;
+
+        Ok(tonic::Response::new(NoteWitnesses {
+            height,
+            anchor: Some(anchor.into()),
+            witnesses: paths
+                .into_iter()
+                .map(|(commitment, (position, auth_path))| NoteWitness {
+                    note_commitment: Some(commitment.into()),
+                    position: u64::from(position),
+                    auth_path: auth_path.into_iter().map(Into::into).collect(),
+                })
+                .collect(),
+        }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn anonymity_stats(
+        &self,
+        request: tonic::Request<AnonymityStatsRequest>,
+    ) -> Result<tonic::Response<Self::AnonymityStatsStream>, Status> {
+        self.check_chain_id(&request.get_ref().chain_id)?;
+
+        let request = request.into_inner();
+        let end_height = if request.end_height == 0 {
+            u64::from(
+                self.height()
+                    .await
+                    .map_err(|_| tonic::Status::unavailable("database error"))?,
+            )
+        } else {
+            request.end_height
+        };
+        if end_height.saturating_sub(request.start_height) >= MAX_ANONYMITY_STATS_BLOCKS {
+            return Err(tonic::Status::invalid_argument(format!(
+                "at most {} blocks' statistics can be requested at once",
+                MAX_ANONYMITY_STATS_BLOCKS
+            )));
+        }
+
+        let stats = self
+            .anonymity_stats(request.start_height, end_height)
+            .await
+            .map_err(|_| tonic::Status::unavailable("database error"))?;
+
+        Ok(tonic::Response::new(
+            futures::stream::iter(stats.into_iter().map(Ok)).boxed(),
+        ))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn nullifier_status(
+        &self,
+        request: tonic::Request<NullifierStatusRequest>,
+    ) -> Result<tonic::Response<NullifierStatus>, Status> {
+        self.check_chain_id(&request.get_ref().chain_id)?;
+
+        let nullifier = Nullifier::try_from(request.into_inner().nullifier)
+            .map_err(|_| tonic::Status::invalid_argument("invalid nullifier"))?;
+        let row = self
+            .nullifier(nullifier)
+            .await
+            .map_err(|_| tonic::Status::unavailable("database error"))?;
+
+        Ok(tonic::Response::new(NullifierStatus {
+            spent: row.is_some(),
+            height: row.map(|row| row.height as u64).unwrap_or_default(),
+        }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn unbonding_totals(
+        &self,
+        request: tonic::Request<UnbondingTotalsRequest>,
+    ) -> Result<tonic::Response<Self::UnbondingTotalsStream>, Status> {
+        self.check_chain_id(&request.get_ref().chain_id)?;
+
+        let totals = self
+            .unbonding_totals()
+            .await
+            .map_err(|_| tonic::Status::unavailable("database error"))?;
+
+        // Like the quarantine schedule, stake is unbonded at the end of the epoch containing its
+        // unbonding height.
+        let epoch_duration = self.chain_params_rx().borrow().epoch_duration;
+        let mut epochs = BTreeMap::<u64, EpochUnbonding>::new();
+        for (unbonding_height, (count, amount, unknown_amount_count)) in totals {
+            let epoch = Epoch::from_height(unbonding_height, epoch_duration);
+            let unbonding = epochs.entry(epoch.index).or_insert_with(|| EpochUnbonding {
+                epoch_index: epoch.index,
+                release_height: epoch.end_height().value(),
+                undelegation_count: 0,
+                amount: 0,
+                unknown_amount_count: 0,
+            });
+            unbonding.undelegation_count += count;
+            unbonding.amount += amount;
+            unbonding.unknown_amount_count += unknown_amount_count;
+        }
+
+        Ok(tonic::Response::new(
+            futures::stream::iter(epochs.into_values().map(Ok)).boxed(),
+        ))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn supply_history(
+        &self,
+        request: tonic::Request<SupplyHistoryRequest>,
+    ) -> Result<tonic::Response<Self::SupplyHistoryStream>, Status> {
+        self.check_chain_id(&request.get_ref().chain_id)?;
+
+        let request = request.into_inner();
+        let asset_id = penumbra_crypto::asset::Id::try_from(
+            request
+                .asset_id
+                .ok_or_else(|| tonic::Status::invalid_argument("missing asset id"))?,
+        )
+        .map_err(|_| tonic::Status::invalid_argument("invalid asset id"))?;
+
+        // Treat end_epoch_index = 0 as a request for every epoch after the start.
+        let end_epoch_index = if request.end_epoch_index == 0 {
+            u64::MAX
+        } else {
+            request.end_epoch_index
+        };
+
+        let supplies = self
+            .supply_history(asset_id, request.start_epoch_index, end_epoch_index)
+            .await
+            .map_err(|_| tonic::Status::unavailable("database error"))?;
+
+        Ok(tonic::Response::new(
+            futures::stream::iter(supplies.into_iter().map(Ok)).boxed(),
+        ))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn time_estimate(
+        &self,
+        request: tonic::Request<TimeEstimateRequest>,
+    ) -> Result<tonic::Response<TimeEstimate>, Status> {
+        self.check_chain_id(&request.get_ref().chain_id)?;
+
+        let height = match request.into_inner().target {
+            Some(Target::Height(height)) => height,
+            Some(Target::EpochIndex(epoch_index)) => Epoch {
+                index: epoch_index,
+                duration: self.chain_params_rx().borrow().epoch_duration,
+            }
+            .start_height()
+            .value(),
+            None => return Err(tonic::Status::invalid_argument("missing target")),
+        };
+
+        let times = self
+            .recent_block_times(BLOCK_TIME_WINDOW)
+            .await
+            .map_err(|_| tonic::Status::unavailable("database error"))?;
+        let average_block_time_ms = average_block_time_ms(&times)
+            .ok_or_else(|| tonic::Status::unavailable("not enough block times recorded"))?;
+        let (current_height, current_time_ms) = times[0];
+
+        Ok(tonic::Response::new(TimeEstimate {
+            height,
+            current_height,
+            current_time_ms,
+            average_block_time_ms,
+            estimated_time_ms: estimate_time_ms(
+                current_height,
+                current_time_ms,
+                average_block_time_ms,
+                height,
+            ),
+        }))
+    }
+}
+
+/// Returns the average time between the given blocks, in milliseconds, from their heights and
+/// times ordered newest first, if they span more than one height.
+fn average_block_time_ms(times: &[(u64, i64)]) -> Option<u64> {
+    let (newest_height, newest_time_ms) = *times.first()?;
+    let (oldest_height, oldest_time_ms) = *times.last()?;
+    if newest_height <= oldest_height {
+        return None;
+    }
+    // Block times only increase, but don't trust the clock to get a negative average.
+    let elapsed_ms = newest_time_ms.saturating_sub(oldest_time_ms).max(0) as u64;
+    Some(elapsed_ms / (newest_height - oldest_height))
+}
+
+/// Extrapolates the time of the block at `height` from the current block at the given average
+/// block time.
+fn estimate_time_ms(
+    current_height: u64,
+    current_time_ms: i64,
+    average_block_time_ms: u64,
+    height: u64,
+) -> i64 {
+    let blocks = height as i128 - current_height as i128;
+    let estimate = current_time_ms as i128 + blocks * average_block_time_ms as i128;
+    estimate.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_time_from_recent_blocks() {
+        // Blocks 10 through 14, five seconds apart, newest first.
+        let times = (10..15)
+            .rev()
+            .map(|height| (height, 1_000_000 + 5_000 * height as i64))
+            .collect::<Vec<_>>();
+        let average = average_block_time_ms(&times).unwrap();
+        assert_eq!(average, 5_000);
+
+        let (current_height, current_time_ms) = times[0];
+        assert_eq!(
+            estimate_time_ms(current_height, current_time_ms, average, 20),
+            current_time_ms + 30_000
+        );
+        assert_eq!(
+            estimate_time_ms(current_height, current_time_ms, average, 10),
+            times[4].1
+        );
+
+        // A single block says nothing about block times.
+        assert_eq!(average_block_time_ms(&times[..1]), None);
+        assert_eq!(average_block_time_ms(&[]), None);
+    }
 }
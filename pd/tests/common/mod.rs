@@ -0,0 +1,476 @@
+//! A devnet driver for `pd` integration tests.
+//!
+//! The driver plays the part of Tendermint for a single-validator chain: it
+//! feeds ABCI consensus requests directly to `pd`'s consensus service, so that
+//! tests can run a chain block-by-block, backed by a scratch Postgres database.
+
+// Each integration test binary only uses some of these helpers.
+#![allow(dead_code)]
+
+pub mod testnet;
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use pd::{
+    genesis, state, CircuitBreaker, Consensus, InvariantChecks, LiveConfig, StatelessCache,
+    TraceContexts, STATELESS_CACHE_SIZE,
+};
+use penumbra_chain::params::ChainParams;
+use penumbra_crypto::{
+    asset,
+    rdsa::{SigningKey, SpendAuth, VerificationKey},
+};
+use penumbra_proto::Protobuf;
+use penumbra_stake::{
+    FundingStream, FundingStreamRecipient, FundingStreams, IdentityKey, RateData, Validator,
+};
+use penumbra_transaction::Transaction;
+use penumbra_wallet::{ClientState, ScanEvent, Wallet};
+use rand_chacha::ChaCha20Rng;
+use rand_core::{CryptoRng, OsRng, RngCore, SeedableRng};
+use tendermint::abci::{request, ConsensusRequest, ConsensusResponse};
+use tendermint_proto::{
+    abci::{Evidence, EvidenceType, RequestBeginBlock, Validator as AbciValidator},
+    google::protobuf::Timestamp,
+    types::Header,
+    version::Consensus as ConsensusVersion,
+};
+use tower::{Service, ServiceExt};
+
+/// The environment variable holding the URI of a scratch Postgres database.
+///
+/// The database is wiped at the start of each test, so don't point this at
+/// anything you care about.
+pub const DATABASE_URI_VAR: &str = "PD_TEST_DATABASE_URI";
+
+/// The amount of delegation tokens allocated at genesis to each genesis
+/// validator, backing its genesis voting power.
+pub const GENESIS_DELEGATION: u64 = 1;
+
+/// The genesis time of seeded chains, which timestamp each block this many seconds plus its
+/// height after the UNIX epoch.
+const SEEDED_GENESIS_TIME: u64 = 1_640_995_200;
+
+/// A single-validator chain, driven block-by-block.
+pub struct Devnet {
+    consensus: Consensus,
+    /// A reader for the chain state.
+    pub state: state::Reader,
+    /// The chain parameters the chain was started with.
+    pub chain_params: ChainParams,
+    /// The genesis validator.
+    pub validator: Validator,
+    /// The height of the last committed block.
+    pub height: u64,
+    /// How long `pd` took to process the last block's `EndBlock` request.
+    pub last_end_block_duration: Duration,
+    /// The app hash returned by the last `Commit` request.
+    pub app_hash: Vec<u8>,
+    /// The genesis time, if blocks are timestamped deterministically rather than with the
+    /// current time.
+    genesis_time: Option<u64>,
+}
+
+impl Devnet {
+    /// Start a new chain with a single validator and the given genesis allocations.
+    pub async fn start(
+        chain_params: ChainParams,
+        allocations: Vec<genesis::Allocation>,
+    ) -> Result<Self> {
+        Self::start_with_validators(chain_params, allocations, 1).await
+    }
+
+    /// Start a new chain with `validator_count` genesis validators, each paying its commission to
+    /// a funding stream.
+    ///
+    /// Only the first validator is driven as part of consensus; the others exist to give the chain
+    /// a realistically sized validator set.
+    pub async fn start_with_validators(
+        chain_params: ChainParams,
+        allocations: Vec<genesis::Allocation>,
+        validator_count: usize,
+    ) -> Result<Self> {
+        Self::start_with_rng(chain_params, allocations, validator_count, OsRng, None).await
+    }
+
+    /// Start a new chain like [`Devnet::start`], but generating the validator's keys from `seed`
+    /// and timestamping blocks from a fixed genesis time, so that chains started with the same
+    /// seed and fed the same blocks should be identical.
+    pub async fn start_seeded(
+        chain_params: ChainParams,
+        allocations: Vec<genesis::Allocation>,
+        seed: u64,
+    ) -> Result<Self> {
+        Self::start_with_rng(
+            chain_params,
+            allocations,
+            1,
+            ChaCha20Rng::seed_from_u64(seed),
+            Some(SEEDED_GENESIS_TIME),
+        )
+        .await
+    }
+
+    async fn start_with_rng<R: RngCore + CryptoRng>(
+        chain_params: ChainParams,
+        mut allocations: Vec<genesis::Allocation>,
+        validator_count: usize,
+        mut rng: R,
+        genesis_time: Option<u64>,
+    ) -> Result<Self> {
+        let database_uri = std::env::var(DATABASE_URI_VAR)
+            .map_err(|_| anyhow!("{} must be set to run this test", DATABASE_URI_VAR))?;
+        reset_database(&database_uri).await?;
+
+        let (state, state_writer) = state::new(&database_uri).await?;
+        // Check the chain state invariants at every epoch boundary, so that tests catch any
+        // violations.
+        let consensus = Consensus::new(
+            state_writer,
+            StatelessCache::new(STATELESS_CACHE_SIZE),
+            TraceContexts::default(),
+            InvariantChecks::Epoch,
+            CircuitBreaker::default(),
+            None,
+            None,
+            LiveConfig::default(),
+        )
+        .await?;
+
+        let validator = Validator {
+            identity_key: IdentityKey(VerificationKey::from(&SigningKey::<SpendAuth>::new(
+                &mut rng,
+            ))),
+            consensus_key: tendermint::PrivateKey::Ed25519(ed25519_consensus::SigningKey::new(
+                &mut rng,
+            ))
+            .public_key(),
+            name: "devnet validator".to_string(),
+            website: String::new(),
+            description: String::new(),
+            funding_streams: FundingStreams::new(),
+            sequence_number: 0,
+        };
+
+        let mut validators = vec![genesis::ValidatorPower {
+            validator: validator.clone(),
+            power: (GENESIS_DELEGATION as u32).into(),
+        }];
+        if validator_count > 1 {
+            let (_label, address) = Wallet::generate(&mut rng).address_by_index(0)?;
+            for i in 1..validator_count {
+                validators.push(genesis::ValidatorPower {
+                    validator: Validator {
+                        identity_key: IdentityKey(VerificationKey::from(
+                            &SigningKey::<SpendAuth>::new(&mut rng),
+                        )),
+                        consensus_key: tendermint::PrivateKey::Ed25519(
+                            ed25519_consensus::SigningKey::new(&mut rng),
+                        )
+                        .public_key(),
+                        name: format!("devnet validator {}", i),
+                        website: String::new(),
+                        description: String::new(),
+                        funding_streams: FundingStreams::try_from(vec![FundingStream {
+                            recipient: FundingStreamRecipient::Address(address),
+                            rate_bps: 100,
+                        }])?,
+                        sequence_number: 0,
+                    },
+                    power: (GENESIS_DELEGATION as u32).into(),
+                });
+            }
+        }
+        let (_label, address) = Wallet::generate(&mut rng).address_by_index(0)?;
+        allocations.extend(delegations_to(address, &validators));
+
+        let app_state = genesis::AppState {
+            chain_params: chain_params.clone(),
+            validators,
+            allocations,
+        };
+
+        let mut devnet = Self {
+            consensus,
+            state,
+            chain_params,
+            validator,
+            height: 0,
+            last_end_block_duration: Duration::default(),
+            app_hash: Vec::new(),
+            genesis_time,
+        };
+
+        devnet
+            .call(ConsensusRequest::InitChain(request::InitChain {
+                time: tendermint::Time::from_unix_timestamp(devnet.block_time(0) as i64, 0)
+                    .expect("able to convert current time into Time"),
+                chain_id: devnet.chain_params.chain_id.clone(),
+                consensus_params: consensus_params(),
+                validators: Vec::new(),
+                app_state_bytes: serde_json::to_vec(&app_state)?.into(),
+                initial_height: 0u32.into(),
+            }))
+            .await?;
+
+        Ok(devnet)
+    }
+
+    /// Produce and commit the next block, containing the given transactions.
+    ///
+    /// Returns an error if any of the transactions were rejected by `DeliverTx`.
+    pub async fn next_block(&mut self, transactions: Vec<Transaction>) -> Result<()> {
+        self.next_block_with_evidence(transactions, Vec::new())
+            .await
+    }
+
+    /// Produce and commit the next block, reporting duplicate-vote evidence
+    /// against each of the validators with the given consensus keys.
+    pub async fn next_block_with_evidence(
+        &mut self,
+        transactions: Vec<Transaction>,
+        byzantine_validators: Vec<tendermint::PublicKey>,
+    ) -> Result<()> {
+        let height = self.height + 1;
+
+        let byzantine_validators = byzantine_validators
+            .into_iter()
+            .map(|consensus_key| Evidence {
+                r#type: EvidenceType::DuplicateVote as i32,
+                validator: Some(AbciValidator {
+                    address: tendermint::account::Id::from(consensus_key)
+                        .as_bytes()
+                        .to_vec(),
+                    power: 1,
+                }),
+                height: self.height as i64,
+                time: Some(Timestamp {
+                    seconds: self.block_time(self.height) as i64,
+                    nanos: 0,
+                }),
+                total_voting_power: 1,
+            })
+            .collect();
+
+        let begin_block = RequestBeginBlock {
+            hash: vec![0; 32],
+            header: Some(Header {
+                version: Some(ConsensusVersion { block: 11, app: 0 }),
+                chain_id: self.chain_params.chain_id.clone(),
+                height: height as i64,
+                time: Some(Timestamp {
+                    seconds: self.block_time(height) as i64,
+                    nanos: 0,
+                }),
+                proposer_address: vec![0; 20],
+                ..Default::default()
+            }),
+            last_commit_info: Some(Default::default()),
+            byzantine_validators,
+        };
+        self.call(ConsensusRequest::BeginBlock(
+            begin_block
+                .try_into()
+                .map_err(|e| anyhow!("invalid BeginBlock request: {}", e))?,
+        ))
+        .await?;
+
+        let mut rejected = Vec::new();
+        for transaction in transactions {
+            let rsp = self
+                .call(ConsensusRequest::DeliverTx(request::DeliverTx {
+                    tx: transaction.encode_to_vec().into(),
+                }))
+                .await?;
+            if let ConsensusResponse::DeliverTx(deliver_tx) = rsp {
+                if deliver_tx.code != 0 {
+                    rejected.push(deliver_tx.log.to_string());
+                }
+            }
+        }
+
+        let end_block_start = Instant::now();
+        self.call(ConsensusRequest::EndBlock(request::EndBlock {
+            height: height as i64,
+        }))
+        .await?;
+        self.last_end_block_duration = end_block_start.elapsed();
+        if let ConsensusResponse::Commit(commit) = self.call(ConsensusRequest::Commit).await? {
+            self.app_hash = commit.data.to_vec();
+        }
+        self.height = height;
+
+        if rejected.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("transactions rejected: {:?}", rejected))
+        }
+    }
+
+    /// Produce empty blocks until the chain reaches `height`.
+    pub async fn advance_to(&mut self, height: u64) -> Result<()> {
+        while self.height < height {
+            self.next_block(Vec::new()).await?;
+        }
+        Ok(())
+    }
+
+    /// Scan all blocks the client has not yet seen.
+    pub async fn sync(&self, client: &mut ClientState) -> Result<()> {
+        self.sync_events(client).await.map(|_| ())
+    }
+
+    /// Scan all blocks the client has not yet seen, returning the changes to its notes.
+    pub async fn sync_events(&self, client: &mut ClientState) -> Result<Vec<ScanEvent>> {
+        // The last block may still be being written to the database.
+        self.state.wait_for_committed_block().await?;
+        sync_client(&self.state, self.height, client).await
+    }
+
+    /// Returns the genesis validator's rates for the next epoch.
+    pub async fn next_rate_data(&self) -> Result<RateData> {
+        self.state
+            .next_rate_data()
+            .await?
+            .remove(&self.validator.identity_key)
+            .ok_or_else(|| anyhow!("missing rate data for genesis validator"))
+    }
+
+    /// Returns the genesis validator's rates for the given epoch.
+    pub async fn rate_data(&self, epoch_index: u64) -> Result<RateData> {
+        self.state
+            .rate_data(epoch_index)
+            .await?
+            .into_iter()
+            .find(|rate| rate.identity_key == self.validator.identity_key)
+            .ok_or_else(|| anyhow!("missing rate data for genesis validator"))
+    }
+
+    /// Returns the recorded total supply of the given asset.
+    pub async fn total_supply(&self, asset_id: asset::Id) -> Result<u64> {
+        self.state
+            .total_supply(asset_id)
+            .await?
+            .unwrap_or_default()
+            .try_into()
+    }
+
+    /// Returns the timestamp, in seconds since the UNIX epoch, of the block at `height`.
+    fn block_time(&self, height: u64) -> u64 {
+        match self.genesis_time {
+            Some(genesis_time) => genesis_time + height,
+            None => unix_now(),
+        }
+    }
+
+    async fn call(&mut self, req: ConsensusRequest) -> Result<ConsensusResponse> {
+        self.consensus
+            .ready()
+            .await
+            .map_err(|e| anyhow!(e))?
+            .call(req)
+            .await
+            .map_err(|e| anyhow!(e))
+    }
+}
+
+/// Returns the spendable balance of `denom` in the client's wallet.
+pub fn balance(client: &ClientState, denom: &asset::Denom) -> u64 {
+    client
+        .unspent_notes_by_denom_and_address()
+        .get(denom)
+        .map(|notes_by_address| {
+            notes_by_address
+                .values()
+                .flatten()
+                .filter_map(|note| note.as_ready())
+                .map(|note| note.amount())
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Scan all blocks up to `height` that the client has not yet seen.
+async fn sync_client(
+    state: &state::Reader,
+    height: u64,
+    client: &mut ClientState,
+) -> Result<Vec<ScanEvent>> {
+    let start_height = client.last_block_height().map(|h| h + 1).unwrap_or(0);
+    let mut blocks = state.compact_blocks(start_height as i64, height as i64);
+    let mut events = Vec::new();
+    while let Some(block) = blocks.next().await {
+        events.extend(client.scan_block(block?)?);
+    }
+    Ok(events)
+}
+
+async fn reset_database(database_uri: &str) -> Result<()> {
+    let pool = sqlx::PgPool::connect(database_uri).await?;
+    sqlx::query("DROP SCHEMA public CASCADE")
+        .execute(&pool)
+        .await?;
+    sqlx::query("CREATE SCHEMA public").execute(&pool).await?;
+    pool.close().await;
+    Ok(())
+}
+
+fn consensus_params() -> tendermint::consensus::Params {
+    tendermint::consensus::Params {
+        block: tendermint::block::Size {
+            max_bytes: 22020096,
+            max_gas: -1,
+            time_iota_ms: 500,
+        },
+        evidence: tendermint::evidence::Params {
+            max_age_num_blocks: 100000,
+            max_age_duration: tendermint::evidence::Duration(Duration::new(86400, 0)),
+            max_bytes: 1048576,
+        },
+        validator: tendermint::consensus::params::ValidatorParams {
+            pub_key_types: vec![tendermint::public_key::Algorithm::Ed25519],
+        },
+        version: Some(tendermint::consensus::params::VersionParams { app_version: 0 }),
+    }
+}
+
+/// Allocates [`GENESIS_DELEGATION`] delegation tokens for each of the given
+/// genesis validators to a throwaway address, so that their genesis voting
+/// power is backed by their delegation pools.
+pub fn genesis_delegations(
+    validators: &[genesis::ValidatorPower],
+) -> Result<Vec<genesis::Allocation>> {
+    let (_label, address) = Wallet::generate(OsRng).address_by_index(0)?;
+    Ok(delegations_to(address, validators))
+}
+
+/// Allocates [`GENESIS_DELEGATION`] delegation tokens for each of the given genesis validators
+/// to `address`.
+fn delegations_to(
+    address: penumbra_crypto::Address,
+    validators: &[genesis::ValidatorPower],
+) -> Vec<genesis::Allocation> {
+    validators
+        .iter()
+        .map(
+            |genesis::ValidatorPower { validator, .. }| genesis::Allocation {
+                amount: GENESIS_DELEGATION,
+                denom: validator
+                    .identity_key
+                    .delegation_token()
+                    .denom()
+                    .to_string(),
+                address,
+            },
+        )
+        .collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time travels linearly in a forward direction")
+        .as_secs()
+}
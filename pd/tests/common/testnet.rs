@@ -0,0 +1,427 @@
+//! A multi-node testnet for consensus-level integration tests.
+//!
+//! Unlike [`super::Devnet`], which plays the part of Tendermint itself, a
+//! [`Testnet`] runs a real `pd` and `tendermint` process for each validator,
+//! all on localhost, so that tests can exercise behavior that depends on
+//! consensus between nodes, like a node going offline and catching up again.
+//!
+//! Each node needs its own Postgres database.  These are created on the server
+//! given by [`super::DATABASE_URI_VAR`], named after that database with a
+//! `_node<n>` suffix.  The `tendermint` binary is taken from
+//! [`TENDERMINT_VAR`], or from the `PATH` if that is unset.
+
+use std::{fs, path::PathBuf, process::Stdio, time::Duration};
+
+use anyhow::{anyhow, Context, Result};
+use pd::{
+    genesis, state,
+    testnet::{generate_tm_config, get_validator_state},
+};
+use penumbra_chain::params::ChainParams;
+use penumbra_crypto::rdsa::{SigningKey, SpendAuth, VerificationKey};
+use penumbra_proto::Protobuf;
+use penumbra_stake::{FundingStreams, IdentityKey, Validator};
+use penumbra_transaction::Transaction;
+use penumbra_wallet::ClientState;
+use rand::Rng;
+use rand_core::OsRng;
+use tendermint::{account, Genesis};
+use tendermint_config::{NodeKey, PrivValidatorKey};
+use tokio::process::{Child, Command};
+
+use super::{
+    consensus_params, genesis_delegations, sync_client, unix_now, DATABASE_URI_VAR,
+    GENESIS_DELEGATION,
+};
+
+/// The environment variable holding the path to the `tendermint` binary.
+pub const TENDERMINT_VAR: &str = "PD_TEST_TENDERMINT";
+
+/// The environment variable holding the first port assigned to testnet nodes.
+///
+/// Each node uses [`PORTS_PER_NODE`] consecutive ports starting from here.
+pub const BASE_PORT_VAR: &str = "PD_TEST_BASE_PORT";
+
+const DEFAULT_BASE_PORT: u16 = 36000;
+const PORTS_PER_NODE: u16 = 10;
+
+/// How often to poll nodes while waiting for them to make progress.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A set of validator nodes running a testnet on localhost.
+///
+/// The nodes' processes are killed, and their configuration deleted, when the
+/// `Testnet` is dropped.
+pub struct Testnet {
+    dir: PathBuf,
+    /// The chain parameters the testnet was started with.
+    pub chain_params: ChainParams,
+    /// The genesis validators, in the same order as the nodes running them.
+    pub validators: Vec<Validator>,
+    /// The nodes running the testnet.
+    pub nodes: Vec<Node>,
+}
+
+/// A single `pd` and `tendermint` pair in a [`Testnet`].
+pub struct Node {
+    /// The node's name, used as its Tendermint moniker.
+    pub name: String,
+    /// The node's Tendermint home directory.
+    pub home: PathBuf,
+    /// The port serving Tendermint's RPC.
+    pub rpc_port: u16,
+    /// The port serving `pd`'s light wallet service.
+    pub light_wallet_port: u16,
+    /// The port serving `pd`'s thin wallet service.
+    pub thin_wallet_port: u16,
+    p2p_port: u16,
+    abci_port: u16,
+    metrics_port: u16,
+    database_uri: String,
+    pd: Option<Child>,
+    tendermint: Option<Child>,
+}
+
+impl Testnet {
+    /// Start a testnet of `num_nodes` equally-weighted validators, with the
+    /// given genesis allocations.
+    pub async fn start(
+        num_nodes: usize,
+        chain_params: ChainParams,
+        mut allocations: Vec<genesis::Allocation>,
+    ) -> Result<Self> {
+        let base_database_uri = std::env::var(DATABASE_URI_VAR)
+            .map_err(|_| anyhow!("{} must be set to run this test", DATABASE_URI_VAR))?;
+        let base_port = match std::env::var(BASE_PORT_VAR) {
+            Ok(port) => port.parse()?,
+            Err(_) => DEFAULT_BASE_PORT,
+        };
+
+        let dir = std::env::temp_dir().join(format!(
+            "pd-testnet-{}",
+            hex::encode(OsRng.gen::<u32>().to_le_bytes())
+        ));
+
+        let mut validators = Vec::new();
+        let mut consensus_keys = Vec::new();
+        let mut node_keys = Vec::new();
+        let mut nodes = Vec::new();
+        for n in 0..num_nodes {
+            let consensus_key =
+                tendermint::PrivateKey::Ed25519(ed25519_consensus::SigningKey::new(OsRng));
+            let node_key =
+                tendermint::PrivateKey::Ed25519(ed25519_consensus::SigningKey::new(OsRng));
+
+            validators.push(Validator {
+                identity_key: IdentityKey(VerificationKey::from(&SigningKey::<SpendAuth>::new(
+                    OsRng,
+                ))),
+                consensus_key: consensus_key.public_key(),
+                name: format!("node{}", n),
+                website: String::new(),
+                description: String::new(),
+                funding_streams: FundingStreams::new(),
+                sequence_number: 0,
+            });
+            consensus_keys.push(consensus_key);
+            node_keys.push(NodeKey { priv_key: node_key });
+
+            let port = base_port + n as u16 * PORTS_PER_NODE;
+            nodes.push(Node {
+                name: format!("node{}", n),
+                home: dir.join(format!("node{}", n)),
+                p2p_port: port,
+                rpc_port: port + 1,
+                abci_port: port + 2,
+                light_wallet_port: port + 3,
+                thin_wallet_port: port + 4,
+                metrics_port: port + 5,
+                database_uri: create_database(&base_database_uri, &format!("node{}", n)).await?,
+                pd: None,
+                tendermint: None,
+            });
+        }
+
+        let genesis_validators = validators
+            .iter()
+            .map(|validator| genesis::ValidatorPower {
+                validator: validator.clone(),
+                power: (GENESIS_DELEGATION as u32).into(),
+            })
+            .collect::<Vec<_>>();
+        allocations.extend(genesis_delegations(&genesis_validators)?);
+
+        // Every node has to start from the same genesis.
+        let genesis = Genesis {
+            genesis_time: tendermint::Time::from_unix_timestamp(unix_now() as i64, 0)
+                .expect("able to convert current time into Time"),
+            chain_id: chain_params
+                .chain_id
+                .parse::<tendermint::chain::Id>()
+                .expect("able to create chain ID"),
+            initial_height: 0,
+            consensus_params: consensus_params(),
+            app_hash: vec![],
+            app_state: genesis::AppState {
+                chain_params: chain_params.clone(),
+                validators: genesis_validators,
+                allocations,
+            },
+            validators: vec![],
+        };
+
+        for (n, node) in nodes.iter().enumerate() {
+            let persistent_peers = nodes
+                .iter()
+                .zip(&node_keys)
+                .filter(|(peer, _)| peer.name != node.name)
+                .map(|(peer, node_key)| {
+                    format!("{}@127.0.0.1:{}", node_key.node_id(), peer.p2p_port)
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+
+            let config_dir = node.home.join("config");
+            let data_dir = node.home.join("data");
+            fs::create_dir_all(&config_dir)?;
+            fs::create_dir_all(&data_dir)?;
+
+            fs::write(
+                config_dir.join("config.toml"),
+                node.tendermint_config(&persistent_peers),
+            )?;
+            fs::write(
+                config_dir.join("genesis.json"),
+                serde_json::to_string_pretty(&genesis)?,
+            )?;
+            fs::write(
+                config_dir.join("node_key.json"),
+                serde_json::to_string_pretty(&node_keys[n])?,
+            )?;
+
+            let consensus_key = &consensus_keys[n];
+            let priv_validator_key = PrivValidatorKey {
+                address: account::Id::from(consensus_key.public_key()),
+                pub_key: consensus_key.public_key(),
+                // the underlying type doesn't implement Copy or Clone (for the best)
+                priv_key: tendermint::PrivateKey::Ed25519(
+                    consensus_key.ed25519_signing_key().unwrap().clone(),
+                ),
+            };
+            fs::write(
+                config_dir.join("priv_validator_key.json"),
+                serde_json::to_string_pretty(&priv_validator_key)?,
+            )?;
+            fs::write(
+                data_dir.join("priv_validator_state.json"),
+                get_validator_state(),
+            )?;
+        }
+
+        let mut testnet = Self {
+            dir,
+            chain_params,
+            validators,
+            nodes,
+        };
+        for node in &mut testnet.nodes {
+            node.start()?;
+        }
+
+        Ok(testnet)
+    }
+
+    /// Wait until every running node has committed a block at `height`.
+    pub async fn wait_for_height(&self, height: u64, timeout: Duration) -> Result<()> {
+        tokio::time::timeout(timeout, async {
+            for node in self.nodes.iter().filter(|node| node.is_running()) {
+                node.wait_for_height(height).await?;
+            }
+            Ok(())
+        })
+        .await
+        .map_err(|_| anyhow!("timed out waiting for height {}", height))?
+    }
+}
+
+impl Drop for Testnet {
+    fn drop(&mut self) {
+        // The processes are killed when their handles are dropped.
+        for node in &mut self.nodes {
+            node.tendermint.take();
+            node.pd.take();
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+impl Node {
+    /// Start the node's `pd` and `tendermint` processes.
+    ///
+    /// The node resumes from its existing state, so this can be used to bring
+    /// a node back after [`Node::stop`].
+    pub fn start(&mut self) -> Result<()> {
+        if self.is_running() {
+            return Ok(());
+        }
+
+        let log_file =
+            |name: &str| -> Result<Stdio> { Ok(fs::File::create(self.home.join(name))?.into()) };
+
+        self.pd = Some(
+            Command::new(env!("CARGO_BIN_EXE_pd"))
+                .arg("start")
+                .args(["--database-uri", &self.database_uri])
+                .args(["--abci-port", &self.abci_port.to_string()])
+                .args(["--light-wallet-port", &self.light_wallet_port.to_string()])
+                .args(["--thin-wallet-port", &self.thin_wallet_port.to_string()])
+                .args(["--metrics-port", &self.metrics_port.to_string()])
+                .args(["--invariant-checks", "epoch"])
+                .stdout(log_file("pd.log")?)
+                .stderr(Stdio::null())
+                .kill_on_drop(true)
+                .spawn()
+                .context("could not start pd")?,
+        );
+
+        let tendermint = std::env::var(TENDERMINT_VAR).unwrap_or_else(|_| "tendermint".to_string());
+        self.tendermint = Some(
+            Command::new(tendermint)
+                .arg("start")
+                .arg("--home")
+                .arg(&self.home)
+                .stdout(log_file("tendermint.log")?)
+                .stderr(Stdio::null())
+                .kill_on_drop(true)
+                .spawn()
+                .context("could not start tendermint")?,
+        );
+
+        Ok(())
+    }
+
+    /// Stop the node's `pd` and `tendermint` processes, taking it offline.
+    pub async fn stop(&mut self) -> Result<()> {
+        if let Some(mut tendermint) = self.tendermint.take() {
+            tendermint.kill().await?;
+        }
+        if let Some(mut pd) = self.pd.take() {
+            pd.kill().await?;
+        }
+        Ok(())
+    }
+
+    /// Returns true if the node's processes have been started, and not stopped.
+    pub fn is_running(&self) -> bool {
+        self.pd.is_some()
+    }
+
+    /// Returns the height of the latest block this node has committed.
+    pub async fn height(&self) -> Result<u64> {
+        let rsp: serde_json::Value =
+            reqwest::get(format!("http://127.0.0.1:{}/status", self.rpc_port))
+                .await?
+                .json()
+                .await?;
+
+        // Sometimes the result is in a result key, and sometimes it's bare? (??)
+        let result = rsp.get("result").unwrap_or(&rsp);
+        result
+            .pointer("/sync_info/latest_block_height")
+            .and_then(|height| height.as_str())
+            .ok_or_else(|| anyhow!("could not parse status response: {}", rsp))?
+            .parse()
+            .map_err(Into::into)
+    }
+
+    /// Wait until this node has committed a block at `height`.
+    pub async fn wait_for_height(&self, height: u64) -> Result<()> {
+        loop {
+            // The RPC isn't available until the node finishes starting up.
+            if matches!(self.height().await, Ok(h) if h >= height) {
+                return Ok(());
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Submit a transaction to this node, returning an error if it was not
+    /// accepted into the mempool.
+    pub async fn submit(&self, transaction: &Transaction) -> Result<()> {
+        let rsp: serde_json::Value = reqwest::Client::new()
+            .post(format!("http://127.0.0.1:{}", self.rpc_port))
+            .json(&serde_json::json!(
+                {
+                    "method": "broadcast_tx_sync",
+                    "params": [&transaction.encode_to_vec()],
+                    "id": 0,
+                }
+            ))
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        let result = rsp.get("result").unwrap_or(&rsp);
+        match result.get("code").and_then(|code| code.as_i64()) {
+            Some(0) => Ok(()),
+            _ => Err(anyhow!("transaction was not accepted: {}", rsp)),
+        }
+    }
+
+    /// Returns a reader for this node's chain state.
+    pub async fn state(&self) -> Result<state::Reader> {
+        let (reader, _writer) = state::new(&self.database_uri).await?;
+        Ok(reader)
+    }
+
+    /// Scan all blocks committed by this node that the client has not yet seen.
+    pub async fn sync(&self, client: &mut ClientState) -> Result<()> {
+        let height = self.height().await?;
+        sync_client(&self.state().await?, height, client).await?;
+        Ok(())
+    }
+
+    fn tendermint_config(&self, persistent_peers: &str) -> String {
+        generate_tm_config(&self.name, None, true)
+            .replace(
+                "proxy-app = \"tcp://127.0.0.1:26658\"",
+                &format!("proxy-app = \"tcp://127.0.0.1:{}\"", self.abci_port),
+            )
+            .replace(
+                "laddr = \"tcp://0.0.0.0:26657\"",
+                &format!("laddr = \"tcp://127.0.0.1:{}\"", self.rpc_port),
+            )
+            .replace(
+                "laddr = \"tcp://0.0.0.0:26656\"",
+                &format!("laddr = \"tcp://127.0.0.1:{}\"", self.p2p_port),
+            )
+            .replace(
+                "persistent-peers = \"\"",
+                &format!("persistent-peers = \"{}\"", persistent_peers),
+            )
+            // These would conflict between nodes, and we don't need them.
+            .replace("pprof-laddr = \":6060\"", "pprof-laddr = \"\"")
+            .replace("prometheus = true", "prometheus = false")
+    }
+}
+
+/// Create (or recreate) an empty database for a node on the server holding
+/// `base_uri`, returning its URI.
+async fn create_database(base_uri: &str, node_name: &str) -> Result<String> {
+    let (server, base_name) = base_uri
+        .rsplit_once('/')
+        .ok_or_else(|| anyhow!("invalid database URI {}", base_uri))?;
+    let name = format!("{}_{}", base_name, node_name);
+
+    let pool = sqlx::PgPool::connect(base_uri).await?;
+    sqlx::query(&format!("DROP DATABASE IF EXISTS \"{}\"", name))
+        .execute(&pool)
+        .await?;
+    sqlx::query(&format!("CREATE DATABASE \"{}\"", name))
+        .execute(&pool)
+        .await?;
+    pool.close().await;
+
+    Ok(format!("{}/{}", server, name))
+}
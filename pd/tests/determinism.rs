@@ -0,0 +1,85 @@
+//! Checks that the chain state is a deterministic function of the blocks fed to `pd`: two nodes
+//! started from the same genesis and fed the same requests must commit the same app hashes, or
+//! they would halt the chain by disagreeing.
+
+mod common;
+
+use anyhow::Result;
+use common::Devnet;
+use pd::genesis;
+use penumbra_chain::params::ChainParams;
+use penumbra_stake::{RateData, STAKING_TOKEN_ASSET_ID, STAKING_TOKEN_DENOM};
+use penumbra_transaction::Transaction;
+use penumbra_wallet::{ClientState, Wallet};
+use rand_core::OsRng;
+
+const SEED: u64 = 7;
+const EPOCH_DURATION: u64 = 4;
+const INITIAL_BALANCE: u64 = 1_000_000;
+const DELEGATION: u64 = 400_000;
+
+/// What a node committed for a run of blocks.
+#[derive(Debug, PartialEq)]
+struct Run {
+    app_hashes: Vec<Vec<u8>>,
+    staking_token_supply: u64,
+    delegation_token_supply: u64,
+    next_rate_data: RateData,
+}
+
+// Requires a scratch Postgres database; run with
+// `PD_TEST_DATABASE_URI=... cargo test -p pd -- --ignored`.
+#[tokio::test]
+#[ignore]
+async fn identical_requests_produce_identical_state() -> Result<()> {
+    let chain_params = ChainParams {
+        chain_id: "penumbra-devnet".to_string(),
+        epoch_duration: EPOCH_DURATION,
+        ..Default::default()
+    };
+
+    let mut client = ClientState::new(Wallet::generate(OsRng));
+    *client.chain_params_mut() = Some(chain_params.clone());
+    let (_label, address) = client.wallet().address_by_index(0)?;
+    let allocations = vec![genesis::Allocation {
+        amount: INITIAL_BALANCE,
+        denom: STAKING_TOKEN_DENOM.to_string(),
+        address,
+    }];
+
+    // Build the transaction against the first node, so that both are fed the same bytes.
+    let devnet = Devnet::start_seeded(chain_params.clone(), allocations.clone(), SEED).await?;
+    devnet.sync(&mut client).await?;
+    let rate_data = devnet.next_rate_data().await?;
+    let delegate = client.build_delegate(&mut OsRng, rate_data, DELEGATION, 0, None)?;
+    let first = run(devnet, vec![delegate.clone()]).await?;
+
+    // The devnet shares one scratch database, so the second node starts over in it.
+    let devnet = Devnet::start_seeded(chain_params, allocations, SEED).await?;
+    let second = run(devnet, vec![delegate]).await?;
+
+    assert_eq!(first, second);
+
+    Ok(())
+}
+
+/// Delivers `transactions` in the first block, then crosses two epoch boundaries, so that the
+/// delegations are applied to the token supplies and the rates.
+async fn run(mut devnet: Devnet, transactions: Vec<Transaction>) -> Result<Run> {
+    let mut app_hashes = Vec::new();
+    devnet.next_block(transactions).await?;
+    app_hashes.push(devnet.app_hash.clone());
+    while devnet.height < 2 * EPOCH_DURATION {
+        devnet.next_block(Vec::new()).await?;
+        app_hashes.push(devnet.app_hash.clone());
+    }
+    devnet.state.wait_for_committed_block().await?;
+
+    let delegation_token = devnet.validator.identity_key.delegation_token();
+    Ok(Run {
+        app_hashes,
+        staking_token_supply: devnet.total_supply(*STAKING_TOKEN_ASSET_ID).await?,
+        delegation_token_supply: devnet.total_supply(delegation_token.id()).await?,
+        next_rate_data: devnet.next_rate_data().await?,
+    })
+}
@@ -0,0 +1,61 @@
+//! Checks that the chain's emergency halt rejects transactions that move value in `DeliverTx`
+//! from the halt height on, while blocks keep being produced.
+
+mod common;
+
+use anyhow::Result;
+use common::Devnet;
+use pd::genesis;
+use penumbra_chain::params::ChainParams;
+use penumbra_stake::STAKING_TOKEN_DENOM;
+use penumbra_wallet::{ClientState, Wallet};
+use rand_core::OsRng;
+
+const HALT_HEIGHT: u64 = 3;
+const INITIAL_BALANCE: u64 = 1_000_000;
+const DELEGATION: u64 = 1_000;
+
+// Requires a scratch Postgres database; run with
+// `PD_TEST_DATABASE_URI=... cargo test -p pd -- --ignored`.
+#[tokio::test]
+#[ignore]
+async fn rejects_value_transfers_from_halt_height() -> Result<()> {
+    let chain_params = ChainParams {
+        chain_id: "penumbra-devnet".to_string(),
+        epoch_duration: 10,
+        emergency_halt_height: HALT_HEIGHT,
+        ..Default::default()
+    };
+
+    let mut client = ClientState::new(Wallet::generate(OsRng));
+    *client.chain_params_mut() = Some(chain_params.clone());
+    let (_label, address) = client.wallet().address_by_index(0)?;
+
+    let mut devnet = Devnet::start(
+        chain_params,
+        vec![genesis::Allocation {
+            amount: INITIAL_BALANCE,
+            denom: STAKING_TOKEN_DENOM.to_string(),
+            address,
+        }],
+    )
+    .await?;
+
+    // Before the halt height, transactions that move value are accepted as usual.
+    devnet.sync(&mut client).await?;
+    let rate_data = devnet.next_rate_data().await?;
+    let delegate = client.build_delegate(&mut OsRng, rate_data.clone(), DELEGATION, 0, None)?;
+    devnet.next_block(vec![delegate]).await?;
+
+    // From the halt height on, they're rejected, but the chain keeps going.
+    devnet.advance_to(HALT_HEIGHT - 1).await?;
+    devnet.sync(&mut client).await?;
+    let delegate = client.build_delegate(&mut OsRng, rate_data, DELEGATION, 0, None)?;
+    let err = devnet.next_block(vec![delegate]).await.unwrap_err();
+    assert!(err.to_string().contains("emergency"), "{}", err);
+    assert_eq!(devnet.height, HALT_HEIGHT);
+
+    devnet.next_block(Vec::new()).await?;
+
+    Ok(())
+}
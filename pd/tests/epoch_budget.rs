@@ -0,0 +1,54 @@
+//! Checks that processing an epoch boundary fits within its time budget with
+//! a realistically sized validator set, since it happens in the `EndBlock`
+//! critical path.
+
+mod common;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use common::Devnet;
+use penumbra_chain::params::ChainParams;
+
+const EPOCH_DURATION: u64 = 4;
+const VALIDATOR_COUNT: usize = 200;
+const EPOCH_BUDGET: Duration = Duration::from_millis(100);
+/// The number of epoch boundaries to time, to smooth out noise.
+const EPOCHS: u64 = 5;
+
+// Requires a scratch Postgres database; run with
+// `PD_TEST_DATABASE_URI=... cargo test -p pd --release -- --ignored`.
+#[tokio::test]
+#[ignore]
+async fn epoch_processing_fits_budget() -> Result<()> {
+    let chain_params = ChainParams {
+        chain_id: "penumbra-devnet".to_string(),
+        epoch_duration: EPOCH_DURATION,
+        ..Default::default()
+    };
+
+    let mut devnet =
+        Devnet::start_with_validators(chain_params, Vec::new(), VALIDATOR_COUNT).await?;
+
+    let mut durations = Vec::new();
+    for epoch in 1..=EPOCHS {
+        // The last block of each epoch is the one that processes the epoch boundary.
+        devnet.advance_to(epoch * EPOCH_DURATION - 1).await?;
+        durations.push(devnet.last_end_block_duration);
+    }
+    durations.sort();
+    let median = durations[durations.len() / 2];
+
+    println!(
+        "epoch processing with {} validators: median {:?}, all {:?}",
+        VALIDATOR_COUNT, median, durations
+    );
+    assert!(
+        median < EPOCH_BUDGET,
+        "epoch processing took {:?}, over the {:?} budget",
+        median,
+        EPOCH_BUDGET
+    );
+
+    Ok(())
+}
@@ -0,0 +1,70 @@
+//! Checks that a fresh wallet discovers its genesis allocation by syncing through the light
+//! wallet service, before any block after genesis has been produced.
+
+mod common;
+
+use anyhow::Result;
+use common::{balance, Devnet};
+use futures::TryStreamExt;
+use pd::genesis;
+use penumbra_chain::params::ChainParams;
+use penumbra_proto::{
+    client::v1alpha1::light_wallet_server::LightWallet, light_wallet::CompactBlockRangeRequest,
+};
+use penumbra_stake::STAKING_TOKEN_DENOM;
+use penumbra_wallet::{ClientState, Wallet};
+use rand_core::OsRng;
+
+const INITIAL_BALANCE: u64 = 1_000_000;
+
+// Requires a scratch Postgres database; run with
+// `PD_TEST_DATABASE_URI=... cargo test -p pd -- --ignored`.
+#[tokio::test]
+#[ignore]
+async fn fresh_wallet_discovers_genesis_allocation() -> Result<()> {
+    let chain_params = ChainParams {
+        chain_id: "penumbra-devnet".to_string(),
+        ..Default::default()
+    };
+
+    let mut client = ClientState::new(Wallet::generate(OsRng));
+    *client.chain_params_mut() = Some(chain_params.clone());
+    let (_label, address) = client.wallet().address_by_index(0)?;
+
+    let devnet = Devnet::start(
+        chain_params.clone(),
+        vec![genesis::Allocation {
+            amount: INITIAL_BALANCE,
+            denom: STAKING_TOKEN_DENOM.to_string(),
+            address,
+        }],
+    )
+    .await?;
+
+    // Sync the way pcli does, immediately after InitChain.
+    let blocks = devnet
+        .state
+        .compact_block_range(tonic::Request::new(CompactBlockRangeRequest {
+            start_height: 0,
+            end_height: 0,
+            chain_id: chain_params.chain_id,
+            release_validators: Vec::new(),
+            max_version: pd::COMPACT_BLOCK_VERSION,
+        }))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    assert_eq!(
+        blocks.iter().map(|block| block.height).collect::<Vec<_>>(),
+        vec![0]
+    );
+    for block in blocks {
+        client.scan_block(block)?;
+    }
+    assert_eq!(client.last_block_height(), Some(0));
+    assert_eq!(balance(&client, &STAKING_TOKEN_DENOM), INITIAL_BALANCE);
+
+    Ok(())
+}
@@ -0,0 +1,104 @@
+//! Restores a wallet without witnessing its notes, and checks that it can spend them with
+//! authentication paths fetched from the thin wallet service, but only ones that authenticate
+//! them against its own anchor.
+
+mod common;
+
+use anyhow::Result;
+use common::{balance, Devnet};
+use pd::genesis;
+use penumbra_chain::params::ChainParams;
+use penumbra_crypto::Value;
+use penumbra_proto::{
+    client::v1alpha1::thin_wallet_server::ThinWallet, thin_wallet::NoteWitnessesRequest,
+};
+use penumbra_stake::{STAKING_TOKEN_ASSET_ID, STAKING_TOKEN_DENOM};
+use penumbra_wallet::{ClientState, Wallet};
+use rand_core::OsRng;
+
+const INITIAL_BALANCE: u64 = 1_000_000;
+const SEND_AMOUNT: u64 = 1_000;
+
+// Requires a scratch Postgres database; run with
+// `PD_TEST_DATABASE_URI=... cargo test -p pd -- --ignored`.
+#[tokio::test]
+#[ignore]
+async fn restored_wallet_spends_with_fetched_witnesses() -> Result<()> {
+    let chain_params = ChainParams {
+        chain_id: "penumbra-devnet".to_string(),
+        ..Default::default()
+    };
+
+    let mut client = ClientState::new(Wallet::generate(OsRng));
+    *client.chain_params_mut() = Some(chain_params.clone());
+    client.set_fetch_witnesses(true);
+    let (_label, address) = client.wallet().address_by_index(0)?;
+    let receiver = ClientState::new(Wallet::generate(OsRng));
+    let (_label, receiver_address) = receiver.wallet().address_by_index(0)?;
+
+    let mut devnet = Devnet::start(
+        chain_params.clone(),
+        vec![genesis::Allocation {
+            amount: INITIAL_BALANCE,
+            denom: STAKING_TOKEN_DENOM.to_string(),
+            address,
+        }],
+    )
+    .await?;
+    devnet.next_block(Vec::new()).await?;
+    devnet.sync(&mut client).await?;
+
+    // The wallet found its allocation, but can't spend it without a path.
+    assert_eq!(balance(&client, &STAKING_TOKEN_DENOM), INITIAL_BALANCE);
+    let note_commitments = client.unwitnessed_notes();
+    assert_eq!(note_commitments.len(), 1);
+    let send = |client: &mut ClientState| {
+        client.build_send(
+            &mut OsRng,
+            &[Value {
+                amount: SEND_AMOUNT,
+                asset_id: *STAKING_TOKEN_ASSET_ID,
+            }],
+            Value {
+                amount: 0,
+                asset_id: *STAKING_TOKEN_ASSET_ID,
+            },
+            receiver_address,
+            None,
+            None,
+        )
+    };
+    assert!(send(&mut client.clone()).is_err());
+
+    let witnesses = devnet
+        .state
+        .note_witnesses(tonic::Request::new(NoteWitnessesRequest {
+            chain_id: chain_params.chain_id.clone(),
+            note_commitments: note_commitments.iter().map(|&c| c.into()).collect(),
+        }))
+        .await?
+        .into_inner();
+
+    // A path to another position, or against another anchor, is rejected.
+    let mut moved = witnesses.clone();
+    moved.witnesses[0].position += 1;
+    assert!(client.clone().add_note_witnesses(moved).is_err());
+    let mut reanchored = witnesses.clone();
+    reanchored.witnesses[0].auth_path[0] = note_commitments[0].into();
+    assert!(client.clone().add_note_witnesses(reanchored).is_err());
+
+    assert_eq!(client.add_note_witnesses(witnesses)?, 1);
+    assert!(client.unwitnessed_notes().is_empty());
+    let send = send(&mut client)?;
+    devnet.next_block(vec![send]).await?;
+
+    // The change is unwitnessed too, and the fetched path is no use against the new anchor.
+    devnet.sync(&mut client).await?;
+    assert_eq!(
+        balance(&client, &STAKING_TOKEN_DENOM),
+        INITIAL_BALANCE - SEND_AMOUNT
+    );
+    assert_eq!(client.unwitnessed_notes().len(), 1);
+
+    Ok(())
+}
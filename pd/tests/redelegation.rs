@@ -0,0 +1,151 @@
+//! Redelegates stake between validators, and checks that a delegator key's first redelegation is
+//! quarantined like an undelegation, that the key can't redelegate again during the cooldown, and
+//! that once the cooldown is over its redelegations move stake without any quarantine.
+
+mod common;
+
+use anyhow::{anyhow, Result};
+use common::{balance, Devnet};
+use pd::genesis;
+use penumbra_chain::params::ChainParams;
+use penumbra_stake::STAKING_TOKEN_DENOM;
+use penumbra_wallet::{ClientState, SelectionStrategy, Wallet};
+use rand_core::OsRng;
+
+const EPOCH_DURATION: u64 = 4;
+const UNBONDING_EPOCHS: u64 = 2;
+const INITIAL_BALANCE: u64 = 1_000_000;
+const DELEGATION: u64 = 400_000;
+
+// Requires a scratch Postgres database; run with
+// `PD_TEST_DATABASE_URI=... cargo test -p pd -- --ignored`.
+#[tokio::test]
+#[ignore]
+async fn redelegations_are_limited_by_a_seasoned_cooldown() -> Result<()> {
+    let chain_params = ChainParams {
+        chain_id: "penumbra-devnet".to_string(),
+        epoch_duration: EPOCH_DURATION,
+        unbonding_epochs: UNBONDING_EPOCHS,
+        ..Default::default()
+    };
+
+    let mut client = ClientState::new(Wallet::generate(OsRng));
+    *client.chain_params_mut() = Some(chain_params.clone());
+    // Spend one delegation note at a time, so that some delegation is left after redelegating.
+    client.set_note_selection(SelectionStrategy::FeeMin);
+    let (_label, address) = client.wallet().address_by_index(0)?;
+
+    let mut devnet = Devnet::start_with_validators(
+        chain_params,
+        vec![genesis::Allocation {
+            amount: INITIAL_BALANCE,
+            denom: STAKING_TOKEN_DENOM.to_string(),
+            address,
+        }],
+        2,
+    )
+    .await?;
+    let from_identity_key = devnet.validator.identity_key.clone();
+    let from_denom = from_identity_key.delegation_token().denom();
+
+    // Delegate to the first validator twice, and wait for the delegations to take effect.
+    let delegate_rate = devnet.next_rate_data().await?;
+    for _ in 0..2 {
+        devnet.sync(&mut client).await?;
+        let delegate =
+            client.build_delegate(&mut OsRng, delegate_rate.clone(), DELEGATION, 0, None)?;
+        devnet.next_block(vec![delegate]).await?;
+    }
+    let delegation_amount = delegate_rate.delegation_amount(DELEGATION);
+    devnet.advance_to(EPOCH_DURATION).await?;
+
+    // The wallet's first redelegation moves one of the delegations to the other validator.
+    devnet.sync(&mut client).await?;
+    let first_amount = delegation_amount;
+    let from_rate = devnet.next_rate_data().await?;
+    let to_rate = devnet
+        .state
+        .next_rate_data()
+        .await?
+        .into_values()
+        .find(|rate| rate.identity_key != from_identity_key)
+        .ok_or_else(|| anyhow!("missing rate data for second validator"))?;
+    let to_denom = to_rate.identity_key.delegation_token().denom();
+    let redelegate = client.build_redelegate(
+        &mut OsRng,
+        from_rate.clone(),
+        to_rate.clone(),
+        first_amount,
+        0,
+        None,
+    )?;
+    devnet.next_block(vec![redelegate]).await?;
+    let redelegation_height = devnet.height;
+    let first_to_amount = to_rate.delegation_amount(from_rate.unbonded_amount(first_amount));
+
+    // A fresh delegator key could skip the cooldown, so its new delegation tokens are quarantined
+    // under the validator the stake left.
+    devnet.sync(&mut client).await?;
+    assert_eq!(balance(&client, &to_denom), 0);
+    assert!(!devnet
+        .state
+        .quarantine_schedule(Some(&from_identity_key))
+        .await?
+        .is_empty());
+
+    // The same key can't redelegate the other delegation until the cooldown is over.
+    assert_eq!(balance(&client, &from_denom), delegation_amount);
+    let redelegate = client.clone().build_redelegate(
+        &mut OsRng,
+        from_rate.clone(),
+        to_rate.clone(),
+        first_amount,
+        0,
+        None,
+    )?;
+    let err = devnet.next_block(vec![redelegate]).await.unwrap_err();
+    assert!(
+        err.to_string().contains("can't redelegate again"),
+        "{}",
+        err
+    );
+
+    // The first redelegation is released at the end of the unbonding period.
+    let unbonding_height = redelegation_height + EPOCH_DURATION * UNBONDING_EPOCHS;
+    let release_height = (unbonding_height / EPOCH_DURATION + 1) * EPOCH_DURATION - 1;
+    devnet.advance_to(release_height + 1).await?;
+    devnet.sync(&mut client).await?;
+    assert_eq!(balance(&client, &to_denom), first_to_amount);
+
+    // Now that the cooldown is over, the key's next redelegation isn't quarantined at all.
+    let second_amount = balance(&client, &from_denom);
+    let from_rate = devnet.next_rate_data().await?;
+    let to_rate = devnet
+        .state
+        .next_rate_data()
+        .await?
+        .remove(&to_rate.identity_key)
+        .ok_or_else(|| anyhow!("missing rate data for second validator"))?;
+    let redelegate = client.build_redelegate(
+        &mut OsRng,
+        from_rate.clone(),
+        to_rate.clone(),
+        second_amount,
+        0,
+        None,
+    )?;
+    devnet.next_block(vec![redelegate]).await?;
+    devnet.sync(&mut client).await?;
+    assert_eq!(balance(&client, &from_denom), 0);
+    assert_eq!(
+        balance(&client, &to_denom),
+        first_to_amount + to_rate.delegation_amount(from_rate.unbonded_amount(second_amount))
+    );
+    assert!(devnet
+        .state
+        .quarantine_schedule(Some(&from_identity_key))
+        .await?
+        .is_empty());
+
+    Ok(())
+}
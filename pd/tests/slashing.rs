@@ -0,0 +1,114 @@
+//! Slashes a validator with a pending undelegation, and checks that the
+//! quarantined undelegation is reverted rather than released.
+
+mod common;
+
+use anyhow::Result;
+use common::{balance, Devnet};
+use pd::genesis;
+use penumbra_chain::params::ChainParams;
+use penumbra_stake::STAKING_TOKEN_DENOM;
+use penumbra_wallet::{ClientState, Wallet};
+use rand_core::OsRng;
+
+const EPOCH_DURATION: u64 = 4;
+const UNBONDING_EPOCHS: u64 = 2;
+const INITIAL_BALANCE: u64 = 1_000_000;
+const DELEGATION: u64 = 400_000;
+
+// Requires a scratch Postgres database; run with
+// `PD_TEST_DATABASE_URI=... cargo test -p pd -- --ignored`.
+#[tokio::test]
+#[ignore]
+async fn slashing_reverts_quarantined_undelegation() -> Result<()> {
+    let chain_params = ChainParams {
+        chain_id: "penumbra-devnet".to_string(),
+        epoch_duration: EPOCH_DURATION,
+        unbonding_epochs: UNBONDING_EPOCHS,
+        ..Default::default()
+    };
+
+    let wallet = Wallet::generate(OsRng);
+    let mut client = ClientState::new(wallet.clone());
+    *client.chain_params_mut() = Some(chain_params.clone());
+    let (_label, address) = client.wallet().address_by_index(0)?;
+
+    let mut devnet = Devnet::start(
+        chain_params.clone(),
+        vec![genesis::Allocation {
+            amount: INITIAL_BALANCE,
+            denom: STAKING_TOKEN_DENOM.to_string(),
+            address,
+        }],
+    )
+    .await?;
+    let identity_key = devnet.validator.identity_key.clone();
+    let delegation_denom = identity_key.delegation_token().denom();
+
+    // Delegate, and wait for the delegation to take effect.
+    devnet.sync(&mut client).await?;
+    let delegate_rate = devnet.next_rate_data().await?;
+    let delegate = client.build_delegate(&mut OsRng, delegate_rate.clone(), DELEGATION, 0, None)?;
+    devnet.next_block(vec![delegate]).await?;
+    let delegation_amount = delegate_rate.delegation_amount(DELEGATION);
+    devnet.advance_to(EPOCH_DURATION).await?;
+
+    // Undelegate everything, leaving the unbonded stake in quarantine.
+    devnet.sync(&mut client).await?;
+    let undelegate_rate = devnet.next_rate_data().await?;
+    let undelegate =
+        client.build_undelegate(&mut OsRng, undelegate_rate, delegation_amount, 0, None)?;
+    devnet.next_block(vec![undelegate]).await?;
+    let undelegation_height = devnet.height;
+
+    devnet.sync(&mut client).await?;
+    assert_eq!(balance(&client, &delegation_denom), 0);
+    assert!(!devnet
+        .state
+        .quarantine_schedule(Some(&identity_key))
+        .await?
+        .is_empty());
+
+    // Report the validator's misbehavior before the undelegation is released.
+    let consensus_key = devnet.validator.consensus_key;
+    devnet
+        .next_block_with_evidence(Vec::new(), vec![consensus_key])
+        .await?;
+
+    // Slashing drops everything the validator had in quarantine...
+    assert!(devnet
+        .state
+        .quarantine_schedule(Some(&identity_key))
+        .await?
+        .is_empty());
+
+    // ... so the unbonded stake is never released, even after the unbonding
+    // period has elapsed ...
+    let unbonding_height = undelegation_height + EPOCH_DURATION * UNBONDING_EPOCHS;
+    let release_height = (unbonding_height / EPOCH_DURATION + 1) * EPOCH_DURATION - 1;
+    devnet.advance_to(release_height + EPOCH_DURATION).await?;
+    devnet.sync(&mut client).await?;
+    assert_eq!(
+        balance(&client, &STAKING_TOKEN_DENOM),
+        INITIAL_BALANCE - DELEGATION
+    );
+
+    // ... and the spend of the delegation tokens is reverted, so they are
+    // spendable again, both for the client that saw the spend ...
+    assert_eq!(balance(&client, &delegation_denom), delegation_amount);
+    let undelegate_rate = devnet.next_rate_data().await?;
+    client.build_undelegate(&mut OsRng, undelegate_rate, delegation_amount, 0, None)?;
+
+    // ... and, since reverted nullifiers are removed from the chain's history,
+    // for a client that scans from genesis and never sees the spend at all.
+    let mut rescanned = ClientState::new(wallet);
+    *rescanned.chain_params_mut() = Some(chain_params);
+    devnet.sync(&mut rescanned).await?;
+    assert_eq!(balance(&rescanned, &delegation_denom), delegation_amount);
+    assert_eq!(
+        balance(&rescanned, &STAKING_TOKEN_DENOM),
+        INITIAL_BALANCE - DELEGATION
+    );
+
+    Ok(())
+}
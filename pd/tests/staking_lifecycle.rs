@@ -0,0 +1,195 @@
+//! Runs a delegation through its full lifecycle on a devnet: delegating,
+//! crossing an epoch boundary, undelegating, and waiting for the unbonded
+//! stake to be released from quarantine.
+
+mod common;
+
+use anyhow::Result;
+use common::{balance, Devnet, GENESIS_DELEGATION};
+use pd::genesis;
+use penumbra_chain::params::ChainParams;
+use penumbra_crypto::Amount;
+use penumbra_stake::{STAKING_TOKEN_ASSET_ID, STAKING_TOKEN_DENOM};
+use penumbra_wallet::{ClientState, ScanEvent, Wallet};
+use rand_core::OsRng;
+
+const EPOCH_DURATION: u64 = 4;
+const UNBONDING_EPOCHS: u64 = 1;
+const INITIAL_BALANCE: u64 = 1_000_000;
+const DELEGATION: u64 = 400_000;
+
+// Requires a scratch Postgres database; run with
+// `PD_TEST_DATABASE_URI=... cargo test -p pd -- --ignored`.
+#[tokio::test]
+#[ignore]
+async fn delegate_undelegate_and_release() -> Result<()> {
+    let chain_params = ChainParams {
+        chain_id: "penumbra-devnet".to_string(),
+        epoch_duration: EPOCH_DURATION,
+        unbonding_epochs: UNBONDING_EPOCHS,
+        ..Default::default()
+    };
+
+    let mut client = ClientState::new(Wallet::generate(OsRng));
+    *client.chain_params_mut() = Some(chain_params.clone());
+    let (_label, address) = client.wallet().address_by_index(0)?;
+
+    let mut devnet = Devnet::start(
+        chain_params,
+        vec![genesis::Allocation {
+            amount: INITIAL_BALANCE,
+            denom: STAKING_TOKEN_DENOM.to_string(),
+            address,
+        }],
+    )
+    .await?;
+    let identity_key = devnet.validator.identity_key.clone();
+    let delegation_token = identity_key.delegation_token();
+
+    devnet.sync(&mut client).await?;
+    assert_eq!(balance(&client, &STAKING_TOKEN_DENOM), INITIAL_BALANCE);
+
+    // Delegate part of the balance during the first epoch.
+    let delegate_rate = devnet.next_rate_data().await?;
+    assert_eq!(delegate_rate.epoch_index, 1);
+    let delegate = client.build_delegate(&mut OsRng, delegate_rate.clone(), DELEGATION, 0, None)?;
+    devnet.next_block(vec![delegate]).await?;
+
+    let delegation_amount = delegate_rate.delegation_amount(DELEGATION);
+    devnet.sync(&mut client).await?;
+    assert_eq!(
+        balance(&client, &STAKING_TOKEN_DENOM),
+        INITIAL_BALANCE - DELEGATION
+    );
+    assert_eq!(
+        balance(&client, &delegation_token.denom()),
+        delegation_amount
+    );
+
+    // Cross the first epoch boundary, which applies the delegation to the
+    // token supplies and publishes rates for the following epoch.
+    devnet.advance_to(EPOCH_DURATION - 1).await?;
+    let epoch_0_rate = devnet.rate_data(0).await?;
+    assert_eq!(
+        devnet.total_supply(*STAKING_TOKEN_ASSET_ID).await?,
+        INITIAL_BALANCE - epoch_0_rate.unbonded_amount(delegation_amount)
+    );
+    assert_eq!(
+        devnet.total_supply(delegation_token.id()).await?,
+        GENESIS_DELEGATION + delegation_amount
+    );
+
+    let undelegate_rate = devnet.next_rate_data().await?;
+    assert_eq!(undelegate_rate.epoch_index, 2);
+    assert!(undelegate_rate.validator_exchange_rate >= delegate_rate.validator_exchange_rate);
+
+    // Undelegate everything during the second epoch.
+    devnet.advance_to(EPOCH_DURATION + 1).await?;
+    devnet.sync(&mut client).await?;
+    let undelegate = client.build_undelegate(
+        &mut OsRng,
+        undelegate_rate.clone(),
+        delegation_amount,
+        0,
+        None,
+    )?;
+    devnet.next_block(vec![undelegate]).await?;
+    let undelegation_height = devnet.height;
+
+    let events = devnet.sync_events(&mut client).await?;
+    assert_eq!(balance(&client, &delegation_token.denom()), 0);
+
+    // The unbonded stake is quarantined until the end of the epoch containing
+    // the unbonding height, which the client reports when it sees the
+    // undelegation included.
+    let unbonding_height = undelegation_height + EPOCH_DURATION * UNBONDING_EPOCHS;
+    let release_height = (unbonding_height / EPOCH_DURATION + 1) * EPOCH_DURATION - 1;
+    let quarantined = events
+        .iter()
+        .filter_map(|event| match event {
+            ScanEvent::NoteQuarantined {
+                height,
+                note,
+                release_height,
+                ..
+            } => Some((*height, note.asset_id(), *release_height)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        quarantined,
+        vec![(undelegation_height, *STAKING_TOKEN_ASSET_ID, release_height)]
+    );
+    let schedule = devnet
+        .state
+        .quarantine_schedule(Some(&identity_key))
+        .await?;
+    assert_eq!(
+        schedule.keys().cloned().collect::<Vec<_>>(),
+        vec![(identity_key.clone(), unbonding_height)]
+    );
+
+    // The client predicted the same release height when it built the undelegation.
+    assert_eq!(
+        client
+            .unbonding_notes()
+            .map(|(release_height, _)| release_height)
+            .collect::<Vec<_>>(),
+        vec![release_height]
+    );
+
+    devnet.advance_to(release_height - 1).await?;
+    devnet.sync(&mut client).await?;
+    assert_eq!(
+        balance(&client, &STAKING_TOKEN_DENOM),
+        INITIAL_BALANCE - DELEGATION
+    );
+
+    devnet.advance_to(release_height).await?;
+    devnet.sync(&mut client).await?;
+    assert!(devnet
+        .state
+        .quarantine_schedule(Some(&identity_key))
+        .await?
+        .is_empty());
+    let unbonded_amount = undelegate_rate.unbonded_amount(delegation_amount);
+    assert_eq!(
+        balance(&client, &STAKING_TOKEN_DENOM),
+        INITIAL_BALANCE - DELEGATION + unbonded_amount
+    );
+    assert_eq!(client.unbonding_notes().count(), 0);
+
+    // The supplies recorded by the chain account for every token minted and
+    // burned over the delegation's lifetime.
+    let epoch_1_rate = devnet.rate_data(1).await?;
+    assert_eq!(
+        devnet.total_supply(delegation_token.id()).await?,
+        GENESIS_DELEGATION
+    );
+    assert_eq!(
+        devnet.total_supply(*STAKING_TOKEN_ASSET_ID).await?,
+        INITIAL_BALANCE - epoch_0_rate.unbonded_amount(delegation_amount)
+            + epoch_1_rate.unbonded_amount(delegation_amount)
+    );
+
+    // The supply history records the delegation token supply at the end of each epoch.
+    let history = devnet
+        .state
+        .supply_history(delegation_token.id(), 0, 1)
+        .await?
+        .into_iter()
+        .map(|supply| (supply.epoch_index, supply.total_supply.map(Amount::from)))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        history,
+        vec![
+            (
+                0,
+                Some(Amount::from(GENESIS_DELEGATION + delegation_amount))
+            ),
+            (1, Some(Amount::from(GENESIS_DELEGATION))),
+        ]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+//! Runs a four-validator testnet, and checks that it tolerates one validator
+//! going offline and that the validator catches up when it comes back.
+
+mod common;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use common::{balance, testnet::Testnet};
+use pd::genesis;
+use penumbra_chain::params::ChainParams;
+use penumbra_crypto::Value;
+use penumbra_stake::{STAKING_TOKEN_ASSET_ID, STAKING_TOKEN_DENOM};
+use penumbra_wallet::{ClientState, Wallet};
+use rand_core::OsRng;
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+// Requires a scratch Postgres server and a `tendermint` binary; run with
+// `PD_TEST_DATABASE_URI=... cargo test -p pd -- --ignored`.
+#[tokio::test]
+#[ignore]
+async fn tolerates_one_offline_validator() -> Result<()> {
+    let chain_params = ChainParams {
+        chain_id: "penumbra-testnet".to_string(),
+        epoch_duration: 10,
+        unbonding_epochs: 1,
+        ..Default::default()
+    };
+
+    let mut sender = ClientState::new(Wallet::generate(OsRng));
+    *sender.chain_params_mut() = Some(chain_params.clone());
+    let (_label, sender_address) = sender.wallet().address_by_index(0)?;
+
+    let mut receiver = ClientState::new(Wallet::generate(OsRng));
+    *receiver.chain_params_mut() = Some(chain_params.clone());
+    let (_label, receiver_address) = receiver.wallet().address_by_index(0)?;
+
+    let mut testnet = Testnet::start(
+        4,
+        chain_params,
+        vec![genesis::Allocation {
+            amount: 1_000_000,
+            denom: STAKING_TOKEN_DENOM.to_string(),
+            address: sender_address,
+        }],
+    )
+    .await?;
+    testnet.wait_for_height(3, TIMEOUT).await?;
+
+    // With three of four equally-weighted validators, the rest of the network
+    // still has enough voting power to make progress.
+    testnet.nodes[3].stop().await?;
+    let height = testnet.nodes[0].height().await?;
+
+    testnet.nodes[0].sync(&mut sender).await?;
+    let send = sender.build_send(
+        &mut OsRng,
+        &[Value {
+            amount: 1_000,
+            asset_id: *STAKING_TOKEN_ASSET_ID,
+        }],
+        Value {
+            amount: 0,
+            asset_id: *STAKING_TOKEN_ASSET_ID,
+        },
+        receiver_address,
+        None,
+        None,
+    )?;
+    testnet.nodes[1].submit(&send).await?;
+    testnet.wait_for_height(height + 5, TIMEOUT).await?;
+
+    // Once it's back, the offline validator catches up on the blocks it
+    // missed, including the transaction.
+    testnet.nodes[3].start()?;
+    let height = testnet.nodes[0].height().await?;
+    testnet.wait_for_height(height, TIMEOUT).await?;
+
+    testnet.nodes[3].sync(&mut receiver).await?;
+    assert_eq!(balance(&receiver, &STAKING_TOKEN_DENOM), 1_000);
+
+    Ok(())
+}
@@ -47,7 +47,17 @@ fn main() -> Result<()> {
     // For the client code, we also want to generate RPC instances, so compile via tonic:
     tonic_build::configure().compile_with_config(
         config,
-        &["proto/light_wallet.proto", "proto/thin_wallet.proto"],
+        &[
+            "proto/client.proto",
+            "proto/light_wallet.proto",
+            "proto/thin_wallet.proto",
+            "proto/admin.proto",
+            "proto/changefeed.proto",
+            "proto/broadcast.proto",
+            "proto/scanning.proto",
+            "proto/epoch_report.proto",
+            "proto/view.proto",
+        ],
         &["proto/"],
     )?;
 
@@ -57,6 +67,8 @@ fn main() -> Result<()> {
 static SERIALIZE: &str = r#"#[derive(::serde::Deserialize, ::serde::Serialize)]"#;
 /// Serializes newtype structs as if the inner field were serialized on its own.
 static SERDE_TRANSPARENT: &str = r#"#[serde(transparent)]"#;
+/// Deserializes a missing field as its default value.
+static SERDE_DEFAULT: &str = r#"#[serde(default)]"#;
 
 static AS_HEX: &str = r#"#[serde(with = "crate::serializers::hexstr")]"#;
 static AS_BASE64: &str = r#"#[serde(with = "crate::serializers::base64str")]"#;
@@ -69,16 +81,20 @@ static TYPE_ATTRIBUTES: &[(&str, &str)] = &[
     (".penumbra.stake.Validator", SERIALIZE),
     (".penumbra.stake.FundingStream", SERIALIZE),
     (".penumbra.stake.ValidatorDefinition", SERIALIZE),
+    (".penumbra.stake.ValidatorMigration", SERIALIZE),
     (".penumbra.stake.ValidatorStatus", SERIALIZE),
     (".penumbra.stake.ValidatorState", SERIALIZE),
     (".penumbra.stake.ValidatorStateName", SERIALIZE),
     (".penumbra.stake.ValidatorInfo", SERIALIZE),
     (".penumbra.stake.RateData", SERIALIZE),
     (".penumbra.stake.BaseRateData", SERIALIZE),
+    (".penumbra.stake.RewardSource", SERIALIZE),
     (".penumbra.stake.IdentityKey", SERIALIZE),
     (".penumbra.stake.IdentityKey", SERDE_TRANSPARENT),
     (".penumbra.stake.Delegate", SERIALIZE),
     (".penumbra.stake.Undelegate", SERIALIZE),
+    (".penumbra.stake.Redelegate", SERIALIZE),
+    (".penumbra.stake.RedelegateBody", SERIALIZE),
     (".penumbra.crypto.Address", SERIALIZE),
     (".penumbra.crypto.Address", SERDE_TRANSPARENT),
     (".penumbra.crypto.NoteCommitment", SERIALIZE),
@@ -88,9 +104,11 @@ static TYPE_ATTRIBUTES: &[(&str, &str)] = &[
     (".penumbra.crypto.Value", SERIALIZE),
     (".penumbra.crypto.Denom", SERIALIZE),
     (".penumbra.crypto.Denom", SERDE_TRANSPARENT),
+    (".penumbra.crypto.DenomMetadata", SERIALIZE),
     (".penumbra.crypto.MerkleRoot", SERIALIZE),
     (".penumbra.crypto.MerkleRoot", SERDE_TRANSPARENT),
     (".penumbra.chain.ChainParams", SERIALIZE),
+    (".penumbra.chain.FeeRate", SERIALIZE),
     (".penumbra.genesis.GenesisAppState", SERIALIZE),
     (".penumbra.genesis.Allocation", SERIALIZE),
     (".penumbra.genesis.ValidatorPower", SERIALIZE),
@@ -101,9 +119,58 @@ static FIELD_ATTRIBUTES: &[(&str, &str)] = &[
     // the format is the same as the Tendermint json config files.
     (".penumbra.stake.Validator.consensus_key", AS_BASE64),
     (".penumbra.stake.ValidatorDefinition.auth_sig", AS_HEX),
+    (".penumbra.stake.ValidatorMigration.old_auth_sig", AS_HEX),
+    (".penumbra.stake.ValidatorMigration.new_auth_sig", AS_HEX),
+    (".penumbra.stake.Redelegate.delegator_sig", AS_HEX),
+    (".penumbra.stake.RedelegateBody.delegator_key", AS_HEX),
     (".penumbra.stake.IdentityKey.ik", AS_BECH32_IDENTITY_KEY),
+    // Funding streams to the community pool have no address, and funding
+    // streams from before the community pool existed don't mention it.
+    (".penumbra.stake.FundingStream.address", SERDE_DEFAULT),
+    (".penumbra.stake.FundingStream.to_community_pool", SERDE_DEFAULT),
     (".penumbra.crypto.Address.inner", AS_BECH32_ADDRESS),
     (".penumbra.crypto.AssetId.inner", AS_BECH32_ASSET_ID),
     (".penumbra.crypto.NoteCommitment.inner", AS_HEX),
     (".penumbra.crypto.MerkleRoot.inner", AS_HEX),
+    // Genesis files may predate these chain parameters.
+    (".penumbra.chain.ChainParams.max_block_bytes", SERDE_DEFAULT),
+    (".penumbra.chain.ChainParams.max_block_gas", SERDE_DEFAULT),
+    (
+        ".penumbra.chain.ChainParams.max_evidence_bytes",
+        SERDE_DEFAULT,
+    ),
+    (".penumbra.chain.ChainParams.proof_version", SERDE_DEFAULT),
+    (
+        ".penumbra.chain.ChainParams.proof_version_height",
+        SERDE_DEFAULT,
+    ),
+    (
+        ".penumbra.chain.ChainParams.max_outputs_per_transaction",
+        SERDE_DEFAULT,
+    ),
+    (".penumbra.chain.ChainParams.fee_rates", SERDE_DEFAULT),
+    (
+        ".penumbra.chain.ChainParams.proposer_reward_per_transaction",
+        SERDE_DEFAULT,
+    ),
+    (
+        ".penumbra.chain.ChainParams.inbound_denom_allowlist",
+        SERDE_DEFAULT,
+    ),
+    (
+        ".penumbra.chain.ChainParams.inbound_denom_denylist",
+        SERDE_DEFAULT,
+    ),
+    (
+        ".penumbra.chain.ChainParams.denom_metadata_authority",
+        SERDE_DEFAULT,
+    ),
+    (
+        ".penumbra.chain.ChainParams.denom_metadata_authority",
+        AS_HEX,
+    ),
+    (
+        ".penumbra.chain.ChainParams.emergency_halt_height",
+        SERDE_DEFAULT,
+    ),
 ];
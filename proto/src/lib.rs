@@ -48,6 +48,16 @@ pub mod genesis {
     tonic::include_proto!("penumbra.genesis");
 }
 
+/// Client protocol services.
+///
+/// The services are versioned, so that later versions can change them without breaking older
+/// clients, and reuse the messages of the unversioned modules.
+pub mod client {
+    pub mod v1alpha1 {
+        tonic::include_proto!("penumbra.client.v1alpha1");
+    }
+}
+
 /// Light wallet protocol structures.
 pub mod light_wallet {
     tonic::include_proto!("penumbra.light_wallet");
@@ -58,12 +68,45 @@ pub mod thin_wallet {
     tonic::include_proto!("penumbra.thin_wallet");
 }
 
+/// Node administration protocol structures.
+pub mod admin {
+    tonic::include_proto!("penumbra.admin");
+}
+
+/// State changefeed protocol structures.
+pub mod changefeed {
+    tonic::include_proto!("penumbra.changefeed");
+}
+
+/// Transaction broadcast protocol structures.
+pub mod broadcast {
+    tonic::include_proto!("penumbra.broadcast");
+}
+
+/// Delegated scanning protocol structures.
+pub mod scanning {
+    tonic::include_proto!("penumbra.scanning");
+}
+
+/// Epoch report protocol structures.
+pub mod epoch_report {
+    tonic::include_proto!("penumbra.epoch_report");
+}
+
+/// Wallet view protocol structures.
+pub mod view {
+    tonic::include_proto!("penumbra.view");
+}
+
 pub mod sighash {
     include!(concat!(env!("OUT_DIR"), "/penumbra.sighash.rs"));
 
     use sig_hash_action::Action as SHAction;
 
-    use super::transaction::{action::Action as TxAction, Spend};
+    use super::{
+        stake::Redelegate,
+        transaction::{action::Action as TxAction, RegisterDenomMetadata, Spend},
+    };
 
     impl From<super::transaction::Action> for SigHashAction {
         fn from(action: super::transaction::Action) -> Self {
@@ -73,12 +116,25 @@ pub mod sighash {
                 Some(TxAction::Delegate(d)) => Some(SHAction::Delegate(d)),
                 Some(TxAction::Undelegate(d)) => Some(SHAction::Undelegate(d)),
                 Some(TxAction::ValidatorDefinition(d)) => Some(SHAction::ValidatorDefinition(d)),
-                // Collapse spends to spend bodies
+                Some(TxAction::ValidatorMigration(m)) => Some(SHAction::ValidatorMigration(m)),
+                // Collapse spends, redelegations and metadata registrations to their bodies
+                Some(TxAction::Redelegate(Redelegate { body: None, .. })) => None,
+                Some(TxAction::Redelegate(Redelegate {
+                    body: Some(redelegate_body),
+                    ..
+                })) => Some(SHAction::Redelegate(redelegate_body)),
                 Some(TxAction::Spend(Spend { body: None, .. })) => None,
                 Some(TxAction::Spend(Spend {
                     body: Some(spend_body),
                     ..
                 })) => Some(SHAction::Spend(spend_body)),
+                Some(TxAction::RegisterDenomMetadata(RegisterDenomMetadata {
+                    body: None, ..
+                })) => None,
+                Some(TxAction::RegisterDenomMetadata(RegisterDenomMetadata {
+                    body: Some(register_body),
+                    ..
+                })) => Some(SHAction::RegisterDenomMetadata(register_body)),
                 None => None,
             };
             Self { action }
@@ -1,37 +1,56 @@
-use penumbra_crypto::Address;
+use penumbra_crypto::{Address, Amount};
 use penumbra_proto::{stake as pb, Protobuf};
 use serde::{Deserialize, Serialize};
 
+use crate::Rate;
+
 /// A destination for a portion of a validator's commission of staking rewards.
 #[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
 #[serde(try_from = "pb::FundingStream", into = "pb::FundingStream")]
 pub struct FundingStream {
-    /// The destinatination address for the funding stream..
-    pub address: Address,
+    /// Where the funding stream's portion of the reward goes.
+    pub recipient: Recipient,
 
     /// The portion (in terms of [basis points](https://en.wikipedia.org/wiki/Basis_point)) of the
     /// validator's total staking reward that goes to this funding stream.
     pub rate_bps: u16,
 }
 
+/// The recipient of a [`FundingStream`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Recipient {
+    /// Rewards are paid as notes to this address.
+    Address(Address),
+    /// Rewards are deposited into the community pool, which belongs to the chain rather than to
+    /// any address.
+    CommunityPool,
+}
+
 impl FundingStream {
     /// Computes the amount of reward at the epoch specified by base_rate_data
+    ///
+    /// The reward is the stream's share of the delegation tokens, at the base reward rate, valued
+    /// at the previous epoch's base exchange rate, rounding down after each multiplication in that
+    /// order.
+    ///
+    /// The reward is paid as a single note, so this returns an error if it doesn't fit in 64 bits.
     pub fn reward_amount(
         &self,
-        total_delegation_tokens: u64,
+        total_delegation_tokens: Amount,
         base_rate_data: &crate::BaseRateData,
         prev_epoch_rate_data: &crate::BaseRateData,
-    ) -> u64 {
+    ) -> anyhow::Result<u64> {
         if prev_epoch_rate_data.epoch_index != base_rate_data.epoch_index - 1 {
             panic!("wrong base rate data for previous epoch")
         }
         // take yv*cve*re*psi(e-1)
-        let mut r =
-            (total_delegation_tokens as u128 * (self.rate_bps as u128 * 1_0000)) / 1_0000_0000;
-        r = (r * base_rate_data.base_reward_rate as u128) / 1_0000_0000;
-        r = (r * prev_epoch_rate_data.base_exchange_rate as u128) / 1_0000_0000;
+        let reward = Rate::from_bps(self.rate_bps.into())
+            .and_then(|rate| rate.apply(total_delegation_tokens.value()))
+            .and_then(|r| Rate::from_raw(base_rate_data.base_reward_rate).apply(r))
+            .and_then(|r| Rate::from_raw(prev_epoch_rate_data.base_exchange_rate).apply(r))
+            .ok_or_else(|| anyhow::anyhow!("funding stream reward overflow"))?;
 
-        r as u64
+        u64::try_from(Amount::from(reward))
     }
 }
 
@@ -39,9 +58,14 @@ impl Protobuf<pb::FundingStream> for FundingStream {}
 
 impl From<FundingStream> for pb::FundingStream {
     fn from(fs: FundingStream) -> Self {
+        let (address, to_community_pool) = match fs.recipient {
+            Recipient::Address(address) => (address.to_string(), false),
+            Recipient::CommunityPool => (String::new(), true),
+        };
         pb::FundingStream {
-            address: fs.address.to_string(),
+            address,
             rate_bps: fs.rate_bps as u32,
+            to_community_pool,
         }
     }
 }
@@ -59,8 +83,20 @@ impl TryFrom<pb::FundingStream> for FundingStream {
             ));
         };
 
+        let recipient = if fs.to_community_pool {
+            if !fs.address.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "funding stream to the community pool has an address {}",
+                    fs.address
+                ));
+            }
+            Recipient::CommunityPool
+        } else {
+            Recipient::Address(fs.address.parse()?)
+        };
+
         Ok(FundingStream {
-            address: fs.address.parse()?,
+            recipient,
             rate_bps,
         })
     }
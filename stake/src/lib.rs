@@ -7,7 +7,10 @@ mod epoch;
 mod funding_stream;
 mod identity_key;
 mod info;
+mod migration;
 mod rate;
+mod redelegate;
+mod reward_source;
 mod status;
 mod token;
 mod undelegate;
@@ -15,15 +18,22 @@ mod validator;
 
 pub use delegate::Delegate;
 pub use epoch::Epoch;
-pub use funding_stream::FundingStream;
+pub use funding_stream::{FundingStream, Recipient as FundingStreamRecipient};
 pub use identity_key::IdentityKey;
 pub use info::ValidatorInfo;
-pub use rate::{BaseRateData, RateData, RateDataById};
+pub use migration::ValidatorMigration;
+pub use rate::{BaseRateData, Rate, RateData, RateDataById};
+pub use redelegate::{Body as RedelegateBody, Redelegate};
+pub use reward_source::RewardSource;
 pub use status::{ValidatorState, ValidatorStateName, ValidatorStatus};
 pub use token::DelegationToken;
 pub use undelegate::Undelegate;
 pub use validator::{FundingStreams, Validator, ValidatorDefinition};
 
+/// The penalty applied to the exchange rate of a slashed validator's delegation tokens, in basis
+/// points.
+pub const SLASHING_PENALTY_BPS: u64 = 1000;
+
 /// The Bech32 prefix used for validator consensus pubkeys.
 pub const VALIDATOR_CONSENSUS_BECH32_PREFIX: &str = "penumbravalconspub";
 
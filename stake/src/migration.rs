@@ -0,0 +1,126 @@
+use penumbra_crypto::rdsa::{Signature, SpendAuth};
+use penumbra_proto::{stake as pb, Message, Protobuf};
+use serde::{Deserialize, Serialize};
+
+use crate::IdentityKey;
+
+/// A transaction action rotating a validator's identity key.
+///
+/// The migration takes effect at the end of the epoch in which it is
+/// included: from the next epoch on, the validator is known by its new
+/// identity key, with the same exchange rate as before.  Delegation tokens
+/// for the old identity key remain valid, and can be undelegated at the new
+/// identity key's rates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "pb::ValidatorMigration", into = "pb::ValidatorMigration")]
+pub struct ValidatorMigration {
+    /// The validator's current identity key.
+    pub old_identity_key: IdentityKey,
+    /// The identity key the validator is migrating to.
+    pub new_identity_key: IdentityKey,
+    /// A signature by the old identity key over [`ValidatorMigration::auth_message`].
+    pub old_auth_sig: Signature<SpendAuth>,
+    /// A signature by the new identity key over [`ValidatorMigration::auth_message`].
+    pub new_auth_sig: Signature<SpendAuth>,
+}
+
+impl ValidatorMigration {
+    /// The message both identity keys must sign to authorize a migration from
+    /// `old_identity_key` to `new_identity_key`.
+    ///
+    /// Requiring a signature from the new key, as well as the old one, ensures
+    /// that the validator actually controls the key it is migrating to.
+    pub fn auth_message(old_identity_key: &IdentityKey, new_identity_key: &IdentityKey) -> Vec<u8> {
+        pb::ValidatorMigration {
+            old_identity_key: Some(old_identity_key.clone().into()),
+            new_identity_key: Some(new_identity_key.clone().into()),
+            old_auth_sig: Vec::new(),
+            new_auth_sig: Vec::new(),
+        }
+        .encode_to_vec()
+    }
+
+    /// Checks that the migration is well-formed and authorized by both identity keys.
+    pub fn verify(&self) -> anyhow::Result<()> {
+        if self.old_identity_key == self.new_identity_key {
+            return Err(anyhow::anyhow!(
+                "validator migration must change the identity key"
+            ));
+        }
+
+        let message = Self::auth_message(&self.old_identity_key, &self.new_identity_key);
+        self.old_identity_key
+            .0
+            .verify(&message, &self.old_auth_sig)
+            .map_err(|_| anyhow::anyhow!("invalid signature by old identity key"))?;
+        self.new_identity_key
+            .0
+            .verify(&message, &self.new_auth_sig)
+            .map_err(|_| anyhow::anyhow!("invalid signature by new identity key"))?;
+
+        Ok(())
+    }
+}
+
+impl Protobuf<pb::ValidatorMigration> for ValidatorMigration {}
+
+impl From<ValidatorMigration> for pb::ValidatorMigration {
+    fn from(m: ValidatorMigration) -> Self {
+        pb::ValidatorMigration {
+            old_identity_key: Some(m.old_identity_key.into()),
+            new_identity_key: Some(m.new_identity_key.into()),
+            old_auth_sig: m.old_auth_sig.to_bytes().to_vec(),
+            new_auth_sig: m.new_auth_sig.to_bytes().to_vec(),
+        }
+    }
+}
+
+impl TryFrom<pb::ValidatorMigration> for ValidatorMigration {
+    type Error = anyhow::Error;
+    fn try_from(m: pb::ValidatorMigration) -> Result<Self, Self::Error> {
+        Ok(Self {
+            old_identity_key: m
+                .old_identity_key
+                .ok_or_else(|| anyhow::anyhow!("missing old identity key"))?
+                .try_into()?,
+            new_identity_key: m
+                .new_identity_key
+                .ok_or_else(|| anyhow::anyhow!("missing new identity key"))?
+                .try_into()?,
+            old_auth_sig: m.old_auth_sig.as_slice().try_into()?,
+            new_auth_sig: m.new_auth_sig.as_slice().try_into()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::rdsa::SigningKey;
+    use rand_core::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn migration_requires_both_signatures() {
+        let old_sk = SigningKey::<SpendAuth>::new(OsRng);
+        let new_sk = SigningKey::<SpendAuth>::new(OsRng);
+        let old_identity_key = IdentityKey(old_sk.into());
+        let new_identity_key = IdentityKey(new_sk.into());
+
+        let message = ValidatorMigration::auth_message(&old_identity_key, &new_identity_key);
+        let migration = ValidatorMigration {
+            old_identity_key: old_identity_key.clone(),
+            new_identity_key: new_identity_key.clone(),
+            old_auth_sig: old_sk.sign(OsRng, &message),
+            new_auth_sig: new_sk.sign(OsRng, &message),
+        };
+        assert!(migration.verify().is_ok());
+
+        // A migration signed only by the old key must be rejected.
+        let unauthorized = ValidatorMigration {
+            new_auth_sig: old_sk.sign(OsRng, &message),
+            ..migration
+        };
+        assert!(unauthorized.verify().is_err());
+    }
+}
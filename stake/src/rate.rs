@@ -1,5 +1,6 @@
 use std::collections::BTreeMap;
 
+use penumbra_crypto::Amount;
 use penumbra_proto::{
     stake::{self as pb},
     Protobuf,
@@ -8,6 +9,10 @@ use serde::{Deserialize, Serialize};
 
 use crate::{FundingStream, IdentityKey};
 
+mod fixed;
+
+pub use fixed::Rate;
+
 pub type RateDataById = BTreeMap<IdentityKey, RateData>;
 
 /// Describes a validator's reward rate and voting power in some epoch.
@@ -18,20 +23,28 @@ pub struct RateData {
     pub identity_key: IdentityKey,
     /// The index of the epoch for which this rate is valid.
     pub epoch_index: u64,
-    /// The validator-specific reward rate.
+    /// The validator-specific reward rate, as a raw [`Rate`].
     pub validator_reward_rate: u64,
-    /// The validator-specific exchange rate.
+    /// The validator-specific exchange rate, as a raw [`Rate`].
     pub validator_exchange_rate: u64,
 }
 
 impl RateData {
     /// Compute the validator rate data for the epoch following the current one.
+    ///
+    /// The validator's reward rate is the base reward rate less the validator's commission,
+    /// rounded down, and its exchange rate grows by the reward rate for the current epoch, also
+    /// rounded down.
+    ///
+    /// # Panics
+    ///
+    /// If the next exchange rate doesn't fit in a [`Rate`].
     pub fn next(
         &self,
         base_rate_data: &BaseRateData,
         funding_streams: &[FundingStream],
     ) -> RateData {
-        // compute the validator's total commissio
+        // compute the validator's total commission
         let commission_rate_bps = funding_streams
             .iter()
             .fold(0u64, |total, stream| total + stream.rate_bps as u64);
@@ -42,25 +55,65 @@ impl RateData {
             // streams
             panic!("commission rate sums to > 100%")
         }
+        let commission_rate = Rate::from_bps(commission_rate_bps).expect("at most 100%");
 
         // compute next validator reward rate
-        // 1 bps = 1e-4, so here we group digits by 4s rather than 3s as is usual
-        let validator_reward_rate = ((1_0000_0000u64 - (commission_rate_bps * 1_0000))
-            * base_rate_data.base_reward_rate)
-            / 1_0000_0000;
+        let validator_reward_rate = Rate::ONE
+            .checked_sub(commission_rate)
+            .and_then(|rate| rate.checked_mul(Rate::from_raw(base_rate_data.base_reward_rate)))
+            .expect("the reward rate is at most the base reward rate");
 
         // compute validator exchange rate
-        let validator_exchange_rate = (self.validator_exchange_rate
-            * (self.validator_reward_rate + 1_0000_0000))
-            / 1_0000_0000;
+        let validator_exchange_rate = Rate::ONE
+            .checked_add(Rate::from_raw(self.validator_reward_rate))
+            .and_then(|growth| Rate::from_raw(self.validator_exchange_rate).checked_mul(growth))
+            .expect("validator exchange rate overflow");
 
         RateData {
             identity_key: self.identity_key.clone(),
             epoch_index: self.epoch_index + 1,
-            validator_reward_rate,
+            validator_reward_rate: validator_reward_rate.raw(),
+            validator_exchange_rate: validator_exchange_rate.raw(),
+        }
+    }
+    /// Computes the validator rate data after applying a slashing penalty of `penalty_bps` basis
+    /// points, effective for the same epoch.
+    ///
+    /// The penalty is socialized among all existing delegation tokens by reducing the exchange
+    /// rate, leaving the token supply unchanged.  The reduced exchange rate is rounded down, so
+    /// that slashing can never create stake: every delegator loses at least the announced penalty,
+    /// and at most one unit of exchange rate (1e-8 of the staking token per delegation token)
+    /// more than it.
+    pub fn slash(&self, penalty_bps: u64) -> RateData {
+        self.slash_as_of(self, penalty_bps)
+    }
+
+    /// Computes the validator rate data after applying a slashing penalty of `penalty_bps` basis
+    /// points for an infraction committed while `infraction_rate` was in effect.
+    ///
+    /// The penalty is a fraction of the exchange rate at the time of the infraction, not of the
+    /// current one, so that rewards accrued since the infraction are not penalized.  It is deducted
+    /// from this rate data's exchange rate, rounding the deduction up as in [`RateData::slash`].
+    pub fn slash_as_of(&self, infraction_rate: &RateData, penalty_bps: u64) -> RateData {
+        if penalty_bps > 1_0000 {
+            // we should never hit this branch: the slashing penalty is a protocol constant
+            panic!("slashing penalty is > 100%")
+        }
+
+        // Round the penalty up; it is at most the infraction exchange rate, so it fits in 64 bits.
+        let penalty = Rate::from_bps(penalty_bps)
+            .and_then(|penalty| penalty.apply_ceil(infraction_rate.validator_exchange_rate as u128))
+            .expect("the penalty is at most 100%") as u64;
+        let validator_exchange_rate = self.validator_exchange_rate.saturating_sub(penalty);
+
+        RateData {
+            identity_key: self.identity_key.clone(),
+            epoch_index: self.epoch_index,
+            validator_reward_rate: self.validator_reward_rate,
             validator_exchange_rate,
         }
     }
+
     /// Computes the amount of delegation tokens corresponding to the given amount of unbonded stake.
     ///
     /// # Warning
@@ -73,13 +126,17 @@ impl RateData {
     /// ```rust,ignore
     /// unbonded_amount == rate_data.unbonded_amount(delegation_amount)
     /// ```
-    /// but in general *not both*, because the computation involves rounding.
+    /// but in general *not both*, because the computation involves rounding.  The amount of
+    /// delegation tokens is rounded down, so that delegating never creates stake.
+    ///
+    /// # Panics
+    ///
+    /// If the exchange rate is zero, or the result doesn't fit in 64 bits.
     pub fn delegation_amount(&self, unbonded_amount: u64) -> u64 {
-        // validator_exchange_rate fits in 32 bits, but unbonded_amount is 64-bit;
-        // upconvert to u128 intermediates and panic if the result is too large (unlikely)
-        ((unbonded_amount as u128 * 1_0000_0000) / self.validator_exchange_rate as u128)
-            .try_into()
-            .unwrap()
+        Rate::from_raw(self.validator_exchange_rate)
+            .divide(unbonded_amount as u128)
+            .and_then(|amount| amount.try_into().ok())
+            .expect("delegation amount overflow")
     }
 
     /// Computes the amount of unbonded stake corresponding to the given amount of delegation tokens.
@@ -94,22 +151,43 @@ impl RateData {
     /// ```rust,ignore
     /// unbonded_amount == rate_data.unbonded_amount(delegation_amount)
     /// ```
-    /// but in general *not both*, because the computation involves rounding.
+    /// but in general *not both*, because the computation involves rounding.  The unbonded amount
+    /// is rounded down, so that undelegating never creates stake.
+    ///
+    /// # Panics
+    ///
+    /// If the result doesn't fit in 64 bits.
     pub fn unbonded_amount(&self, delegation_amount: u64) -> u64 {
-        // validator_exchange_rate fits in 32 bits, but unbonded_amount is 64-bit;
-        // upconvert to u128 intermediates and panic if the result is too large (unlikely)
-        ((delegation_amount as u128 * self.validator_exchange_rate as u128) / 1_0000_0000)
-            .try_into()
-            .unwrap()
+        Rate::from_raw(self.validator_exchange_rate)
+            .apply(delegation_amount as u128)
+            .and_then(|amount| amount.try_into().ok())
+            .expect("unbonded amount overflow")
+    }
+
+    /// Computes the staking rewards accrued by the given amount of delegation tokens between the
+    /// epoch of `earlier` and the epoch of this rate data, in units of the staking token.
+    pub fn rewards_accrued_since(&self, earlier: &RateData, delegation_amount: u64) -> u64 {
+        self.unbonded_amount(delegation_amount)
+            .saturating_sub(earlier.unbonded_amount(delegation_amount))
     }
 
     /// Computes the validator's voting power at this epoch given the total supply of the
     /// validator's delegation tokens.
-    pub fn voting_power(&self, total_delegation_tokens: u64, base_rate_data: &BaseRateData) -> u64 {
-        ((total_delegation_tokens as u128 * self.validator_exchange_rate as u128)
-            / base_rate_data.base_exchange_rate as u128)
-            .try_into()
-            .unwrap()
+    ///
+    /// The voting power is the value of the delegation tokens in units of the base exchange rate,
+    /// rounded down.  Returns an error if it doesn't fit in 64 bits.
+    pub fn voting_power(
+        &self,
+        total_delegation_tokens: Amount,
+        base_rate_data: &BaseRateData,
+    ) -> anyhow::Result<u64> {
+        let voting_power = Rate::from_raw(self.validator_exchange_rate)
+            .apply_ratio(
+                Rate::from_raw(base_rate_data.base_exchange_rate),
+                total_delegation_tokens.value(),
+            )
+            .ok_or_else(|| anyhow::anyhow!("voting power overflow"))?;
+        u64::try_from(Amount::from(voting_power))
     }
 }
 
@@ -119,20 +197,28 @@ impl RateData {
 pub struct BaseRateData {
     /// The index of the epoch for which this rate is valid.
     pub epoch_index: u64,
-    /// The base reward rate.
+    /// The base reward rate, as a raw [`Rate`].
     pub base_reward_rate: u64,
-    /// The base exchange rate.
+    /// The base exchange rate, as a raw [`Rate`].
     pub base_exchange_rate: u64,
 }
 
 impl BaseRateData {
     /// Compute the base rate data for the epoch following the current one,
     /// given the next epoch's base reward rate.
+    ///
+    /// The base exchange rate grows by the next base reward rate, rounded down.
+    ///
+    /// # Panics
+    ///
+    /// If the next base exchange rate doesn't fit in a [`Rate`].
     pub fn next(&self, base_reward_rate: u64) -> BaseRateData {
-        let base_exchange_rate =
-            (self.base_exchange_rate * (base_reward_rate + 1_0000_0000)) / 1_0000_0000;
+        let base_exchange_rate = Rate::ONE
+            .checked_add(Rate::from_raw(base_reward_rate))
+            .and_then(|growth| Rate::from_raw(self.base_exchange_rate).checked_mul(growth))
+            .expect("base exchange rate overflow");
         BaseRateData {
-            base_exchange_rate,
+            base_exchange_rate: base_exchange_rate.raw(),
             base_reward_rate,
             epoch_index: self.epoch_index + 1,
         }
@@ -189,3 +275,168 @@ impl TryFrom<pb::BaseRateData> for BaseRateData {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::{
+        keys::SpendKey,
+        rdsa::{SigningKey, SpendAuth},
+        Address,
+    };
+    use proptest::prelude::*;
+    use rand_core::OsRng;
+
+    use super::*;
+    use crate::FundingStreamRecipient;
+
+    fn rate_data(epoch_index: u64, validator_exchange_rate: u64) -> RateData {
+        RateData {
+            identity_key: IdentityKey(SigningKey::<SpendAuth>::new(OsRng).into()),
+            epoch_index,
+            validator_reward_rate: 3_0000,
+            validator_exchange_rate,
+        }
+    }
+
+    fn address() -> Address {
+        SpendKey::generate(OsRng)
+            .full_viewing_key()
+            .incoming()
+            .payment_address(0u64.into())
+            .0
+    }
+
+    #[test]
+    fn slashing_reduces_value_by_penalty() {
+        let pre_slash = rate_data(3, 1_0000_0000);
+        let post_slash = pre_slash.slash(1000);
+
+        assert_eq!(post_slash.epoch_index, pre_slash.epoch_index);
+        assert_eq!(post_slash.validator_exchange_rate, 9000_0000);
+        assert_eq!(pre_slash.unbonded_amount(1_000_000), 1_000_000);
+        assert_eq!(post_slash.unbonded_amount(1_000_000), 900_000);
+    }
+
+    #[test]
+    fn slashing_rounds_down() {
+        // 1_0000_0007 * 0.9 = 9000_0006.3
+        let pre_slash = rate_data(1, 1_0000_0007);
+        let post_slash = pre_slash.slash(1000);
+        assert_eq!(post_slash.validator_exchange_rate, 9000_0006);
+
+        // A delegator never loses less than the announced penalty.
+        let delegation_amount = 123_456_789;
+        let loss = pre_slash.unbonded_amount(delegation_amount)
+            - post_slash.unbonded_amount(delegation_amount);
+        assert!(loss * 1_0000 >= pre_slash.unbonded_amount(delegation_amount) * 1000);
+    }
+
+    #[test]
+    fn deferred_slashing_uses_infraction_rate() {
+        let infraction_rate = rate_data(2, 1_0000_0000);
+        let current_rate = RateData {
+            epoch_index: 5,
+            validator_exchange_rate: 1_0010_0000,
+            ..infraction_rate.clone()
+        };
+
+        // The penalty is 10% of the infraction epoch's exchange rate, so the rewards accrued
+        // since then are kept.
+        let post_slash = current_rate.slash_as_of(&infraction_rate, 1000);
+        assert_eq!(post_slash.epoch_index, 5);
+        assert_eq!(post_slash.validator_exchange_rate, 9010_0000);
+
+        // Slashing as of the current epoch is the same as slashing the current rate.
+        assert_eq!(
+            current_rate.slash_as_of(&current_rate, 1000),
+            current_rate.slash(1000)
+        );
+    }
+
+    #[test]
+    fn slashed_rate_carries_into_later_epochs() {
+        let base_rate_data = BaseRateData {
+            epoch_index: 2,
+            base_reward_rate: 3_0000,
+            base_exchange_rate: 1_0000_0000,
+        };
+
+        let pre_slash = rate_data(2, 1_0000_0000);
+        let post_slash = pre_slash.slash(1000);
+
+        // Rewards in the following epoch accrue on top of the slashed exchange rate.
+        let next = post_slash.next(&base_rate_data, &[]);
+        assert_eq!(next.epoch_index, 3);
+        assert_eq!(
+            next.validator_exchange_rate,
+            pre_slash.next(&base_rate_data, &[]).validator_exchange_rate * 9 / 10
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn next_rates_follow_commission_and_never_shrink(
+            base_reward_rate in 0u64..1_0000_0000,
+            validator_reward_rate in 0u64..1_0000_0000,
+            validator_exchange_rate in 1u64..1_0000_0000_0000,
+            commission_bps in 0u16..=1_0000,
+        ) {
+            let base_rate_data = BaseRateData {
+                epoch_index: 0,
+                base_reward_rate,
+                base_exchange_rate: 1_0000_0000,
+            };
+            let current = RateData {
+                validator_reward_rate,
+                ..rate_data(0, validator_exchange_rate)
+            };
+            let funding_streams = [FundingStream {
+                recipient: FundingStreamRecipient::Address(address()),
+                rate_bps: commission_bps,
+            }];
+            let next = current.next(&base_rate_data, &funding_streams);
+
+            prop_assert_eq!(next.epoch_index, 1);
+            // The commission comes out of the base reward rate, rounded down.
+            let exact = (1_0000 - commission_bps as u128) * base_reward_rate as u128;
+            prop_assert_eq!(next.validator_reward_rate as u128, exact / 1_0000);
+            // The exchange rate grows by the current reward rate, rounded down.
+            prop_assert!(next.validator_exchange_rate >= validator_exchange_rate);
+            let exact =
+                validator_exchange_rate as u128 * (1_0000_0000 + validator_reward_rate as u128);
+            prop_assert_eq!(next.validator_exchange_rate as u128, exact / 1_0000_0000);
+        }
+
+        #[test]
+        fn delegating_and_undelegating_never_creates_stake(
+            validator_exchange_rate in 1_0000u64..1_0000_0000_0000,
+            unbonded_amount in 0u64..1 << 48,
+        ) {
+            let rate = rate_data(0, validator_exchange_rate);
+            let delegation_amount = rate.delegation_amount(unbonded_amount);
+            prop_assert!(rate.unbonded_amount(delegation_amount) <= unbonded_amount);
+        }
+
+        #[test]
+        fn voting_power_is_value_at_base_rate(
+            validator_exchange_rate in 1u64..1_0000_0000_0000,
+            base_exchange_rate in 1u64..1_0000_0000_0000,
+            supply: u64,
+        ) {
+            let base_rate_data = BaseRateData {
+                epoch_index: 0,
+                base_reward_rate: 0,
+                base_exchange_rate,
+            };
+            let exact = supply as u128 * validator_exchange_rate as u128;
+            let rate = rate_data(0, validator_exchange_rate);
+            match rate.voting_power(supply.into(), &base_rate_data) {
+                Ok(power) => {
+                    prop_assert!(power as u128 * base_exchange_rate as u128 <= exact);
+                    prop_assert!(exact < (power as u128 + 1) * base_exchange_rate as u128);
+                }
+                Err(_) => prop_assert!(exact / base_exchange_rate as u128 > u64::MAX as u128),
+            }
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Fixed-point arithmetic for reward and exchange rates.
+//!
+//! Every node, and every client building a delegation or undelegation, must compute exactly the
+//! same rates and amounts, so each operation here states how it rounds, and computes its
+//! intermediates in 128 bits so that nothing overflows before the result is rounded.
+
+/// A non-negative rate, as a fixed-point number with eight decimal places: the rate `r` is
+/// represented by the integer `r * 10^8`.
+///
+/// One basis point (`1e-4`) is `1_0000` units, so digits are usually grouped by fours rather
+/// than threes: `1_0000_0000` is `1.0`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Rate(u64);
+
+impl Rate {
+    /// The number of units in `1.0`.
+    pub const SCALE: u64 = 1_0000_0000;
+    pub const ZERO: Rate = Rate(0);
+    pub const ONE: Rate = Rate(Self::SCALE);
+
+    /// The rate represented by `raw` units of `1e-8`, as stored in rate data.
+    pub const fn from_raw(raw: u64) -> Rate {
+        Rate(raw)
+    }
+
+    /// The number of units of `1e-8` in this rate.
+    pub const fn raw(self) -> u64 {
+        self.0
+    }
+
+    /// The rate of `bps` basis points, if it is representable.
+    pub fn from_bps(bps: u64) -> Option<Rate> {
+        bps.checked_mul(1_0000).map(Rate)
+    }
+
+    pub fn checked_add(self, other: Rate) -> Option<Rate> {
+        self.0.checked_add(other.0).map(Rate)
+    }
+
+    pub fn checked_sub(self, other: Rate) -> Option<Rate> {
+        self.0.checked_sub(other.0).map(Rate)
+    }
+
+    /// Multiplies two rates, rounding down.
+    pub fn checked_mul(self, other: Rate) -> Option<Rate> {
+        (self.0 as u128 * other.0 as u128 / Self::SCALE as u128)
+            .try_into()
+            .ok()
+            .map(Rate)
+    }
+
+    /// Computes `amount * self`, rounding down.
+    pub fn apply(self, amount: u128) -> Option<u128> {
+        amount
+            .checked_mul(self.0 as u128)
+            .map(|product| product / Self::SCALE as u128)
+    }
+
+    /// Computes `amount * self`, rounding up.
+    pub fn apply_ceil(self, amount: u128) -> Option<u128> {
+        let product = amount.checked_mul(self.0 as u128)?;
+        let scale = Self::SCALE as u128;
+        Some(product / scale + (product % scale != 0) as u128)
+    }
+
+    /// Computes `amount / self`, rounding down, or `None` if this rate is zero.
+    pub fn divide(self, amount: u128) -> Option<u128> {
+        amount
+            .checked_mul(Self::SCALE as u128)?
+            .checked_div(self.0 as u128)
+    }
+
+    /// Computes `amount * self / denominator`, rounding down once, rather than once for each of
+    /// the multiplication and the division.  Returns `None` if `denominator` is zero.
+    pub fn apply_ratio(self, denominator: Rate, amount: u128) -> Option<u128> {
+        amount
+            .checked_mul(self.0 as u128)?
+            .checked_div(denominator.0 as u128)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const SCALE: u128 = Rate::SCALE as u128;
+
+    #[test]
+    fn edge_cases() {
+        assert_eq!(Rate::from_bps(1), Some(Rate::from_raw(1_0000)));
+        assert_eq!(Rate::from_bps(1_0000), Some(Rate::ONE));
+        assert_eq!(Rate::from_bps(u64::MAX), None);
+
+        assert_eq!(Rate::ONE.checked_mul(Rate::ONE), Some(Rate::ONE));
+        assert_eq!(
+            Rate::from_raw(u64::MAX).checked_mul(Rate::ONE.checked_add(Rate::ONE).unwrap()),
+            None
+        );
+        assert_eq!(
+            Rate::from_raw(1).checked_mul(Rate::from_raw(1)),
+            Some(Rate::ZERO)
+        );
+
+        assert_eq!(Rate::from_raw(1).apply(1), Some(0));
+        assert_eq!(Rate::from_raw(1).apply_ceil(1), Some(1));
+        assert_eq!(Rate::ZERO.apply_ceil(u128::MAX), Some(0));
+        assert_eq!(Rate::ONE.apply(u128::MAX), None);
+
+        assert_eq!(Rate::ZERO.divide(1), None);
+        assert_eq!(Rate::ONE.apply_ratio(Rate::ZERO, 1), None);
+    }
+
+    proptest! {
+        #[test]
+        fn mul_rounds_down(a: u64, b: u64) {
+            let exact = a as u128 * b as u128;
+            match Rate::from_raw(a).checked_mul(Rate::from_raw(b)) {
+                Some(product) => {
+                    let product = product.raw() as u128;
+                    prop_assert!(product * SCALE <= exact);
+                    prop_assert!(exact < (product + 1) * SCALE);
+                }
+                None => prop_assert!(exact / SCALE > u64::MAX as u128),
+            }
+        }
+
+        #[test]
+        fn mul_is_commutative_with_identity_one(a: u64, b: u64) {
+            let (a, b) = (Rate::from_raw(a), Rate::from_raw(b));
+            prop_assert_eq!(a.checked_mul(b), b.checked_mul(a));
+            prop_assert_eq!(a.checked_mul(Rate::ONE), Some(a));
+        }
+
+        #[test]
+        fn apply_rounds_down_and_apply_ceil_rounds_up(rate: u64, amount: u64) {
+            let rate = Rate::from_raw(rate);
+            let exact = amount as u128 * rate.raw() as u128;
+            let down = rate.apply(amount as u128).unwrap();
+            let up = rate.apply_ceil(amount as u128).unwrap();
+            prop_assert!(down * SCALE <= exact && exact < (down + 1) * SCALE);
+            prop_assert!(up * SCALE >= exact && exact + SCALE > up * SCALE);
+            prop_assert_eq!(up - down, (exact % SCALE != 0) as u128);
+        }
+
+        #[test]
+        fn divide_rounds_down(rate in 1u64.., amount: u64) {
+            let rate = Rate::from_raw(rate);
+            let exact = amount as u128 * SCALE;
+            let quotient = rate.divide(amount as u128).unwrap();
+            prop_assert!(quotient * rate.raw() as u128 <= exact);
+            prop_assert!(exact < (quotient + 1) * rate.raw() as u128);
+        }
+
+        #[test]
+        fn divide_then_apply_never_gains(rate in 1u64.., amount: u64) {
+            // Converting an amount at a rate and back can lose to rounding, but never gain.
+            let rate = Rate::from_raw(rate);
+            let there = rate.divide(amount as u128).unwrap();
+            prop_assert!(rate.apply(there).unwrap() <= amount as u128);
+        }
+
+        #[test]
+        fn apply_ratio_rounds_once(rate: u64, denominator in 1u64.., amount: u64) {
+            let exact = amount as u128 * rate as u128;
+            let result = Rate::from_raw(rate)
+                .apply_ratio(Rate::from_raw(denominator), amount as u128)
+                .unwrap();
+            prop_assert!(result * denominator as u128 <= exact);
+            prop_assert!(exact < (result + 1) * denominator as u128);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use penumbra_crypto::{
+    rdsa::{Signature, SpendAuth, VerificationKey},
+    value, Fr, Value, Zero,
+};
+use penumbra_proto::{stake as pb, Protobuf};
+use serde::{Deserialize, Serialize};
+
+use crate::{DelegationToken, IdentityKey};
+
+/// A transaction action moving stake from one validator's delegation pool to another's, without
+/// unbonding it.
+///
+/// The stake skips the unbonding quarantine, so to stop it from hopping between validators to
+/// escape slashing, each delegator key may only redelegate once per unbonding period.  A key's
+/// first redelegation is quarantined like an undelegation, so that making a fresh key doesn't
+/// skip the cooldown.  The delegator key is revealed, so a delegator's redelegations can be linked
+/// to each other.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "pb::Redelegate", into = "pb::Redelegate")]
+pub struct Redelegate {
+    pub body: Body,
+    /// A signature over the transaction's sighash by [`Body::delegator_key`].
+    pub delegator_sig: Signature<SpendAuth>,
+}
+
+/// The body of a [`Redelegate`], stored separately from the signature that authorizes it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "pb::RedelegateBody", into = "pb::RedelegateBody")]
+pub struct Body {
+    /// The identity key of the validator to undelegate from.
+    pub from_validator_identity: IdentityKey,
+    /// The identity key of the validator to delegate to.
+    pub to_validator_identity: IdentityKey,
+    /// The index of the epoch whose rates the redelegation is priced at, the one after the epoch
+    /// in which it was performed.
+    pub epoch_index: u64,
+    /// The amount of the first validator's delegation tokens consumed by this action.
+    pub from_delegation_amount: u64,
+    /// The amount of stake moved, implied by the first validator's exchange rate.
+    pub unbonded_amount: u64,
+    /// The amount of the second validator's delegation tokens produced by this action, implied by
+    /// its exchange rate.
+    pub to_delegation_amount: u64,
+    /// The key identifying the delegator, whose redelegations share a cooldown.
+    pub delegator_key: VerificationKey<SpendAuth>,
+}
+
+impl Redelegate {
+    /// Compute a commitment to the value contributed to a transaction by this redelegation.
+    pub fn value_commitment(&self) -> value::Commitment {
+        let from = Value {
+            amount: self.body.from_delegation_amount,
+            asset_id: DelegationToken::new(self.body.from_validator_identity.clone()).id(),
+        }
+        .commit(Fr::zero());
+        let to = Value {
+            amount: self.body.to_delegation_amount,
+            asset_id: DelegationToken::new(self.body.to_validator_identity.clone()).id(),
+        }
+        .commit(Fr::zero());
+
+        // We consume the first validator's delegation tokens and produce the second's; the
+        // unbonded stake never leaves the delegation pools.
+        to - from
+    }
+
+    /// Checks that the redelegation is well-formed and signed by its delegator key.
+    pub fn verify(&self, sighash: &[u8; 64]) -> anyhow::Result<()> {
+        if self.body.from_validator_identity == self.body.to_validator_identity {
+            return Err(anyhow::anyhow!(
+                "redelegation must move stake to a different validator"
+            ));
+        }
+
+        self.body
+            .delegator_key
+            .verify(sighash, &self.delegator_sig)
+            .map_err(|_| anyhow::anyhow!("invalid signature by delegator key"))
+    }
+}
+
+impl Protobuf<pb::Redelegate> for Redelegate {}
+
+impl From<Redelegate> for pb::Redelegate {
+    fn from(r: Redelegate) -> Self {
+        pb::Redelegate {
+            body: Some(r.body.into()),
+            delegator_sig: r.delegator_sig.to_bytes().to_vec(),
+        }
+    }
+}
+
+impl TryFrom<pb::Redelegate> for Redelegate {
+    type Error = anyhow::Error;
+    fn try_from(r: pb::Redelegate) -> Result<Self, Self::Error> {
+        Ok(Self {
+            body: r
+                .body
+                .ok_or_else(|| anyhow::anyhow!("missing redelegation body"))?
+                .try_into()?,
+            delegator_sig: r.delegator_sig.as_slice().try_into()?,
+        })
+    }
+}
+
+impl Protobuf<pb::RedelegateBody> for Body {}
+
+impl From<Body> for pb::RedelegateBody {
+    fn from(b: Body) -> Self {
+        pb::RedelegateBody {
+            from_validator_identity: Some(b.from_validator_identity.into()),
+            to_validator_identity: Some(b.to_validator_identity.into()),
+            epoch_index: b.epoch_index,
+            from_delegation_amount: b.from_delegation_amount,
+            unbonded_amount: b.unbonded_amount,
+            to_delegation_amount: b.to_delegation_amount,
+            delegator_key: b.delegator_key.to_bytes().to_vec(),
+        }
+    }
+}
+
+impl TryFrom<pb::RedelegateBody> for Body {
+    type Error = anyhow::Error;
+    fn try_from(b: pb::RedelegateBody) -> Result<Self, Self::Error> {
+        Ok(Self {
+            from_validator_identity: b
+                .from_validator_identity
+                .ok_or_else(|| anyhow::anyhow!("missing validator identity to undelegate from"))?
+                .try_into()?,
+            to_validator_identity: b
+                .to_validator_identity
+                .ok_or_else(|| anyhow::anyhow!("missing validator identity to delegate to"))?
+                .try_into()?,
+            epoch_index: b.epoch_index,
+            from_delegation_amount: b.from_delegation_amount,
+            unbonded_amount: b.unbonded_amount,
+            to_delegation_amount: b.to_delegation_amount,
+            delegator_key: b.delegator_key.as_slice().try_into()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::rdsa::SigningKey;
+    use rand_core::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn redelegation_requires_delegator_signature() {
+        let delegator_sk = SigningKey::<SpendAuth>::new(OsRng);
+        let other_sk = SigningKey::<SpendAuth>::new(OsRng);
+        let body = Body {
+            from_validator_identity: IdentityKey(SigningKey::<SpendAuth>::new(OsRng).into()),
+            to_validator_identity: IdentityKey(SigningKey::<SpendAuth>::new(OsRng).into()),
+            epoch_index: 1,
+            from_delegation_amount: 100,
+            unbonded_amount: 100,
+            to_delegation_amount: 100,
+            delegator_key: delegator_sk.into(),
+        };
+        let sighash = [7; 64];
+
+        let redelegation = Redelegate {
+            body: body.clone(),
+            delegator_sig: delegator_sk.sign(OsRng, &sighash),
+        };
+        assert!(redelegation.verify(&sighash).is_ok());
+        assert!(redelegation.verify(&[8; 64]).is_err());
+
+        // A redelegation signed by some other key must be rejected.
+        let unauthorized = Redelegate {
+            body: body.clone(),
+            delegator_sig: other_sk.sign(OsRng, &sighash),
+        };
+        assert!(unauthorized.verify(&sighash).is_err());
+
+        // As must one that doesn't move the stake anywhere.
+        let same_validator = Redelegate {
+            body: Body {
+                to_validator_identity: body.from_validator_identity.clone(),
+                ..body
+            },
+            delegator_sig: delegator_sk.sign(OsRng, &sighash),
+        };
+        assert!(same_validator.verify(&sighash).is_err());
+    }
+}
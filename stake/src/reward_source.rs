@@ -0,0 +1,39 @@
+use penumbra_proto::{stake as pb, Protobuf};
+use serde::{Deserialize, Serialize};
+
+use crate::IdentityKey;
+
+/// Identifies a note minted by the chain to pay a validator's funding stream, so that its
+/// recipient can tell commission income apart from notes sent by transactions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "pb::RewardSource", into = "pb::RewardSource")]
+pub struct RewardSource {
+    /// The validator whose funding stream the note pays.
+    pub validator_identity: IdentityKey,
+    /// The index of the epoch in which the reward was paid.
+    pub epoch_index: u64,
+}
+
+impl Protobuf<pb::RewardSource> for RewardSource {}
+
+impl From<RewardSource> for pb::RewardSource {
+    fn from(s: RewardSource) -> Self {
+        pb::RewardSource {
+            validator_identity: Some(s.validator_identity.into()),
+            epoch_index: s.epoch_index,
+        }
+    }
+}
+
+impl TryFrom<pb::RewardSource> for RewardSource {
+    type Error = anyhow::Error;
+    fn try_from(s: pb::RewardSource) -> Result<Self, Self::Error> {
+        Ok(Self {
+            validator_identity: s
+                .validator_identity
+                .ok_or_else(|| anyhow::anyhow!("missing validator identity"))?
+                .try_into()?,
+            epoch_index: s.epoch_index,
+        })
+    }
+}
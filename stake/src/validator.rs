@@ -2,7 +2,7 @@ use penumbra_crypto::rdsa::{Signature, SpendAuth};
 use penumbra_proto::{stake as pb, Protobuf};
 use serde::{Deserialize, Serialize};
 
-use crate::{FundingStream, IdentityKey};
+use crate::{FundingStream, FundingStreamRecipient, IdentityKey};
 
 /// Describes a Penumbra validator's configuration data.
 ///
@@ -65,11 +65,27 @@ impl TryFrom<Vec<FundingStream>> for FundingStreams {
     type Error = anyhow::Error;
 
     fn try_from(funding_streams: Vec<FundingStream>) -> Result<Self, Self::Error> {
-        if funding_streams.iter().map(|fs| fs.rate_bps).sum::<u16>() > 10_000 {
+        // Summed as u64, since enough streams of u16 rates could wrap around back under 100%.
+        if funding_streams
+            .iter()
+            .map(|fs| u64::from(fs.rate_bps))
+            .sum::<u64>()
+            > 10_000
+        {
             return Err(anyhow::anyhow!(
                 "sum of funding rates exceeds 100% (10000bps)"
             ));
         }
+        if funding_streams
+            .iter()
+            .filter(|fs| fs.recipient == FundingStreamRecipient::CommunityPool)
+            .count()
+            > 1
+        {
+            return Err(anyhow::anyhow!(
+                "more than one funding stream to the community pool"
+            ));
+        }
 
         Ok(Self { funding_streams })
     }
@@ -104,6 +120,21 @@ pub struct ValidatorDefinition {
     pub auth_sig: Signature<SpendAuth>,
 }
 
+impl ValidatorDefinition {
+    /// Checks that the definition is signed by the validator's identity key.
+    ///
+    /// The signature covers the sequence number, so a definition can't be
+    /// replayed once the chain has accepted a later one; checking that the
+    /// sequence number actually increases requires the chain state.
+    pub fn verify(&self) -> anyhow::Result<()> {
+        self.validator
+            .identity_key
+            .0
+            .verify(&self.validator.encode_to_vec(), &self.auth_sig)
+            .map_err(|_| anyhow::anyhow!("invalid signature by validator identity key"))
+    }
+}
+
 impl Protobuf<pb::Validator> for Validator {}
 
 impl From<Validator> for pb::Validator {
@@ -167,3 +198,73 @@ impl TryFrom<pb::ValidatorDefinition> for ValidatorDefinition {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::{keys::SpendKey, rdsa::SigningKey};
+    use rand_core::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn definition_signature_covers_sequence_number() {
+        let sk = SigningKey::<SpendAuth>::new(OsRng);
+        let validator = Validator {
+            identity_key: IdentityKey(sk.into()),
+            consensus_key: tendermint::PrivateKey::Ed25519(ed25519_consensus::SigningKey::new(
+                OsRng,
+            ))
+            .public_key(),
+            name: "test validator".to_string(),
+            website: String::new(),
+            description: String::new(),
+            funding_streams: FundingStreams::new(),
+            sequence_number: 1,
+        };
+        let definition = ValidatorDefinition {
+            auth_sig: sk.sign(OsRng, &validator.encode_to_vec()),
+            validator,
+        };
+        assert!(definition.verify().is_ok());
+
+        // Bumping the sequence number of an old definition must invalidate its signature.
+        let mut replayed = definition;
+        replayed.validator.sequence_number += 1;
+        assert!(replayed.verify().is_err());
+    }
+
+    #[test]
+    fn funding_streams_to_the_community_pool_are_validated() {
+        let to_pool = |rate_bps| FundingStream {
+            recipient: FundingStreamRecipient::CommunityPool,
+            rate_bps,
+        };
+        assert!(FundingStreams::try_from(vec![to_pool(100)]).is_ok());
+        assert!(FundingStreams::try_from(vec![to_pool(100), to_pool(100)]).is_err());
+
+        let round_tripped = FundingStream::try_from(pb::FundingStream::from(to_pool(100)));
+        assert_eq!(round_tripped.unwrap(), to_pool(100));
+        assert!(FundingStream::try_from(pb::FundingStream {
+            address: "penumbrav0t1".to_string(),
+            rate_bps: 100,
+            to_community_pool: true,
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn funding_stream_rates_cannot_wrap_under_the_limit() {
+        let address = SpendKey::generate(OsRng)
+            .full_viewing_key()
+            .incoming()
+            .payment_address(0u64.into())
+            .0;
+        let full_rate = FundingStream {
+            recipient: FundingStreamRecipient::Address(address),
+            rate_bps: 10_000,
+        };
+        assert!(FundingStreams::try_from(vec![full_rate]).is_ok());
+        // Seven streams of 10000bps sum to 70000, which is 4464 as a u16.
+        assert!(FundingStreams::try_from(vec![full_rate; 7]).is_err());
+    }
+}
@@ -8,9 +8,11 @@ use penumbra_stake as stake;
 pub mod error;
 
 pub mod output;
+pub mod register_denom_metadata;
 pub mod spend;
 
 pub use output::Output;
+pub use register_denom_metadata::RegisterDenomMetadata;
 pub use spend::Spend;
 
 /// Supported actions in a Penumbra transaction.
@@ -20,7 +22,10 @@ pub enum Action {
     Spend(spend::Spend),
     Delegate(stake::Delegate),
     Undelegate(stake::Undelegate),
+    Redelegate(stake::Redelegate),
     ValidatorDefinition(stake::ValidatorDefinition),
+    ValidatorMigration(stake::ValidatorMigration),
+    RegisterDenomMetadata(RegisterDenomMetadata),
 }
 
 impl Action {
@@ -32,7 +37,10 @@ impl Action {
             Action::Spend(spend) => spend.body.value_commitment,
             Action::Delegate(delegate) => delegate.value_commitment(),
             Action::Undelegate(undelegate) => undelegate.value_commitment(),
+            Action::Redelegate(redelegate) => redelegate.value_commitment(),
             Action::ValidatorDefinition(_) => value::Commitment::default(),
+            Action::ValidatorMigration(_) => value::Commitment::default(),
+            Action::RegisterDenomMetadata(_) => value::Commitment::default(),
         }
     }
 }
@@ -54,9 +62,18 @@ impl From<Action> for pb::Action {
             Action::Undelegate(inner) => pb::Action {
                 action: Some(pb::action::Action::Undelegate(inner.into())),
             },
+            Action::Redelegate(inner) => pb::Action {
+                action: Some(pb::action::Action::Redelegate(inner.into())),
+            },
             Action::ValidatorDefinition(inner) => pb::Action {
                 action: Some(pb::action::Action::ValidatorDefinition(inner.into())),
             },
+            Action::ValidatorMigration(inner) => pb::Action {
+                action: Some(pb::action::Action::ValidatorMigration(inner.into())),
+            },
+            Action::RegisterDenomMetadata(inner) => pb::Action {
+                action: Some(pb::action::Action::RegisterDenomMetadata(inner.into())),
+            },
         }
     }
 }
@@ -75,9 +92,16 @@ impl TryFrom<pb::Action> for Action {
             pb::action::Action::Spend(inner) => Ok(Action::Spend(inner.try_into()?)),
             pb::action::Action::Delegate(inner) => Ok(Action::Delegate(inner.try_into()?)),
             pb::action::Action::Undelegate(inner) => Ok(Action::Undelegate(inner.try_into()?)),
+            pb::action::Action::Redelegate(inner) => Ok(Action::Redelegate(inner.try_into()?)),
             pb::action::Action::ValidatorDefinition(inner) => {
                 Ok(Action::ValidatorDefinition(inner.try_into()?))
             }
+            pb::action::Action::ValidatorMigration(inner) => {
+                Ok(Action::ValidatorMigration(inner.try_into()?))
+            }
+            pb::action::Action::RegisterDenomMetadata(inner) => {
+                Ok(Action::RegisterDenomMetadata(inner.try_into()?))
+            }
         }
     }
 }
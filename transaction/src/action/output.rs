@@ -2,7 +2,11 @@ use std::convert::{TryFrom, TryInto};
 
 use bytes::Bytes;
 use penumbra_crypto::{
-    ka, memo::MemoCiphertext, note, proofs::transparent::OutputProof, value, Fr, Note,
+    ka,
+    memo::MemoCiphertext,
+    note,
+    proofs::{transparent::OutputProof, ProofVersion},
+    value, Fr, Note,
 };
 use penumbra_proto::{transaction, Protobuf};
 
@@ -62,6 +66,7 @@ pub struct Body {
     pub ephemeral_key: ka::Public,
     pub encrypted_note: [u8; note::NOTE_CIPHERTEXT_BYTES],
     pub proof: OutputProof,
+    pub proof_version: ProofVersion,
 }
 
 impl Body {
@@ -96,6 +101,7 @@ impl Body {
             ephemeral_key,
             encrypted_note,
             proof,
+            proof_version: ProofVersion::LATEST,
         }
     }
 }
@@ -113,6 +119,7 @@ impl From<Body> for transaction::OutputBody {
             ephemeral_key: Bytes::copy_from_slice(&msg.ephemeral_key.0),
             encrypted_note: Bytes::copy_from_slice(&msg.encrypted_note),
             zkproof: proof.into(),
+            proof_version: msg.proof_version.into(),
         }
     }
 }
@@ -136,6 +143,10 @@ impl TryFrom<transaction::OutputBody> for Body {
             proof: proto.zkproof[..]
                 .try_into()
                 .map_err(|_| ProtoError::OutputBodyMalformed)?,
+            proof_version: proto
+                .proof_version
+                .try_into()
+                .map_err(|_| ProtoError::OutputBodyMalformed)?,
         })
     }
 }
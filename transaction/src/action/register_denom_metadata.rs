@@ -0,0 +1,134 @@
+use std::convert::{TryFrom, TryInto};
+
+use penumbra_crypto::{
+    asset,
+    rdsa::{Signature, SpendAuth, VerificationKey},
+};
+use penumbra_proto::{transaction as pb, Protobuf};
+
+/// A transaction action registering or updating the display metadata for a denomination.
+///
+/// Only the denom metadata authority named in the chain parameters can do this, so that nobody
+/// else can claim a symbol for someone else's asset.  The authority signs the transaction, so the
+/// action can't be lifted into another transaction to revert a later update.
+#[derive(Clone, Debug)]
+pub struct RegisterDenomMetadata {
+    pub body: Body,
+    /// A signature over the transaction's sighash by [`Body::authority_key`].
+    pub authority_sig: Signature<SpendAuth>,
+}
+
+/// The body of a [`RegisterDenomMetadata`], stored separately from the signature that authorizes
+/// it.
+#[derive(Clone, Debug)]
+pub struct Body {
+    /// The metadata to register.
+    pub metadata: asset::Metadata,
+    /// The key of the denom metadata authority.
+    ///
+    /// Whether this is actually the chain's authority depends on the chain parameters, so it is
+    /// checked during stateful verification.
+    pub authority_key: VerificationKey<SpendAuth>,
+}
+
+impl RegisterDenomMetadata {
+    /// Checks that the metadata is well-formed and that the registration is signed by its
+    /// authority key.
+    pub fn verify(&self, sighash: &[u8; 64]) -> anyhow::Result<()> {
+        self.body.metadata.check()?;
+
+        self.body
+            .authority_key
+            .verify(sighash, &self.authority_sig)
+            .map_err(|_| anyhow::anyhow!("invalid signature by denom metadata authority key"))
+    }
+}
+
+impl Protobuf<pb::RegisterDenomMetadata> for RegisterDenomMetadata {}
+
+impl From<RegisterDenomMetadata> for pb::RegisterDenomMetadata {
+    fn from(r: RegisterDenomMetadata) -> Self {
+        pb::RegisterDenomMetadata {
+            body: Some(r.body.into()),
+            authority_sig: r.authority_sig.to_bytes().to_vec().into(),
+        }
+    }
+}
+
+impl TryFrom<pb::RegisterDenomMetadata> for RegisterDenomMetadata {
+    type Error = anyhow::Error;
+
+    fn try_from(r: pb::RegisterDenomMetadata) -> Result<Self, Self::Error> {
+        Ok(Self {
+            body: r
+                .body
+                .ok_or_else(|| anyhow::anyhow!("missing denom metadata registration body"))?
+                .try_into()?,
+            authority_sig: r.authority_sig[..].try_into()?,
+        })
+    }
+}
+
+impl Protobuf<pb::RegisterDenomMetadataBody> for Body {}
+
+impl From<Body> for pb::RegisterDenomMetadataBody {
+    fn from(b: Body) -> Self {
+        pb::RegisterDenomMetadataBody {
+            metadata: Some(b.metadata.into()),
+            authority_key: b.authority_key.to_bytes().to_vec().into(),
+        }
+    }
+}
+
+impl TryFrom<pb::RegisterDenomMetadataBody> for Body {
+    type Error = anyhow::Error;
+
+    fn try_from(b: pb::RegisterDenomMetadataBody) -> Result<Self, Self::Error> {
+        Ok(Self {
+            metadata: b
+                .metadata
+                .ok_or_else(|| anyhow::anyhow!("missing denom metadata"))?
+                .try_into()?,
+            authority_key: b.authority_key[..].try_into()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::rdsa::SigningKey;
+    use rand_core::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn registration_requires_authority_signature() {
+        let authority_sk = SigningKey::<SpendAuth>::new(OsRng);
+        let other_sk = SigningKey::<SpendAuth>::new(OsRng);
+        let body = Body {
+            metadata: asset::Metadata {
+                denom: asset::REGISTRY.parse_denom("cube").unwrap(),
+                symbol: "CUBE".to_string(),
+                decimals: 6,
+                description: "A cube.".to_string(),
+            },
+            authority_key: authority_sk.into(),
+        };
+        let sighash = [7; 64];
+
+        let registration = RegisterDenomMetadata {
+            body: body.clone(),
+            authority_sig: authority_sk.sign(OsRng, &sighash),
+        };
+        assert!(registration.verify(&sighash).is_ok());
+        // The signature is bound to the transaction it was made for.
+        assert!(registration.verify(&[8; 64]).is_err());
+
+        // A registration signed by some other key must be rejected.
+        let unauthorized = RegisterDenomMetadata {
+            body,
+            authority_sig: other_sk.sign(OsRng, &sighash),
+        };
+        assert!(unauthorized.verify(&sighash).is_err());
+    }
+}
@@ -3,7 +3,7 @@ use std::convert::{TryFrom, TryInto};
 use bytes::Bytes;
 use penumbra_crypto::{
     keys, merkle,
-    proofs::transparent::SpendProof,
+    proofs::{transparent::SpendProof, ProofVersion},
     rdsa::{Signature, SigningKey, SpendAuth, VerificationKey},
     value, Fr, Note, Nullifier,
 };
@@ -57,6 +57,7 @@ pub struct Body {
     // Randomized verification key.
     pub rk: VerificationKey<SpendAuth>,
     pub proof: SpendProof,
+    pub proof_version: ProofVersion,
 }
 
 impl Body {
@@ -93,6 +94,7 @@ impl Body {
             nullifier: nk.derive_nullifier(position, &note_commitment),
             rk,
             proof,
+            proof_version: ProofVersion::LATEST,
         }
     }
 }
@@ -117,6 +119,7 @@ impl From<Body> for transaction::SpendBody {
             nullifier: Bytes::copy_from_slice(&nullifier_bytes),
             rk: Bytes::copy_from_slice(&rk_bytes),
             zkproof: proof.into(),
+            proof_version: msg.proof_version.into(),
         }
     }
 }
@@ -144,11 +147,17 @@ impl TryFrom<transaction::SpendBody> for Body {
             .try_into()
             .map_err(|_| ProtoError::SpendBodyMalformed)?;
 
+        let proof_version = proto
+            .proof_version
+            .try_into()
+            .map_err(|_| ProtoError::SpendBodyMalformed)?;
+
         Ok(Body {
             value_commitment,
             nullifier,
             rk,
             proof,
+            proof_version,
         })
     }
 }
@@ -97,7 +97,7 @@ impl GenesisBuilder {
             actions: self.actions.clone(),
             expiry_height: 0,
             chain_id: self.chain_id.unwrap(),
-            fee: Fee(0),
+            fee: Fee::from_staking_token_amount(0),
         };
 
         let binding_sig = [0u8; 64].into();
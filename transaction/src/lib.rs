@@ -8,5 +8,8 @@ pub use error::Error;
 mod genesis;
 pub use genesis::GenesisBuilder;
 
+mod shape;
+pub use shape::{Shape, MIN_SHAPE_CLASS};
+
 mod transaction;
-pub use transaction::{Fee, Transaction, TransactionBody};
+pub use transaction::{Fee, Padding, Transaction, TransactionBody};
@@ -0,0 +1,100 @@
+use std::fmt;
+
+use crate::{Action, TransactionBody};
+
+/// The smallest number of spends (and outputs) in a transaction that moves any value.
+pub const MIN_SHAPE_CLASS: usize = 2;
+
+/// The number of spends and outputs in a transaction.
+///
+/// Consensus only accepts transactions with a *standard* shape: either no spends and no outputs,
+/// or `n` spends and `n` outputs where `n` is a power of two and at least [`MIN_SHAPE_CLASS`]
+/// (2-in/2-out, 4-in/4-out, ...).  Transactions are padded up to a standard shape with dummy
+/// spends and outputs, so every transaction in a shape class looks alike, and the anonymity set
+/// isn't split up by arbitrary combinations of counts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Shape {
+    pub spends: usize,
+    pub outputs: usize,
+}
+
+impl Shape {
+    /// The shape of a transaction body.
+    pub fn of(body: &TransactionBody) -> Self {
+        let mut shape = Shape {
+            spends: 0,
+            outputs: 0,
+        };
+        for action in &body.actions {
+            match action {
+                Action::Spend(_) => shape.spends += 1,
+                Action::Output(_) => shape.outputs += 1,
+                _ => {}
+            }
+        }
+        shape
+    }
+
+    /// The smallest standard shape with at least `min_class` spends and outputs that can hold
+    /// this one.
+    pub fn padded(&self, min_class: usize) -> Self {
+        if self.spends == 0 && self.outputs == 0 {
+            return *self;
+        }
+        let class = self
+            .spends
+            .max(self.outputs)
+            .max(min_class)
+            .max(MIN_SHAPE_CLASS)
+            .next_power_of_two();
+        Shape {
+            spends: class,
+            outputs: class,
+        }
+    }
+
+    /// Whether this is one of the shapes consensus accepts.
+    pub fn is_standard(&self) -> bool {
+        self.padded(MIN_SHAPE_CLASS) == *self
+    }
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-in/{}-out", self.spends, self.outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shape(spends: usize, outputs: usize) -> Shape {
+        Shape { spends, outputs }
+    }
+
+    #[test]
+    fn standard_shapes() {
+        for standard in [shape(0, 0), shape(2, 2), shape(4, 4), shape(32, 32)] {
+            assert!(standard.is_standard(), "{} should be standard", standard);
+        }
+        for other in [
+            shape(1, 1),
+            shape(2, 4),
+            shape(0, 2),
+            shape(3, 3),
+            shape(6, 6),
+        ] {
+            assert!(!other.is_standard(), "{} should not be standard", other);
+        }
+    }
+
+    #[test]
+    fn padding_fits_the_shape() {
+        assert_eq!(shape(1, 2).padded(MIN_SHAPE_CLASS), shape(2, 2));
+        assert_eq!(shape(1, 3).padded(MIN_SHAPE_CLASS), shape(4, 4));
+        assert_eq!(shape(5, 1).padded(MIN_SHAPE_CLASS), shape(8, 8));
+        assert_eq!(shape(1, 2).padded(8), shape(8, 8));
+        assert_eq!(shape(0, 0).padded(8), shape(0, 0));
+    }
+}
@@ -4,6 +4,7 @@ use ark_ff::Zero;
 use bytes::Bytes;
 use decaf377::FieldExt;
 use penumbra_crypto::{
+    asset,
     merkle::{self, NoteCommitmentTree, TreeExt},
     rdsa::{Binding, Signature, VerificationKey, VerificationKeyBytes},
     Fr, Value,
@@ -20,7 +21,7 @@ use penumbra_stake::STAKING_TOKEN_ASSET_ID;
 use crate::{action::error::ProtoError, Action, GenesisBuilder};
 
 mod builder;
-pub use builder::Builder;
+pub use builder::{Builder, Padding};
 
 #[derive(Clone, Debug)]
 pub struct TransactionBody {
@@ -45,8 +46,32 @@ impl TransactionBody {
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct Fee(pub u64);
+/// The fee paid by a transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fee {
+    pub amount: u64,
+    /// The asset the fee is paid in: the staking token, or one of the assets with a fee rate in
+    /// the chain parameters.
+    pub asset_id: asset::Id,
+}
+
+impl Fee {
+    /// A fee of `amount` of the staking token.
+    pub fn from_staking_token_amount(amount: u64) -> Self {
+        Fee {
+            amount,
+            asset_id: *STAKING_TOKEN_ASSET_ID,
+        }
+    }
+
+    /// The value of the fee.
+    pub fn value(&self) -> Value {
+        Value {
+            amount: self.amount,
+            asset_id: self.asset_id,
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct Transaction {
@@ -62,6 +87,8 @@ impl Transaction {
             outputs: Vec::new(),
             delegations: Vec::new(),
             undelegations: Vec::new(),
+            redelegations: Vec::new(),
+            denom_metadata: Vec::new(),
             fee: None,
             synthetic_blinding_factor: Fr::zero(),
             value_balance: decaf377::Element::default(),
@@ -69,6 +96,7 @@ impl Transaction {
             merkle_root,
             expiry_height: None,
             chain_id: None,
+            padding: Default::default(),
         }
     }
 
@@ -113,10 +141,7 @@ impl Transaction {
         }
 
         // Add fee into binding verification key computation.
-        let fee_value = Value {
-            amount: self.transaction_body.fee.0,
-            asset_id: *STAKING_TOKEN_ASSET_ID,
-        };
+        let fee_value = self.transaction_body.fee.value();
         let fee_v_blinding = Fr::zero();
         let fee_value_commitment = fee_value.commit(fee_v_blinding);
         value_commitments -= fee_value_commitment.0;
@@ -175,7 +200,8 @@ impl TryFrom<ProtoTransactionBody> for TransactionBody {
         let fee: Fee = proto
             .fee
             .ok_or(ProtoError::TransactionBodyMalformed)?
-            .into();
+            .try_into()
+            .map_err(|_| ProtoError::TransactionBodyMalformed)?;
 
         Ok(TransactionBody {
             actions,
@@ -263,13 +289,31 @@ impl Protobuf<ProtoFee> for Fee {}
 
 impl From<Fee> for ProtoFee {
     fn from(fee: Fee) -> Self {
-        ProtoFee { amount: fee.0 }
+        // Fees in the staking token leave the asset ID out, so that they encode the same way as
+        // before fees could be paid in other assets.
+        let asset_id = if fee.asset_id == *STAKING_TOKEN_ASSET_ID {
+            None
+        } else {
+            Some(fee.asset_id.into())
+        };
+        ProtoFee {
+            amount: fee.amount,
+            asset_id,
+        }
     }
 }
 
-impl From<ProtoFee> for Fee {
-    fn from(proto: ProtoFee) -> Self {
-        Fee(proto.amount)
+impl TryFrom<ProtoFee> for Fee {
+    type Error = anyhow::Error;
+
+    fn try_from(proto: ProtoFee) -> anyhow::Result<Self> {
+        Ok(Fee {
+            amount: proto.amount,
+            asset_id: match proto.asset_id {
+                Some(asset_id) => asset_id.try_into()?,
+                None => *STAKING_TOKEN_ASSET_ID,
+            },
+        })
     }
 }
 
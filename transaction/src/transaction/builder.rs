@@ -1,24 +1,78 @@
-use std::ops::Deref;
+use std::{ops::Deref, str::FromStr};
 
 use ark_ff::{UniformRand, Zero};
 use incrementalmerkletree::Tree;
 use penumbra_crypto::{
-    ka,
+    asset, ka,
     keys::{OutgoingViewingKey, SpendKey},
     memo::MemoPlaintext,
     merkle::{self, NoteCommitmentTree},
+    note,
     rdsa::{Binding, Signature, SigningKey, SpendAuth},
-    value, Address, Fr, Note, Value,
+    value, Address, Fq, Fr, Note, Value,
+};
+use penumbra_stake::{
+    Delegate, RateData, Redelegate, RedelegateBody, Undelegate, STAKING_TOKEN_ASSET_ID,
 };
-use penumbra_stake::{Delegate, RateData, Undelegate, STAKING_TOKEN_ASSET_ID};
 use rand::seq::SliceRandom;
 use rand_core::{CryptoRng, RngCore};
 
 use crate::{
-    action::{output, spend, Action, Output, Spend},
+    action::{
+        output, register_denom_metadata, spend, Action, Output, RegisterDenomMetadata, Spend,
+    },
+    shape::{Shape, MIN_SHAPE_CLASS},
     Error, Fee, Transaction, TransactionBody,
 };
 
+/// How far to pad a transaction with dummy spends and outputs.
+///
+/// Transactions are always padded to a standard [`Shape`], since consensus rejects any other;
+/// padding further makes small transactions indistinguishable from larger ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Padding {
+    /// Pad to the smallest standard shape that fits the transaction.
+    Minimal,
+    /// Pad to a standard shape with at least this many spends and outputs.
+    AtLeast(usize),
+}
+
+impl Default for Padding {
+    fn default() -> Self {
+        Padding::Minimal
+    }
+}
+
+impl Padding {
+    /// The standard shape to pad a transaction of the given shape up to.
+    pub fn padded_shape(&self, shape: Shape) -> Shape {
+        match self {
+            Padding::Minimal => shape.padded(MIN_SHAPE_CLASS),
+            Padding::AtLeast(class) => shape.padded(*class),
+        }
+    }
+}
+
+impl FromStr for Padding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "minimal" {
+            return Ok(Padding::Minimal);
+        }
+        match s.parse::<usize>() {
+            Ok(class) if class >= MIN_SHAPE_CLASS && class.is_power_of_two() => {
+                Ok(Padding::AtLeast(class))
+            }
+            _ => Err(anyhow::anyhow!(
+                "unknown padding policy {:?}, expected \"minimal\" or a power of two of at least {}",
+                s,
+                MIN_SHAPE_CLASS
+            )),
+        }
+    }
+}
+
 /// Used to construct a Penumbra transaction.
 pub struct Builder {
     /// List of spends. We store the spend key and body rather than a Spend
@@ -30,6 +84,12 @@ pub struct Builder {
     pub delegations: Vec<Delegate>,
     /// List of undelegations in the transaction.
     pub undelegations: Vec<Undelegate>,
+    /// List of redelegations in the transaction. Like spends, we store the delegator's signing
+    /// key and the body, to sign once the transaction is complete.
+    pub redelegations: Vec<(SigningKey<SpendAuth>, RedelegateBody)>,
+    /// List of denom metadata registrations in the transaction. Like spends, we store the
+    /// authority's signing key and the body, to sign once the transaction is complete.
+    pub denom_metadata: Vec<(SigningKey<SpendAuth>, register_denom_metadata::Body)>,
     /// Transaction fee. None if unset.
     pub fee: Option<Fee>,
     /// Sum of blinding factors for each value commitment.
@@ -44,6 +104,8 @@ pub struct Builder {
     pub expiry_height: Option<u32>,
     /// Chain ID. None if unset.
     pub chain_id: Option<String>,
+    /// How far to pad the spends and outputs when the transaction is finalized.
+    pub padding: Padding,
 }
 
 impl Builder {
@@ -64,6 +126,20 @@ impl Builder {
                 )
             })?;
 
+        Ok(self.add_spend_with_path(rng, merkle_path, spend_key, note))
+    }
+
+    /// Create a new `Spend` to spend an existing note, given its authentication path.
+    ///
+    /// The path must authenticate the note against the builder's merkle root, e.g. one fetched
+    /// from a node and checked against it, for the transaction to be valid.
+    pub fn add_spend_with_path<R: RngCore + CryptoRng>(
+        &mut self,
+        rng: &mut R,
+        merkle_path: merkle::Path,
+        spend_key: &SpendKey,
+        note: Note,
+    ) -> &mut Self {
         let v_blinding = Fr::rand(rng);
         let value_commitment = note.value().commit(v_blinding);
 
@@ -88,7 +164,7 @@ impl Builder {
 
         self.spends.push((rsk, body));
 
-        Ok(self)
+        self
     }
 
     /// Create a new `Output`, implicitly creating a new note for it and encrypting the provided
@@ -153,6 +229,78 @@ impl Builder {
         note
     }
 
+    /// Add a dummy spend, of a fresh zero-value note under a throwaway key.
+    ///
+    /// Zero-value spends don't need to prove their note is in the note commitment tree, and their
+    /// nullifier is unlinkable to anything, so this only changes the transaction's shape.
+    pub fn add_dummy_spend<R: RngCore + CryptoRng>(&mut self, rng: &mut R) -> &mut Self {
+        let spend_key = SpendKey::generate(&mut *rng);
+        let (address, _) = spend_key
+            .full_viewing_key()
+            .incoming()
+            .payment_address(0u64.into());
+        let note = Note::generate(
+            rng,
+            &address,
+            Value {
+                amount: 0,
+                asset_id: *STAKING_TOKEN_ASSET_ID,
+            },
+        );
+        let merkle_path = (
+            merkle::Position::from(0usize),
+            vec![note::Commitment(Fq::zero()); merkle::DEPTH],
+        );
+
+        let v_blinding = Fr::rand(rng);
+        let value_commitment = note.value().commit(v_blinding);
+        // The value is zero, so only the blinding factor affects the balance.
+        self.synthetic_blinding_factor += v_blinding;
+        self.value_commitments += value_commitment.0;
+
+        let spend_auth_randomizer = Fr::rand(rng);
+        let rsk = spend_key.spend_auth_key().randomize(&spend_auth_randomizer);
+
+        let body = spend::Body::new(
+            value_commitment,
+            *spend_key.spend_auth_key(),
+            spend_auth_randomizer,
+            merkle_path,
+            note,
+            v_blinding,
+            *spend_key.nullifier_key(),
+        );
+
+        self.spends.push((rsk, body));
+
+        self
+    }
+
+    /// Add a dummy output, of a zero-value note to a throwaway address.
+    pub fn add_dummy_output<R: RngCore + CryptoRng>(&mut self, rng: &mut R) -> &mut Self {
+        let spend_key = SpendKey::generate(&mut *rng);
+        let (address, _) = spend_key
+            .full_viewing_key()
+            .incoming()
+            .payment_address(0u64.into());
+        self.add_output(
+            rng,
+            &address,
+            Value {
+                amount: 0,
+                asset_id: *STAKING_TOKEN_ASSET_ID,
+            },
+            MemoPlaintext::default(),
+            spend_key.outgoing_viewing_key(),
+        )
+    }
+
+    /// Set how far to pad the spends and outputs with dummies when the transaction is finalized.
+    pub fn set_padding(&mut self, padding: Padding) -> &mut Self {
+        self.padding = padding;
+        self
+    }
+
     /// Create a new `Delegate` description for the transaction.
     pub fn add_delegation(&mut self, rate_data: &RateData, unbonded_amount: u64) -> &mut Self {
         let delegate = Delegate {
@@ -193,16 +341,72 @@ impl Builder {
         self
     }
 
+    /// Create a new `Redelegate` description for the transaction, moving `delegation_amount` of
+    /// the first validator's delegation tokens to the second, signed by `delegator_key`.
+    pub fn add_redelegation(
+        &mut self,
+        from_rate_data: &RateData,
+        to_rate_data: &RateData,
+        delegation_amount: u64,
+        delegator_key: SigningKey<SpendAuth>,
+    ) -> &mut Self {
+        let unbonded_amount = from_rate_data.unbonded_amount(delegation_amount);
+        let body = RedelegateBody {
+            from_validator_identity: from_rate_data.identity_key.clone(),
+            to_validator_identity: to_rate_data.identity_key.clone(),
+            epoch_index: from_rate_data.epoch_index,
+            from_delegation_amount: delegation_amount,
+            unbonded_amount,
+            to_delegation_amount: to_rate_data.delegation_amount(unbonded_amount),
+            delegator_key: delegator_key.into(),
+        };
+
+        let value_commitment = Redelegate {
+            body: body.clone(),
+            delegator_sig: Signature::from([0; 64]),
+        }
+        .value_commitment();
+        // The value commitment has 0 blinding factor, so we skip
+        // accumulating a blinding term into the synthetic blinding factor.
+        self.value_balance += value_commitment.0;
+        self.value_commitments += value_commitment.0;
+
+        self.redelegations.push((delegator_key, body));
+
+        self
+    }
+
+    /// Register or update display metadata for a denomination, signed by the chain's denom
+    /// metadata `authority_key`.
+    pub fn add_denom_metadata(
+        &mut self,
+        metadata: asset::Metadata,
+        authority_key: SigningKey<SpendAuth>,
+    ) -> &mut Self {
+        // Registering metadata doesn't move any value, so there's no value commitment to add.
+        let body = register_denom_metadata::Body {
+            metadata,
+            authority_key: authority_key.into(),
+        };
+        self.denom_metadata.push((authority_key, body));
+        self
+    }
+
     /// Set the transaction fee in PEN.
     ///
     /// Note that we're using the lower case `pen` in the code.
     pub fn set_fee(&mut self, fee: u64) -> &mut Self {
-        let asset_id = *STAKING_TOKEN_ASSET_ID;
-        let fee_value = Value {
+        self.set_fee_value(Value {
             amount: fee,
-            asset_id: asset_id.clone(),
-        };
+            asset_id: *STAKING_TOKEN_ASSET_ID,
+        })
+    }
 
+    /// Set the transaction fee, in any asset.
+    ///
+    /// Fees in assets other than the staking token are only accepted if the chain parameters
+    /// give a fee rate for the asset.
+    pub fn set_fee_value(&mut self, fee_value: Value) -> &mut Self {
         // The fee is effectively an additional output, so we
         // add to the transaction's value balance.
         let value_commitment = fee_value.commit(Fr::zero());
@@ -211,7 +415,10 @@ impl Builder {
         self.value_balance -= value_commitment.0;
         self.value_commitments -= value_commitment.0;
 
-        self.fee = Some(Fee(fee));
+        self.fee = Some(Fee {
+            amount: fee_value.amount,
+            asset_id: fee_value.asset_id,
+        });
         self
     }
 
@@ -266,11 +473,25 @@ impl Builder {
 
         let mut actions = Vec::<Action>::new();
 
+        // Pad the spends and outputs with dummies up to a standard shape, before shuffling so
+        // they're mixed in.
+        let padded = self.padding.padded_shape(Shape {
+            spends: self.spends.len(),
+            outputs: self.outputs.len(),
+        });
+        while self.spends.len() < padded.spends {
+            self.add_dummy_spend(rng);
+        }
+        while self.outputs.len() < padded.outputs {
+            self.add_dummy_output(rng);
+        }
+
         // Randomize all actions to minimize info leakage.
         self.spends.shuffle(rng);
         self.outputs.shuffle(rng);
         self.delegations.shuffle(rng);
         self.undelegations.shuffle(rng);
+        self.redelegations.shuffle(rng);
 
         // Fill in the spends using blank signatures, so we can build the sighash tx
         for (_, body) in &self.spends {
@@ -288,6 +509,18 @@ impl Builder {
         for undelegation in self.undelegations.drain(..) {
             actions.push(Action::Undelegate(undelegation));
         }
+        for (_, body) in &self.redelegations {
+            actions.push(Action::Redelegate(Redelegate {
+                body: body.clone(),
+                delegator_sig: Signature::from([0; 64]),
+            }));
+        }
+        for (_, body) in &self.denom_metadata {
+            actions.push(Action::RegisterDenomMetadata(RegisterDenomMetadata {
+                body: body.clone(),
+                authority_sig: Signature::from([0; 64]),
+            }));
+        }
 
         let mut transaction_body = TransactionBody {
             actions,
@@ -314,6 +547,39 @@ impl Builder {
             }
         }
 
+        // ... and the redelegations' delegator sigs, in the order they were added ...
+        let mut delegator_keys = self.redelegations.iter().map(|(key, _)| *key);
+        for action in transaction_body.actions.iter_mut() {
+            if let Action::Redelegate(Redelegate {
+                ref mut delegator_sig,
+                ..
+            }) = action
+            {
+                let key = delegator_keys
+                    .next()
+                    .expect("one delegator key per redelegation");
+                *delegator_sig = key.sign(&mut rng, &sighash);
+            }
+        }
+
+        // ... and the denom metadata authority's sigs, likewise ...
+        let mut authority_keys = self.denom_metadata.iter().map(|(key, _)| *key);
+        for action in transaction_body.actions.iter_mut() {
+            if let Action::RegisterDenomMetadata(RegisterDenomMetadata {
+                ref mut authority_sig,
+                ..
+            }) = action
+            {
+                let key = authority_keys
+                    .next()
+                    .expect("one authority key per registration");
+                *authority_sig = key.sign(&mut rng, &sighash);
+            }
+        }
+
+        self.redelegations.clear();
+        self.denom_metadata.clear();
+
         // ... and the binding sig
         let binding_sig = self.compute_binding_sig(rng, &sighash);
 
@@ -1,5 +1,12 @@
+mod note_selection;
+mod plan;
 mod state;
 mod wallet;
 
-pub use state::{ClientState, UnspentNote};
+pub use note_selection::SelectionStrategy;
+pub use plan::{Intent, OutputPlan, SpendPlan, TransactionPlan};
+pub use state::{
+    unbonding_release_height, ClientState, ClientStateHelper, ScanEvent, UnspentNote,
+    COMPACT_BLOCK_VERSION,
+};
 pub use wallet::Wallet;
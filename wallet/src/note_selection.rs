@@ -0,0 +1,253 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use penumbra_crypto::Note;
+use rand::seq::SliceRandom;
+use rand_core::{CryptoRng, RngCore};
+
+/// How to choose which notes to spend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectionStrategy {
+    /// Draw notes in a random order, to avoid leaking information via arity.
+    Random,
+    /// Avoid linking notes received by different addresses in the same transaction: spend from a
+    /// single address whenever one has enough, and otherwise from as few addresses as possible.
+    PrivacyMax,
+    /// Spend as few notes as possible, to keep the transaction small.
+    FeeMin,
+    /// Spend the oldest notes first.
+    AgePriority,
+}
+
+impl Default for SelectionStrategy {
+    fn default() -> Self {
+        SelectionStrategy::Random
+    }
+}
+
+impl FromStr for SelectionStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(SelectionStrategy::Random),
+            "privacy-max" => Ok(SelectionStrategy::PrivacyMax),
+            "fee-min" => Ok(SelectionStrategy::FeeMin),
+            "age-priority" => Ok(SelectionStrategy::AgePriority),
+            _ => Err(anyhow::anyhow!(
+                "unknown note selection strategy {:?}, expected one of \"random\", \"privacy-max\", \"fee-min\" or \"age-priority\"",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SelectionStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SelectionStrategy::Random => "random",
+            SelectionStrategy::PrivacyMax => "privacy-max",
+            SelectionStrategy::FeeMin => "fee-min",
+            SelectionStrategy::AgePriority => "age-priority",
+        })
+    }
+}
+
+/// A note that could be spent.
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub note: Note,
+    /// The index of the address the note was sent to.
+    pub address_index: u64,
+    /// The position of the note in the note commitment tree, so older notes have lower positions.
+    pub position: u64,
+}
+
+impl SelectionStrategy {
+    /// Selects notes from `candidates` worth at least `amount` in total, or returns `None` if
+    /// they aren't worth enough.
+    ///
+    /// At least one note is always selected, even if `amount` is zero.
+    pub fn select<R: CryptoRng + RngCore>(
+        &self,
+        rng: &mut R,
+        mut candidates: Vec<Candidate>,
+        amount: u64,
+    ) -> Option<Vec<Candidate>> {
+        match self {
+            SelectionStrategy::Random => {
+                candidates.shuffle(rng);
+                take_until(candidates, amount)
+            }
+            SelectionStrategy::FeeMin => {
+                // A single note is as few as it gets, and the smallest one that suffices leaves
+                // the least change.
+                if let Some(note) = candidates
+                    .iter()
+                    .filter(|c| c.note.amount() >= amount)
+                    .min_by_key(|c| c.note.amount())
+                {
+                    return Some(vec![note.clone()]);
+                }
+                candidates.sort_by_key(|c| std::cmp::Reverse(c.note.amount()));
+                take_until(candidates, amount)
+            }
+            SelectionStrategy::AgePriority => {
+                candidates.sort_by_key(|c| c.position);
+                take_until(candidates, amount)
+            }
+            SelectionStrategy::PrivacyMax => {
+                let mut by_address = BTreeMap::<u64, Vec<Candidate>>::new();
+                for candidate in candidates {
+                    by_address
+                        .entry(candidate.address_index)
+                        .or_default()
+                        .push(candidate);
+                }
+                let mut groups = by_address.into_values().collect::<Vec<_>>();
+                for group in groups.iter_mut() {
+                    group.shuffle(rng);
+                }
+                let total =
+                    |group: &Vec<Candidate>| -> u64 { group.iter().map(|c| c.note.amount()).sum() };
+
+                // Prefer any one address that has enough on its own, chosen at random so that
+                // which address gets spent from doesn't depend on the balances...
+                let sufficient = groups
+                    .iter()
+                    .filter(|group| total(group) >= amount)
+                    .collect::<Vec<_>>();
+                if let Some(group) = sufficient.choose(rng) {
+                    return take_until((*group).clone(), amount);
+                }
+
+                // ... and otherwise link as few addresses as possible, by drawing from the
+                // largest balances first.
+                groups.sort_by_key(|group| std::cmp::Reverse(total(group)));
+                take_until(groups.into_iter().flatten().collect(), amount)
+            }
+        }
+    }
+}
+
+/// Takes candidates in order until they're worth at least `amount`.
+fn take_until(candidates: Vec<Candidate>, amount: u64) -> Option<Vec<Candidate>> {
+    let mut selected = Vec::new();
+    let mut total = 0u64;
+    for candidate in candidates {
+        total += candidate.note.amount();
+        selected.push(candidate);
+        if total >= amount {
+            return Some(selected);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use penumbra_crypto::{keys::SpendKey, Value};
+    use penumbra_stake::STAKING_TOKEN_ASSET_ID;
+    use rand::{rngs::StdRng, SeedableRng};
+    use rand_core::OsRng;
+
+    use super::*;
+
+    /// Makes candidates with the given (address index, amount) pairs, in order of age.
+    fn candidates(notes: &[(u64, u64)]) -> Vec<Candidate> {
+        let ivk = SpendKey::generate(OsRng)
+            .full_viewing_key()
+            .incoming()
+            .clone();
+        notes
+            .iter()
+            .enumerate()
+            .map(|(position, &(address_index, amount))| {
+                let (address, _dtk) = ivk.payment_address(address_index.into());
+                Candidate {
+                    note: Note::generate(
+                        &mut OsRng,
+                        &address,
+                        Value {
+                            amount,
+                            asset_id: *STAKING_TOKEN_ASSET_ID,
+                        },
+                    ),
+                    address_index,
+                    position: position as u64,
+                }
+            })
+            .collect()
+    }
+
+    fn select(strategy: SelectionStrategy, notes: &[(u64, u64)], amount: u64) -> Vec<Candidate> {
+        strategy
+            .select(&mut StdRng::seed_from_u64(0), candidates(notes), amount)
+            .expect("enough notes")
+    }
+
+    fn amounts(selected: &[Candidate]) -> Vec<u64> {
+        let mut amounts = selected.iter().map(|c| c.note.amount()).collect::<Vec<_>>();
+        amounts.sort_unstable();
+        amounts
+    }
+
+    #[test]
+    fn fee_min_spends_fewest_notes() {
+        let notes = [(0, 1), (0, 2), (0, 3), (0, 50), (0, 100)];
+        assert_eq!(
+            amounts(&select(SelectionStrategy::FeeMin, &notes, 40)),
+            [50]
+        );
+        assert_eq!(
+            amounts(&select(SelectionStrategy::FeeMin, &notes, 120)),
+            [50, 100]
+        );
+    }
+
+    #[test]
+    fn age_priority_spends_oldest_notes() {
+        let notes = [(0, 5), (0, 5), (0, 100), (0, 5)];
+        let selected = select(SelectionStrategy::AgePriority, &notes, 10);
+        assert_eq!(
+            selected.iter().map(|c| c.position).collect::<Vec<_>>(),
+            [0, 1]
+        );
+    }
+
+    #[test]
+    fn privacy_max_avoids_linking_addresses() {
+        // Address 1 has enough on its own, so nothing from the others is spent, even though
+        // fewer notes would do otherwise.
+        let notes = [(0, 45), (1, 20), (1, 20), (1, 20), (2, 30)];
+        let selected = select(SelectionStrategy::PrivacyMax, &notes, 50);
+        assert!(selected.iter().all(|c| c.address_index == 1));
+        assert_eq!(
+            amounts(&select(SelectionStrategy::FeeMin, &notes, 50)),
+            [30, 45]
+        );
+
+        // No address has enough, so the largest balances are combined.
+        let selected = select(SelectionStrategy::PrivacyMax, &notes, 100);
+        let addresses = selected
+            .iter()
+            .map(|c| c.address_index)
+            .collect::<BTreeSet<_>>();
+        assert_eq!(addresses, [0, 1].into_iter().collect());
+    }
+
+    #[test]
+    fn insufficient_notes() {
+        for strategy in [
+            SelectionStrategy::Random,
+            SelectionStrategy::PrivacyMax,
+            SelectionStrategy::FeeMin,
+            SelectionStrategy::AgePriority,
+        ] {
+            assert!(strategy
+                .select(&mut OsRng, candidates(&[(0, 10), (1, 10)]), 21)
+                .is_none());
+        }
+    }
+}
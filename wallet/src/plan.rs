@@ -0,0 +1,403 @@
+use std::collections::BTreeMap;
+
+use anyhow::anyhow;
+use penumbra_crypto::{
+    asset, memo,
+    merkle::{self, TreeExt},
+    Address, Note, Value,
+};
+use penumbra_proto::view as pb;
+use penumbra_stake::{Delegate, RateData, STAKING_TOKEN_ASSET_ID};
+use rand_core::{CryptoRng, RngCore};
+
+use crate::ClientState;
+
+/// Something a transaction should do, leaving which notes pay for it to the planner.
+#[derive(Clone, Debug)]
+pub enum Intent {
+    /// Send `values` to `address`, attaching `memo` to each output.
+    Send {
+        address: Address,
+        values: Vec<Value>,
+        memo: Option<String>,
+    },
+    /// Delegate `unbonded_amount` of stake to the validator whose next rates are `rate_data`.
+    Delegate {
+        rate_data: RateData,
+        unbonded_amount: u64,
+    },
+}
+
+/// A transaction planned by [`ClientState::plan_transaction`]: which notes it spends and which
+/// outputs and delegations it makes, leaving only the randomness, proofs and signatures to the
+/// builder.
+#[derive(Clone, Debug)]
+pub struct TransactionPlan {
+    pub chain_id: String,
+    /// The anchor the spends are proven against.
+    pub anchor: merkle::Root,
+    pub fee: Value,
+    pub spends: Vec<SpendPlan>,
+    pub outputs: Vec<OutputPlan>,
+    pub delegations: Vec<Delegate>,
+}
+
+/// A note chosen to be spent by a [`TransactionPlan`].
+#[derive(Clone, Debug)]
+pub struct SpendPlan {
+    pub note: Note,
+    /// The position of the note in the note commitment tree.
+    pub position: u64,
+    /// The index of the address the note was sent to.
+    pub address_index: u64,
+}
+
+/// An output to be created by a [`TransactionPlan`].
+#[derive(Clone, Debug)]
+pub struct OutputPlan {
+    pub address: Address,
+    pub value: Value,
+    pub memo: Option<String>,
+}
+
+impl ClientState {
+    /// Plans a transaction that does what `intents` describe and pays `fee`, choosing which notes
+    /// to spend with the wallet's [`SelectionStrategy`](crate::SelectionStrategy), and sending any
+    /// change back to the address each spent amount came from.
+    ///
+    /// Unlike the `build_*` methods, this doesn't mark the chosen notes as spent.  If
+    /// `source_address` is `Some`, only notes sent to that address are spent.
+    pub fn plan_transaction<R: CryptoRng + RngCore>(
+        &self,
+        rng: &mut R,
+        intents: Vec<Intent>,
+        fee: Value,
+        source_address: Option<u64>,
+    ) -> Result<TransactionPlan, anyhow::Error> {
+        let chain_id = self.chain_id().ok_or_else(|| anyhow!("missing chain_id"))?;
+
+        // Delegation tokens go to the source address, or to the default address if there is none.
+        let (_label, self_address) = self
+            .wallet()
+            .address_by_index(source_address.unwrap_or(0) as usize)?;
+
+        let mut outputs = Vec::new();
+        let mut delegations = Vec::new();
+        let mut value_to_spend = BTreeMap::<asset::Id, u64>::new();
+        for intent in intents {
+            match intent {
+                Intent::Send {
+                    address,
+                    values,
+                    memo,
+                } => {
+                    if let Some(memo) = &memo {
+                        memo::MemoPlaintext::try_from(memo.clone())?;
+                    }
+                    for value in values {
+                        add_to_spend(&mut value_to_spend, value.asset_id, value.amount)?;
+                        outputs.push(OutputPlan {
+                            address,
+                            value,
+                            memo: memo.clone(),
+                        });
+                    }
+                }
+                Intent::Delegate {
+                    rate_data,
+                    unbonded_amount,
+                } => {
+                    add_to_spend(
+                        &mut value_to_spend,
+                        *STAKING_TOKEN_ASSET_ID,
+                        unbonded_amount,
+                    )?;
+                    outputs.push(OutputPlan {
+                        address: self_address,
+                        value: Value {
+                            amount: rate_data.delegation_amount(unbonded_amount),
+                            asset_id: rate_data.identity_key.delegation_token().id(),
+                        },
+                        memo: None,
+                    });
+                    delegations.push(Delegate {
+                        delegation_amount: rate_data.delegation_amount(unbonded_amount),
+                        epoch_index: rate_data.epoch_index,
+                        unbonded_amount,
+                        validator_identity: rate_data.identity_key,
+                    });
+                }
+            }
+        }
+        add_to_spend(&mut value_to_spend, fee.asset_id, fee.amount)?;
+
+        let mut spends = Vec::new();
+        for (asset_id, amount) in value_to_spend {
+            if amount == 0 {
+                continue;
+            }
+            let denom = self
+                .asset_cache()
+                .get(&asset_id)
+                .ok_or_else(|| anyhow!("unknown denomination for asset id {}", asset_id))?;
+
+            let candidates = self.select_notes(rng, amount, denom, source_address)?;
+            let change_address = self
+                .wallet()
+                .change_address(&candidates.last().expect("selected at least one note").note)?;
+            let spent: u64 = candidates.iter().map(|c| c.note.amount()).sum();
+            spends.extend(candidates.into_iter().map(|c| SpendPlan {
+                note: c.note,
+                position: c.position,
+                address_index: c.address_index,
+            }));
+
+            let change = spent - amount;
+            if change > 0 {
+                outputs.push(OutputPlan {
+                    address: change_address,
+                    value: Value {
+                        amount: change,
+                        asset_id,
+                    },
+                    memo: None,
+                });
+            }
+        }
+
+        Ok(TransactionPlan {
+            chain_id,
+            anchor: self.note_commitment_tree().root2(),
+            fee,
+            spends,
+            outputs,
+            delegations,
+        })
+    }
+}
+
+/// Adds `amount` to the total of `asset_id` a transaction needs to spend, failing rather than
+/// wrapping around if the amounts requested don't fit in a single total.
+fn add_to_spend(
+    value_to_spend: &mut BTreeMap<asset::Id, u64>,
+    asset_id: asset::Id,
+    amount: u64,
+) -> Result<(), anyhow::Error> {
+    let total = value_to_spend.entry(asset_id).or_default();
+    *total = total
+        .checked_add(amount)
+        .ok_or_else(|| anyhow!("total amount of asset id {} to spend overflows", asset_id))?;
+    Ok(())
+}
+
+impl From<TransactionPlan> for pb::TransactionPlan {
+    fn from(plan: TransactionPlan) -> Self {
+        pb::TransactionPlan {
+            chain_id: plan.chain_id,
+            anchor: Some(plan.anchor.into()),
+            fee: Some(plan.fee.into()),
+            spends: plan.spends.into_iter().map(Into::into).collect(),
+            outputs: plan.outputs.into_iter().map(Into::into).collect(),
+            delegations: plan.delegations.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<SpendPlan> for pb::SpendPlan {
+    fn from(spend: SpendPlan) -> Self {
+        pb::SpendPlan {
+            note: spend.note.to_bytes().to_vec(),
+            position: spend.position,
+            address_index: spend.address_index,
+        }
+    }
+}
+
+impl From<OutputPlan> for pb::OutputPlan {
+    fn from(output: OutputPlan) -> Self {
+        pb::OutputPlan {
+            address: Some(output.address.into()),
+            value: Some(output.value.into()),
+            memo: output.memo.unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_chain::params::ChainParams;
+    use penumbra_crypto::rdsa::{SigningKey, SpendAuth, VerificationKey};
+    use penumbra_stake::{IdentityKey, STAKING_TOKEN_DENOM};
+    use rand_core::OsRng;
+
+    use super::*;
+    use crate::{SelectionStrategy, Wallet};
+
+    const FEE: u64 = 1;
+
+    /// Makes a client state with two addresses, holding notes of the staking token with the given
+    /// (address index, amount) pairs.
+    fn client_with_notes(notes: &[(u64, u64)]) -> ClientState {
+        let mut client = ClientState::new(Wallet::generate(OsRng));
+        *client.chain_params_mut() = Some(ChainParams {
+            chain_id: "penumbra-test".to_string(),
+            ..Default::default()
+        });
+        client
+            .asset_cache_mut()
+            .extend([STAKING_TOKEN_DENOM.clone()]);
+        client.set_note_selection(SelectionStrategy::FeeMin);
+        client.wallet_mut().new_address("Second".to_string());
+
+        for &(address_index, amount) in notes {
+            let (_label, address) = client
+                .wallet()
+                .address_by_index(address_index as usize)
+                .unwrap();
+            let note = Note::generate(
+                &mut OsRng,
+                &address,
+                Value {
+                    amount,
+                    asset_id: *STAKING_TOKEN_ASSET_ID,
+                },
+            );
+            client.receive_note(note);
+        }
+        client
+    }
+
+    fn fee() -> Value {
+        Value {
+            amount: FEE,
+            asset_id: *STAKING_TOKEN_ASSET_ID,
+        }
+    }
+
+    fn spent(plan: &TransactionPlan) -> u64 {
+        plan.spends.iter().map(|spend| spend.note.amount()).sum()
+    }
+
+    #[test]
+    fn send_spends_enough_and_returns_change() {
+        let client = client_with_notes(&[(0, 100), (0, 50), (0, 10)]);
+        let (_label, recipient) = Wallet::generate(OsRng).address_by_index(0).unwrap();
+        let (_label, source) = client.wallet().address_by_index(0).unwrap();
+
+        let plan = client
+            .plan_transaction(
+                &mut OsRng,
+                vec![Intent::Send {
+                    address: recipient,
+                    values: vec![Value {
+                        amount: 120,
+                        asset_id: *STAKING_TOKEN_ASSET_ID,
+                    }],
+                    memo: None,
+                }],
+                fee(),
+                None,
+            )
+            .unwrap();
+
+        assert_eq!(spent(&plan), 150);
+        assert_eq!(plan.outputs.len(), 2);
+        assert_eq!(plan.outputs[0].address, recipient);
+        assert_eq!(plan.outputs[0].value.amount, 120);
+        // The change goes back to the address the spent notes came from.
+        assert_eq!(plan.outputs[1].address, source);
+        assert_eq!(plan.outputs[1].value.amount, 150 - 120 - FEE);
+        assert!(plan.delegations.is_empty());
+    }
+
+    #[test]
+    fn change_returns_to_source_address() {
+        let client = client_with_notes(&[(0, 1_000), (1, 100)]);
+        let (_label, recipient) = Wallet::generate(OsRng).address_by_index(0).unwrap();
+        let (_label, source) = client.wallet().address_by_index(1).unwrap();
+
+        let plan = client
+            .plan_transaction(
+                &mut OsRng,
+                vec![Intent::Send {
+                    address: recipient,
+                    values: vec![Value {
+                        amount: 40,
+                        asset_id: *STAKING_TOKEN_ASSET_ID,
+                    }],
+                    memo: None,
+                }],
+                fee(),
+                Some(1),
+            )
+            .unwrap();
+
+        assert_eq!(spent(&plan), 100);
+        assert!(plan.spends.iter().all(|spend| spend.address_index == 1));
+        let change = plan.outputs.last().unwrap();
+        assert_eq!(change.address, source);
+        assert_eq!(change.value.amount, 100 - 40 - FEE);
+    }
+
+    #[test]
+    fn delegate_outputs_delegation_tokens_to_self() {
+        let client = client_with_notes(&[(0, 1_000)]);
+        let (_label, self_address) = client.wallet().address_by_index(0).unwrap();
+        let identity_key = IdentityKey(VerificationKey::from(&SigningKey::<SpendAuth>::new(OsRng)));
+        let rate_data = RateData {
+            identity_key: identity_key.clone(),
+            epoch_index: 1,
+            validator_reward_rate: 0,
+            validator_exchange_rate: 1_0000_0000,
+        };
+
+        let plan = client
+            .plan_transaction(
+                &mut OsRng,
+                vec![Intent::Delegate {
+                    rate_data,
+                    unbonded_amount: 300,
+                }],
+                fee(),
+                None,
+            )
+            .unwrap();
+
+        assert_eq!(spent(&plan), 1_000);
+        assert_eq!(plan.delegations.len(), 1);
+        assert_eq!(plan.delegations[0].unbonded_amount, 300);
+        assert_eq!(plan.delegations[0].delegation_amount, 300);
+        assert_eq!(plan.outputs.len(), 2);
+        assert_eq!(plan.outputs[0].address, self_address);
+        assert_eq!(
+            plan.outputs[0].value,
+            Value {
+                amount: 300,
+                asset_id: identity_key.delegation_token().id(),
+            }
+        );
+        assert_eq!(plan.outputs[1].value.amount, 1_000 - 300 - FEE);
+    }
+
+    #[test]
+    fn overflowing_amounts_are_rejected() {
+        let client = client_with_notes(&[(0, 1_000)]);
+        let (_label, recipient) = Wallet::generate(OsRng).address_by_index(0).unwrap();
+        let value = |amount| Value {
+            amount,
+            asset_id: *STAKING_TOKEN_ASSET_ID,
+        };
+
+        let result = client.plan_transaction(
+            &mut OsRng,
+            vec![Intent::Send {
+                address: recipient,
+                values: vec![value(u64::MAX), value(2)],
+                memo: None,
+            }],
+            fee(),
+            None,
+        );
+        assert!(result.unwrap_err().to_string().contains("overflows"));
+    }
+}
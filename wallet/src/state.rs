@@ -1,5 +1,5 @@
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
     mem,
     time::{Duration, SystemTime},
 };
@@ -9,24 +9,34 @@ use penumbra_chain::params::ChainParams;
 use penumbra_crypto::{
     asset::{self, Denom},
     memo,
-    merkle::{Frontier, NoteCommitmentTree, Tree, TreeExt},
+    merkle::{self, Frontier, NoteCommitmentTree, Tree, TreeExt},
     note, Address, FieldExt, Note, Nullifier, Value,
 };
-use penumbra_proto::light_wallet::{CompactBlock, StateFragment};
-use penumbra_stake::{RateData, STAKING_TOKEN_ASSET_ID, STAKING_TOKEN_DENOM};
-use penumbra_transaction::Transaction;
-use rand::seq::SliceRandom;
+use penumbra_proto::{
+    light_wallet::{CompactBlock, StateFragment},
+    thin_wallet::{NoteWitness, NoteWitnesses},
+};
+use penumbra_stake::{Epoch, RateData, RewardSource, STAKING_TOKEN_ASSET_ID, STAKING_TOKEN_DENOM};
+use penumbra_transaction::{Padding, Transaction};
 use rand_core::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
-use crate::Wallet;
+use crate::{
+    note_selection::{Candidate, SelectionStrategy},
+    Wallet,
+};
+
+pub use serde_helpers::ClientStateHelper;
 
 const MAX_MERKLE_CHECKPOINTS_CLIENT: usize = 10;
 
 /// The time after which a locally cached submitted transaction is considered to have failed.
 const SUBMITTED_TRANSACTION_TIMEOUT: Duration = Duration::from_secs(60);
 
+/// The newest compact block format this wallet can scan.
+pub const COMPACT_BLOCK_VERSION: u32 = 1;
+
 /// State about the chain and our transactions.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(
@@ -38,16 +48,38 @@ pub struct ClientState {
     last_block_height: Option<u64>,
     /// Note commitment tree.
     note_commitment_tree: NoteCommitmentTree,
+    /// Authentication paths fetched from a node for notes we aren't witnessing in our own note
+    /// commitment tree, which were checked against its current root.
+    ///
+    /// They're only valid against that root, so they're dropped whenever we scan a block, and
+    /// aren't saved.
+    fetched_witnesses: BTreeMap<note::Commitment, merkle::Path>,
     /// Our nullifiers and the notes they correspond to.
     nullifier_map: BTreeMap<Nullifier, note::Commitment>,
     /// Notes that we have received.
     unspent_set: BTreeMap<note::Commitment, Note>,
+    /// The index of the address each note we found while scanning was sent to.
+    address_indices: BTreeMap<note::Commitment, u64>,
     /// Notes that we have spent but which have not yet been confirmed on-chain.
     submitted_spend_set: BTreeMap<note::Commitment, (SystemTime, Note)>,
     /// Notes that we anticipate receiving on-chain as change but which have not yet been confirmed.
     submitted_change_set: BTreeMap<note::Commitment, (SystemTime, Note)>,
     /// Notes that we have spent.
     spent_set: BTreeMap<note::Commitment, Note>,
+    /// Spent notes whose spends could still be reverted, with the height of the block expected to
+    /// release them from quarantine.
+    ///
+    /// A spend in the same transaction as an undelegation is quarantined with it, and reverted if
+    /// the validator is slashed before it's released, so we keep the witnesses of these notes
+    /// until then, to be able to spend them again.
+    revertible_spends: BTreeMap<note::Commitment, u64>,
+    /// Outputs of our undelegations, which are quarantined until their unbonding period ends, with
+    /// the height of the block expected to release them.
+    unbonding_set: BTreeMap<note::Commitment, (u64, Note)>,
+    /// The notes spent by the transactions producing notes in the unbonding set, until one of
+    /// those spends is confirmed, which means the transaction was included and its outputs
+    /// quarantined.
+    unconfirmed_unbondings: BTreeMap<note::Commitment, Vec<note::Commitment>>,
     /// Map of note commitment to full transaction data for transactions we have visibility into.
     transactions: BTreeMap<note::Commitment, Option<Vec<u8>>>,
     /// Map of asset IDs to (raw) asset denominations.
@@ -56,6 +88,20 @@ pub struct ClientState {
     wallet: Wallet,
     /// Global chain parameters. May not have been fetched yet.
     chain_params: Option<ChainParams>,
+    /// How to pad the transactions we build with dummy spends and outputs.
+    ///
+    /// This is a client setting rather than wallet state, so it isn't saved.
+    padding: Padding,
+    /// How to choose which notes to spend.
+    ///
+    /// Like the padding, this is a client setting, so it isn't saved.
+    note_selection: SelectionStrategy,
+    /// Whether to leave the notes we receive unwitnessed in our note commitment tree, and spend
+    /// them with authentication paths fetched from a node instead, which keeps the tree small
+    /// when restoring a wallet with a long history.
+    ///
+    /// Like the padding, this is a client setting, so it isn't saved.
+    fetch_witnesses: bool,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -88,6 +134,50 @@ impl<'a> UnspentNote<'a> {
     }
 }
 
+/// A change to the wallet's notes observed while scanning a block.
+///
+/// Quarantined notes (such as the output of an undelegation) are not included
+/// in compact blocks until they are released, so they are reported as
+/// [`ScanEvent::NoteReceived`] at the height of their release.  The outputs of
+/// our own undelegations and redelegations are also reported as
+/// [`ScanEvent::NoteQuarantined`] when the transaction is included.
+#[derive(Clone, Debug)]
+pub enum ScanEvent {
+    /// A note sent to one of our addresses was included in a block.
+    NoteReceived {
+        height: u64,
+        note_commitment: note::Commitment,
+        note: Note,
+        /// The index of the address the note was sent to.
+        address_index: u64,
+        /// If the note pays one of a validator's funding streams, rather than being sent by a
+        /// transaction, which validator and epoch it was paid for.
+        reward_source: Option<RewardSource>,
+    },
+    /// One of our notes was spent.
+    NoteSpent {
+        height: u64,
+        note_commitment: note::Commitment,
+        note: Note,
+    },
+    /// The spend of one of our notes was reverted, because it was quarantined for a validator that
+    /// was slashed, so the note can be spent again.
+    NoteRestored {
+        height: u64,
+        note_commitment: note::Commitment,
+        note: Note,
+    },
+    /// A transaction of ours producing a note that's quarantined until its unbonding period ends,
+    /// such as an undelegation, was included in a block.
+    NoteQuarantined {
+        height: u64,
+        note_commitment: note::Commitment,
+        note: Note,
+        /// The height of the block expected to release the note.
+        release_height: u64,
+    },
+}
+
 impl AsRef<Note> for UnspentNote<'_> {
     fn as_ref(&self) -> &Note {
         match self {
@@ -103,15 +193,23 @@ impl ClientState {
         Self {
             last_block_height: None,
             note_commitment_tree: NoteCommitmentTree::new(MAX_MERKLE_CHECKPOINTS_CLIENT),
+            fetched_witnesses: BTreeMap::new(),
             nullifier_map: BTreeMap::new(),
             unspent_set: BTreeMap::new(),
+            address_indices: BTreeMap::new(),
             submitted_spend_set: BTreeMap::new(),
             submitted_change_set: BTreeMap::new(),
             spent_set: BTreeMap::new(),
+            revertible_spends: BTreeMap::new(),
+            unbonding_set: BTreeMap::new(),
+            unconfirmed_unbondings: BTreeMap::new(),
             transactions: BTreeMap::new(),
             asset_cache: Default::default(),
             wallet,
             chain_params: None,
+            padding: Padding::default(),
+            note_selection: SelectionStrategy::default(),
+            fetch_witnesses: false,
         }
     }
 
@@ -145,6 +243,162 @@ impl ClientState {
         &mut self.chain_params
     }
 
+    /// Sets how to pad the transactions we build with dummy spends and outputs.
+    pub fn set_padding(&mut self, padding: Padding) {
+        self.padding = padding;
+    }
+
+    /// Returns how to pad the transactions we build with dummy spends and outputs.
+    pub fn padding(&self) -> Padding {
+        self.padding
+    }
+
+    /// Sets how to choose which notes to spend.
+    pub fn set_note_selection(&mut self, note_selection: SelectionStrategy) {
+        self.note_selection = note_selection;
+    }
+
+    /// Returns how to choose which notes to spend.
+    pub fn note_selection(&self) -> SelectionStrategy {
+        self.note_selection
+    }
+
+    /// Sets whether to leave the notes we receive unwitnessed, and spend them with authentication
+    /// paths fetched from a node instead.
+    pub fn set_fetch_witnesses(&mut self, fetch_witnesses: bool) {
+        self.fetch_witnesses = fetch_witnesses;
+    }
+
+    /// Returns whether to leave the notes we receive unwitnessed, and spend them with
+    /// authentication paths fetched from a node instead.
+    pub fn fetch_witnesses(&self) -> bool {
+        self.fetch_witnesses
+    }
+
+    /// Returns the commitments of our unspent notes that we have no authentication path for,
+    /// which have to be fetched with [`Self::add_note_witnesses`] before the notes can be spent.
+    pub fn unwitnessed_notes(&self) -> Vec<note::Commitment> {
+        self.unspent_set
+            .keys()
+            .filter(|commitment| {
+                !self.fetched_witnesses.contains_key(commitment)
+                    && self
+                        .note_commitment_tree
+                        .authentication_path(commitment)
+                        .is_none()
+            })
+            .copied()
+            .collect()
+    }
+
+    /// Adds authentication paths for our notes fetched from a node, returning how many were added.
+    ///
+    /// The paths must be against the root of our note commitment tree at the height we've scanned
+    /// to, which we've already checked against the roots the node reported block by block.  Each
+    /// path must authenticate its note against that root, at the position we derived the note's
+    /// nullifier from, so that a node can't make us build transactions the chain would reject, or
+    /// miss our own spends.
+    pub fn add_note_witnesses(
+        &mut self,
+        NoteWitnesses {
+            height,
+            anchor,
+            witnesses,
+        }: NoteWitnesses,
+    ) -> Result<usize, anyhow::Error> {
+        if Some(height) != self.last_block_height {
+            return Err(anyhow!(
+                "note witnesses are for height {}, but the wallet has scanned to height {:?}",
+                height,
+                self.last_block_height
+            ));
+        }
+        let anchor = merkle::Root::try_from(anchor.ok_or_else(|| anyhow!("missing anchor"))?)?;
+        let root = self.note_commitment_tree.root2();
+        if anchor != root {
+            return Err(anyhow!(
+                "note witnesses are against anchor {}, but the wallet's note commitment tree root is {}",
+                hex::encode(anchor.to_bytes()),
+                hex::encode(root.to_bytes())
+            ));
+        }
+
+        let mut added = 0;
+        for NoteWitness {
+            note_commitment,
+            position,
+            auth_path,
+        } in witnesses
+        {
+            let note_commitment = note::Commitment::try_from(
+                note_commitment.ok_or_else(|| anyhow!("missing note commitment"))?,
+            )?;
+            let auth_path = auth_path
+                .into_iter()
+                .map(note::Commitment::try_from)
+                .collect::<Result<Vec<_>, _>>()?;
+            if auth_path.len() != merkle::DEPTH {
+                return Err(anyhow!(
+                    "authentication path for note commitment {:?} has length {}, expected {}",
+                    note_commitment,
+                    auth_path.len(),
+                    merkle::DEPTH
+                ));
+            }
+            let path = (merkle::Position::from(position as usize), auth_path);
+            if merkle::root_from_path(&note_commitment, &path) != root {
+                return Err(anyhow!(
+                    "authentication path for note commitment {:?} does not lead to the anchor",
+                    note_commitment
+                ));
+            }
+            let nullifier = self
+                .wallet
+                .full_viewing_key()
+                .derive_nullifier(path.0, &note_commitment);
+            if self.nullifier_map.get(&nullifier) != Some(&note_commitment) {
+                return Err(anyhow!(
+                    "authentication path for note commitment {:?} is at position {}, where we didn't find it",
+                    note_commitment,
+                    position
+                ));
+            }
+
+            // There's no use keeping paths for notes we can't spend.
+            if self.unspent_set.contains_key(&note_commitment) {
+                self.fetched_witnesses.insert(note_commitment, path);
+                added += 1;
+            }
+        }
+
+        Ok(added)
+    }
+
+    /// Returns the authentication path to spend `note` with against the current root of our note
+    /// commitment tree: our own if we're witnessing it, or one fetched from a node otherwise.
+    pub fn note_witness(&self, note: &Note) -> Result<merkle::Path, anyhow::Error> {
+        let commitment = note.commit();
+        self.note_commitment_tree
+            .authentication_path(&commitment)
+            .or_else(|| self.fetched_witnesses.get(&commitment).cloned())
+            .ok_or_else(|| {
+                anyhow!(
+                    "no authentication path for note commitment {:?}; fetch its witness from the node to spend it",
+                    commitment
+                )
+            })
+    }
+
+    /// Returns the number of notes in the note commitment tree, which is the position of the next
+    /// one to be appended.
+    fn note_commitment_tree_size(&self) -> u64 {
+        self.note_commitment_tree
+            .bridges()
+            .last()
+            .map(|b| u64::from(b.frontier().position()) + 1)
+            .unwrap_or(0)
+    }
+
     /// Returns a mutable reference to the wallet the state is tracking.
     pub fn wallet_mut(&mut self) -> &mut Wallet {
         &mut self.wallet
@@ -169,6 +423,18 @@ impl ClientState {
             .insert(commitment, (timeout, note));
     }
 
+    /// Adds a note to the unspent set as if it had just been found while scanning, so that tests
+    /// can plan transactions without syncing a chain.
+    #[cfg(test)]
+    pub(crate) fn receive_note(&mut self, note: Note) {
+        let commitment = note.commit();
+        self.note_commitment_tree.append(&commitment);
+        self.note_commitment_tree.witness();
+        self.address_indices
+            .insert(commitment, self.diversifier_index(&note));
+        self.unspent_set.insert(commitment, note);
+    }
+
     /// Register a note as spent.
     ///
     /// This marks the note as having been spent (pending confirmation) by the
@@ -186,6 +452,60 @@ impl ClientState {
         self.submitted_spend_set.insert(commitment, (timeout, note));
     }
 
+    /// Add the output of an undelegation or redelegation to the unbonding set, until it is released
+    /// from quarantine.
+    ///
+    /// The release height is estimated from the chain parameters, assuming the undelegation is
+    /// included in the next block.  Once one of the `spent` notes, those spent by the same
+    /// transaction, is seen spent on chain, the note is reported as quarantined, and its release
+    /// height corrected.
+    pub fn register_unbonding(
+        &mut self,
+        note: Note,
+        spent: &[note::Commitment],
+    ) -> Result<(), anyhow::Error> {
+        let chain_params = self
+            .chain_params()
+            .ok_or_else(|| anyhow!("missing chain params"))?;
+        let height = self.last_block_height.map(|h| h + 1).unwrap_or(0);
+        let release_height = unbonding_release_height(height, chain_params);
+        let commitment = note.commit();
+        self.unbonding_set
+            .insert(commitment, (release_height, note));
+        self.unconfirmed_unbondings
+            .insert(commitment, spent.to_vec());
+        Ok(())
+    }
+
+    /// Returns the outputs of our undelegations that have not been released from quarantine yet,
+    /// with the height of the block expected to release each.
+    pub fn unbonding_notes(&self) -> impl Iterator<Item = (u64, &Note)> {
+        self.unbonding_set
+            .values()
+            .map(|(release_height, note)| (*release_height, note))
+    }
+
+    /// Returns a description of the amount of `denom` still unbonding, and when it is expected to
+    /// be released, if any is.
+    fn unbonding_message(&self, denom: &Denom) -> Option<String> {
+        let (amount, release_height) = self
+            .unbonding_notes()
+            .filter(|(_, note)| note.asset_id() == denom.id())
+            .fold(None, |acc, (release_height, note)| {
+                let (amount, latest) = acc.unwrap_or((0, 0));
+                Some((amount + note.amount(), u64::max(latest, release_height)))
+            })?;
+        let epoch_duration = self.chain_params()?.epoch_duration;
+        Some(format!(
+            "{}{} is still unbonding, and can't be spent until it is released at the end of \
+             epoch {} (height {})",
+            amount,
+            denom,
+            Epoch::from_height(release_height, epoch_duration).index,
+            release_height
+        ))
+    }
+
     /// Returns a list of notes to spend to release (at least) the provided
     /// value.
     ///
@@ -193,7 +513,8 @@ impl ClientState {
     /// been spent (pending confirmation) by the chain.
     ///
     /// If `source_address` is `Some`, restrict to only the notes sent to that
-    /// address.
+    /// address.  Which of the available notes are spent is up to the
+    /// [`SelectionStrategy`].
     pub fn notes_to_spend<R: CryptoRng + RngCore>(
         &mut self,
         rng: &mut R,
@@ -201,55 +522,76 @@ impl ClientState {
         denom: &Denom,
         source_address: Option<u64>,
     ) -> Result<Vec<Note>, anyhow::Error> {
+        let notes_to_spend = self
+            .select_notes(rng, amount, denom, source_address)?
+            .into_iter()
+            .map(|candidate| candidate.note)
+            .collect::<Vec<_>>();
+
+        // Before returning the notes to the caller, mark them as having been
+        // spent.  (If the caller does not spend them, or the tx fails, etc.,
+        // this state will be erased after the timeout).
+        for note in &notes_to_spend {
+            self.register_spend(note);
+        }
+
+        Ok(notes_to_spend)
+    }
+
+    /// Chooses notes to spend to release (at least) the provided value, like
+    /// [`Self::notes_to_spend`], but without marking them as spent.
+    pub(crate) fn select_notes<R: CryptoRng + RngCore>(
+        &self,
+        rng: &mut R,
+        amount: u64,
+        denom: &Denom,
+        source_address: Option<u64>,
+    ) -> Result<Vec<Candidate>, anyhow::Error> {
+        let unbonding_message = self.unbonding_message(denom);
+        let insufficient = |message: String| match &unbonding_message {
+            Some(unbonding_message) => anyhow!("{}: {}", message, unbonding_message),
+            None => anyhow!(message),
+        };
+
         let mut notes_by_address = self
             .unspent_notes_by_denom_and_address()
             .remove(denom)
-            .ok_or_else(|| anyhow::anyhow!("no notes of denomination {} found", denom))?;
+            .ok_or_else(|| insufficient(format!("no notes of denomination {} found", denom)))?;
 
-        let mut notes = if let Some(source) = source_address {
-            notes_by_address.remove(&source).ok_or_else(|| {
+        if let Some(source) = source_address {
+            let notes = notes_by_address.remove(&source).ok_or_else(|| {
                 anyhow::anyhow!(
                     "no notes of denomination {} found in address {}",
                     denom,
                     source
                 )
-            })?
-        } else {
-            notes_by_address.values().flatten().cloned().collect()
-        };
-
-        // Draw notes in a random order, to avoid leaking information via arity.
-        notes.shuffle(rng);
-
-        let mut notes_to_spend = Vec::new();
-        let mut total_spend_value = 0u64;
-        for note in notes.into_iter() {
-            // A note is only spendable if it has been confirmed on chain to us (change outputs
-            // cannot be spent yet because they do not have a position):
-            if let UnspentNote::Ready(note) = note {
-                notes_to_spend.push(note.clone());
-                total_spend_value += note.amount();
-
-                if total_spend_value >= amount {
-                    break;
-                }
-            }
+            })?;
+            notes_by_address = BTreeMap::from([(source, notes)]);
         }
 
-        if total_spend_value >= amount {
-            // Before returning the notes to the caller, mark them as having been
-            // spent.  (If the caller does not spend them, or the tx fails, etc.,
-            // this state will be erased after the timeout).
-            for note in &notes_to_spend {
-                self.register_spend(note);
-            }
+        // A note is only spendable if it has been confirmed on chain to us (change outputs
+        // cannot be spent yet because they do not have a position):
+        let candidates = notes_by_address
+            .into_iter()
+            .flat_map(|(address_index, notes)| {
+                notes
+                    .into_iter()
+                    .filter_map(|note| note.as_ready())
+                    .map(move |note| (address_index, note))
+            })
+            .map(|(address_index, note)| Candidate {
+                note: note.clone(),
+                address_index,
+                position: self
+                    .note_witness(note)
+                    .map(|(position, _)| u64::from(position))
+                    .unwrap_or(u64::MAX),
+            })
+            .collect();
 
-            Ok(notes_to_spend)
-        } else {
-            Err(anyhow::anyhow!(
-                "not enough available notes for requested spend"
-            ))
-        }
+        self.note_selection
+            .select(rng, candidates, amount)
+            .ok_or_else(|| insufficient("not enough available notes for requested spend".into()))
     }
 
     /// Returns the chain id, if the chain parameters are set.
@@ -274,6 +616,7 @@ impl ClientState {
             .address_by_index(source_address.unwrap_or(0) as usize)?;
 
         let mut tx_builder = Transaction::build_with_root(self.note_commitment_tree.root2());
+        tx_builder.set_padding(self.padding);
 
         tx_builder
             .set_fee(fee)
@@ -285,12 +628,8 @@ impl ClientState {
 
         for note in self.notes_to_spend(rng, spend_amount, &*STAKING_TOKEN_DENOM, source_address)? {
             spent_amount += note.amount();
-            tx_builder.add_spend(
-                rng,
-                &self.note_commitment_tree,
-                self.wallet.spend_key(),
-                note,
-            )?;
+            let merkle_path = self.note_witness(&note)?;
+            tx_builder.add_spend_with_path(rng, merkle_path, self.wallet.spend_key(), note);
         }
 
         let delegation_note = tx_builder.add_output_producing_note(
@@ -343,6 +682,7 @@ impl ClientState {
             .address_by_index(source_address.unwrap_or(0) as usize)?;
 
         let mut tx_builder = Transaction::build_with_root(self.note_commitment_tree.root2());
+        tx_builder.set_padding(self.padding);
 
         tx_builder
             .set_fee(fee)
@@ -368,17 +708,15 @@ impl ClientState {
         // all of their remaining delegation tokens will also be quarantined.
         // this sucks lmao
         let mut spent_amount = 0;
+        let mut spent = Vec::new();
 
         for note in
             self.notes_to_spend(rng, delegation_amount, &delegation_denom, source_address)?
         {
             spent_amount += note.amount();
-            tx_builder.add_spend(
-                rng,
-                &self.note_commitment_tree,
-                self.wallet.spend_key(),
-                note,
-            )?;
+            spent.push(note.commit());
+            let merkle_path = self.note_witness(&note)?;
+            tx_builder.add_spend_with_path(rng, merkle_path, self.wallet.spend_key(), note);
         }
 
         let output_note = tx_builder.add_output_producing_note(
@@ -409,7 +747,100 @@ impl ClientState {
             self.register_change(change_note);
         }
 
-        self.register_change(output_note);
+        // The unbonded stake is quarantined, so it can't be spent like ordinary change.
+        self.register_unbonding(output_note, &spent)?;
+
+        tx_builder.finalize(rng).map_err(Into::into)
+    }
+
+    /// Generate a new transaction moving delegation tokens from one validator to another, without
+    /// unbonding the stake.
+    ///
+    /// The redelegation is authorized by the wallet's spend authorization key, which identifies the
+    /// delegator to the chain, so this wallet's redelegations can be linked to each other.
+    ///
+    /// The chain quarantines the first redelegation by each delegator key like an undelegation, and
+    /// whether this is the first isn't known here, so every output is added to the unbonding set.
+    /// Outputs that aren't quarantined are found in the next block like any other.
+    #[instrument(skip(self, rng))]
+    pub fn build_redelegate<R: RngCore + CryptoRng>(
+        &mut self,
+        rng: &mut R,
+        from_rate_data: RateData,
+        to_rate_data: RateData,
+        delegation_amount: u64,
+        fee: u64,
+        source_address: Option<u64>,
+    ) -> Result<Transaction, anyhow::Error> {
+        // If the source address is set, send the delegation tokens to the same
+        // address; otherwise, send them to the default address.
+        let (_label, self_address) = self
+            .wallet()
+            .address_by_index(source_address.unwrap_or(0) as usize)?;
+
+        let mut tx_builder = Transaction::build_with_root(self.note_commitment_tree.root2());
+        tx_builder.set_padding(self.padding);
+
+        tx_builder
+            .set_fee(fee)
+            .set_chain_id(self.chain_id().ok_or_else(|| anyhow!("missing chain_id"))?)
+            .add_redelegation(
+                &from_rate_data,
+                &to_rate_data,
+                delegation_amount,
+                self.wallet.spend_key().spend_auth_key().clone(),
+            );
+
+        // The fee is paid from the wallet's unbonded stake like any other, even if the outputs
+        // end up quarantined.
+        let from_denom = from_rate_data.identity_key.delegation_token().denom();
+        let mut spent_from_amount = 0;
+        let mut spent = Vec::new();
+        for note in self.notes_to_spend(rng, delegation_amount, &from_denom, source_address)? {
+            spent_from_amount += note.amount();
+            spent.push(note.commit());
+            let merkle_path = self.note_witness(&note)?;
+            tx_builder.add_spend_with_path(rng, merkle_path, self.wallet.spend_key(), note);
+        }
+        let mut spent_fee_amount = 0;
+        if fee > 0 {
+            for note in self.notes_to_spend(rng, fee, &*STAKING_TOKEN_DENOM, source_address)? {
+                spent_fee_amount += note.amount();
+                spent.push(note.commit());
+                let merkle_path = self.note_witness(&note)?;
+                tx_builder.add_spend_with_path(rng, merkle_path, self.wallet.spend_key(), note);
+            }
+        }
+
+        let unbonded_amount = from_rate_data.unbonded_amount(delegation_amount);
+        let delegation_note = tx_builder.add_output_producing_note(
+            rng,
+            &self_address,
+            Value {
+                amount: to_rate_data.delegation_amount(unbonded_amount),
+                asset_id: to_rate_data.identity_key.delegation_token().id(),
+            },
+            memo::MemoPlaintext([0u8; memo::MEMO_LEN_BYTES]),
+            self.wallet.outgoing_viewing_key(),
+        );
+        self.register_unbonding(delegation_note, &spent)?;
+
+        // TODO: support dummy notes, and produce change outputs unconditionally.
+        for (amount, asset_id) in [
+            (spent_from_amount - delegation_amount, from_denom.id()),
+            (spent_fee_amount - fee, *STAKING_TOKEN_ASSET_ID),
+        ] {
+            if amount > 0 {
+                let change_note = tx_builder.add_output_producing_note(
+                    rng,
+                    &self_address,
+                    Value { amount, asset_id },
+                    memo::MemoPlaintext([0u8; memo::MEMO_LEN_BYTES]),
+                    self.wallet.outgoing_viewing_key(),
+                );
+                self.register_unbonding(change_note, &spent)?;
+            }
+        }
 
         tx_builder.finalize(rng).map_err(Into::into)
     }
@@ -420,15 +851,16 @@ impl ClientState {
         &mut self,
         rng: &mut R,
         values: &[Value],
-        fee: u64,
+        fee: Value,
         dest_address: Address,
         source_address: Option<u64>,
         tx_memo: Option<String>,
     ) -> Result<Transaction, anyhow::Error> {
         let mut tx_builder = Transaction::build_with_root(self.note_commitment_tree.root2());
+        tx_builder.set_padding(self.padding);
 
         tx_builder
-            .set_fee(fee)
+            .set_fee_value(fee)
             .set_chain_id(self.chain_id().ok_or_else(|| anyhow!("missing chain_id"))?);
 
         let mut output_value = HashMap::<Denom, u64>::new();
@@ -459,10 +891,11 @@ impl ClientState {
 
         // The value we need to spend is the output value, plus fees.
         let mut value_to_spend = output_value;
-        if fee > 0 {
-            *value_to_spend
-                .entry(STAKING_TOKEN_DENOM.clone())
-                .or_default() += fee;
+        if fee.amount > 0 {
+            let fee_denom = self.asset_cache().get(&fee.asset_id).ok_or_else(|| {
+                anyhow::anyhow!("unknown denomination for fee asset id {}", fee.asset_id)
+            })?;
+            *value_to_spend.entry(fee_denom.clone()).or_default() += fee.amount;
         }
 
         for (denom, amount) in value_to_spend {
@@ -480,12 +913,8 @@ impl ClientState {
 
             // Spend each of the notes we selected.
             for note in notes {
-                tx_builder.add_spend(
-                    rng,
-                    &self.note_commitment_tree,
-                    self.wallet.spend_key(),
-                    note,
-                )?;
+                let merkle_path = self.note_witness(&note)?;
+                tx_builder.add_spend_with_path(rng, merkle_path, self.wallet.spend_key(), note);
             }
 
             // Find out how much change we have and whether to add a change output.
@@ -542,14 +971,7 @@ impl ClientState {
                     .expect("all asset IDs should have denominations stored locally")
                     .clone();
 
-                let index: u64 = self
-                    .wallet()
-                    .incoming_viewing_key()
-                    .index_for_diversifier(&note.as_ref().diversifier())
-                    .try_into()
-                    .expect("diversifiers created by `pcli` are well-formed");
-
-                (index, denom, note)
+                (self.address_index(note.as_ref()), denom, note)
             })
     }
 
@@ -571,6 +993,26 @@ impl ClientState {
         notemap
     }
 
+    /// Returns the index of the address in this wallet that a note was sent to.
+    ///
+    /// This is the index recorded when we found the note while scanning, if we have, or otherwise
+    /// the index its diversifier decrypts to, e.g. for a change note we haven't found yet.
+    pub fn address_index(&self, note: &Note) -> u64 {
+        self.address_indices
+            .get(&note.commit())
+            .copied()
+            .unwrap_or_else(|| self.diversifier_index(note))
+    }
+
+    /// Returns the index of the address in this wallet with the diversifier a note was sent to.
+    fn diversifier_index(&self, note: &Note) -> u64 {
+        self.wallet()
+            .incoming_viewing_key()
+            .index_for_diversifier(&note.diversifier())
+            .try_into()
+            .expect("diversifiers created by `pcli` are well-formed")
+    }
+
     /// Returns unspent notes, grouped by denomination and then by address index.
     pub fn unspent_notes_by_denom_and_address(
         &self,
@@ -651,18 +1093,34 @@ impl ClientState {
         }
     }
 
-    /// Scan the provided block and update the client state.
+    /// Scan the provided block and update the client state, returning the
+    /// changes to our notes that it contained.
     ///
     /// The provided block must be the one immediately following [`Self::last_block_height`].
-    #[instrument(skip(self, fragments, nullifiers))]
+    #[instrument(skip(self, fragments, nullifiers, reverted_nullifiers))]
     pub fn scan_block(
         &mut self,
         CompactBlock {
             height,
             fragments,
             nullifiers,
+            reverted_nullifiers,
+            nct_root,
+            app_hash: _,
+            chain_id,
+            start_position,
+            version,
         }: CompactBlock,
-    ) -> Result<(), anyhow::Error> {
+    ) -> Result<Vec<ScanEvent>, anyhow::Error> {
+        // Servers that predate versioning leave the version unset, meaning version 1.
+        if version > COMPACT_BLOCK_VERSION {
+            return Err(anyhow::anyhow!(
+                "block {} has compact block version {}, but this wallet only understands up to version {}; upgrade to sync",
+                height,
+                version,
+                COMPACT_BLOCK_VERSION
+            ));
+        }
         // We have to do a bit of a dance to use None as "-1" and handle genesis notes.
         match (height, self.last_block_height()) {
             (0, None) => {}
@@ -675,12 +1133,42 @@ impl ClientState {
                 ))
             }
         }
+        // Servers that predate these fields leave them empty, so only check them when present.
+        if let Some(expected_chain_id) = self.chain_id() {
+            if !chain_id.is_empty() && chain_id != expected_chain_id {
+                return Err(anyhow::anyhow!(
+                    "block {} is from chain {}, expecting {}",
+                    height,
+                    chain_id,
+                    expected_chain_id
+                ));
+            }
+        }
+        // The block's notes must be appended right where our copy of the tree ends.  Servers that
+        // predate the start position leave it zero, which is also the start of the tree.
+        if start_position != 0 {
+            let tree_size = self.note_commitment_tree_size();
+            if start_position != tree_size {
+                return Err(anyhow::anyhow!(
+                    "block {} starts at note position {}, but our note commitment tree has {} notes",
+                    height,
+                    start_position,
+                    tree_size
+                ));
+            }
+        }
         tracing::debug!(fragments_len = fragments.len(), "starting block scan");
 
+        // Paths fetched against our current root won't authenticate anything against the next.
+        self.fetched_witnesses.clear();
+
+        let mut events = Vec::new();
+
         for StateFragment {
             note_commitment,
             ephemeral_key,
             encrypted_note,
+            reward_source,
         } in fragments.into_iter()
         {
             // Unconditionally insert the note commitment into the merkle tree
@@ -701,16 +1189,23 @@ impl ClientState {
                     .try_into()
                     .context("invalid ephemeral key")?,
             ) {
-                tracing::debug!(?note_commitment, ?note, "found note while scanning");
+                let address_index = self.diversifier_index(&note);
+                tracing::debug!(
+                    ?note_commitment,
+                    ?note,
+                    address_index,
+                    label = ?self.wallet.label_for_index(address_index as usize),
+                    "found note while scanning"
+                );
                 // Mark the most-recently-inserted note commitment (the one corresponding to this
-                // note) as worth keeping track of, because it's ours
-                self.note_commitment_tree.witness();
+                // note) as worth keeping track of, because it's ours, unless we'll fetch its
+                // authentication path when we spend it
+                if !self.fetch_witnesses {
+                    self.note_commitment_tree.witness();
+                }
 
                 // Insert the note associated with its computed nullifier into the nullifier map
-                let (pos, _auth_path) = self
-                    .note_commitment_tree
-                    .authentication_path(&note_commitment)
-                    .expect("we just witnessed this commitment");
+                let pos = merkle::Position::from(self.note_commitment_tree_size() as usize - 1);
                 self.nullifier_map.insert(
                     self.wallet
                         .full_viewing_key()
@@ -722,12 +1217,42 @@ impl ClientState {
                 if self.submitted_change_set.remove(&note_commitment).is_some() {
                     tracing::debug!(value = ?note.value(), "found submitted change note while scanning, removing it from the submitted change set");
                 }
+                // Likewise if it was released from quarantine at the end of its unbonding period
+                if self.unbonding_set.remove(&note_commitment).is_some() {
+                    tracing::debug!(value = ?note.value(), "found unbonded note while scanning, removing it from the unbonding set");
+                }
 
-                // Insert the note into the received set
+                events.push(ScanEvent::NoteReceived {
+                    height,
+                    note_commitment,
+                    note: note.clone(),
+                    address_index,
+                    reward_source: reward_source
+                        .map(RewardSource::try_from)
+                        .transpose()
+                        .context("invalid reward source")?,
+                });
+
+                // Insert the note into the received set, attributed to the address it was sent to
+                self.address_indices.insert(note_commitment, address_index);
                 self.unspent_set.insert(note_commitment, note.clone());
             }
         }
 
+        // Check that our copy of the note commitment tree matches the server's, so that we detect
+        // scanning bugs or an equivocating server at the block where they occur.
+        if !nct_root.is_empty() {
+            let root = self.note_commitment_tree.root2();
+            if root.to_bytes()[..] != nct_root[..] {
+                return Err(anyhow::anyhow!(
+                    "note commitment tree root mismatch at height {}: computed {}, server reported {}",
+                    height,
+                    hex::encode(root.to_bytes()),
+                    hex::encode(&nct_root),
+                ));
+            }
+        }
+
         // Scan through the list of nullifiers to find those which refer to notes in our unspent
         // set, submitted change set, or submitted spend set and move them into the spent set.
         for nullifier in nullifiers {
@@ -744,8 +1269,13 @@ impl ClientState {
                         ?nullifier,
                         "found nullifier for unspent note, marking it as spent"
                     );
+                    events.push(ScanEvent::NoteSpent {
+                        height,
+                        note_commitment,
+                        note: note.clone(),
+                    });
                     self.spent_set.insert(note_commitment, note);
-                    self.note_commitment_tree.remove_witness(&note_commitment);
+                    self.retire_witness(height, note_commitment);
                 } else if let Some((_, note)) = self.submitted_spend_set.remove(&note_commitment) {
                     // Insert the note into the spent set
                     tracing::debug!(
@@ -753,8 +1283,13 @@ impl ClientState {
                         ?nullifier,
                         "found nullifier for submitted spend note, marking it as spent"
                     );
+                    events.push(ScanEvent::NoteSpent {
+                        height,
+                        note_commitment,
+                        note: note.clone(),
+                    });
                     self.spent_set.insert(note_commitment, note);
-                    self.note_commitment_tree.remove_witness(&note_commitment);
+                    self.retire_witness(height, note_commitment);
                 } else if let Some((_, note)) = self.submitted_change_set.remove(&note_commitment) {
                     // Insert the note into the spent set
                     tracing::debug!(
@@ -762,8 +1297,13 @@ impl ClientState {
                         ?nullifier,
                         "found nullifier for submitted change note, marking it as spent"
                     );
+                    events.push(ScanEvent::NoteSpent {
+                        height,
+                        note_commitment,
+                        note: note.clone(),
+                    });
                     self.spent_set.insert(note_commitment, note);
-                    self.note_commitment_tree.remove_witness(&note_commitment);
+                    self.retire_witness(height, note_commitment);
                 } else if self.spent_set.contains_key(&note_commitment) {
                     // If the nullifier is already in the spent set, it means we've already
                     // processed this note and it's spent. This should never happen
@@ -779,36 +1319,176 @@ impl ClientState {
             }
         }
 
+        // A transaction of ours producing unbonding notes was included once any of its spends is
+        // confirmed, so its outputs are now quarantined, unless we already received them above
+        // because they weren't.
+        let spent_in_block = events
+            .iter()
+            .filter_map(|event| match event {
+                ScanEvent::NoteSpent {
+                    note_commitment, ..
+                } => Some(*note_commitment),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+        let included = self
+            .unconfirmed_unbondings
+            .iter()
+            .filter(|(_, spent)| spent.iter().any(|c| spent_in_block.contains(c)))
+            .map(|(note_commitment, _)| *note_commitment)
+            .collect::<Vec<_>>();
+        for note_commitment in included {
+            self.unconfirmed_unbondings.remove(&note_commitment);
+            if let Some((release_height, note)) = self.unbonding_set.get_mut(&note_commitment) {
+                // Now that we know when it was included, we know when it will be released.
+                if let Some(chain_params) = &self.chain_params {
+                    *release_height = unbonding_release_height(height, chain_params);
+                }
+                tracing::debug!(
+                    value = ?note.value(),
+                    release_height = *release_height,
+                    "transaction producing unbonding note was included, so it is quarantined"
+                );
+                events.push(ScanEvent::NoteQuarantined {
+                    height,
+                    note_commitment,
+                    note: note.clone(),
+                    release_height: *release_height,
+                });
+            }
+        }
+
+        // Notes whose spends were reverted can be spent again, with their witnesses if we kept them,
+        // or with authentication paths fetched from a node otherwise.  A wallet that scanned from
+        // after the spend never saw it, and already has them unspent.
+        for nullifier in reverted_nullifiers {
+            let nullifier = nullifier.as_ref().try_into()?;
+            let note_commitment = match self.nullifier_map.get(&nullifier) {
+                Some(&note_commitment) if self.spent_set.contains_key(&note_commitment) => {
+                    note_commitment
+                }
+                _ => continue,
+            };
+            if self.revertible_spends.remove(&note_commitment).is_none() {
+                tracing::debug!(
+                    ?nullifier,
+                    "spend of our note was reverted after we forgot its witness, so it must be fetched to spend it"
+                );
+            }
+            let note = self
+                .spent_set
+                .remove(&note_commitment)
+                .expect("we just checked the note is in the spent set");
+            tracing::debug!(
+                value = ?note.value(),
+                ?nullifier,
+                "found reverted nullifier for spent note, marking it as unspent"
+            );
+            events.push(ScanEvent::NoteRestored {
+                height,
+                note_commitment,
+                note: note.clone(),
+            });
+            self.unspent_set.insert(note_commitment, note);
+        }
+
+        // The release height of an unbonding note is only an estimate, since the undelegation may
+        // have been included in a later block than expected, but if the note hasn't been released
+        // an epoch after it, the undelegation was never included at all.
+        if let Some(epoch_duration) = self.chain_params().map(|p| p.epoch_duration) {
+            self.unbonding_set.retain(|_, (release_height, note)| {
+                let expected = height <= *release_height + epoch_duration;
+                if !expected {
+                    tracing::warn!(
+                        value = ?note.value(),
+                        release_height,
+                        "unbonding note was never released, dropping it"
+                    );
+                }
+                expected
+            });
+            let unbonding_set = &self.unbonding_set;
+            self.unconfirmed_unbondings
+                .retain(|note_commitment, _| unbonding_set.contains_key(note_commitment));
+
+            // Likewise, a spend can only be reverted until its quarantine would be released, give
+            // or take an epoch, after which we no longer need the note's witness.
+            let note_commitment_tree = &mut self.note_commitment_tree;
+            self.revertible_spends
+                .retain(|note_commitment, release_height| {
+                    let revertible = height <= *release_height + epoch_duration;
+                    if !revertible {
+                        note_commitment_tree.remove_witness(note_commitment);
+                    }
+                    revertible
+                });
+        }
+
         // Remember that we've scanned this block & we're ready for the next one.
         self.last_block_height = Some(height);
         tracing::debug!(self.last_block_height, "finished scanning block");
 
-        Ok(())
+        Ok(events)
+    }
+
+    /// Stops keeping the witness of a note spent at `height` once its spend can no longer be
+    /// reverted, or right away if we don't know the unbonding period.
+    fn retire_witness(&mut self, height: u64, note_commitment: note::Commitment) {
+        match &self.chain_params {
+            Some(chain_params) => {
+                self.revertible_spends.insert(
+                    note_commitment,
+                    unbonding_release_height(height, chain_params),
+                );
+            }
+            None => {
+                self.note_commitment_tree.remove_witness(&note_commitment);
+            }
+        }
     }
 }
 
+/// Returns the height of the block expected to release notes quarantined at `height`, which is the
+/// last block of the epoch in which their unbonding period ends.
+pub fn unbonding_release_height(height: u64, chain_params: &ChainParams) -> u64 {
+    let unbonding_height = height + chain_params.epoch_duration * chain_params.unbonding_epochs;
+    Epoch::from_height(unbonding_height, chain_params.epoch_duration)
+        .end_height()
+        .value()
+}
+
 mod serde_helpers {
     use serde_with::serde_as;
 
     use super::*;
 
+    /// The persisted parts of a [`ClientState`], with note commitments, nullifiers and notes
+    /// hex-encoded, as stored in a wallet file or split into the tables of a wallet database.
     #[serde_as]
     #[derive(Serialize, Deserialize)]
     pub struct ClientStateHelper {
-        wallet: Wallet, // this should be at the top to make `wallet reset` faster
-        last_block_height: Option<u64>,
+        pub wallet: Wallet, // this should be at the top to make `wallet reset` faster
+        pub last_block_height: Option<u64>,
         #[serde_as(as = "serde_with::hex::Hex")]
-        note_commitment_tree: Vec<u8>,
-        nullifier_map: Vec<(String, String)>,
-        unspent_set: Vec<(String, String)>,
+        pub note_commitment_tree: Vec<u8>,
+        pub nullifier_map: Vec<(String, String)>,
+        pub unspent_set: Vec<(String, String)>,
+        #[serde(default)]
+        pub address_indices: Vec<(String, u64)>,
         #[serde(default, alias = "pending_set")]
-        submitted_spend_set: Vec<(String, SystemTime, String)>,
+        pub submitted_spend_set: Vec<(String, SystemTime, String)>,
         #[serde(default, alias = "pending_change_set")]
-        submitted_change_set: Vec<(String, SystemTime, String)>,
-        spent_set: Vec<(String, String)>,
-        transactions: Vec<(String, String)>,
-        asset_registry: Vec<(asset::Id, String)>,
-        chain_params: Option<ChainParams>,
+        pub submitted_change_set: Vec<(String, SystemTime, String)>,
+        pub spent_set: Vec<(String, String)>,
+        #[serde(default)]
+        pub revertible_spends: Vec<(String, u64)>,
+        #[serde(default)]
+        pub unbonding_set: Vec<(String, u64, String)>,
+        #[serde(default)]
+        pub unconfirmed_unbondings: Vec<(String, Vec<String>)>,
+        pub transactions: Vec<(String, String)>,
+        pub asset_registry: Vec<(asset::Id, String)>,
+        pub chain_params: Option<ChainParams>,
     }
 
     #[serde_as]
@@ -878,6 +1558,13 @@ mod serde_helpers {
                         )
                     })
                     .collect(),
+                address_indices: state
+                    .address_indices
+                    .iter()
+                    .map(|(commitment, address_index)| {
+                        (hex::encode(commitment.0.to_bytes()), *address_index)
+                    })
+                    .collect(),
                 submitted_spend_set: state
                     .submitted_spend_set
                     .iter()
@@ -910,6 +1597,37 @@ mod serde_helpers {
                         )
                     })
                     .collect(),
+                revertible_spends: state
+                    .revertible_spends
+                    .iter()
+                    .map(|(commitment, release_height)| {
+                        (hex::encode(commitment.0.to_bytes()), *release_height)
+                    })
+                    .collect(),
+                unbonding_set: state
+                    .unbonding_set
+                    .iter()
+                    .map(|(commitment, (release_height, note))| {
+                        (
+                            hex::encode(commitment.0.to_bytes()),
+                            *release_height,
+                            hex::encode(note.to_bytes()),
+                        )
+                    })
+                    .collect(),
+                unconfirmed_unbondings: state
+                    .unconfirmed_unbondings
+                    .iter()
+                    .map(|(commitment, spent)| {
+                        (
+                            hex::encode(commitment.0.to_bytes()),
+                            spent
+                                .iter()
+                                .map(|commitment| hex::encode(commitment.0.to_bytes()))
+                                .collect(),
+                        )
+                    })
+                    .collect(),
                 asset_registry: state
                     .asset_cache
                     .iter()
@@ -943,6 +1661,14 @@ mod serde_helpers {
                 );
             }
 
+            let mut address_indices = BTreeMap::new();
+            for (commitment, address_index) in state.address_indices.into_iter() {
+                address_indices.insert(
+                    hex::decode(commitment)?.as_slice().try_into()?,
+                    address_index,
+                );
+            }
+
             let mut submitted_spend_set = BTreeMap::new();
             for (commitment, timeout, note) in state.submitted_spend_set.into_iter() {
                 submitted_spend_set.insert(
@@ -967,6 +1693,33 @@ mod serde_helpers {
                 );
             }
 
+            let mut revertible_spends = BTreeMap::new();
+            for (commitment, release_height) in state.revertible_spends.into_iter() {
+                revertible_spends.insert(
+                    hex::decode(commitment)?.as_slice().try_into()?,
+                    release_height,
+                );
+            }
+
+            let mut unbonding_set = BTreeMap::new();
+            for (commitment, release_height, note) in state.unbonding_set.into_iter() {
+                unbonding_set.insert(
+                    hex::decode(commitment)?.as_slice().try_into()?,
+                    (release_height, hex::decode(note)?.as_slice().try_into()?),
+                );
+            }
+
+            let mut unconfirmed_unbondings = BTreeMap::new();
+            for (commitment, spent) in state.unconfirmed_unbondings.into_iter() {
+                unconfirmed_unbondings.insert(
+                    hex::decode(commitment)?.as_slice().try_into()?,
+                    spent
+                        .into_iter()
+                        .map(|commitment| Ok(hex::decode(commitment)?.as_slice().try_into()?))
+                        .collect::<Result<Vec<_>, anyhow::Error>>()?,
+                );
+            }
+
             let mut asset_registry = BTreeMap::new();
             for (id, denom) in state.asset_registry.into_iter() {
                 asset_registry.insert(id, denom);
@@ -976,15 +1729,23 @@ mod serde_helpers {
                 wallet: state.wallet,
                 last_block_height: state.last_block_height,
                 note_commitment_tree: bincode::deserialize(&state.note_commitment_tree)?,
+                fetched_witnesses: BTreeMap::new(),
                 nullifier_map,
                 unspent_set,
+                address_indices,
                 submitted_spend_set,
                 submitted_change_set,
                 spent_set,
+                revertible_spends,
+                unbonding_set,
+                unconfirmed_unbondings,
                 asset_cache: asset_registry.try_into()?,
                 // TODO: serialize full transactions
                 transactions: Default::default(),
                 chain_params: state.chain_params,
+                padding: Padding::default(),
+                note_selection: SelectionStrategy::default(),
+                fetch_witnesses: false,
             })
         }
     }
@@ -77,6 +77,11 @@ impl Wallet {
         Ok((label.clone(), address))
     }
 
+    /// Get the local label for the address with the given index, if it has one.
+    pub fn label_for_index(&self, index: usize) -> Option<&str> {
+        self.address_labels.get(index).map(String::as_str)
+    }
+
     /// Iterate through the addresses in this wallet.
     pub fn addresses(&self) -> impl Iterator<Item = (usize, String, Address)> {
         let incoming = self.incoming_viewing_key().clone();